@@ -0,0 +1,136 @@
+//! Connection table benchmarks.
+//!
+//! Measures the connection table operations on the packet processing path for millions of
+//! connections, and the same operations on a `LinkedHashMap` with the table hasher and with the
+//! standard library's SipHash for comparison.
+//! Run with `cargo bench -p retina-core --bench conntrack [-- <nb_connections>]`.
+
+use retina_core::conntrack::conn_id::{ConnId, FiveTuple};
+use retina_core::conntrack::table::{ConnIdHashBuilder, ConnTable, TableEntry};
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::hint::black_box;
+use std::time::Instant;
+
+use hashlink::linked_hash_map::{LinkedHashMap, RawEntryMut};
+
+/// Default number of concurrent connections.
+const NB_CONNECTIONS: usize = 4_000_000;
+
+/// Returns the `i`-th connection ID, spread across a /16 of clients and servers.
+fn conn_id(i: usize) -> ConnId {
+    let client = [10, 0, (i >> 24) as u8, (i >> 16) as u8];
+    let server = [172, 16, (i >> 8) as u8, i as u8];
+    FiveTuple {
+        orig: (client, 1024 + (i % 60000) as u16).into(),
+        resp: (server, 443).into(),
+        proto: 6,
+    }
+    .conn_id()
+}
+
+/// Runs `op` on each of `conn_ids`, and prints the time per operation.
+fn measure(name: &str, conn_ids: &[ConnId], mut op: impl FnMut(&ConnId)) {
+    let start = Instant::now();
+    for conn_id in conn_ids {
+        op(conn_id);
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "  {:<24} {:>8.1} ns/op",
+        name,
+        elapsed.as_nanos() as f64 / conn_ids.len() as f64
+    );
+}
+
+fn bench_table(conn_ids: &[ConnId]) {
+    println!("ConnTable ({} connections)", conn_ids.len());
+    let new_ids: Vec<ConnId> = (conn_ids.len()..2 * conn_ids.len()).map(conn_id).collect();
+    let mut table: ConnTable<u64> = ConnTable::with_capacity(conn_ids.len());
+
+    measure("insert", conn_ids, |conn_id| {
+        let hash = table.hasher().hash(conn_id);
+        if let TableEntry::Vacant(vacant) = table.entry(hash, conn_id) {
+            vacant.insert(conn_id.clone(), 0);
+        }
+    });
+    measure("lookup (hit)", conn_ids, |conn_id| {
+        let hash = table.hasher().hash(conn_id);
+        if let TableEntry::Occupied(mut occupied) = table.entry(hash, conn_id) {
+            *occupied.get_mut() += 1;
+        }
+    });
+    measure("lookup (miss)", &new_ids, |conn_id| {
+        black_box(table.get(conn_id));
+    });
+    measure("lookup (hit, lru)", conn_ids, |conn_id| {
+        let hash = table.hasher().hash(conn_id);
+        if let TableEntry::Occupied(mut occupied) = table.entry(hash, conn_id) {
+            occupied.to_back();
+        }
+    });
+    measure("evict oldest + insert", &new_ids, |conn_id| {
+        let oldest = table.front().map(|(oldest, _)| oldest.clone());
+        if let Some(oldest) = oldest {
+            table.remove(&oldest);
+        }
+        let hash = table.hasher().hash(conn_id);
+        if let TableEntry::Vacant(vacant) = table.entry(hash, conn_id) {
+            vacant.insert(conn_id.clone(), 0);
+        }
+    });
+    measure("remove", &new_ids, |conn_id| {
+        black_box(table.remove(conn_id));
+    });
+}
+
+/// Measures the same operations on a `LinkedHashMap`, which allocates each entry, for comparison.
+fn bench_linked<S: BuildHasher + Clone>(name: &str, hash_builder: S, conn_ids: &[ConnId]) {
+    println!("{} ({} connections)", name, conn_ids.len());
+    let new_ids: Vec<ConnId> = (conn_ids.len()..2 * conn_ids.len()).map(conn_id).collect();
+    let mut table: LinkedHashMap<ConnId, u64, S> =
+        LinkedHashMap::with_capacity_and_hasher(conn_ids.len(), hash_builder.clone());
+
+    measure("insert", conn_ids, |conn_id| {
+        table.insert(conn_id.clone(), 0);
+    });
+    measure("lookup (hit)", conn_ids, |conn_id| {
+        let hash = hash_builder.hash_one(conn_id);
+        if let RawEntryMut::Occupied(mut occupied) =
+            table.raw_entry_mut().from_key_hashed_nocheck(hash, conn_id)
+        {
+            *occupied.get_mut() += 1;
+        }
+    });
+    measure("lookup (miss)", &new_ids, |conn_id| {
+        black_box(table.get(conn_id));
+    });
+    measure("lookup (hit, lru)", conn_ids, |conn_id| {
+        if let RawEntryMut::Occupied(mut occupied) = table.raw_entry_mut().from_key(conn_id) {
+            occupied.to_back();
+        }
+    });
+    measure("evict oldest + insert", &new_ids, |conn_id| {
+        table.pop_front();
+        table.insert(conn_id.clone(), 0);
+    });
+    measure("remove", &new_ids, |conn_id| {
+        black_box(table.remove(conn_id));
+    });
+}
+
+fn main() {
+    let nb_connections = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(NB_CONNECTIONS);
+    let conn_ids: Vec<ConnId> = (0..nb_connections).map(conn_id).collect();
+    bench_table(&conn_ids);
+    bench_linked(
+        "LinkedHashMap, ConnIdHashBuilder",
+        ConnIdHashBuilder::new(),
+        &conn_ids,
+    );
+    bench_linked("LinkedHashMap, SipHash", RandomState::new(), &conn_ids);
+}
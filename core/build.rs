@@ -124,4 +124,14 @@ fn main() {
         builder.include(header_location);
     }
     builder.compile("inlined");
+
+    // Step 4: Generate the control-plane service.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/control.proto");
+        tonic_build::configure()
+            .build_client(false)
+            .compile(&["proto/control.proto"], &["proto"])
+            .unwrap_or_else(|e| panic!("Failed to compile control.proto: {:?}", e));
+    }
 }
@@ -9,27 +9,70 @@
 //! "offline" mode (reading packets from a capture file). See
 //! [configs](https://github.com/stanford-esrg/retina/tree/main/configs) for examples.
 
+use crate::conntrack::tunnel::MAX_TUNNEL_DEPTH;
 use crate::lcore::{CoreId, SocketId};
+use crate::protocols::packet::geneve::GENEVE_PORT;
+use crate::protocols::packet::gtpu::GTPU_PORT;
+use crate::protocols::packet::teredo::TEREDO_PORT;
+use crate::protocols::packet::vxlan::{VXLAN_LINUX_PORT, VXLAN_PORT};
 
+use std::cmp;
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
+/// Largest per-core mempool cache supported by DPDK (`RTE_MEMPOOL_CACHE_MAX_SIZE`).
+const MAX_CACHE_SIZE: usize = 512;
+
+/// Size of a minimum-size Ethernet frame, without the FCS.
+const MIN_FRAME_LEN: usize = 60;
+
 /// Loads a configuration file from `path`.
 pub fn load_config<P: AsRef<Path>>(path: P) -> RuntimeConfig {
-    let config_str = fs::read_to_string(path).expect("ERROR: File read failed");
-    let config: RuntimeConfig = toml::from_str(&config_str).expect("Invalid config file");
+    match read_config(path) {
+        Ok(config) => config,
+        Err(error) => {
+            log::error!("{}", error);
+            panic!();
+        }
+    }
+}
+
+/// Reads and checks the configuration file at `path`.
+pub(crate) fn read_config<P: AsRef<Path>>(path: P) -> Result<RuntimeConfig, String> {
+    let config_str =
+        fs::read_to_string(path).map_err(|error| format!("File read failed: {}", error))?;
+    let mut config: RuntimeConfig =
+        toml::from_str(&config_str).map_err(|error| format!("Invalid config file: {}", error))?;
 
     // error check config
     if config.online.is_some() == config.offline.is_some() {
-        log::error!(
+        return Err(format!(
             "Configure either live ports or offline analysis: {:#?}",
             config
-        );
-        panic!();
+        ));
     }
     config
+        .assign_cores()
+        .map_err(|error| format!("Invalid core assignment: {}", error))?;
+    config
+        .mempool
+        .check()
+        .map_err(|error| format!("Invalid mempool configuration: {}", error))?;
+    if let Some(online) = &config.online {
+        online
+            .check_ports()
+            .map_err(|error| format!("Invalid port configuration: {}", error))?;
+    }
+    if let Some(offline) = &config.offline {
+        offline
+            .check()
+            .map_err(|error| format!("Invalid offline configuration: {}", error))?;
+    }
+    Ok(config)
 }
 
 /// Loads a default configuration file.
@@ -59,9 +102,14 @@ pub fn default_config() -> RuntimeConfig {
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct RuntimeConfig {
     /// Main core identifier. Initializes and manages packet processing cores and logging, but does
-    /// not process packets itself.
+    /// not process packets itself. Overridden by the management core of `cores`, if set.
     pub main_core: u32,
 
+    /// Explicit assignment of roles to cores. Defaults to `None` (the cores of each role are
+    /// inferred from `main_core`, the ports, and software dispatch).
+    #[serde(default = "default_cores")]
+    pub cores: Option<CoreRoles>,
+
     /// Sets the number of memory channels to use.
     ///
     /// This controls the spread layout used by the memory allocator and is mainly for performance
@@ -74,6 +122,18 @@ pub struct RuntimeConfig {
     #[serde(default = "default_suppress_dpdk_output")]
     pub suppress_dpdk_output: bool,
 
+    /// Serve Retina's runtime metrics (connection table size, filter hits per layer, deliveries)
+    /// over the DPDK telemetry socket, under the `/retina/` commands. Enables DPDK telemetry even
+    /// if `suppress_dpdk_output` is set. Defaults to `false`.
+    #[serde(default = "default_telemetry")]
+    pub telemetry: bool,
+
+    /// Count the TSC cycles each packet processing core spends in each stage of the pipeline (RX,
+    /// packet filter, connection tracking, reassembly, parsing, delivery), reported with the other
+    /// runtime metrics. Adds two TSC reads per stage transition. Defaults to `false`.
+    #[serde(default = "default_profile")]
+    pub profile: bool,
+
     /// Per-mempool settings.
     pub mempool: MempoolConfig,
 
@@ -88,6 +148,56 @@ pub struct RuntimeConfig {
     /// Connection tracking settings.
     pub conntrack: ConnTrackConfig,
 
+    /// Limits on the length of the run. Defaults to `None` (run until terminated).
+    #[serde(default = "default_run")]
+    pub runtime: Option<RunConfig>,
+
+    /// Settings for writing the packets of matched connections to pcap files. Only used by
+    /// subscriptions that request packet capture.
+    #[serde(default)]
+    pub pcap_writer: PcapWriterConfig,
+
+    /// Files that [filter sets](crate::filter::sets) are loaded from, by set name. Each line of a
+    /// file is a member; blank lines and lines starting with `#` are ignored. The files are loaded
+    /// when the runtime starts, and again on SIGHUP (see
+    /// [Runtime::reload_on_sighup](crate::Runtime::reload_on_sighup)). Defaults to none.
+    ///
+    /// ## Example
+    /// ```toml
+    /// [filter_sets]
+    ///     blocklist = "/etc/retina/blocklist.txt"
+    /// ```
+    #[serde(default)]
+    pub filter_sets: BTreeMap<String, String>,
+
+    /// Output files of the [sinks](crate::utils::sink::Sink) registered with
+    /// [Runtime::add_sink](crate::Runtime::add_sink), by the path the sink was created with.
+    /// Overrides that path, and can be changed on SIGHUP: the records written so far are merged
+    /// into the previous file, and later records into the new one. Defaults to none.
+    ///
+    /// ## Example
+    /// ```toml
+    /// [sinks]
+    ///     "tls.jsonl" = "/data/retina/tls.jsonl"
+    /// ```
+    #[serde(default)]
+    pub sinks: BTreeMap<String, String>,
+
+    /// Settings for delivering data to async callbacks. Only used by applications built with the
+    /// `async` feature.
+    #[serde(default)]
+    pub async_callbacks: AsyncConfig,
+
+    /// Settings for running callbacks on a worker thread pool instead of the packet processing
+    /// cores. Defaults to running callbacks inline.
+    #[serde(default)]
+    pub callbacks: CallbackConfig,
+
+    /// Settings for the gRPC control-plane service. Only used by applications built with the
+    /// `grpc` feature. Defaults to `None` (no control plane).
+    #[serde(default = "default_control")]
+    pub control: Option<ControlConfig>,
+
     #[doc(hidden)]
     /// Runtime filter for testing purposes.
     #[serde(default = "default_filter")]
@@ -105,17 +215,25 @@ impl RuntimeConfig {
                     cores.push(CoreId(sink.core));
                 }
             }
+            if let Some(dispatch) = &online.dispatch {
+                cores.extend(dispatch.cores.iter().map(|c| CoreId(*c)));
+            }
         }
         cores.sort();
         cores.dedup();
         cores
     }
 
+    /// Returns the cores that process packets. With software dispatch, these are the worker cores.
     pub fn get_all_rx_core_ids(&self) -> Vec<CoreId> {
         let mut cores = vec![];
         if let Some(online) = &self.online {
-            for port in online.ports.iter() {
-                cores.extend(port.cores.iter().map(|c| CoreId(*c)));
+            if let Some(dispatch) = &online.dispatch {
+                cores.extend(dispatch.cores.iter().map(|c| CoreId(*c)));
+            } else {
+                for port in online.ports.iter() {
+                    cores.extend(port.cores.iter().map(|c| CoreId(*c)));
+                }
             }
         } else {
             cores.push(CoreId(self.main_core));
@@ -144,34 +262,230 @@ impl RuntimeConfig {
         eal_params.push("--main-lcore".to_owned());
         eal_params.push(self.main_core.to_string());
 
-        eal_params.push("-l".to_owned());
-        let core_list: Vec<String> = self
-            .get_all_core_ids()
-            .iter()
-            .map(|c| c.raw().to_string())
-            .collect();
-        eal_params.push(core_list.join(","));
+        match self
+            .cores
+            .as_ref()
+            .and_then(|roles| roles.coremask.as_ref())
+        {
+            Some(coremask) => {
+                eal_params.push("-c".to_owned());
+                eal_params.push(coremask.to_string());
+            }
+            None => {
+                eal_params.push("-l".to_owned());
+                let core_list: Vec<String> = self
+                    .get_all_core_ids()
+                    .iter()
+                    .map(|c| c.raw().to_string())
+                    .collect();
+                eal_params.push(core_list.join(","));
+            }
+        }
 
         if let Some(online) = &self.online {
             for supl_arg in online.dpdk_supl_args.iter() {
                 eal_params.push(supl_arg.to_string())
             }
             for port in online.ports.iter() {
-                eal_params.push("-a".to_owned());
-                eal_params.push(port.device.to_string());
+                match &port.virtual_port {
+                    Some(virtual_port) => {
+                        let args = virtual_port.devargs(online.nb_queues(port));
+                        eal_params.push(format!("--vdev={},{}", port.device, args));
+                    }
+                    None => {
+                        eal_params.push("-a".to_owned());
+                        eal_params.push(port.device.to_string());
+                    }
+                }
+            }
+            // Without an allowlist, all PCI devices bound to DPDK would be probed
+            let virtual_only = online.ports.iter().all(|p| p.virtual_port.is_some());
+            if virtual_only && !online.dpdk_supl_args.iter().any(|a| a == "--no-pci") {
+                eal_params.push("--no-pci".to_owned());
             }
         }
 
         eal_params.push("-n".to_owned());
         eal_params.push(self.nb_memory_channels.to_string());
 
+        if !self.mempool.socket_limit.is_empty() {
+            eal_params.push(format!(
+                "--socket-limit={}",
+                self.mempool.socket_limit.iter().join(",")
+            ));
+        }
+
         if self.suppress_dpdk_output {
             eal_params.push("--log-level=6".to_owned());
-            eal_params.push("--no-telemetry".to_owned());
+            if !self.telemetry {
+                eal_params.push("--no-telemetry".to_owned());
+            }
         }
 
         eal_params
     }
+
+    /// Applies the role assignment of `cores`: sets the main core, assigns the rx cores to ports
+    /// without cores and the worker cores to software dispatch, and checks that the cores of the
+    /// ports, sinks and dispatch match their roles. Does nothing if `cores` is not set.
+    pub(crate) fn assign_cores(&mut self) -> Result<(), String> {
+        let roles = match &self.cores {
+            Some(roles) => roles.clone(),
+            None => return Ok(()),
+        };
+        if let Some(management) = roles.management {
+            self.main_core = management;
+        }
+        let mut assigned: BTreeMap<u32, &str> = BTreeMap::new();
+        let all_roles = [
+            ("management", vec![self.main_core]),
+            ("rx", roles.rx.clone()),
+            ("worker", roles.workers.clone()),
+            ("sink", roles.sink.clone()),
+        ];
+        for (role, cores) in all_roles.iter() {
+            for core in cores {
+                if let Some(other) = assigned.insert(*core, *role) {
+                    if other != *role {
+                        return Err(format!(
+                            "core {} is assigned both the {} and {} roles",
+                            core, other, role
+                        ));
+                    }
+                }
+            }
+        }
+        if let Some(coremask) = &roles.coremask {
+            let mask = parse_coremask(coremask)?;
+            if let Some((core, role)) = assigned
+                .iter()
+                .find(|(core, _)| **core >= 128 || mask & (1u128 << **core) == 0)
+            {
+                return Err(format!(
+                    "core {} ({}) is not in the EAL coremask {}",
+                    core, role, coremask
+                ));
+            }
+        }
+
+        let online = match self.online.as_mut() {
+            Some(online) => online,
+            None if roles.rx.is_empty() && roles.workers.is_empty() && roles.sink.is_empty() => {
+                return Ok(());
+            }
+            None => return Err("rx, worker and sink roles are only used online".to_string()),
+        };
+        if roles.rx.is_empty() {
+            return Err("no cores are assigned the rx role".to_string());
+        }
+        for port in online.ports.iter_mut() {
+            if port.cores.is_empty() {
+                port.cores = roles.rx.clone();
+            } else if let Some(core) = port.cores.iter().find(|c| !roles.rx.contains(c)) {
+                return Err(format!(
+                    "core {} of port {} is not assigned the rx role",
+                    core, port.device
+                ));
+            }
+            if let Some(sink) = port.sink.as_ref().filter(|s| !roles.sink.contains(&s.core)) {
+                return Err(format!(
+                    "sink core {} of port {} is not assigned the sink role",
+                    sink.core, port.device
+                ));
+            }
+        }
+        if let Some(core) = roles
+            .rx
+            .iter()
+            .find(|c| !online.ports.iter().any(|p| p.cores.contains(c)))
+        {
+            return Err(format!("rx core {} does not poll any port", core));
+        }
+        if let Some(core) = roles.sink.iter().find(|c| {
+            !online
+                .ports
+                .iter()
+                .any(|p| p.sink.as_ref().is_some_and(|s| s.core == **c))
+        }) {
+            return Err(format!("sink core {} is not the sink of any port", core));
+        }
+        match online.dispatch.as_mut() {
+            None if !roles.workers.is_empty() => {
+                online.dispatch = Some(DispatchConfig {
+                    cores: roles.workers.clone(),
+                    ring_size: default_dispatch_ring_size(),
+                });
+            }
+            Some(dispatch) if dispatch.cores.is_empty() => dispatch.cores = roles.workers.clone(),
+            Some(dispatch) => {
+                if let Some(core) = dispatch.cores.iter().find(|c| !roles.workers.contains(c)) {
+                    return Err(format!(
+                        "dispatch worker core {} is not assigned the worker role",
+                        core
+                    ));
+                }
+                if let Some(core) = roles.workers.iter().find(|c| !dispatch.cores.contains(c)) {
+                    return Err(format!("worker core {} is not used for dispatch", core));
+                }
+            }
+            None => (),
+        }
+        Ok(())
+    }
+
+    /// Returns the role of each core assigned to the runtime, with the ports it serves.
+    pub(crate) fn core_placement(&self) -> BTreeMap<CoreId, String> {
+        let mut placement = BTreeMap::new();
+        let online = match &self.online {
+            Some(online) => online,
+            None => {
+                placement.insert(
+                    CoreId(self.main_core),
+                    "management, processes offline packets".to_string(),
+                );
+                return placement;
+            }
+        };
+        placement.insert(CoreId(self.main_core), "management".to_string());
+        for core in self.get_all_core_ids() {
+            let polled = online
+                .ports
+                .iter()
+                .filter(|p| p.cores.contains(&core.raw()))
+                .map(|p| p.device.as_str())
+                .join(", ");
+            if !polled.is_empty() {
+                placement.insert(core, format!("rx, polls {}", polled));
+            }
+            let sinks = online
+                .ports
+                .iter()
+                .filter(|p| p.sink.as_ref().is_some_and(|s| s.core == core.raw()))
+                .map(|p| p.device.as_str())
+                .join(", ");
+            if !sinks.is_empty() {
+                placement.insert(core, format!("sink, drops from {}", sinks));
+            }
+        }
+        if let Some(dispatch) = &online.dispatch {
+            for core in dispatch.cores.iter() {
+                placement.insert(
+                    CoreId(*core),
+                    "worker, processes dispatched packets".to_string(),
+                );
+            }
+        }
+        placement
+    }
+}
+
+/// Parses a hexadecimal EAL coremask.
+fn parse_coremask(coremask: &str) -> Result<u128, String> {
+    let digits = coremask
+        .strip_prefix("0x")
+        .or_else(|| coremask.strip_prefix("0X"))
+        .unwrap_or(coremask);
+    u128::from_str_radix(digits, 16).map_err(|_| format!("invalid coremask {}", coremask))
 }
 
 fn default_nb_memory_channels() -> usize {
@@ -182,6 +496,18 @@ fn default_suppress_dpdk_output() -> bool {
     true
 }
 
+fn default_telemetry() -> bool {
+    false
+}
+
+fn default_profile() -> bool {
+    false
+}
+
+fn default_cores() -> Option<CoreRoles> {
+    None
+}
+
 fn default_online() -> Option<OnlineConfig> {
     None
 }
@@ -190,6 +516,14 @@ fn default_offline() -> Option<OfflineConfig> {
     None
 }
 
+fn default_run() -> Option<RunConfig> {
+    None
+}
+
+fn default_control() -> Option<ControlConfig> {
+    None
+}
+
 fn default_filter() -> Option<String> {
     None
 }
@@ -198,21 +532,35 @@ impl Default for RuntimeConfig {
     fn default() -> Self {
         RuntimeConfig {
             main_core: 0,
+            cores: None,
             nb_memory_channels: 1,
             suppress_dpdk_output: true,
+            telemetry: false,
+            profile: false,
             mempool: MempoolConfig {
-                capacity: 8192,
+                capacity: Some(8192),
                 cache_size: 512,
+                buffered_connections: default_buffered_connections(),
+                segment_size: None,
+                jumbo_capacity: default_jumbo_capacity(),
+                tracked: None,
+                socket_limit: default_socket_limit(),
             },
             online: None,
             offline: Some(OfflineConfig {
                 mtu: 9702,
                 // assumes Retina is being run from crate root
                 pcap: "./traces/small_flows.pcap".to_string(),
+                pcaps: vec![],
+                replay_speed: None,
             }),
             conntrack: ConnTrackConfig {
                 max_connections: 100_000,
                 max_out_of_order: 100,
+                max_out_of_order_bytes: default_max_out_of_order_bytes(),
+                max_out_of_order_total_bytes: default_max_out_of_order_total_bytes(),
+                out_of_order_overflow: OutOfOrderOverflow::Drop,
+                tcp_overlap_policy: OverlapPolicy::Bsd,
                 timeout_resolution: 100,
                 udp_inactivity_timeout: 60_000,
                 tcp_inactivity_timeout: 300_000,
@@ -221,7 +569,35 @@ impl Default for RuntimeConfig {
                 init_fin: false,
                 init_rst: false,
                 init_data: false,
+                defrag: DefragConfig::default(),
+                midstream: false,
+                port_timeouts: vec![],
+                eviction: EvictionPolicy::Reject,
+                tcp_rst_timeout: 0,
+                tcp_half_closed_timeout: None,
+                tcp_time_wait: 0,
+                skip_acked_gaps: false,
+                sctp_inactivity_timeout: 300_000,
+                vxlan: VxlanConfig::default(),
+                gre: GreConfig::default(),
+                geneve: GeneveConfig::default(),
+                gtpu: GtpuConfig::default(),
+                ipip: IpipConfig::default(),
+                teredo: TeredoConfig::default(),
+                mpls: MplsConfig::default(),
+                pppoe: PppoeConfig::default(),
+                max_tunnel_depth: default_max_tunnel_depth(),
+                unidirectional: false,
+                sampling: None,
+                bypass: false,
             },
+            runtime: None,
+            pcap_writer: PcapWriterConfig::default(),
+            filter_sets: BTreeMap::new(),
+            sinks: BTreeMap::new(),
+            async_callbacks: AsyncConfig::default(),
+            callbacks: CallbackConfig::default(),
+            control: None,
             filter: None,
         }
     }
@@ -229,478 +605,3037 @@ impl Default for RuntimeConfig {
 
 /* --------------------------------------------------------------------------------- */
 
-/// Memory pool options.
+/// Core role assignment.
 ///
-/// Retina manages packet buffer memory using DPDK's pool-based memory allocator. This takes
-/// advantage of built-in DPDK huge page support, NUMA affinity, and access to DMA addresses. See
-/// [Memory in DPDK](https://www.dpdk.org/blog/2019/08/21/memory-in-dpdk-part-1-general-concepts/)
-/// for more details.
+/// By default, the role of each core is implied by where it appears in the configuration: the
+/// `main_core` manages the runtime, the `cores` of each port poll its receive queues, sink cores
+/// are set per port, and software dispatch lists its worker cores. The `cores` section instead
+/// assigns each role explicitly, which makes the placement of a deployment easy to review:
+/// - `management`: the main core, which initializes the runtime, monitors the ports and displays
+///   statistics. Defaults to `main_core`.
+/// - `rx`: cores that poll the receive queues of the ports. Ports without `cores` are polled by
+///   all rx cores, and the `cores` of other ports must be rx cores.
+/// - `workers`: cores that process the packets dispatched by the rx cores. Software dispatch is
+///   enabled if there are workers (see [DispatchConfig](DispatchConfig)), whose `cores` can then be
+///   omitted.
+/// - `sink`: cores that drop the traffic of the sampled out connections of ports (see
+///   [SinkConfig](SinkConfig)).
+///
+/// A core has at most one role, and every rx, worker and sink core must be used. If `coremask` is
+/// set, it is passed to DPDK instead of the list of assigned cores, and all assigned cores must be
+/// in it. Retina also checks that DPDK enabled all assigned cores, and logs the placement of each
+/// core at startup.
 ///
 /// ## Example
 /// ```toml
-/// [mempool]
-///     capacity = 1_048_576
-///     cache_size = 512
+/// [cores]
+///     management = 0
+///     rx = [1,2]
+///     workers = [3,4,5,6]
+///     sink = [7]
+///     coremask = "0xff"
+///
+/// [[online.ports]]
+///     device = "0000:3b:00.0"
+///     [online.ports.sink]
+///         core = 7
 /// ```
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct MempoolConfig {
-    /// Number of mbufs allocated per mempool. The maximum value that can be set will depend on
-    /// the available memory (number of hugepages allocated) and the MTU. Defaults to `65536`.
-    #[serde(default = "default_capacity")]
-    pub capacity: usize,
+pub struct CoreRoles {
+    /// Management (main) core. Defaults to `None` (`main_core`).
+    #[serde(default = "default_management_core")]
+    pub management: Option<u32>,
+
+    /// Cores that poll the receive queues of the ports. Defaults to `[]`.
+    #[serde(default = "default_role_cores")]
+    pub rx: Vec<u32>,
+
+    /// Worker cores for software dispatch. Defaults to `[]`.
+    #[serde(default = "default_role_cores")]
+    pub workers: Vec<u32>,
+
+    /// Sink cores. Defaults to `[]`.
+    #[serde(default = "default_role_cores")]
+    pub sink: Vec<u32>,
+
+    /// Hexadecimal EAL coremask (e.g., `"0xff"`). Defaults to `None` (the assigned cores).
+    #[serde(default = "default_coremask")]
+    pub coremask: Option<String>,
+}
 
-    /// The size of the per-core object cache. It is recommended that `cache_size` evenly divides
-    /// `capacity`. Defaults to `512`.
-    #[serde(default = "default_cache_size")]
-    pub cache_size: usize,
+fn default_management_core() -> Option<u32> {
+    None
 }
 
-fn default_capacity() -> usize {
-    65536
+fn default_role_cores() -> Vec<u32> {
+    vec![]
 }
 
-fn default_cache_size() -> usize {
-    512
+fn default_coremask() -> Option<String> {
+    None
 }
 
 /* --------------------------------------------------------------------------------- */
 
-/// Live traffic analysis options.
+/// Run limit options.
 ///
-/// Online mode performs traffic analysis on a live network interface. Either
-/// [OnlineConfig](OnlineConfig) or [OfflineConfig](OfflineConfig) must be specified, but not both.
+/// Stops processing once any of the configured limits is reached, in both online and offline
+/// mode. Connections that are still being tracked are delivered before the runtime exits, as on
+/// `ctrl-c`. This is useful for bounded experiments and benchmarking.
+///
+/// Packet and connection limits are counted across all cores. Packets beyond `max_packets` are
+/// dropped without being processed, but cores may track a few connections past `max_connections`
+/// before observing that the limit was reached.
 ///
 /// ## Example
 /// ```toml
-/// [online]
-///     duration = 30
-///     nb_rxd = 32768
-///     promiscuous = true
-///     mtu = 1500
-///     hardware_assist = true
-///     dpdk_supl_args = []
-///
-/// [online.monitor.display]
-///     throughput = true
-///     mempool_usage = true
-///
-///     [online.monitor.log]
-///         directory = "./log"
-///         interval = 1000
-///
-///     [[online.ports]]
-///         device = "0000:3b:00.0"
-///         cores = [1,2,3,4]
-///
-///     [[online.ports]]
-///         device = "0000:3b:00.1"
-///         cores = [5,6,7,8]
+/// [runtime]
+///     duration = 60
+///     max_packets = 10_000_000
+///     max_connections = 100_000
 /// ```
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct OnlineConfig {
-    /// If set, the applicaton will stop after `duration` seconds. Defaults to `None`.
+pub struct RunConfig {
+    /// If set, processing stops after `duration` seconds. Defaults to `None`.
     #[serde(default = "default_duration")]
     pub duration: Option<u64>,
 
-    /// Whether promiscuous mode is enabled for all ports. Defaults to `true`.
-    #[serde(default = "default_promiscuous")]
-    pub promiscuous: bool,
-
-    /// The number of RX descriptors per receive queue. Defaults to `4096`.
-    ///
-    /// Receive queues are polled for packets using a run-to-completion model. Deeper queues will be
-    /// more tolerant of processing delays at the cost of higher memory usage and hugepage
-    /// reservation.
-    #[serde(default = "default_portqueue_nb_rxd")]
-    pub nb_rxd: usize,
+    /// If set, processing stops after `max_packets` packets have been received. Defaults to
+    /// `None`.
+    #[serde(default = "default_max_count")]
+    pub max_packets: Option<u64>,
 
-    /// Maximum transmission unit (in bytes) allowed for ingress packets. Defaults to `1500`.
+    /// If set, processing stops after `max_connections` connections have been tracked. Defaults
+    /// to `None`.
     ///
-    /// To capture jumbo frames, set this value higher (e.g., `9702`).
-    #[serde(default = "default_mtu")]
-    pub mtu: usize,
-
-    /// If set, will attempt to offload parts of the filter to the NIC, depending on its hardware
-    /// filtering support. Defaults to `true`.
-    #[serde(default = "default_hardware_assist")]
-    pub hardware_assist: bool,
-
-    /// If set, will pass supplementary arguments to DPDK EAL (see DPDK
-    /// configuration). For instance `--no-huge`.
-    /// Defaults to empty string.
-    #[serde(default = "default_dpdk_supl_args")]
-    pub dpdk_supl_args: Vec<String>,
-
-    /// Live performance monitoring. Defaults to `None`.
-    #[serde(default = "default_monitor")]
-    pub monitor: Option<MonitorConfig>,
-
-    /// List of network interfaces to read from.
-    pub ports: Vec<PortMap>,
+    /// Unlike [ConnTrackConfig::max_connections](ConnTrackConfig), which bounds the number of
+    /// simultaneous connections per core, this is the total number of connections over the run.
+    #[serde(default = "default_max_count")]
+    pub max_connections: Option<u64>,
 }
 
-fn default_duration() -> Option<u64> {
+fn default_max_count() -> Option<u64> {
     None
 }
 
-fn default_hardware_assist() -> bool {
-    true
-}
+/* --------------------------------------------------------------------------------- */
 
-fn default_dpdk_supl_args() -> Vec<String> {
-    Vec::new()
-}
+/// Packet capture options.
+///
+/// Subscriptions that request packet capture have all packets of their matched connections
+/// written to pcap files, including the packets received before the connection matched. Each core
+/// writes its own sequence of files, named `core<id>_<n>.pcap`, to `directory`.
+///
+/// The same options configure a [PcapSink](crate::utils::pcap::PcapSink), which callbacks can use
+/// to write the packets of a subscription to a separate set of files.
+///
+/// ## Example
+/// ```toml
+/// [pcap_writer]
+///     directory = "./pcap"
+///     snaplen = 128
+///     rotate_size = 1_073_741_824
+///     rotate_interval = 3600
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PcapWriterConfig {
+    /// Output directory, created if it does not exist. Defaults to `"./pcap"`.
+    #[serde(default = "default_pcap_directory")]
+    pub directory: String,
 
-fn default_promiscuous() -> bool {
-    true
+    /// Maximum number of bytes captured per packet. Defaults to `65535`.
+    #[serde(default = "default_snaplen")]
+    pub snaplen: u32,
+
+    /// If set, a new file is started once the current file exceeds `rotate_size` bytes. Defaults
+    /// to `None`.
+    #[serde(default = "default_max_count")]
+    pub rotate_size: Option<u64>,
+
+    /// If set, a new file is started once the current file is `rotate_interval` seconds old.
+    /// Defaults to `None`.
+    #[serde(default = "default_max_count")]
+    pub rotate_interval: Option<u64>,
 }
 
-fn default_portqueue_nb_rxd() -> usize {
-    4096
+impl Default for PcapWriterConfig {
+    fn default() -> Self {
+        PcapWriterConfig {
+            directory: default_pcap_directory(),
+            snaplen: default_snaplen(),
+            rotate_size: None,
+            rotate_interval: None,
+        }
+    }
 }
 
-fn default_mtu() -> usize {
-    1500
+fn default_pcap_directory() -> String {
+    "./pcap".to_string()
 }
 
-fn default_monitor() -> Option<MonitorConfig> {
-    None
+fn default_snaplen() -> u32 {
+    65535
 }
 
 /* --------------------------------------------------------------------------------- */
 
-/// Sink core options.
-///
-/// A "sink" core is a utility core whose sole purpose is to drop received traffic. This is useful
-/// for connection sampling, as entire 4-tuples can be discarded by redirecting them to the sink
-/// core.
+/// Options of a [ParquetSink](crate::utils::parquet::ParquetSink), available with the `parquet`
+/// feature.
 ///
-/// ## Remarks
-/// Adding a sink core prevents ethtool counters from classifying intentionally discarded packets as
-/// packet loss. However, it can be quite wasteful of system resources, as it requires configuring
-/// one additional core per interface and thrashes the cache.
+/// Each core buffers the records written to the sink into Arrow record batches of `batch_size`
+/// rows, and writes the batches to its own sequence of Parquet files, named `core<id>_<n>.parquet`,
+/// in `directory`.
 ///
 /// ## Example
 /// ```toml
-/// [online.ports.sink]
-///     core = 9
-///     nb_buckets = 384   # drops 25% of 4-tuples
+/// [parquet]
+///     directory = "./parquet/tls"
+///     batch_size = 8192
+///     rotate_size = 268_435_456
+///     rotate_interval = 3600
+///     compression = "zstd"
 /// ```
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct SinkConfig {
-    /// Sink core identifier.
-    pub core: u32,
+pub struct ParquetConfig {
+    /// Output directory, created if it does not exist. Defaults to `"./parquet"`.
+    #[serde(default = "default_parquet_directory")]
+    pub directory: String,
 
-    /// Number of RSS redirection table buckets to use for receive queues. Defaults to `512`, which
-    /// indicates no sampling.
-    ///
-    /// ## Remarks
-    /// Connection sampling is implemented by only polling from a fraction of the available RSS
-    /// redirection buckets. `nb_buckets` must range from the number of cores polling the port (call
-    /// this `n`) to `512`, which is the maximum number of buckets in the RSS redirection table. It
-    /// is recommended that `nb_buckets` be a multiple of `n` for better load balancing. For
-    /// example, setting `nb_buckets = 256` would drop 50% of connections.
-    #[serde(default = "default_nb_buckets")]
-    pub nb_buckets: usize,
+    /// Number of rows buffered by each core before they are written as a record batch. Defaults
+    /// to `8192`.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+
+    /// If set, a new file is started once the current file exceeds `rotate_size` bytes. Checked
+    /// after each record batch. Defaults to `None`.
+    #[serde(default = "default_max_count")]
+    pub rotate_size: Option<u64>,
+
+    /// If set, a new file is started once the current file is `rotate_interval` seconds old.
+    /// Checked after each record batch. Defaults to `None`.
+    #[serde(default = "default_max_count")]
+    pub rotate_interval: Option<u64>,
+
+    /// Compression codec of the column chunks. Defaults to `"snappy"`.
+    #[serde(default)]
+    pub compression: ParquetCompression,
 }
 
-fn default_nb_buckets() -> usize {
-    512
+impl Default for ParquetConfig {
+    fn default() -> Self {
+        ParquetConfig {
+            directory: default_parquet_directory(),
+            batch_size: default_batch_size(),
+            rotate_size: None,
+            rotate_interval: None,
+            compression: ParquetCompression::default(),
+        }
+    }
+}
+
+/// Compression codec of Parquet files.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParquetCompression {
+    /// No compression.
+    None,
+    /// Snappy: fast, with moderate compression.
+    #[default]
+    Snappy,
+    /// Zstandard: slower, with better compression.
+    Zstd,
+}
+
+fn default_parquet_directory() -> String {
+    "./parquet".to_string()
+}
+
+fn default_batch_size() -> usize {
+    8192
 }
 
 /* --------------------------------------------------------------------------------- */
 
-/// Network interface options.
+/// Options of a [KafkaSink](crate::utils::kafka::KafkaSink), available with the `kafka` feature.
+///
+/// Callbacks serialize records on the packet processing cores and queue them on a bounded queue,
+/// which is drained by `sink_threads` dedicated threads that publish them to Kafka. If the queue
+/// is full (e.g., the brokers cannot keep up), `drop_policy` applies. If the producer's own queue
+/// is full, sink threads wait for deliveries to complete before publishing more.
 ///
 /// ## Example
 /// ```toml
-/// [[online.ports]]
-///     device = "0000:3b:00.0"
-///     cores = [1,2,3,4,5,6,7,8]
+/// [kafka]
+///     brokers = "kafka1:9092,kafka2:9092"
+///     topic = "retina.tls"
+///     sink_threads = 2
+///     queue_depth = 65536
+///     batch_size = 1000
+///     linger_ms = 5
+///     drop_policy = "drop_newest"
+///
+///     [kafka.properties]
+///         "compression.type" = "lz4"
 /// ```
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct PortMap {
-    /// PCI address of interface.
-    pub device: String,
+pub struct KafkaConfig {
+    /// Comma-separated list of bootstrap brokers. Defaults to `"localhost:9092"`.
+    #[serde(default = "default_kafka_brokers")]
+    pub brokers: String,
+
+    /// Topic records are published to, unless sent to another topic. Defaults to `"retina"`.
+    #[serde(default = "default_kafka_topic")]
+    pub topic: String,
+
+    /// Number of threads publishing records. These should not be pinned to cores used for packet
+    /// processing. Defaults to `1`.
+    #[serde(default = "default_sink_threads")]
+    pub sink_threads: usize,
+
+    /// Maximum number of records queued for the sink threads. Defaults to `65536`.
+    #[serde(default = "default_sink_queue_depth")]
+    pub queue_depth: usize,
+
+    /// Maximum number of records a sink thread takes from the queue at once, and per message
+    /// batch of the producer. Defaults to `1000`.
+    #[serde(default = "default_kafka_batch_size")]
+    pub batch_size: usize,
+
+    /// Time (in milliseconds) the producer waits for more records before sending a message
+    /// batch. Defaults to `5`.
+    #[serde(default = "default_linger_ms")]
+    pub linger_ms: u64,
+
+    /// Policy applied when the queue is full. Defaults to `"drop_newest"`.
+    #[serde(default = "default_drop_policy")]
+    pub drop_policy: DropPolicy,
+
+    /// Additional [librdkafka
+    /// properties](https://github.com/confluentinc/librdkafka/blob/master/CONFIGURATION.md) of the
+    /// producer (e.g., compression or authentication). Defaults to none.
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+}
 
-    /// List of packet processing cores used to poll the interface.
-    ///
-    /// ## Remarks
-    /// For performance, it is recommended that the processing cores reside on the same NUMA node as
-    /// the PCI device.
-    pub cores: Vec<u32>,
+impl Default for KafkaConfig {
+    fn default() -> Self {
+        KafkaConfig {
+            brokers: default_kafka_brokers(),
+            topic: default_kafka_topic(),
+            sink_threads: default_sink_threads(),
+            queue_depth: default_sink_queue_depth(),
+            batch_size: default_kafka_batch_size(),
+            linger_ms: default_linger_ms(),
+            drop_policy: default_drop_policy(),
+            properties: BTreeMap::new(),
+        }
+    }
+}
 
-    /// Sink core configuration. Defaults to `None`.
-    #[serde(default = "default_sink")]
-    pub sink: Option<SinkConfig>,
+fn default_kafka_brokers() -> String {
+    "localhost:9092".to_string()
+}
+
+fn default_kafka_topic() -> String {
+    "retina".to_string()
+}
+
+fn default_sink_threads() -> usize {
+    1
+}
+
+fn default_sink_queue_depth() -> usize {
+    65536
+}
+
+fn default_kafka_batch_size() -> usize {
+    1000
+}
+
+fn default_linger_ms() -> u64 {
+    5
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Options of a [ZmqSink](crate::utils::zmq::ZmqSink), available with the `zmq` feature.
+///
+/// The sink publishes records on a ZeroMQ PUB socket as two-part messages: a topic, then the record
+/// serialized as JSON. Subscribers select records by topic prefix. The topic of a record is the one
+/// mapped to the subscription that sent it in `topics`, or the subscription's name otherwise.
+///
+/// Callbacks queue records on a bounded queue, drained by a dedicated thread that owns the socket.
+/// If the queue is full, `drop_policy` applies. Subscribers that cannot keep up lose messages once
+/// their `high_water_mark` is reached, without slowing down the sink.
+///
+/// ## Example
+/// ```toml
+/// [zmq]
+///     endpoint = "tcp://*:5556"
+///     queue_depth = 65536
+///     high_water_mark = 100000
+///     drop_policy = "drop_newest"
+///
+///     [zmq.topics]
+///         tls_cb = "tls"
+///         dns_cb = "dns"
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ZmqConfig {
+    /// Endpoint the PUB socket binds to. Defaults to `"tcp://*:5556"`.
+    #[serde(default = "default_zmq_endpoint")]
+    pub endpoint: String,
+
+    /// Maximum number of records queued for the sink thread. Defaults to `65536`.
+    #[serde(default = "default_sink_queue_depth")]
+    pub queue_depth: usize,
+
+    /// Maximum number of messages ZeroMQ buffers per subscriber. Defaults to `100000`.
+    #[serde(default = "default_high_water_mark")]
+    pub high_water_mark: i32,
+
+    /// Policy applied when the queue is full. Defaults to `"drop_newest"`.
+    #[serde(default = "default_drop_policy")]
+    pub drop_policy: DropPolicy,
+
+    /// Topic of the records sent with each key (see `ZmqSink::send`), e.g., the name of the
+    /// callback that sends them. Defaults to none (the key is the topic).
+    #[serde(default)]
+    pub topics: BTreeMap<String, String>,
+}
+
+impl Default for ZmqConfig {
+    fn default() -> Self {
+        ZmqConfig {
+            endpoint: default_zmq_endpoint(),
+            queue_depth: default_sink_queue_depth(),
+            high_water_mark: default_high_water_mark(),
+            drop_policy: default_drop_policy(),
+            topics: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_zmq_endpoint() -> String {
+    "tcp://*:5556".to_string()
+}
+
+fn default_high_water_mark() -> i32 {
+    100000
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// gRPC control-plane options, available with the `grpc` feature.
+///
+/// If set, Retina serves the `retina.control.v1.Control` service (defined in
+/// `core/proto/control.proto`) at `address`, so that an orchestrator can manage a fleet of
+/// instances: read the runtime status and statistics, toggle [subscription
+/// groups](crate::subscription::group), and update [filter sets](crate::filter::sets). The service
+/// is unauthenticated, so `address` should only be reachable from the management network.
+///
+/// ## Example
+/// ```toml
+/// [control]
+///     address = "10.0.0.5:50051"
+///     allow_shutdown = true
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ControlConfig {
+    /// Socket address to serve the control plane on. Defaults to `"127.0.0.1:50051"`.
+    #[serde(default = "default_control_address")]
+    pub address: String,
+
+    /// Whether clients can stop the runtime. Defaults to `false`.
+    #[serde(default)]
+    pub allow_shutdown: bool,
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        ControlConfig {
+            address: default_control_address(),
+            allow_shutdown: false,
+        }
+    }
+}
+
+fn default_control_address() -> String {
+    "127.0.0.1:50051".to_string()
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Async callback options.
+///
+/// Async callbacks (`async fn`) are not run on the packet processing cores. Instead, each core
+/// hands a copy of the subscribed data to a bounded queue per subscription, which is drained by a
+/// dedicated tokio runtime. If a queue is full, the delivery is dropped rather than stalling the
+/// core, and the number of dropped deliveries is logged when the runtime stops.
+///
+/// ## Example
+/// ```toml
+/// [async_callbacks]
+///     worker_threads = 4
+///     queue_depth = 4096
+///     max_in_flight = 256
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AsyncConfig {
+    /// Number of tokio worker threads. These should not be pinned to cores used for packet
+    /// processing. Defaults to `2`.
+    #[serde(default = "default_async_worker_threads")]
+    pub worker_threads: usize,
+
+    /// Maximum number of deliveries queued per subscription. Defaults to `1024`.
+    #[serde(default = "default_async_queue_depth")]
+    pub queue_depth: usize,
+
+    /// Maximum number of callbacks running concurrently per subscription. Defaults to `64`.
+    #[serde(default = "default_async_max_in_flight")]
+    pub max_in_flight: usize,
+
+    /// Time to wait, in milliseconds, for queued and running callbacks to complete once the
+    /// runtime stops. Defaults to `5000`.
+    #[serde(default = "default_async_drain_timeout")]
+    pub drain_timeout: u64,
+}
+
+impl Default for AsyncConfig {
+    fn default() -> Self {
+        AsyncConfig {
+            worker_threads: default_async_worker_threads(),
+            queue_depth: default_async_queue_depth(),
+            max_in_flight: default_async_max_in_flight(),
+            drain_timeout: default_async_drain_timeout(),
+        }
+    }
+}
+
+fn default_async_worker_threads() -> usize {
+    2
+}
+
+fn default_async_queue_depth() -> usize {
+    1024
+}
+
+fn default_async_max_in_flight() -> usize {
+    64
+}
+
+fn default_async_drain_timeout() -> u64 {
+    5000
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Callback worker pool options.
+///
+/// By default, callbacks run inline on the packet processing cores, so a slow callback delays
+/// packet processing and can cause packet loss. With `workers > 0`, callbacks instead run on a
+/// pool of `workers` threads: the packet processing core copies the subscribed data and queues the
+/// call on a bounded queue per subscription. When a queue is full, its `drop_policy` determines
+/// whether the new delivery is dropped, the oldest queued delivery is dropped, or the core waits
+/// for space.
+///
+/// Callbacks run on the pool cannot return a verdict, and subscriptions to packets (or packet
+/// lists) and to QUIC streams or session lists, which cannot be copied, always run inline.
+///
+/// ## Example
+/// ```toml
+/// [callbacks]
+///     workers = 4
+///     queue_depth = 4096
+///     drop_policy = "drop_newest"
+///
+/// [[callbacks.subscriptions]]
+///     callback = "log_conn"
+///     queue_depth = 65536
+///     drop_policy = "block"
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CallbackConfig {
+    /// Number of worker threads. Defaults to `0` (callbacks run inline).
+    #[serde(default)]
+    pub workers: usize,
+
+    /// Maximum number of deliveries queued per subscription. Defaults to `1024`.
+    #[serde(default = "default_callback_queue_depth")]
+    pub queue_depth: usize,
+
+    /// What to do when a subscription's queue is full. Defaults to `"drop_newest"`.
+    #[serde(default = "default_drop_policy")]
+    pub drop_policy: DropPolicy,
+
+    /// Per-subscription overrides of the queue depth and drop policy. Defaults to `[]`.
+    #[serde(default)]
+    pub subscriptions: Vec<CallbackQueueConfig>,
+}
+
+impl CallbackConfig {
+    /// Returns the queue depth and drop policy of the subscription with callback `callback`.
+    pub(crate) fn queue(&self, callback: &str) -> (usize, DropPolicy) {
+        let over = self.subscriptions.iter().find(|s| s.callback == callback);
+        (
+            over.and_then(|s| s.queue_depth).unwrap_or(self.queue_depth),
+            over.and_then(|s| s.drop_policy).unwrap_or(self.drop_policy),
+        )
+    }
+}
+
+impl Default for CallbackConfig {
+    fn default() -> Self {
+        CallbackConfig {
+            workers: 0,
+            queue_depth: default_callback_queue_depth(),
+            drop_policy: default_drop_policy(),
+            subscriptions: vec![],
+        }
+    }
+}
+
+fn default_callback_queue_depth() -> usize {
+    1024
+}
+
+fn default_drop_policy() -> DropPolicy {
+    DropPolicy::DropNewest
+}
+
+/// Queue settings of one subscription, identified by the name of its callback.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CallbackQueueConfig {
+    /// Name of the callback function.
+    pub callback: String,
+
+    /// Overrides [CallbackConfig::queue_depth](CallbackConfig).
+    #[serde(default)]
+    pub queue_depth: Option<usize>,
+
+    /// Overrides [CallbackConfig::drop_policy](CallbackConfig).
+    #[serde(default)]
+    pub drop_policy: Option<DropPolicy>,
+}
+
+/// Policy applied when a callback queue is full.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DropPolicy {
+    /// Drop the new delivery.
+    DropNewest,
+    /// Drop the oldest queued delivery to make room for the new one.
+    DropOldest,
+    /// Wait until the workers make room. This applies backpressure to the packet processing
+    /// core, which may drop packets instead.
+    Block,
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Memory pool options.
+///
+/// Retina manages packet buffer memory using DPDK's pool-based memory allocator. This takes
+/// advantage of built-in DPDK huge page support, NUMA affinity, and access to DMA addresses. See
+/// [Memory in DPDK](https://www.dpdk.org/blog/2019/08/21/memory-in-dpdk-part-1-general-concepts/)
+/// for more details.
+///
+/// By default, each mbuf holds a full frame of the configured MTU. On links with jumbo frames,
+/// setting `segment_size` allocates smaller mbufs instead: frames that do not fit are received in
+/// chains of mbufs (segments), which are copied into a single mbuf from a separate pool of
+/// `jumbo_capacity` full-size mbufs before they are parsed and reassembled.
+///
+/// If `capacity` is not set, each mempool is sized from the rest of the configuration: the
+/// receive and transmit descriptors of the ports on its socket, the dispatch rings, the per-core
+/// caches and bursts, and the packets that `buffered_connections` connections per core may buffer
+/// (more if a subscription tracks packets). The runtime fails at startup, with the amount of
+/// memory missing, if a mempool does not fit in the free hugepage memory of its socket.
+///
+/// To share a host with other DPDK applications, `socket_limit` caps the hugepage memory Retina
+/// uses on each socket (passed to the EAL as `--socket-limit`). Mempools are checked against the
+/// limit as well, and the memory used by each mempool is logged at startup. `cache_size` and
+/// `segment_size` further reduce the footprint, at the cost of per-core allocation speed and
+/// segmented frames.
+///
+/// Connections that track packets (e.g., for a `PacketList` subscription) hold on to their mbufs
+/// until they are delivered or the connection can no longer match, which can exhaust the receive
+/// mempool and cause the NIC to drop packets. In online mode, setting `tracked` stores copies of
+/// the tracked packets in a separate pool of smaller mbufs instead, optionally truncated to
+/// `snaplen` bytes, so that the receive mbufs are released as soon as the packet is processed.
+///
+/// ## Example
+/// ```toml
+/// [mempool]
+///     capacity = 1_048_576
+///     cache_size = 512
+///     segment_size = 2048
+/// ```
+///
+/// ```toml
+/// [mempool]
+///     buffered_connections = 50_000
+///     socket_limit = [2048, 2048]
+/// ```
+///
+/// ```toml
+/// [mempool.tracked]
+///     capacity = 262_143
+///     snaplen = 256
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MempoolConfig {
+    /// Number of mbufs allocated per mempool. The maximum value that can be set will depend on
+    /// the available memory (number of hugepages allocated) and the MTU. Defaults to `None` (sized
+    /// automatically).
+    #[serde(default = "default_capacity")]
+    pub capacity: Option<usize>,
+
+    /// The size of the per-core object cache. It is recommended that `cache_size` evenly divides
+    /// `capacity`. Defaults to `512`.
+    #[serde(default = "default_cache_size")]
+    pub cache_size: usize,
+
+    /// Expected number of connections per core that buffer packets at the same time (e.g.,
+    /// out-of-order TCP segments, or packets tracked until a subscription's filter matches). Only
+    /// used to size mempools automatically. Defaults to `4096`.
+    #[serde(default = "default_buffered_connections")]
+    pub buffered_connections: usize,
+
+    /// Maximum number of bytes of packet data per mbuf in online mode. Defaults to `None` (each
+    /// mbuf holds a full frame).
+    #[serde(default = "default_segment_size")]
+    pub segment_size: Option<usize>,
+
+    /// Number of full-size mbufs per socket that segmented frames are copied into, if
+    /// `segment_size` is set. Defaults to `8192`.
+    #[serde(default = "default_jumbo_capacity")]
+    pub jumbo_capacity: usize,
+
+    /// Separate storage for the packets tracked by connections, in online mode. Defaults to `None`
+    /// (tracked packets hold on to the mbufs they were received in).
+    #[serde(default = "default_tracked")]
+    pub tracked: Option<TrackedPacketsConfig>,
+
+    /// Maximum hugepage memory used on each socket, in MB, indexed by socket ID. `0` leaves a
+    /// socket unlimited. Defaults to `[]` (no limits).
+    #[serde(default = "default_socket_limit")]
+    pub socket_limit: Vec<usize>,
+}
+
+impl MempoolConfig {
+    /// Checks the mempool sizes against the limits of DPDK mempools.
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if self.cache_size > MAX_CACHE_SIZE {
+            return Err(format!(
+                "cache_size {} exceeds the maximum of {}",
+                self.cache_size, MAX_CACHE_SIZE
+            ));
+        }
+        // DPDK rejects pools whose per-core caches could hold more than the pool itself
+        let check_capacity = |name: &str, capacity: usize| {
+            if self.cache_size * 3 / 2 > capacity {
+                return Err(format!(
+                    "{} {} is too small for cache_size {} (at least {})",
+                    name,
+                    capacity,
+                    self.cache_size,
+                    self.cache_size * 3 / 2
+                ));
+            }
+            Ok(())
+        };
+        if let Some(capacity) = self.capacity {
+            check_capacity("capacity", capacity)?;
+        }
+        if let Some(segment_size) = self.segment_size {
+            if segment_size < MIN_FRAME_LEN {
+                return Err(format!(
+                    "segment_size {} is smaller than a minimum-size frame ({} bytes)",
+                    segment_size, MIN_FRAME_LEN
+                ));
+            }
+            check_capacity("jumbo_capacity", self.jumbo_capacity)?;
+        }
+        if let Some(tracked) = &self.tracked {
+            if tracked
+                .snaplen
+                .is_some_and(|snaplen| snaplen < MIN_FRAME_LEN)
+            {
+                return Err(format!(
+                    "tracked snaplen must be at least {} bytes",
+                    MIN_FRAME_LEN
+                ));
+            }
+            check_capacity("tracked capacity", tracked.capacity)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the memory limit of `socket_id`, in MB, if set.
+    pub(crate) fn socket_limit(&self, socket_id: SocketId) -> Option<usize> {
+        self.socket_limit
+            .get(socket_id.raw() as usize)
+            .copied()
+            .filter(|limit| *limit > 0)
+    }
+}
+
+fn default_capacity() -> Option<usize> {
+    None
+}
+
+fn default_buffered_connections() -> usize {
+    4096
+}
+
+fn default_cache_size() -> usize {
+    512
+}
+
+fn default_segment_size() -> Option<usize> {
+    None
+}
+
+fn default_jumbo_capacity() -> usize {
+    8192
+}
+
+fn default_socket_limit() -> Vec<usize> {
+    vec![]
+}
+
+fn default_tracked() -> Option<TrackedPacketsConfig> {
+    None
+}
+
+/// Storage options for tracked packets.
+///
+/// Each packet a connection tracks is copied into an mbuf of its own per-socket pool, allocated
+/// through the per-core cache of the processing core, and the receive mbuf is released. Mbufs hold
+/// at most `snaplen` bytes: longer packets are truncated, but keep their original length. If the
+/// pool is exhausted, the packet is dropped from the connection's tracked packets and counted in
+/// the `untracked_pkts` metric, so that the receive mempool is never held on to.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TrackedPacketsConfig {
+    /// Number of mbufs allocated per socket for tracked packets. Defaults to `262_143`.
+    #[serde(default = "default_tracked_capacity")]
+    pub capacity: usize,
+
+    /// Maximum number of bytes stored per tracked packet. Defaults to `None` (a full frame of the
+    /// configured MTU).
+    #[serde(default = "default_tracked_snaplen")]
+    pub snaplen: Option<usize>,
+}
+
+fn default_tracked_capacity() -> usize {
+    262_143
+}
+
+fn default_tracked_snaplen() -> Option<usize> {
+    None
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Live traffic analysis options.
+///
+/// Online mode performs traffic analysis on a live network interface. Either
+/// [OnlineConfig](OnlineConfig) or [OfflineConfig](OfflineConfig) must be specified, but not both.
+///
+/// Multiple ports can be captured in one runtime, each with its own set of cores (e.g., a tap that
+/// splits the two directions of a link across two NICs). Traffic from all ports is processed by the
+/// same subscriptions. A core may poll receive queues of several ports, but a sink core cannot
+/// also be a packet processing core.
+///
+/// If each port sees only one direction of the traffic, list the pair in `links` so that both
+/// directions of a connection are processed by the same core. Linked ports must be assigned the
+/// same cores (and the same sink configuration), and are configured with the same symmetric RSS
+/// hash, so a connection is steered to the same core regardless of the port it arrives on.
+///
+/// ## Example
+/// ```toml
+/// [online]
+///     duration = 30
+///     nb_rxd = 32768
+///     promiscuous = true
+///     mtu = 1500
+///     hardware_assist = true
+///     dpdk_supl_args = []
+///
+/// [online.monitor.display]
+///     throughput = true
+///     mempool_usage = true
+///
+///     [online.monitor.log]
+///         directory = "./log"
+///         interval = 1000
+///
+///     [[online.ports]]
+///         device = "0000:3b:00.0"
+///         cores = [1,2,3,4]
+///
+///     [[online.ports]]
+///         device = "0000:3b:00.1"
+///         cores = [5,6,7,8]
+/// ```
+///
+/// Two ports capturing opposite directions of the same link:
+/// ```toml
+/// [online]
+///     links = [["0000:3b:00.0", "0000:3b:00.1"]]
+///
+///     [[online.ports]]
+///         device = "0000:3b:00.0"
+///         cores = [1,2,3,4]
+///
+///     [[online.ports]]
+///         device = "0000:3b:00.1"
+///         cores = [1,2,3,4]
+/// ```
+///
+/// In inline mode, Retina bridges pairs of ports listed in `bridges`: packets received on one port
+/// are transmitted on the other, unless a callback returns [Verdict::Drop](crate::Verdict) for
+/// them. Bridged ports are linked, must not have sink cores, and are never filtered in hardware.
+/// Packets that subscriptions divert are transmitted on the `divert` port instead.
+///
+/// Copies of the packets that subscriptions mirror are transmitted on the `mirror` port, in inline
+/// mode or not (see [MirrorConfig](MirrorConfig)).
+///
+/// Callbacks and the application can also send their own packets (e.g., TCP resets or replayed
+/// test traffic) on the ports listed in `transmit`, with or without bridges.
+/// ```toml
+/// [online]
+///     bridges = [["0000:3b:00.0", "0000:3b:00.1"]]
+///     divert = "0000:5e:00.0"
+///
+///     [[online.ports]]
+///         device = "0000:3b:00.0"
+///         cores = [1,2,3,4]
+///
+///     [[online.ports]]
+///         device = "0000:3b:00.1"
+///         cores = [1,2,3,4]
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OnlineConfig {
+    /// If set, the applicaton will stop after `duration` seconds. Defaults to `None`.
+    #[serde(default = "default_duration")]
+    pub duration: Option<u64>,
+
+    /// Whether promiscuous mode is enabled for all ports. Defaults to `true`.
+    #[serde(default = "default_promiscuous")]
+    pub promiscuous: bool,
+
+    /// The number of RX descriptors per receive queue. Defaults to `4096`.
+    ///
+    /// Receive queues are polled for packets using a run-to-completion model. Deeper queues will be
+    /// more tolerant of processing delays at the cost of higher memory usage and hugepage
+    /// reservation.
+    #[serde(default = "default_portqueue_nb_rxd")]
+    pub nb_rxd: usize,
+
+    /// The number of TX descriptors per transmit queue. Defaults to `4096`.
+    #[serde(default = "default_portqueue_nb_rxd")]
+    pub nb_txd: usize,
+
+    /// Maximum transmission unit (in bytes) allowed for ingress packets. Defaults to `1500`.
+    ///
+    /// To capture jumbo frames, set this value higher (e.g., `9000`), and consider setting the
+    /// mempool `segment_size` (see [MempoolConfig](MempoolConfig)).
+    #[serde(default = "default_mtu")]
+    pub mtu: usize,
+
+    /// If set, will attempt to offload parts of the filter to the NIC, depending on its hardware
+    /// filtering support. Defaults to `true`.
+    #[serde(default = "default_hardware_assist")]
+    pub hardware_assist: bool,
+
+    /// If set, ports that support it timestamp packets on reception, and connection tracking and
+    /// datatypes use the NIC time instead of the time packets are processed (see
+    /// [Mbuf::timestamp](crate::Mbuf::timestamp)). Defaults to `false`.
+    #[serde(default = "default_hardware_timestamps")]
+    pub hardware_timestamps: bool,
+
+    /// If set, will pass supplementary arguments to DPDK EAL (see DPDK
+    /// configuration). For instance `--no-huge`.
+    /// Defaults to empty string.
+    #[serde(default = "default_dpdk_supl_args")]
+    pub dpdk_supl_args: Vec<String>,
+
+    /// Live performance monitoring. Defaults to `None`.
+    #[serde(default = "default_monitor")]
+    pub monitor: Option<MonitorConfig>,
+
+    /// List of network interfaces to read from.
+    pub ports: Vec<PortMap>,
+
+    /// Pairs of ports (by device) that capture the two directions of the same link. Defaults to
+    /// `[]`.
+    #[serde(default = "default_links")]
+    pub links: Vec<[String; 2]>,
+
+    /// Pairs of ports (by device) to bridge in inline mode. Defaults to `[]`.
+    #[serde(default = "default_links")]
+    pub bridges: Vec<[String; 2]>,
+
+    /// Port (by device) that diverted packets are transmitted on in inline mode. Must be one of
+    /// the `ports`. Defaults to `None` (diverted packets are dropped).
+    #[serde(default = "default_divert")]
+    pub divert: Option<String>,
+
+    /// Ports (by device) that packets can be transmitted on from callbacks (see
+    /// [transmit](crate::lcore::transmit)) and from the application (see
+    /// [Runtime::transmitter](crate::Runtime::transmitter)). Defaults to `[]`.
+    #[serde(default = "default_transmit")]
+    pub transmit: Vec<String>,
+
+    /// Software dispatch configuration. Defaults to `None` (packets are processed on the cores
+    /// that poll the ports).
+    #[serde(default = "default_dispatch")]
+    pub dispatch: Option<DispatchConfig>,
+
+    /// How to handle cores that reside on a different NUMA node (socket) than the port they poll.
+    /// Defaults to `"warn"`.
+    #[serde(default = "default_numa")]
+    pub numa: NumaPolicy,
+
+    /// Load shedding configuration. Defaults to `None` (cores never shed load, and packets are
+    /// dropped by the NIC when the receive queues overflow).
+    #[serde(default = "default_overload")]
+    pub overload: Option<OverloadConfig>,
+
+    /// Port mirroring configuration. Defaults to `None` (mirrored packets are not transmitted).
+    #[serde(default = "default_mirror")]
+    pub mirror: Option<MirrorConfig>,
+
+    /// Back-off of idle cores. Defaults to `None` (cores busy-poll their queues).
+    #[serde(default = "default_idle")]
+    pub idle: Option<IdleConfig>,
+}
+
+impl OnlineConfig {
+    /// Returns whether the ports must be configured to raise receive interrupts.
+    pub(crate) fn rx_interrupts(&self) -> bool {
+        matches!(
+            self.idle,
+            Some(IdleConfig {
+                mode: IdleMode::Interrupt,
+                ..
+            })
+        )
+    }
+
+    /// Checks that the port-to-core assignments are consistent across ports.
+    pub(crate) fn check_ports(&self) -> Result<(), String> {
+        let mut devices = HashSet::new();
+        let mut rx_cores = HashSet::new();
+        let mut sink_cores = HashSet::new();
+        for port in self.ports.iter() {
+            if !devices.insert(port.device.as_str()) {
+                return Err(format!("duplicate port {}", port.device));
+            }
+            if port.cores.is_empty() {
+                return Err(format!("no cores assigned to port {}", port.device));
+            }
+            port.rss
+                .check()
+                .map_err(|error| format!("port {}: {}", port.device, error))?;
+            if let Some(virtual_port) = &port.virtual_port {
+                virtual_port
+                    .check(&port.device)
+                    .map_err(|error| format!("port {}: {}", port.device, error))?;
+            }
+            rx_cores.extend(port.cores.iter().copied());
+            if let Some(sink) = &port.sink {
+                sink_cores.insert(sink.core);
+            }
+        }
+        if let Some(core) = rx_cores.intersection(&sink_cores).next() {
+            return Err(format!(
+                "core {} is assigned as both an RX and sink core",
+                core
+            ));
+        }
+        if let Some(dispatch) = &self.dispatch {
+            if dispatch.cores.is_empty() {
+                return Err("no worker cores assigned for software dispatch".to_string());
+            }
+            if let Some(core) = dispatch
+                .cores
+                .iter()
+                .find(|c| rx_cores.contains(c) || sink_cores.contains(c))
+            {
+                return Err(format!(
+                    "dispatch worker core {} is also assigned to a port",
+                    core
+                ));
+            }
+            if !dispatch.ring_size.is_power_of_two() {
+                return Err(format!(
+                    "dispatch ring size {} is not a power of 2",
+                    dispatch.ring_size
+                ));
+            }
+            if !self.bridges.is_empty() {
+                return Err("software dispatch is not supported in inline mode".to_string());
+            }
+        }
+        if let Some(divert) = &self.divert {
+            if self.port(divert).is_none() {
+                return Err(format!("divert port {} is not configured", divert));
+            }
+            if self.bridges.is_empty() {
+                return Err("divert port is only used in inline mode".to_string());
+            }
+        }
+        if let Some(device) = self.transmit.iter().find(|d| self.port(d).is_none()) {
+            return Err(format!("transmit port {} is not configured", device));
+        }
+        if let Some(mirror) = &self.mirror {
+            if self.port(&mirror.device).is_none() {
+                return Err(format!("mirror port {} is not configured", mirror.device));
+            }
+            mirror.check()?;
+        }
+        for [a, b] in self.bridges.iter() {
+            if let Some(port) = [a, b]
+                .into_iter()
+                .filter_map(|device| self.port(device))
+                .find(|port| port.sink.is_some())
+            {
+                return Err(format!("bridged port {} has a sink core", port.device));
+            }
+        }
+
+        // Bridged ports are also linked
+        let mut linked = HashSet::new();
+        for [a, b] in self.links.iter().chain(self.bridges.iter()) {
+            let (port_a, port_b) = match (self.port(a), self.port(b)) {
+                (Some(port_a), Some(port_b)) => (port_a, port_b),
+                _ => return Err(format!("link {} <-> {} refers to an unknown port", a, b)),
+            };
+            if a == b || !linked.insert(a) || !linked.insert(b) {
+                return Err(format!(
+                    "port in link {} <-> {} is linked more than once",
+                    a, b
+                ));
+            }
+            let mut cores_a = port_a.cores.clone();
+            cores_a.sort_unstable();
+            cores_a.dedup();
+            let mut cores_b = port_b.cores.clone();
+            cores_b.sort_unstable();
+            cores_b.dedup();
+            if cores_a != cores_b {
+                return Err(format!("linked ports {} and {} use different cores", a, b));
+            }
+            let buckets_a = port_a.sink.as_ref().map(|s| s.nb_buckets);
+            let buckets_b = port_b.sink.as_ref().map(|s| s.nb_buckets);
+            if buckets_a != buckets_b {
+                return Err(format!(
+                    "linked ports {} and {} use different sink configurations",
+                    a, b
+                ));
+            }
+            if port_a.rss != port_b.rss {
+                return Err(format!(
+                    "linked ports {} and {} use different RSS configurations",
+                    a, b
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the port with PCI address `device`, if it is configured.
+    fn port(&self, device: &str) -> Option<&PortMap> {
+        self.ports.iter().find(|p| p.device == device)
+    }
+
+    /// Returns the device linked (or bridged) with `device`, if any.
+    pub(crate) fn linked_device(&self, device: &str) -> Option<&str> {
+        self.links
+            .iter()
+            .chain(self.bridges.iter())
+            .find_map(|[a, b]| {
+                if a == device {
+                    Some(b.as_str())
+                } else if b == device {
+                    Some(a.as_str())
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Returns the device bridged with `device`, if any.
+    pub(crate) fn bridged_device(&self, device: &str) -> Option<&str> {
+        self.bridges.iter().find_map(|[a, b]| {
+            if a == device {
+                Some(b.as_str())
+            } else if b == device {
+                Some(a.as_str())
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Returns the number of receive or transmit queues of `port`, whichever is larger.
+    pub(crate) fn nb_queues(&self, port: &PortMap) -> usize {
+        let mut rx_cores = port.cores.clone();
+        rx_cores.sort_unstable();
+        rx_cores.dedup();
+        let nb_rx = rx_cores.len() + port.sink.iter().count();
+        let transmit = self.transmit.iter().any(|d| *d == port.device);
+        let nb_tx = self.tx_cores(&port.device).len() + transmit as usize;
+        cmp::max(nb_rx, nb_tx)
+    }
+
+    /// Returns the cores that transmit on `device`: the cores of the port bridged with `device`,
+    /// for the divert port, the cores of all bridged ports, and for `transmit` ports and the mirror
+    /// port, all cores that process packets.
+    pub(crate) fn tx_cores(&self, device: &str) -> Vec<u32> {
+        let is_divert = self.divert.as_deref() == Some(device);
+        let is_transmit = self.transmit.iter().any(|d| d == device)
+            || self.mirror.as_ref().is_some_and(|m| m.device == device);
+        let mut cores = vec![];
+        for port in self.ports.iter() {
+            match self.bridged_device(&port.device) {
+                _ if is_transmit => cores.extend(port.cores.iter()),
+                Some(peer) if peer == device || is_divert => cores.extend(port.cores.iter()),
+                _ => (),
+            }
+        }
+        if let Some(dispatch) = self.dispatch.as_ref().filter(|_| is_transmit) {
+            cores.extend(dispatch.cores.iter());
+        }
+        cores.sort_unstable();
+        cores.dedup();
+        cores
+    }
+}
+
+fn default_divert() -> Option<String> {
+    None
+}
+
+fn default_transmit() -> Vec<String> {
+    vec![]
+}
+
+fn default_hardware_timestamps() -> bool {
+    false
+}
+
+fn default_links() -> Vec<[String; 2]> {
+    vec![]
+}
+
+fn default_dispatch() -> Option<DispatchConfig> {
+    None
+}
+
+fn default_numa() -> NumaPolicy {
+    NumaPolicy::Warn
+}
+
+fn default_overload() -> Option<OverloadConfig> {
+    None
+}
+
+fn default_mirror() -> Option<MirrorConfig> {
+    None
+}
+
+fn default_idle() -> Option<IdleConfig> {
+    None
+}
+
+fn default_duration() -> Option<u64> {
+    None
+}
+
+fn default_hardware_assist() -> bool {
+    true
+}
+
+fn default_dpdk_supl_args() -> Vec<String> {
+    Vec::new()
+}
+
+fn default_promiscuous() -> bool {
+    true
+}
+
+fn default_portqueue_nb_rxd() -> usize {
+    4096
+}
+
+fn default_mtu() -> usize {
+    1500
+}
+
+fn default_monitor() -> Option<MonitorConfig> {
+    None
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// NUMA placement policy.
+///
+/// Each port receives packets into a memory pool allocated on the NUMA node (socket) of the port
+/// (see [PortMap](PortMap)). Cores that poll a port from a different socket access packet memory
+/// across the interconnect, which reduces throughput. The policy determines whether such
+/// assignments are allowed (`"allow"`), logged as a warning (`"warn"`), or rejected at startup
+/// (`"deny"`).
+///
+/// ## Example
+/// ```toml
+/// [online]
+///     numa = "deny"
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NumaPolicy {
+    /// Allow cross-socket core assignments.
+    Allow,
+    /// Warn about cross-socket core assignments.
+    Warn,
+    /// Reject cross-socket core assignments.
+    Deny,
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Idle core options.
+///
+/// By default, packet processing cores poll their queues continuously, and use 100% of their CPU
+/// even without traffic. With an `IdleConfig`, a core that finds all of its queues empty for
+/// `threshold` consecutive polls backs off until packets arrive, according to `mode`:
+/// - `"pause"`: the core spins with a pause instruction between polls, which lowers its power
+///   usage and frees execution resources for a sibling hyperthread.
+/// - `"sleep"`: the core sleeps between polls, starting at 1 microsecond and doubling up to
+///   `max_sleep` microseconds.
+/// - `"interrupt"`: the core waits for a receive interrupt on any of its queues, for at most
+///   `max_sleep` microseconds. Ports are configured to raise receive interrupts, which not all
+///   drivers support. Cores that do not poll a port (software dispatch workers) sleep instead.
+///
+/// Backing off adds latency to the first packets after an idle period, and packets may be dropped
+/// if a burst fills the receive queues before the core wakes up. It is meant for lab and low-rate
+/// deployments, not for line-rate capture.
+///
+/// ## Example
+/// ```toml
+/// [online.idle]
+///     mode = "sleep"
+///     threshold = 1000
+///     max_sleep = 100
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct IdleConfig {
+    /// How an idle core backs off. Defaults to `"sleep"`.
+    #[serde(default = "default_idle_mode")]
+    pub mode: IdleMode,
+
+    /// Number of consecutive empty polls after which a core is idle. Defaults to `1000`.
+    #[serde(default = "default_idle_threshold")]
+    pub threshold: u32,
+
+    /// Maximum time an idle core waits between polls, in microseconds. Defaults to `100`.
+    #[serde(default = "default_max_sleep")]
+    pub max_sleep: u64,
+}
+
+fn default_idle_mode() -> IdleMode {
+    IdleMode::Sleep
+}
+
+fn default_idle_threshold() -> u32 {
+    1000
+}
+
+fn default_max_sleep() -> u64 {
+    100
+}
+
+/// How an idle core backs off.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdleMode {
+    /// Spin with a pause instruction between polls.
+    Pause,
+    /// Sleep between polls, with an exponential back-off.
+    Sleep,
+    /// Wait for a receive interrupt.
+    Interrupt,
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Load shedding options.
+///
+/// A core is overloaded when the fill level of any receive queue it polls (or, with software
+/// dispatch, of its worker ring) reaches `queue_threshold`, or when the fraction of mbufs in use in
+/// its socket's memory pool reaches `mempool_threshold`. While overloaded, the core sheds load
+/// according to `policy`, and stops once all fill levels have fallen below 80% of their
+/// thresholds. The fill levels are checked every few bursts.
+///
+/// Shedding policies:
+/// - `"no_new_connections"`: new connections are not tracked, so their packets are not processed.
+///   Connections that are already tracked are unaffected.
+/// - `"no_packet_tracking"`: connections stop buffering packets for packet-level subscriptions and
+///   `PacketList`s, and release the packets they have buffered. Deliveries of such subscriptions
+///   may be incomplete.
+/// - `"sample"`: only a `sample_rate` fraction of new connections are tracked, selected by a hash
+///   of the connection's 4-tuple.
+///
+/// The number of connections shed by each core is reported in the runtime metrics and logged when
+/// the runtime stops.
+///
+/// ## Example
+/// ```toml
+/// [online.overload]
+///     queue_threshold = 0.5
+///     mempool_threshold = 0.9
+///     policy = "sample"
+///     sample_rate = 0.25
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OverloadConfig {
+    /// Receive queue (or worker ring) fill level, between `0` and `1`, at which a core is
+    /// overloaded. Defaults to `0.75`.
+    #[serde(default = "default_queue_threshold")]
+    pub queue_threshold: f64,
+
+    /// Fraction of mbufs in use, between `0` and `1`, at which a core is overloaded. Defaults to
+    /// `0.9`.
+    #[serde(default = "default_mempool_threshold")]
+    pub mempool_threshold: f64,
+
+    /// How an overloaded core sheds load. Defaults to `"no_new_connections"`.
+    #[serde(default = "default_shed_policy")]
+    pub policy: ShedPolicy,
+
+    /// Fraction of new connections tracked under the `"sample"` policy. Defaults to `0.1`.
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: f64,
+}
+
+fn default_queue_threshold() -> f64 {
+    0.75
+}
+
+fn default_mempool_threshold() -> f64 {
+    0.9
+}
+
+fn default_shed_policy() -> ShedPolicy {
+    ShedPolicy::NoNewConnections
+}
+
+fn default_sample_rate() -> f64 {
+    0.1
+}
+
+/// How an overloaded core sheds load (see [OverloadConfig](OverloadConfig)).
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShedPolicy {
+    /// Stop tracking new connections.
+    NoNewConnections,
+    /// Stop buffering packets in tracked connections.
+    NoPacketTracking,
+    /// Track a sample of new connections.
+    Sample,
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Port mirroring options.
+///
+/// Subscriptions with the `mirror` action (e.g., `#[filter("tls.sni ~ 'example'", action =
+/// "mirror")]`) copy the packets they match to the mirror port, toward external tools such as an
+/// IDS. For subscriptions to connections or sessions, all packets of the connection from the packet
+/// on which the filter matched are mirrored. Mirrored packets are still forwarded in inline mode.
+///
+/// To preserve direction, copies of packets sent by the originator of the connection are tagged
+/// with VLAN `originator_vlan`, and those sent by the responder with VLAN `responder_vlan`. Packets
+/// mirrored by packet-level subscriptions have no connection direction and are tagged with
+/// `originator_vlan`. Tagging adds 4 bytes to each frame, which the MTU of the mirror port must
+/// allow for.
+///
+/// ## Example
+/// ```toml
+/// [online.mirror]
+///     device = "0000:5e:00.0"
+///     originator_vlan = 100
+///     responder_vlan = 200
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MirrorConfig {
+    /// Port (by device) that mirrored packets are transmitted on. Must be one of the `ports`.
+    pub device: String,
+
+    /// VLAN ID of packets sent by the originator of their connection. Defaults to `None` (packets
+    /// are mirrored untagged).
+    #[serde(default = "default_mirror_vlan")]
+    pub originator_vlan: Option<u16>,
+
+    /// VLAN ID of packets sent by the responder of their connection. Defaults to `None` (packets
+    /// are mirrored untagged).
+    #[serde(default = "default_mirror_vlan")]
+    pub responder_vlan: Option<u16>,
+}
+
+fn default_mirror_vlan() -> Option<u16> {
+    None
+}
+
+impl MirrorConfig {
+    pub(crate) fn check(&self) -> Result<(), String> {
+        for vlan in [self.originator_vlan, self.responder_vlan]
+            .into_iter()
+            .flatten()
+        {
+            if !(1..=4094).contains(&vlan) {
+                return Err(format!("invalid mirror VLAN ID {} (expected 1-4094)", vlan));
+            }
+        }
+        Ok(())
+    }
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Software dispatch options.
+///
+/// By default, the NIC distributes packets across the cores polling a port with a symmetric RSS
+/// hash (see [RssConfig](RssConfig)). Some devices (e.g., virtio NICs) do not support RSS, or only
+/// support non-symmetric keys, in which case the two directions of a connection may be processed
+/// on different cores. With software dispatch, the cores polling the ports instead compute a
+/// symmetric hash of each packet's 5-tuple and forward it to one of the worker `cores`, which
+/// perform connection tracking and run the subscription. Packets are dropped if a worker's ring is
+/// full.
+///
+/// Worker cores must not be assigned to any port.
+///
+/// ## Example
+/// ```toml
+/// [online.dispatch]
+///     cores = [2,3,4,5]
+///     ring_size = 8192
+///
+/// [[online.ports]]
+///     device = "0000:00:04.0"
+///     cores = [1]
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DispatchConfig {
+    /// Worker cores that process dispatched packets. Can be omitted if worker cores are assigned
+    /// in the `cores` section (see [CoreRoles](CoreRoles)).
+    #[serde(default = "default_role_cores")]
+    pub cores: Vec<u32>,
+
+    /// Number of slots in the ring of each worker core. Must be a power of 2. Defaults to `4096`.
+    #[serde(default = "default_dispatch_ring_size")]
+    pub ring_size: u32,
+}
+
+fn default_dispatch_ring_size() -> u32 {
+    4096
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Sink core options.
+///
+/// A "sink" core is a utility core whose sole purpose is to drop received traffic. This is useful
+/// for connection sampling, as entire 4-tuples can be discarded by redirecting them to the sink
+/// core.
+///
+/// ## Remarks
+/// Adding a sink core prevents ethtool counters from classifying intentionally discarded packets as
+/// packet loss. However, it can be quite wasteful of system resources, as it requires configuring
+/// one additional core per interface and thrashes the cache.
+///
+/// ## Example
+/// ```toml
+/// [online.ports.sink]
+///     core = 9
+///     nb_buckets = 384   # drops 25% of 4-tuples
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SinkConfig {
+    /// Sink core identifier.
+    pub core: u32,
+
+    /// Number of RSS redirection table buckets to use for receive queues. Defaults to `512`, which
+    /// indicates no sampling.
+    ///
+    /// ## Remarks
+    /// Connection sampling is implemented by only polling from a fraction of the available RSS
+    /// redirection buckets. `nb_buckets` must range from the number of cores polling the port (call
+    /// this `n`) to `512`, which is the maximum number of buckets in the RSS redirection table. It
+    /// is recommended that `nb_buckets` be a multiple of `n` for better load balancing. For
+    /// example, setting `nb_buckets = 256` would drop 50% of connections.
+    #[serde(default = "default_nb_buckets")]
+    pub nb_buckets: usize,
+}
+
+fn default_nb_buckets() -> usize {
+    512
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Virtual port options.
+///
+/// Virtual ports capture the traffic of virtual machines and containers without a physical NIC
+/// passed through to Retina:
+/// - `"vhost_user"`: Retina is the vhost-user backend of a virtio-net device, e.g., of a QEMU VM
+///   started with a `vhost-user` netdev on the same socket. The port's `device` must start with
+///   `net_vhost` (e.g., `net_vhost0`).
+/// - `"virtio_user"`: Retina is a virtio frontend attached to a vhost-user port of a virtual switch,
+///   e.g., an OVS-DPDK `dpdkvhostuserclient` port that OVS mirrors traffic to. The port's `device`
+///   must start with `net_virtio_user` (e.g., `net_virtio_user0`).
+///
+/// The port has one queue pair per core polling or transmitting on it; the peer must be
+/// configured with at least as many queues. Virtual ports do not support RSS or hardware
+/// filtering: with several polling cores, enable software dispatch (see
+/// [DispatchConfig](DispatchConfig)) so that both directions of a connection are processed by the
+/// same core. DPDK must be built with the `net/vhost` and `net/virtio` drivers.
+///
+/// ## Example
+/// ```toml
+/// [[online.ports]]
+///     device = "net_virtio_user0"
+///     cores = [1]
+///     [online.ports.virtual_port]
+///         kind = "virtio_user"
+///         path = "/var/run/openvswitch/vhu-retina"
+///         server = true
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct VirtualPortConfig {
+    /// Kind of virtual device.
+    pub kind: VirtualPortKind,
+
+    /// Path of the vhost-user socket.
+    pub path: String,
+
+    /// Whether Retina creates the socket and waits for the peer to connect, rather than connecting
+    /// to a socket created by the peer. Defaults to `true` for `"vhost_user"` and to `false` for
+    /// `"virtio_user"`.
+    #[serde(default = "default_virtual_port_server")]
+    pub server: Option<bool>,
+}
+
+fn default_virtual_port_server() -> Option<bool> {
+    None
+}
+
+impl VirtualPortConfig {
+    /// Checks that `device` names a virtual device of the configured kind.
+    pub(crate) fn check(&self, device: &str) -> Result<(), String> {
+        let prefix = match self.kind {
+            VirtualPortKind::VhostUser => "net_vhost",
+            VirtualPortKind::VirtioUser => "net_virtio_user",
+        };
+        if !device.starts_with(prefix) {
+            return Err(format!("virtual device name must start with {}", prefix));
+        }
+        if self.path.is_empty() || self.path.contains(',') {
+            return Err(format!("invalid socket path {:?}", self.path));
+        }
+        Ok(())
+    }
+
+    /// Returns the DPDK device arguments for a port with `nb_queues` queue pairs.
+    pub(crate) fn devargs(&self, nb_queues: usize) -> String {
+        match self.kind {
+            VirtualPortKind::VhostUser => {
+                let client = !self.server.unwrap_or(true);
+                format!(
+                    "iface={},queues={},client={}",
+                    self.path, nb_queues, client as u8
+                )
+            }
+            VirtualPortKind::VirtioUser => {
+                let server = self.server.unwrap_or(false);
+                format!(
+                    "path={},queues={},server={}",
+                    self.path, nb_queues, server as u8
+                )
+            }
+        }
+    }
+}
+
+/// Kind of virtual port (see [VirtualPortConfig](VirtualPortConfig)).
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VirtualPortKind {
+    /// vhost-user backend, e.g., of a VM's virtio-net device.
+    VhostUser,
+    /// virtio-user frontend, e.g., of a virtual switch's vhost-user port.
+    VirtioUser,
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Network interface options.
+///
+/// ## Example
+/// ```toml
+/// [[online.ports]]
+///     device = "0000:3b:00.0"
+///     cores = [1,2,3,4,5,6,7,8]
+/// ```
+///
+/// A virtual port (see [VirtualPortConfig](VirtualPortConfig)):
+/// ```toml
+/// [[online.ports]]
+///     device = "net_vhost0"
+///     cores = [1]
+///     [online.ports.virtual_port]
+///         kind = "vhost_user"
+///         path = "/tmp/retina-vhost0.sock"
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PortMap {
+    /// PCI address of interface, or name of the virtual device if `virtual_port` is set.
+    pub device: String,
+
+    /// List of packet processing cores used to poll the interface. Can be omitted if rx cores are
+    /// assigned in the `cores` section (see [CoreRoles](CoreRoles)), in which case all rx cores
+    /// poll the interface.
+    ///
+    /// ## Remarks
+    /// For performance, it is recommended that the processing cores reside on the same NUMA node as
+    /// the PCI device (see [NumaPolicy](NumaPolicy)).
+    #[serde(default = "default_role_cores")]
+    pub cores: Vec<u32>,
+
+    /// NUMA node (socket) to allocate the port's memory pool and receive queues on. Defaults to
+    /// the socket of the device, or the socket of the first core polling the port if the device
+    /// does not report one.
+    #[serde(default = "default_socket")]
+    pub socket: Option<u32>,
+
+    /// Sink core configuration. Defaults to `None`.
+    #[serde(default = "default_sink")]
+    pub sink: Option<SinkConfig>,
+
+    /// Receive side scaling (RSS) configuration. Defaults to a symmetric hash on IP addresses and
+    /// TCP/UDP ports.
+    #[serde(default = "default_rss")]
+    pub rss: RssConfig,
+
+    /// Virtual device backing the port. Defaults to `None` (`device` is a physical NIC).
+    #[serde(default = "default_virtual_port")]
+    pub virtual_port: Option<VirtualPortConfig>,
+}
+
+fn default_virtual_port() -> Option<VirtualPortConfig> {
+    None
 }
 
 fn default_sink() -> Option<SinkConfig> {
     None
 }
 
+fn default_socket() -> Option<u32> {
+    None
+}
+
+fn default_rss() -> RssConfig {
+    RssConfig {
+        key: default_rss_key(),
+        functions: default_rss_functions(),
+    }
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Receive side scaling (RSS) options.
+///
+/// The NIC distributes packets across the receive queues of a port by hashing header fields with
+/// a Toeplitz hash. Retina tracks each connection on a single core, so both directions of a
+/// connection (A→B and B→A) must hash to the same queue. This requires a symmetric key, which
+/// repeats the same two bytes (the default key is `0x6d5a` repeated). Retina warns if the
+/// configured key is not symmetric, or if the NIC does not support the requested hash functions.
+///
+/// ## Example
+/// ```toml
+/// [[online.ports]]
+///     device = "0000:3b:00.0"
+///     cores = [1,2,3,4]
+///     [online.ports.rss]
+///         functions = ["ip", "tcp", "udp"]
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RssConfig {
+    /// RSS hash key. Defaults to a 40-byte symmetric key.
+    #[serde(default = "default_rss_key")]
+    pub key: Vec<u8>,
+
+    /// Header fields to hash: `"ip"` (IP addresses), `"tcp"`, `"udp"`, and `"sctp"` (IP
+    /// addresses and ports). Defaults to `["ip", "tcp", "udp"]`.
+    #[serde(default = "default_rss_functions")]
+    pub functions: Vec<String>,
+}
+
+impl RssConfig {
+    /// Returns `true` if the key hashes both directions of a connection to the same value.
+    pub fn is_symmetric(&self) -> bool {
+        self.key.len() >= 2
+            && self
+                .key
+                .chunks(2)
+                .all(|pair| pair == &self.key[..pair.len()])
+    }
+
+    /// Checks that the key is non-empty and that all hash functions are known.
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if self.key.is_empty() || self.key.len() > u8::MAX as usize {
+            return Err(format!("invalid RSS key length {}", self.key.len()));
+        }
+        if let Some(function) = self
+            .functions
+            .iter()
+            .find(|f| !matches!(f.as_str(), "ip" | "tcp" | "udp" | "sctp"))
+        {
+            return Err(format!("unknown RSS hash function {}", function));
+        }
+        Ok(())
+    }
+}
+
+fn default_rss_key() -> Vec<u8> {
+    [0x6D, 0x5A].repeat(20)
+}
+
+fn default_rss_functions() -> Vec<String> {
+    vec!["ip".to_string(), "tcp".to_string(), "udp".to_string()]
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Statistics logging and live monitoring operations.
+///
+/// ## Example
+/// ```toml
+/// [online.monitor.display]
+///     throughput = true
+///     mempool_usage = true
+///
+/// [online.monitor.log]
+///     directory = "./log"
+///     interval = 1000
+///
+/// [online.monitor.prometheus]
+///     address = "0.0.0.0:9100"
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MonitorConfig {
+    /// Live display configuration. Defaults to `None` (no output).
+    #[serde(default = "default_display")]
+    pub display: Option<DisplayConfig>,
+
+    /// Logging configuration. Defaults to `None` (no logs).
+    #[serde(default = "default_log")]
+    pub log: Option<LogConfig>,
+
+    /// Prometheus metrics endpoint configuration. Defaults to `None` (no endpoint).
+    #[serde(default = "default_prometheus")]
+    pub prometheus: Option<PrometheusConfig>,
+}
+
+fn default_display() -> Option<DisplayConfig> {
+    None
+}
+
+fn default_log() -> Option<LogConfig> {
+    None
+}
+
+fn default_prometheus() -> Option<PrometheusConfig> {
+    None
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Live statistics display options.
+///
+/// If enabled, live statistics will be displayed to stdout once per second.
+///
+/// ## Example
+/// ```toml
+/// [online.monitor.display]
+///     throughput = true
+///     mempool_usage = true
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DisplayConfig {
+    /// Display live throughputs. Defaults to `true`.
+    #[serde(default = "default_display_throughput")]
+    pub throughput: bool,
+
+    /// Display live mempool usage. Defaults to `true`.
+    #[serde(default = "default_display_mempool_usage")]
+    pub mempool_usage: bool,
+
+    /// List of live port statistics to display.
+    ///
+    /// ## Remarks
+    /// Available options vary depending on the NIC driver and its supported counters. A port
+    /// statistic will be displayed if it contains (as a substring) any item in the `port_stats`
+    /// list. To display all available port statistics, set this value to a list containing the
+    /// empty string (`port_stats = [""]`). Defaults to displaying no statistics (`port_stats =
+    /// []`).
+    #[serde(default = "default_display_port_stats")]
+    pub port_stats: Vec<String>,
+}
+
+fn default_display_throughput() -> bool {
+    true
+}
+
+fn default_display_mempool_usage() -> bool {
+    true
+}
+
+fn default_display_port_stats() -> Vec<String> {
+    vec![]
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Logging options.
+///
+/// ## Example
+/// ```toml
+/// [online.monitor.log]
+///     directory = "./log"
+///     interval = 1000
+///     port_stats = ["rx"]   # only log stats with "rx" in its name
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LogConfig {
+    /// Log directory path. If logging is enabled, Retina will write logs to a timestamped folder
+    /// inside `directory`. Defaults to `"./log"`.
+    #[serde(default = "default_log_directory")]
+    pub directory: String,
+
+    /// How often to log port statistics (in milliseconds). Defaults to `1000`.
+    #[serde(default = "default_log_interval")]
+    pub interval: u64,
+
+    /// List of port statistics to log.
+    ///
+    /// Available options vary depending on the NIC driver and its supported counters. A port
+    /// statistic will be logged if it contains (as a substring) any item in the `port_stats` list.
+    /// To log all available port statistics, set this value to a list containing the empty string
+    /// (`port_stats = [""]`). Defaults to logging receive statistics (`port_stats = ["rx"]`).
+    #[serde(default = "default_log_port_stats")]
+    pub port_stats: Vec<String>,
+}
+
+fn default_log_directory() -> String {
+    "./log/".to_string()
+}
+
+fn default_log_interval() -> u64 {
+    1000
+}
+
+fn default_log_port_stats() -> Vec<String> {
+    vec!["rx".to_string()]
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Prometheus metrics options.
+///
+/// If enabled, Retina serves metrics in the Prometheus text exposition format over HTTP at
+/// `address`. Exported metrics include per-core packet and drop counters, connection table
+/// occupancy, the number of sessions parsed per protocol, the number of deliveries to each
+/// subscription callback, and per-port statistics.
+///
+/// ## Example
+/// ```toml
+/// [online.monitor.prometheus]
+///     address = "0.0.0.0:9100"
+///     interval = 1000
+///     port_stats = ["rx"]
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PrometheusConfig {
+    /// Socket address to serve metrics on. Defaults to `"0.0.0.0:9100"`.
+    #[serde(default = "default_prometheus_address")]
+    pub address: String,
+
+    /// How often to refresh metrics (in milliseconds). Defaults to `1000`.
+    #[serde(default = "default_log_interval")]
+    pub interval: u64,
+
+    /// List of port statistics to export.
+    ///
+    /// A port statistic will be exported if it contains (as a substring) any item in the
+    /// `port_stats` list. Defaults to exporting receive statistics (`port_stats = ["rx"]`).
+    #[serde(default = "default_log_port_stats")]
+    pub port_stats: Vec<String>,
+}
+
+fn default_prometheus_address() -> String {
+    "0.0.0.0:9100".to_string()
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Offline traffic analysis options.
+///
+/// Offline mode runs using a single core and performs offline analysis of already captured pcap
+/// files. Either [OnlineConfig](OnlineConfig) or [OfflineConfig](OfflineConfig) must be specified,
+/// but not both. This mode is primarily intended for functional testing.
+///
+/// A long capture split across several files (e.g., rotated by `tcpdump -C` or `-G`) can be
+/// processed as one continuous capture, either by setting `pcap` to the directory containing the
+/// files, or by listing them in `pcaps`. Connection state is preserved across file boundaries, so
+/// connections that span several files are tracked and delivered once.
+///
+/// By default, packets are processed as fast as possible. With `replay_speed`, they are instead
+/// processed at the pace they were captured at, so that time-based logic (e.g., inactivity
+/// timeouts) behaves as it would on a live interface. Either way, packets are timestamped with
+/// their capture timestamps (see [Mbuf::timestamp](crate::Mbuf::timestamp)), on which rate
+/// predicates and connection durations are measured.
+///
+/// ## Example
+/// ```toml
+/// [offline]
+///     pcap = "sample_pcaps/smallFlows.pcap"
+///     mtu = 9702
+/// ```
+///
+/// ```toml
+/// [offline]
+///     pcaps = ["traces/capture_00.pcap", "traces/capture_01.pcap"]
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OfflineConfig {
+    /// Path to packet capture (pcap) file, or to a directory of pcap files. The files in a
+    /// directory are processed in the lexicographic order of their names, skipping hidden files.
+    /// Either `pcap` or `pcaps` must be specified, but not both.
+    #[serde(default)]
+    pub pcap: String,
+
+    /// Paths to packet capture files, processed in the given order.
+    #[serde(default)]
+    pub pcaps: Vec<String>,
+
+    /// Processes packets at the pace given by their capture timestamps, multiplied by this factor
+    /// (e.g., `1.0` for the original pace, `10.0` for ten times faster). Connections are checked
+    /// for inactivity while waiting between packets. Defaults to `None` (packets are processed as
+    /// fast as possible, and connections never time out).
+    #[serde(default = "default_replay_speed")]
+    pub replay_speed: Option<f64>,
+
+    /// Maximum frame size, equivalent to MTU on a live interface. Defaults to `1500`.
+    ///
+    /// To include jumbo frames, set this value higher (e.g., `9702`).
+    #[serde(default = "default_mtu")]
+    pub mtu: usize,
+}
+
+fn default_replay_speed() -> Option<f64> {
+    None
+}
+
+impl OfflineConfig {
+    /// Checks that the capture files exist and that the replay speed is positive.
+    pub(crate) fn check(&self) -> Result<(), String> {
+        self.pcap_files()?;
+        if let Some(speed) = self.replay_speed {
+            if !speed.is_finite() || speed <= 0.0 {
+                return Err(format!("invalid replay_speed {}", speed));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the packet capture files to process, in order.
+    pub fn pcap_files(&self) -> Result<Vec<PathBuf>, String> {
+        if self.pcap.is_empty() == self.pcaps.is_empty() {
+            return Err("specify either pcap or pcaps".to_string());
+        }
+        if !self.pcaps.is_empty() {
+            return Ok(self.pcaps.iter().map(PathBuf::from).collect());
+        }
+        let path = Path::new(&self.pcap);
+        if !path.is_dir() {
+            return Ok(vec![path.to_path_buf()]);
+        }
+        let entries =
+            fs::read_dir(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let mut files = vec![];
+        for entry in entries {
+            let entry = entry.map_err(|error| format!("{}: {}", path.display(), error))?;
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !hidden && entry.path().is_file() {
+                files.push(entry.path());
+            }
+        }
+        if files.is_empty() {
+            return Err(format!("no pcap files in {}", path.display()));
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Connection tracking options.
+///
+/// These options can be used to tune for resource usage vs. accuracy depending on expected network
+/// characteristics.
+///
+/// ## Example
+/// ```toml
+/// [conntrack]
+///     max_connections = 10_000_000
+///     max_out_of_order = 100
+///     max_out_of_order_bytes = 1_048_576
+///     max_out_of_order_total_bytes = 268_435_456
+///     out_of_order_overflow = "skip_gap"
+///     tcp_overlap_policy = "linux"
+///     timeout_resolution = 100
+///     udp_inactivity_timeout = 60_000
+///     tcp_inactivity_timeout = 300_000
+///     tcp_establish_timeout = 5000
+///     midstream = true
+///     eviction = "lru"
+///     tcp_rst_timeout = 1000
+///     tcp_half_closed_timeout = 30_000
+///     tcp_time_wait = 10_000
+///     skip_acked_gaps = true
+///     sctp_inactivity_timeout = 300_000
+///     unidirectional = false
+///     bypass = true
+///
+/// [[conntrack.port_timeouts]]
+///     protocol = "udp"
+///     port = 53
+///     inactivity_timeout = 5000
+///
+/// [[conntrack.port_timeouts]]
+///     protocol = "tcp"
+///     port = 22
+///     inactivity_timeout = 3_600_000
+///
+/// [conntrack.sampling]
+///     method = "hash"
+///     rate = 0.1
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ConnTrackConfig {
+    /// Maximum number of connections that can be tracked simultaneously per-core. When the table
+    /// is full, new connections are handled according to `eviction`. The table is allocated as
+    /// connections are tracked, not for the maximum up front. On reload, a higher limit lets the
+    /// table grow further, and a lower one applies to new connections only. Defaults to
+    /// `10_000_000`.
+    #[serde(default = "default_max_connections")]
+    pub max_connections: usize,
+
+    /// Maximum number of out-of-order packets buffered in each direction of a TCP connection.
+    /// Defaults to `100`.
+    #[serde(default = "default_max_out_of_order")]
+    pub max_out_of_order: usize,
+
+    /// Maximum number of payload bytes buffered out of order in each direction of a TCP
+    /// connection. Defaults to `1_048_576` (1 MiB).
+    #[serde(default = "default_max_out_of_order_bytes")]
+    pub max_out_of_order_bytes: usize,
+
+    /// Maximum number of payload bytes buffered out of order across all TCP connections of a
+    /// core. Defaults to `268_435_456` (256 MiB).
+    #[serde(default = "default_max_out_of_order_total_bytes")]
+    pub max_out_of_order_total_bytes: usize,
+
+    /// What happens to a TCP connection when buffering an out-of-order packet would exceed one of
+    /// the limits above. Defaults to `"drop"`.
+    #[serde(default = "default_out_of_order_overflow")]
+    pub out_of_order_overflow: OutOfOrderOverflow,
+
+    /// Which data is kept when TCP segments overlap. Defaults to `"bsd"`.
+    #[serde(default = "default_tcp_overlap_policy")]
+    pub tcp_overlap_policy: OverlapPolicy,
+
+    /// Frequency to check for inactive streams (in milliseconds). Defaults to `1000` (1 second).
+    #[serde(default = "default_timeout_resolution")]
+    pub timeout_resolution: usize,
+
+    /// A UDP connection can be inactive for up to this amount of time (in milliseconds) before it
+    /// is force expired. Defaults to `60_000` (1 minute).
+    #[serde(default = "default_udp_inactivity_timeout")]
+    pub udp_inactivity_timeout: usize,
+
+    /// A TCP connection can be inactive for up to this amount of time (in milliseconds) before it
+    /// is force expired. Defaults to `300_000` (5 minutes).
+    #[serde(default = "default_tcp_inactivity_timeout")]
+    pub tcp_inactivity_timeout: usize,
+
+    /// Inactivity time between the first and second packet of a TCP connection before it is force
+    /// expired (in milliseconds).
+    ///
+    /// This approximates connections that remain inactive in either the `SYN-SENT` or
+    /// `SYN-RECEIVED` state without progressing. It is used to prevent memory exhaustion due to SYN
+    /// scans and SYN floods. Defaults to `5000` (5 seconds).
+    #[serde(default = "default_tcp_establish_timeout")]
+    pub tcp_establish_timeout: usize,
+
+    #[doc(hidden)]
+    /// Whether to track TCP connections where the first observed packet is a SYN/ACK. Defaults to
+    /// `false`.
+    #[serde(default = "default_init_synack")]
+    pub init_synack: bool,
+
+    #[doc(hidden)]
+    /// Whether to track TCP connections where the first observed packet is a FIN. Defaults to
+    /// `false`.
+    #[serde(default = "default_init_fin")]
+    pub init_fin: bool,
+
+    #[doc(hidden)]
+    /// Whether to track TCP connections where the first observed packet is a RST. Defaults to
+    /// `false`.
+    #[serde(default = "default_init_rst")]
+    pub init_rst: bool,
+
+    #[doc(hidden)]
+    /// Whether to track TCP connections where the first observed packet is a DATA. Defaults to
+    /// `false`.
+    #[serde(default = "default_init_data")]
+    pub init_data: bool,
+
+    /// IPv4 and IPv6 reassembly settings.
+    #[serde(default)]
+    pub defrag: DefragConfig,
+
+    /// Whether to track TCP connections whose handshake was not observed (e.g., long-lived
+    /// connections established before Retina started). Defaults to `false`.
+    ///
+    /// Such a connection is picked up from its first observed packet that is not a RST, and its
+    /// stream is reassembled from there: the sequence number of each direction is taken from its
+    /// first packet. The sender of the first observed packet is considered the originator, which
+    /// may not be the host that opened the connection. Application-layer parsers that need the
+    /// start of a session (e.g., the TLS handshake) do not identify mid-stream connections.
+    #[serde(default = "default_midstream")]
+    pub midstream: bool,
+
+    /// Inactivity timeouts of connections to specific ports, overriding `udp_inactivity_timeout`
+    /// and `tcp_inactivity_timeout`. Defaults to none.
+    #[serde(default)]
+    pub port_timeouts: Vec<PortTimeoutConfig>,
+
+    /// Which connection makes room for a new one when the connection table is full. Defaults to
+    /// `"reject"`.
+    #[serde(default = "default_eviction")]
+    pub eviction: EvictionPolicy,
+
+    /// Time (in milliseconds) a TCP connection is still tracked after a RST, before it is
+    /// considered closed. Data sent after the RST (e.g., because the RST was ignored by the
+    /// receiver) is reassembled meanwhile, and any further packet restarts the delay. Defaults to
+    /// `0`: the connection is closed on the RST.
+    #[serde(default = "default_tcp_rst_timeout")]
+    pub tcp_rst_timeout: usize,
+
+    /// Inactivity timeout (in milliseconds) of a TCP connection once one side has sent a FIN,
+    /// if shorter than its regular inactivity timeout. Defaults to none: half-closed connections
+    /// wait for the FIN of the other side as long as any other connection.
+    #[serde(default)]
+    pub tcp_half_closed_timeout: Option<usize>,
+
+    /// Time (in milliseconds) a closed TCP connection is kept in the table, like the `TIME_WAIT`
+    /// state of end hosts. Late packets of the connection (e.g., retransmitted FINs or ACKs) are
+    /// discarded meanwhile instead of being picked up as a new connection, while a new SYN reopens
+    /// the connection. Lingering connections count towards `max_connections`. Defaults to `0`.
+    #[serde(default = "default_tcp_time_wait")]
+    pub tcp_time_wait: usize,
+
+    /// Whether to skip missing data of a TCP stream as soon as the receiver acknowledges it,
+    /// either cumulatively or with a SACK block. Such data was lost by the monitor (e.g., dropped
+    /// by the NIC) rather than by the network, so it will not be retransmitted, and waiting for it
+    /// only holds the data buffered after it until the out-of-order buffer overflows. Parsers see a
+    /// gap in the stream. Defaults to `false`.
+    #[serde(default = "default_skip_acked_gaps")]
+    pub skip_acked_gaps: bool,
+
+    /// An SCTP association can be inactive for up to this amount of time (in milliseconds) before
+    /// it is force expired. Defaults to `300_000` (5 minutes).
+    #[serde(default = "default_sctp_inactivity_timeout")]
+    pub sctp_inactivity_timeout: usize,
+
+    /// VXLAN decapsulation settings.
+    #[serde(default)]
+    pub vxlan: VxlanConfig,
+
+    /// GRE and ERSPAN decapsulation settings.
+    #[serde(default)]
+    pub gre: GreConfig,
+
+    /// GENEVE decapsulation settings.
+    #[serde(default)]
+    pub geneve: GeneveConfig,
+
+    /// GTP-U decapsulation settings.
+    #[serde(default)]
+    pub gtpu: GtpuConfig,
+
+    /// IP-in-IP decapsulation settings.
+    #[serde(default)]
+    pub ipip: IpipConfig,
+
+    /// Teredo and ISATAP decapsulation settings.
+    #[serde(default)]
+    pub teredo: TeredoConfig,
+
+    /// MPLS decapsulation settings.
+    #[serde(default)]
+    pub mpls: MplsConfig,
+
+    /// PPPoE decapsulation settings.
+    #[serde(default)]
+    pub pppoe: PppoeConfig,
+
+    /// Maximum number of nested tunnels a packet is decapsulated from (e.g., `3` for GRE carrying
+    /// VXLAN carrying MPLS), with the tunnel protocols enabled above. Inner tunnels beyond this
+    /// depth are left encapsulated. At most `4`, which is the default.
+    #[serde(default = "default_max_tunnel_depth")]
+    pub max_tunnel_depth: usize,
+
+    /// Whether to track each direction of a connection as a separate flow, for taps that only see
+    /// one direction of the traffic (e.g., because of asymmetric routing). Defaults to `false`.
+    ///
+    /// Each flow is keyed by its source and destination, and its sender is the originator, so
+    /// subscriptions receive one record per direction. A TCP flow also starts on a SYN/ACK, and
+    /// is closed as soon as its sender sends a FIN. Application-layer parsers only see the data of
+    /// one direction, so protocols identified from an exchange (e.g., the TLS handshake) may not
+    /// be identified.
+    #[serde(default)]
+    pub unidirectional: bool,
+
+    /// Flow sampling settings. Defaults to `None`: all connections are tracked.
+    #[serde(default)]
+    pub sampling: Option<SamplingConfig>,
+
+    /// Whether TCP connections and SCTP associations stay in the table once no subscription needs
+    /// more of their data (e.g., their session was delivered, and no connection-level datatype is
+    /// subscribed), until they close or time out. Their remaining packets are then discarded right
+    /// after the table lookup, without being parsed or reassembled, and counted per core in the
+    /// `bypassed_pkts` metric. Defaults to `false`: such connections are removed from the table,
+    /// and their remaining packets are looked up again as new connections (and picked up again
+    /// with `midstream`).
+    ///
+    /// Bypassed connections count towards `max_connections`, and are removed on a FIN or RST.
+    /// Packets are discarded in software: no rule is installed on the NIC.
+    #[serde(default)]
+    pub bypass: bool,
+}
+
+fn default_max_tunnel_depth() -> usize {
+    MAX_TUNNEL_DEPTH
+}
+
+fn default_max_connections() -> usize {
+    10_000_000
+}
+
+fn default_max_out_of_order() -> usize {
+    100
+}
+
+fn default_max_out_of_order_bytes() -> usize {
+    1_048_576
+}
+
+fn default_max_out_of_order_total_bytes() -> usize {
+    268_435_456
+}
+
+fn default_out_of_order_overflow() -> OutOfOrderOverflow {
+    OutOfOrderOverflow::Drop
+}
+
+fn default_tcp_overlap_policy() -> OverlapPolicy {
+    OverlapPolicy::Bsd
+}
+
+fn default_timeout_resolution() -> usize {
+    1000
+}
+
+fn default_udp_inactivity_timeout() -> usize {
+    60_000
+}
+
+fn default_tcp_inactivity_timeout() -> usize {
+    300_000
+}
+
+fn default_tcp_establish_timeout() -> usize {
+    5000
+}
+
+fn default_init_synack() -> bool {
+    false
+}
+
+fn default_init_fin() -> bool {
+    false
+}
+
+fn default_init_rst() -> bool {
+    false
+}
+
+fn default_init_data() -> bool {
+    false
+}
+
+fn default_midstream() -> bool {
+    false
+}
+
+fn default_eviction() -> EvictionPolicy {
+    EvictionPolicy::Reject
+}
+
+fn default_tcp_rst_timeout() -> usize {
+    0
+}
+
+fn default_tcp_time_wait() -> usize {
+    0
+}
+
+fn default_skip_acked_gaps() -> bool {
+    false
+}
+
+fn default_sctp_inactivity_timeout() -> usize {
+    300_000
+}
+
+/// What happens to a TCP connection whose out-of-order buffer overflows (see
+/// [ConnTrackConfig](ConnTrackConfig)).
+///
+/// Overflows are counted per core, and exported as the `ooo_overflows` metric.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutOfOrderOverflow {
+    /// Stop tracking the connection and release its buffered packets.
+    Drop,
+    /// Give up on the missing data: skip to the earliest buffered packet, and deliver the buffered
+    /// data from there, until the buffer is back within its limits. Parsers see a gap in the
+    /// stream.
+    SkipGap,
+}
+
+/// Which data is kept when TCP segments overlap (see [ConnTrackConfig](ConnTrackConfig)).
+///
+/// End hosts disagree on which copy of the data to keep when a segment overlaps data they already
+/// buffered, which attackers can use to evade inspection by sending a different payload to the
+/// monitor than the one the end host sees. Choosing the policy of the monitored hosts makes Retina
+/// reassemble streams as they do, following the target-based semantics of Snort and Suricata.
+///
+/// Policies apply to segments buffered out of order. Data that was already delivered to the
+/// parsers is never replaced, and overlapping retransmissions of it are trimmed.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapPolicy {
+    /// Keep the data received first.
+    First,
+    /// Keep the data received last.
+    Last,
+    /// Keep the data received first, unless the new segment starts before the buffered one.
+    Bsd,
+    /// Like `Bsd`, but also keep the new data if the new segment starts at the same sequence
+    /// number as the buffered one and ends after it.
+    Linux,
+    /// Keep the data received first, unless the new segment starts before the buffered one and
+    /// ends at or after its end.
+    Windows,
+}
+
+/// Inactivity timeout of the connections to a port (see [ConnTrackConfig](ConnTrackConfig)).
+///
+/// The port is the destination port of the first observed packet of the connection, typically
+/// the port of the server. If several entries match a connection, the last one applies. TCP
+/// connections use the timeout once established, `tcp_establish_timeout` still applies before.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PortTimeoutConfig {
+    /// Transport protocol of the connections.
+    pub protocol: TransportProtocol,
+
+    /// Destination port of the connections.
+    pub port: u16,
+
+    /// Time (in milliseconds) a connection can be inactive before it is force expired.
+    pub inactivity_timeout: usize,
+}
+
+/// Transport protocol of a [PortTimeoutConfig](PortTimeoutConfig).
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportProtocol {
+    /// TCP.
+    Tcp,
+    /// UDP.
+    Udp,
+    /// SCTP.
+    Sctp,
+}
+
+/// Which connection makes room for a new one when the connection table of a core is full (see
+/// [ConnTrackConfig](ConnTrackConfig)).
+///
+/// Evicted connections are terminated as if they had expired, and counted per core in the
+/// `evicted_conns` metric. Under a SYN flood, evicting connections keeps new legitimate
+/// connections tracked at the expense of existing ones.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionPolicy {
+    /// Do not track new connections until there is room in the table.
+    Reject,
+    /// Evict the least recently active connection.
+    Lru,
+    /// Evict the connection closest to its inactivity timeout.
+    EarliestExpiry,
+    /// Evict a connection at random among the 1024 oldest ones.
+    Random,
+}
+
+/// Flow sampling options.
+///
+/// Only a sample of the connections is tracked, so that heavyweight subscriptions can run on a
+/// representative subset of the traffic of fast links. Each new connection is sampled or not
+/// before it is inserted in the connection table, by comparing a hash of its 5-tuple to a
+/// threshold: packets of connections that are not sampled are dropped without further processing,
+/// and counted per core in the `unsampled_pkts` and `unsampled_bytes` metrics.
+///
+/// Sampling methods:
+/// - `"hash"`: a `rate` fraction of the connections. All cores and all Retina instances sample
+///   the same connections.
+/// - `"one_in_n"`: one in every `n` connections, selected by hash as above.
+/// - `"rate"`: about `target_rate` new connections per second on each core. The sampled fraction
+///   is adjusted every second to the rate of new connections, so a connection first seen before
+///   an adjustment may be sampled differently afterwards.
+///
+/// ## Example
+/// ```toml
+/// [conntrack.sampling]
+///     method = "rate"
+///     target_rate = 10_000
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SamplingConfig {
+    /// How connections are sampled. Defaults to `"hash"`.
+    #[serde(default = "default_sampling_method")]
+    pub method: SamplingMethod,
+
+    /// Fraction of the connections sampled by the `"hash"` method, between `0` and `1`. Defaults
+    /// to `0.1`.
+    #[serde(default = "default_sample_rate")]
+    pub rate: f64,
+
+    /// One in `n` connections are sampled by the `"one_in_n"` method. Defaults to `10`.
+    #[serde(default = "default_sampling_n")]
+    pub n: u64,
+
+    /// New connections per second sampled on each core by the `"rate"` method. Defaults to
+    /// `10_000`.
+    #[serde(default = "default_sampling_target_rate")]
+    pub target_rate: f64,
+}
+
+fn default_sampling_method() -> SamplingMethod {
+    SamplingMethod::Hash
+}
+
+fn default_sampling_n() -> u64 {
+    10
+}
+
+fn default_sampling_target_rate() -> f64 {
+    10_000.0
+}
+
+/// How connections are sampled (see [SamplingConfig](SamplingConfig)).
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplingMethod {
+    /// Sample a fixed fraction of the connections.
+    Hash,
+    /// Sample one in every `n` connections.
+    OneInN,
+    /// Sample a target rate of new connections.
+    Rate,
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// IPv4 and IPv6 reassembly options.
+///
+/// Only the first fragment of a fragmented datagram carries the TCP or UDP header, so the other
+/// fragments cannot be attributed to a connection. When reassembly is enabled, each core
+/// holds the fragments it receives until the datagram is complete, and processes the reassembled
+/// datagram as a single packet. Datagrams that are not completed within `timeout` (measured on
+/// the receive timestamps of the fragments), or that have overlapping fragments, are dropped.
+/// Complete datagrams that do not fit in a packet buffer of the memory pool (see
+/// [MempoolConfig](MempoolConfig)) are not reassembled: their fragments are processed as they
+/// are. Reassembled IPv6 datagrams no longer carry the Fragment extension header.
+///
+/// Fragments are held in their receive buffers until their datagram is complete, so reassembly
+/// may require a larger memory pool.
+///
+/// ## Remarks
+/// All fragments of a datagram must be received by the same core. With hardware RSS, this
+/// requires hashing fragmented packets on their IP addresses only, which most NICs do. With
+/// software dispatch, datagrams are reassembled before they are dispatched. Fragments received on
+/// bridged ports in inline mode are forwarded and processed as they are, without reassembly.
+///
+/// ## Example
+/// ```toml
+/// [conntrack.defrag]
+///     enabled = true
+///     max_datagrams = 1024
+///     timeout = 1000
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DefragConfig {
+    /// Whether fragmented IPv4 and IPv6 datagrams are reassembled. Defaults to `false`.
+    #[serde(default = "default_defrag_enabled")]
+    pub enabled: bool,
+
+    /// Maximum number of datagrams being reassembled per core. When full, the oldest datagram is
+    /// dropped. Defaults to `256`.
+    #[serde(default = "default_defrag_max_datagrams")]
+    pub max_datagrams: usize,
+
+    /// Time (in milliseconds) to wait for all fragments of a datagram. Defaults to `1000` (1
+    /// second).
+    #[serde(default = "default_defrag_timeout")]
+    pub timeout: u64,
+}
+
+impl Default for DefragConfig {
+    fn default() -> Self {
+        DefragConfig {
+            enabled: default_defrag_enabled(),
+            max_datagrams: default_defrag_max_datagrams(),
+            timeout: default_defrag_timeout(),
+        }
+    }
+}
+
+fn default_defrag_enabled() -> bool {
+    false
+}
+
+fn default_defrag_max_datagrams() -> usize {
+    256
+}
+
+fn default_defrag_timeout() -> u64 {
+    1000
+}
+
 /* --------------------------------------------------------------------------------- */
 
-/// Statistics logging and live monitoring operations.
+/// VXLAN decapsulation options.
+///
+/// When enabled, VXLAN-encapsulated frames (RFC 7348) are decapsulated before the packet filter
+/// and the connection tracker: filters, connection tracking, and subscribed packets apply to the
+/// inner Ethernet frame. The VXLAN Network Identifier (VNI) and the outer 5-tuple are kept as
+/// [Tunnel](crate::conntrack::tunnel::Tunnel) metadata, available in the
+/// [L4Context](crate::conntrack::pdu::L4Context) of the packets of the connection.
+///
+/// ## Remarks
+/// Connections are identified by their inner 5-tuple only: connections of different VNIs with the
+/// same 5-tuple (e.g., in overlapping tenant address spaces) are tracked as one, with the tunnel
+/// metadata of their first packet. Outer IP fragments are reassembled before decapsulation if
+/// [DefragConfig](DefragConfig) is enabled. With software dispatch, packets are dispatched on their
+/// inner 5-tuple and decapsulated by the worker cores. Frames received on bridged ports in inline
+/// mode are forwarded and processed as they are, without decapsulation.
 ///
 /// ## Example
 /// ```toml
-/// [online.monitor.display]
-///     throughput = true
-///     mempool_usage = true
-///
-/// [online.monitor.log]
-///     directory = "./log"
-///     interval = 1000
+/// [conntrack.vxlan]
+///     enabled = true
+///     ports = [4789, 8472]
 /// ```
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct MonitorConfig {
-    /// Live display configuration. Defaults to `None` (no output).
-    #[serde(default = "default_display")]
-    pub display: Option<DisplayConfig>,
+pub struct VxlanConfig {
+    /// Whether VXLAN frames are decapsulated. Defaults to `false`.
+    #[serde(default = "default_vxlan_enabled")]
+    pub enabled: bool,
+
+    /// UDP destination ports that identify VXLAN. Defaults to `[4789, 8472]` (the IANA-assigned
+    /// port, and the default port of the Linux kernel).
+    #[serde(default = "default_vxlan_ports")]
+    pub ports: Vec<u16>,
+}
 
-    /// Logging configuration. Defaults to `None` (no logs).
-    #[serde(default = "default_log")]
-    pub log: Option<LogConfig>,
+impl Default for VxlanConfig {
+    fn default() -> Self {
+        VxlanConfig {
+            enabled: default_vxlan_enabled(),
+            ports: default_vxlan_ports(),
+        }
+    }
 }
 
-fn default_display() -> Option<DisplayConfig> {
-    None
+fn default_vxlan_enabled() -> bool {
+    false
 }
 
-fn default_log() -> Option<LogConfig> {
-    None
+fn default_vxlan_ports() -> Vec<u16> {
+    vec![VXLAN_PORT, VXLAN_LINUX_PORT]
 }
 
 /* --------------------------------------------------------------------------------- */
 
-/// Live statistics display options.
+/// GRE and ERSPAN decapsulation options.
 ///
-/// If enabled, live statistics will be displayed to stdout once per second.
+/// When enabled, packets carried over GRE (RFC 2784, with the optional key and sequence number of
+/// RFC 2890) are decapsulated before the packet filter and the connection tracker, like VXLAN
+/// frames (see [VxlanConfig](VxlanConfig)). This includes the Ethernet frames of ERSPAN Type II
+/// and Type III mirror sessions, and of Transparent Ethernet Bridging. IPv4 and IPv6 packets
+/// carried directly over GRE are given an Ethernet header with the addresses of the outer frame.
+/// The GRE key or ERSPAN session ID and the outer addresses are kept as
+/// [Tunnel](crate::conntrack::tunnel::Tunnel) metadata.
 ///
 /// ## Example
 /// ```toml
-/// [online.monitor.display]
-///     throughput = true
-///     mempool_usage = true
+/// [conntrack.gre]
+///     enabled = true
 /// ```
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct DisplayConfig {
-    /// Display live throughputs. Defaults to `true`.
-    #[serde(default = "default_display_throughput")]
-    pub throughput: bool,
-
-    /// Display live mempool usage. Defaults to `true`.
-    #[serde(default = "default_display_mempool_usage")]
-    pub mempool_usage: bool,
-
-    /// List of live port statistics to display.
-    ///
-    /// ## Remarks
-    /// Available options vary depending on the NIC driver and its supported counters. A port
-    /// statistic will be displayed if it contains (as a substring) any item in the `port_stats`
-    /// list. To display all available port statistics, set this value to a list containing the
-    /// empty string (`port_stats = [""]`). Defaults to displaying no statistics (`port_stats =
-    /// []`).
-    #[serde(default = "default_display_port_stats")]
-    pub port_stats: Vec<String>,
-}
-
-fn default_display_throughput() -> bool {
-    true
+pub struct GreConfig {
+    /// Whether GRE packets are decapsulated. Defaults to `false`.
+    #[serde(default = "default_gre_enabled")]
+    pub enabled: bool,
 }
 
-fn default_display_mempool_usage() -> bool {
-    true
+impl Default for GreConfig {
+    fn default() -> Self {
+        GreConfig {
+            enabled: default_gre_enabled(),
+        }
+    }
 }
 
-fn default_display_port_stats() -> Vec<String> {
-    vec![]
+fn default_gre_enabled() -> bool {
+    false
 }
 
 /* --------------------------------------------------------------------------------- */
 
-/// Logging options.
+/// GENEVE decapsulation options.
+///
+/// When enabled, GENEVE packets (RFC 8926) are decapsulated before the packet filter and the
+/// connection tracker, like VXLAN frames (see [VxlanConfig](VxlanConfig)). The Virtual Network
+/// Identifier, the outer 5-tuple, and the option TLVs selected by `options` are kept as
+/// [Tunnel](crate::conntrack::tunnel::Tunnel) metadata.
+///
+/// ## Remarks
+/// At most [GENEVE_MAX_OPTIONS_LEN](crate::conntrack::tunnel::GENEVE_MAX_OPTIONS_LEN) bytes of
+/// selected options (including their headers) are kept per packet; selected options beyond this
+/// limit are discarded.
 ///
 /// ## Example
 /// ```toml
-/// [online.monitor.log]
-///     directory = "./log"
-///     interval = 1000
-///     port_stats = ["rx"]   # only log stats with "rx" in its name
+/// [conntrack.geneve]
+///     enabled = true
+///     ports = [6081]
+///
+/// [[conntrack.geneve.options]]
+///     class = 0x0103
+///     option_type = 0x80
 /// ```
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct LogConfig {
-    /// Log directory path. If logging is enabled, Retina will write logs to a timestamped folder
-    /// inside `directory`. Defaults to `"./log"`.
-    #[serde(default = "default_log_directory")]
-    pub directory: String,
+pub struct GeneveConfig {
+    /// Whether GENEVE packets are decapsulated. Defaults to `false`.
+    #[serde(default = "default_geneve_enabled")]
+    pub enabled: bool,
+
+    /// UDP destination ports that identify GENEVE. Defaults to `[6081]`.
+    #[serde(default = "default_geneve_ports")]
+    pub ports: Vec<u16>,
+
+    /// Option TLVs to keep as tunnel metadata. Defaults to none.
+    #[serde(default)]
+    pub options: Vec<GeneveOptionConfig>,
+}
 
-    /// How often to log port statistics (in milliseconds). Defaults to `1000`.
-    #[serde(default = "default_log_interval")]
-    pub interval: u64,
+impl Default for GeneveConfig {
+    fn default() -> Self {
+        GeneveConfig {
+            enabled: default_geneve_enabled(),
+            ports: default_geneve_ports(),
+            options: vec![],
+        }
+    }
+}
 
-    /// List of port statistics to log.
-    ///
-    /// Available options vary depending on the NIC driver and its supported counters. A port
-    /// statistic will be logged if it contains (as a substring) any item in the `port_stats` list.
-    /// To log all available port statistics, set this value to a list containing the empty string
-    /// (`port_stats = [""]`). Defaults to logging receive statistics (`port_stats = ["rx"]`).
-    #[serde(default = "default_log_port_stats")]
-    pub port_stats: Vec<String>,
+fn default_geneve_enabled() -> bool {
+    false
 }
 
-fn default_log_directory() -> String {
-    "./log/".to_string()
+fn default_geneve_ports() -> Vec<u16> {
+    vec![GENEVE_PORT]
 }
 
-fn default_log_interval() -> u64 {
-    1000
+/// Selects GENEVE option TLVs to keep (see [GeneveConfig](GeneveConfig)).
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct GeneveOptionConfig {
+    /// Option class.
+    pub class: u16,
+
+    /// Option type. If unset, all options of the class are selected.
+    #[serde(default)]
+    pub option_type: Option<u8>,
 }
 
-fn default_log_port_stats() -> Vec<String> {
-    vec!["rx".to_string()]
+impl GeneveOptionConfig {
+    /// Returns `true` if the option of class `class` and type `option_type` is selected.
+    pub fn matches(&self, class: u16, option_type: u8) -> bool {
+        self.class == class && self.option_type.unwrap_or(option_type) == option_type
+    }
 }
 
 /* --------------------------------------------------------------------------------- */
 
-/// Offline traffic analysis options.
+/// GTP-U decapsulation options.
 ///
-/// Offline mode runs using a single core and performs offline analysis of already captured pcap
-/// files. Either [OnlineConfig](OnlineConfig) or [OfflineConfig](OfflineConfig) must be specified,
-/// but not both. This mode is primarily intended for functional testing.
+/// When enabled, the G-PDUs of GTP-U (3GPP TS 29.281), which carry the IP packets of mobile
+/// subscribers (e.g., on S1-U and N3 interfaces), are decapsulated before the packet filter and
+/// the connection tracker, like VXLAN frames (see [VxlanConfig](VxlanConfig)). The inner IP
+/// packets are given an Ethernet header with the addresses of the outer frame. The Tunnel Endpoint
+/// Identifier (TEID) and the outer 5-tuple are kept as
+/// [Tunnel](crate::conntrack::tunnel::Tunnel) metadata.
+///
+/// ## Remarks
+/// Uplink and downlink packets are sent with the TEIDs assigned by their receiving endpoint, so
+/// the tunnel metadata of a connection (taken from its first packet) has the TEID of one direction
+/// only. GTP-U signalling messages (e.g., Echo Request) are processed as they are.
 ///
 /// ## Example
 /// ```toml
-/// [offline]
-///     pcap = "sample_pcaps/smallFlows.pcap"
-///     mtu = 9702
+/// [conntrack.gtpu]
+///     enabled = true
+///     ports = [2152]
 /// ```
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct OfflineConfig {
-    /// Path to packet capture (pcap) file.
-    pub pcap: String,
+pub struct GtpuConfig {
+    /// Whether GTP-U packets are decapsulated. Defaults to `false`.
+    #[serde(default = "default_gtpu_enabled")]
+    pub enabled: bool,
+
+    /// UDP destination ports that identify GTP-U. Defaults to `[2152]`.
+    #[serde(default = "default_gtpu_ports")]
+    pub ports: Vec<u16>,
+}
 
-    /// Maximum frame size, equivalent to MTU on a live interface. Defaults to `1500`.
-    ///
-    /// To include jumbo frames, set this value higher (e.g., `9702`).
-    #[serde(default = "default_mtu")]
-    pub mtu: usize,
+impl Default for GtpuConfig {
+    fn default() -> Self {
+        GtpuConfig {
+            enabled: default_gtpu_enabled(),
+            ports: default_gtpu_ports(),
+        }
+    }
+}
+
+fn default_gtpu_enabled() -> bool {
+    false
+}
+
+fn default_gtpu_ports() -> Vec<u16> {
+    vec![GTPU_PORT]
 }
 
 /* --------------------------------------------------------------------------------- */
 
-/// Connection tracking options.
+/// IP-in-IP decapsulation options.
 ///
-/// These options can be used to tune for resource usage vs. accuracy depending on expected network
-/// characteristics.
+/// When enabled, IPv4 and IPv6 packets encapsulated directly in an IPv4 or IPv6 header (IP
+/// protocol 4, e.g., IPIP and 4in6, and IP protocol 41, e.g., 6in4 and 6in6) are decapsulated
+/// before the packet filter and the connection tracker, like VXLAN frames (see
+/// [VxlanConfig](VxlanConfig)). The inner IP packets are given an Ethernet header with the
+/// addresses of the outer frame. The outer addresses and protocol are kept as
+/// [Tunnel](crate::conntrack::tunnel::Tunnel) metadata.
 ///
 /// ## Example
 /// ```toml
-/// [conntrack]
-///     max_connections = 10_000_000
-///     max_out_of_order = 100
-///     timeout_resolution = 100
-///     udp_inactivity_timeout = 60_000
-///     tcp_inactivity_timeout = 300_000
-///     tcp_establish_timeout = 5000
+/// [conntrack.ipip]
+///     enabled = true
 /// ```
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct ConnTrackConfig {
-    /// Maximum number of connections that can be tracked simultaneously per-core. Defaults to
-    /// `10_000_000`.
-    #[serde(default = "default_max_connections")]
-    pub max_connections: usize,
-
-    /// Maximum number of out-of-order packets allowed per TCP connection before it is force
-    /// expired. Defaults to `100`.
-    #[serde(default = "default_max_out_of_order")]
-    pub max_out_of_order: usize,
-
-    /// Frequency to check for inactive streams (in milliseconds). Defaults to `1000` (1 second).
-    #[serde(default = "default_timeout_resolution")]
-    pub timeout_resolution: usize,
-
-    /// A UDP connection can be inactive for up to this amount of time (in milliseconds) before it
-    /// is force expired. Defaults to `60_000` (1 minute).
-    #[serde(default = "default_udp_inactivity_timeout")]
-    pub udp_inactivity_timeout: usize,
-
-    /// A TCP connection can be inactive for up to this amount of time (in milliseconds) before it
-    /// is force expired. Defaults to `300_000` (5 minutes).
-    #[serde(default = "default_tcp_inactivity_timeout")]
-    pub tcp_inactivity_timeout: usize,
-
-    /// Inactivity time between the first and second packet of a TCP connection before it is force
-    /// expired (in milliseconds).
-    ///
-    /// This approximates connections that remain inactive in either the `SYN-SENT` or
-    /// `SYN-RECEIVED` state without progressing. It is used to prevent memory exhaustion due to SYN
-    /// scans and SYN floods. Defaults to `5000` (5 seconds).
-    #[serde(default = "default_tcp_establish_timeout")]
-    pub tcp_establish_timeout: usize,
+pub struct IpipConfig {
+    /// Whether IP-in-IP packets are decapsulated. Defaults to `false`.
+    #[serde(default = "default_ipip_enabled")]
+    pub enabled: bool,
+}
 
-    #[doc(hidden)]
-    /// Whether to track TCP connections where the first observed packet is a SYN/ACK. Defaults to
-    /// `false`.
-    #[serde(default = "default_init_synack")]
-    pub init_synack: bool,
+impl Default for IpipConfig {
+    fn default() -> Self {
+        IpipConfig {
+            enabled: default_ipip_enabled(),
+        }
+    }
+}
 
-    #[doc(hidden)]
-    /// Whether to track TCP connections where the first observed packet is a FIN. Defaults to
-    /// `false`.
-    #[serde(default = "default_init_fin")]
-    pub init_fin: bool,
+fn default_ipip_enabled() -> bool {
+    false
+}
 
-    #[doc(hidden)]
-    /// Whether to track TCP connections where the first observed packet is a RST. Defaults to
-    /// `false`.
-    #[serde(default = "default_init_rst")]
-    pub init_rst: bool,
+/* --------------------------------------------------------------------------------- */
 
-    #[doc(hidden)]
-    /// Whether to track TCP connections where the first observed packet is a DATA. Defaults to
-    /// `false`.
-    #[serde(default = "default_init_data")]
-    pub init_data: bool,
+/// Teredo and ISATAP decapsulation options.
+///
+/// When enabled, IPv6 packets tunneled over UDP with Teredo (RFC 4380), and over IPv4 with ISATAP
+/// (RFC 5214), are decapsulated before the packet filter and the connection tracker, like VXLAN
+/// frames (see [VxlanConfig](VxlanConfig)). The inner IPv6 packets are given an Ethernet header
+/// with the addresses of the outer frame. The outer 5-tuple (and for Teredo, the mapped address of
+/// the client, if indicated) are kept as [Tunnel](crate::conntrack::tunnel::Tunnel) metadata.
+///
+/// ## Remarks
+/// Teredo packets are recognized by their source or destination UDP port, and by an IPv6 header
+/// following the optional Teredo indicators; direct traffic between Teredo clients and relays
+/// that does not use a Teredo port is not recognized. ISATAP packets are IPv6-in-IPv4 packets
+/// (IP protocol 41) whose source or destination address has an ISATAP interface identifier. Other
+/// IPv6-in-IPv4 packets are decapsulated only if [IpipConfig](IpipConfig) is enabled.
+///
+/// ## Example
+/// ```toml
+/// [conntrack.teredo]
+///     enabled = true
+///     ports = [3544]
+///     isatap = true
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TeredoConfig {
+    /// Whether Teredo packets are decapsulated. Defaults to `false`.
+    #[serde(default = "default_teredo_enabled")]
+    pub enabled: bool,
+
+    /// UDP ports that identify Teredo, as a source or destination port. Defaults to `[3544]`.
+    #[serde(default = "default_teredo_ports")]
+    pub ports: Vec<u16>,
+
+    /// Whether ISATAP packets are decapsulated. Defaults to `false`.
+    #[serde(default = "default_isatap")]
+    pub isatap: bool,
 }
 
-fn default_max_connections() -> usize {
-    10_000_000
+impl Default for TeredoConfig {
+    fn default() -> Self {
+        TeredoConfig {
+            enabled: default_teredo_enabled(),
+            ports: default_teredo_ports(),
+            isatap: default_isatap(),
+        }
+    }
 }
 
-fn default_max_out_of_order() -> usize {
-    100
+fn default_teredo_enabled() -> bool {
+    false
 }
 
-fn default_timeout_resolution() -> usize {
-    1000
+fn default_teredo_ports() -> Vec<u16> {
+    vec![TEREDO_PORT]
 }
 
-fn default_udp_inactivity_timeout() -> usize {
-    60_000
+fn default_isatap() -> bool {
+    false
 }
 
-fn default_tcp_inactivity_timeout() -> usize {
-    300_000
+/* --------------------------------------------------------------------------------- */
+
+/// MPLS decapsulation options.
+///
+/// When enabled, MPLS packets (EtherType `0x8847` and `0x8848`) are decapsulated before the packet
+/// filter and the connection tracker, like VXLAN frames (see [VxlanConfig](VxlanConfig)), so that
+/// the IP traffic of provider core links is tracked. Label stacks of any depth are removed. The
+/// labels are kept as [Tunnel](crate::conntrack::tunnel::Tunnel) metadata, and are filterable with
+/// the `mpls` protocol in connection-level subscriptions (e.g., `mpls.label = 16004`).
+///
+/// ## Remarks
+/// MPLS does not identify its payload: it is inferred from Explicit NULL labels and from the first
+/// nibble of the payload (see [Mpls](crate::protocols::packet::mpls::Mpls)). Ethernet pseudowires
+/// are decapsulated to their inner Ethernet frame if they use a control word, and are otherwise
+/// left as is. Only the top [MPLS_MAX_LABELS](crate::conntrack::tunnel::MPLS_MAX_LABELS) labels
+/// and the bottom label of a stack are kept.
+///
+/// ## Example
+/// ```toml
+/// [conntrack.mpls]
+///     enabled = true
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MplsConfig {
+    /// Whether MPLS packets are decapsulated. Defaults to `false`.
+    #[serde(default = "default_mpls_enabled")]
+    pub enabled: bool,
 }
 
-fn default_tcp_establish_timeout() -> usize {
-    5000
+impl Default for MplsConfig {
+    fn default() -> Self {
+        MplsConfig {
+            enabled: default_mpls_enabled(),
+        }
+    }
 }
 
-fn default_init_synack() -> bool {
+fn default_mpls_enabled() -> bool {
     false
 }
 
-fn default_init_fin() -> bool {
-    false
+/* --------------------------------------------------------------------------------- */
+
+/// PPPoE decapsulation options.
+///
+/// When enabled, the IPv4 and IPv6 packets of PPPoE sessions (EtherType `0x8864`), e.g., on the
+/// subscriber links of a broadband network gateway, are decapsulated before the packet filter and
+/// the connection tracker, like VXLAN frames (see [VxlanConfig](VxlanConfig)). The inner IP packets
+/// are given an Ethernet header with the addresses of the outer frame. The PPPoE session ID is kept
+/// as [Tunnel](crate::conntrack::tunnel::Tunnel) metadata.
+///
+/// ## Remarks
+/// PPPoE discovery packets and PPP control protocols (e.g., LCP, IPCP) are not decapsulated.
+///
+/// ## Example
+/// ```toml
+/// [conntrack.pppoe]
+///     enabled = true
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PppoeConfig {
+    /// Whether PPPoE session packets are decapsulated. Defaults to `false`.
+    #[serde(default = "default_pppoe_enabled")]
+    pub enabled: bool,
 }
 
-fn default_init_rst() -> bool {
-    false
+impl Default for PppoeConfig {
+    fn default() -> Self {
+        PppoeConfig {
+            enabled: default_pppoe_enabled(),
+        }
+    }
 }
 
-fn default_init_data() -> bool {
+fn default_pppoe_enabled() -> bool {
     false
 }
@@ -4,14 +4,22 @@
 // Terminate handler
 // Probe, parse, etc.
 
+use crate::conntrack::conn::TerminationReason;
 use crate::conntrack::pdu::L4Pdu;
 use crate::filter::Actions;
+use crate::lcore::metrics::METRICS;
+use crate::lcore::profile::{self, Stage};
+use crate::lcore::verdict;
 use crate::lcore::CoreId;
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::ethernet::Ethernet;
 use crate::protocols::packet::tcp::TCP_PROTOCOL;
+use crate::protocols::packet::Packet;
 use crate::protocols::stream::{
     ConnData, ParseResult, ParserRegistry, ParsingState, ProbeRegistryResult,
 };
 use crate::subscription::{Subscription, Trackable};
+use crate::utils::pcap::{PcapWriter, PendingPacket};
 use crate::FiveTuple;
 
 #[derive(Debug)]
@@ -25,6 +33,8 @@ where
     pub(crate) cdata: ConnData,
     /// Subscription data (for delivering)
     pub(crate) sdata: T,
+    /// Packets held for the pcap writer until the connection matches
+    pub(crate) pcap_pending: Vec<PendingPacket>,
 }
 
 impl<T> ConnInfo<T>
@@ -33,10 +43,15 @@ where
 {
     pub(super) fn new(pdu: &L4Pdu, core_id: CoreId) -> Self {
         let five_tuple = FiveTuple::from_ctxt(pdu.ctxt);
+        let vlan_ids = match pdu.mbuf_ref().parse_to::<Ethernet>() {
+            Ok(eth) => [eth.vlan_id(), eth.inner_vlan_id()],
+            Err(_) => [None, None],
+        };
         ConnInfo {
             actions: Actions::new(),
-            cdata: ConnData::new(five_tuple),
+            cdata: ConnData::new(five_tuple, pdu.ctxt.tunnels, vlan_ids, pdu.ctxt.echo()),
             sdata: T::new(pdu, core_id),
+            pcap_pending: vec![],
         }
     }
 
@@ -87,6 +102,7 @@ where
         subscription: &Subscription<T::Subscribed>,
         registry: &ParserRegistry,
     ) {
+        let _parsing = profile::enter(Stage::Parsing);
         // In probing stage: application-layer protocol unknown
         if self.actions.session_probe() {
             self.on_probe(pdu, subscription, registry);
@@ -144,6 +160,7 @@ where
 
     fn handle_session(&mut self, subscription: &Subscription<T::Subscribed>, id: usize) {
         if let Some(session) = self.cdata.conn_parser.remove_session(id) {
+            METRICS.record_session(self.sdata.core_id(), &session);
             // Check if session was matched (to be tracked) at protocol level
             // (e.g., "tls" filter), but ensure tracking only happens once
             let session_track = self.actions.session_track();
@@ -174,7 +191,7 @@ where
                 // i.e., no more `updates` are required -- then we can deliver now,
                 // as no more session parsing is expected.
                 if self.actions.conn_deliver_only() {
-                    self.handle_terminate(subscription);
+                    self.handle_terminate(subscription, TerminationReason::Complete);
                     self.actions.clear();
                 }
             }
@@ -184,10 +201,15 @@ where
         }
     }
 
-    pub(crate) fn handle_terminate(&mut self, subscription: &Subscription<T::Subscribed>) {
+    pub(crate) fn handle_terminate(
+        &mut self,
+        subscription: &Subscription<T::Subscribed>,
+        reason: TerminationReason,
+    ) {
         // Session parsing is ongoing: drain any remaining sessions
         if self.actions.session_parse() {
             for session in self.cdata.conn_parser.drain_sessions() {
+                METRICS.record_session(self.sdata.core_id(), &session);
                 let session_track = self.actions.session_track();
                 if self.actions.apply_session_filter() {
                     let actions = subscription.filter_session(&session, &self.cdata, &self.sdata);
@@ -200,10 +222,46 @@ where
         }
 
         if self.actions.connection_matched() {
+            self.sdata.terminate(reason);
             subscription.deliver_conn(&self.cdata, &self.sdata)
         }
+    }
+
+    /// Writes `mbuf` to `pcap` if the connection matched, or holds a copy of it if the
+    /// connection may still match. Must be invoked before the PDU is consumed.
+    pub(crate) fn write_packet(&mut self, mbuf: &Mbuf, pcap: &mut PcapWriter) {
+        if self.actions.packet_write_matched() {
+            self.flush_pcap(pcap);
+            pcap.write_mbuf(mbuf);
+        } else if self.actions.packet_write() {
+            self.pcap_pending.push(pcap.pending(mbuf));
+        }
+    }
+
+    /// Writes the held packets once the connection has matched, or discards them if it can
+    /// no longer match. Invoked after filter updates.
+    pub(crate) fn flush_pcap(&mut self, pcap: &mut PcapWriter) {
+        if self.pcap_pending.is_empty() {
+            return;
+        }
+        if self.actions.packet_write_matched() {
+            for pkt in self.pcap_pending.drain(..) {
+                pcap.write_pending(&pkt);
+            }
+        } else if !self.actions.packet_write() {
+            self.pcap_pending.clear();
+        }
+    }
 
-        self.actions.clear();
+    /// Applies the requests of the callbacks invoked for the packet being processed: a callback
+    /// that has what it needs stops the connection, and others can add actions to it.
+    pub(crate) fn apply_callback_requests(&mut self) {
+        let escalated = verdict::take_escalated();
+        if verdict::take_stop() {
+            self.actions.clear();
+        } else if !escalated.is_none() {
+            self.actions.escalate(escalated);
+        }
     }
 
     // Helper used after filter updates
@@ -229,5 +287,16 @@ where
     pub(crate) fn clear(&mut self) {
         self.cdata.clear();
         self.sdata.clear();
+        self.pcap_pending = vec![];
+    }
+}
+
+impl<T> Drop for ConnInfo<T>
+where
+    T: Trackable,
+{
+    fn drop(&mut self) {
+        // Return the buffer of tracked packets to the pool of this core
+        self.clear_packets();
     }
 }
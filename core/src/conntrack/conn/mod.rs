@@ -4,28 +4,63 @@
 //! manages protocol parser state throughout the duration of the connection.
 
 pub mod conn_info;
+pub mod sctp_conn;
 pub mod tcp_conn;
 pub mod udp_conn;
 
 use self::conn_info::ConnInfo;
+use self::sctp_conn::SctpConn;
+use self::tcp_conn::reassembly::ReassemblyConfig;
 use self::tcp_conn::TcpConn;
 use self::udp_conn::UdpConn;
 use crate::conntrack::conn_id::FiveTuple;
 use crate::conntrack::pdu::{L4Context, L4Pdu};
+use crate::lcore::profile::{self, Stage};
 use crate::lcore::CoreId;
-use crate::protocols::packet::tcp::{ACK, RST, SYN};
+use crate::protocols::packet::tcp::{ACK, FIN, RST, SYN};
 use crate::protocols::stream::ParserRegistry;
 use crate::subscription::{Subscription, Trackable};
+use crate::utils::pcap::PcapWriter;
 
 use anyhow::{bail, Result};
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+use serde::Serialize;
 use std::time::Instant;
 
-/// Tracks either a TCP or a UDP connection.
+/// Tracks a TCP or a UDP connection, or an SCTP association. ICMP Echo exchanges are tracked as
+/// UDP connections.
 ///
-/// Performs light-weight stream reassembly for TCP connections and tracks UDP connections.
+/// Performs light-weight stream reassembly for TCP connections and tracks UDP connections and SCTP
+/// associations.
 pub(crate) enum L4Conn {
     Tcp(TcpConn),
     Udp(UdpConn),
+    Sctp(SctpConn),
+}
+
+/// Why a connection was delivered to the subscription at termination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[serde(rename_all = "snake_case")]
+pub enum TerminationReason {
+    /// Both sides sent a FIN.
+    Fin,
+    /// A side sent a RST.
+    Rst,
+    /// The connection was inactive for longer than its inactivity timeout.
+    Timeout,
+    /// The connection was evicted from a full connection table (see
+    /// [EvictionPolicy](crate::config::EvictionPolicy)).
+    Eviction,
+    /// The connection was still tracked at the end of the run.
+    Drain,
+    /// All sessions were parsed and nothing else was tracked, so the connection was delivered
+    /// before it ended.
+    Complete,
+    /// An ICMP error message reported that the destination of a UDP connection or an ICMP Echo
+    /// exchange is unreachable.
+    Unreachable,
 }
 
 /// Connection state.
@@ -41,6 +76,8 @@ where
     pub(crate) l4conn: L4Conn,
     /// Connection tracking for filtering and parsing.
     pub(crate) info: ConnInfo<T>,
+    /// Whether the connection was terminated, and is only kept to discard its late packets.
+    pub(crate) closed: bool,
 }
 
 impl<T> Conn<T>
@@ -48,28 +85,33 @@ where
     T: Trackable,
 {
     /// Creates a new TCP connection from `ctxt` with an initial inactivity window of
-    /// `initial_timeout` and a maximum out-or-order tolerance of `max_ooo`. This means that there
-    /// can be at most `max_ooo` packets buffered out of sequence before Retina chooses to discard
-    /// the connection.
+    /// `initial_timeout` and out-of-order buffers configured by `config`. Connections that do not
+    /// start with a SYN are only created if `midstream` is set. If `unidirectional` is set, the
+    /// connection only tracks the direction of `ctxt`, which may also start with a SYN/ACK.
     pub(super) fn new_tcp(
         initial_timeout: usize,
-        max_ooo: usize,
+        config: ReassemblyConfig,
+        midstream: bool,
+        unidirectional: bool,
         pdu: &L4Pdu,
         core_id: CoreId,
     ) -> Result<Self> {
         let tcp_conn = if pdu.ctxt.flags & SYN != 0
-            && pdu.ctxt.flags & ACK == 0
+            && (pdu.ctxt.flags & ACK == 0 || unidirectional)
             && pdu.ctxt.flags & RST == 0
         {
-            TcpConn::new_on_syn(pdu.ctxt, max_ooo)
+            TcpConn::new_on_syn(pdu.ctxt, config, unidirectional)
+        } else if midstream && pdu.ctxt.flags & RST == 0 {
+            TcpConn::new_midstream(pdu.ctxt, config, unidirectional)
         } else {
             bail!("Not SYN")
         };
         Ok(Conn {
-            last_seen_ts: Instant::now(),
+            last_seen_ts: pdu.mbuf_ref().rx_time(),
             inactivity_window: initial_timeout,
             l4conn: L4Conn::Tcp(tcp_conn),
             info: ConnInfo::new(pdu, core_id),
+            closed: false,
         })
     }
 
@@ -79,10 +121,28 @@ where
     pub(super) fn new_udp(initial_timeout: usize, pdu: &L4Pdu, core_id: CoreId) -> Result<Self> {
         let udp_conn = UdpConn;
         Ok(Conn {
-            last_seen_ts: Instant::now(),
+            last_seen_ts: pdu.mbuf_ref().rx_time(),
             inactivity_window: initial_timeout,
             l4conn: L4Conn::Udp(udp_conn),
             info: ConnInfo::new(pdu, core_id),
+            closed: false,
+        })
+    }
+
+    /// Creates a new SCTP association from `ctxt` with an initial inactivity window of
+    /// `initial_timeout`. Associations are picked up from any packet but their teardown.
+    pub(super) fn new_sctp(initial_timeout: usize, pdu: &L4Pdu, core_id: CoreId) -> Result<Self> {
+        if pdu.ctxt.flags & (RST | FIN) != 0 {
+            bail!("SCTP teardown");
+        }
+        Ok(Conn {
+            last_seen_ts: pdu.mbuf_ref().rx_time(),
+            inactivity_window: initial_timeout,
+            l4conn: L4Conn::Sctp(SctpConn {
+                flags: pdu.ctxt.flags,
+            }),
+            info: ConnInfo::new(pdu, core_id),
+            closed: false,
         })
     }
 
@@ -95,17 +155,24 @@ where
     ) {
         match &mut self.l4conn {
             L4Conn::Tcp(tcp_conn) => {
+                let _reassembly = profile::enter(Stage::Reassembly);
                 tcp_conn.reassemble(pdu, &mut self.info, subscription, registry);
             }
             L4Conn::Udp(_udp_conn) => self.info.consume_pdu(pdu, subscription, registry),
+            L4Conn::Sctp(sctp_conn) => {
+                sctp_conn.flags |= pdu.flags();
+                self.info.consume_pdu(pdu, subscription, registry);
+            }
         }
     }
 
     /// Updates flags
     #[inline]
     pub(super) fn update_tcp_flags(&mut self, flags: u8, dir: bool) {
-        if let L4Conn::Tcp(tcp_conn) = &mut self.l4conn {
-            tcp_conn.update_flags(flags, dir);
+        match &mut self.l4conn {
+            L4Conn::Tcp(tcp_conn) => tcp_conn.update_flags(flags, dir),
+            L4Conn::Sctp(sctp_conn) => sctp_conn.flags |= flags,
+            L4Conn::Udp(_) => {}
         }
     }
 
@@ -119,11 +186,12 @@ where
     /// that pass the packet filter stage are assumed to represent an
     /// existing or new connection and are inserted into the connection
     /// table. Keeping UDP connections in "drop" state for a buffer
-    /// period prevents dropped connections from being re-inserted.
-    pub(super) fn remove_from_table(&self) -> bool {
+    /// period prevents dropped connections from being re-inserted. With `bypass`, TCP connections
+    /// and SCTP associations are kept as well, until they close or time out.
+    pub(super) fn remove_from_table(&self, bypass: bool) -> bool {
         match &self.l4conn {
             L4Conn::Udp(_) => false,
-            _ => self.info.actions.drop(),
+            _ => !bypass && self.info.actions.drop(),
         }
     }
 
@@ -132,11 +200,12 @@ where
         self.info.actions.drop()
     }
 
-    /// Returns `true` if the connection has been naturally terminated.
-    pub(super) fn terminated(&self) -> bool {
+    /// Returns why the connection was naturally terminated, or `None` if it was not.
+    pub(super) fn terminated(&self) -> Option<TerminationReason> {
         match &self.l4conn {
-            L4Conn::Tcp(tcp_conn) => tcp_conn.is_terminated(),
-            L4Conn::Udp(_udp_conn) => false,
+            L4Conn::Tcp(tcp_conn) => tcp_conn.termination(),
+            L4Conn::Udp(_udp_conn) => None,
+            L4Conn::Sctp(sctp_conn) => sctp_conn.termination(),
         }
     }
 
@@ -150,8 +219,22 @@ where
     /// occur:
     /// - the connection naturally terminates (e.g., FIN/RST)
     /// - the connection expires due to inactivity
+    /// - the connection is evicted from a full table
     /// - the connection is drained at the end of the run
-    pub(crate) fn terminate(&mut self, subscription: &Subscription<T::Subscribed>) {
-        self.info.handle_terminate(subscription);
+    ///
+    /// Connections are only terminated once: later calls have no effect.
+    pub(crate) fn terminate(
+        &mut self,
+        subscription: &Subscription<T::Subscribed>,
+        pcap: &mut PcapWriter,
+        reason: TerminationReason,
+    ) {
+        if self.closed {
+            return;
+        }
+        self.info.handle_terminate(subscription, reason);
+        self.info.flush_pcap(pcap);
+        self.info.actions.clear();
+        self.closed = true;
     }
 }
@@ -0,0 +1,26 @@
+use crate::conntrack::conn::TerminationReason;
+use crate::protocols::packet::tcp::{FIN, RST};
+
+/// Tracks the setup and teardown of an SCTP association.
+///
+/// SCTP chunks are not reassembled: the payload of each packet is consumed as it arrives.
+pub(crate) struct SctpConn {
+    /// Setup and teardown chunks observed in the association, mapped to TCP flags (see
+    /// [L4Context](crate::conntrack::pdu::L4Context)).
+    pub(crate) flags: u8,
+}
+
+impl SctpConn {
+    /// Returns why the association should be terminated (an ABORT or a SHUTDOWN COMPLETE has been
+    /// sent), or `None` if it should not.
+    #[inline]
+    pub(crate) fn termination(&self) -> Option<TerminationReason> {
+        if self.flags & RST != 0 {
+            Some(TerminationReason::Rst)
+        } else if self.flags & FIN != 0 {
+            Some(TerminationReason::Fin)
+        } else {
+            None
+        }
+    }
+}
@@ -1,24 +1,50 @@
 pub mod reassembly;
 
-use self::reassembly::TcpFlow;
+use self::reassembly::{ReassemblyConfig, TcpFlow};
 use crate::conntrack::conn::conn_info::ConnInfo;
+use crate::conntrack::conn::TerminationReason;
 use crate::conntrack::pdu::{L4Context, L4Pdu};
-use crate::protocols::packet::tcp::{FIN, RST};
+use crate::protocols::packet::tcp::{ACK, FIN, RST, SYN};
 use crate::protocols::stream::ParserRegistry;
 use crate::subscription::{Subscription, Trackable};
 
 pub(crate) struct TcpConn {
     pub(crate) ctos: TcpFlow,
     pub(crate) stoc: TcpFlow,
+    /// Whether only the originator -> responder direction is tracked.
+    pub(crate) unidirectional: bool,
 }
 
 impl TcpConn {
-    pub(crate) fn new_on_syn(ctxt: L4Context, max_ooo: usize) -> Self {
+    pub(crate) fn new_on_syn(
+        ctxt: L4Context,
+        config: ReassemblyConfig,
+        unidirectional: bool,
+    ) -> Self {
         let flags = ctxt.flags;
         let next_seq = ctxt.seq_no.wrapping_add(1 + ctxt.length as u32);
         TcpConn {
-            ctos: TcpFlow::new(max_ooo, next_seq, flags),
-            stoc: TcpFlow::default(max_ooo),
+            ctos: TcpFlow::new(config, next_seq, flags),
+            stoc: TcpFlow::default(config),
+            unidirectional,
+        }
+    }
+
+    /// Picks up a connection from a packet that is not a SYN. The sequence number of the other
+    /// direction is taken from its first packet with an ACK.
+    pub(crate) fn new_midstream(
+        ctxt: L4Context,
+        config: ReassemblyConfig,
+        unidirectional: bool,
+    ) -> Self {
+        let flags = ctxt.flags;
+        let next_seq = ctxt
+            .seq_no
+            .wrapping_add(ctxt.length as u32 + (flags & (SYN | FIN) != 0) as u32);
+        TcpConn {
+            ctos: TcpFlow::new(config, next_seq, flags),
+            stoc: TcpFlow::default(config),
+            unidirectional,
         }
     }
 
@@ -31,6 +57,16 @@ impl TcpConn {
         subscription: &Subscription<T::Subscribed>,
         registry: &ParserRegistry,
     ) {
+        if segment.flags() & ACK != 0 {
+            let flow = if segment.dir {
+                &mut self.stoc
+            } else {
+                &mut self.ctos
+            };
+            if flow.ooo_buf.len() > 0 && flow.ooo_buf.skips_acked_gaps() {
+                flow.skip_acked_gap::<T>(&segment, info, subscription, registry);
+            }
+        }
         if segment.dir {
             self.ctos
                 .insert_segment::<T>(segment, info, subscription, registry);
@@ -40,14 +76,29 @@ impl TcpConn {
         }
     }
 
-    /// Returns `true` if the connection should be terminated
+    /// Returns why the connection should be terminated (a RST has been sent, or both sides have
+    /// sent FIN, or the originator has if only its direction is tracked), or `None` if it should
+    /// not.
+    #[inline]
+    pub(crate) fn termination(&self) -> Option<TerminationReason> {
+        let fin = if self.unidirectional {
+            self.ctos.consumed_flags
+        } else {
+            self.ctos.consumed_flags & self.stoc.consumed_flags
+        };
+        if (self.ctos.consumed_flags | self.stoc.consumed_flags) & RST != 0 {
+            Some(TerminationReason::Rst)
+        } else if fin & FIN != 0 {
+            Some(TerminationReason::Fin)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if either side has sent a FIN.
     #[inline]
-    pub(crate) fn is_terminated(&self) -> bool {
-        // Both sides have sent FIN, or a RST has been sent
-        (self.ctos.consumed_flags & self.stoc.consumed_flags & FIN
-            | self.ctos.consumed_flags & RST
-            | self.stoc.consumed_flags & RST)
-            != 0
+    pub(crate) fn half_closed(&self) -> bool {
+        (self.ctos.consumed_flags | self.stoc.consumed_flags) & FIN != 0
     }
 
     /// Updates connection termination flags
@@ -1,13 +1,50 @@
+use crate::config::{OutOfOrderOverflow, OverlapPolicy};
 use crate::conntrack::conn::conn_info::ConnInfo;
 use crate::conntrack::pdu::L4Pdu;
 use crate::filter::Actions;
+use crate::memory::pool;
 use crate::protocols::packet::tcp::{ACK, FIN, RST, SYN};
 use crate::protocols::stream::ParserRegistry;
 use crate::subscription::{Subscription, Trackable};
 
-use anyhow::{bail, Result};
+use std::cell::Cell;
 use std::collections::VecDeque;
 
+thread_local! {
+    /// Payload bytes held in the out-of-order buffers of the current core.
+    static HELD_BYTES: Cell<usize> = const { Cell::new(0) };
+    /// Number of out-of-order buffer overflows on the current core.
+    static OVERFLOWS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Returns the number of payload bytes held in the out-of-order buffers of the current core.
+pub(crate) fn held_bytes() -> usize {
+    HELD_BYTES.with(|b| b.get())
+}
+
+/// Returns the number of out-of-order buffer overflows on the current core.
+pub(crate) fn nb_overflows() -> u64 {
+    OVERFLOWS.with(|o| o.get())
+}
+
+/// Options for the out-of-order buffer of a TCP flow (see
+/// [ConnTrackConfig](crate::config::ConnTrackConfig)).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ReassemblyConfig {
+    /// Maximum number of segments buffered in the flow.
+    pub(crate) max_segments: usize,
+    /// Maximum number of payload bytes buffered in the flow.
+    pub(crate) max_bytes: usize,
+    /// Maximum number of payload bytes buffered across all flows of the core.
+    pub(crate) max_total_bytes: usize,
+    /// Handling of a segment that does not fit in the buffer.
+    pub(crate) overflow: OutOfOrderOverflow,
+    /// Data kept when a segment overlaps buffered segments.
+    pub(crate) overlap: OverlapPolicy,
+    /// Whether to skip missing data once the receiver acknowledged it.
+    pub(crate) skip_acked_gaps: bool,
+}
+
 /// Represents a uni-directional TCP flow
 #[derive(Debug)]
 pub(crate) struct TcpFlow {
@@ -23,28 +60,28 @@ pub(crate) struct TcpFlow {
 impl TcpFlow {
     /// Creates a default TCP flow
     #[inline]
-    pub(super) fn default(capacity: usize) -> Self {
+    pub(super) fn default(config: ReassemblyConfig) -> Self {
         TcpFlow {
             next_seq: None,
             consumed_flags: 0,
-            ooo_buf: OutOfOrderBuffer::new(capacity),
+            ooo_buf: OutOfOrderBuffer::new(config),
         }
     }
 
     /// Creates a new TCP flow with given next sequence number, flags,
     /// and out-of-order buffer
     #[inline]
-    pub(super) fn new(capacity: usize, next_seq: u32, flags: u8) -> Self {
+    pub(super) fn new(config: ReassemblyConfig, next_seq: u32, flags: u8) -> Self {
         TcpFlow {
             next_seq: Some(next_seq),
             consumed_flags: flags,
-            ooo_buf: OutOfOrderBuffer::new(capacity),
+            ooo_buf: OutOfOrderBuffer::new(config),
         }
     }
 
     /// Attempt to insert incoming data segment into flow.
     /// Buffer future segments and drop old segments.
-    /// Shunts TcpStream if the incoming segment causes out-of-order buffer overflow
+    /// Handles out-of-order buffer overflows according to the configured policy
     #[inline]
     pub(super) fn insert_segment<T: Trackable>(
         &mut self,
@@ -72,7 +109,7 @@ impl TcpFlow {
                 self.flush_ooo_buffer::<T>(expected_seq, info, subscription, registry);
             } else if wrapping_lt(next_seq, cur_seq) {
                 // Segment comes after the next expected segment
-                self.buffer_ooo_seg(segment, info);
+                self.buffer_ooo_seg::<T>(segment, info, subscription, registry);
             } else if let Some(expected_seq) = overlap(&mut segment, next_seq) {
                 // Segment starts before the next expected segment but has new data
                 self.consumed_flags |= segment.flags();
@@ -88,27 +125,88 @@ impl TcpFlow {
                 drop(segment);
             }
         } else {
-            // expecting SYNACK in response to the originator's SYN
+            // expecting SYNACK in response to the originator's SYN, or any ACK on connections
+            // picked up mid-stream
             if segment.flags() & (SYN | ACK) != 0 {
-                let expected_seq = cur_seq.wrapping_add(1 + length);
+                let expected_seq =
+                    cur_seq.wrapping_add((segment.flags() & SYN != 0) as u32 + length);
                 self.next_seq = Some(expected_seq);
                 self.consumed_flags |= segment.flags();
                 info.consume_pdu(segment, subscription, registry);
                 self.flush_ooo_buffer::<T>(expected_seq, info, subscription, registry);
             } else {
                 // Buffer out-of-order non-SYNACK packets
-                self.buffer_ooo_seg(segment, info);
+                self.buffer_ooo_seg::<T>(segment, info, subscription, registry);
             }
         }
     }
 
+    /// Skips the missing data at the start of the flow if `ack`, a segment of the other direction,
+    /// shows that the receiver already has it: the data was lost by the monitor and will not be
+    /// retransmitted. The receiver has the data if it is acknowledged, or covered by a SACK block.
+    #[inline]
+    pub(super) fn skip_acked_gap<T: Trackable>(
+        &mut self,
+        ack: &L4Pdu,
+        info: &mut ConnInfo<T>,
+        subscription: &Subscription<T::Subscribed>,
+        registry: &ParserRegistry,
+    ) {
+        let (next_seq, first_seq) = match (self.next_seq, self.ooo_buf.first_seq()) {
+            (Some(next_seq), Some(first_seq)) => (next_seq, first_seq),
+            _ => return,
+        };
+        let acked = wrapping_lt(next_seq, ack.ack_no())
+            || ack.sack_blocks().iter().any(|(left, right)| {
+                !wrapping_lt(next_seq, *left) && wrapping_lt(next_seq, *right)
+            });
+        if acked {
+            log::debug!(
+                "Skipping acknowledged gap. next: {}, first buffered: {}",
+                next_seq,
+                first_seq
+            );
+            self.flush_ooo_buffer::<T>(first_seq, info, subscription, registry);
+        }
+    }
+
     /// Insert packet into ooo buffer and handle overflow
     #[inline]
-    fn buffer_ooo_seg<T: Trackable>(&mut self, segment: L4Pdu, info: &mut ConnInfo<T>) {
-        if self.ooo_buf.insert_back(segment).is_err() {
-            log::warn!("Out-of-order buffer overflow");
-            // Drop the connection
-            info.actions = Actions::new();
+    fn buffer_ooo_seg<T: Trackable>(
+        &mut self,
+        segment: L4Pdu,
+        info: &mut ConnInfo<T>,
+        subscription: &Subscription<T::Subscribed>,
+        registry: &ParserRegistry,
+    ) {
+        let segment = match self.ooo_buf.insert_back(segment) {
+            Ok(()) => return,
+            Err(segment) => segment,
+        };
+        OVERFLOWS.with(|o| o.set(o.get() + 1));
+        match self.ooo_buf.config.overflow {
+            OutOfOrderOverflow::Drop => {
+                log::debug!("Out-of-order buffer overflow, dropping connection");
+                drop(segment);
+                self.ooo_buf.clear();
+                info.actions = Actions::new();
+            }
+            OutOfOrderOverflow::SkipGap => {
+                log::debug!("Out-of-order buffer overflow, skipping gap");
+                self.ooo_buf.push_back(segment);
+                // Each flush consumes at least the earliest segment
+                while self.ooo_buf.exceeds_limits() {
+                    match self.ooo_buf.first_seq() {
+                        Some(first_seq) if !info.actions.drop() => {
+                            self.flush_ooo_buffer::<T>(first_seq, info, subscription, registry);
+                        }
+                        _ => {
+                            self.ooo_buf.clear();
+                            break;
+                        }
+                    }
+                }
+            }
         }
     }
 
@@ -140,36 +238,125 @@ impl TcpFlow {
 /// A buffer to hold reordered TCP segments
 #[derive(Debug)]
 pub(crate) struct OutOfOrderBuffer {
-    capacity: usize,
-    pub(crate) buf: VecDeque<L4Pdu>,
+    config: ReassemblyConfig,
+    /// Payload bytes held in the buffer.
+    bytes: usize,
+    buf: VecDeque<L4Pdu>,
 }
 
 impl OutOfOrderBuffer {
-    /// Creates a new OutOfOrderBuffer with capacity
-    fn new(capacity: usize) -> Self {
+    /// Creates a new OutOfOrderBuffer with options `config`
+    fn new(config: ReassemblyConfig) -> Self {
         OutOfOrderBuffer {
-            capacity,
+            config,
+            bytes: 0,
             buf: VecDeque::new(),
         }
     }
 
+    /// Returns `true` if missing data acknowledged by the receiver is skipped.
+    pub(crate) fn skips_acked_gaps(&self) -> bool {
+        self.config.skip_acked_gaps
+    }
+
     /// Returns the number of elements in the buffer
     pub(crate) fn len(&self) -> usize {
         self.buf.len()
     }
 
-    /// Inserts segment at the end of the buffer.
-    fn insert_back(&mut self, segment: L4Pdu) -> Result<()> {
+    /// Inserts segment at the end of the buffer. Returns the segment if it does not fit within
+    /// the limits.
+    fn insert_back(&mut self, segment: L4Pdu) -> Result<(), L4Pdu> {
         log::debug!("insert with seq : {:#?}", segment.seq_no());
-        if self.len() >= self.capacity {
-            // // must clear to drop buffered Mbufs
-            // self.buf.clear();
-            bail!("Out-of-order buffer overflow.");
+        let length = segment.length();
+        if self.len() >= self.config.max_segments
+            || self.bytes + length > self.config.max_bytes
+            || held_bytes() + length > self.config.max_total_bytes
+        {
+            return Err(segment);
         }
-        self.buf.push_back(segment);
+        self.push_back(segment);
         Ok(())
     }
 
+    /// Inserts segment at the end of the buffer, regardless of the limits. Data that overlaps
+    /// buffered segments is resolved according to the overlap policy.
+    fn push_back(&mut self, segment: L4Pdu) {
+        let start = segment.seq_no();
+        let len = segment.length() as i64;
+        let mut pieces = vec![segment];
+        let mut index = 0;
+        while index < self.len() && len > 0 {
+            // Buffered segment, relative to the start of the new segment
+            let old = &self.buf[index];
+            let (old_seq, old_len) = (old.seq_no(), old.length() as i64);
+            let old_start = old_seq.wrapping_sub(start) as i32 as i64;
+            let old_end = old_start + old_len;
+            if old_end <= 0 || old_start >= len {
+                index += 1;
+            } else if new_wins(self.config.overlap, len, old_start, old_end) {
+                // The remaining parts do not overlap, and are skipped when visited again
+                let old = self.remove(index).unwrap();
+                for piece in remove_range(old, -old_start, len - old_start) {
+                    self.push_piece(piece);
+                }
+            } else {
+                pieces = pieces
+                    .into_iter()
+                    .flat_map(|piece| {
+                        let from = old_seq.wrapping_sub(piece.seq_no()) as i32 as i64;
+                        remove_range(piece, from, from + old_len)
+                    })
+                    .collect();
+                index += 1;
+            }
+        }
+        for piece in pieces {
+            self.push_piece(piece);
+        }
+    }
+
+    fn push_piece(&mut self, segment: L4Pdu) {
+        self.hold(segment.length() as isize);
+        if self.buf.capacity() == 0 {
+            self.buf = VecDeque::from(pool::take_segments());
+        }
+        self.buf.push_back(segment);
+    }
+
+    /// Removes the segment at `index`.
+    fn remove(&mut self, index: usize) -> Option<L4Pdu> {
+        let segment = self.buf.remove(index)?;
+        self.hold(-(segment.length() as isize));
+        Some(segment)
+    }
+
+    /// Drops all buffered segments.
+    fn clear(&mut self) {
+        self.hold(-(self.bytes as isize));
+        self.buf.clear();
+    }
+
+    fn hold(&mut self, bytes: isize) {
+        self.bytes = self.bytes.wrapping_add_signed(bytes);
+        HELD_BYTES.with(|b| b.set(b.get().wrapping_add_signed(bytes)));
+    }
+
+    /// Returns `true` if the buffer holds more than its limits allow.
+    fn exceeds_limits(&self) -> bool {
+        self.len() > self.config.max_segments
+            || self.bytes > self.config.max_bytes
+            || held_bytes() > self.config.max_total_bytes
+    }
+
+    /// Returns the earliest sequence number in the buffer.
+    fn first_seq(&self) -> Option<u32> {
+        self.buf
+            .iter()
+            .map(|segment| segment.seq_no())
+            .reduce(|first, seq| if wrapping_lt(seq, first) { seq } else { first })
+    }
+
     /// Consumes segments with expected data, retains segments with future data,
     /// and drops segments with old data.
     /// Returns the next expected sequence number and control flags of consumed segments.
@@ -194,7 +381,7 @@ impl OutOfOrderBuffer {
             log::debug!("Flushing...current seq: {:#?}", cur_seq);
 
             if next_seq == cur_seq {
-                let segment = self.buf.remove(index).unwrap();
+                let segment = self.remove(index).unwrap();
                 *consumed_flags |= segment.flags();
                 if segment.flags() & RST != 0 {
                     info.consume_pdu(segment, subscription, registry);
@@ -209,7 +396,7 @@ impl OutOfOrderBuffer {
             } else if wrapping_lt(next_seq, cur_seq) {
                 index += 1;
             } else {
-                let mut segment = self.buf.remove(index).unwrap();
+                let mut segment = self.remove(index).unwrap();
                 if let Some(update_seq) = overlap(&mut segment, next_seq) {
                     next_seq = update_seq;
                     *consumed_flags |= segment.flags();
@@ -226,6 +413,56 @@ impl OutOfOrderBuffer {
     }
 }
 
+/// Returns `true` if the data of a new segment of length `len` replaces the data of a buffered
+/// segment spanning `old_start..old_end`, relative to the start of the new segment.
+fn new_wins(policy: OverlapPolicy, len: i64, old_start: i64, old_end: i64) -> bool {
+    match policy {
+        OverlapPolicy::First => false,
+        OverlapPolicy::Last => true,
+        OverlapPolicy::Bsd => old_start > 0,
+        OverlapPolicy::Linux => old_start > 0 || (old_start == 0 && len > old_end),
+        OverlapPolicy::Windows => old_start > 0 && len >= old_end,
+    }
+}
+
+/// Returns the parts of `segment` outside of `from..to`, relative to the start of the segment.
+fn remove_range(segment: L4Pdu, from: i64, to: i64) -> Vec<L4Pdu> {
+    let len = segment.length() as i64;
+    if to <= 0 || from >= len {
+        return vec![segment];
+    }
+    let mut parts = vec![];
+    if from > 0 {
+        parts.push(slice(&segment, 0, from as usize));
+    }
+    if to < len {
+        parts.push(slice(&segment, to as usize, len as usize));
+    }
+    parts
+}
+
+/// Returns the payload of `segment` in `from..to`, sharing its packet buffer.
+fn slice(segment: &L4Pdu, from: usize, to: usize) -> L4Pdu {
+    let mut piece = segment.clone();
+    piece.ctxt.offset += from;
+    piece.ctxt.length = to - from;
+    piece.ctxt.seq_no = segment.seq_no().wrapping_add(from as u32);
+    if from > 0 {
+        piece.ctxt.flags &= !SYN;
+    }
+    if to < segment.length() {
+        piece.ctxt.flags &= !FIN;
+    }
+    piece
+}
+
+impl Drop for OutOfOrderBuffer {
+    fn drop(&mut self) {
+        self.clear();
+        pool::recycle_segments(Vec::from(std::mem::take(&mut self.buf)));
+    }
+}
+
 pub fn wrapping_lt(lhs: u32, rhs: u32) -> bool {
     // From RFC1323:
     //     TCP determines if a data segment is "old" or "new" by testing
@@ -257,3 +494,32 @@ fn overlap(segment: &mut L4Pdu, expected_seq: u32) -> Option<u32> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_overlap_policies() {
+        // New segment 0..10 over buffered segments 5..15, 0..5, and 0..15
+        let cases = [(5, 15), (0, 5), (0, 15)];
+        let expected = [
+            (OverlapPolicy::First, [false, false, false]),
+            (OverlapPolicy::Last, [true, true, true]),
+            (OverlapPolicy::Bsd, [true, false, false]),
+            (OverlapPolicy::Linux, [true, true, false]),
+            (OverlapPolicy::Windows, [false, false, false]),
+        ];
+        for (policy, wins) in expected {
+            for ((old_start, old_end), win) in cases.into_iter().zip(wins) {
+                assert_eq!(
+                    new_wins(policy, 10, old_start, old_end),
+                    win,
+                    "{:?}",
+                    policy
+                );
+            }
+        }
+        assert!(new_wins(OverlapPolicy::Windows, 10, 2, 8));
+    }
+}
@@ -5,12 +5,17 @@
 
 use crate::conntrack::L4Context;
 
+use crate::protocols::packet::icmp::ICMP_PROTOCOL;
+use crate::protocols::packet::icmpv6::ICMPV6_PROTOCOL;
+use crate::protocols::packet::sctp::SCTP_PROTOCOL;
 use crate::protocols::packet::tcp::TCP_PROTOCOL;
 use crate::protocols::packet::udp::UDP_PROTOCOL;
 use std::cmp;
 use std::fmt;
 use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddr::V4, SocketAddr::V6};
 
+#[cfg(feature = "serde")]
+use serde::Deserialize;
 use serde::Serialize;
 
 /// Connection 5-tuple.
@@ -18,6 +23,7 @@ use serde::Serialize;
 /// The sender of the first observed packet in the connection becomes the originator `orig`, and the
 /// recipient becomes the responder `resp`.
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct FiveTuple {
     /// The originator connection endpoint.
     pub orig: SocketAddr,
@@ -85,6 +91,9 @@ impl FiveTuple {
         let proto = match self.proto {
             UDP_PROTOCOL => "udp",
             TCP_PROTOCOL => "tcp",
+            ICMP_PROTOCOL => "icmp",
+            ICMPV6_PROTOCOL => "icmpv6",
+            SCTP_PROTOCOL => "sctp",
             _ => "none",
         };
         format!(
@@ -106,7 +115,9 @@ impl fmt::Display for FiveTuple {
 /// A generic connection identifier.
 ///
 /// Identifies a connection independent of the source and destination socket address order. Does not
-/// distinguish between the originator and responder of the connection.
+/// distinguish between the originator and responder of the connection. When each direction is
+/// tracked separately (see [unidirectional](crate::config::ConnTrackConfig::unidirectional)), flows
+/// are identified by their ordered socket addresses instead.
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct ConnId(SocketAddr, SocketAddr, usize);
 
@@ -115,6 +126,12 @@ impl ConnId {
     pub(super) fn new(src: SocketAddr, dst: SocketAddr, protocol: usize) -> Self {
         ConnId(cmp::max(src, dst), cmp::min(src, dst), protocol)
     }
+
+    /// Returns the ID of the flow of packets from `src` to `dst`, which differs from the ID of the
+    /// flow in the opposite direction.
+    pub(super) fn new_unidirectional(src: SocketAddr, dst: SocketAddr, protocol: usize) -> Self {
+        ConnId(src, dst, protocol)
+    }
 }
 
 impl fmt::Display for ConnId {
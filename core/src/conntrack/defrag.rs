@@ -0,0 +1,548 @@
+//! IPv4 and IPv6 reassembly.
+//!
+//! Fragmented datagrams are reassembled before the packet filter and the connection tracker, so
+//! that their payload is attributed to the right connection (see
+//! [DefragConfig](crate::config::DefragConfig)). Unfragmented packets pass through unchanged.
+//!
+//! The fragments of a datagram are held until it is complete. The reassembled datagram is copied
+//! into a single packet buffer; if it does not fit, its fragments pass through unchanged instead.
+
+use crate::config::DefragConfig;
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::ethernet::Ethernet;
+use crate::protocols::packet::ipv4::Ipv4;
+use crate::protocols::packet::ipv6::{
+    Ipv6, IPV6_EXT_AUTH, IPV6_EXT_DST_OPTS, IPV6_EXT_FRAGMENT, IPV6_EXT_HOP_BY_HOP,
+    IPV6_EXT_ROUTING,
+};
+use crate::protocols::packet::Packet;
+
+use std::net::IpAddr;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+use std::{iter, option, vec};
+
+use hashlink::linked_hash_map::LinkedHashMap;
+
+/// Maximum value of the IPv4 Total Length and IPv6 Payload Length fields.
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+const IPV6_HEADER_LEN: usize = 40;
+const IPV6_FRAGMENT_HEADER_LEN: usize = 8;
+
+/// Packets released by [Defrag::push](Defrag::push), in order.
+pub(crate) type Released = iter::Chain<option::IntoIter<Mbuf>, vec::IntoIter<Mbuf>>;
+
+fn released(packet: Option<Mbuf>, fragments: Vec<Mbuf>) -> Released {
+    packet.into_iter().chain(fragments)
+}
+
+/// Identifies the fragments of the same datagram (RFC 791, RFC 8200).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct DatagramId {
+    src: IpAddr,
+    dst: IpAddr,
+    id: u32,
+    /// Protocol, part of the identifier for IPv4 only.
+    proto: Option<u8>,
+}
+
+/// IP version of a datagram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Version {
+    V4,
+    /// IPv6, with the Next Header field of the Fragment header.
+    V6 {
+        next_header: u8,
+    },
+}
+
+/// A fragment, located in the packet buffer that carries it.
+#[derive(Debug)]
+struct Fragment {
+    datagram: DatagramId,
+    version: Version,
+    /// Offset of the IP header.
+    ip_start: usize,
+    /// End of the headers kept in the reassembled datagram: the IPv4 header, or the IPv6 header
+    /// and the extension headers preceding the Fragment header.
+    headers_end: usize,
+    /// Maximum length of the datagram payload.
+    max_len: usize,
+    /// Offset of the fragment payload in the datagram payload.
+    offset: usize,
+    more: bool,
+    /// Fragment payload.
+    payload: Range<usize>,
+}
+
+impl Fragment {
+    /// Returns the fragment carried by `mbuf`, or `None` if it does not carry a complete IPv4 or
+    /// IPv6 fragment.
+    fn parse(mbuf: &Mbuf) -> Option<Fragment> {
+        let eth = mbuf.parse_to::<Ethernet>().ok()?;
+        if let Ok(ipv4) = eth.parse_to::<Ipv4>() {
+            return Self::parse_ipv4(&eth, &ipv4);
+        }
+        let ipv6 = eth.parse_to::<Ipv6>().ok()?;
+        Self::parse_ipv6(&eth, &ipv6)
+    }
+
+    fn parse_ipv4(eth: &Ethernet, ipv4: &Ipv4) -> Option<Fragment> {
+        if !ipv4.mf() && ipv4.fragment_offset() == 0 {
+            return None;
+        }
+        let ip_start = eth.next_header_offset();
+        let end = ip_start + ipv4.total_length() as usize;
+        let start = ipv4.next_header_offset();
+        if start > end || end > eth.mbuf().data_len() {
+            return None;
+        }
+        Some(Fragment {
+            datagram: DatagramId {
+                src: ipv4.src_addr().into(),
+                dst: ipv4.dst_addr().into(),
+                id: ipv4.identification().into(),
+                proto: Some(ipv4.protocol()),
+            },
+            version: Version::V4,
+            ip_start,
+            headers_end: start,
+            max_len: MAX_DATAGRAM_SIZE - (start - ip_start),
+            offset: ipv4.fragment_offset() as usize * 8,
+            more: ipv4.mf(),
+            payload: start..end,
+        })
+    }
+
+    fn parse_ipv6(eth: &Ethernet, ipv6: &Ipv6) -> Option<Fragment> {
+        // Atomic fragments (RFC 6946) are processed as they are
+        if !ipv6.mf() && ipv6.fragment_offset() == 0 {
+            return None;
+        }
+        let ip_start = eth.next_header_offset();
+        let end = ip_start + IPV6_HEADER_LEN + ipv6.payload_length() as usize;
+        let data = eth.mbuf().data();
+        if end > data.len() {
+            return None;
+        }
+        let (_, fragment_start) = find_fragment_header(&data[..end], ip_start)?;
+        let start = fragment_start + IPV6_FRAGMENT_HEADER_LEN;
+        if start > end {
+            return None;
+        }
+        Some(Fragment {
+            datagram: DatagramId {
+                src: ipv6.src_addr().into(),
+                dst: ipv6.dst_addr().into(),
+                id: ipv6.fragment_id(),
+                proto: None,
+            },
+            version: Version::V6 {
+                next_header: data[fragment_start],
+            },
+            ip_start,
+            headers_end: fragment_start,
+            max_len: MAX_DATAGRAM_SIZE - (fragment_start - ip_start - IPV6_HEADER_LEN),
+            offset: ipv6.fragment_offset() as usize * 8,
+            more: ipv6.mf(),
+            payload: start..end,
+        })
+    }
+}
+
+/// Walks the IPv6 extension headers of `packet` up to the Fragment header. Returns the offsets of
+/// the Next Header field that points to it, and of the Fragment header itself.
+fn find_fragment_header(packet: &[u8], ip_start: usize) -> Option<(usize, usize)> {
+    let mut field = ip_start + 6;
+    let mut pos = ip_start + IPV6_HEADER_LEN;
+    loop {
+        let len = match *packet.get(field)? {
+            IPV6_EXT_FRAGMENT => return Some((field, pos)),
+            IPV6_EXT_HOP_BY_HOP | IPV6_EXT_ROUTING | IPV6_EXT_DST_OPTS => {
+                (usize::from(*packet.get(pos + 1)?) + 1) * 8
+            }
+            IPV6_EXT_AUTH => (usize::from(*packet.get(pos + 1)?) + 2) * 4,
+            _ => return None,
+        };
+        field = pos;
+        pos += len;
+    }
+}
+
+/// Headers of the first fragment of a datagram.
+#[derive(Debug)]
+struct Headers {
+    /// Link-layer and IP headers, up to [headers_end](Fragment::headers_end).
+    data: Vec<u8>,
+    /// Offset of the IP header.
+    ip_start: usize,
+    version: Version,
+}
+
+/// The fragments of a datagram received so far, held in packet buffers of type `F`.
+#[derive(Debug)]
+struct Datagram<F> {
+    first_seen: Instant,
+    headers: Option<Headers>,
+    /// Maximum length of the payload.
+    max_len: usize,
+    /// Payload received so far, at its offset in the datagram.
+    payload: Vec<u8>,
+    /// Ranges of the payload that were received.
+    received: Vec<Range<usize>>,
+    /// Length of the payload, once the last fragment is received.
+    len: Option<usize>,
+    /// Packets carrying the fragments, in the order they were received.
+    fragments: Vec<F>,
+}
+
+impl<F> Datagram<F> {
+    fn new(first_seen: Instant, max_len: usize) -> Self {
+        Datagram {
+            first_seen,
+            headers: None,
+            max_len,
+            payload: vec![],
+            received: vec![],
+            len: None,
+            fragments: vec![],
+        }
+    }
+
+    /// Adds the fragment at `offset`. Returns `false` if it is inconsistent with the fragments
+    /// received so far.
+    fn add(&mut self, offset: usize, more: bool, payload: &[u8]) -> bool {
+        let range = offset..offset + payload.len();
+        if range.end > self.max_len
+            || (more && payload.len() % 8 != 0)
+            || self.len.is_some_and(|len| range.end > len)
+            || self
+                .received
+                .iter()
+                .any(|r| r.start < range.end && range.start < r.end)
+        {
+            return false;
+        }
+        if !more {
+            if self.received.iter().any(|r| r.end > range.end) {
+                return false;
+            }
+            self.len = Some(range.end);
+        }
+        if self.payload.len() < range.end {
+            self.payload.resize(range.end, 0);
+        }
+        self.payload[range.clone()].copy_from_slice(payload);
+        self.received.push(range);
+        true
+    }
+
+    fn is_complete(&self) -> bool {
+        let received: usize = self.received.iter().map(|r| r.len()).sum();
+        self.headers.is_some() && self.len == Some(received)
+    }
+
+    /// Returns the reassembled packet: the headers of the first fragment, with the IP header
+    /// updated for the full datagram, followed by the payload.
+    fn assemble(self) -> Vec<u8> {
+        let Headers {
+            data: mut packet,
+            ip_start,
+            version,
+        } = self.headers.expect("incomplete datagram");
+        match version {
+            Version::V4 => {
+                let ip_header_len = packet.len() - ip_start;
+                let total_length = (ip_header_len + self.payload.len()) as u16;
+                let ip_header = &mut packet[ip_start..];
+                ip_header[2..4].copy_from_slice(&total_length.to_be_bytes());
+                ip_header[6..8].copy_from_slice(&[0, 0]);
+                ip_header[10..12].copy_from_slice(&[0, 0]);
+                let checksum = checksum(ip_header);
+                ip_header[10..12].copy_from_slice(&checksum.to_be_bytes());
+            }
+            Version::V6 { next_header } => {
+                // The Fragment header is removed, the header preceding it points past it instead
+                let mut field = ip_start + 6;
+                let mut pos = ip_start + IPV6_HEADER_LEN;
+                while pos < packet.len() {
+                    let len = match packet[field] {
+                        IPV6_EXT_AUTH => (usize::from(packet[pos + 1]) + 2) * 4,
+                        _ => (usize::from(packet[pos + 1]) + 1) * 8,
+                    };
+                    field = pos;
+                    pos += len;
+                }
+                packet[field] = next_header;
+                let payload_length =
+                    (packet.len() - ip_start - IPV6_HEADER_LEN + self.payload.len()) as u16;
+                packet[ip_start + 4..ip_start + 6].copy_from_slice(&payload_length.to_be_bytes());
+            }
+        }
+        packet.extend_from_slice(&self.payload);
+        packet
+    }
+
+    /// Returns the packet that `build` makes of the reassembled datagram and the last fragment
+    /// received, or the fragments if it fails (e.g., the datagram does not fit in a packet
+    /// buffer).
+    fn reassemble<P, E>(
+        mut self,
+        build: impl FnOnce(&F, &[u8]) -> Result<P, E>,
+    ) -> Result<P, Vec<F>> {
+        let fragments = std::mem::take(&mut self.fragments);
+        let packet = self.assemble();
+        match fragments.last().map(|last| build(last, &packet)) {
+            Some(Ok(packet)) => Ok(packet),
+            _ => Err(fragments),
+        }
+    }
+}
+
+/// Internet checksum of `header`.
+fn checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|word| u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Reassembles the fragmented IPv4 and IPv6 datagrams received by a core.
+#[derive(Debug)]
+pub(crate) struct Defrag {
+    datagrams: LinkedHashMap<DatagramId, Datagram<Mbuf>>,
+    max_datagrams: usize,
+    timeout: Duration,
+    nb_reassembled: u64,
+    nb_dropped: u64,
+    nb_passed: u64,
+}
+
+impl Defrag {
+    /// Returns a reassembly table, or `None` if reassembly is disabled.
+    pub(crate) fn new(config: &DefragConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        Some(Defrag {
+            datagrams: LinkedHashMap::new(),
+            max_datagrams: config.max_datagrams,
+            timeout: Duration::from_millis(config.timeout),
+            nb_reassembled: 0,
+            nb_dropped: 0,
+            nb_passed: 0,
+        })
+    }
+
+    /// Returns `mbuf` as released by a core that does not reassemble datagrams.
+    pub(crate) fn pass(mbuf: Mbuf) -> Released {
+        released(Some(mbuf), vec![])
+    }
+
+    /// Returns `mbuf` if it is not a fragment, and nothing if it is a fragment of an incomplete
+    /// datagram. Once `mbuf` completes its datagram, returns the reassembled datagram, or its
+    /// fragments unchanged if it does not fit in a packet buffer.
+    pub(crate) fn push(&mut self, mbuf: Mbuf) -> Released {
+        let fragment = match Fragment::parse(&mbuf) {
+            Some(fragment) => fragment,
+            None => return Self::pass(mbuf),
+        };
+        let now = mbuf.rx_time();
+        self.expire(now);
+        if !self.datagrams.contains_key(&fragment.datagram) {
+            if self.datagrams.len() >= self.max_datagrams {
+                self.datagrams.pop_front();
+                self.nb_dropped += 1;
+            }
+            self.datagrams
+                .insert(fragment.datagram, Datagram::new(now, fragment.max_len));
+        }
+        let datagram = match self.datagrams.get_mut(&fragment.datagram) {
+            Some(datagram) => datagram,
+            None => return released(None, vec![]),
+        };
+        let data = mbuf.data();
+        if !datagram.add(
+            fragment.offset,
+            fragment.more,
+            &data[fragment.payload.clone()],
+        ) {
+            self.datagrams.remove(&fragment.datagram);
+            self.nb_dropped += 1;
+            return released(None, vec![]);
+        }
+        if fragment.offset == 0 {
+            datagram.headers = Some(Headers {
+                data: data[..fragment.headers_end].to_vec(),
+                ip_start: fragment.ip_start,
+                version: fragment.version,
+            });
+        }
+        datagram.fragments.push(mbuf);
+        if !datagram.is_complete() {
+            return released(None, vec![]);
+        }
+        let datagram = match self.datagrams.remove(&fragment.datagram) {
+            Some(datagram) => datagram,
+            None => return released(None, vec![]),
+        };
+        match datagram.reassemble(|last, packet| last.with_data(packet)) {
+            Ok(reassembled) => {
+                self.nb_reassembled += 1;
+                released(Some(reassembled), vec![])
+            }
+            Err(fragments) => {
+                self.nb_passed += 1;
+                released(None, fragments)
+            }
+        }
+    }
+
+    /// Drops the datagrams that were not completed in time.
+    fn expire(&mut self, now: Instant) {
+        while let Some((_, datagram)) = self.datagrams.front() {
+            if now.duration_since(datagram.first_seen) < self.timeout {
+                break;
+            }
+            self.datagrams.pop_front();
+            self.nb_dropped += 1;
+        }
+    }
+
+    /// Returns the number of datagrams reassembled.
+    pub(crate) fn nb_reassembled(&self) -> u64 {
+        self.nb_reassembled
+    }
+
+    /// Returns the number of incomplete datagrams dropped.
+    pub(crate) fn nb_dropped(&self) -> u64 {
+        self.nb_dropped
+    }
+
+    /// Returns the number of complete datagrams whose fragments were passed through, as they did
+    /// not fit in a packet buffer.
+    pub(crate) fn nb_passed(&self) -> u64 {
+        self.nb_passed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_defrag_out_of_order() {
+        // Ethernet header, then an IPv4 header with MF set
+        let mut headers = vec![0; 14];
+        headers[12..14].copy_from_slice(&[0x08, 0x00]);
+        headers.extend_from_slice(&[
+            0x45, 0, 0, 36, 0x12, 0x34, 0x20, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2,
+        ]);
+        let payload: Vec<u8> = (0..20).collect();
+
+        let mut datagram = Datagram::<()>::new(Instant::now(), MAX_DATAGRAM_SIZE - 20);
+        assert!(datagram.add(16, false, &payload[16..]));
+        assert!(!datagram.add(12, true, &payload[12..20]));
+        assert!(datagram.add(0, true, &payload[..8]));
+        assert!(!datagram.is_complete());
+        datagram.headers = Some(Headers {
+            data: headers,
+            ip_start: 14,
+            version: Version::V4,
+        });
+        assert!(datagram.add(8, true, &payload[8..16]));
+        assert!(datagram.is_complete());
+
+        let packet = datagram.assemble();
+        let ip_header = &packet[14..34];
+        assert_eq!(u16::from_be_bytes([ip_header[2], ip_header[3]]), 40);
+        assert_eq!(&ip_header[6..8], &[0, 0]);
+        assert_eq!(checksum(ip_header), 0);
+        assert_eq!(&packet[34..], &payload[..]);
+    }
+
+    #[test]
+    fn core_defrag_ipv6_ext_headers() {
+        // Ethernet header, IPv6 header, Hop-by-Hop Options, then a Fragment header for UDP
+        let mut packet = vec![0; 14];
+        packet[12..14].copy_from_slice(&[0x86, 0xdd]);
+        let mut ipv6 = vec![0; IPV6_HEADER_LEN];
+        ipv6[0] = 0x60;
+        ipv6[4..6].copy_from_slice(&32u16.to_be_bytes());
+        ipv6[6] = IPV6_EXT_HOP_BY_HOP;
+        packet.extend_from_slice(&ipv6);
+        packet.extend_from_slice(&[IPV6_EXT_FRAGMENT, 0, 1, 4, 0, 0, 0, 0]);
+        packet.extend_from_slice(&[17, 0, 0, 1, 0, 0, 0x56, 0x78]);
+        packet.extend_from_slice(&[0; 16]);
+        assert_eq!(find_fragment_header(&packet, 14), Some((54, 62)));
+
+        let mut datagram = Datagram::<()>::new(Instant::now(), MAX_DATAGRAM_SIZE - 8);
+        datagram.headers = Some(Headers {
+            data: packet[..62].to_vec(),
+            ip_start: 14,
+            version: Version::V6 { next_header: 17 },
+        });
+        assert!(datagram.add(0, true, &[1; 16]));
+        assert!(datagram.add(16, false, &[2; 4]));
+        assert!(datagram.is_complete());
+
+        let packet = datagram.assemble();
+        assert_eq!(packet.len(), 14 + IPV6_HEADER_LEN + 8 + 20);
+        assert_eq!(u16::from_be_bytes([packet[18], packet[19]]), 28);
+        assert_eq!(packet[20], IPV6_EXT_HOP_BY_HOP);
+        assert_eq!(packet[54], 17);
+        assert_eq!(&packet[62..78], &[1; 16]);
+    }
+
+    #[test]
+    fn core_defrag_larger_than_buffer() {
+        // Ethernet header, then an IPv4 header with MF set
+        let mut headers = vec![0; 14];
+        headers[12..14].copy_from_slice(&[0x08, 0x00]);
+        headers.extend_from_slice(&[
+            0x45, 0, 0x05, 0xdc, 0x12, 0x34, 0x20, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2,
+        ]);
+        // A 4000-byte datagram in three fragments, larger than the default mbuf data room
+        const DATA_ROOM: usize = 2048;
+        let payload: Vec<u8> = (0..3980).map(|i| i as u8).collect();
+        let new_datagram = || {
+            let mut datagram = Datagram::new(Instant::now(), MAX_DATAGRAM_SIZE - 20);
+            datagram.headers = Some(Headers {
+                data: headers.clone(),
+                ip_start: 14,
+                version: Version::V4,
+            });
+            for (idx, range) in [0..1480, 1480..2960, 2960..3980].into_iter().enumerate() {
+                assert!(datagram.add(range.start, range.end < 3980, &payload[range]));
+                datagram.fragments.push(idx);
+            }
+            assert!(datagram.is_complete());
+            datagram
+        };
+        let build = |room: usize| {
+            move |last: &usize, packet: &[u8]| {
+                if packet.len() <= room {
+                    Ok((*last, packet.to_vec()))
+                } else {
+                    Err(())
+                }
+            }
+        };
+
+        // Passed through, in order, if it does not fit
+        assert_eq!(
+            new_datagram().reassemble(build(DATA_ROOM)),
+            Err(vec![0, 1, 2])
+        );
+        let (last, packet) = new_datagram().reassemble(build(8192)).unwrap();
+        assert_eq!(last, 2);
+        assert_eq!(packet.len(), 14 + 20 + 3980);
+        assert_eq!(u16::from_be_bytes([packet[16], packet[17]]), 4000);
+        assert_eq!(&packet[34..], &payload[..]);
+    }
+}
@@ -0,0 +1,198 @@
+//! Fast path for the headers of common packets.
+//!
+//! Most packets are untagged Ethernet frames carrying an IPv4 packet without options and a TCP or
+//! UDP segment. For these packets, [l4_context](l4_context) checks the fixed header bytes at once
+//! (with SSE2 on x86_64) and reads the context of the segment at fixed offsets, instead of parsing
+//! each header in turn. Other packets (e.g., with VLAN tags, IPv6, IPv4 options, or ICMP) are left
+//! to the scalar parser, which returns the same context for the packets covered by the fast path.
+//!
+//! The packets of an RX burst are processed in a software pipeline: while a packet is processed,
+//! the headers of the packet `PREFETCH_DISTANCE` positions ahead are prefetched (see
+//! [prefetch_ahead](prefetch_ahead)), and the connection of the next packet, whose headers are
+//! loaded by then, is looked up and prefetched (see
+//! [ConnTracker::prefetch](crate::conntrack::ConnTracker::prefetch)). The memory latency of both
+//! is hidden behind the processing of the current packet.
+
+use crate::conntrack::pdu::L4Context;
+use crate::conntrack::tunnel::Tunnels;
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::tcp::TCP_PROTOCOL;
+use crate::protocols::packet::udp::UDP_PROTOCOL;
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const TCP_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+
+/// Offset of the checked bytes in the frame, from the EtherType to the IPv4 protocol.
+const CHECK_OFFSET: usize = 12;
+/// Checked bytes: EtherType, IPv4 version and header length, and IPv4 protocol.
+const CHECK_MASK: [u8; 16] = [0xff, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0, 0, 0, 0];
+/// Expected bytes for IPv4 (`0x0800`) without options (`0x45`), carrying TCP.
+const EXPECTED_TCP: [u8; 16] = [0x08, 0x00, 0x45, 0, 0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 0];
+/// Expected bytes for IPv4 without options, carrying UDP.
+const EXPECTED_UDP: [u8; 16] = [0x08, 0x00, 0x45, 0, 0, 0, 0, 0, 0, 0, 0, 17, 0, 0, 0, 0];
+
+/// Number of packets ahead of the packet being processed whose headers are prefetched.
+pub(crate) const PREFETCH_DISTANCE: usize = 4;
+/// Size of a cache line.
+const CACHE_LINE: usize = 64;
+/// Maximum number of cache lines prefetched for a connection.
+const CONN_LINES: usize = 4;
+
+/// Prefetches the cache line at `ptr`.
+#[inline]
+fn prefetch_line(ptr: *const u8) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        _mm_prefetch(ptr as *const i8, _MM_HINT_T0)
+    };
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = ptr;
+}
+
+/// Prefetches the headers of the first `PREFETCH_DISTANCE` packets of an RX burst, before the
+/// first packet is processed.
+#[inline]
+pub(crate) fn prefetch_burst(mbufs: &[Mbuf]) {
+    for mbuf in mbufs.iter().take(PREFETCH_DISTANCE) {
+        prefetch_line(mbuf.data().as_ptr());
+    }
+}
+
+/// Prefetches the headers of the packet `PREFETCH_DISTANCE` positions ahead, given the packets
+/// that remain to be processed after the current one.
+#[inline]
+pub(crate) fn prefetch_ahead(remaining: &[Mbuf]) {
+    if let Some(mbuf) = remaining.get(PREFETCH_DISTANCE - 1) {
+        prefetch_line(mbuf.data().as_ptr());
+    }
+}
+
+/// Prefetches the first cache lines of `object` (e.g., a connection's state).
+#[inline]
+pub(crate) fn prefetch_object<T>(object: &T) {
+    let ptr = object as *const T as *const u8;
+    let size = std::mem::size_of::<T>().min(CONN_LINES * CACHE_LINE);
+    for offset in (0..size).step_by(CACHE_LINE) {
+        prefetch_line(unsafe { ptr.add(offset) });
+    }
+}
+
+/// Returns the transport protocol of a frame covered by the fast path, from its bytes at
+/// `CHECK_OFFSET`.
+#[inline]
+fn classify(header: &[u8; 16]) -> Option<usize> {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        use std::arch::x86_64::*;
+        let load = |bytes: &[u8; 16]| _mm_loadu_si128(bytes.as_ptr() as *const __m128i);
+        let masked = _mm_and_si128(load(header), load(&CHECK_MASK));
+        for (proto, expected) in [(TCP_PROTOCOL, &EXPECTED_TCP), (UDP_PROTOCOL, &EXPECTED_UDP)] {
+            if _mm_movemask_epi8(_mm_cmpeq_epi8(masked, load(expected))) == 0xffff {
+                return Some(proto);
+            }
+        }
+        None
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        let matches = |expected: &[u8; 16]| {
+            header
+                .iter()
+                .zip(CHECK_MASK.iter().zip(expected))
+                .all(|(byte, (mask, expected))| byte & mask == *expected)
+        };
+        match (matches(&EXPECTED_TCP), matches(&EXPECTED_UDP)) {
+            (true, _) => Some(TCP_PROTOCOL),
+            (_, true) => Some(UDP_PROTOCOL),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the context of the frame `data` if it is covered by the fast path and well-formed, and
+/// `None` if it must be parsed by the scalar parser.
+#[inline]
+pub(crate) fn l4_context(data: &[u8]) -> Option<L4Context> {
+    let header: &[u8; 16] = data.get(CHECK_OFFSET..CHECK_OFFSET + 16)?.try_into().ok()?;
+    let proto = classify(header)?;
+    let l4_offset = ETHERNET_HEADER_LEN + IPV4_HEADER_LEN;
+    let ip = data.get(ETHERNET_HEADER_LEN..l4_offset)?;
+    let total_length = u16::from_be_bytes([ip[2], ip[3]]) as usize;
+    let src_addr = IpAddr::V4(Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15]));
+    let dst_addr = IpAddr::V4(Ipv4Addr::new(ip[16], ip[17], ip[18], ip[19]));
+    let be16 = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]);
+    let be32 = |bytes: &[u8]| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+    if proto == TCP_PROTOCOL {
+        let tcp = data.get(l4_offset..l4_offset + TCP_HEADER_LEN)?;
+        let header_len = ((tcp[12] & 0xf0) >> 2) as usize;
+        Some(L4Context {
+            src: SocketAddr::new(src_addr, be16(&tcp[0..])),
+            dst: SocketAddr::new(dst_addr, be16(&tcp[2..])),
+            proto: TCP_PROTOCOL,
+            offset: l4_offset + header_len,
+            length: total_length.checked_sub(IPV4_HEADER_LEN + header_len)?,
+            seq_no: be32(&tcp[4..]),
+            flags: tcp[13],
+            ack_no: be32(&tcp[8..]),
+            tunnels: Tunnels::default(),
+            icmp_error: None,
+        })
+    } else {
+        let udp = data.get(l4_offset..l4_offset + UDP_HEADER_LEN)?;
+        Some(L4Context {
+            src: SocketAddr::new(src_addr, be16(&udp[0..])),
+            dst: SocketAddr::new(dst_addr, be16(&udp[2..])),
+            proto: UDP_PROTOCOL,
+            offset: l4_offset + UDP_HEADER_LEN,
+            length: total_length.checked_sub(IPV4_HEADER_LEN + UDP_HEADER_LEN)?,
+            seq_no: 0,
+            flags: 0,
+            ack_no: 0,
+            tunnels: Tunnels::default(),
+            icmp_error: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_fastpath_l4_context() {
+        let mut frame = vec![0u8; 64];
+        frame[12..14].copy_from_slice(&[0x08, 0x00]);
+        // IPv4: 20-byte header, total length 50, TCP, 10.0.0.1 -> 10.0.0.2
+        frame[14] = 0x45;
+        frame[16..18].copy_from_slice(&50u16.to_be_bytes());
+        frame[23] = 6;
+        frame[26..34].copy_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        // TCP: 443 -> 80, seq 7, ack 9, 20-byte header, SYN
+        frame[34..38].copy_from_slice(&[0x01, 0xbb, 0x00, 0x50]);
+        frame[38..42].copy_from_slice(&7u32.to_be_bytes());
+        frame[42..46].copy_from_slice(&9u32.to_be_bytes());
+        frame[46] = 0x50;
+        frame[47] = 0x02;
+
+        let ctxt = l4_context(&frame).unwrap();
+        assert_eq!(ctxt.src, "10.0.0.1:443".parse().unwrap());
+        assert_eq!(ctxt.dst, "10.0.0.2:80".parse().unwrap());
+        assert_eq!(ctxt.proto, TCP_PROTOCOL);
+        assert_eq!((ctxt.offset, ctxt.length), (54, 10));
+        assert_eq!((ctxt.seq_no, ctxt.ack_no, ctxt.flags), (7, 9, 0x02));
+
+        // IPv4 options are left to the scalar parser
+        frame[14] = 0x46;
+        assert!(l4_context(&frame).is_none());
+        // As are VLAN tags
+        frame[14] = 0x45;
+        frame[12..14].copy_from_slice(&[0x81, 0x00]);
+        assert!(l4_context(&frame).is_none());
+    }
+}
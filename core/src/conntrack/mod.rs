@@ -6,26 +6,49 @@
 
 pub mod conn;
 pub mod conn_id;
+pub(crate) mod defrag;
+pub(crate) mod fastpath;
+pub mod mptcp;
 pub mod pdu;
+mod sampling;
+pub mod table;
 mod timerwheel;
+pub mod tunnel;
 
-use self::conn::{Conn, L4Conn};
+use self::conn::tcp_conn::reassembly::{self, ReassemblyConfig};
+use self::conn::{Conn, L4Conn, TerminationReason};
 use self::conn_id::ConnId;
-use self::pdu::{L4Context, L4Pdu};
+use self::pdu::{IcmpError, L4Context, L4Pdu};
+use self::sampling::Sampler;
+use self::table::{ConnTable, TableEntry};
 use self::timerwheel::TimerWheel;
-use crate::config::ConnTrackConfig;
+use crate::config::{
+    ConnTrackConfig, EvictionPolicy, OutOfOrderOverflow, OverlapPolicy, OverloadConfig,
+    PcapWriterConfig, SamplingConfig, ShedPolicy, TransportProtocol,
+};
+use crate::filter::ActionData;
+use crate::lcore::mirror::{self, Direction};
+use crate::lcore::verdict;
 use crate::lcore::CoreId;
 use crate::memory::mbuf::Mbuf;
-use crate::protocols::packet::tcp::TCP_PROTOCOL;
+use crate::protocols::packet::icmp::ICMP_PROTOCOL;
+use crate::protocols::packet::icmpv6::ICMPV6_PROTOCOL;
+use crate::protocols::packet::sctp::SCTP_PROTOCOL;
+use crate::protocols::packet::tcp::{ACK, FIN, RST, SYN, TCP_PROTOCOL};
 use crate::protocols::packet::udp::UDP_PROTOCOL;
 use crate::protocols::stream::ParserRegistry;
 use crate::subscription::{Subscription, Trackable};
+use crate::utils::pcap::PcapWriter;
 
 use std::cmp;
-use std::time::Instant;
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher};
 
 use anyhow::anyhow;
-use hashlink::linked_hash_map::{LinkedHashMap, RawEntryMut};
+
+/// Number of oldest connections among which the `random` eviction policy picks.
+const EVICTION_SAMPLE: usize = 1024;
 
 /// Manages state for all TCP and UDP connections.
 ///
@@ -42,11 +65,31 @@ where
     /// Contains required protocol parsers for `T`.
     registry: ParserRegistry,
     /// Manages `ConnId` to `Conn<T>` mappings.
-    table: LinkedHashMap<ConnId, Conn<T>>,
+    table: ConnTable<Conn<T>>,
     /// Manages connection timeouts.
     timerwheel: TimerWheel,
     /// ID of the core that the table is assigned to.
     core_id: CoreId,
+    /// Number of connections created since the table was initialized.
+    nb_created: u64,
+    /// Writes packets of connections that match a subscription requesting packet capture.
+    pcap: PcapWriter,
+    /// Load shedding, while the core is overloaded.
+    shedding: Option<Shedding>,
+    /// Number of connections not tracked or degraded by load shedding.
+    nb_shed: u64,
+    /// Number of connections evicted to make room for new ones.
+    nb_evicted: u64,
+    /// State of the random number generator of the `random` eviction policy.
+    rng: u64,
+    /// Flow sampling, if enabled.
+    sampler: Option<Sampler>,
+    /// Number of packets of connections not sampled.
+    nb_unsampled_pkts: u64,
+    /// Number of bytes of connections not sampled.
+    unsampled_bytes: u64,
+    /// Number of packets of bypassed connections.
+    nb_bypassed_pkts: u64,
 }
 
 impl<T> ConnTracker<T>
@@ -54,19 +97,55 @@ where
     T: Trackable,
 {
     /// Creates a new `ConnTracker`.
-    pub(crate) fn new(config: TrackerConfig, registry: ParserRegistry, core_id: CoreId) -> Self {
-        let table = LinkedHashMap::with_capacity(config.max_connections);
-        let timerwheel = TimerWheel::new(
-            cmp::max(config.tcp_inactivity_timeout, config.udp_inactivity_timeout),
-            config.timeout_resolution,
-        );
+    pub(crate) fn new(
+        config: TrackerConfig,
+        registry: ParserRegistry,
+        core_id: CoreId,
+        pcap: PcapWriter,
+    ) -> Self {
+        let table = ConnTable::with_capacity(config.max_connections);
+        let timerwheel = TimerWheel::new(config.max_timeout(), config.timeout_resolution);
+        let sampler = config.sampling.as_ref().map(Sampler::new);
         ConnTracker {
             config,
             registry,
             table,
             timerwheel,
             core_id,
+            nb_created: 0,
+            pcap,
+            shedding: None,
+            nb_shed: 0,
+            nb_evicted: 0,
+            rng: {
+                let mut hasher = RandomState::new().build_hasher();
+                core_id.raw().hash(&mut hasher);
+                hasher.finish() | 1
+            },
+            sampler,
+            nb_unsampled_pkts: 0,
+            unsampled_bytes: 0,
+            nb_bypassed_pkts: 0,
+        }
+    }
+
+    /// Applies new timeouts and limits, and a new packet capture configuration. The timeout
+    /// resolution, the unidirectional mode and bypassing cannot be changed. A higher connection
+    /// limit lets the table grow further; a lower one only stops new connections from being
+    /// tracked until enough entries expire.
+    pub(crate) fn reconfigure(&mut self, config: TrackerConfig, pcap: &PcapWriterConfig) {
+        if config.max_connections > self.table.capacity() {
+            self.table
+                .reserve(config.max_connections - self.table.len());
         }
+        self.sampler = config.sampling.as_ref().map(Sampler::new);
+        self.config = TrackerConfig {
+            timeout_resolution: self.config.timeout_resolution,
+            unidirectional: self.config.unidirectional,
+            bypass: self.config.bypass,
+            ..config
+        };
+        self.pcap.reconfigure(pcap);
     }
 
     /// Returns the number of entries in the table.
@@ -75,6 +154,75 @@ where
         self.table.len()
     }
 
+    /// Returns the number of connections created since the table was initialized.
+    #[inline]
+    pub(crate) fn nb_created(&self) -> u64 {
+        self.nb_created
+    }
+
+    /// Returns the number of TCP out-of-order buffer overflows on this core.
+    #[inline]
+    pub(crate) fn nb_ooo_overflows(&self) -> u64 {
+        reassembly::nb_overflows()
+    }
+
+    /// Returns the number of payload bytes held in the TCP out-of-order buffers of this core.
+    #[inline]
+    pub(crate) fn ooo_bytes(&self) -> usize {
+        reassembly::held_bytes()
+    }
+
+    /// Returns the number of connections evicted to make room for new ones.
+    #[inline]
+    pub(crate) fn nb_evicted(&self) -> u64 {
+        self.nb_evicted
+    }
+
+    /// Starts (or stops, if `None`) shedding load.
+    pub(crate) fn set_shedding(&mut self, shedding: Option<Shedding>) {
+        self.shedding = shedding;
+    }
+
+    /// Returns the number of connections not tracked or degraded by load shedding.
+    #[inline]
+    pub(crate) fn nb_shed(&self) -> u64 {
+        self.nb_shed
+    }
+
+    /// Returns the number of packets of connections not sampled.
+    #[inline]
+    pub(crate) fn nb_unsampled_pkts(&self) -> u64 {
+        self.nb_unsampled_pkts
+    }
+
+    /// Returns the number of bytes of connections not sampled.
+    #[inline]
+    pub(crate) fn unsampled_bytes(&self) -> u64 {
+        self.unsampled_bytes
+    }
+
+    /// Returns the number of packets of connections that no subscription needed anymore, which
+    /// were discarded without processing.
+    #[inline]
+    pub(crate) fn nb_bypassed_pkts(&self) -> u64 {
+        self.nb_bypassed_pkts
+    }
+
+    /// Prefetches the state of the connection of `mbuf`, if it is tracked, so that it is loaded
+    /// by the time the packet is processed. Only packets covered by the fast path are looked up.
+    #[inline]
+    pub(crate) fn prefetch(&self, mbuf: &Mbuf) {
+        let ctxt = match fastpath::l4_context(mbuf.data()) {
+            Some(ctxt) => ctxt,
+            None => return,
+        };
+        let conn_id = self.config.conn_id(&ctxt);
+        let hash = self.table.hasher().hash(&conn_id);
+        if let Some(conn) = self.table.get_hashed(hash, &conn_id) {
+            fastpath::prefetch_object(conn);
+        }
+    }
+
     /// Process a single incoming packet `mbuf` with layer-4 context `ctxt`.
     pub(crate) fn process(
         &mut self,
@@ -82,24 +230,63 @@ where
         ctxt: L4Context,
         subscription: &Subscription<T::Subscribed>,
     ) {
-        let conn_id = ConnId::new(ctxt.src, ctxt.dst, ctxt.proto);
-        match self.table.raw_entry_mut().from_key(&conn_id) {
-            RawEntryMut::Occupied(mut occupied) => {
+        if let Some(error) = ctxt.icmp_error {
+            self.process_icmp_error(mbuf, ctxt, error, subscription);
+            return;
+        }
+        let conn_id = self.config.conn_id(&ctxt);
+        // Discard requests returned by callbacks outside of this connection (e.g., at termination)
+        verdict::take_stop();
+        verdict::take_escalated();
+        let hash = self.table.hasher().hash(&conn_id);
+        match self.table.entry(hash, &conn_id) {
+            TableEntry::Occupied(mut occupied) => {
+                if self.config.eviction == EvictionPolicy::Lru {
+                    occupied.to_back();
+                }
                 let conn = occupied.get_mut();
-                conn.last_seen_ts = Instant::now();
+                if conn.closed {
+                    // Lingering after close: only a new SYN reopens the connection (or a SYN/ACK,
+                    // if directions are tracked separately)
+                    if ctxt.flags & SYN != 0
+                        && (ctxt.flags & ACK == 0 || self.config.unidirectional)
+                    {
+                        occupied.remove();
+                        self.process(mbuf, ctxt, subscription);
+                    } else {
+                        drop(mbuf);
+                    }
+                    return;
+                }
+                conn.last_seen_ts = mbuf.rx_time();
                 let dir = conn.packet_dir(&ctxt);
-                conn.inactivity_window = match &conn.l4conn {
-                    L4Conn::Tcp(_) => self.config.tcp_inactivity_timeout,
-                    L4Conn::Udp(_) => self.config.udp_inactivity_timeout,
-                };
-                if conn.remove_from_table() {
+                conn.inactivity_window = self.config.inactivity_timeout(conn);
+                if conn.remove_from_table(self.config.bypass) {
                     log::error!("Conn in Drop state when occupied in table");
                 }
                 if conn.drop_pdu() {
+                    // Bypassed: no subscription needs more data from the connection
+                    self.nb_bypassed_pkts += 1;
+                    if ctxt.flags & (FIN | RST) != 0 && !matches!(conn.l4conn, L4Conn::Udp(_)) {
+                        occupied.remove();
+                    }
                     drop(mbuf);
                     return;
                 }
+                if matches!(self.shedding, Some(Shedding::NoPacketTracking))
+                    && shed_packet_tracking(&mut conn.info)
+                {
+                    self.nb_shed += 1;
+                }
                 let pdu = L4Pdu::new(mbuf, ctxt, dir);
+                conn.info.cdata.update_volume(pdu.mbuf_ref().pkt_len());
+                if subscription.volume_limits.exceeded(&conn.info.cdata) {
+                    // Too large to match any subscription
+                    conn.info.actions.clear();
+                }
+                if conn.info.actions.packet_write() {
+                    conn.info.write_packet(pdu.mbuf_ref(), &mut self.pcap);
+                }
                 if conn.info.actions.update_pdu() {
                     conn.info.sdata.update(&pdu, false);
                 }
@@ -108,46 +295,128 @@ where
                 } else {
                     conn.update_tcp_flags(pdu.flags(), pdu.dir);
                 }
+                conn.info.flush_pcap(&mut self.pcap);
+                conn.info.apply_callback_requests();
+                // The packet may have half-closed the connection or carried a RST
+                let inactivity_window = self.config.inactivity_timeout(conn);
+                if inactivity_window < conn.inactivity_window {
+                    conn.inactivity_window = inactivity_window;
+                    self.timerwheel
+                        .insert(&conn_id, conn.last_seen_ts, inactivity_window);
+                }
+                verdict::record(conn.info.actions.verdict());
+                if conn.info.actions.packet_mirror() {
+                    mirror::record(if dir {
+                        Direction::Originator
+                    } else {
+                        Direction::Responder
+                    });
+                }
 
                 // Delete stale data for connections no longer matching
-                if conn.remove_from_table() {
+                if conn.remove_from_table(self.config.bypass) {
                     occupied.remove();
                 } else if conn.drop_pdu() {
                     conn.info.clear();
-                } else if conn.terminated() {
-                    conn.terminate(subscription);
-                    occupied.remove();
+                } else if let Some(reason) = self.config.closes(conn) {
+                    conn.terminate(subscription, &mut self.pcap, reason);
+                    if self.config.tcp_time_wait > 0 {
+                        conn.inactivity_window = self.config.tcp_time_wait;
+                        self.timerwheel
+                            .insert(&conn_id, conn.last_seen_ts, conn.inactivity_window);
+                    } else {
+                        occupied.remove();
+                    }
                 }
             }
-            RawEntryMut::Vacant(_) => {
-                if self.size() < self.config.max_connections {
+            TableEntry::Vacant(_) => {
+                // `DefaultHasher::new` uses fixed keys, so all cores and instances agree on samples
+                let sample_hash = || {
+                    let mut hasher = DefaultHasher::new();
+                    conn_id.hash(&mut hasher);
+                    hasher.finish()
+                };
+                if let Some(sampler) = &self.sampler {
+                    if !sampler.is_sampled(sample_hash()) {
+                        self.nb_unsampled_pkts += 1;
+                        self.unsampled_bytes += mbuf.pkt_len() as u64;
+                        drop(mbuf);
+                        return;
+                    }
+                }
+                let shed = match self.shedding {
+                    Some(Shedding::NoNewConnections) => true,
+                    Some(Shedding::Sample(threshold)) => sample_hash() > threshold,
+                    _ => false,
+                };
+                if shed {
+                    self.nb_shed += 1;
+                    drop(mbuf);
+                } else if self.size() < self.config.max_connections || self.evict(subscription) {
                     let pdu = L4Pdu::new(mbuf, ctxt, true);
                     let conn = match ctxt.proto {
                         TCP_PROTOCOL => Conn::<T>::new_tcp(
                             self.config.tcp_establish_timeout,
-                            self.config.max_out_of_order,
+                            self.config.reassembly(),
+                            self.config.midstream,
+                            self.config.unidirectional,
+                            &pdu,
+                            self.core_id,
+                        ),
+                        // ICMP Echo exchanges are tracked like UDP connections
+                        UDP_PROTOCOL | ICMP_PROTOCOL | ICMPV6_PROTOCOL => Conn::<T>::new_udp(
+                            self.config
+                                .port_timeouts
+                                .get(&(ctxt.proto, ctxt.dst.port()))
+                                .copied()
+                                .unwrap_or(self.config.udp_inactivity_timeout),
                             &pdu,
                             self.core_id,
                         ),
-                        UDP_PROTOCOL => Conn::<T>::new_udp(
-                            self.config.udp_inactivity_timeout,
+                        SCTP_PROTOCOL => Conn::<T>::new_sctp(
+                            self.config
+                                .port_timeouts
+                                .get(&(ctxt.proto, ctxt.dst.port()))
+                                .copied()
+                                .unwrap_or(self.config.sctp_inactivity_timeout),
                             &pdu,
                             self.core_id,
                         ),
                         _ => Err(anyhow!("Invalid L4 Protocol")),
                     };
                     if let Ok(mut conn) = conn {
+                        self.nb_created += 1;
+                        conn.info.cdata.update_volume(pdu.mbuf_ref().pkt_len());
                         conn.info.filter_first_packet(&pdu, subscription);
+                        if subscription.volume_limits.exceeded(&conn.info.cdata) {
+                            conn.info.actions.clear();
+                        }
+                        if matches!(self.shedding, Some(Shedding::NoPacketTracking))
+                            && shed_packet_tracking(&mut conn.info)
+                        {
+                            self.nb_shed += 1;
+                        }
+                        if conn.info.actions.packet_write() {
+                            conn.info.write_packet(pdu.mbuf_ref(), &mut self.pcap);
+                        }
                         if !conn.info.actions.drop() {
                             conn.info.consume_pdu(pdu, subscription, &self.registry);
                         }
-                        if !conn.remove_from_table() {
+                        conn.info.flush_pcap(&mut self.pcap);
+                        conn.info.apply_callback_requests();
+                        verdict::record(conn.info.actions.verdict());
+                        if conn.info.actions.packet_mirror() {
+                            mirror::record(Direction::Originator);
+                        }
+                        if !conn.remove_from_table(false) {
                             self.timerwheel.insert(
                                 &conn_id,
                                 conn.last_seen_ts,
                                 conn.inactivity_window,
                             );
-                            self.table.insert(conn_id, conn);
+                            if let TableEntry::Vacant(vacant) = self.table.entry(hash, &conn_id) {
+                                vacant.insert(conn_id, conn);
+                            }
                         }
                     }
                 } else {
@@ -157,18 +426,122 @@ where
         }
     }
 
+    /// Attributes an ICMP `error` message about a packet of the connection in `ctxt` to that
+    /// connection. UDP connections (and ICMP Echo exchanges) are terminated if the destination is
+    /// unreachable, while TCP connections and SCTP associations only record the error, as they may
+    /// recover from it. Messages about connections that are not tracked are dropped.
+    fn process_icmp_error(
+        &mut self,
+        mbuf: Mbuf,
+        ctxt: L4Context,
+        error: IcmpError,
+        subscription: &Subscription<T::Subscribed>,
+    ) {
+        drop(mbuf);
+        let conn_id = self.config.conn_id(&ctxt);
+        let hash = self.table.hasher().hash(&conn_id);
+        if let TableEntry::Occupied(mut occupied) = self.table.entry(hash, &conn_id) {
+            let conn = occupied.get_mut();
+            if conn.closed || conn.drop_pdu() {
+                return;
+            }
+            let dir = conn.packet_dir(&ctxt);
+            conn.info.sdata.icmp_error(&error, dir);
+            if error.unreachable() && matches!(conn.l4conn, L4Conn::Udp(_)) {
+                conn.terminate(subscription, &mut self.pcap, TerminationReason::Unreachable);
+                if self.config.tcp_time_wait > 0 {
+                    conn.inactivity_window = self.config.tcp_time_wait;
+                    self.timerwheel
+                        .insert(&conn_id, conn.last_seen_ts, conn.inactivity_window);
+                } else {
+                    occupied.remove();
+                }
+            }
+        }
+    }
+
+    /// Removes a connection to make room for a new one, according to the eviction policy. Returns
+    /// whether a connection was evicted.
+    fn evict(&mut self, subscription: &Subscription<T::Subscribed>) -> bool {
+        let conn_id = match self.config.eviction {
+            EvictionPolicy::Reject => return false,
+            EvictionPolicy::Lru => self.table.front().map(|(conn_id, _)| conn_id.clone()),
+            EvictionPolicy::EarliestExpiry => self
+                .timerwheel
+                .earliest(&self.table)
+                .or_else(|| self.table.front().map(|(conn_id, _)| conn_id.clone())),
+            EvictionPolicy::Random => {
+                // xorshift64
+                self.rng ^= self.rng << 13;
+                self.rng ^= self.rng >> 7;
+                self.rng ^= self.rng << 17;
+                let sample = cmp::min(self.table.len(), EVICTION_SAMPLE);
+                (sample > 0)
+                    .then(|| self.table.keys().nth(self.rng as usize % sample).cloned())
+                    .flatten()
+            }
+        };
+        match conn_id.and_then(|conn_id| self.table.remove(&conn_id)) {
+            Some(mut conn) => {
+                conn.terminate(subscription, &mut self.pcap, TerminationReason::Eviction);
+                self.nb_evicted += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Drains any remaining connections that satisfy the filter on runtime termination.
     pub(crate) fn drain(&mut self, subscription: &Subscription<T::Subscribed>) {
         log::info!("Draining Connection table");
         for (_, mut conn) in self.table.drain() {
-            conn.terminate(subscription);
+            conn.terminate(subscription, &mut self.pcap, TerminationReason::Drain);
         }
+        self.pcap.flush();
     }
 
     /// Checks for and removes inactive connections.
     pub(crate) fn check_inactive(&mut self, subscription: &Subscription<T::Subscribed>) {
+        if let Some(sampler) = self.sampler.as_mut() {
+            sampler.adjust(self.nb_created);
+        }
         self.timerwheel
-            .check_inactive(&mut self.table, subscription);
+            .check_inactive(&mut self.table, subscription, &mut self.pcap);
+    }
+}
+
+/// Stops buffering packets in a connection and releases the packets it has buffered. Returns
+/// whether the connection was buffering packets.
+fn shed_packet_tracking<T: Trackable>(info: &mut conn::conn_info::ConnInfo<T>) -> bool {
+    if !info.actions.buffer_frame() {
+        return false;
+    }
+    info.actions.clear_mask(ActionData::PacketTrack);
+    info.clear_packets();
+    true
+}
+
+/// Load shedding applied by a `ConnTracker` while its core is overloaded (see
+/// [OverloadConfig](crate::config::OverloadConfig)).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Shedding {
+    /// New connections are not tracked.
+    NoNewConnections,
+    /// Connections stop buffering packets.
+    NoPacketTracking,
+    /// New connections are only tracked if the hash of their ID is at most the threshold.
+    Sample(u64),
+}
+
+impl From<&OverloadConfig> for Shedding {
+    fn from(config: &OverloadConfig) -> Self {
+        match config.policy {
+            ShedPolicy::NoNewConnections => Shedding::NoNewConnections,
+            ShedPolicy::NoPacketTracking => Shedding::NoPacketTracking,
+            ShedPolicy::Sample => {
+                Shedding::Sample((config.sample_rate.clamp(0.0, 1.0) * u64::MAX as f64) as u64)
+            }
+        }
     }
 }
 
@@ -177,8 +550,16 @@ where
 pub(crate) struct TrackerConfig {
     /// Maximum number of connections that can be tracked per-core.
     pub(super) max_connections: usize,
-    /// Maximum number of out-of-order packets allowed per TCP connection.
+    /// Maximum number of out-of-order packets buffered per TCP flow.
     pub(super) max_out_of_order: usize,
+    /// Maximum number of out-of-order payload bytes buffered per TCP flow.
+    pub(super) max_out_of_order_bytes: usize,
+    /// Maximum number of out-of-order payload bytes buffered across all TCP flows.
+    pub(super) max_out_of_order_total_bytes: usize,
+    /// Handling of out-of-order buffer overflows.
+    pub(super) out_of_order_overflow: OutOfOrderOverflow,
+    /// Data kept when TCP segments overlap.
+    pub(super) tcp_overlap_policy: OverlapPolicy,
     /// Time to expire inactive UDP connections (in milliseconds).
     pub(super) udp_inactivity_timeout: usize,
     /// Time to expire inactive TCP connections (in milliseconds).
@@ -187,6 +568,29 @@ pub(crate) struct TrackerConfig {
     pub(super) tcp_establish_timeout: usize,
     /// Frequency to check for inactive streams (in milliseconds).
     pub(super) timeout_resolution: usize,
+    /// Whether to track TCP connections picked up mid-stream.
+    pub(super) midstream: bool,
+    /// Inactivity timeouts keyed by transport protocol and destination port.
+    pub(super) port_timeouts: HashMap<(usize, u16), usize>,
+    /// Connection evicted when the table is full.
+    pub(super) eviction: EvictionPolicy,
+    /// Time to close TCP connections after a RST (in milliseconds).
+    pub(super) tcp_rst_timeout: usize,
+    /// Time to expire inactive half-closed TCP connections (in milliseconds).
+    pub(super) tcp_half_closed_timeout: Option<usize>,
+    /// Time to keep closed TCP connections in the table (in milliseconds).
+    pub(super) tcp_time_wait: usize,
+    /// Whether to skip missing TCP data acknowledged by the receiver.
+    pub(super) skip_acked_gaps: bool,
+    /// Time to expire inactive SCTP associations (in milliseconds).
+    pub(super) sctp_inactivity_timeout: usize,
+    /// Whether each direction of a connection is tracked as a separate flow.
+    pub(super) unidirectional: bool,
+    /// Flow sampling options.
+    pub(super) sampling: Option<SamplingConfig>,
+    /// Whether TCP connections and SCTP associations are kept in the table once no subscription
+    /// needs their data.
+    pub(super) bypass: bool,
 }
 
 impl From<&ConnTrackConfig> for TrackerConfig {
@@ -194,10 +598,115 @@ impl From<&ConnTrackConfig> for TrackerConfig {
         TrackerConfig {
             max_connections: config.max_connections,
             max_out_of_order: config.max_out_of_order,
+            max_out_of_order_bytes: config.max_out_of_order_bytes,
+            max_out_of_order_total_bytes: config.max_out_of_order_total_bytes,
+            out_of_order_overflow: config.out_of_order_overflow,
+            tcp_overlap_policy: config.tcp_overlap_policy,
             udp_inactivity_timeout: config.udp_inactivity_timeout,
             tcp_inactivity_timeout: config.tcp_inactivity_timeout,
             tcp_establish_timeout: config.tcp_establish_timeout,
             timeout_resolution: config.timeout_resolution,
+            midstream: config.midstream,
+            port_timeouts: config
+                .port_timeouts
+                .iter()
+                .map(|t| {
+                    let proto = match t.protocol {
+                        TransportProtocol::Tcp => TCP_PROTOCOL,
+                        TransportProtocol::Udp => UDP_PROTOCOL,
+                        TransportProtocol::Sctp => SCTP_PROTOCOL,
+                    };
+                    ((proto, t.port), t.inactivity_timeout)
+                })
+                .collect(),
+            eviction: config.eviction,
+            tcp_rst_timeout: config.tcp_rst_timeout,
+            tcp_half_closed_timeout: config.tcp_half_closed_timeout,
+            tcp_time_wait: config.tcp_time_wait,
+            skip_acked_gaps: config.skip_acked_gaps,
+            sctp_inactivity_timeout: config.sctp_inactivity_timeout,
+            unidirectional: config.unidirectional,
+            sampling: config.sampling.clone(),
+            bypass: config.bypass,
+        }
+    }
+}
+
+impl TrackerConfig {
+    /// Returns the ID of the connection (or of the flow, in unidirectional mode) of `ctxt`.
+    #[inline]
+    fn conn_id(&self, ctxt: &L4Context) -> ConnId {
+        if self.unidirectional {
+            ConnId::new_unidirectional(ctxt.src, ctxt.dst, ctxt.proto)
+        } else {
+            ConnId::new(ctxt.src, ctxt.dst, ctxt.proto)
+        }
+    }
+
+    /// Returns the inactivity timeout of an existing connection.
+    fn inactivity_timeout<T: Trackable>(&self, conn: &Conn<T>) -> usize {
+        let timeout = match &conn.l4conn {
+            L4Conn::Tcp(tcp_conn) => {
+                if self.tcp_rst_timeout > 0
+                    && tcp_conn.termination() == Some(TerminationReason::Rst)
+                {
+                    return self.tcp_rst_timeout;
+                }
+                self.tcp_inactivity_timeout
+            }
+            L4Conn::Udp(_) => self.udp_inactivity_timeout,
+            L4Conn::Sctp(_) => self.sctp_inactivity_timeout,
+        };
+        let mut timeout = if self.port_timeouts.is_empty() {
+            timeout
+        } else {
+            let five_tuple = conn.five_tuple();
+            self.port_timeouts
+                .get(&(five_tuple.proto, five_tuple.resp.port()))
+                .copied()
+                .unwrap_or(timeout)
+        };
+        if let (L4Conn::Tcp(tcp_conn), Some(half_closed)) =
+            (&conn.l4conn, self.tcp_half_closed_timeout)
+        {
+            if tcp_conn.half_closed() {
+                timeout = cmp::min(timeout, half_closed);
+            }
+        }
+        timeout
+    }
+
+    /// Returns why a connection should be closed on its last packet, or `None` if it should not.
+    fn closes<T: Trackable>(&self, conn: &Conn<T>) -> Option<TerminationReason> {
+        conn.terminated()
+            .filter(|reason| *reason != TerminationReason::Rst || self.tcp_rst_timeout == 0)
+    }
+
+    /// Returns the longest inactivity timeout.
+    fn max_timeout(&self) -> usize {
+        self.port_timeouts
+            .values()
+            .copied()
+            .chain([
+                self.tcp_inactivity_timeout,
+                self.udp_inactivity_timeout,
+                self.sctp_inactivity_timeout,
+                self.tcp_rst_timeout,
+                self.tcp_time_wait,
+            ])
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Returns the reassembly options of new TCP connections.
+    fn reassembly(&self) -> ReassemblyConfig {
+        ReassemblyConfig {
+            max_segments: self.max_out_of_order,
+            max_bytes: self.max_out_of_order_bytes,
+            max_total_bytes: self.max_out_of_order_total_bytes,
+            overflow: self.out_of_order_overflow,
+            overlap: self.tcp_overlap_policy,
+            skip_acked_gaps: self.skip_acked_gaps,
         }
     }
 }
@@ -0,0 +1,154 @@
+//! Multipath TCP (MPTCP) subflow identification.
+//!
+//! An MPTCP connection (RFC 8684) is made of several TCP connections, its subflows, each tracked
+//! as a separate connection. The MPTCP options exchanged in the handshake of a subflow identify
+//! the MPTCP connection it belongs to by the token of the server, which lets connection-level
+//! datatypes associate the subflows of the same MPTCP connection.
+//!
+//! Subflows are tracked by the core their 5-tuple hashes to, so the subflows of an MPTCP
+//! connection are generally spread across cores.
+
+use crate::conntrack::pdu::L4Pdu;
+use crate::protocols::packet::tcp::{ACK, OPT_MPTCP, SYN};
+
+use ring::digest;
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+use serde::Serialize;
+
+/// `MP_CAPABLE` option subtype, in the handshake of the first subflow.
+pub const MP_CAPABLE: u8 = 0;
+/// `MP_JOIN` option subtype, in the handshake of additional subflows.
+pub const MP_JOIN: u8 = 1;
+
+/// An MPTCP option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MptcpOption {
+    /// `MP_CAPABLE`, with the keys it carries: the key of the sender in the SYN (version 0 only)
+    /// and SYN/ACK, and the keys of both sides in the third ACK.
+    Capable {
+        version: u8,
+        sender_key: Option<u64>,
+        receiver_key: Option<u64>,
+    },
+    /// `MP_JOIN`, with the token of the receiver in the SYN.
+    Join { token: Option<u32> },
+    /// Any other option subtype (e.g., `DSS` or `ADD_ADDR`).
+    Other(u8),
+}
+
+impl MptcpOption {
+    /// Parses the data of an MPTCP option (following its kind and length).
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let subtype = data.first()? >> 4;
+        let read_u64 = |at: usize| {
+            data.get(at..at + 8)
+                .map(|b| u64::from_be_bytes(b.try_into().unwrap()))
+        };
+        match subtype {
+            MP_CAPABLE => Some(MptcpOption::Capable {
+                version: data[0] & 0x0f,
+                sender_key: read_u64(2),
+                receiver_key: read_u64(10),
+            }),
+            MP_JOIN => Some(MptcpOption::Join {
+                token: match data.len() {
+                    // SYN: flags, address ID, token, nonce
+                    10 => Some(u32::from_be_bytes(data[2..6].try_into().unwrap())),
+                    _ => None,
+                },
+            }),
+            _ => Some(MptcpOption::Other(subtype)),
+        }
+    }
+}
+
+/// Returns the token of the host that chose `key`, the most significant 32 bits of the hash of
+/// the key (SHA-1 for MPTCP version 0, SHA-256 for version 1).
+pub fn token(key: u64, version: u8) -> u32 {
+    let algorithm = match version {
+        0 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        _ => &digest::SHA256,
+    };
+    let hash = digest::digest(algorithm, &key.to_be_bytes());
+    u32::from_be_bytes(hash.as_ref()[..4].try_into().unwrap())
+}
+
+/// Identifies the MPTCP connection of a subflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct MptcpSubflow {
+    /// Token of the server in the MPTCP connection. All subflows of the connection share it.
+    pub token: u32,
+    /// `false` for the first subflow of the connection, `true` for additional subflows.
+    pub join: bool,
+}
+
+impl MptcpSubflow {
+    /// Identifies the MPTCP connection of `segment`, if it is a handshake segment carrying the
+    /// token of the server: the SYN/ACK or third ACK of a first subflow, or the SYN of an
+    /// additional subflow.
+    pub fn from_pdu(segment: &L4Pdu) -> Option<Self> {
+        let flags = segment.flags();
+        if flags & (SYN | ACK) == 0 {
+            return None;
+        }
+        let option = segment
+            .parse_tcp(|tcp| {
+                tcp.options()
+                    .filter(|(kind, _)| *kind == OPT_MPTCP)
+                    .find_map(|(_, data)| MptcpOption::parse(data))
+            })
+            .flatten()?;
+        match option {
+            MptcpOption::Join { token: Some(token) } if flags & ACK == 0 => {
+                Some(MptcpSubflow { token, join: true })
+            }
+            // The server sends its key in the SYN/ACK, and receives it back in the third ACK
+            MptcpOption::Capable {
+                version,
+                sender_key: Some(key),
+                ..
+            } if flags & SYN != 0 && flags & ACK != 0 => Some(MptcpSubflow {
+                token: token(key, version),
+                join: false,
+            }),
+            MptcpOption::Capable {
+                version,
+                receiver_key: Some(key),
+                ..
+            } if flags & SYN == 0 => Some(MptcpSubflow {
+                token: token(key, version),
+                join: false,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_mptcp_parse_options() {
+        // MP_JOIN in a SYN: subtype and backup flag, address ID, token, nonce
+        let join = [0x10, 0x02, 0xde, 0xad, 0xbe, 0xef, 0, 0, 0, 1];
+        assert_eq!(
+            MptcpOption::parse(&join),
+            Some(MptcpOption::Join {
+                token: Some(0xdeadbeef)
+            })
+        );
+        // MP_CAPABLE version 1 in a SYN/ACK: subtype and version, flags, key of the server
+        let capable = [0x01, 0x81, 0, 0, 0, 0, 0, 0, 0, 7];
+        assert_eq!(
+            MptcpOption::parse(&capable),
+            Some(MptcpOption::Capable {
+                version: 1,
+                sender_key: Some(7),
+                receiver_key: None,
+            })
+        );
+    }
+}
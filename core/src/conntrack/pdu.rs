@@ -1,12 +1,26 @@
+use crate::conntrack::fastpath;
+use crate::conntrack::tunnel::Tunnels;
 use crate::memory::mbuf::Mbuf;
 use crate::protocols::packet::ethernet::Ethernet;
+use crate::protocols::packet::icmp::{
+    Icmp, ICMP_DEST_UNREACHABLE, ICMP_ECHO_REQUEST, ICMP_FRAG_NEEDED, ICMP_PROTOCOL,
+};
+use crate::protocols::packet::icmpv6::{
+    Icmpv6, ICMPV6_DEST_UNREACHABLE, ICMPV6_ECHO_REQUEST, ICMPV6_PROTOCOL,
+};
 use crate::protocols::packet::ipv4::Ipv4;
 use crate::protocols::packet::ipv6::Ipv6;
-use crate::protocols::packet::tcp::{Tcp, TCP_PROTOCOL};
+use crate::protocols::packet::sctp::{
+    Sctp, SCTP_ABORT, SCTP_INIT, SCTP_INIT_ACK, SCTP_PROTOCOL, SCTP_SHUTDOWN_COMPLETE,
+};
+use crate::protocols::packet::tcp::{Tcp, ACK, FIN, RST, SYN, TCP_PROTOCOL};
 use crate::protocols::packet::udp::{Udp, UDP_PROTOCOL};
 use crate::protocols::packet::Packet;
 
 use anyhow::{bail, Result};
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+use serde::Serialize;
 
 use std::net::{IpAddr, SocketAddr};
 
@@ -55,6 +69,37 @@ impl L4Pdu {
     pub fn flags(&self) -> u8 {
         self.ctxt.flags
     }
+
+    #[inline]
+    pub fn ack_no(&self) -> u32 {
+        self.ctxt.ack_no
+    }
+
+    /// Returns the blocks of the TCP SACK option of the segment, as `(left edge, right edge)`
+    /// sequence numbers. The TCP header is parsed again to read them.
+    pub fn sack_blocks(&self) -> Vec<(u32, u32)> {
+        self.parse_tcp(|tcp| tcp.sack_blocks()).unwrap_or_default()
+    }
+
+    /// Returns `true` if the segment is a TCP segment with the SACK-permitted option.
+    pub fn sack_permitted(&self) -> bool {
+        self.parse_tcp(|tcp| tcp.sack_permitted())
+            .unwrap_or_default()
+    }
+
+    /// Parses the TCP header of the segment and applies `f` to it.
+    pub(crate) fn parse_tcp<R>(&self, f: impl FnOnce(&Tcp) -> R) -> Option<R> {
+        if self.ctxt.proto != TCP_PROTOCOL {
+            return None;
+        }
+        let eth = self.mbuf.parse_to::<Ethernet>().ok()?;
+        if let Ok(ipv4) = eth.parse_to::<Ipv4>() {
+            ipv4.parse_to::<Tcp>().ok().map(|tcp| f(&tcp))
+        } else {
+            let ipv6 = eth.parse_to::<Ipv6>().ok()?;
+            ipv6.parse_to::<Tcp>().ok().map(|tcp| f(&tcp))
+        }
+    }
 }
 
 /// Parsed transport-layer context from the packet used for connection tracking.
@@ -70,92 +115,432 @@ pub struct L4Context {
     pub offset: usize,
     /// Length of the payload in bytes.
     pub length: usize,
-    /// Raw sequence number of segment.
+    /// Raw sequence number of segment (Echo sequence number for ICMP).
     pub seq_no: u32,
-    /// TCP flags.
+    /// TCP flags. For SCTP, the association setup and teardown chunks are mapped to TCP flags:
+    /// INIT to `SYN`, INIT ACK to `SYN | ACK`, ABORT to `RST`, and SHUTDOWN COMPLETE to `FIN`.
     pub flags: u8,
+    /// Raw acknowledgment number of segment (`0` for UDP and ICMP).
+    pub ack_no: u32,
+    /// Tunnels the packet was decapsulated from, if any (see [tunnel](crate::conntrack::tunnel)).
+    pub tunnels: Tunnels,
+    /// The ICMP error message carried by the packet, if any. The context is then that of the
+    /// offending packet quoted in the message, and carries no payload.
+    pub icmp_error: Option<IcmpError>,
 }
 
 impl L4Context {
     pub fn new(mbuf: &Mbuf) -> Result<Self> {
-        if let Ok(eth) = mbuf.parse_to::<Ethernet>() {
-            if let Ok(ipv4) = eth.parse_to::<Ipv4>() {
-                if let Ok(tcp) = ipv4.parse_to::<Tcp>() {
-                    if let Some(payload_size) = (ipv4.total_length() as usize)
-                        .checked_sub(ipv4.header_len() + tcp.header_len())
-                    {
-                        Ok(L4Context {
-                            src: SocketAddr::new(IpAddr::V4(ipv4.src_addr()), tcp.src_port()),
-                            dst: SocketAddr::new(IpAddr::V4(ipv4.dst_addr()), tcp.dst_port()),
-                            proto: TCP_PROTOCOL,
-                            offset: tcp.next_header_offset(),
-                            length: payload_size,
-                            seq_no: tcp.seq_no(),
-                            flags: tcp.flags(),
-                        })
-                    } else {
-                        bail!("Malformed Packet");
-                    }
-                } else if let Ok(udp) = ipv4.parse_to::<Udp>() {
-                    if let Some(payload_size) = (ipv4.total_length() as usize)
-                        .checked_sub(ipv4.header_len() + udp.header_len())
-                    {
-                        Ok(L4Context {
-                            src: SocketAddr::new(IpAddr::V4(ipv4.src_addr()), udp.src_port()),
-                            dst: SocketAddr::new(IpAddr::V4(ipv4.dst_addr()), udp.dst_port()),
-                            proto: UDP_PROTOCOL,
-                            offset: udp.next_header_offset(),
-                            length: payload_size,
-                            seq_no: 0,
-                            flags: 0,
-                        })
-                    } else {
-                        bail!("Malformed Packet");
-                    }
+        if let Some(ctxt) = fastpath::l4_context(mbuf.data()) {
+            return Ok(ctxt);
+        }
+        match mbuf.parse_to::<Ethernet>() {
+            Ok(eth) => Self::from_link(&eth),
+            Err(_) => bail!("Not Ethernet"),
+        }
+    }
+
+    /// Parses the context of the IP packet carried by `link`, an Ethernet frame or a tunnel whose
+    /// payload is identified by its EtherType, which may be encapsulated in another packet.
+    pub(crate) fn from_link<'a>(link: &'a impl Packet<'a>) -> Result<Self> {
+        if let Ok(ipv4) = link.parse_to::<Ipv4>() {
+            if let Ok(tcp) = ipv4.parse_to::<Tcp>() {
+                if let Some(payload_size) =
+                    (ipv4.total_length() as usize).checked_sub(ipv4.header_len() + tcp.header_len())
+                {
+                    Ok(L4Context {
+                        src: SocketAddr::new(IpAddr::V4(ipv4.src_addr()), tcp.src_port()),
+                        dst: SocketAddr::new(IpAddr::V4(ipv4.dst_addr()), tcp.dst_port()),
+                        proto: TCP_PROTOCOL,
+                        offset: tcp.next_header_offset(),
+                        length: payload_size,
+                        seq_no: tcp.seq_no(),
+                        flags: tcp.flags(),
+                        ack_no: tcp.ack_no(),
+                        tunnels: Tunnels::default(),
+                        icmp_error: None,
+                    })
                 } else {
-                    bail!("Not TCP or UDP");
+                    bail!("Malformed Packet");
                 }
-            } else if let Ok(ipv6) = eth.parse_to::<Ipv6>() {
-                if let Ok(tcp) = ipv6.parse_to::<Tcp>() {
-                    if let Some(payload_size) =
-                        (ipv6.payload_length() as usize).checked_sub(tcp.header_len())
-                    {
-                        Ok(L4Context {
-                            src: SocketAddr::new(IpAddr::V6(ipv6.src_addr()), tcp.src_port()),
-                            dst: SocketAddr::new(IpAddr::V6(ipv6.dst_addr()), tcp.dst_port()),
-                            proto: TCP_PROTOCOL,
-                            offset: tcp.next_header_offset(),
-                            length: payload_size,
-                            seq_no: tcp.seq_no(),
-                            flags: tcp.flags(),
-                        })
-                    } else {
-                        bail!("Malformed Packet");
-                    }
-                } else if let Ok(udp) = ipv6.parse_to::<Udp>() {
-                    if let Some(payload_size) =
-                        (ipv6.payload_length() as usize).checked_sub(udp.header_len())
-                    {
-                        Ok(L4Context {
-                            src: SocketAddr::new(IpAddr::V6(ipv6.src_addr()), udp.src_port()),
-                            dst: SocketAddr::new(IpAddr::V6(ipv6.dst_addr()), udp.dst_port()),
-                            proto: UDP_PROTOCOL,
-                            offset: udp.next_header_offset(),
-                            length: payload_size,
-                            seq_no: 0,
-                            flags: 0,
-                        })
-                    } else {
-                        bail!("Malformed Packet");
-                    }
+            } else if let Ok(udp) = ipv4.parse_to::<Udp>() {
+                if let Some(payload_size) =
+                    (ipv4.total_length() as usize).checked_sub(ipv4.header_len() + udp.header_len())
+                {
+                    Ok(L4Context {
+                        src: SocketAddr::new(IpAddr::V4(ipv4.src_addr()), udp.src_port()),
+                        dst: SocketAddr::new(IpAddr::V4(ipv4.dst_addr()), udp.dst_port()),
+                        proto: UDP_PROTOCOL,
+                        offset: udp.next_header_offset(),
+                        length: payload_size,
+                        seq_no: 0,
+                        flags: 0,
+                        ack_no: 0,
+                        tunnels: Tunnels::default(),
+                        icmp_error: None,
+                    })
                 } else {
-                    bail!("Not TCP or UDP");
+                    bail!("Malformed Packet");
+                }
+            } else if let Ok(icmp) = ipv4.parse_to::<Icmp>() {
+                if icmp.error() != 0 {
+                    let error = IcmpError {
+                        proto: ICMP_PROTOCOL,
+                        r#type: icmp.r#type(),
+                        code: icmp.code(),
+                        reporter: IpAddr::V4(ipv4.src_addr()),
+                    };
+                    return match icmp.parse_to::<Ipv4>() {
+                        Ok(quoted) if quoted.fragment_offset() == 0 => Self::from_quoted(
+                            &quoted,
+                            IpAddr::V4(quoted.src_addr()),
+                            IpAddr::V4(quoted.dst_addr()),
+                            error,
+                        ),
+                        _ => bail!("Malformed Packet"),
+                    };
+                }
+                if icmp.echo() == 0 {
+                    bail!("Not ICMP Echo or error");
+                }
+                if let Some(payload_size) = (ipv4.total_length() as usize)
+                    .checked_sub(ipv4.header_len() + icmp.header_len())
+                {
+                    let (src_port, dst_port) = echo_ports(
+                        icmp.r#type() == ICMP_ECHO_REQUEST,
+                        icmp.identifier(),
+                        icmp.sequence(),
+                    );
+                    Ok(L4Context {
+                        src: SocketAddr::new(IpAddr::V4(ipv4.src_addr()), src_port),
+                        dst: SocketAddr::new(IpAddr::V4(ipv4.dst_addr()), dst_port),
+                        proto: ICMP_PROTOCOL,
+                        offset: icmp.next_header_offset(),
+                        length: payload_size,
+                        seq_no: icmp.sequence().into(),
+                        flags: 0,
+                        ack_no: 0,
+                        tunnels: Tunnels::default(),
+                        icmp_error: None,
+                    })
+                } else {
+                    bail!("Malformed Packet");
+                }
+            } else if let Ok(sctp) = ipv4.parse_to::<Sctp>() {
+                if let Some(payload_size) = (ipv4.total_length() as usize)
+                    .checked_sub(ipv4.header_len() + sctp.header_len())
+                {
+                    Ok(L4Context {
+                        src: SocketAddr::new(IpAddr::V4(ipv4.src_addr()), sctp.src_port()),
+                        dst: SocketAddr::new(IpAddr::V4(ipv4.dst_addr()), sctp.dst_port()),
+                        proto: SCTP_PROTOCOL,
+                        offset: sctp.next_header_offset(),
+                        length: payload_size,
+                        seq_no: 0,
+                        flags: sctp_flags(&sctp),
+                        ack_no: 0,
+                        tunnels: Tunnels::default(),
+                        icmp_error: None,
+                    })
+                } else {
+                    bail!("Malformed Packet");
                 }
             } else {
-                bail!("Not IP");
+                bail!("Not TCP, UDP, SCTP, or ICMP");
+            }
+        } else if let Ok(ipv6) = link.parse_to::<Ipv6>() {
+            if let Ok(tcp) = ipv6.parse_to::<Tcp>() {
+                if let Some(payload_size) = (ipv6.payload_length() as usize)
+                    .checked_sub(ipv6.ext_length() + tcp.header_len())
+                {
+                    Ok(L4Context {
+                        src: SocketAddr::new(IpAddr::V6(ipv6.src_addr()), tcp.src_port()),
+                        dst: SocketAddr::new(IpAddr::V6(ipv6.dst_addr()), tcp.dst_port()),
+                        proto: TCP_PROTOCOL,
+                        offset: tcp.next_header_offset(),
+                        length: payload_size,
+                        seq_no: tcp.seq_no(),
+                        flags: tcp.flags(),
+                        ack_no: tcp.ack_no(),
+                        tunnels: Tunnels::default(),
+                        icmp_error: None,
+                    })
+                } else {
+                    bail!("Malformed Packet");
+                }
+            } else if let Ok(udp) = ipv6.parse_to::<Udp>() {
+                if let Some(payload_size) = (ipv6.payload_length() as usize)
+                    .checked_sub(ipv6.ext_length() + udp.header_len())
+                {
+                    Ok(L4Context {
+                        src: SocketAddr::new(IpAddr::V6(ipv6.src_addr()), udp.src_port()),
+                        dst: SocketAddr::new(IpAddr::V6(ipv6.dst_addr()), udp.dst_port()),
+                        proto: UDP_PROTOCOL,
+                        offset: udp.next_header_offset(),
+                        length: payload_size,
+                        seq_no: 0,
+                        flags: 0,
+                        ack_no: 0,
+                        tunnels: Tunnels::default(),
+                        icmp_error: None,
+                    })
+                } else {
+                    bail!("Malformed Packet");
+                }
+            } else if let Ok(icmp) = ipv6.parse_to::<Icmpv6>() {
+                if icmp.error() != 0 {
+                    let error = IcmpError {
+                        proto: ICMPV6_PROTOCOL,
+                        r#type: icmp.r#type(),
+                        code: icmp.code(),
+                        reporter: IpAddr::V6(ipv6.src_addr()),
+                    };
+                    return match icmp.parse_to::<Ipv6>() {
+                        Ok(quoted) => Self::from_quoted(
+                            &quoted,
+                            IpAddr::V6(quoted.src_addr()),
+                            IpAddr::V6(quoted.dst_addr()),
+                            error,
+                        ),
+                        Err(_) => bail!("Malformed Packet"),
+                    };
+                }
+                if icmp.echo() == 0 {
+                    bail!("Not ICMPv6 Echo or error");
+                }
+                if let Some(payload_size) = (ipv6.payload_length() as usize)
+                    .checked_sub(ipv6.ext_length() + icmp.header_len())
+                {
+                    let (src_port, dst_port) = echo_ports(
+                        icmp.r#type() == ICMPV6_ECHO_REQUEST,
+                        icmp.identifier(),
+                        icmp.sequence(),
+                    );
+                    Ok(L4Context {
+                        src: SocketAddr::new(IpAddr::V6(ipv6.src_addr()), src_port),
+                        dst: SocketAddr::new(IpAddr::V6(ipv6.dst_addr()), dst_port),
+                        proto: ICMPV6_PROTOCOL,
+                        offset: icmp.next_header_offset(),
+                        length: payload_size,
+                        seq_no: icmp.sequence().into(),
+                        flags: 0,
+                        ack_no: 0,
+                        tunnels: Tunnels::default(),
+                        icmp_error: None,
+                    })
+                } else {
+                    bail!("Malformed Packet");
+                }
+            } else if let Ok(sctp) = ipv6.parse_to::<Sctp>() {
+                if let Some(payload_size) = (ipv6.payload_length() as usize)
+                    .checked_sub(ipv6.ext_length() + sctp.header_len())
+                {
+                    Ok(L4Context {
+                        src: SocketAddr::new(IpAddr::V6(ipv6.src_addr()), sctp.src_port()),
+                        dst: SocketAddr::new(IpAddr::V6(ipv6.dst_addr()), sctp.dst_port()),
+                        proto: SCTP_PROTOCOL,
+                        offset: sctp.next_header_offset(),
+                        length: payload_size,
+                        seq_no: 0,
+                        flags: sctp_flags(&sctp),
+                        ack_no: 0,
+                        tunnels: Tunnels::default(),
+                        icmp_error: None,
+                    })
+                } else {
+                    bail!("Malformed Packet");
+                }
+            } else {
+                bail!("Not TCP, UDP, SCTP, or ICMP");
             }
         } else {
-            bail!("Not Ethernet");
+            bail!("Not IP");
+        }
+    }
+
+    /// Parses the context of the connection that an ICMP `error` message is about, from the IP
+    /// header `quoted` of the offending packet, sent from `src` to `dst`, and the start of its
+    /// transport header. Only the first 8 bytes of the transport header are guaranteed to be
+    /// quoted, so TCP, UDP, and SCTP headers are not fully parsed.
+    fn from_quoted<'a>(
+        quoted: &'a impl Packet<'a>,
+        src: IpAddr,
+        dst: IpAddr,
+        error: IcmpError,
+    ) -> Result<Self> {
+        let proto = quoted.next_header().unwrap_or_default();
+        let (src_port, dst_port) = match proto {
+            TCP_PROTOCOL | UDP_PROTOCOL | SCTP_PROTOCOL => {
+                let ports = quoted
+                    .mbuf()
+                    .get_data_slice(quoted.next_header_offset(), 4)?;
+                (
+                    u16::from_be_bytes([ports[0], ports[1]]),
+                    u16::from_be_bytes([ports[2], ports[3]]),
+                )
+            }
+            ICMP_PROTOCOL => match quoted.parse_to::<Icmp>() {
+                Ok(icmp) if icmp.echo() != 0 => echo_ports(
+                    icmp.r#type() == ICMP_ECHO_REQUEST,
+                    icmp.identifier(),
+                    icmp.sequence(),
+                ),
+                _ => bail!("Not about ICMP Echo"),
+            },
+            ICMPV6_PROTOCOL => match quoted.parse_to::<Icmpv6>() {
+                Ok(icmp) if icmp.echo() != 0 => echo_ports(
+                    icmp.r#type() == ICMPV6_ECHO_REQUEST,
+                    icmp.identifier(),
+                    icmp.sequence(),
+                ),
+                _ => bail!("Not about ICMPv6 Echo"),
+            },
+            _ => bail!("Not about TCP, UDP, SCTP, or ICMP"),
+        };
+        Ok(L4Context {
+            src: SocketAddr::new(src, src_port),
+            dst: SocketAddr::new(dst, dst_port),
+            proto,
+            offset: quoted.next_header_offset(),
+            length: 0,
+            seq_no: 0,
+            flags: 0,
+            ack_no: 0,
+            tunnels: Tunnels::default(),
+            icmp_error: Some(error),
+        })
+    }
+
+    /// Returns the identifier and the sequence number of an ICMP or ICMPv6 Echo message, whether
+    /// it is a request or a reply. The sequence number is kept in `seq_no`, and the identifier is
+    /// the other port (see [echo_ports](echo_ports)).
+    pub(crate) fn echo(&self) -> Option<(u16, u16)> {
+        if !matches!(self.proto, ICMP_PROTOCOL | ICMPV6_PROTOCOL) || self.icmp_error.is_some() {
+            return None;
+        }
+        let sequence = self.seq_no as u16;
+        match self.src.port() == sequence {
+            true => Some((self.dst.port(), sequence)),
+            false => Some((self.src.port(), sequence)),
+        }
+    }
+}
+
+/// An ICMP or ICMPv6 error message, such as Destination Unreachable or Time Exceeded, about a
+/// packet of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct IcmpError {
+    /// Protocol of the message, ICMP or ICMPv6.
+    pub proto: usize,
+    /// Message type.
+    pub r#type: u8,
+    /// Message code.
+    pub code: u8,
+    /// Address of the host that sent the message, often a router on the path.
+    pub reporter: IpAddr,
+}
+
+impl IcmpError {
+    /// Returns `true` if the message reports that the destination cannot be reached, rather than
+    /// a packet too big to be forwarded or a packet that expired in transit.
+    pub fn unreachable(&self) -> bool {
+        match self.proto {
+            ICMP_PROTOCOL => self.r#type == ICMP_DEST_UNREACHABLE && self.code != ICMP_FRAG_NEEDED,
+            _ => self.r#type == ICMPV6_DEST_UNREACHABLE,
         }
     }
 }
+
+/// Maps the association setup and teardown chunks of an SCTP packet to TCP flags.
+fn sctp_flags(sctp: &Sctp) -> u8 {
+    sctp.chunks().fold(0, |flags, chunk| {
+        flags
+            | match chunk.chunk_type() {
+                SCTP_INIT => SYN,
+                SCTP_INIT_ACK => SYN | ACK,
+                SCTP_ABORT => RST,
+                SCTP_SHUTDOWN_COMPLETE => FIN,
+                _ => 0,
+            }
+    })
+}
+
+/// Returns the source and destination ports of an ICMP Echo message. Each exchange is keyed by
+/// identifier and sequence number, in place of the ports: the identifier is on the side of the
+/// requester, so that a request and its reply belong to the same connection.
+fn echo_ports(request: bool, identifier: u16, sequence: u16) -> (u16, u16) {
+    match request {
+        true => (identifier, sequence),
+        false => (sequence, identifier),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conntrack::conn_id::ConnId;
+    use std::net::Ipv4Addr;
+
+    fn echo_conn_id(src: Ipv4Addr, dst: Ipv4Addr, request: bool, sequence: u16) -> ConnId {
+        let (src_port, dst_port) = echo_ports(request, 0x1234, sequence);
+        ConnId::new(
+            SocketAddr::new(IpAddr::V4(src), src_port),
+            SocketAddr::new(IpAddr::V4(dst), dst_port),
+            ICMP_PROTOCOL,
+        )
+    }
+
+    #[test]
+    fn core_icmp_echo_pairing() {
+        let client = Ipv4Addr::new(10, 0, 0, 1);
+        let server = Ipv4Addr::new(10, 0, 0, 2);
+        // A request and its reply are the same connection
+        assert_eq!(
+            echo_conn_id(client, server, true, 1),
+            echo_conn_id(server, client, false, 1)
+        );
+        // Each request is a connection of its own
+        assert_ne!(
+            echo_conn_id(client, server, true, 1),
+            echo_conn_id(client, server, true, 2)
+        );
+        assert_ne!(
+            echo_conn_id(client, server, true, 1),
+            echo_conn_id(server, client, false, 2)
+        );
+        // Requests in the opposite direction are distinct
+        assert_ne!(
+            echo_conn_id(client, server, true, 1),
+            echo_conn_id(server, client, true, 1)
+        );
+    }
+
+    #[test]
+    fn core_icmp_echo_reply_first() {
+        let client = Ipv4Addr::new(10, 0, 0, 1);
+        let server = Ipv4Addr::new(10, 0, 0, 2);
+        let echo = |src: Ipv4Addr, dst: Ipv4Addr, request: bool, sequence: u16| {
+            let (src_port, dst_port) = echo_ports(request, 0x1234, sequence);
+            L4Context {
+                src: SocketAddr::new(IpAddr::V4(src), src_port),
+                dst: SocketAddr::new(IpAddr::V4(dst), dst_port),
+                proto: ICMP_PROTOCOL,
+                offset: 0,
+                length: 0,
+                seq_no: sequence.into(),
+                flags: 0,
+                ack_no: 0,
+                tunnels: Tunnels::default(),
+                icmp_error: None,
+            }
+        };
+        // The identifier and sequence number do not depend on which message is seen first
+        assert_eq!(echo(client, server, true, 7).echo(), Some((0x1234, 7)));
+        assert_eq!(echo(server, client, false, 7).echo(), Some((0x1234, 7)));
+        assert_eq!(
+            echo(server, client, false, 0x1234).echo(),
+            Some((0x1234, 0x1234))
+        );
+    }
+}
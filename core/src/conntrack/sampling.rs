@@ -0,0 +1,100 @@
+//! Flow sampling (see [SamplingConfig](crate::config::SamplingConfig)).
+
+use crate::config::{SamplingConfig, SamplingMethod};
+
+use std::time::{Duration, Instant};
+
+/// Interval between adjustments of the sampled fraction of the `rate` method.
+const ADJUST_INTERVAL: Duration = Duration::from_secs(1);
+/// Smallest fraction sampled by the `rate` method, so that it can recover from bursts.
+const MIN_FRACTION: f64 = 1e-6;
+
+/// Decides which new connections of a core are tracked.
+#[derive(Debug)]
+pub(crate) struct Sampler {
+    method: SamplingMethod,
+    target_rate: f64,
+    /// Fraction of the connections sampled.
+    fraction: f64,
+    /// Connections are sampled if the hash of their ID is at most the threshold.
+    threshold: u64,
+    /// Start of the current adjustment interval of the `rate` method.
+    interval_start: Instant,
+    /// Number of connections created by the core before the current adjustment interval.
+    interval_created: u64,
+}
+
+impl Sampler {
+    pub(crate) fn new(config: &SamplingConfig) -> Self {
+        let fraction = match config.method {
+            SamplingMethod::Hash => config.rate,
+            SamplingMethod::OneInN => 1.0 / config.n.max(1) as f64,
+            SamplingMethod::Rate => 1.0,
+        };
+        let mut sampler = Sampler {
+            method: config.method,
+            target_rate: config.target_rate,
+            fraction: 0.0,
+            threshold: 0,
+            interval_start: Instant::now(),
+            interval_created: 0,
+        };
+        sampler.set_fraction(fraction);
+        sampler
+    }
+
+    fn set_fraction(&mut self, fraction: f64) {
+        self.fraction = fraction.clamp(0.0, 1.0);
+        self.threshold = (self.fraction * u64::MAX as f64) as u64;
+    }
+
+    /// Returns `true` if the connection whose ID hashes to `hash` is sampled.
+    #[inline]
+    pub(crate) fn is_sampled(&self, hash: u64) -> bool {
+        hash <= self.threshold
+    }
+
+    /// Adjusts the sampled fraction of the `rate` method to its target, from the number of
+    /// connections `nb_created` by the core so far.
+    #[inline]
+    pub(crate) fn adjust(&mut self, nb_created: u64) {
+        if self.method != SamplingMethod::Rate {
+            return;
+        }
+        let elapsed = self.interval_start.elapsed();
+        if elapsed < ADJUST_INTERVAL {
+            return;
+        }
+        let rate = (nb_created - self.interval_created) as f64 / elapsed.as_secs_f64();
+        let fraction = if rate > 0.0 {
+            self.fraction * self.target_rate / rate
+        } else {
+            self.fraction * 2.0
+        };
+        self.set_fraction(fraction.max(MIN_FRACTION));
+        self.interval_start = Instant::now();
+        self.interval_created = nb_created;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_sampling_adjust() {
+        let config = SamplingConfig {
+            method: SamplingMethod::Rate,
+            rate: 0.1,
+            n: 10,
+            target_rate: 100.0,
+        };
+        let mut sampler = Sampler::new(&config);
+        assert!(sampler.is_sampled(u64::MAX));
+        sampler.interval_start -= Duration::from_secs(2);
+        sampler.adjust(800);
+        assert!((sampler.fraction - 0.25).abs() < 1e-9);
+        assert!(sampler.is_sampled(u64::MAX / 8));
+        assert!(!sampler.is_sampled(u64::MAX / 2));
+    }
+}
@@ -0,0 +1,625 @@
+//! Per-core connection table.
+//!
+//! The table is open-addressed: connections are stored inline in a dense array of entries, and
+//! indexed by an array of 8-byte slots probed linearly from the slot the hash of the connection ID
+//! maps to. Each slot holds the index of its entry and 32 bits of its hash, so that probing rarely
+//! compares connection IDs of other entries, and slots are kept at most three-quarters full.
+//! Removing a connection moves the last entry into its place and moves slots of its cluster back
+//! into the hole, leaving no tombstones.
+//!
+//! Both arrays start small and grow as connections are inserted: entries double up to
+//! `max_connections`, and slots double and are rehashed when they become three-quarters full.
+//! They are not shrunk, so once the table has held its peak number of connections, inserting and
+//! removing connections no longer allocates.
+//!
+//! Entries are also linked in insertion (or, with the `lru` eviction policy, recency) order, which
+//! the eviction policies rely on.
+//!
+//! Keys are hashed with [ConnIdHasher](ConnIdHasher), a multiply-fold hash of the 5-tuple words
+//! instead of SipHash, seeded at random for each table so that the slot of a connection cannot be
+//! predicted from its 5-tuple. The hash of a packet's connection ID is computed once, and reused
+//! for the lookup and the insertion of new connections.
+//!
+//! The `conntrack` benchmark (`cargo bench -p retina-core`) measures the table operations for
+//! millions of connections.
+
+use super::conn_id::ConnId;
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// Value of an empty slot.
+const EMPTY: u64 = 0;
+/// Index of no entry, at the ends of the order list.
+const NIL: u32 = u32::MAX;
+/// Minimum number of slots.
+const MIN_SLOTS: usize = 8;
+/// Minimum number of entries allocated at once.
+const MIN_ENTRIES: usize = 64;
+
+/// Connection table of a `ConnTracker`, mapping connection IDs to values of type `V`.
+pub struct ConnTable<V> {
+    /// Empty, or the low 32 bits of the hash of an entry and its index plus one.
+    slots: Vec<u64>,
+    /// Shift of a hash to the index of its first slot.
+    shift: u32,
+    /// Entries, in no particular order.
+    entries: Vec<Entry<V>>,
+    /// Number of entries the table is sized for.
+    capacity: usize,
+    /// First entry in order.
+    head: u32,
+    /// Last entry in order.
+    tail: u32,
+    hash_builder: ConnIdHashBuilder,
+}
+
+struct Entry<V> {
+    hash: u64,
+    /// Previous entry in order.
+    prev: u32,
+    /// Next entry in order.
+    next: u32,
+    conn_id: ConnId,
+    value: V,
+}
+
+impl<V> ConnTable<V> {
+    /// Creates a table sized for up to `capacity` connections, with a new random hash seed.
+    /// Memory is allocated as connections are inserted, not up front.
+    pub fn with_capacity(capacity: usize) -> Self {
+        check_capacity(capacity);
+        ConnTable {
+            slots: vec![EMPTY; MIN_SLOTS],
+            shift: u64::BITS - MIN_SLOTS.trailing_zeros(),
+            entries: Vec::new(),
+            capacity,
+            head: NIL,
+            tail: NIL,
+            hash_builder: ConnIdHashBuilder::new(),
+        }
+    }
+
+    /// Returns the hasher of the connection IDs.
+    #[inline]
+    pub fn hasher(&self) -> &ConnIdHashBuilder {
+        &self.hash_builder
+    }
+
+    /// Returns the number of connections in the table.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the table is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the number of connections the table is sized for.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sizes the table for at least `additional` more connections. Like insertions, this does
+    /// not allocate until the connections are inserted.
+    pub fn reserve(&mut self, additional: usize) {
+        let capacity = self.entries.len() + additional;
+        if capacity > self.capacity {
+            check_capacity(capacity);
+            self.capacity = capacity;
+        }
+    }
+
+    /// Returns the value of `conn_id`.
+    #[inline]
+    pub fn get(&self, conn_id: &ConnId) -> Option<&V> {
+        self.get_hashed(self.hash_builder.hash(conn_id), conn_id)
+    }
+
+    /// Returns the value of `conn_id`, whose hash is `hash`.
+    #[inline]
+    pub fn get_hashed(&self, hash: u64, conn_id: &ConnId) -> Option<&V> {
+        self.find(hash, conn_id)
+            .ok()
+            .map(|(_, index)| &self.entries[index].value)
+    }
+
+    /// Returns the entry of `conn_id`, whose hash is `hash`, for in-place manipulation.
+    #[inline]
+    pub fn entry(&mut self, hash: u64, conn_id: &ConnId) -> TableEntry<'_, V> {
+        match self.find(hash, conn_id) {
+            Ok((pos, index)) => TableEntry::Occupied(OccupiedEntry {
+                table: self,
+                pos,
+                index,
+            }),
+            Err(pos) => TableEntry::Vacant(VacantEntry {
+                table: self,
+                pos,
+                hash,
+            }),
+        }
+    }
+
+    /// Removes `conn_id` from the table, and returns its value.
+    pub fn remove(&mut self, conn_id: &ConnId) -> Option<V> {
+        let (pos, index) = self.find(self.hash_builder.hash(conn_id), conn_id).ok()?;
+        Some(self.remove_at(pos, index))
+    }
+
+    /// Returns the first connection in order.
+    pub fn front(&self) -> Option<(&ConnId, &V)> {
+        self.entry_at(self.head)
+            .map(|entry| (&entry.conn_id, &entry.value))
+    }
+
+    /// Returns the connections in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&ConnId, &V)> {
+        std::iter::successors(self.entry_at(self.head), |entry| self.entry_at(entry.next))
+            .map(|entry| (&entry.conn_id, &entry.value))
+    }
+
+    /// Returns the connection IDs in order.
+    pub fn keys(&self) -> impl Iterator<Item = &ConnId> {
+        self.iter().map(|(conn_id, _)| conn_id)
+    }
+
+    /// Removes all connections, and returns them in no particular order.
+    pub fn drain(&mut self) -> impl Iterator<Item = (ConnId, V)> + '_ {
+        self.slots = vec![EMPTY; self.slots.len()];
+        self.head = NIL;
+        self.tail = NIL;
+        self.entries
+            .drain(..)
+            .map(|entry| (entry.conn_id, entry.value))
+    }
+
+    #[inline]
+    fn entry_at(&self, index: u32) -> Option<&Entry<V>> {
+        (index != NIL).then(|| &self.entries[index as usize])
+    }
+
+    /// Returns the first slot of the probe sequence of `hash` that satisfies `pred`. The table
+    /// always has an empty slot, at which probing for an empty slot stops.
+    #[inline]
+    fn probe(&self, hash: u64, mut pred: impl FnMut(u64) -> bool) -> usize {
+        let mask = self.slots.len() - 1;
+        let mut pos = (hash >> self.shift) as usize;
+        while !pred(self.slots[pos]) {
+            pos = (pos + 1) & mask;
+        }
+        pos
+    }
+
+    /// Returns the slot and the index of the entry of `conn_id`, or the empty slot where it
+    /// would be inserted.
+    #[inline]
+    fn find(&self, hash: u64, conn_id: &ConnId) -> Result<(usize, usize), usize> {
+        let tag = hash as u32;
+        let pos = self.probe(hash, |slot| {
+            slot == EMPTY
+                || ((slot >> 32) as u32 == tag
+                    && self.entries[slot_index(slot)].conn_id == *conn_id)
+        });
+        match self.slots[pos] {
+            EMPTY => Err(pos),
+            slot => Ok((pos, slot_index(slot))),
+        }
+    }
+
+    /// Grows the entries and the slots to hold one more entry, and returns whether the slots were
+    /// rehashed.
+    fn grow(&mut self) -> bool {
+        let len = self.entries.len();
+        if len == self.entries.capacity() {
+            // Past the capacity only if the caller does not bound the number of connections
+            let target = if len < self.capacity {
+                (len * 2).max(MIN_ENTRIES).min(self.capacity)
+            } else {
+                (len * 2).max(MIN_ENTRIES)
+            };
+            self.entries.reserve_exact(target - len);
+        }
+        let len = len + 1;
+        if len + len / 3 < self.slots.len() {
+            return false;
+        }
+        let nb_slots = nb_slots(len);
+        self.slots = vec![EMPTY; nb_slots];
+        self.shift = u64::BITS - nb_slots.trailing_zeros();
+        for index in 0..self.entries.len() {
+            let hash = self.entries[index].hash;
+            let pos = self.probe(hash, |slot| slot == EMPTY);
+            self.slots[pos] = slot(hash, index);
+        }
+        true
+    }
+
+    /// Inserts a new connection at the empty slot `pos` of its probe sequence, and returns the
+    /// index of its entry.
+    fn insert_at(&mut self, mut pos: usize, hash: u64, conn_id: ConnId, value: V) -> usize {
+        if self.grow() {
+            pos = self.probe(hash, |slot| slot == EMPTY);
+        }
+        let index = self.entries.len();
+        self.entries.push(Entry {
+            hash,
+            prev: NIL,
+            next: NIL,
+            conn_id,
+            value,
+        });
+        self.slots[pos] = slot(hash, index);
+        self.link_back(index);
+        index
+    }
+
+    /// Removes the entry `index`, held in slot `pos`, and returns its value.
+    fn remove_at(&mut self, mut pos: usize, index: usize) -> V {
+        self.unlink(index);
+        // Moves the following slots of the cluster into the hole, unless their probe sequence
+        // starts after it
+        let mask = self.slots.len() - 1;
+        let mut next = pos;
+        loop {
+            next = (next + 1) & mask;
+            let slot = self.slots[next];
+            if slot == EMPTY {
+                break;
+            }
+            let start = (self.entries[slot_index(slot)].hash >> self.shift) as usize;
+            if next.wrapping_sub(start) & mask >= next.wrapping_sub(pos) & mask {
+                self.slots[pos] = slot;
+                pos = next;
+            }
+        }
+        self.slots[pos] = EMPTY;
+
+        // The last entry takes the place of the removed one
+        let last = self.entries.len() - 1;
+        if index != last {
+            let moved = &self.entries[last];
+            let (hash, prev, next) = (moved.hash, moved.prev, moved.next);
+            let pos = self.probe(hash, |slot| slot_index(slot) == last);
+            self.slots[pos] = slot(hash, index);
+            match prev {
+                NIL => self.head = index as u32,
+                prev => self.entries[prev as usize].next = index as u32,
+            }
+            match next {
+                NIL => self.tail = index as u32,
+                next => self.entries[next as usize].prev = index as u32,
+            }
+        }
+        self.entries.swap_remove(index).value
+    }
+
+    /// Appends entry `index` to the order list.
+    #[inline]
+    fn link_back(&mut self, index: usize) {
+        let entry = &mut self.entries[index];
+        entry.prev = self.tail;
+        entry.next = NIL;
+        match self.tail {
+            NIL => self.head = index as u32,
+            tail => self.entries[tail as usize].next = index as u32,
+        }
+        self.tail = index as u32;
+    }
+
+    /// Removes entry `index` from the order list.
+    #[inline]
+    fn unlink(&mut self, index: usize) {
+        let Entry { prev, next, .. } = self.entries[index];
+        match prev {
+            NIL => self.head = next,
+            prev => self.entries[prev as usize].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.entries[next as usize].prev = prev,
+        }
+    }
+}
+
+/// Panics if entry indices of a table of `capacity` connections do not fit in the order list.
+fn check_capacity(capacity: usize) {
+    assert!(
+        capacity < NIL as usize,
+        "Connection table capacity exceeds {}",
+        NIL
+    );
+}
+
+/// Returns the number of slots of a table of `capacity` connections.
+fn nb_slots(capacity: usize) -> usize {
+    check_capacity(capacity);
+    (capacity + capacity / 3 + 1)
+        .next_power_of_two()
+        .max(MIN_SLOTS)
+}
+
+/// Returns the slot of entry `index`, whose hash is `hash`.
+#[inline]
+fn slot(hash: u64, index: usize) -> u64 {
+    (hash << 32) | (index as u64 + 1)
+}
+
+/// Returns the index of the entry of a non-empty `slot`.
+#[inline]
+fn slot_index(slot: u64) -> usize {
+    (slot as u32).wrapping_sub(1) as usize
+}
+
+/// An entry of a [ConnTable](ConnTable), occupied or vacant.
+pub enum TableEntry<'a, V> {
+    Occupied(OccupiedEntry<'a, V>),
+    Vacant(VacantEntry<'a, V>),
+}
+
+/// The entry of a connection in the table.
+pub struct OccupiedEntry<'a, V> {
+    table: &'a mut ConnTable<V>,
+    pos: usize,
+    index: usize,
+}
+
+impl<V> OccupiedEntry<'_, V> {
+    /// Returns the value of the connection.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.table.entries[self.index].value
+    }
+
+    /// Moves the connection to the back of the order.
+    #[inline]
+    pub fn to_back(&mut self) {
+        if self.table.tail != self.index as u32 {
+            self.table.unlink(self.index);
+            self.table.link_back(self.index);
+        }
+    }
+
+    /// Removes the connection from the table, and returns its value.
+    #[inline]
+    pub fn remove(self) -> V {
+        self.table.remove_at(self.pos, self.index)
+    }
+}
+
+/// The entry of a connection not in the table.
+pub struct VacantEntry<'a, V> {
+    table: &'a mut ConnTable<V>,
+    pos: usize,
+    hash: u64,
+}
+
+impl<'a, V> VacantEntry<'a, V> {
+    /// Inserts the connection `conn_id` at the back of the order, and returns its value.
+    #[inline]
+    pub fn insert(self, conn_id: ConnId, value: V) -> &'a mut V {
+        let VacantEntry { table, pos, hash } = self;
+        let index = table.insert_at(pos, hash, conn_id, value);
+        &mut table.entries[index].value
+    }
+}
+
+/// Multiplier of the hash, an odd constant with well-mixed bits.
+const MULTIPLE: u64 = 0x5851_f42d_4c95_7f2d;
+
+/// Builds the hashers of a connection table, with a random seed.
+#[derive(Debug, Clone)]
+pub struct ConnIdHashBuilder {
+    seed: u64,
+}
+
+impl ConnIdHashBuilder {
+    /// Creates a hash builder with a new random seed.
+    pub fn new() -> Self {
+        ConnIdHashBuilder {
+            seed: RandomState::new().build_hasher().finish(),
+        }
+    }
+
+    /// Returns the hash of `conn_id`.
+    #[inline]
+    pub fn hash(&self, conn_id: &ConnId) -> u64 {
+        self.hash_one(conn_id)
+    }
+}
+
+impl Default for ConnIdHashBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildHasher for ConnIdHashBuilder {
+    type Hasher = ConnIdHasher;
+
+    #[inline]
+    fn build_hasher(&self) -> ConnIdHasher {
+        ConnIdHasher { hash: self.seed }
+    }
+}
+
+/// Hashes connection IDs, one word at a time.
+///
+/// Each word is folded into the hash by multiplying it (XORed with the hash) by a constant and
+/// XORing the high and low halves of the 128-bit product.
+#[derive(Debug, Clone)]
+pub struct ConnIdHasher {
+    hash: u64,
+}
+
+impl ConnIdHasher {
+    #[inline]
+    fn fold(&mut self, word: u64) {
+        let product = (self.hash ^ word) as u128 * MULTIPLE as u128;
+        self.hash = (product as u64) ^ ((product >> 64) as u64);
+    }
+}
+
+impl Hasher for ConnIdHasher {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            self.fold(u64::from_le_bytes(chunk.try_into().unwrap()));
+        }
+        let remainder = chunks.remainder();
+        if !remainder.is_empty() {
+            let mut word = [0u8; 8];
+            word[..remainder.len()].copy_from_slice(remainder);
+            self.fold(u64::from_le_bytes(word));
+        }
+    }
+
+    #[inline]
+    fn write_u8(&mut self, i: u8) {
+        self.fold(i as u64);
+    }
+
+    #[inline]
+    fn write_u16(&mut self, i: u16) {
+        self.fold(i as u64);
+    }
+
+    #[inline]
+    fn write_u32(&mut self, i: u32) {
+        self.fold(i as u64);
+    }
+
+    #[inline]
+    fn write_u64(&mut self, i: u64) {
+        self.fold(i);
+    }
+
+    #[inline]
+    fn write_usize(&mut self, i: usize) {
+        self.fold(i as u64);
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conntrack::conn_id::FiveTuple;
+
+    #[test]
+    fn core_table_hash() {
+        let tuple = |src: &str, dst: &str| FiveTuple {
+            orig: src.parse().unwrap(),
+            resp: dst.parse().unwrap(),
+            proto: 6,
+        };
+        let hasher = ConnIdHashBuilder::new();
+        let conn_id = tuple("10.0.0.1:443", "10.0.0.2:5000").conn_id();
+        let reverse = tuple("10.0.0.2:5000", "10.0.0.1:443").conn_id();
+        let other = tuple("10.0.0.1:443", "10.0.0.2:5001").conn_id();
+        assert_eq!(hasher.hash(&conn_id), hasher.hash(&reverse));
+        assert_ne!(hasher.hash(&conn_id), hasher.hash(&other));
+    }
+
+    #[test]
+    fn core_table_entries() {
+        let conn_id = |port: u16| {
+            FiveTuple {
+                orig: ([10, 0, 0, 1], port).into(),
+                resp: ([10, 0, 0, 2], 443).into(),
+                proto: 6,
+            }
+            .conn_id()
+        };
+        let mut table = ConnTable::with_capacity(4);
+        for port in 1..=4 {
+            let hash = table.hasher().hash(&conn_id(port));
+            if let TableEntry::Vacant(vacant) = table.entry(hash, &conn_id(port)) {
+                vacant.insert(conn_id(port), port);
+            }
+        }
+        let order =
+            |table: &ConnTable<u16>| table.iter().map(|(_, port)| *port).collect::<Vec<_>>();
+        assert_eq!(order(&table), vec![1, 2, 3, 4]);
+
+        let hash = table.hasher().hash(&conn_id(2));
+        match table.entry(hash, &conn_id(2)) {
+            TableEntry::Occupied(mut occupied) => {
+                occupied.to_back();
+                *occupied.get_mut() += 10;
+            }
+            TableEntry::Vacant(_) => panic!("connection not found"),
+        }
+        assert_eq!(order(&table), vec![1, 3, 4, 12]);
+        assert_eq!(table.remove(&conn_id(1)), Some(1));
+        assert_eq!(table.remove(&conn_id(1)), None);
+        assert_eq!(table.front().map(|(_, port)| *port), Some(3));
+        assert_eq!(table.get(&conn_id(4)), Some(&4));
+
+        // Grows past its capacity, and keeps its entries
+        for port in 101..=104 {
+            let hash = table.hasher().hash(&conn_id(port));
+            if let TableEntry::Vacant(vacant) = table.entry(hash, &conn_id(port)) {
+                vacant.insert(conn_id(port), port);
+            }
+        }
+        assert_eq!(table.len(), 7);
+        assert!((101..=104).all(|port| table.remove(&conn_id(port)) == Some(port)));
+        table.reserve(100);
+        assert_eq!(table.capacity(), 103);
+        for port in 5..=100 {
+            let hash = table.hasher().hash(&conn_id(port));
+            if let TableEntry::Vacant(vacant) = table.entry(hash, &conn_id(port)) {
+                vacant.insert(conn_id(port), port);
+            }
+        }
+        assert_eq!(table.len(), 99);
+        assert!((2..=100).all(|port| table.get(&conn_id(port)).is_some()));
+        for port in (5..=100).step_by(2) {
+            assert_eq!(table.remove(&conn_id(port)), Some(port));
+        }
+        assert!((6..=100)
+            .step_by(2)
+            .all(|port| table.get(&conn_id(port)) == Some(&port)));
+        assert_eq!(table.drain().count(), 51);
+        assert!(table.is_empty() && table.get(&conn_id(6)).is_none());
+    }
+
+    #[test]
+    fn core_table_lazy_allocation() {
+        // Sized for the default limit, without allocating for it
+        let config: crate::config::ConnTrackConfig = toml::from_str("").unwrap();
+        let max_connections = config.max_connections;
+        assert_eq!(max_connections, 10_000_000);
+        let mut table = ConnTable::with_capacity(max_connections);
+        assert_eq!(table.capacity(), max_connections);
+        assert_eq!(table.slots.len(), MIN_SLOTS);
+        assert_eq!(table.entries.capacity(), 0);
+
+        for port in 0..1000 {
+            let conn_id = FiveTuple {
+                orig: ([10, 0, 0, 1], port).into(),
+                resp: ([10, 0, 0, 2], 443).into(),
+                proto: 6,
+            }
+            .conn_id();
+            let hash = table.hasher().hash(&conn_id);
+            if let TableEntry::Vacant(vacant) = table.entry(hash, &conn_id) {
+                vacant.insert(conn_id, port);
+            }
+        }
+        assert_eq!(table.len(), 1000);
+        assert!(table.entries.capacity() < 2048);
+        assert_eq!(table.slots.len(), 2048);
+        assert!(table.iter().map(|(_, port)| *port).eq(0..1000));
+    }
+}
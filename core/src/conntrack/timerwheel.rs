@@ -1,13 +1,40 @@
-use crate::conntrack::{Conn, ConnId};
+use crate::conntrack::conn::Conn;
+use crate::conntrack::conn::TerminationReason;
+use crate::conntrack::table::{ConnTable, TableEntry};
+use crate::conntrack::ConnId;
 use crate::subscription::{Subscription, Trackable};
+use crate::utils::pcap::PcapWriter;
 
 use crossbeam_channel::{tick, Receiver};
-use hashlink::linked_hash_map::LinkedHashMap;
-use hashlink::linked_hash_map::RawEntryMut;
 use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
+/// Number of bits of the tick count resolved by each level of the wheel.
+const LEVEL_BITS: usize = 6;
+/// Number of buckets per level.
+const LEVEL_SIZE: usize = 1 << LEVEL_BITS;
+
+/// Timers of the connections in a wheel.
+pub(super) trait Timers {
+    /// Returns the time the connection was last seen and its inactivity window (in
+    /// milliseconds), or `None` if it is no longer tracked.
+    fn timer(&self, conn_id: &ConnId) -> Option<(Instant, usize)>;
+}
+
+impl<T: Trackable> Timers for ConnTable<Conn<T>> {
+    #[inline]
+    fn timer(&self, conn_id: &ConnId) -> Option<(Instant, usize)> {
+        let conn = self.get(conn_id)?;
+        Some((conn.last_seen_ts, conn.inactivity_window))
+    }
+}
+
 /// Tracks inactive connection expiration.
+///
+/// Timers are kept in a hierarchical wheel: level 0 has one bucket per tick of `period`
+/// milliseconds, and each bucket of level `l` spans `LEVEL_SIZE^l` ticks. Buckets of higher
+/// levels are cascaded into lower ones as their span comes due, so each tick only visits the
+/// connections that are due or need rescheduling, independently of the table size.
 pub(super) struct TimerWheel {
     /// Period to check for inactive connections (in milliseconds).
     period: usize,
@@ -15,27 +42,43 @@ pub(super) struct TimerWheel {
     start_ts: Instant,
     /// Timeout ticker, fires every `period` milliseconds.
     ticker: Receiver<Instant>,
-    /// Index of the next bucket to expire.
-    next_bucket: usize,
-    /// List of timers.
-    timers: Vec<VecDeque<ConnId>>,
+    /// Next tick to expire.
+    next_tick: usize,
+    /// Timer buckets, `LEVEL_SIZE` per level.
+    levels: Vec<Vec<VecDeque<ConnId>>>,
+    /// Timers of the level 0 bucket being expired, swapped with the bucket.
+    due: VecDeque<ConnId>,
+    /// Tick of the bucket being expired.
+    due_tick: usize,
 }
 
 impl TimerWheel {
     /// Creates a new `TimerWheel` with a maximum timeout of `max_timeout` and a timeout check
     /// period of `timeout_resolution`.
+    ///
+    /// The maximum timeout only sizes the wheel: longer timeouts are still honored, at the cost
+    /// of being rescheduled once per revolution of the top level.
     pub(super) fn new(max_timeout: usize, timeout_resolution: usize) -> Self {
         if timeout_resolution > max_timeout {
             panic!("Timeout check period must be smaller than maximum inactivity timeout")
         }
         let start_ts = Instant::now();
         let ticker = tick(Duration::from_millis(timeout_resolution as u64));
+        let max_ticks = max_timeout / timeout_resolution;
+        let mut nb_levels = 1;
+        while nb_levels * LEVEL_BITS < usize::BITS as usize
+            && max_ticks >> (nb_levels * LEVEL_BITS) > 0
+        {
+            nb_levels += 1;
+        }
         TimerWheel {
             period: timeout_resolution,
             start_ts,
             ticker,
-            next_bucket: 0,
-            timers: vec![VecDeque::new(); max_timeout / timeout_resolution],
+            next_tick: 0,
+            levels: vec![vec![VecDeque::new(); LEVEL_SIZE]; nb_levels],
+            due: VecDeque::new(),
+            due_tick: 0,
         }
     }
 
@@ -47,22 +90,88 @@ impl TimerWheel {
         last_seen_ts: Instant,
         inactivity_window: usize,
     ) {
-        let current_time = (last_seen_ts - self.start_ts).as_millis() as usize;
-        let timer_index = ((current_time + inactivity_window) / self.period) % self.timers.len();
-        log::debug!("Inserting into index: {}, {:?}", timer_index, current_time);
-        self.timers[timer_index].push_back(conn_id.to_owned());
+        let expire_tick = self.expire_tick(last_seen_ts, inactivity_window);
+        log::debug!("Inserting with expiration tick: {}", expire_tick);
+        self.schedule(conn_id.to_owned(), expire_tick);
+    }
+
+    /// Returns the tick at which a connection last seen at `last_seen_ts` expires.
+    #[inline]
+    fn expire_tick(&self, last_seen_ts: Instant, inactivity_window: usize) -> usize {
+        let last_seen_time = (last_seen_ts - self.start_ts).as_millis() as usize;
+        (last_seen_time + inactivity_window) / self.period
+    }
+
+    /// Places `conn_id` in the bucket covering `expire_tick`.
+    fn schedule(&mut self, conn_id: ConnId, expire_tick: usize) {
+        let nb_levels = self.levels.len();
+        let expire_tick = expire_tick.max(self.next_tick);
+        let delta = expire_tick - self.next_tick;
+        let level = (0..nb_levels)
+            .find(|level| delta >> ((level + 1) * LEVEL_BITS) == 0)
+            .unwrap_or(nb_levels - 1);
+        // Timeouts beyond the top level are parked in its furthest bucket and rescheduled when
+        // it is cascaded.
+        let span = (nb_levels * LEVEL_BITS).min(usize::BITS as usize - 1);
+        let expire_tick = expire_tick.min(self.next_tick + (1 << span) - 1);
+        let index = (expire_tick >> (level * LEVEL_BITS)) & (LEVEL_SIZE - 1);
+        self.levels[level][index].push_back(conn_id);
+    }
+
+    /// Returns the connection of `table` that expires first, re-inserting the connections
+    /// found active since they were scheduled.
+    ///
+    /// Connections held above level 0 are picked from the earliest non-empty bucket.
+    pub(super) fn earliest(&mut self, table: &impl Timers) -> Option<ConnId> {
+        for tick in self.next_tick..self.next_tick + LEVEL_SIZE {
+            let index = tick & (LEVEL_SIZE - 1);
+            for _ in 0..self.levels[0][index].len() {
+                let conn_id = self.levels[0][index].pop_front()?;
+                let (last_seen_ts, inactivity_window) = match table.timer(&conn_id) {
+                    Some(timer) => timer,
+                    None => continue,
+                };
+                let expire_tick = self.expire_tick(last_seen_ts, inactivity_window);
+                if expire_tick <= tick {
+                    return Some(conn_id);
+                }
+                self.schedule(conn_id, expire_tick);
+            }
+        }
+        for level in 1..self.levels.len() {
+            let current = self.next_tick >> (level * LEVEL_BITS);
+            for bucket in current + 1..=current + LEVEL_SIZE {
+                let list = &mut self.levels[level][bucket & (LEVEL_SIZE - 1)];
+                let earliest = list
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(pos, conn_id)| {
+                        let (last_seen_ts, inactivity_window) = table.timer(conn_id)?;
+                        Some((last_seen_ts, inactivity_window, pos))
+                    })
+                    .min_by_key(|(last_seen_ts, inactivity_window, _)| {
+                        *last_seen_ts + Duration::from_millis(*inactivity_window as u64)
+                    })
+                    .map(|(_, _, pos)| pos);
+                if let Some(pos) = earliest {
+                    return list.remove(pos);
+                }
+            }
+        }
+        None
     }
 
     /// Checks for and remove inactive connections.
     #[inline]
     pub(super) fn check_inactive<T: Trackable>(
         &mut self,
-        table: &mut LinkedHashMap<ConnId, Conn<T>>,
+        table: &mut ConnTable<Conn<T>>,
         subscription: &Subscription<T::Subscribed>,
+        pcap: &mut PcapWriter,
     ) {
         let table_len = table.len();
         if let Ok(now) = self.ticker.try_recv() {
-            let nb_removed = self.remove_inactive(now, table, subscription);
+            let nb_removed = self.remove_inactive(now, table, subscription, pcap);
             log::debug!(
                 "expired: {} ({})",
                 nb_removed,
@@ -80,54 +189,186 @@ impl TimerWheel {
     pub(super) fn remove_inactive<T: Trackable>(
         &mut self,
         now: Instant,
-        table: &mut LinkedHashMap<ConnId, Conn<T>>,
+        table: &mut ConnTable<Conn<T>>,
         subscription: &Subscription<T::Subscribed>,
+        pcap: &mut PcapWriter,
     ) -> usize {
-        let period = self.period;
-        let nb_buckets = self.timers.len();
-        let mut not_expired: Vec<(usize, ConnId)> = vec![];
-        let check_time = (now - self.start_ts).as_millis() as usize / period * period;
+        let last_tick = (now - self.start_ts).as_millis() as usize / self.period;
+        log::debug!("next tick: {}, last: {}", self.next_tick, last_tick);
 
         let mut cnt_exp = 0;
-        let last_expire_bucket = check_time / period;
-        log::debug!(
-            "check time: {}, next: {}, last: {}",
-            check_time,
-            self.next_bucket,
-            last_expire_bucket
-        );
-
-        for expire_bucket in self.next_bucket..last_expire_bucket {
+        while let Some(conn_id) = self.next_expired(last_tick, table) {
+            let hash = table.hasher().hash(&conn_id);
+            if let TableEntry::Occupied(mut occupied) = table.entry(hash, &conn_id) {
+                cnt_exp += 1;
+                let conn = occupied.get_mut();
+                let reason = conn.terminated().unwrap_or(TerminationReason::Timeout);
+                conn.terminate(subscription, pcap, reason);
+                occupied.remove();
+            }
+        }
+        cnt_exp
+    }
+
+    /// Advances the wheel up to `last_tick` (excluded), and returns the next connection whose
+    /// timer expired, if any. Connections found active since they were scheduled are re-inserted.
+    fn next_expired(&mut self, last_tick: usize, table: &impl Timers) -> Option<ConnId> {
+        loop {
+            while let Some(conn_id) = self.due.pop_front() {
+                let (last_seen_ts, inactivity_window) = match table.timer(&conn_id) {
+                    Some(timer) => timer,
+                    None => continue,
+                };
+                let expire_tick = self.expire_tick(last_seen_ts, inactivity_window);
+                if expire_tick <= self.due_tick {
+                    return Some(conn_id);
+                }
+                self.schedule(conn_id, expire_tick);
+            }
+            if self.next_tick >= last_tick {
+                return None;
+            }
+            let tick = self.next_tick;
+            let index = tick & (LEVEL_SIZE - 1);
+            if index == 0 {
+                self.cascade(tick, table);
+            }
+            self.next_tick += 1;
+            // The emptied list takes the place of the bucket, keeping its capacity
+            std::mem::swap(&mut self.due, &mut self.levels[0][index]);
+            self.due_tick = tick;
             log::debug!(
-                "bucket: {}, index: {}",
-                expire_bucket,
-                expire_bucket % nb_buckets
+                "tick: {}, index: {}, timers: {}",
+                tick,
+                index,
+                self.due.len()
             );
-            let list = &mut self.timers[expire_bucket % nb_buckets];
-
-            for conn_id in list.drain(..) {
-                if let RawEntryMut::Occupied(mut occupied) =
-                    table.raw_entry_mut().from_key(&conn_id)
-                {
-                    let conn = occupied.get_mut();
-                    let last_seen_time = (conn.last_seen_ts - self.start_ts).as_millis() as usize;
-                    log::debug!("Last seen time: {}", last_seen_time);
-                    let expire_time = last_seen_time + conn.inactivity_window;
-                    if expire_time < check_time {
-                        cnt_exp += 1;
-                        conn.terminate(subscription);
-                        occupied.remove();
-                    } else {
-                        let timer_index = (expire_time / period) % nb_buckets;
-                        not_expired.push((timer_index, conn_id));
-                    }
+        }
+    }
+
+    /// Moves the timers of the higher level buckets that come due at `tick` down the wheel.
+    fn cascade(&mut self, tick: usize, table: &impl Timers) {
+        for level in 1..self.levels.len() {
+            let index = (tick >> (level * LEVEL_BITS)) & (LEVEL_SIZE - 1);
+            let list = std::mem::take(&mut self.levels[level][index]);
+            for conn_id in list {
+                if let Some((last_seen_ts, inactivity_window)) = table.timer(&conn_id) {
+                    let expire_tick = self.expire_tick(last_seen_ts, inactivity_window);
+                    self.schedule(conn_id, expire_tick);
                 }
             }
-            for (timer_index, conn_id) in not_expired.drain(..) {
-                self.timers[timer_index].push_back(conn_id);
+            if index != 0 {
+                break;
             }
         }
-        self.next_bucket = last_expire_bucket;
-        cnt_exp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+
+    impl Timers for HashMap<ConnId, (Instant, usize)> {
+        fn timer(&self, conn_id: &ConnId) -> Option<(Instant, usize)> {
+            self.get(conn_id).copied()
+        }
+    }
+
+    fn conn_id(port: u16) -> ConnId {
+        let src: SocketAddr = ([10, 0, 0, 1], port).into();
+        let dst: SocketAddr = ([10, 0, 0, 2], 80).into();
+        ConnId::new(src, dst, 6)
+    }
+
+    /// Schedules `conn_id` to expire `timeout` ticks after the start of the wheel.
+    fn schedule(
+        wheel: &mut TimerWheel,
+        timers: &mut HashMap<ConnId, (Instant, usize)>,
+        conn_id: &ConnId,
+        timeout: usize,
+    ) {
+        timers.insert(conn_id.clone(), (wheel.start_ts, timeout));
+        wheel.insert(conn_id, wheel.start_ts, timeout);
+    }
+
+    fn contains(bucket: &VecDeque<ConnId>, conn_id: &ConnId) -> bool {
+        bucket.iter().any(|c| c == conn_id)
+    }
+
+    #[test]
+    fn core_timerwheel_levels() {
+        // 1ms ticks, 4 levels
+        let mut wheel = TimerWheel::new(300_000, 1);
+        assert_eq!(wheel.levels.len(), 4);
+        let mut timers = HashMap::new();
+        let (a, b, c, d) = (conn_id(1), conn_id(2), conn_id(3), conn_id(4));
+        schedule(&mut wheel, &mut timers, &a, 10);
+        schedule(&mut wheel, &mut timers, &b, 100);
+        schedule(&mut wheel, &mut timers, &c, 5000);
+        schedule(&mut wheel, &mut timers, &d, 30);
+        assert!(contains(&wheel.levels[0][10], &a));
+        assert!(contains(&wheel.levels[1][1], &b));
+        assert!(contains(&wheel.levels[2][1], &c));
+
+        // `d` was active since it was scheduled, and is rescheduled when its bucket comes due
+        timers.insert(d.clone(), (wheel.start_ts + Duration::from_millis(50), 30));
+        assert_eq!(wheel.next_expired(10, &timers), None);
+        assert_eq!(wheel.next_expired(11, &timers), Some(a));
+        assert_eq!(wheel.next_expired(64, &timers), None);
+        assert!(contains(&wheel.levels[0][80 & 63], &d));
+
+        // Level 1 is cascaded at tick 64
+        assert_eq!(wheel.next_expired(65, &timers), None);
+        assert!(contains(&wheel.levels[0][100 & 63], &b));
+        assert_eq!(wheel.next_expired(81, &timers), Some(d));
+        assert_eq!(wheel.next_expired(100, &timers), None);
+        assert_eq!(wheel.next_expired(101, &timers), Some(b));
+
+        // Level 2 is cascaded at tick 4096, then level 1 at tick 4992
+        assert_eq!(wheel.next_expired(4097, &timers), None);
+        assert!(contains(&wheel.levels[1][(5000 >> 6) & 63], &c));
+        assert_eq!(wheel.next_expired(4993, &timers), None);
+        assert!(contains(&wheel.levels[0][5000 & 63], &c));
+        assert_eq!(wheel.next_expired(5000, &timers), None);
+        assert_eq!(wheel.next_expired(5001, &timers), Some(c));
+        assert_eq!(wheel.next_expired(100_000, &timers), None);
+    }
+
+    #[test]
+    fn core_timerwheel_beyond_max_timeout() {
+        // 1ms ticks, 2 levels spanning 4096 ticks
+        let mut wheel = TimerWheel::new(100, 1);
+        assert_eq!(wheel.levels.len(), 2);
+        let mut timers = HashMap::new();
+        let a = conn_id(1);
+        schedule(&mut wheel, &mut timers, &a, 10_000);
+        // Parked in the furthest bucket of the top level
+        assert!(contains(&wheel.levels[1][63], &a));
+        assert_eq!(wheel.next_expired(5000, &timers), None);
+        assert_eq!(wheel.next_expired(10_000, &timers), None);
+        assert_eq!(wheel.next_expired(10_001, &timers), Some(a));
+    }
+
+    #[test]
+    fn core_timerwheel_earliest() {
+        let mut wheel = TimerWheel::new(300_000, 1);
+        let mut timers = HashMap::new();
+        let (a, b, c, d) = (conn_id(1), conn_id(2), conn_id(3), conn_id(4));
+        schedule(&mut wheel, &mut timers, &a, 5000);
+        schedule(&mut wheel, &mut timers, &b, 300);
+        schedule(&mut wheel, &mut timers, &c, 200);
+        assert_eq!(wheel.earliest(&timers), Some(c.clone()));
+        timers.remove(&c);
+        schedule(&mut wheel, &mut timers, &d, 30);
+        assert_eq!(wheel.earliest(&timers), Some(d.clone()));
+        timers.remove(&d);
+        // Picked from level 1, then level 2
+        assert_eq!(wheel.earliest(&timers), Some(b.clone()));
+        timers.remove(&b);
+        assert_eq!(wheel.earliest(&timers), Some(a.clone()));
+        timers.remove(&a);
+        assert_eq!(wheel.earliest(&timers), None);
     }
 }
@@ -0,0 +1,751 @@
+//! VXLAN, GRE, ERSPAN, GENEVE, GTP-U, IP-in-IP, Teredo, ISATAP, MPLS, and PPPoE decapsulation.
+//!
+//! Tunneled packets are decapsulated before the packet filter and the connection tracker, so that
+//! subscriptions apply to the inner packet (see [VxlanConfig](crate::config::VxlanConfig),
+//! [GreConfig](crate::config::GreConfig), [GeneveConfig](crate::config::GeneveConfig),
+//! [GtpuConfig](crate::config::GtpuConfig), [IpipConfig](crate::config::IpipConfig),
+//! [TeredoConfig](crate::config::TeredoConfig), [MplsConfig](crate::config::MplsConfig), and
+//! [PppoeConfig](crate::config::PppoeConfig)). Nested tunnels are decapsulated one layer at a
+//! time, up to [max_tunnel_depth](crate::config::ConnTrackConfig::max_tunnel_depth) layers. The
+//! tunnels the packet was received on are kept as [Tunnels](Tunnels) metadata in the
+//! [L4Context](crate::conntrack::pdu::L4Context) of the inner packet.
+
+use crate::config::{ConnTrackConfig, GeneveOptionConfig};
+use crate::conntrack::conn_id::FiveTuple;
+use crate::conntrack::pdu::L4Context;
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::erspan::Erspan;
+use crate::protocols::packet::ethernet::Ethernet;
+use crate::protocols::packet::geneve::{Geneve, GeneveOption, GeneveOptions};
+use crate::protocols::packet::gre::{Gre, GRE_PROTOCOL, GRE_TEB};
+use crate::protocols::packet::gtpu::Gtpu;
+use crate::protocols::packet::ipv4::{Ipv4, IPV4_PROTOCOL};
+use crate::protocols::packet::ipv6::{Ipv6, IPV6_PROTOCOL};
+use crate::protocols::packet::mpls::{LabelEntry, Mpls};
+use crate::protocols::packet::pppoe::Pppoe;
+use crate::protocols::packet::teredo::Teredo;
+use crate::protocols::packet::udp::{Udp, UDP_PROTOCOL};
+use crate::protocols::packet::vxlan::Vxlan;
+use crate::protocols::packet::{Packet, PacketParseError};
+
+use std::cell::Cell;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV4};
+
+use anyhow::{bail, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+
+/// Length of the Ethernet header given to the IP packets carried by tunnels.
+const ETHER_HEADER_LEN: usize = 14;
+const ETHER_ADDRS_LEN: usize = 12;
+
+/// Maximum length of the GENEVE option TLVs kept per packet, in bytes.
+pub const GENEVE_MAX_OPTIONS_LEN: usize = 64;
+
+/// Maximum number of MPLS labels kept per packet. Deeper label stacks are decapsulated, but only
+/// their top labels and their bottom label are kept.
+pub const MPLS_MAX_LABELS: usize = 8;
+
+/// Maximum number of nested tunnels a packet is decapsulated from.
+pub const MAX_TUNNEL_DEPTH: usize = 4;
+
+/// IP protocol number of IPv4 encapsulated in IP.
+const IPIP_PROTOCOL: usize = 4;
+/// IP protocol number of IPv6 encapsulated in IP.
+const IPV6_ENCAP_PROTOCOL: usize = 41;
+
+/// Encapsulation protocol of a tunnel, with its identifiers.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[serde(rename_all = "snake_case")]
+pub enum Encap {
+    /// VXLAN, with its VXLAN Network Identifier.
+    Vxlan { vni: u32 },
+    /// GRE, with its key if present.
+    Gre { key: Option<u32> },
+    /// ERSPAN over GRE, with its version (`1` for Type II, `2` for Type III) and the identifier
+    /// of the mirror session.
+    Erspan { version: u8, session_id: u16 },
+    /// GENEVE, with its Virtual Network Identifier and the selected option TLVs.
+    Geneve { vni: u32, options: GeneveTlvs },
+    /// GTP-U, with its Tunnel Endpoint Identifier.
+    Gtpu { teid: u32 },
+    /// IPv4 or IPv6 encapsulated directly in IP. The protocol of the outer 5-tuple tells the
+    /// version of the inner packet (`4` for IPv4, `41` for IPv6).
+    IpInIp,
+    /// Teredo, with the mapped address of the client from the origin indication, if present.
+    Teredo { origin: Option<SocketAddrV4> },
+    /// ISATAP: IPv6 encapsulated in IPv4, between ISATAP addresses.
+    Isatap,
+    /// MPLS, with its label stack.
+    Mpls { labels: MplsLabels },
+    /// PPPoE, with its session ID.
+    Pppoe { session_id: u16 },
+}
+
+/// GENEVE option TLVs kept as tunnel metadata (see [GeneveConfig](crate::config::GeneveConfig)).
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub struct GeneveTlvs {
+    /// Options, encoded as on the wire.
+    data: [u8; GENEVE_MAX_OPTIONS_LEN],
+    len: usize,
+}
+
+impl GeneveTlvs {
+    /// Returns an iterator over the options.
+    pub fn iter(&self) -> GeneveOptions<'_> {
+        GeneveOptions::new(&self.data[..self.len])
+    }
+
+    /// Appends `option`. Returns `false` if it does not fit.
+    fn push(&mut self, option: &GeneveOption) -> bool {
+        let end = self.len + option.len();
+        if end > GENEVE_MAX_OPTIONS_LEN {
+            return false;
+        }
+        let [class_hi, class_lo] = option.class.to_be_bytes();
+        let nb_words = (option.data.len() / 4) as u8;
+        self.data[self.len..self.len + 4].copy_from_slice(&[
+            class_hi,
+            class_lo,
+            option.option_type,
+            nb_words,
+        ]);
+        self.data[self.len + 4..end].copy_from_slice(option.data);
+        self.len = end;
+        true
+    }
+}
+
+impl Default for GeneveTlvs {
+    fn default() -> Self {
+        GeneveTlvs {
+            data: [0; GENEVE_MAX_OPTIONS_LEN],
+            len: 0,
+        }
+    }
+}
+
+impl Serialize for GeneveTlvs {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for GeneveTlvs {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct OwnedOption {
+            class: u16,
+            option_type: u8,
+            data: Vec<u8>,
+        }
+
+        let mut options = GeneveTlvs::default();
+        for option in Vec::<OwnedOption>::deserialize(deserializer)? {
+            let option = GeneveOption {
+                class: option.class,
+                option_type: option.option_type,
+                data: &option.data,
+            };
+            if option.data.len() % 4 != 0 || !options.push(&option) {
+                return Err(serde::de::Error::custom("invalid GENEVE options"));
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// MPLS labels kept as tunnel metadata (see [MplsConfig](crate::config::MplsConfig)).
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub struct MplsLabels {
+    labels: [u32; MPLS_MAX_LABELS],
+    depth: usize,
+    bottom: u32,
+}
+
+impl MplsLabels {
+    /// Returns the labels, from the top to the bottom of the stack. Only the first
+    /// [MPLS_MAX_LABELS](MPLS_MAX_LABELS) labels are kept.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.labels[..self.depth.min(MPLS_MAX_LABELS)]
+            .iter()
+            .copied()
+    }
+
+    /// Returns the number of labels in the stack, including those not kept.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the top label.
+    pub fn top(&self) -> u32 {
+        self.labels[0]
+    }
+
+    /// Returns the bottom label.
+    pub fn bottom(&self) -> u32 {
+        self.bottom
+    }
+
+    fn push(&mut self, entry: LabelEntry) {
+        if self.depth < MPLS_MAX_LABELS {
+            self.labels[self.depth] = entry.label;
+        }
+        self.depth += 1;
+        self.bottom = entry.label;
+    }
+}
+
+impl Default for MplsLabels {
+    fn default() -> Self {
+        MplsLabels {
+            labels: [0; MPLS_MAX_LABELS],
+            depth: 0,
+            bottom: 0,
+        }
+    }
+}
+
+impl Serialize for MplsLabels {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for MplsLabels {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let stack = Vec::<u32>::deserialize(deserializer)?;
+        let mut labels = MplsLabels::default();
+        for (slot, label) in labels.labels.iter_mut().zip(stack.iter()) {
+            *slot = *label;
+        }
+        labels.depth = stack.len();
+        labels.bottom = stack.last().copied().unwrap_or_default();
+        Ok(labels)
+    }
+}
+
+/// Metadata of the tunnel a packet was decapsulated from.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct Tunnel {
+    /// Encapsulation protocol.
+    pub encap: Encap,
+    /// Outer addresses, from the sending to the receiving tunnel endpoint. For GRE, ERSPAN, and
+    /// IP-in-IP, the ports are `0` and the protocol is that of the outer IP header. `None` for
+    /// MPLS and PPPoE, which have no outer IP header.
+    pub outer: Option<FiveTuple>,
+}
+
+impl Tunnel {
+    /// Returns the virtual network identifier, if the packet was received over VXLAN or GENEVE.
+    pub fn vni(&self) -> Option<u32> {
+        match self.encap {
+            Encap::Vxlan { vni } | Encap::Geneve { vni, .. } => Some(vni),
+            _ => None,
+        }
+    }
+
+    /// Returns the MPLS labels, if the packet was received over MPLS.
+    pub fn mpls_labels(&self) -> Option<&MplsLabels> {
+        match &self.encap {
+            Encap::Mpls { labels } => Some(labels),
+            _ => None,
+        }
+    }
+}
+
+/// Tunnels a packet was decapsulated from, from the outermost to the innermost.
+#[derive(Debug, Copy, Clone, Default, Hash, Eq, PartialEq)]
+pub struct Tunnels {
+    layers: [Option<Tunnel>; MAX_TUNNEL_DEPTH],
+}
+
+impl Tunnels {
+    /// Returns an iterator over the tunnels, from the outermost to the innermost.
+    pub fn iter(&self) -> impl Iterator<Item = &Tunnel> + '_ {
+        self.layers.iter().map_while(|layer| layer.as_ref())
+    }
+
+    /// Returns the number of nested tunnels.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns `true` if the packet was not tunneled.
+    pub fn is_empty(&self) -> bool {
+        self.layers[0].is_none()
+    }
+
+    /// Returns the outermost tunnel, if any.
+    pub fn outermost(&self) -> Option<&Tunnel> {
+        self.layers[0].as_ref()
+    }
+
+    /// Returns the innermost tunnel, if any.
+    pub fn innermost(&self) -> Option<&Tunnel> {
+        self.iter().last()
+    }
+
+    /// Appends an inner tunnel. Returns `false` if the maximum depth is reached.
+    fn push(&mut self, tunnel: Tunnel) -> bool {
+        match self.layers.iter_mut().find(|layer| layer.is_none()) {
+            Some(layer) => {
+                *layer = Some(tunnel);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Serialize for Tunnels {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Tunnels {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut tunnels = Tunnels::default();
+        for tunnel in Vec::<Tunnel>::deserialize(deserializer)? {
+            if !tunnels.push(tunnel) {
+                return Err(serde::de::Error::custom("too many nested tunnels"));
+            }
+        }
+        Ok(tunnels)
+    }
+}
+
+thread_local! {
+    /// Tunnels of the packet being processed on the current core.
+    static TUNNELS: Cell<Tunnels> = const {
+        Cell::new(Tunnels {
+            layers: [None; MAX_TUNNEL_DEPTH],
+        })
+    };
+}
+
+/// Returns the tunnels of the packet being processed, and clears them.
+pub(crate) fn take() -> Tunnels {
+    TUNNELS.with(|t| t.take())
+}
+
+/// Location of the inner packet of a tunnel.
+#[derive(Debug, Clone, Copy)]
+enum Payload {
+    /// An Ethernet frame at the offset.
+    Ethernet(usize),
+    /// An IP packet at `offset`, identified by its EtherType.
+    Ip { offset: usize, ether_type: u16 },
+}
+
+/// Decapsulates the tunneled packets received by a core.
+#[derive(Debug)]
+pub(crate) struct Decap {
+    /// UDP ports of VXLAN, empty if VXLAN decapsulation is disabled.
+    vxlan_ports: Vec<u16>,
+    gre: bool,
+    /// UDP ports of GENEVE, empty if GENEVE decapsulation is disabled.
+    geneve_ports: Vec<u16>,
+    /// GENEVE options kept as tunnel metadata.
+    geneve_options: Vec<GeneveOptionConfig>,
+    /// UDP ports of GTP-U, empty if GTP-U decapsulation is disabled.
+    gtpu_ports: Vec<u16>,
+    ipip: bool,
+    /// UDP ports of Teredo, empty if Teredo decapsulation is disabled.
+    teredo_ports: Vec<u16>,
+    /// Whether ISATAP decapsulation is enabled.
+    isatap: bool,
+    mpls: bool,
+    pppoe: bool,
+    /// Maximum number of nested tunnels decapsulated.
+    max_depth: usize,
+    nb_decapsulated: u64,
+}
+
+impl Decap {
+    /// Returns a decapsulator, or `None` if decapsulation is disabled.
+    pub(crate) fn new(config: &ConnTrackConfig) -> Option<Self> {
+        if !config.vxlan.enabled
+            && !config.gre.enabled
+            && !config.geneve.enabled
+            && !config.gtpu.enabled
+            && !config.ipip.enabled
+            && !config.teredo.enabled
+            && !config.teredo.isatap
+            && !config.mpls.enabled
+            && !config.pppoe.enabled
+        {
+            return None;
+        }
+        Some(Decap {
+            vxlan_ports: match config.vxlan.enabled {
+                true => config.vxlan.ports.clone(),
+                false => vec![],
+            },
+            gre: config.gre.enabled,
+            geneve_ports: match config.geneve.enabled {
+                true => config.geneve.ports.clone(),
+                false => vec![],
+            },
+            geneve_options: config.geneve.options.clone(),
+            gtpu_ports: match config.gtpu.enabled {
+                true => config.gtpu.ports.clone(),
+                false => vec![],
+            },
+            ipip: config.ipip.enabled,
+            teredo_ports: match config.teredo.enabled {
+                true => config.teredo.ports.clone(),
+                false => vec![],
+            },
+            isatap: config.teredo.isatap,
+            mpls: config.mpls.enabled,
+            pppoe: config.pppoe.enabled,
+            max_depth: config.max_tunnel_depth.clamp(1, MAX_TUNNEL_DEPTH),
+            nb_decapsulated: 0,
+        })
+    }
+
+    /// Returns the innermost packet of `mbuf` if it is tunneled, and `mbuf` otherwise. The tunnels
+    /// are recorded for the packet being processed.
+    pub(crate) fn push(&mut self, mut mbuf: Mbuf) -> Mbuf {
+        let tunnels = match self.parse(&mbuf) {
+            Some((tunnels, payload)) if decapsulate(&mut mbuf, payload).is_ok() => {
+                self.nb_decapsulated += 1;
+                tunnels
+            }
+            _ => Tunnels::default(),
+        };
+        TUNNELS.with(|t| t.set(tunnels));
+        mbuf
+    }
+
+    /// Returns the context of the innermost packet of `mbuf` if it is tunneled, and of `mbuf`
+    /// otherwise, without decapsulating it.
+    pub(crate) fn flow_ctxt(&self, mbuf: &Mbuf) -> Result<L4Context> {
+        match self.parse(mbuf) {
+            Some((_, Payload::Ethernet(offset))) => {
+                let inner = Inner {
+                    mbuf,
+                    offset,
+                    ether_type: None,
+                };
+                let eth = inner.parse_to::<Ethernet>()?;
+                L4Context::from_link(&eth)
+            }
+            Some((_, Payload::Ip { offset, ether_type })) => L4Context::from_link(&Inner {
+                mbuf,
+                offset,
+                ether_type: Some(ether_type.into()),
+            }),
+            None => L4Context::new(mbuf),
+        }
+    }
+
+    /// Returns the number of packets decapsulated.
+    pub(crate) fn nb_decapsulated(&self) -> u64 {
+        self.nb_decapsulated
+    }
+
+    /// Returns the tunnels of `mbuf` and the location of its innermost packet, or `None` if `mbuf`
+    /// is not tunneled.
+    fn parse(&self, mbuf: &Mbuf) -> Option<(Tunnels, Payload)> {
+        let mut tunnels = Tunnels::default();
+        let mut payload = Payload::Ethernet(0);
+        // Each layer is parsed from the payload of the previous one
+        for _ in 0..self.max_depth {
+            match self.parse_layer(mbuf, payload) {
+                Some((tunnel, inner)) => {
+                    tunnels.push(tunnel);
+                    payload = inner;
+                }
+                None => break,
+            }
+        }
+        match tunnels.is_empty() {
+            true => None,
+            false => Some((tunnels, payload)),
+        }
+    }
+
+    /// Returns the tunnel of the packet at `at` and the location of its inner packet, or `None`
+    /// if it is not tunneled.
+    fn parse_layer(&self, mbuf: &Mbuf, at: Payload) -> Option<(Tunnel, Payload)> {
+        match at {
+            Payload::Ethernet(offset) => {
+                let inner = Inner {
+                    mbuf,
+                    offset,
+                    ether_type: None,
+                };
+                let eth = inner.parse_to::<Ethernet>().ok()?;
+                self.parse_link(&eth)
+            }
+            Payload::Ip { offset, ether_type } => self.parse_link(&Inner {
+                mbuf,
+                offset,
+                ether_type: Some(ether_type.into()),
+            }),
+        }
+    }
+
+    /// Returns the tunnel of the packet carried by `link`, an Ethernet frame or the payload of a
+    /// tunnel, and the location of its inner packet.
+    fn parse_link<'a>(&self, link: &'a impl Packet<'a>) -> Option<(Tunnel, Payload)> {
+        if self.mpls {
+            if let Ok(mpls) = link.parse_to::<Mpls>() {
+                let mut labels = MplsLabels::default();
+                mpls.labels().for_each(|entry| labels.push(entry));
+                let encap = Encap::Mpls { labels };
+                let payload = payload(&mpls, mpls.next_header()? as u16)?;
+                return Some((Tunnel { encap, outer: None }, payload));
+            }
+        }
+        if self.pppoe {
+            if let Ok(pppoe) = link.parse_to::<Pppoe>() {
+                let encap = Encap::Pppoe {
+                    session_id: pppoe.session_id(),
+                };
+                let payload = payload(&pppoe, pppoe.next_header()? as u16)?;
+                return Some((Tunnel { encap, outer: None }, payload));
+            }
+        }
+        if let Ok(ipv4) = link.parse_to::<Ipv4>() {
+            let src = IpAddr::V4(ipv4.src_addr());
+            let dst = IpAddr::V4(ipv4.dst_addr());
+            self.parse_ip(&ipv4, src, dst)
+        } else {
+            let ipv6 = link.parse_to::<Ipv6>().ok()?;
+            let src = IpAddr::V6(ipv6.src_addr());
+            let dst = IpAddr::V6(ipv6.dst_addr());
+            self.parse_ip(&ipv6, src, dst)
+        }
+    }
+
+    fn parse_ip<'a>(
+        &self,
+        ip: &'a impl Packet<'a>,
+        src: IpAddr,
+        dst: IpAddr,
+    ) -> Option<(Tunnel, Payload)> {
+        if let Ok(udp) = ip.parse_to::<Udp>() {
+            let outer = Some(FiveTuple {
+                orig: SocketAddr::new(src, udp.src_port()),
+                resp: SocketAddr::new(dst, udp.dst_port()),
+                proto: UDP_PROTOCOL,
+            });
+            if self.vxlan_ports.contains(&udp.dst_port()) {
+                let vxlan = udp.parse_to::<Vxlan>().ok()?;
+                let encap = Encap::Vxlan { vni: vxlan.vni() };
+                let payload = Payload::Ethernet(vxlan.next_header_offset());
+                return Some((Tunnel { encap, outer }, payload));
+            }
+            if self.geneve_ports.contains(&udp.dst_port()) {
+                let geneve = udp.parse_to::<Geneve>().ok()?;
+                let mut options = GeneveTlvs::default();
+                for option in geneve.options() {
+                    let selected = self
+                        .geneve_options
+                        .iter()
+                        .any(|config| config.matches(option.class, option.option_type));
+                    if selected && !options.push(&option) {
+                        break;
+                    }
+                }
+                let encap = Encap::Geneve {
+                    vni: geneve.vni(),
+                    options,
+                };
+                let payload = payload(&geneve, geneve.protocol_type())?;
+                return Some((Tunnel { encap, outer }, payload));
+            }
+            if self.gtpu_ports.contains(&udp.dst_port()) {
+                let gtpu = udp.parse_to::<Gtpu>().ok()?;
+                let encap = Encap::Gtpu { teid: gtpu.teid() };
+                let payload = payload(&gtpu, gtpu.next_header()? as u16)?;
+                return Some((Tunnel { encap, outer }, payload));
+            }
+            // Teredo servers and relays use the Teredo port, clients any port
+            if self.teredo_ports.contains(&udp.dst_port())
+                || self.teredo_ports.contains(&udp.src_port())
+            {
+                let teredo = udp.parse_to::<Teredo>().ok()?;
+                let encap = Encap::Teredo {
+                    origin: teredo.origin(),
+                };
+                let payload = payload(&teredo, teredo.next_header()? as u16)?;
+                return Some((Tunnel { encap, outer }, payload));
+            }
+            return None;
+        }
+        let protocol = ip.next_header()?;
+        let outer = Some(FiveTuple {
+            orig: SocketAddr::new(src, 0),
+            resp: SocketAddr::new(dst, 0),
+            proto: protocol,
+        });
+        if protocol == IPIP_PROTOCOL || protocol == IPV6_ENCAP_PROTOCOL {
+            let ether_type = match protocol {
+                IPIP_PROTOCOL => IPV4_PROTOCOL,
+                _ => IPV6_PROTOCOL,
+            };
+            let inner = Inner {
+                mbuf: ip.mbuf(),
+                offset: ip.next_header_offset(),
+                ether_type: Some(ether_type),
+            };
+            let encap = if self.isatap && protocol == IPV6_ENCAP_PROTOCOL && is_isatap(&inner) {
+                Encap::Isatap
+            } else if self.ipip {
+                Encap::IpInIp
+            } else {
+                return None;
+            };
+            let payload = payload(&inner, ether_type as u16)?;
+            return Some((Tunnel { encap, outer }, payload));
+        }
+        if !self.gre || protocol != GRE_PROTOCOL {
+            return None;
+        }
+        let gre = ip.parse_to::<Gre>().ok()?;
+        if let Ok(erspan) = gre.parse_to::<Erspan>() {
+            let encap = Encap::Erspan {
+                version: erspan.version(),
+                session_id: erspan.session_id(),
+            };
+            let payload = Payload::Ethernet(erspan.next_header_offset());
+            return Some((Tunnel { encap, outer }, payload));
+        }
+        let encap = Encap::Gre { key: gre.key() };
+        let payload = payload(&gre, gre.protocol_type())?;
+        Some((Tunnel { encap, outer }, payload))
+    }
+}
+
+/// Returns the location of the payload of `link`, a tunnel header whose payload is identified by
+/// `ether_type`, or `None` if it is neither an Ethernet frame nor an IP packet.
+fn payload<'a>(link: &'a impl Packet<'a>, ether_type: u16) -> Option<Payload> {
+    if ether_type == GRE_TEB {
+        Some(Payload::Ethernet(link.next_header_offset()))
+    } else if link.parse_to::<Ipv4>().is_ok() || link.parse_to::<Ipv6>().is_ok() {
+        Some(Payload::Ip {
+            offset: link.next_header_offset(),
+            ether_type,
+        })
+    } else {
+        None
+    }
+}
+
+/// Returns `true` if the IPv6 packet carried by `inner` is sent from or to an ISATAP address,
+/// whose interface identifier is `0000:5efe` or `0200:5efe` followed by an IPv4 address (RFC 5214).
+fn is_isatap(inner: &Inner) -> bool {
+    fn isatap_address(addr: Ipv6Addr) -> bool {
+        let octets = addr.octets();
+        octets[8] & !0x02 == 0 && octets[9..12] == [0x00, 0x5e, 0xfe]
+    }
+    match inner.parse_to::<Ipv6>() {
+        Ok(ipv6) => isatap_address(ipv6.src_addr()) || isatap_address(ipv6.dst_addr()),
+        Err(_) => false,
+    }
+}
+
+/// Removes the outer headers of `mbuf`, up to `payload`. IP packets are given an Ethernet header
+/// with the addresses of the outer frame.
+fn decapsulate(mbuf: &mut Mbuf, payload: Payload) -> Result<()> {
+    match payload {
+        Payload::Ethernet(offset) => mbuf.strip(offset),
+        Payload::Ip { offset, ether_type } => {
+            let mut header = [0; ETHER_HEADER_LEN];
+            header[..ETHER_ADDRS_LEN].copy_from_slice(mbuf.get_data_slice(0, ETHER_ADDRS_LEN)?);
+            header[ETHER_ADDRS_LEN..].copy_from_slice(&ether_type.to_be_bytes());
+            mbuf.strip(offset - ETHER_HEADER_LEN)?;
+            mbuf.write_data(0, &header)
+        }
+    }
+}
+
+/// Inner packet of a tunnel, located in the packet buffer of the outer packet.
+struct Inner<'a> {
+    mbuf: &'a Mbuf,
+    offset: usize,
+    /// EtherType of the inner packet, if it is not an Ethernet frame.
+    ether_type: Option<usize>,
+}
+
+impl<'a> Packet<'a> for Inner<'a> {
+    fn mbuf(&self) -> &Mbuf {
+        self.mbuf
+    }
+
+    fn header_len(&self) -> usize {
+        0
+    }
+
+    fn next_header_offset(&self) -> usize {
+        self.offset
+    }
+
+    fn next_header(&self) -> Option<usize> {
+        self.ether_type
+    }
+
+    fn parse_from(_outer: &'a impl Packet<'a>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        bail!(PacketParseError::InvalidProtocol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_tunnel_stack() {
+        let mut tunnels = Tunnels::default();
+        assert!(tunnels.is_empty());
+        for key in 0..MAX_TUNNEL_DEPTH as u32 {
+            let encap = Encap::Gre { key: Some(key) };
+            assert!(tunnels.push(Tunnel { encap, outer: None }));
+        }
+        let encap = Encap::Pppoe { session_id: 1 };
+        assert!(!tunnels.push(Tunnel { encap, outer: None }));
+        assert_eq!(tunnels.len(), MAX_TUNNEL_DEPTH);
+        assert_eq!(
+            tunnels.outermost().unwrap().encap,
+            Encap::Gre { key: Some(0) }
+        );
+        assert_eq!(
+            tunnels.innermost().unwrap().encap,
+            Encap::Gre {
+                key: Some(MAX_TUNNEL_DEPTH as u32 - 1)
+            }
+        );
+    }
+}
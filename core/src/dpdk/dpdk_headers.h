@@ -10,3 +10,6 @@
 #include <rte_mbuf.h>
 #include <rte_flow.h>
 #include <rte_ring.h>
+#include <rte_malloc.h>
+#include <rte_telemetry.h>
+#include <rte_interrupts.h>
@@ -4,6 +4,7 @@
 #include <rte_lcore.h>
 #include <rte_ring.h>
 #include <rte_cycles.h>
+#include <rte_pause.h>
 
 void rte_pktmbuf_free_(struct rte_mbuf *packet) {
     rte_pktmbuf_free(packet);
@@ -21,6 +22,10 @@ uint16_t rte_eth_rx_burst_(uint16_t port_id, uint16_t queue_id, struct rte_mbuf
     return rte_eth_rx_burst(port_id, queue_id, rx_pkts, nb_pkts);
 }
 
+int rte_eth_rx_queue_count_(uint16_t port_id, uint16_t queue_id) {
+    return rte_eth_rx_queue_count(port_id, queue_id);
+}
+
 uint16_t rte_mbuf_refcnt_read_(const struct rte_mbuf* m) {
     return rte_mbuf_refcnt_read(m);
 }
@@ -45,6 +50,10 @@ uint64_t rte_rdtsc_(void) {
     return rte_rdtsc();
 }
 
+void rte_pause_(void) {
+    rte_pause();
+}
+
 /* RTE_RING functions */
 
 int rte_ring_enqueue_(struct rte_ring* r, void* obj) {
@@ -94,3 +103,11 @@ unsigned rte_ring_get_size_(const struct rte_ring* r) {
 unsigned rte_ring_get_capacity_(const struct rte_ring* r) {
     return rte_ring_get_capacity(r);
 }
+
+unsigned rte_ring_mp_enqueue_burst_(struct rte_ring* r, void* const* obj_table, unsigned n, unsigned* free_space) {
+    return rte_ring_mp_enqueue_burst(r, obj_table, n, free_space);
+}
+
+unsigned rte_ring_sc_dequeue_burst_(struct rte_ring* r, void** obj_table, unsigned n, unsigned* available) {
+    return rte_ring_sc_dequeue_burst(r, obj_table, n, available);
+}
@@ -25,12 +25,14 @@ extern "C" {
         rx_pkts: *mut *mut rte_mbuf,
         nb_pkts: u16,
     ) -> u16;
+    fn rte_eth_rx_queue_count_(port_id: u16, queue_id: u16) -> c_int;
     fn rte_mbuf_refcnt_read_(m: *const rte_mbuf) -> u16;
     fn rte_mbuf_refcnt_update_(m: *mut rte_mbuf, value: i16) -> u16;
     fn rte_pktmbuf_adj_(packet: *mut rte_mbuf, len: u16) -> *mut c_char;
     fn rte_pktmbuf_trim_(packet: *mut rte_mbuf, len: u16) -> c_int;
     fn rte_lcore_id_() -> u16;
     fn rte_rdtsc_() -> u64;
+    fn rte_pause_();
     fn rte_ring_enqueue_(ring: *mut rte_ring, obj: *mut c_void) -> c_int;
     fn rte_ring_sp_enqueue_(ring: *mut rte_ring, obj: *mut c_void) -> c_int;
     fn rte_ring_mp_enqueue_(ring: *mut rte_ring, obj: *mut c_void) -> c_int;
@@ -43,6 +45,18 @@ extern "C" {
     fn rte_ring_empty_(ring: *const rte_ring) -> c_int;
     fn rte_ring_get_size_(ring: *const rte_ring) -> c_uint;
     fn rte_ring_get_capacity_(ring: *const rte_ring) -> c_uint;
+    fn rte_ring_mp_enqueue_burst_(
+        ring: *mut rte_ring,
+        obj_table: *const *mut c_void,
+        n: c_uint,
+        free_space: *mut c_uint,
+    ) -> c_uint;
+    fn rte_ring_sc_dequeue_burst_(
+        ring: *mut rte_ring,
+        obj_table: *mut *mut c_void,
+        n: c_uint,
+        available: *mut c_uint,
+    ) -> c_uint;
 }
 
 #[cfg(feature = "mlx5")]
@@ -95,6 +109,11 @@ pub unsafe fn rte_eth_rx_burst(
     rte_eth_rx_burst_(port_id, queue_id, rx_pkts, nb_pkts)
 }
 
+#[inline]
+pub unsafe fn rte_eth_rx_queue_count(port_id: u16, queue_id: u16) -> c_int {
+    rte_eth_rx_queue_count_(port_id, queue_id)
+}
+
 #[inline]
 pub unsafe fn rte_mbuf_refcnt_read(m: *const rte_mbuf) -> u16 {
     rte_mbuf_refcnt_read_(m)
@@ -140,6 +159,15 @@ pub unsafe fn rte_rdtsc() -> u64 {
     rte_rdtsc_()
 }
 
+/// Hints the CPU that the core is in a spin-wait loop.
+///
+/// ## Remarks
+/// This is `unsafe` because it calls the DPDK `rte_pause()` function via FFI.
+#[inline]
+pub unsafe fn rte_pause() {
+    rte_pause_()
+}
+
 /* RTE_RING functions */
 
 #[inline]
@@ -201,3 +229,23 @@ pub unsafe fn rte_ring_get_size(ring: *const rte_ring) -> c_uint {
 pub unsafe fn rte_ring_get_capacity(ring: *const rte_ring) -> c_uint {
     rte_ring_get_capacity_(ring)
 }
+
+#[inline]
+pub unsafe fn rte_ring_mp_enqueue_burst(
+    ring: *mut rte_ring,
+    obj_table: *const *mut c_void,
+    n: c_uint,
+    free_space: *mut c_uint,
+) -> c_uint {
+    rte_ring_mp_enqueue_burst_(ring, obj_table, n, free_space)
+}
+
+#[inline]
+pub unsafe fn rte_ring_sc_dequeue_burst(
+    ring: *mut rte_ring,
+    obj_table: *mut *mut c_void,
+    n: c_uint,
+    available: *mut c_uint,
+) -> c_uint {
+    rte_ring_sc_dequeue_burst_(ring, obj_table, n, available)
+}
@@ -1,4 +1,3 @@
-use bitmask_enum::bitmask;
 /// For each connectionn, the Retina framework applies multiple filtering stages as
 /// packets are received in order to determine (1) whether packets from that connection
 /// should continue to be processed and (2) what to do with these packets.
@@ -12,6 +11,8 @@ use bitmask_enum::bitmask;
 /// Each filter stage returns a set of actions and a set of terminal actions.
 /// The terminal actions are the subset of actions that are maintained through
 /// the next filter stage.
+use crate::lcore::verdict::Verdict;
+use bitmask_enum::bitmask;
 use std::fmt;
 
 #[bitmask]
@@ -53,6 +54,17 @@ pub enum ActionData {
 
     /// Deliver connection data (via the ConnectionDelivery filter) when it terminates
     ConnDeliver,
+
+    /// Write packets in this connection to the pcap writer (pre-reassembly)
+    /// Packets received before the connection matches are held until it does
+    PacketWrite,
+
+    /// Drop future packets in this connection in inline mode
+    PacketDrop,
+    /// Transmit future packets in this connection on the divert port in inline mode
+    PacketDivert,
+    /// Transmit a copy of future packets in this connection on the mirror port
+    PacketMirror,
 }
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
@@ -256,12 +268,62 @@ impl Actions {
          */
     }
 
+    /// True if the framework should write (or hold for writing) packets in this connection
+    #[inline]
+    pub(crate) fn packet_write(&self) -> bool {
+        self.data.intersects(ActionData::PacketWrite)
+    }
+
+    /// True if the connection matched a subscription that writes packets, i.e., packets
+    /// can be written immediately
+    #[inline]
+    pub(crate) fn packet_write_matched(&self) -> bool {
+        self.terminal_actions.intersects(ActionData::PacketWrite)
+    }
+
+    /// Verdict for packets in this connection in inline mode
+    #[inline]
+    pub(crate) fn verdict(&self) -> Verdict {
+        if self.terminal_actions.intersects(ActionData::PacketDrop) {
+            Verdict::Drop
+        } else if self.terminal_actions.intersects(ActionData::PacketDivert) {
+            Verdict::Divert
+        } else {
+            Verdict::Forward
+        }
+    }
+
+    /// True if packets in this connection should be mirrored
+    #[inline]
+    pub(crate) fn packet_mirror(&self) -> bool {
+        self.terminal_actions.intersects(ActionData::PacketMirror)
+    }
+
     /// True if the connection should be delivered at termination
     #[inline]
     pub(crate) fn connection_matched(&self) -> bool {
         self.terminal_actions.intersects(ActionData::ConnDeliver)
     }
 
+    /// Adds the actions that callbacks requested for the rest of the connection (see
+    /// [verdict](crate::lcore::verdict)). Only actions on the packets of the connection and its
+    /// delivery at termination can be added, as probing, parsing, and filtering depend on the
+    /// state of the filters; other actions are ignored.
+    pub fn escalate(&mut self, actions: ActionData) {
+        let actions = actions
+            & (ActionData::UpdatePDU
+                | ActionData::ReassembledUpdatePDU
+                | ActionData::PacketTrack
+                | ActionData::PacketDeliver
+                | ActionData::ConnDeliver
+                | ActionData::PacketWrite
+                | ActionData::PacketDrop
+                | ActionData::PacketDivert
+                | ActionData::PacketMirror);
+        self.data |= actions;
+        self.terminal_actions |= actions;
+    }
+
     /// Clear all actions
     #[inline]
     pub(crate) fn clear(&mut self) {
@@ -296,6 +358,10 @@ impl FromStr for ActionData {
             "ReassembledUpdatePDU" => Ok(ActionData::ReassembledUpdatePDU),
             "PacketTrack" => Ok(ActionData::PacketTrack),
             "ConnDeliver" => Ok(ActionData::ConnDeliver),
+            "PacketWrite" => Ok(ActionData::PacketWrite),
+            "PacketDrop" => Ok(ActionData::PacketDrop),
+            "PacketDivert" => Ok(ActionData::PacketDivert),
+            "PacketMirror" => Ok(ActionData::PacketMirror),
             _ => Result::Err(core::fmt::Error),
         }
     }
@@ -315,6 +381,10 @@ impl fmt::Display for ActionData {
             ActionData::ReassembledUpdatePDU => "ReassembledUpdatePDU",
             ActionData::PacketTrack => "PacketTrack",
             ActionData::ConnDeliver => "ConnDeliver",
+            ActionData::PacketWrite => "PacketWrite",
+            ActionData::PacketDrop => "PacketDrop",
+            ActionData::PacketDivert => "PacketDivert",
+            ActionData::PacketMirror => "PacketMirror",
             _ => panic!("Unknown ActionData"),
         };
         write!(f, "{}", s)
@@ -5,6 +5,7 @@ use super::Level;
 use std::collections::HashSet;
 use std::fmt;
 
+use crate::protocols::stream::custom::custom_protocols;
 use crate::protocols::stream::ConnData;
 use bimap::BiMap;
 use ipnet::{Ipv4Net, Ipv6Net};
@@ -23,21 +24,41 @@ lazy_static! {
         let ipv6     = g.add_node(protocol!("ipv6"));
         let tcp      = g.add_node(protocol!("tcp"));
         let udp      = g.add_node(protocol!("udp"));
+        let icmp     = g.add_node(protocol!("icmp"));
+        let icmpv6   = g.add_node(protocol!("icmpv6"));
+        let sctp     = g.add_node(protocol!("sctp"));
+        let conn     = g.add_node(protocol!("conn"));
         let tls      = g.add_node(protocol!("tls"));
         let http     = g.add_node(protocol!("http"));
         let dns      = g.add_node(protocol!("dns"));
         let quic     = g.add_node(protocol!("quic"));
+        let mpls     = g.add_node(protocol!("mpls"));
+        let vlan     = g.add_node(protocol!("vlan"));
         // define valid outer layers for each protocol header
         g.extend_with_edges([
             (ipv4, ethernet),
             (ipv6, ethernet),
             (tcp, ipv4), (tcp, ipv6),
             (udp, ipv4), (udp, ipv6),
+            (icmp, ipv4),
+            (icmpv6, ipv6),
+            (sctp, ipv4), (sctp, ipv6),
+            (conn, tcp), (conn, udp), (conn, sctp),
             (tls, tcp),
             (http, tcp),
             (dns, udp), (dns, tcp),
             (quic, udp), //TODO: tls over quic
+            (mpls, tcp), (mpls, udp), (mpls, sctp),
+            (vlan, tcp), (vlan, udp), (vlan, sctp),
         ]);
+        // application-defined stream protocols
+        for (name, transports) in custom_protocols() {
+            let node = g.add_node(protocol!(name));
+            for transport in transports {
+                let outer = if transport == "tcp" { tcp } else { udp };
+                g.add_edge(node, outer, ());
+            }
+        }
         g
     };
 }
@@ -122,6 +143,7 @@ impl Predicate {
         {
             return field_name.name() != "port"
                 && field_name.name() != "addr"
+                && !field_name.is_sample()
                 && !ConnData::supported_fields().contains(&field_name.name());
         }
         !ConnData::supported_protocols().contains(&self.get_protocol().name())
@@ -139,11 +161,20 @@ impl Predicate {
         self.needs_conntrack() && self.is_binary()
     }
 
+    /// Returns `true` if the predicate is on connection volume (e.g., `conn.bytes`), or on the MPLS
+    /// labels or VLAN tags of the connection (e.g., `mpls.label`, `vlan.id`).
+    /// These are checked on connection data when the connection is delivered, and are treated as
+    /// matching by earlier filters.
+    pub fn on_conn(&self) -> bool {
+        matches!(self.get_protocol().name(), "conn" | "mpls" | "vlan")
+    }
+
     /// Returns `true` if the predicate's protocol requires connection tracking
     /// i.e., is an application-layer protocol that runs on top of TCP or UDP.
     fn needs_conntrack(&self) -> bool {
-        has_path(self.get_protocol(), &protocol!("tcp"))
-            || has_path(self.get_protocol(), &protocol!("udp"))
+        !self.on_conn()
+            && (has_path(self.get_protocol(), &protocol!("tcp"))
+                || has_path(self.get_protocol(), &protocol!("udp")))
     }
 
     pub(crate) fn is_next_layer(&self, filter_layer: FilterLayer) -> bool {
@@ -193,11 +224,32 @@ impl Predicate {
         match filter_layer {
             FilterLayer::PacketContinue => false,
             FilterLayer::Packet | FilterLayer::Protocol => self.on_packet(),
-            FilterLayer::PacketDeliver | FilterLayer::ConnectionDeliver => true,
+            FilterLayer::PacketDeliver => true,
+            FilterLayer::ConnectionDeliver => !self.on_conn(),
             FilterLayer::Session => self.on_packet() || self.on_proto(),
         }
     }
 
+    /// Returns `true` if the predicate samples a fraction of connections (e.g., `sample(0.01)`).
+    pub fn is_sample(&self) -> bool {
+        matches!(self, Predicate::Binary { field, .. } if field.is_sample())
+    }
+
+    // Same predicate on another protocol header
+    pub(super) fn with_protocol(&self, protocol: ProtocolName) -> Predicate {
+        match self {
+            Predicate::Unary { .. } => Predicate::Unary { protocol },
+            Predicate::Binary {
+                field, op, value, ..
+            } => Predicate::Binary {
+                protocol,
+                field: field.to_owned(),
+                op: *op,
+                value: value.to_owned(),
+            },
+        }
+    }
+
     pub(super) fn default_pred() -> Predicate {
         Predicate::Unary {
             protocol: protocol!("ethernet"),
@@ -212,6 +264,10 @@ impl Predicate {
     /// Returns `true` if `self` and `pred` are entirely mutually exclusive
     /// (i.e., could be correctly represented by "if `a` {} else if `b` {}"...)
     pub(super) fn is_excl(&self, pred: &Predicate) -> bool {
+        // Connection volume is checked alongside (not instead of) other predicates
+        if self.on_conn() || pred.on_conn() {
+            return false;
+        }
         // Unary predicates at the same layer are mutually exclusive
         // E.g.: `ipv4 | ipv6`, `tcp | udp`
         if self.is_unary() && pred.is_unary() {
@@ -733,6 +789,13 @@ impl fmt::Display for Predicate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match &self {
             Predicate::Unary { protocol } => write!(f, "{}", protocol),
+            Predicate::Binary {
+                protocol: _,
+                field,
+                op,
+                value,
+            } if field.is_rate() => write!(f, "{} {} {}", field, op, value),
+            Predicate::Binary { field, .. } if field.is_sample() => write!(f, "{}", field),
             Predicate::Binary {
                 protocol,
                 field,
@@ -775,6 +838,80 @@ impl FieldName {
     pub fn is_combined(&self) -> bool {
         self.name() == "addr" || self.name() == "port"
     }
+
+    /// Builds the field of a rate predicate from its key and (optional) event,
+    /// e.g., `rate(ipv4.src_addr, tcp.syn)`.
+    pub fn rate(
+        key: (&ProtocolName, &FieldName),
+        event: Option<(&ProtocolName, &FieldName)>,
+    ) -> Self {
+        match event {
+            Some((event_proto, event_field)) => FieldName(format!(
+                "rate({}.{}, {}.{})",
+                key.0, key.1, event_proto, event_field
+            )),
+            None => FieldName(format!("rate({}.{})", key.0, key.1)),
+        }
+    }
+
+    /// Builds the field of a sampling predicate, e.g., `sample(0.01)`.
+    pub fn sample(fraction: f64) -> Self {
+        FieldName(format!("sample({})", fraction))
+    }
+
+    /// Builds the field of an accessor that takes a string argument, e.g., `header('host')`.
+    pub fn with_arg(method: &str, arg: &str) -> Self {
+        FieldName(format!("{}('{}')", method, arg))
+    }
+
+    /// Returns the name of the accessor method (e.g., `header` for `header('host')`).
+    pub fn method(&self) -> &str {
+        match self.name().split_once('(') {
+            Some((method, _)) => method,
+            None => self.name(),
+        }
+    }
+
+    /// Returns the string argument passed to the accessor, if any (e.g., `host` for
+    /// `header('host')`).
+    pub fn arg(&self) -> Option<&str> {
+        let (_, arg) = self.name().split_once("('")?;
+        arg.strip_suffix("')")
+    }
+
+    // rate predicates count events per key over a time window
+    pub fn is_rate(&self) -> bool {
+        self.name().starts_with("rate(")
+    }
+
+    /// Returns the key and the (optional) event of a rate predicate as (protocol, field) pairs.
+    #[allow(clippy::type_complexity)]
+    pub fn rate_args(
+        &self,
+    ) -> Option<((ProtocolName, FieldName), Option<(ProtocolName, FieldName)>)> {
+        let args = self.name().strip_prefix("rate(")?.strip_suffix(')')?;
+        let mut args = args.split(',').map(|arg| {
+            let (proto, field) = arg.trim().split_once('.')?;
+            Some((protocol!(proto), field!(field)))
+        });
+        let key = args.next()??;
+        let event = match args.next() {
+            Some(event) => Some(event?),
+            None => None,
+        };
+        Some((key, event))
+    }
+
+    // sampling predicates match a fraction of connections
+    pub fn is_sample(&self) -> bool {
+        self.name().starts_with("sample(")
+    }
+
+    /// Returns the fraction of connections matched by a sampling predicate.
+    pub fn sample_fraction(&self) -> Option<f64> {
+        let fraction = self.name().strip_prefix("sample(")?.strip_suffix(')')?;
+        fraction.parse().ok()
+    }
 }
 
 impl fmt::Display for FieldName {
@@ -817,10 +954,20 @@ impl fmt::Display for BinOp {
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Value {
     Int(u64),
-    IntRange { from: u64, to: u64 },
+    IntRange {
+        from: u64,
+        to: u64,
+    },
     Ipv4(Ipv4Net),
     Ipv6(Ipv6Net),
     Text(String),
+    /// Number of events per time window (in milliseconds)
+    Rate {
+        count: u64,
+        window: u64,
+    },
+    /// Named set whose members change at runtime (see [sets](crate::filter::sets))
+    Set(String),
 }
 
 impl fmt::Display for Value {
@@ -831,6 +978,8 @@ impl fmt::Display for Value {
             Value::Ipv4(net) => write!(f, "{}", net),
             Value::Ipv6(net) => write!(f, "{}", net),
             Value::Text(val) => write!(f, "{}", val),
+            Value::Rate { count, window } => write!(f, "{}/{}ms", count, window),
+            Value::Set(name) => write!(f, "${}", name),
         }
     }
 }
@@ -872,11 +1021,16 @@ mod tests {
         assert!(has_path(&protocol!("dns"), &protocol!("ipv6")));
         assert!(has_path(&protocol!("dns"), &protocol!("udp")));
         assert!(has_path(&protocol!("tcp"), &protocol!("ipv4")));
+        assert!(has_path(&protocol!("icmpv6"), &protocol!("ipv6")));
+        assert!(!has_path(&protocol!("icmp"), &protocol!("ipv6")));
+        assert!(has_path(&protocol!("sctp"), &protocol!("ipv6")));
         assert!(!has_path(&protocol!("ipv4"), &protocol!("tcp")));
         assert!(!has_path(&protocol!("ipv4"), &protocol!("ipv4")));
         assert!(!has_path(&protocol!("http"), &protocol!("udp")));
         assert!(has_path(&protocol!("quic"), &protocol!("udp")));
         assert!(!has_path(&protocol!("quic"), &protocol!("dns")));
+        assert!(has_path(&protocol!("mpls"), &protocol!("ethernet")));
+        assert!(has_path(&protocol!("vlan"), &protocol!("ipv4")));
     }
 
     #[test]
@@ -2,7 +2,7 @@
 
 use super::ast::Predicate;
 use super::ptree::FilterLayer;
-use super::{ActionData, Actions};
+use super::{ActionData, Actions, Filter};
 
 /// The abstraction levels for subscribable datatypes
 /// These essentially dictate at what point a datatype can/should be delivered
@@ -16,8 +16,10 @@ pub enum Level {
     Packet,
     /// Deliver at (UDP/TCP) connection termination
     Connection,
-    /// Deliver when session is parsed
-    /// Note: only one session-level datatype is permitted per subscription.
+    /// Deliver when session is parsed (the first matching session of the
+    /// connection, or each one if the datatype streams sessions)
+    /// Note: only one session-level datatype is permitted per subscription,
+    /// as each connection is parsed by a single session parser.
     Session,
     /// Deliver at any point in the connection
     /// Static-only subscriptions are delivered either on first packet
@@ -42,6 +44,56 @@ pub struct SubscriptionSpec {
     /// at which, if the filter has matched, all datatypes can be delivered.
     /// If needed, data is buffered until the full subscription can be delivered.
     pub level: Level,
+    /// Action applied to matched packets in inline mode, if any.
+    pub action: Option<PacketAction>,
+    /// True if the callback is an `async fn`, run off the packet processing cores.
+    pub is_async: bool,
+    /// Fraction of the connections matching the filter that are tracked for this subscription,
+    /// if sampled. Connections are sampled by hash of their 5-tuple (see
+    /// [sample](crate::filter::sample)).
+    pub sample_connections: Option<f64>,
+    /// Name of the group the subscription is tagged into, if any. The subscriptions of a group
+    /// can be enabled and disabled at runtime (see [group](crate::subscription::group)).
+    pub group: Option<String>,
+    /// Order in which the callback is invoked relative to other subscriptions matched by the same
+    /// packet, session, or connection. Higher priorities are invoked first; subscriptions with the
+    /// same priority are invoked in the order they are defined.
+    pub priority: i32,
+    /// Maximum number of deliveries to the callback, if capped. The subscription stops being
+    /// applied once it is reached (see [budget](crate::subscription::budget)).
+    pub max_matches: Option<u64>,
+}
+
+/// Action that the framework applies to the packets matched by a subscription, without invoking
+/// the callback for a verdict. Packets are only dropped or diverted in inline mode.
+///
+/// For packet-level subscriptions, the action applies to each delivered packet. For all other
+/// subscriptions, it applies to all packets of the connection from the packet on which the filter
+/// matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketAction {
+    /// Drop the packets.
+    Drop,
+    /// Transmit the packets on the divert port instead of the bridged port.
+    Divert,
+    /// Transmit a copy of the packets on the mirror port.
+    Mirror,
+}
+
+impl std::str::FromStr for PacketAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(PacketAction::Drop),
+            "divert" => Ok(PacketAction::Divert),
+            "mirror" => Ok(PacketAction::Mirror),
+            _ => Err(format!(
+                "Invalid action: {} (expected \"drop\", \"divert\" or \"mirror\")",
+                s
+            )),
+        }
+    }
 }
 
 /// Describes a single subscribable datatype and the operations it requires
@@ -59,11 +111,16 @@ pub struct DataType {
     pub needs_update_reassembled: bool,
     /// True if the datatype requires the framework to buffer matched packets
     pub track_packets: bool,
+    /// True if the datatype requires the framework to write matched packets to pcap files
+    pub write_packets: bool,
     /// A vector of the application-layer parsers required by this datatype
     /// Retina loads the union of parsers required by all datatypes and filters
     pub stream_protos: Vec<&'static str>,
     /// The name of the datatype as a string, used in code generation and Display.
     pub as_str: &'static str,
+    /// True if a session-level datatype is delivered for each parsed session that matches
+    /// (e.g., each HTTP transaction of a keep-alive connection), rather than only the first one
+    pub stream_sessions: bool,
 }
 
 impl DataType {
@@ -77,8 +134,10 @@ impl DataType {
             needs_update: true,
             needs_update_reassembled: false,
             track_packets: false,
+            write_packets: false,
             stream_protos: vec![],
             as_str,
+            stream_sessions: false,
         }
     }
 
@@ -92,8 +151,10 @@ impl DataType {
             needs_update: false,
             needs_update_reassembled: false,
             track_packets: false,
+            write_packets: false,
             stream_protos,
             as_str,
+            stream_sessions: false,
         }
     }
 
@@ -107,8 +168,10 @@ impl DataType {
             needs_update: false,
             needs_update_reassembled: false,
             track_packets: false,
+            write_packets: false,
             stream_protos: vec![],
             as_str,
+            stream_sessions: false,
         }
     }
 
@@ -122,8 +185,10 @@ impl DataType {
             needs_update: false,
             needs_update_reassembled: false,
             track_packets: false,
+            write_packets: false,
             stream_protos: vec![],
             as_str,
+            stream_sessions: false,
         }
     }
 
@@ -217,6 +282,15 @@ impl DataType {
         }
     }
 
+    // Helper
+    fn write_packets(&self, actions: &mut MatchingActions) {
+        if self.write_packets {
+            actions.if_matched.data |= ActionData::PacketWrite;
+            actions.if_matched.terminal_actions |= ActionData::PacketWrite;
+            actions.if_matching.data |= ActionData::PacketWrite;
+        }
+    }
+
     // Helper for proto_filter and session_filter
     fn track_sessions(&self, actions: &mut MatchingActions, sub_level: &Level) {
         // SessionTrack should only be terminal if matched at packet layer
@@ -229,6 +303,21 @@ impl DataType {
         }
     }
 
+    // Helper for proto_filter and session_filter
+    fn stream_sessions(&self, actions: &mut MatchingActions, sub_level: &Level) {
+        // Keep filtering and delivering the following sessions, whether or not
+        // the current one matched
+        if self.stream_sessions
+            && matches!(self.level, Level::Session)
+            && matches!(sub_level, Level::Session)
+        {
+            actions.if_matched.data |= ActionData::SessionDeliver;
+            actions.if_matched.terminal_actions |= ActionData::SessionDeliver;
+            actions.if_matching.data |= ActionData::SessionFilter;
+            actions.if_matching.terminal_actions |= ActionData::SessionFilter;
+        }
+    }
+
     fn conn_deliver(&self, sub_level: &Level, actions: &mut MatchingActions) {
         if matches!(sub_level, Level::Connection) {
             actions.if_matched.data |= ActionData::ConnDeliver;
@@ -252,6 +341,7 @@ impl DataType {
         // Connection- and session-level subscriptions depend on the actions required
         self.needs_update(&mut actions);
         self.track_packets(&mut actions);
+        self.write_packets(&mut actions);
         self.conn_deliver(sub_level, &mut actions);
 
         if self.needs_parse {
@@ -293,6 +383,7 @@ impl DataType {
         // Connection- and session-level subscriptions depend on the actions required
         self.needs_update(&mut actions);
         self.track_packets(&mut actions);
+        self.write_packets(&mut actions);
         self.track_sessions(&mut actions, sub_level);
         self.conn_deliver(sub_level, &mut actions);
 
@@ -300,6 +391,7 @@ impl DataType {
             // Deliver session when parsed (done in session filter)
             actions.if_matched.data |= ActionData::SessionDeliver;
         }
+        self.stream_sessions(&mut actions, sub_level);
 
         actions
     }
@@ -317,10 +409,12 @@ impl DataType {
 
         self.needs_update(&mut actions);
         self.track_packets(&mut actions);
+        self.write_packets(&mut actions);
         self.track_sessions(&mut actions, sub_level);
         self.conn_deliver(sub_level, &mut actions);
 
         // Session-level subscriptions will be delivered in session filter
+        self.stream_sessions(&mut actions, sub_level);
 
         MatchingActions {
             if_matched: actions.if_matched,
@@ -360,6 +454,52 @@ impl SubscriptionSpec {
             filter,
             callback,
             level: Level::Static, // Will be overwritten by any future levels
+            action: None,
+            is_async: false,
+            sample_connections: None,
+            group: None,
+            priority: 0,
+            max_matches: None,
+        }
+    }
+
+    /// Set the action applied to matched packets in inline mode
+    pub fn set_action(&mut self, action: PacketAction) {
+        self.action = Some(action);
+    }
+
+    /// Deliver to an async callback
+    pub fn set_async(&mut self) {
+        self.is_async = true;
+    }
+
+    /// Only track the given fraction of the connections matching the filter
+    pub fn set_sample_connections(&mut self, fraction: f64) {
+        self.sample_connections = Some(fraction);
+    }
+
+    /// Tag the subscription into group `group`
+    pub fn set_group(&mut self, group: String) {
+        self.group = Some(group);
+    }
+
+    /// Set the priority of the callback (see `priority`)
+    pub fn set_priority(&mut self, priority: i32) {
+        self.priority = priority;
+    }
+
+    /// Stop delivering to the callback after `max_matches` deliveries
+    pub fn set_max_matches(&mut self, max_matches: u64) {
+        self.max_matches = Some(max_matches);
+    }
+
+    /// The filter applied to the subscription, including the sampling predicate if sampled.
+    /// `filter` is left as specified by the user, e.g., to deliver as the matched filter string.
+    pub fn sampled_filter(&self) -> String {
+        match self.sample_connections {
+            Some(fraction) if self.filter.trim().is_empty() => format!("sample({})", fraction),
+            Some(fraction) => format!("({}) and sample({})", self.filter, fraction),
+            None => self.filter.clone(),
         }
     }
 
@@ -379,6 +519,9 @@ impl SubscriptionSpec {
     /// - One packet-level datatype per subscription
     /// - Packet-level datatype only permitted with static datatype
     /// - At most one session-level datatype per subscription
+    /// - Connection volume, MPLS, and VLAN predicates only in connection-level subscriptions
+    /// - No packet-level or packet-buffering datatypes in async subscriptions
+    /// - Sampling fraction in (0, 1]
     pub fn validate_spec(&self) {
         if matches!(self.level, Level::Packet) {
             if self.datatypes.len() > 1 {
@@ -413,15 +556,49 @@ impl SubscriptionSpec {
             );
         }
 
+        // Each connection is parsed by a single session parser, so the sessions of several
+        // datatypes (e.g., TLS and HTTP) are never all found on the same connection
         assert!(
             self.datatypes
                 .iter()
                 .filter(|d| matches!(d.level, Level::Session))
                 .count()
                 <= 1,
-            "Multiple session-level datatypes in subscription: {:?}",
+            "Multiple session-level datatypes in subscription (a connection is parsed as a single \
+             protocol, so the callback would never be invoked): {:?}",
             self
         );
+
+        assert!(
+            !self.is_async
+                || !self
+                    .datatypes
+                    .iter()
+                    .any(|d| matches!(d.level, Level::Packet) || d.track_packets),
+            "Packet datatype in async subscription (packets cannot leave the core): {:?}",
+            self
+        );
+
+        if let Some(fraction) = self.sample_connections {
+            assert!(
+                fraction > 0.0 && fraction <= 1.0,
+                "Sampling fraction must be in (0, 1]: {:?}",
+                self
+            );
+        }
+
+        if !matches!(self.level, Level::Connection) {
+            if let Ok(filter) = Filter::new(&self.filter) {
+                assert!(
+                    !filter
+                        .get_patterns_flat()
+                        .iter()
+                        .any(|p| p.predicates.iter().any(|p| p.on_conn())),
+                    "Connection data predicate in non-connection subscription: {:?}",
+                    self
+                );
+            }
+        }
     }
 
     /// Add a new datatype to the subscription
@@ -500,12 +677,29 @@ impl SubscriptionSpec {
         }
     }
 
+    // Helper
+    // Packet-level subscriptions apply the action when packets are delivered
+    fn packet_action(&self, actions: &mut MatchingActions) {
+        if matches!(self.level, Level::Packet) {
+            return;
+        }
+        let action = match self.action {
+            Some(PacketAction::Drop) => ActionData::PacketDrop,
+            Some(PacketAction::Divert) => ActionData::PacketDivert,
+            Some(PacketAction::Mirror) => ActionData::PacketMirror,
+            None => return,
+        };
+        actions.if_matched.data |= action;
+        actions.if_matched.terminal_actions |= action;
+    }
+
     // Actions for PacketFilter stage
     pub(crate) fn packet_filter(&self) -> MatchingActions {
         let mut actions = MatchingActions::new();
         for datatype in &self.datatypes {
             actions.push(&datatype.packet_filter(&self.level));
         }
+        self.packet_action(&mut actions);
         actions.if_matching.data |= ActionData::ProtoFilter;
         actions
     }
@@ -516,6 +710,7 @@ impl SubscriptionSpec {
         for datatype in &self.datatypes {
             actions.push(&datatype.proto_filter(&self.level));
         }
+        self.packet_action(&mut actions);
         if matches!(self.level, Level::Static) {
             actions.if_matched.data |= ActionData::ConnDeliver;
             actions.if_matched.terminal_actions |= ActionData::ConnDeliver;
@@ -530,6 +725,7 @@ impl SubscriptionSpec {
         for datatype in &self.datatypes {
             actions.push(&datatype.session_filter(&self.level));
         }
+        self.packet_action(&mut actions);
         if matches!(self.level, Level::Static) {
             actions.if_matched.data |= ActionData::ConnDeliver;
             actions.if_matched.terminal_actions |= ActionData::ConnDeliver;
@@ -575,6 +771,7 @@ impl SubscriptionSpec {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::filter::ast::ProtocolName;
 
     #[test]
     fn basic_multispec() {
@@ -599,4 +796,42 @@ mod tests {
         assert!(spec.proto_filter().if_matched.packet_deliver());
         assert!(spec.proto_filter().if_matching.buffer_frame());
     }
+
+    #[test]
+    fn streaming_session_datatype() {
+        let mut spec = SubscriptionSpec::new(String::from(""), String::from("cb"));
+        spec.add_datatype(DataType {
+            stream_sessions: true,
+            ..DataType::new_default_session("Http", vec!["http"])
+        });
+        let matching_actions = spec.proto_filter();
+        assert!(matching_actions
+            .if_matched
+            .terminal_actions
+            .intersects(ActionData::SessionDeliver));
+        assert!(matching_actions
+            .if_matching
+            .terminal_actions
+            .intersects(ActionData::SessionFilter));
+
+        let mut spec = SubscriptionSpec::new(String::from(""), String::from("cb"));
+        spec.add_datatype(DataType::new_default_session("Http", vec!["http"]));
+        assert!(spec.proto_filter().if_matched.terminal_actions.is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Multiple session-level datatypes")]
+    fn multiple_session_datatypes() {
+        let mut spec = SubscriptionSpec::new(String::from(""), String::from("cb"));
+        spec.add_datatype(DataType::new_default_session("Tls", vec!["tls"]));
+        spec.validate_spec();
+        assert!(matches!(spec.level, Level::Session));
+        let pred = Predicate::Unary {
+            protocol: ProtocolName(String::from("tls")),
+        };
+        assert!(spec.should_deliver(FilterLayer::Session, &pred));
+
+        spec.add_datatype(DataType::new_default_session("Http", vec!["http"]));
+        spec.validate_spec();
+    }
 }
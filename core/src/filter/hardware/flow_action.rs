@@ -1,5 +1,5 @@
 use crate::dpdk;
-use crate::port::{PortId, SYMMETRIC_RSS_KEY};
+use crate::port::PortId;
 
 use std::mem;
 
@@ -60,7 +60,8 @@ impl FlowAction {
         self.mark.push(mark_conf);
     }
 
-    pub(super) fn append_rss(&mut self) {
+    /// Appends an RSS action that hashes with `rss_key`, which must outlive the flow rule.
+    pub(super) fn append_rss(&mut self, rss_key: &[u8]) {
         let mut a_rss: dpdk::rte_flow_action = unsafe { mem::zeroed() };
         a_rss.type_ = dpdk::rte_flow_action_type_RTE_FLOW_ACTION_TYPE_RSS;
         self.rules.push(a_rss);
@@ -74,11 +75,11 @@ impl FlowAction {
         // Innermost encapsulation level PMD can handle
         a_rss_conf.level = 0;
         a_rss_conf.types = rss_conf.rss_hf;
-        a_rss_conf.key_len = rss_conf.rss_key_len as u32;
+        a_rss_conf.key_len = rss_key.len() as u32;
 
-        // Since the RSS key needs to outlive this method, we use the static
-        // SYMMETRIC_RSS_KEY instead of the key queried from the existing rss_conf
-        a_rss_conf.key = SYMMETRIC_RSS_KEY.as_ptr();
+        // Since the RSS key needs to outlive this method, we use the key owned by the port
+        // instead of the key queried from the existing rss_conf
+        a_rss_conf.key = rss_key.as_ptr();
 
         self.rss.push(a_rss_conf);
     }
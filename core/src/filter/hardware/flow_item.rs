@@ -113,6 +113,22 @@ impl FlowPattern {
                         }
                         _ => bail!(FilterError::InvalidRhsType(value.to_string())),
                     },
+                    "dscp" => match value {
+                        Value::Int(i) if *i < 64 => {
+                            ipv4_spec.hdr.type_of_service |= (*i as u8) << 2;
+                            ipv4_mask.hdr.type_of_service |= 0xfc;
+                        }
+                        Value::Int(_) => bail!(FilterError::InvalidRhsValue(value.to_string())),
+                        _ => bail!(FilterError::InvalidRhsType(value.to_string())),
+                    },
+                    "ecn" => match value {
+                        Value::Int(i) if *i < 4 => {
+                            ipv4_spec.hdr.type_of_service |= *i as u8;
+                            ipv4_mask.hdr.type_of_service |= 0x03;
+                        }
+                        Value::Int(_) => bail!(FilterError::InvalidRhsValue(value.to_string())),
+                        _ => bail!(FilterError::InvalidRhsType(value.to_string())),
+                    },
                     "total_length" => match value {
                         Value::Int(i) => {
                             if let Ok(val) = u16::try_from(*i) {
@@ -146,7 +162,7 @@ impl FlowPattern {
                         }
                         _ => bail!(FilterError::InvalidRhsType(value.to_string())),
                     },
-                    "time_to_live" => match value {
+                    "time_to_live" | "ttl" => match value {
                         Value::Int(i) => {
                             if let Ok(val) = u8::try_from(*i) {
                                 ipv4_spec.hdr.time_to_live = val;
@@ -231,6 +247,22 @@ impl FlowPattern {
                         }
                         _ => bail!(FilterError::InvalidRhsType(value.to_string())),
                     },
+                    "dscp" => match value {
+                        Value::Int(i) if *i < 64 => {
+                            ipv6_spec.hdr.vtc_flow |= ((*i as u32) << 22).to_be();
+                            ipv6_mask.hdr.vtc_flow |= 0x0fc0_0000_u32.to_be();
+                        }
+                        Value::Int(_) => bail!(FilterError::InvalidRhsValue(value.to_string())),
+                        _ => bail!(FilterError::InvalidRhsType(value.to_string())),
+                    },
+                    "ecn" => match value {
+                        Value::Int(i) if *i < 4 => {
+                            ipv6_spec.hdr.vtc_flow |= ((*i as u32) << 20).to_be();
+                            ipv6_mask.hdr.vtc_flow |= 0x0030_0000_u32.to_be();
+                        }
+                        Value::Int(_) => bail!(FilterError::InvalidRhsValue(value.to_string())),
+                        _ => bail!(FilterError::InvalidRhsType(value.to_string())),
+                    },
                     "payload_length" => match value {
                         Value::Int(i) => {
                             if let Ok(val) = u16::try_from(*i) {
@@ -491,3 +523,77 @@ impl FlowPattern {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::Filter;
+
+    fn flow_pattern(filter: &str) -> Result<FlowPattern> {
+        let patterns = Filter::new(filter)?.get_patterns_flat();
+        let fq_patterns = patterns[0].to_fully_qualified()?;
+        FlowPattern::from_layered_pattern(&fq_patterns[0])
+    }
+
+    fn ipv4_item(pattern: &FlowPattern) -> (dpdk::rte_flow_item_ipv4, dpdk::rte_flow_item_ipv4) {
+        let item = &pattern.items[0];
+        assert_eq!(
+            item.item_type(),
+            dpdk::rte_flow_item_type_RTE_FLOW_ITEM_TYPE_IPV4
+        );
+        unsafe {
+            (
+                *(item.spec() as *const dpdk::rte_flow_item_ipv4),
+                *(item.mask() as *const dpdk::rte_flow_item_ipv4),
+            )
+        }
+    }
+
+    fn ipv6_item(pattern: &FlowPattern) -> (dpdk::rte_flow_item_ipv6, dpdk::rte_flow_item_ipv6) {
+        let item = &pattern.items[0];
+        assert_eq!(
+            item.item_type(),
+            dpdk::rte_flow_item_type_RTE_FLOW_ITEM_TYPE_IPV6
+        );
+        unsafe {
+            (
+                *(item.spec() as *const dpdk::rte_flow_item_ipv6),
+                *(item.mask() as *const dpdk::rte_flow_item_ipv6),
+            )
+        }
+    }
+
+    #[test]
+    fn core_flow_item_ipv4_ttl() {
+        let (spec, mask) = ipv4_item(&flow_pattern("ipv4.ttl = 64").unwrap());
+        assert_eq!(spec.hdr.time_to_live, 64);
+        assert_eq!(mask.hdr.time_to_live, u8::MAX);
+        assert!(flow_pattern("ipv4.ttl = 256").is_err());
+    }
+
+    #[test]
+    fn core_flow_item_ipv4_dscp_ecn() {
+        let (spec, mask) = ipv4_item(&flow_pattern("ipv4.dscp = 46").unwrap());
+        assert_eq!(spec.hdr.type_of_service, 46 << 2);
+        assert_eq!(mask.hdr.type_of_service, 0xfc);
+
+        let (spec, mask) = ipv4_item(&flow_pattern("ipv4.dscp = 46 and ipv4.ecn = 1").unwrap());
+        assert_eq!(spec.hdr.type_of_service, (46 << 2) | 1);
+        assert_eq!(mask.hdr.type_of_service, 0xff);
+
+        assert!(flow_pattern("ipv4.dscp = 64").is_err());
+        assert!(flow_pattern("ipv4.ecn = 4").is_err());
+    }
+
+    #[test]
+    fn core_flow_item_ipv6_dscp_ecn() {
+        let (spec, mask) = ipv6_item(&flow_pattern("ipv6.dscp = 46 and ipv6.ecn = 3").unwrap());
+        // Copied out of the packed header
+        let (vtc_flow, vtc_mask) = ({ spec.hdr.vtc_flow }, { mask.hdr.vtc_flow });
+        assert_eq!(u32::from_be(vtc_flow), (46 << 22) | (3 << 20));
+        assert_eq!(u32::from_be(vtc_mask), 0x0ff0_0000);
+
+        assert!(flow_pattern("ipv6.dscp = 64").is_err());
+        assert!(flow_pattern("ipv6.ecn = 4").is_err());
+    }
+}
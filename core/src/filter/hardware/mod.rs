@@ -135,6 +135,36 @@ pub(crate) fn device_supported(pred: &Predicate, port: &Port) -> bool {
         return false;
     }
 
+    // Stateful predicates are evaluated in software
+    if let Predicate::Binary { field, .. } = pred {
+        if field.is_rate() {
+            info!(
+                "Hardware filter does not support stateful predicate: [{}]",
+                pred
+            );
+            return false;
+        }
+    }
+
+    // Filter sets change at runtime
+    if let Predicate::Binary {
+        value: Value::Set(_),
+        ..
+    } = pred
+    {
+        info!("Hardware filter does not support filter set: [{}]", pred);
+        return false;
+    }
+
+    // Sampling predicates hash the 5-tuple in software
+    if pred.is_sample() {
+        info!(
+            "Hardware filter does not support sampling predicate: [{}]",
+            pred
+        );
+        return false;
+    }
+
     // Only allow equality predicates
     // MLX5 only supports equality or masked IP address
     let op_supported = match pred {
@@ -190,7 +220,7 @@ fn pattern_supported(lpattern: &LayeredPattern, port: &Port, group: u32, priorit
         Ok(mut pattern) => {
             let mut action = FlowAction::new(port.id);
 
-            action.append_rss();
+            action.append_rss(&port.rss.key);
             action.finish();
 
             validate_rule(port, attr, &mut pattern, &mut action)
@@ -254,7 +284,7 @@ fn install_pattern(
         let mut action = FlowAction::new(port.id);
         // action.append_mark(tag as u32);
 
-        action.append_rss();
+        action.append_rss(&port.rss.key);
         action.finish();
 
         create_rule(lpattern, port, attr, &mut pattern, &mut action)
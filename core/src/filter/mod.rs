@@ -5,15 +5,21 @@ pub use actions::{ActionData, Actions};
 pub mod macros;
 pub mod ast;
 mod hardware;
+mod names;
 #[allow(clippy::upper_case_acronyms)]
 mod parser;
 mod pattern;
 pub mod ptree;
 pub mod ptree_flat;
+pub mod rate;
+pub mod regexes;
+pub mod sample;
+pub mod sets;
 
 pub mod datatypes;
-pub use datatypes::{DataType, Level, SubscriptionSpec};
+pub use datatypes::{DataType, Level, PacketAction, SubscriptionSpec};
 
+use crate::filter::ast::{BinOp, Predicate, Value};
 use crate::filter::hardware::{flush_rules, HardwareFilter};
 use crate::filter::parser::FilterParser;
 use crate::filter::pattern::{FlatPattern, LayeredPattern};
@@ -54,6 +60,18 @@ where
     pub session_filter: SessionFilterFn<T>,
     pub packet_deliver: PacketDeliverFn<T>,
     pub conn_deliver: ConnDeliverFn<T>,
+    /// Whether connections buffer packets for the subscriptions (packet-level subscriptions, or
+    /// datatypes that track packets). Used to size mempools.
+    pub track_packets: bool,
+    /// Whether all subscriptions are packet-level with packet-layer filters, so that packets are
+    /// delivered by the packet continue filter and the connection tracker is bypassed.
+    pub bypass_conntrack: bool,
+    /// Names of the subscription groups (see [group](crate::subscription::group)).
+    pub groups: Vec<&'static str>,
+    /// Names of the filter sets referenced by the filter (see [sets](crate::filter::sets)).
+    pub sets: Vec<&'static str>,
+    /// Largest connection volume that the subscriptions can match.
+    pub volume_limits: VolumeLimits,
 }
 
 impl<T> FilterFactory<T>
@@ -77,8 +95,101 @@ where
             session_filter,
             packet_deliver,
             conn_deliver,
+            track_packets: false,
+            bypass_conntrack: false,
+            groups: vec![],
+            sets: vec![],
+            volume_limits: VolumeLimits::default(),
         }
     }
+
+    pub fn with_packet_tracking(mut self, track_packets: bool) -> Self {
+        self.track_packets = track_packets;
+        self
+    }
+
+    pub fn with_conntrack_bypass(mut self, bypass_conntrack: bool) -> Self {
+        self.bypass_conntrack = bypass_conntrack;
+        self
+    }
+
+    pub fn with_groups(mut self, groups: &[&'static str]) -> Self {
+        self.groups = groups.to_vec();
+        self
+    }
+
+    pub fn with_sets(mut self, sets: &[&'static str]) -> Self {
+        self.sets = sets.to_vec();
+        self
+    }
+
+    pub fn with_volume_limits(mut self, volume_limits: VolumeLimits) -> Self {
+        self.volume_limits = volume_limits;
+        self
+    }
+}
+
+/// Largest connection volume that a filter can match, from the upper bounds of its `conn.bytes`
+/// and `conn.packets` predicates (e.g., `conn.packets < 10`), or `None` if unbounded.
+///
+/// Volume predicates are checked when connections are delivered, but the connection tracker also
+/// checks the limits as packets are counted: connections that exceed them can no longer match, and
+/// are dropped at once rather than tracked until they terminate.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VolumeLimits {
+    /// Maximum number of bytes.
+    pub bytes: Option<u64>,
+    /// Maximum number of packets.
+    pub packets: Option<u64>,
+}
+
+impl VolumeLimits {
+    /// Returns the limits of `pattern`: the lowest upper bound of its volume predicates.
+    fn from_pattern(pattern: &FlatPattern) -> Self {
+        let mut limits = VolumeLimits::default();
+        for pred in pattern.predicates.iter() {
+            if let Predicate::Binary {
+                protocol,
+                field,
+                op,
+                value,
+            } = pred
+            {
+                if protocol != &protocol!("conn") {
+                    continue;
+                }
+                let max = match (op, value) {
+                    (BinOp::Eq | BinOp::Le, Value::Int(val)) => *val,
+                    (BinOp::Lt, Value::Int(val)) => val.saturating_sub(1),
+                    (BinOp::In, Value::IntRange { to, .. }) => *to,
+                    _ => continue,
+                };
+                let limit = match field.name() {
+                    "bytes" => &mut limits.bytes,
+                    "packets" => &mut limits.packets,
+                    _ => continue,
+                };
+                *limit = Some(limit.map_or(max, |limit| limit.min(max)));
+            }
+        }
+        limits
+    }
+
+    /// Returns the limits of matching either `self` or `other`.
+    pub fn union(self, other: VolumeLimits) -> Self {
+        let max = |a: Option<u64>, b: Option<u64>| Some(a?.max(b?));
+        VolumeLimits {
+            bytes: max(self.bytes, other.bytes),
+            packets: max(self.packets, other.packets),
+        }
+    }
+
+    /// Returns `true` if the volume of connection `conn` exceeds the limits.
+    #[inline]
+    pub(crate) fn exceeded(&self, conn: &ConnData) -> bool {
+        self.bytes.is_some_and(|max| conn.num_bytes > max)
+            || self.packets.is_some_and(|max| conn.num_pkts > max)
+    }
 }
 
 #[derive(Default, Debug, Clone)]
@@ -128,6 +239,15 @@ impl Filter {
             .collect::<Vec<_>>()
     }
 
+    /// Returns the largest connection volume that the filter can match
+    pub fn volume_limits(&self) -> VolumeLimits {
+        self.get_patterns_flat()
+            .iter()
+            .map(VolumeLimits::from_pattern)
+            .reduce(VolumeLimits::union)
+            .unwrap_or_default()
+    }
+
     /// Returns predicate tree
     pub fn to_ptree(&self) -> FlatPTree {
         FlatPTree::new(&self.get_patterns_flat())
@@ -0,0 +1,111 @@
+//! Named constants and typed aliases for filter fields.
+//!
+//! Some fields are more naturally written with a name than a number (e.g., `tls.version =
+//! 'TLS1.2'`, `dns.rcode = 'NXDOMAIN'`), and some fields are exposed both as a name and a numeric identifier (e.g.,
+//! `tls.cipher`). The parser resolves these to integer predicates before building the filter, so
+//! they are handled like any other integer comparison.
+//!
+//! Flags (e.g., `tcp.syn`, `ipv6.has_ext('routing')`) can be written without a comparison, as a
+//! shorthand for `= 1`.
+
+use super::ast::{FieldName, ProtocolName};
+
+/// Returns the integer value of the constant `name` for `protocol.field`, if one exists.
+pub(super) fn resolve_name(protocol: &ProtocolName, field: &FieldName, name: &str) -> Option<u64> {
+    let table: &[(&str, u64)] = match (protocol.name(), field.name()) {
+        ("tls", "version" | "client_version" | "server_version") => &[
+            ("SSL3.0", 0x0300),
+            ("TLS1.0", 0x0301),
+            ("TLS1.1", 0x0302),
+            ("TLS1.2", 0x0303),
+            ("TLS1.3", 0x0304),
+        ],
+        ("dns", "qtype") => &[
+            ("A", 1),
+            ("NS", 2),
+            ("CNAME", 5),
+            ("SOA", 6),
+            ("NULL", 10),
+            ("PTR", 12),
+            ("HINFO", 13),
+            ("MX", 15),
+            ("TXT", 16),
+            ("AAAA", 28),
+            ("SRV", 33),
+            ("OPT", 41),
+            ("DS", 43),
+            ("RRSIG", 46),
+            ("DNSKEY", 48),
+            ("SVCB", 64),
+            ("HTTPS", 65),
+            ("AXFR", 252),
+            ("ANY", 255),
+            ("CAA", 257),
+        ],
+        ("dns", "rcode") => &[
+            ("NOERROR", 0),
+            ("FORMERR", 1),
+            ("SERVFAIL", 2),
+            ("NXDOMAIN", 3),
+            ("NOTIMP", 4),
+            ("REFUSED", 5),
+        ],
+        _ => return None,
+    };
+    table
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| *v)
+}
+
+/// Returns the name of the integer-valued accessor for `protocol.field`, if `field` is not itself
+/// integer-valued.
+pub(super) fn int_field(protocol: &ProtocolName, field: &FieldName) -> Option<FieldName> {
+    match (protocol.name(), field.name()) {
+        ("tls", "cipher") => Some(FieldName("cipher_id".to_owned())),
+        _ => None,
+    }
+}
+
+/// Names of the IPv6 extension headers tested by `ipv6.has_ext('<name>')`.
+const IPV6_EXTENSIONS: [&str; 5] = ["hop_by_hop", "routing", "fragment", "auth", "dst_opts"];
+
+/// Returns `true` if `protocol.field` is a flag, whose value is either `0` or `1`.
+pub(super) fn is_flag(protocol: &ProtocolName, field: &FieldName) -> bool {
+    if field.arg().is_some() {
+        // Accessors that take an argument, which cannot be written without one
+        return matches!(
+            (protocol.name(), field.method()),
+            ("ipv6", "has_ext") | ("http", "has_header")
+        );
+    }
+    matches!(
+        (protocol.name(), field.name()),
+        (
+            "tcp",
+            "ns" | "cwr" | "ece" | "urg" | "ack" | "psh" | "rst" | "syn" | "fin" | "synack"
+        ) | (
+            "ipv6",
+            "frag"
+                | "has_ext_hop_by_hop"
+                | "has_ext_routing"
+                | "has_ext_fragment"
+                | "has_ext_auth"
+                | "has_ext_dst_opts"
+        ) | ("icmp" | "icmpv6", "echo" | "error")
+            | (
+                "sctp",
+                "data" | "init" | "init_ack" | "abort" | "shutdown" | "shutdown_complete"
+            )
+            | ("vlan", "qinq")
+    )
+}
+
+/// Returns `false` if the argument of `protocol.field` is not one of the values the accessor
+/// accepts (e.g., an unknown extension header in `ipv6.has_ext('<name>')`).
+pub(super) fn is_valid_arg(protocol: &ProtocolName, field: &FieldName) -> bool {
+    match (protocol.name(), field.method(), field.arg()) {
+        ("ipv6", "has_ext", Some(name)) => IPV6_EXTENSIONS.contains(&name),
+        _ => true,
+    }
+}
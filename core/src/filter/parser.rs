@@ -1,4 +1,5 @@
 use super::ast::*;
+use super::names;
 use crate::filter::FilterError;
 
 use ipnet::{Ipv4Net, Ipv6Net};
@@ -95,6 +96,8 @@ impl FilterParser {
             match pair.as_rule() {
                 Rule::expr => terms.push(FilterParser::parse_disjunct(pair)?),
                 Rule::predicate => terms.extend(FilterParser::parse_predicate(pair)?),
+                Rule::rate_predicate => terms.extend(FilterParser::parse_rate_predicate(pair)?),
+                Rule::sample_predicate => terms.push(FilterParser::parse_sample_predicate(pair)?),
                 _ => (),
             }
         }
@@ -106,16 +109,50 @@ impl FilterParser {
         let protocol = inner.next().unwrap();
         match inner.next() {
             Some(field) => {
-                let op = inner.next().unwrap();
+                let mut next = inner.next();
+                let field_name = match next {
+                    Some(arg) if arg.as_rule() == Rule::field_arg => {
+                        next = inner.next();
+                        let arg = arg.into_inner().next().unwrap();
+                        FieldName::with_arg(field.as_str(), arg.as_str())
+                    }
+                    _ => FieldName(field.as_str().to_owned()),
+                };
+                if !names::is_valid_arg(
+                    &FilterParser::parse_protocol(protocol.clone()),
+                    &field_name,
+                ) {
+                    bail!(FilterError::InvalidField(format!(
+                        "{}.{}",
+                        protocol.as_str(),
+                        field_name
+                    )));
+                }
+                let op = match next {
+                    Some(op) if op.as_rule() == Rule::bin_op => op,
+                    op => {
+                        let exists = op.is_some();
+                        return Ok(vec![FilterParser::parse_flag_predicate(
+                            FilterParser::parse_protocol(protocol),
+                            field_name,
+                            exists,
+                        )?]);
+                    }
+                };
                 let value = inner.next().unwrap();
+                // Named sets are only tested for membership, e.g. "ipv4.src_addr in $blocklist"
+                let is_set = value.clone().into_inner().next().unwrap().as_rule() == Rule::set_ref;
+                if is_set && FilterParser::parse_binop(op.clone())? != BinOp::In {
+                    bail!(FilterError::InvalidBinOp(op.as_str().to_string()));
+                }
 
                 match field.as_rule() {
-                    Rule::field => Ok(vec![Node::Predicate(Predicate::Binary {
-                        protocol: FilterParser::parse_protocol(protocol),
-                        field: FilterParser::parse_field(field),
-                        op: FilterParser::parse_binop(op)?,
-                        value: FilterParser::parse_value(value)?,
-                    })]),
+                    Rule::field => FilterParser::parse_field_predicate(
+                        FilterParser::parse_protocol(protocol),
+                        field_name,
+                        FilterParser::parse_binop(op)?,
+                        value,
+                    ),
                     Rule::combined_field => {
                         let mut src_field = "src_".to_owned();
                         src_field.push_str(field.as_str());
@@ -162,6 +199,163 @@ impl FilterParser {
         }
     }
 
+    fn parse_field_predicate(
+        protocol: ProtocolName,
+        field: FieldName,
+        op: BinOp,
+        value: Pair<Rule>,
+    ) -> Result<Vec<Node>> {
+        let mut field = field;
+        let int_field = names::int_field(&protocol, &field);
+        let rhs = value.clone().into_inner().next().unwrap();
+        let value = match rhs.as_rule() {
+            // Set membership, e.g. "tls.cipher in {47, 53}" -> "tls.cipher = 47 or tls.cipher = 53"
+            Rule::int_set => {
+                if op != BinOp::In {
+                    bail!(FilterError::InvalidBinOp(op.to_string()));
+                }
+                let field = int_field.unwrap_or(field);
+                let mut terms = vec![];
+                for int in rhs.into_inner() {
+                    terms.push(Node::Conjunct(vec![Node::Predicate(Predicate::Binary {
+                        protocol: protocol.clone(),
+                        field: field.clone(),
+                        op: BinOp::Eq,
+                        value: Value::Int(FilterParser::parse_int(int)?),
+                    })]));
+                }
+                return Ok(vec![Node::Disjunct(terms)]);
+            }
+            _ => match FilterParser::parse_value(value)? {
+                Value::Text(text) => match names::resolve_name(&protocol, &field, &text) {
+                    Some(val) => Value::Int(val),
+                    None => Value::Text(text),
+                },
+                value => value,
+            },
+        };
+        if let (Value::Int(_) | Value::IntRange { .. } | Value::Set(_), Some(int_field)) =
+            (&value, int_field)
+        {
+            field = int_field;
+        }
+        Ok(vec![Node::Predicate(Predicate::Binary {
+            protocol,
+            field,
+            op,
+            value,
+        })])
+    }
+
+    // Flag shorthands: `ipv6.frag` is equivalent to `ipv6.frag = 1`, and
+    // `http.header('host') exists` is equivalent to `http.has_header('host') = 1`. Fields that
+    // are not flags must be compared, e.g., `tcp.port` alone is rejected.
+    fn parse_flag_predicate(
+        protocol: ProtocolName,
+        field: FieldName,
+        exists: bool,
+    ) -> Result<Node> {
+        let field = match (exists, field.arg()) {
+            (true, Some(arg)) => FieldName::with_arg(&format!("has_{}", field.method()), arg),
+            (true, None) => FieldName(format!("has_{}", field.name())),
+            (false, _) => {
+                if !names::is_flag(&protocol, &field) {
+                    bail!(FilterError::InvalidField(format!("{}.{}", protocol, field)));
+                }
+                field
+            }
+        };
+        Ok(Node::Predicate(Predicate::Binary {
+            protocol,
+            field,
+            op: BinOp::Eq,
+            value: Value::Int(1),
+        }))
+    }
+
+    fn parse_rate_predicate(pair: Pair<Rule>) -> Result<Vec<Node>> {
+        let mut inner = pair.into_inner();
+        let mut key = inner.next().unwrap().into_inner();
+        let key_proto = FilterParser::parse_protocol(key.next().unwrap());
+        let key_field = FilterParser::parse_field(key.next().unwrap());
+
+        let mut next = inner.next().unwrap();
+        let event = match next.as_rule() {
+            Rule::rate_event => {
+                let mut event = next.into_inner();
+                let event_proto = FilterParser::parse_protocol(event.next().unwrap());
+                let event_field = FilterParser::parse_field(event.next().unwrap());
+                next = inner.next().unwrap();
+                Some((event_proto, event_field))
+            }
+            _ => None,
+        };
+
+        let op = FilterParser::parse_binop(next)?;
+        if !matches!(op, BinOp::Gt | BinOp::Ge | BinOp::Lt | BinOp::Le) {
+            bail!(FilterError::InvalidBinOp(op.to_string()));
+        }
+        let value = FilterParser::parse_rate(inner.next().unwrap())?;
+
+        // The predicate is checked once both the key and event headers are parsed.
+        // e.g., "rate(ipv4.src_addr, tcp.syn) > 100/60s" -> "ipv4 and tcp.rate(...) > 100/60s"
+        let protocol = match &event {
+            Some((event_proto, _)) => event_proto.clone(),
+            None => key_proto.clone(),
+        };
+        let field = FieldName::rate(
+            (&key_proto, &key_field),
+            event.as_ref().map(|(p, f)| (p, f)),
+        );
+        Ok(vec![
+            Node::Predicate(Predicate::Unary {
+                protocol: key_proto,
+            }),
+            Node::Predicate(Predicate::Binary {
+                protocol,
+                field,
+                op,
+                value,
+            }),
+        ])
+    }
+
+    fn parse_sample_predicate(pair: Pair<Rule>) -> Result<Node> {
+        let lit = pair.into_inner().next().unwrap();
+        let fraction = lit.as_str().parse::<f64>()?;
+        if fraction <= 0.0 || fraction > 1.0 {
+            bail!(FilterError::InvalidRhsValue(lit.as_str().to_string()));
+        }
+        // The predicate does not depend on any header field. It is moved to the network layer
+        // header when the pattern is fully qualified, e.g., "tcp and sample(0.01)" ->
+        // "ipv4 and ipv4.sample(0.01) = 1 and tcp", "ipv6 and ipv6.sample(0.01) = 1 and tcp"
+        Ok(Node::Predicate(Predicate::Binary {
+            protocol: protocol!("ethernet"),
+            field: FieldName::sample(fraction),
+            op: BinOp::Eq,
+            value: Value::Int(1),
+        }))
+    }
+
+    fn parse_rate(pair: Pair<Rule>) -> Result<Value> {
+        let pair_str = pair.as_str().to_string();
+        let mut inner = pair.into_inner();
+        let count = inner.next().unwrap().as_str().parse::<u64>()?;
+        let window = inner.next().unwrap().as_str().parse::<u64>()?;
+        let unit: u64 = match inner.next().unwrap().as_str() {
+            "ms" => 1,
+            "s" => 1000,
+            "m" => 60_000,
+            "h" => 3_600_000,
+            _ => bail!(FilterError::InvalidRhsValue(pair_str)),
+        };
+        let window = match window.checked_mul(unit) {
+            Some(window) if window > 0 => window,
+            _ => bail!(FilterError::InvalidRhsValue(pair_str)),
+        };
+        Ok(Value::Rate { count, window })
+    }
+
     fn parse_protocol(pair: Pair<Rule>) -> ProtocolName {
         protocol!(pair.as_str())
     }
@@ -199,9 +393,20 @@ impl FilterParser {
                 let ipnet = FilterParser::parse_ipv6(rhs.into_inner())?;
                 Ok(Value::Ipv6(ipnet))
             }
-            Rule::int_lit => {
-                let val = rhs.as_str().parse::<u64>()?;
-                Ok(Value::Int(val))
+            Rule::int_lit | Rule::hex_lit => Ok(Value::Int(FilterParser::parse_int(rhs)?)),
+            Rule::size_lit => {
+                let mut inner = rhs.into_inner();
+                let val = inner.next().unwrap().as_str().parse::<u64>()?;
+                let unit = match inner.next().unwrap().as_str() {
+                    "KB" => 1_000,
+                    "MB" => 1_000_000,
+                    "GB" => 1_000_000_000,
+                    _ => bail!(FilterError::InvalidRhsValue(pair_str)),
+                };
+                match val.checked_mul(unit) {
+                    Some(val) => Ok(Value::Int(val)),
+                    None => bail!(FilterError::InvalidRhsValue(pair_str)),
+                }
             }
             Rule::text => {
                 // str_lit is a silent rule, parses directly to Rule::text
@@ -214,10 +419,21 @@ impl FilterParser {
                     to: *range.end(),
                 })
             }
+            Rule::set_ref => {
+                let name = rhs.into_inner().next().unwrap();
+                Ok(Value::Set(name.as_str().to_owned()))
+            }
             _ => bail!(FilterError::InvalidRhsType(pair_str)),
         }
     }
 
+    fn parse_int(pair: Pair<Rule>) -> Result<u64> {
+        match pair.as_rule() {
+            Rule::hex_lit => Ok(u64::from_str_radix(&pair.as_str()[2..], 16)?),
+            _ => Ok(pair.as_str().parse::<u64>()?),
+        }
+    }
+
     fn parse_ipv4(mut ipv4_lit: Pairs<Rule>) -> Result<Ipv4Net> {
         let ipv4_addr = ipv4_lit.next().unwrap();
         let ip = Ipv4Addr::from_str(ipv4_addr.as_str())?;
@@ -69,13 +69,21 @@ impl FlatPattern {
 
         let (layers, labels) = (&*LAYERS, &*NODE_BIMAP);
 
+        // Sampling predicates are not tied to a header, and are checked at the network layer
+        let (sample_predicates, predicates): (Vec<&Predicate>, Vec<&Predicate>) =
+            self.predicates.iter().partition(|p| p.is_sample());
+
         let mut node_paths: HashSet<Vec<NodeIndex>> = HashSet::new();
-        let headers = self
-            .predicates
+        let headers = predicates
             .iter()
             .map(|c| c.get_protocol())
             .collect::<HashSet<_>>();
-        for header in headers.iter() {
+        let network_headers = [protocol!("ipv4"), protocol!("ipv6")];
+        let mut path_headers = headers.clone();
+        if path_headers.is_empty() {
+            path_headers.extend(network_headers.iter());
+        }
+        for header in path_headers.iter() {
             match labels.get_by_right(header) {
                 Some(node) => {
                     let ethernet = labels
@@ -107,13 +115,19 @@ impl FlatPattern {
             let fq_headers: HashSet<&ProtocolName> = fq_path.iter().clone().collect();
             if headers.is_subset(&fq_headers) {
                 let mut fq_pattern = LayeredPattern::new();
-                for protocol in fq_path.iter() {
-                    let proto_predicates = self
-                        .predicates
+                for (i, protocol) in fq_path.iter().enumerate() {
+                    let mut proto_predicates = predicates
                         .iter()
                         .filter(|c| c.get_protocol() == protocol && c.is_binary())
-                        .map(|c| c.to_owned())
+                        .map(|&c| c.to_owned())
                         .collect::<HashSet<_>>();
+                    if i == 0 {
+                        proto_predicates.extend(
+                            sample_predicates
+                                .iter()
+                                .map(|c| c.with_protocol(protocol.to_owned())),
+                        );
+                    }
 
                     let mut proto_predicates = proto_predicates.into_iter().collect::<Vec<_>>();
                     proto_predicates.sort();
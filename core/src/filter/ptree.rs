@@ -492,6 +492,14 @@ impl PTree {
         Some(callbacks.iter().next().unwrap().clone())
     }
 
+    // Returns `true` if any node checks connection volume, MPLS labels, or VLAN tags
+    fn has_conn_preds(&self) -> bool {
+        fn has_conn_preds(node: &PNode) -> bool {
+            node.pred.on_conn() || node.children.iter().any(has_conn_preds)
+        }
+        has_conn_preds(&self.root)
+    }
+
     // Remove all nodes and callbacks from the tree
     fn clear(&mut self) {
         let pred = Predicate::Unary {
@@ -615,7 +623,11 @@ impl PTree {
             }
             // Tree layer is only drop/keep (i.e., one condition),
             // and condition checked at prev. layer
-            while node.children.len() == 1 && node.children[0].pred.on_packet() {
+            while node.children.len() == 1
+                && node.children[0].pred.on_packet()
+                && !(node.children[0].pred.on_conn()
+                    && matches!(filter_layer, FilterLayer::ConnectionDeliver))
+            {
                 // If the protocol needs to be extracted, can't remove node
                 // Look for unary predicate (e.g., `ipv4`) and child with
                 // binary predicate of same protocol (e.g., `ipv4.addr = ...`)
@@ -715,7 +727,8 @@ impl PTree {
             // determined that delivery is needed at the corresponding stage.
             // If disambiguation is not needed (i.e., only one possible delivery
             // outcome), then no filter condition is needed.
-            if let Some(deliver) = self.get_single_callback() {
+            // Connection volume, MPLS, and VLAN predicates are always checked at delivery.
+            if let (Some(deliver), false) = (self.get_single_callback(), self.has_conn_preds()) {
                 self.clear();
                 self.root.deliver.insert(deliver);
                 self.update_size();
@@ -792,6 +805,88 @@ impl PTree {
         s
     }
 
+    /// Returns the tree as a Graphviz DOT graph. Nodes are labelled with their predicate, actions
+    /// and deliveries; edges to nodes that are mutually exclusive with their preceding sibling
+    /// (`else if` branches in the generated code) are dashed.
+    pub fn to_dot(&self) -> String {
+        fn to_dot(s: &mut String, node: &PNode) {
+            let mut lines = vec![format!("{}: {}", node.id, node.pred)];
+            if !node.actions.drop() {
+                lines.push(format!("A: {:?}", node.actions.data));
+                if !node.actions.terminal_actions.is_none() {
+                    lines.push(format!("T: {:?}", node.actions.terminal_actions));
+                }
+            }
+            let mut deliver: Vec<_> = node.deliver.iter().map(|d| d.as_str.as_str()).collect();
+            deliver.sort();
+            lines.extend(deliver.iter().map(|d| format!("D: {}", d)));
+            let label: Vec<_> = lines
+                .iter()
+                .map(|line| line.replace('\\', "\\\\").replace('"', "\\\""))
+                .collect();
+            s.push_str(&format!(
+                "    n{} [label=\"{}\"];\n",
+                node.id,
+                label.join("\\n")
+            ));
+            for child in &node.children {
+                let style = if child.if_else { " [style=dashed]" } else { "" };
+                s.push_str(&format!("    n{} -> n{}{};\n", node.id, child.id, style));
+                to_dot(s, child);
+            }
+        }
+
+        let mut s = format!(
+            "digraph \"{}\" {{\n    node [shape=box];\n",
+            self.filter_layer
+        );
+        to_dot(&mut s, &self.root);
+        s.push_str("}\n");
+        s
+    }
+
+    /// Returns a human-readable explanation of the tree: for each node that applies actions or
+    /// delivers subscriptions, the predicates evaluated to reach it and the outcome of a match.
+    pub fn explain(&self) -> String {
+        fn explain(s: &mut String, node: &PNode, path: &mut Vec<String>) {
+            let pushed = *node.pred.get_protocol() != protocol!("ethernet");
+            if pushed {
+                path.push(node.pred.to_string());
+            }
+            if !node.actions.drop() || !node.deliver.is_empty() {
+                match path.is_empty() {
+                    true => s.push_str("  (all traffic)\n"),
+                    false => s.push_str(&format!("  {}\n", path.join(" and "))),
+                }
+                if !node.actions.drop() {
+                    s.push_str(&format!(
+                        "    => actions {:?}, terminal {:?}\n",
+                        node.actions.data, node.actions.terminal_actions
+                    ));
+                }
+                let mut deliver: Vec<_> = node.deliver.iter().map(|d| d.as_str.as_str()).collect();
+                deliver.sort();
+                for d in deliver {
+                    s.push_str(&format!("    => deliver {}\n", d));
+                }
+            }
+            for child in &node.children {
+                explain(s, child, path);
+            }
+            if pushed {
+                path.pop();
+            }
+        }
+
+        let mut s = format!("{} filter ({} nodes)\n", self.filter_layer, self.size);
+        let len = s.len();
+        explain(&mut s, &self.root, &mut vec![]);
+        if s.len() == len {
+            s.push_str("  (no match)\n");
+        }
+        s
+    }
+
     // Displays the conditions in the PTree as a filter string
     pub fn to_filter_string(&self) -> String {
         fn to_filter_string(p: &PNode, all: &mut Vec<String>, curr: String) {
@@ -1196,6 +1291,27 @@ mod tests {
         assert!(ptree.size == 1);
     }
 
+    #[test]
+    fn core_ptree_explain() {
+        let filter = Filter::new("tls.sni = 'abc'").unwrap();
+        let mut ptree = PTree::new_empty(FilterLayer::Session);
+        ptree.add_filter(
+            &filter.get_patterns_flat(),
+            &SubscriptionSpec::new_default_connection(),
+            &DELIVER,
+        );
+        ptree.collapse();
+
+        let explain = ptree.explain();
+        assert!(explain.contains("tls.sni"));
+        assert!(explain.contains("=> actions"));
+
+        let dot = ptree.to_dot();
+        assert!(dot.starts_with("digraph"));
+        assert!(dot.contains("n0 -> "));
+        assert!(dot.contains("tls.sni"));
+    }
+
     #[test]
     fn core_ptree_neq() {
         let filters = vec![
@@ -1236,4 +1352,179 @@ mod tests {
             ptree_2.get_subtree(3).unwrap().children.is_empty()
         );
     }
+
+    #[test]
+    fn core_ptree_conn_volume() {
+        let filter = Filter::new("tcp.port = 443 and conn.bytes > 10MB").unwrap();
+        let patterns = filter.get_patterns_flat();
+        assert!(patterns
+            .iter()
+            .all(|p| p.predicates.last().unwrap().to_string() == "conn.bytes > 10000000"));
+
+        // Volume is checked at delivery, even with a single callback
+        let spec = SubscriptionSpec::new_default_connection();
+        let mut ptree = PTree::new_empty(FilterLayer::ConnectionDeliver);
+        ptree.add_filter(&patterns, &spec, &DELIVER);
+        ptree.collapse();
+        assert!(ptree.to_filter_string().contains("conn.bytes > 10000000"));
+    }
+
+    #[test]
+    fn core_parser_named_values() {
+        let filter = Filter::new("tls.version = 'TLS1.0' and tls.cipher in {0x002f, 53}").unwrap();
+        let preds: Vec<String> = filter
+            .get_patterns_flat()
+            .iter()
+            .flat_map(|p| p.predicates.iter().map(|p| p.to_string()))
+            .collect();
+        assert!(preds.contains(&"tls.version = 769".to_string()));
+        assert!(preds.contains(&"tls.cipher_id = 47".to_string()));
+        assert!(preds.contains(&"tls.cipher_id = 53".to_string()));
+        assert!(Filter::new("tls.cipher = {47, 53}").is_err());
+
+        let filter = Filter::new("dns.qtype = 'TXT' or dns.rcode = 'nxdomain'").unwrap();
+        let preds: Vec<String> = filter
+            .get_patterns_flat()
+            .iter()
+            .map(|p| p.predicates.last().unwrap().to_string())
+            .collect();
+        assert!(preds.contains(&"dns.qtype = 16".to_string()));
+        assert!(preds.contains(&"dns.rcode = 3".to_string()));
+    }
+
+    #[test]
+    fn core_filter_volume_limits() {
+        let filter =
+            Filter::new("tcp.port = 80 and conn.packets < 10 and conn.packets <= 20").unwrap();
+        assert_eq!(
+            filter.volume_limits(),
+            VolumeLimits {
+                bytes: None,
+                packets: Some(9),
+            }
+        );
+        // Either pattern may match
+        let filter = Filter::new("conn.bytes in 0..1000 or (tcp and conn.bytes = 5KB)").unwrap();
+        assert_eq!(filter.volume_limits().bytes, Some(5000));
+        // Lower bounds and filters without volume predicates are unbounded
+        let filter = Filter::new("tcp and conn.bytes < 1000 or udp").unwrap();
+        assert_eq!(filter.volume_limits(), VolumeLimits::default());
+        let filter = Filter::new("conn.bytes > 1000").unwrap();
+        assert_eq!(filter.volume_limits(), VolumeLimits::default());
+        assert_eq!(
+            VolumeLimits {
+                bytes: Some(10),
+                packets: Some(1),
+            }
+            .union(VolumeLimits {
+                bytes: Some(20),
+                packets: None,
+            }),
+            VolumeLimits {
+                bytes: Some(20),
+                packets: None,
+            }
+        );
+    }
+
+    #[test]
+    fn core_parser_flag() {
+        let filter = Filter::new("ipv6.has_ext('routing') or ipv6.frag").unwrap();
+        let patterns = filter.get_patterns_flat();
+        assert!(patterns.len() == 2);
+        let preds: Vec<String> = patterns
+            .iter()
+            .map(|p| p.predicates.last().unwrap().to_string())
+            .collect();
+        assert!(preds.contains(&"ipv6.has_ext('routing') = 1".to_string()));
+        assert!(preds.contains(&"ipv6.frag = 1".to_string()));
+        assert!(Filter::new("ipv6.has_ext()").is_err());
+        // Accessors that take an argument are not flags on their own
+        assert!(Filter::new("ipv6.has_ext").is_err());
+        assert!(Filter::new("http.has_header").is_err());
+        // Only known extension headers are tested
+        assert!(Filter::new("ipv6.has_ext('bogus')").is_err());
+        assert!(Filter::new("ipv6.has_ext('bogus') = 1").is_err());
+        assert!(Filter::new("ipv6.has_ext('dst_opts') = 0").is_ok());
+        // Only flags can be written without a comparison
+        assert!(Filter::new("tcp.syn").is_ok());
+        assert!(Filter::new("tcp.port").is_err());
+        assert!(Filter::new("ipv4.ttl").is_err());
+
+        let filter = Filter::new("http.header('x-forwarded-for') exists").unwrap();
+        let pred = filter.get_patterns_flat()[0]
+            .predicates
+            .last()
+            .unwrap()
+            .clone();
+        assert!(pred.to_string() == "http.has_header('x-forwarded-for') = 1");
+        let filter = Filter::new("http.header('host') ~ 'internal'").unwrap();
+        let pred = filter.get_patterns_flat()[0]
+            .predicates
+            .last()
+            .unwrap()
+            .clone();
+        assert!(pred.on_session() && pred.to_string().starts_with("http.header('host')"));
+    }
+
+    #[test]
+    fn core_parser_ttl() {
+        let filter = Filter::new("ipv4.ttl < 64").unwrap();
+        let patterns = filter.get_patterns_flat();
+        assert!(patterns.len() == 1);
+        let pred = patterns[0].predicates.last().unwrap();
+        assert!(pred.on_packet());
+        assert_eq!(pred.to_string(), "ipv4.ttl < 64");
+
+        // Packets are delivered by the packet filter: eth -> ipv4 -> ipv4.ttl < 64
+        let spec = SubscriptionSpec::new_default_packet();
+        let mut ptree = PTree::new_empty(FilterLayer::PacketContinue);
+        ptree.add_filter(&patterns, &spec, &DELIVER);
+        ptree.collapse();
+        assert!(ptree.size == 3);
+        assert!(!ptree.get_subtree(2).unwrap().deliver.is_empty());
+    }
+
+    #[test]
+    fn core_parser_rate() {
+        let filter = Filter::new("rate(ipv4.src_addr, tcp.syn) > 100/60s").unwrap();
+        let patterns = filter.get_patterns_flat();
+        // Key header is required: ipv4 -> tcp -> rate
+        assert!(patterns.len() == 1);
+        assert!(patterns[0].predicates.len() == 3);
+        assert!(patterns[0].predicates[2]
+            .to_string()
+            .starts_with("rate(ipv4.src_addr, tcp.syn) > 100/60000ms"));
+
+        let spec = SubscriptionSpec::new_default_packet();
+        let mut ptree = PTree::new_empty(FilterLayer::PacketContinue);
+        ptree.add_filter(&patterns, &spec, &DELIVER);
+        ptree.collapse();
+        // Filter string must be re-parsable for the hardware filter
+        assert!(Filter::new(&ptree.to_filter_string()).is_ok());
+        assert!(Filter::new("rate(ipv4.src_addr) = 10/1s").is_err());
+        // Window overflows u64 milliseconds
+        assert!(Filter::new("rate(ipv4.src_addr) > 10/18446744073709552s").is_err());
+    }
+
+    #[test]
+    fn core_parser_sample() {
+        let filter = Filter::new("tcp and sample(0.01)").unwrap();
+        let patterns = filter.get_patterns_flat();
+        // ipv4 -> tcp, ipv6 -> tcp
+        assert!(patterns.len() == 2);
+        assert!(patterns.iter().all(|p| p
+            .predicates
+            .iter()
+            .any(|pred| pred.to_string() == "sample(0.01)" && !pred.req_packet())));
+
+        let spec = SubscriptionSpec::new_default_connection();
+        let mut ptree = PTree::new_empty(FilterLayer::PacketContinue);
+        ptree.add_filter(&patterns, &spec, &DELIVER);
+        ptree.collapse();
+        // Filter string must be re-parsable for the hardware filter
+        assert!(Filter::new(&ptree.to_filter_string()).is_ok());
+        assert!(Filter::new("sample(0)").is_err());
+        assert!(Filter::new("sample(1.5)").is_err());
+    }
 }
@@ -0,0 +1,179 @@
+//! Per-core state for stateful rate predicates.
+//!
+//! A rate predicate (e.g., `rate(ipv4.src_addr, tcp.syn) > 100/60s`) counts, per key, the number
+//! of packets for which an event occurred over a sliding time window. Counts are kept in a
+//! count-min sketch, so memory usage is constant regardless of the number of distinct keys, at the
+//! cost of occasionally over-estimating the count for a key.
+//!
+//! The retina_filtergen crate generates one `RateSketch` per distinct rate predicate and per core
+//! (as a thread-local), so no synchronization is required on the packet processing path. The
+//! predicate may be evaluated several times for the same packet (e.g., by several filter stages,
+//! or for several subscriptions), but each packet is counted once. Windows are advanced on the
+//! receive time of the packets (see [Mbuf::rx_time](crate::Mbuf::rx_time)), so rates are not
+//! skewed by processing delays, or when reading packets from a capture (whose timestamps are used
+//! instead).
+
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+/// Number of independent hash rows in the sketch.
+const SKETCH_DEPTH: usize = 4;
+/// Number of counters per hash row.
+const SKETCH_WIDTH: usize = 4096;
+
+thread_local! {
+    /// Sequence number of the packet being filtered on this core.
+    static PACKET: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Starts filtering a new packet on this core.
+#[inline]
+pub(crate) fn next_packet() {
+    PACKET.with(|packet| packet.set(packet.get().wrapping_add(1)));
+}
+
+/// A count-min sketch over a sliding window of `window` milliseconds.
+///
+/// The sliding window is approximated using two fixed windows: the count for a key is the count in
+/// the current window plus the count in the previous window, weighted by the fraction of the
+/// previous window that still overlaps the sliding window.
+#[derive(Debug)]
+pub struct RateSketch {
+    /// Window duration (in milliseconds).
+    window: u64,
+    /// Start time of the current window, set by the first observation.
+    window_start: Option<Instant>,
+    /// Counters for the current window.
+    current: Vec<u32>,
+    /// Counters for the previous window.
+    previous: Vec<u32>,
+    /// Sequence number of the last packet observed, and the estimate returned for it.
+    last: Option<(u64, u64)>,
+}
+
+impl RateSketch {
+    /// Creates a new sketch counting events over a window of `window` milliseconds.
+    pub fn new(window: u64) -> Self {
+        RateSketch {
+            window: window.max(1),
+            window_start: None,
+            current: vec![0; SKETCH_DEPTH * SKETCH_WIDTH],
+            previous: vec![0; SKETCH_DEPTH * SKETCH_WIDTH],
+            last: None,
+        }
+    }
+
+    /// Records an observation for `key` in the packet being filtered on this core, like
+    /// [observe](RateSketch::observe). Further observations of the same packet are not counted,
+    /// and return the same estimate.
+    #[inline]
+    pub fn observe_packet<K: Hash>(&mut self, key: &K, event: bool, now: Instant) -> u64 {
+        let packet = PACKET.with(Cell::get);
+        if let Some((last, count)) = self.last {
+            if last == packet {
+                return count;
+            }
+        }
+        let count = self.observe(key, event, now);
+        self.last = Some((packet, count));
+        count
+    }
+
+    /// Records an observation for `key` at time `now`, incrementing its count if `event` is
+    /// `true`.
+    ///
+    /// Returns the estimated number of events for `key` in the sliding window, including this
+    /// observation.
+    #[inline]
+    pub fn observe<K: Hash>(&mut self, key: &K, event: bool, now: Instant) -> u64 {
+        let elapsed = self.rotate(now);
+        let mut cur = u32::MAX;
+        let mut prev = u32::MAX;
+        for row in 0..SKETCH_DEPTH {
+            let idx = row * SKETCH_WIDTH + Self::bucket(key, row);
+            if event {
+                self.current[idx] = self.current[idx].saturating_add(1);
+            }
+            cur = cur.min(self.current[idx]);
+            prev = prev.min(self.previous[idx]);
+        }
+        let overlap = (self.window - elapsed) as f64 / self.window as f64;
+        cur as u64 + (prev as f64 * overlap) as u64
+    }
+
+    /// Advances the window if it has expired. Returns the time (in milliseconds) elapsed in the
+    /// current window. Observations older than the current window (e.g., received on another
+    /// queue) are counted in the current window.
+    #[inline]
+    fn rotate(&mut self, now: Instant) -> u64 {
+        let window_start = *self.window_start.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(window_start).as_millis() as u64;
+        if elapsed < self.window {
+            return elapsed;
+        }
+        if elapsed < 2 * self.window {
+            // Current window becomes the previous window
+            std::mem::swap(&mut self.current, &mut self.previous);
+        } else {
+            // No observations in the last full window
+            self.previous.iter_mut().for_each(|c| *c = 0);
+        }
+        self.current.iter_mut().for_each(|c| *c = 0);
+        let elapsed = elapsed % self.window;
+        self.window_start = Some(now - Duration::from_millis(elapsed));
+        elapsed
+    }
+
+    #[inline]
+    fn bucket<K: Hash>(key: &K, row: usize) -> usize {
+        let mut hasher = DefaultHasher::new();
+        row.hash(&mut hasher);
+        key.hash(&mut hasher);
+        hasher.finish() as usize % SKETCH_WIDTH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn core_rate_sketch_count() {
+        let mut sketch = RateSketch::new(60_000);
+        let src = Ipv4Addr::new(10, 0, 0, 1);
+        let other = Ipv4Addr::new(10, 0, 0, 2);
+        let start = Instant::now();
+        for _ in 0..99 {
+            sketch.observe(&src, true, start);
+        }
+        assert_eq!(sketch.observe(&src, true, start), 100);
+        // Non-events are not counted
+        assert_eq!(sketch.observe(&src, false, start), 100);
+        assert_eq!(sketch.observe(&other, false, start), 0);
+        // Half of the previous window overlaps the sliding window
+        let later = start + Duration::from_millis(90_000);
+        assert_eq!(sketch.observe(&src, false, later), 50);
+        // No events in the last full window
+        let later = start + Duration::from_millis(180_000);
+        assert_eq!(sketch.observe(&src, false, later), 0);
+    }
+
+    #[test]
+    fn core_rate_sketch_shared() {
+        // A predicate shared by two subscriptions is evaluated twice per packet
+        let mut sketch = RateSketch::new(60_000);
+        let src = Ipv4Addr::new(10, 0, 0, 1);
+        let start = Instant::now();
+        for _ in 0..10 {
+            next_packet();
+            let first = sketch.observe_packet(&src, true, start);
+            let second = sketch.observe_packet(&src, true, start);
+            assert_eq!(first, second);
+        }
+        next_packet();
+        assert_eq!(sketch.observe_packet(&src, false, start), 10);
+    }
+}
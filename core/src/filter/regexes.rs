@@ -0,0 +1,185 @@
+//! Regular expressions of filter predicates.
+//!
+//! The retina_filtergen crate collects the regular expressions of all `~` predicates into a single
+//! [RegexDatabase](RegexDatabase), and each predicate matches its expression by index. By default,
+//! each expression is matched separately with the `regex` crate.
+//!
+//! With the `hyperscan` feature, the expressions are compiled into a single Hyperscan database
+//! (this requires libhs, which Vectorscan provides on platforms that Hyperscan does not support).
+//! A field value is then scanned once for all expressions, and the matches are cached per core for
+//! the other predicates on the same value, so that the cost of many regex filters no longer grows
+//! with their number. Expressions that Hyperscan does not support (e.g., that match the empty
+//! string) are matched with the `regex` crate.
+
+use regex::Regex;
+
+/// The regular expressions of the filter predicates.
+#[derive(Debug)]
+pub struct RegexDatabase {
+    regexes: Vec<Regex>,
+    #[cfg(feature = "hyperscan")]
+    hyperscan: Option<hs::Hyperscan>,
+}
+
+impl RegexDatabase {
+    /// Compiles `exprs`. Panics if an expression is invalid, which the retina_filtergen crate
+    /// checks at compile time.
+    pub fn new(exprs: &[&str]) -> Self {
+        let regexes = exprs.iter().map(|expr| Regex::new(expr).unwrap()).collect();
+        RegexDatabase {
+            regexes,
+            #[cfg(feature = "hyperscan")]
+            hyperscan: hs::Hyperscan::new(exprs),
+        }
+    }
+
+    /// Returns `true` if expression `id` matches `text`.
+    #[inline]
+    pub fn is_match(&self, id: usize, text: &str) -> bool {
+        #[cfg(feature = "hyperscan")]
+        if let Some(hyperscan) = &self.hyperscan {
+            if let Some(is_match) = hyperscan.is_match(id, text) {
+                return is_match;
+            }
+        }
+        self.regexes[id].is_match(text)
+    }
+}
+
+#[cfg(feature = "hyperscan")]
+mod hs {
+    use hyperscan::prelude::*;
+    use hyperscan::PatternFlags;
+
+    use std::cell::RefCell;
+    use std::fmt;
+
+    /// Matches of the last value scanned on this core.
+    struct ScanCache {
+        /// Address of the database that scanned `text`.
+        database: usize,
+        scratch: Option<Scratch>,
+        text: Vec<u8>,
+        matches: Vec<bool>,
+    }
+
+    thread_local! {
+        static CACHE: RefCell<ScanCache> = const {
+            RefCell::new(ScanCache {
+                database: 0,
+                scratch: None,
+                text: Vec::new(),
+                matches: Vec::new(),
+            })
+        };
+    }
+
+    pub(super) struct Hyperscan {
+        database: BlockDatabase,
+        /// Whether each expression is in the database.
+        supported: Vec<bool>,
+    }
+
+    impl Hyperscan {
+        /// Compiles the expressions that Hyperscan supports, if any.
+        pub(super) fn new(exprs: &[&str]) -> Option<Self> {
+            let mut supported = vec![false; exprs.len()];
+            let mut patterns = vec![];
+            for (id, expr) in exprs.iter().enumerate() {
+                let flags = PatternFlags::UTF8 | PatternFlags::UCP | PatternFlags::SINGLEMATCH;
+                let mut pattern = match Pattern::with_flags(*expr, flags) {
+                    Ok(pattern) => pattern,
+                    Err(_) => continue,
+                };
+                pattern.id = Some(id);
+                if pattern.build::<Block>().is_err() {
+                    log::info!("Regex {} not supported by Hyperscan", expr);
+                    continue;
+                }
+                supported[id] = true;
+                patterns.push(pattern);
+            }
+            if patterns.is_empty() {
+                return None;
+            }
+            let database = match patterns.into_iter().collect::<Patterns>().build() {
+                Ok(database) => database,
+                Err(err) => {
+                    log::warn!("Failed to compile Hyperscan database: {:?}", err);
+                    return None;
+                }
+            };
+            Some(Hyperscan {
+                database,
+                supported,
+            })
+        }
+
+        /// Returns whether expression `id` matches `text`, or `None` if it is not in the database.
+        #[inline]
+        pub(super) fn is_match(&self, id: usize, text: &str) -> Option<bool> {
+            if !self.supported[id] {
+                return None;
+            }
+            CACHE.with(|cache| {
+                let mut cache = cache.borrow_mut();
+                let database = self as *const Hyperscan as usize;
+                if cache.database != database {
+                    cache.database = database;
+                    cache.scratch = Some(self.database.alloc_scratch().ok()?);
+                    cache.text.clear();
+                    cache.matches = vec![false; self.supported.len()];
+                    self.scan(&mut *cache, text)?;
+                } else if cache.text != text.as_bytes() {
+                    self.scan(&mut *cache, text)?;
+                }
+                Some(cache.matches[id])
+            })
+        }
+
+        // Scans `text` for all expressions
+        fn scan(&self, cache: &mut ScanCache, text: &str) -> Option<()> {
+            let ScanCache {
+                scratch,
+                text: scanned,
+                matches,
+                ..
+            } = cache;
+            matches.iter_mut().for_each(|is_match| *is_match = false);
+            scanned.clear();
+            let result = self
+                .database
+                .scan(text, scratch.as_ref()?, |id, _from, _to, _flags| {
+                    matches[id as usize] = true;
+                    Matching::Continue
+                });
+            if result.is_err() {
+                return None;
+            }
+            scanned.extend_from_slice(text.as_bytes());
+            Some(())
+        }
+    }
+
+    impl fmt::Debug for Hyperscan {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Hyperscan")
+                .field("supported", &self.supported)
+                .finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_regexes_match() {
+        let regexes = RegexDatabase::new(&["^adm", "netflix"]);
+        assert!(regexes.is_match(0, "admin"));
+        assert!(!regexes.is_match(0, "sysadmin"));
+        assert!(regexes.is_match(1, "www.netflix.com"));
+        assert!(!regexes.is_match(1, "www.example.com"));
+    }
+}
@@ -0,0 +1,70 @@
+//! Stateless connection sampling predicates.
+//!
+//! A sampling predicate (e.g., `sample(0.01)`) matches a fixed fraction of connections. Whether a
+//! connection is sampled is decided by a hash of its 5-tuple that does not depend on the direction
+//! of the packet, so every packet of the connection, at every filter stage and on every core,
+//! reaches the same decision without keeping any state.
+
+use crate::conntrack::pdu::L4Context;
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::stream::ConnData;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+
+/// Returns `true` if the connection of the packet in `mbuf` is sampled, with probability
+/// `fraction`. Packets without a transport-layer context are never sampled.
+#[inline]
+pub fn sample_packet(mbuf: &Mbuf, fraction: f64) -> bool {
+    match L4Context::new(mbuf) {
+        Ok(ctxt) => is_sampled(ctxt.src, ctxt.dst, ctxt.proto, fraction),
+        Err(_) => false,
+    }
+}
+
+/// Returns `true` if `conn` is sampled, with probability `fraction`.
+#[inline]
+pub fn sample_conn(conn: &ConnData, fraction: f64) -> bool {
+    let five_tuple = &conn.five_tuple;
+    is_sampled(five_tuple.orig, five_tuple.resp, five_tuple.proto, fraction)
+}
+
+#[inline]
+fn is_sampled(src: SocketAddr, dst: SocketAddr, proto: usize, fraction: f64) -> bool {
+    // `DefaultHasher::new` uses fixed keys, so all cores agree on the hash
+    let mut hasher = DefaultHasher::new();
+    if src <= dst {
+        (src, dst, proto).hash(&mut hasher);
+    } else {
+        (dst, src, proto).hash(&mut hasher);
+    }
+    (hasher.finish() as f64) < fraction * u64::MAX as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_sample_symmetric() {
+        let a: SocketAddr = "10.0.0.1:51000".parse().unwrap();
+        let b: SocketAddr = "10.0.0.2:443".parse().unwrap();
+        for fraction in [0.1, 0.5, 0.9] {
+            assert_eq!(is_sampled(a, b, 6, fraction), is_sampled(b, a, 6, fraction));
+        }
+        assert!(is_sampled(a, b, 6, 1.0));
+    }
+
+    #[test]
+    fn core_sample_fraction() {
+        let dst: SocketAddr = "10.0.0.2:443".parse().unwrap();
+        let sampled = (0..10000u16)
+            .filter(|port| {
+                let src = SocketAddr::new("10.0.0.1".parse().unwrap(), 40000 + port);
+                is_sampled(src, dst, 6, 0.1)
+            })
+            .count();
+        assert!((800..1200).contains(&sampled), "sampled {}", sampled);
+    }
+}
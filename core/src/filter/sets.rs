@@ -0,0 +1,329 @@
+//! Filter sets.
+//!
+//! A filter can test a field against a named set whose members change while the runtime is
+//! running, e.g., `ipv4.src_addr in $blocklist` or `tls.sni in $watched_domains`. A set holds IP
+//! addresses and networks, integers, and strings, and a field is in the set if it matches any
+//! member of its type. Sets are empty when the runtime starts, and are updated with
+//! [insert](insert), [remove](remove), and [replace](replace) from any thread (or over the control
+//! plane, see [ControlConfig](crate::config::ControlConfig)). Sets can also be loaded from files
+//! listed in the runtime configuration (see [RuntimeConfig](crate::config::RuntimeConfig)'s
+//! `filter_sets`), which are read again on SIGHUP.
+//!
+//! Changes apply to subsequent filter evaluations: connections that a filter stage has already
+//! matched or discarded are not evaluated again. Sets are never offloaded to hardware filters.
+//!
+//! Each generated predicate holds a [FilterSet](FilterSet), which looks up its set once, so testing
+//! membership on the packet processing path takes an uncontended read lock.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+use anyhow::{bail, Result};
+use ipnet::IpNet;
+
+/// Members of each set, by name.
+static SETS: Mutex<BTreeMap<String, Arc<RwLock<Members>>>> = Mutex::new(BTreeMap::new());
+
+/// The members of a filter set.
+#[derive(Debug, Default)]
+pub struct Members {
+    addrs: HashSet<IpAddr>,
+    /// Networks with a prefix shorter than an address.
+    nets: Vec<IpNet>,
+    ints: HashSet<u64>,
+    text: HashSet<String>,
+}
+
+impl Members {
+    /// Adds `member`, parsed as an IP address or network (`10.0.0.0/8`), an integer (decimal, or
+    /// hexadecimal with a `0x` prefix), or a string otherwise.
+    fn insert(&mut self, member: &str) {
+        if let Ok(addr) = IpAddr::from_str(member) {
+            self.addrs.insert(addr);
+        } else if let Ok(net) = IpNet::from_str(member) {
+            if net.prefix_len() == net.max_prefix_len() {
+                self.addrs.insert(net.addr());
+            } else if !self.nets.contains(&net.trunc()) {
+                self.nets.push(net.trunc());
+            }
+        } else if let Some(int) = parse_int(member) {
+            self.ints.insert(int);
+        } else {
+            self.text.insert(member.to_string());
+        }
+    }
+
+    /// Removes `member`, parsed as in [insert](Members::insert).
+    fn remove(&mut self, member: &str) {
+        if let Ok(addr) = IpAddr::from_str(member) {
+            self.addrs.remove(&addr);
+        } else if let Ok(net) = IpNet::from_str(member) {
+            self.addrs.remove(&net.addr());
+            self.nets.retain(|n| *n != net.trunc());
+        } else if let Some(int) = parse_int(member) {
+            self.ints.remove(&int);
+        } else {
+            self.text.remove(member);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.addrs.len() + self.nets.len() + self.ints.len() + self.text.len()
+    }
+
+    /// Returns the members, formatted as they are inserted, in sorted order.
+    fn to_strings(&self) -> Vec<String> {
+        let mut members: Vec<String> = self
+            .addrs
+            .iter()
+            .map(|addr| addr.to_string())
+            .chain(self.nets.iter().map(|net| net.to_string()))
+            .chain(self.ints.iter().map(|int| int.to_string()))
+            .chain(self.text.iter().cloned())
+            .collect();
+        members.sort();
+        members
+    }
+
+    fn contains_addr(&self, addr: IpAddr) -> bool {
+        self.addrs.contains(&addr) || self.nets.iter().any(|net| net.contains(&addr))
+    }
+}
+
+fn parse_int(member: &str) -> Option<u64> {
+    match member.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => member.parse().ok(),
+    }
+}
+
+/// A field value that can be tested for membership in a filter set.
+pub trait SetMember {
+    /// Returns `true` if `self` is in `members`.
+    fn is_in(&self, members: &Members) -> bool;
+}
+
+impl SetMember for IpAddr {
+    fn is_in(&self, members: &Members) -> bool {
+        members.contains_addr(*self)
+    }
+}
+
+impl SetMember for Ipv4Addr {
+    fn is_in(&self, members: &Members) -> bool {
+        members.contains_addr(IpAddr::V4(*self))
+    }
+}
+
+impl SetMember for Ipv6Addr {
+    fn is_in(&self, members: &Members) -> bool {
+        members.contains_addr(IpAddr::V6(*self))
+    }
+}
+
+macro_rules! int_member {
+    ($($ty:ty),*) => {
+        $(
+            impl SetMember for $ty {
+                fn is_in(&self, members: &Members) -> bool {
+                    members.ints.contains(&(*self as u64))
+                }
+            }
+        )*
+    };
+}
+
+int_member!(u8, u16, u32, u64, usize);
+
+impl SetMember for str {
+    fn is_in(&self, members: &Members) -> bool {
+        members.text.contains(self)
+    }
+}
+
+impl SetMember for String {
+    fn is_in(&self, members: &Members) -> bool {
+        members.text.contains(self)
+    }
+}
+
+impl<T: SetMember + ?Sized> SetMember for &T {
+    fn is_in(&self, members: &Members) -> bool {
+        (**self).is_in(members)
+    }
+}
+
+/// A filter set referenced by a predicate. Declared as a `static` by the generated code.
+#[derive(Debug)]
+pub struct FilterSet {
+    name: &'static str,
+    members: OnceLock<Arc<RwLock<Members>>>,
+}
+
+impl FilterSet {
+    pub const fn new(name: &'static str) -> Self {
+        FilterSet {
+            name,
+            members: OnceLock::new(),
+        }
+    }
+
+    /// Returns `true` if `value` is in the set.
+    #[inline]
+    pub fn contains<T: SetMember + ?Sized>(&self, value: &T) -> bool {
+        let members = self.members.get_or_init(|| set(self.name));
+        value.is_in(&members.read().unwrap())
+    }
+}
+
+fn set(name: &str) -> Arc<RwLock<Members>> {
+    let mut sets = SETS.lock().unwrap();
+    let set = sets.entry(name.to_string()).or_default();
+    Arc::clone(set)
+}
+
+/// Registers the sets referenced by the filter. Called once when the [Runtime](crate::Runtime) is
+/// created.
+pub(crate) fn register(names: &[&str]) {
+    for name in names {
+        set(name);
+    }
+}
+
+fn get(name: &str) -> Result<Arc<RwLock<Members>>> {
+    match SETS.lock().unwrap().get(name) {
+        Some(set) => Ok(Arc::clone(set)),
+        None => bail!("Unknown filter set: {}", name),
+    }
+}
+
+/// Adds `members` to set `name`. Members are IP addresses or networks (e.g., `10.0.0.0/8`),
+/// integers (decimal, or hexadecimal with a `0x` prefix), or strings. Returns an error if no
+/// filter references set `name`.
+pub fn insert<S: AsRef<str>>(name: &str, members: &[S]) -> Result<()> {
+    let set = get(name)?;
+    let mut set = set.write().unwrap();
+    for member in members {
+        set.insert(member.as_ref());
+    }
+    log::info!("Filter set {}: {} members", name, set.len());
+    Ok(())
+}
+
+/// Removes `members` from set `name`. Returns an error if no filter references set `name`.
+pub fn remove<S: AsRef<str>>(name: &str, members: &[S]) -> Result<()> {
+    let set = get(name)?;
+    let mut set = set.write().unwrap();
+    for member in members {
+        set.remove(member.as_ref());
+    }
+    log::info!("Filter set {}: {} members", name, set.len());
+    Ok(())
+}
+
+/// Replaces the members of set `name` with `members`. Returns an error if no filter references set
+/// `name`.
+pub fn replace<S: AsRef<str>>(name: &str, members: &[S]) -> Result<()> {
+    let set = get(name)?;
+    let mut replaced = Members::default();
+    for member in members {
+        replaced.insert(member.as_ref());
+    }
+    let len = replaced.len();
+    *set.write().unwrap() = replaced;
+    log::info!("Filter set {}: {} members", name, len);
+    Ok(())
+}
+
+/// Replaces the members of set `name` with those listed in file `path`, one per line. Blank lines
+/// and lines starting with `#` are ignored. Returns an error if the file cannot be read, or if no
+/// filter references set `name`.
+pub fn load<P: AsRef<Path>>(name: &str, path: P) -> Result<()> {
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(error) => bail!(
+            "Failed to read filter set {} from {}: {}",
+            name,
+            path.as_ref().display(),
+            error
+        ),
+    };
+    replace(name, &parse_members(&contents))
+}
+
+fn parse_members(contents: &str) -> Vec<&str> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// Returns the members of set `name`, in sorted order, or `None` if no filter references set
+/// `name`.
+pub fn members(name: &str) -> Option<Vec<String>> {
+    let set = get(name).ok()?;
+    let members = set.read().unwrap().to_strings();
+    Some(members)
+}
+
+/// Returns the name and number of members of each set.
+pub fn sets() -> Vec<(String, usize)> {
+    SETS.lock()
+        .unwrap()
+        .iter()
+        .map(|(name, set)| (name.clone(), set.read().unwrap().len()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_filter_set_members() {
+        static SET: FilterSet = FilterSet::new("core_filter_set_members");
+        register(&["core_filter_set_members"]);
+        insert(
+            "core_filter_set_members",
+            &[
+                "10.0.0.0/8",
+                "192.168.1.1",
+                "2001:db8::/32",
+                "443",
+                "0x50",
+                "example.com",
+            ],
+        )
+        .unwrap();
+        assert!(SET.contains(&Ipv4Addr::new(10, 1, 2, 3)));
+        assert!(SET.contains(&Ipv4Addr::new(192, 168, 1, 1)));
+        assert!(!SET.contains(&Ipv4Addr::new(192, 168, 1, 2)));
+        assert!(SET.contains(&Ipv6Addr::from_str("2001:db8::1").unwrap()));
+        assert!(SET.contains(&443u16));
+        assert!(SET.contains(&80u16));
+        assert!(SET.contains("example.com"));
+        assert!(!SET.contains("example.org"));
+
+        remove("core_filter_set_members", &["10.0.0.0/8", "443"]).unwrap();
+        assert!(!SET.contains(&Ipv4Addr::new(10, 1, 2, 3)));
+        assert!(!SET.contains(&443u16));
+
+        replace("core_filter_set_members", &["example.org"]).unwrap();
+        assert!(!SET.contains("example.com"));
+        assert_eq!(
+            members("core_filter_set_members"),
+            Some(vec!["example.org".to_string()])
+        );
+        assert!(insert("core_filter_set_unknown", &["1.1.1.1"]).is_err());
+
+        assert_eq!(
+            parse_members("# blocklist\n10.0.0.1\n\n  example.com \n"),
+            vec!["10.0.0.1", "example.com"]
+        );
+    }
+}
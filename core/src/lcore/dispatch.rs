@@ -0,0 +1,75 @@
+//! Software packet dispatch.
+//!
+//! When the NIC cannot steer both directions of a connection to the same receive queue (e.g.,
+//! virtio devices without RSS, or NICs that only support non-symmetric keys), the RX cores of a
+//! port can instead act as dispatchers: they compute a symmetric flow hash in software and forward
+//! each packet to the ring of a worker core, which performs connection tracking. See
+//! [DispatchConfig](crate::config::DispatchConfig).
+
+use super::ring::Ring;
+use crate::conntrack::pdu::L4Context;
+use crate::conntrack::tunnel::Decap;
+use crate::memory::mbuf::Mbuf;
+
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// Role of an RX core in software dispatch.
+#[derive(Debug, Clone)]
+pub(crate) enum DispatchRole {
+    /// Processes the packets polled from its receive queues (no software dispatch).
+    None,
+    /// Polls its receive queues and forwards packets to worker cores.
+    Dispatcher(Arc<Dispatcher>),
+    /// Processes the packets forwarded to its ring by dispatcher cores.
+    Worker(Arc<Ring>),
+}
+
+/// Distributes packets to the rings of worker cores by flow hash.
+#[derive(Debug)]
+pub(crate) struct Dispatcher {
+    rings: Vec<Arc<Ring>>,
+}
+
+impl Dispatcher {
+    pub(crate) fn new(rings: Vec<Arc<Ring>>) -> Self {
+        assert!(!rings.is_empty());
+        Dispatcher { rings }
+    }
+
+    /// Forwards `mbufs` to the worker rings, and returns the number of packets dropped because a
+    /// ring was full. If `decap` is set, tunneled packets are hashed on their inner 5-tuple.
+    pub(crate) fn dispatch(&self, mbufs: Vec<Mbuf>, decap: Option<&Decap>) -> usize {
+        let mut bursts: Vec<Vec<Mbuf>> = vec![vec![]; self.rings.len()];
+        for mbuf in mbufs.into_iter() {
+            let idx = (flow_hash(&mbuf, decap) % self.rings.len() as u64) as usize;
+            bursts[idx].push(mbuf);
+        }
+        self.rings
+            .iter()
+            .zip(bursts)
+            .map(|(ring, burst)| ring.enqueue_mbufs(burst))
+            .sum()
+    }
+}
+
+/// Returns a hash of the 5-tuple of `mbuf` that is the same for both directions of a connection.
+/// Packets without a 5-tuple hash to `0`.
+fn flow_hash(mbuf: &Mbuf, decap: Option<&Decap>) -> u64 {
+    let ctxt = match decap {
+        Some(decap) => decap.flow_ctxt(mbuf),
+        None => L4Context::new(mbuf),
+    };
+    match ctxt {
+        Ok(ctxt) => {
+            let mut hasher = DefaultHasher::new();
+            cmp::max(ctxt.src, ctxt.dst).hash(&mut hasher);
+            cmp::min(ctxt.src, ctxt.dst).hash(&mut hasher);
+            ctxt.proto.hash(&mut hasher);
+            hasher.finish()
+        }
+        Err(_) => 0,
+    }
+}
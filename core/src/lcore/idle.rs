@@ -0,0 +1,150 @@
+//! Idle back-off.
+//!
+//! With an [IdleConfig](crate::config::IdleConfig), a packet processing core that finds its queues
+//! empty for a number of consecutive polls stops busy-polling, and pauses, sleeps, or waits for a
+//! receive interrupt between polls until packets arrive.
+
+use super::CoreId;
+use crate::config::{IdleConfig, IdleMode};
+use crate::dpdk;
+use crate::port::RxQueue;
+
+use std::cmp;
+use std::os::raw::c_int;
+use std::time::Duration;
+
+/// `RTE_EPOLL_PER_THREAD`: the epoll instance of the calling thread.
+const EPOLL_PER_THREAD: c_int = -1;
+/// `RTE_INTR_EVENT_ADD`: adds a queue's interrupt to an epoll instance.
+const INTR_EVENT_ADD: c_int = 1;
+/// Maximum number of interrupt events returned by a wait.
+const MAX_EVENTS: usize = 16;
+
+/// Backs off a core while its queues are empty.
+pub(crate) struct Idle {
+    config: IdleConfig,
+    /// Port and queue of each polled receive queue, if the core waits for receive interrupts.
+    interrupts: Vec<(u16, u16)>,
+    /// Number of consecutive empty polls.
+    empty_polls: u32,
+    /// Current sleep time.
+    sleep: Duration,
+}
+
+impl Idle {
+    pub(crate) fn new(config: &IdleConfig, core_id: CoreId, rxqueues: &[RxQueue]) -> Self {
+        let mut interrupts = vec![];
+        if config.mode == IdleMode::Interrupt {
+            for rxqueue in rxqueues.iter() {
+                let (pid, qid) = (rxqueue.pid.raw(), rxqueue.qid.raw());
+                let ret = unsafe {
+                    dpdk::rte_eth_dev_rx_intr_ctl_q(
+                        pid,
+                        qid,
+                        EPOLL_PER_THREAD,
+                        INTR_EVENT_ADD,
+                        std::ptr::null_mut(),
+                    )
+                };
+                if ret == 0 {
+                    interrupts.push((pid, qid));
+                } else {
+                    log::warn!(
+                        "Core {}: receive interrupts not supported on port {} queue {} ({}), sleeping instead.",
+                        core_id,
+                        pid,
+                        qid,
+                        ret
+                    );
+                    interrupts.clear();
+                    break;
+                }
+            }
+        }
+        Idle {
+            config: config.clone(),
+            interrupts,
+            empty_polls: 0,
+            sleep: Duration::from_micros(1),
+        }
+    }
+
+    /// Called once per polling iteration with the number of packets received.
+    #[inline]
+    pub(crate) fn poll(&mut self, nb_rx: usize) {
+        if nb_rx > 0 {
+            self.empty_polls = 0;
+            self.sleep = Duration::from_micros(1);
+            return;
+        }
+        if self.empty_polls < self.config.threshold {
+            self.empty_polls += 1;
+            return;
+        }
+        match self.config.mode {
+            IdleMode::Pause => unsafe { dpdk::rte_pause() },
+            IdleMode::Interrupt if !self.interrupts.is_empty() => self.wait_interrupt(),
+            IdleMode::Sleep | IdleMode::Interrupt => {
+                std::thread::sleep(self.sleep);
+                self.sleep = self.next_sleep();
+            }
+        }
+    }
+
+    /// Returns the sleep time after `self.sleep`, doubled up to `max_sleep`.
+    fn next_sleep(&self) -> Duration {
+        cmp::min(self.sleep * 2, Duration::from_micros(self.config.max_sleep))
+    }
+
+    /// Waits for a receive interrupt on any of the core's queues, or for `max_sleep`.
+    fn wait_interrupt(&mut self) {
+        for (pid, qid) in self.interrupts.iter() {
+            unsafe { dpdk::rte_eth_dev_rx_intr_enable(*pid, *qid) };
+        }
+        // Packets that arrived before the interrupts were enabled do not raise one
+        let pending = self
+            .interrupts
+            .iter()
+            .any(|(pid, qid)| unsafe { dpdk::rte_eth_rx_queue_count(*pid, *qid) } > 0);
+        if !pending {
+            let timeout = self.config.max_sleep.div_ceil(1000).max(1) as c_int;
+            let mut events: [dpdk::rte_epoll_event; MAX_EVENTS] = unsafe { std::mem::zeroed() };
+            unsafe {
+                dpdk::rte_epoll_wait(
+                    EPOLL_PER_THREAD,
+                    events.as_mut_ptr(),
+                    MAX_EVENTS as c_int,
+                    timeout,
+                )
+            };
+        }
+        for (pid, qid) in self.interrupts.iter() {
+            unsafe { dpdk::rte_eth_dev_rx_intr_disable(*pid, *qid) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_idle_backoff() {
+        let config = IdleConfig {
+            mode: IdleMode::Sleep,
+            threshold: 2,
+            max_sleep: 4,
+        };
+        let mut idle = Idle::new(&config, CoreId(1), &[]);
+        idle.poll(0);
+        idle.poll(0);
+        assert_eq!(idle.sleep, Duration::from_micros(1));
+        idle.poll(0);
+        idle.poll(0);
+        idle.poll(0);
+        assert_eq!(idle.sleep, Duration::from_micros(4));
+        idle.poll(1);
+        assert_eq!(idle.empty_polls, 0);
+        assert_eq!(idle.sleep, Duration::from_micros(1));
+    }
+}
@@ -0,0 +1,656 @@
+//! Runtime metrics.
+//!
+//! Packet processing cores record counters into per-core slots of a global registry, which the
+//! monitor aggregates and exports in the Prometheus text exposition format (see
+//! [PrometheusConfig](crate::config::PrometheusConfig)). Each core only writes to its own slot, so
+//! recording a metric does not contend with other cores.
+//!
+//! Applications can also receive periodic [RuntimeStats](RuntimeStats) snapshots by registering a
+//! callback with [Runtime::on_stats](crate::Runtime::on_stats), and the same counters can be read
+//! over the DPDK telemetry socket (see [RuntimeConfig](crate::config::RuntimeConfig)'s `telemetry`
+//! option).
+//!
+//! If profiling is enabled, the metrics also include the cycles each core spent in each stage of
+//! the pipeline (see [profile](super::profile)).
+
+use super::profile::STAGES;
+use super::{CoreId, MAX_CORES};
+use crate::dpdk;
+use crate::protocols::stream::{Session, SessionData};
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, Once};
+use std::time::{Duration, Instant};
+
+/// Application-layer protocols that parsed sessions are counted for.
+const SESSION_PROTOCOLS: [&str; 5] = ["tls", "dns", "http", "quic", "other"];
+
+/// Filter layers that matches are counted for. Matches of the packet filter are the packets that
+/// it did not drop.
+const FILTER_LAYERS: [&str; 4] = ["packet", "connection", "protocol", "session"];
+
+/// A layer of the subscription filter, applied by the connection tracker.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum FilterLayer {
+    /// The filter applied to the first packet of a connection.
+    Connection = 1,
+    /// The filter applied once the application-layer protocol is identified.
+    Protocol = 2,
+    /// The filter applied to parsed sessions.
+    Session = 3,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+/// Global metrics registry.
+pub(crate) static METRICS: Metrics = Metrics::new();
+
+/// Counters recorded by a single packet processing core.
+#[derive(Debug)]
+pub(crate) struct CoreMetrics {
+    /// Number of packets received.
+    pub(crate) rx_pkts: AtomicU64,
+    /// Number of bytes received.
+    pub(crate) rx_bytes: AtomicU64,
+    /// Number of packets dropped by the software packet filter.
+    pub(crate) dropped_pkts: AtomicU64,
+    /// Number of connections currently in the connection table.
+    pub(crate) conn_table_size: AtomicU64,
+    /// Number of connections not tracked or degraded by load shedding.
+    pub(crate) shed_conns: AtomicU64,
+    /// Number of TCP out-of-order buffer overflows.
+    pub(crate) ooo_overflows: AtomicU64,
+    /// Number of connections evicted from a full connection table.
+    pub(crate) evicted_conns: AtomicU64,
+    /// Number of payload bytes currently held in TCP out-of-order buffers.
+    pub(crate) ooo_bytes: AtomicU64,
+    /// Number of packets of connections not sampled.
+    pub(crate) unsampled_pkts: AtomicU64,
+    /// Number of bytes of connections not sampled.
+    pub(crate) unsampled_bytes: AtomicU64,
+    /// Number of packets of bypassed connections.
+    pub(crate) bypassed_pkts: AtomicU64,
+    /// Number of tracked packets dropped because the tracked packet mempool was exhausted.
+    pub(crate) untracked_pkts: AtomicU64,
+    /// Number of sessions parsed, indexed by `SESSION_PROTOCOLS`.
+    sessions: [AtomicU64; SESSION_PROTOCOLS.len()],
+    /// Number of filter matches, indexed by `FILTER_LAYERS` (the packet layer is unused).
+    filter_hits: [AtomicU64; FILTER_LAYERS.len()],
+    /// Number of TSC cycles spent in each stage, indexed by `STAGES`.
+    pub(crate) stage_cycles: [AtomicU64; STAGES.len()],
+}
+
+impl CoreMetrics {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NEW: CoreMetrics = CoreMetrics {
+        rx_pkts: ZERO,
+        rx_bytes: ZERO,
+        dropped_pkts: ZERO,
+        conn_table_size: ZERO,
+        shed_conns: ZERO,
+        ooo_overflows: ZERO,
+        ooo_bytes: ZERO,
+        evicted_conns: ZERO,
+        unsampled_pkts: ZERO,
+        unsampled_bytes: ZERO,
+        bypassed_pkts: ZERO,
+        untracked_pkts: ZERO,
+        sessions: [ZERO; SESSION_PROTOCOLS.len()],
+        filter_hits: [ZERO; FILTER_LAYERS.len()],
+        stage_cycles: [ZERO; STAGES.len()],
+    };
+
+    /// Returns the number of matches of each filter layer, in the order of `FILTER_LAYERS`.
+    fn filter_hits(&self) -> [u64; FILTER_LAYERS.len()] {
+        let mut hits = [0; FILTER_LAYERS.len()];
+        for (hit, count) in hits.iter_mut().zip(self.filter_hits.iter()) {
+            *hit = count.load(Ordering::Relaxed);
+        }
+        hits[0] = self
+            .rx_pkts
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.dropped_pkts.load(Ordering::Relaxed));
+        hits
+    }
+
+    /// Returns the cycles spent in each stage, in the order of `STAGES`.
+    fn stage_cycles(&self) -> [u64; STAGES.len()] {
+        let mut cycles = [0; STAGES.len()];
+        for (total, count) in cycles.iter_mut().zip(self.stage_cycles.iter()) {
+            *total = count.load(Ordering::Relaxed);
+        }
+        cycles
+    }
+}
+
+/// Registry of all runtime metrics.
+#[derive(Debug)]
+pub(crate) struct Metrics {
+    cores: [CoreMetrics; MAX_CORES],
+    deliveries: Mutex<Vec<&'static Counter>>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            cores: [CoreMetrics::NEW; MAX_CORES],
+            deliveries: Mutex::new(vec![]),
+        }
+    }
+
+    /// Returns the counters for `core_id`, or `None` if metrics are not recorded for the core.
+    #[inline]
+    pub(crate) fn core(&self, core_id: &CoreId) -> Option<&CoreMetrics> {
+        self.cores.get(core_id.raw() as usize)
+    }
+
+    /// Records a parsed session on `core_id`.
+    #[inline]
+    pub(crate) fn record_session(&self, core_id: &CoreId, session: &Session) {
+        let idx = match session.data {
+            SessionData::Tls(_) => 0,
+            SessionData::Dns(_) => 1,
+            SessionData::Http(_) => 2,
+            SessionData::Quic(_) => 3,
+            _ => 4,
+        };
+        if let Some(core) = self.core(core_id) {
+            core.sessions[idx].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records the result of applying the filter at `layer` on `core_id`.
+    #[inline]
+    pub(crate) fn record_filter(&self, core_id: &CoreId, layer: FilterLayer, matched: bool) {
+        if !matched {
+            return;
+        }
+        if let Some(core) = self.core(core_id) {
+            core.filter_hits[layer as usize].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the number of deliveries to each callback, summed across delivery sites.
+    pub(crate) fn deliveries(&self) -> Vec<(&'static str, u64)> {
+        let mut deliveries: Vec<(&str, u64)> = vec![];
+        for counter in self.deliveries.lock().unwrap().iter() {
+            match deliveries.iter_mut().find(|(cb, _)| *cb == counter.name) {
+                Some((_, total)) => *total += counter.total(),
+                None => deliveries.push((counter.name, counter.total())),
+            }
+        }
+        deliveries
+    }
+
+    /// Returns the counters of `cores`, summed across cores, as `(name, value)` pairs.
+    pub(crate) fn counters(&self, cores: &[CoreId]) -> Vec<(String, u64)> {
+        let mut counters: Vec<(String, u64)> = vec![
+            ("rx_pkts".to_string(), 0),
+            ("rx_bytes".to_string(), 0),
+            ("dropped_pkts".to_string(), 0),
+            ("conn_table_size".to_string(), 0),
+            ("shed_conns".to_string(), 0),
+            ("ooo_overflows".to_string(), 0),
+            ("ooo_bytes".to_string(), 0),
+            ("evicted_conns".to_string(), 0),
+            ("unsampled_pkts".to_string(), 0),
+            ("unsampled_bytes".to_string(), 0),
+            ("bypassed_pkts".to_string(), 0),
+            ("untracked_pkts".to_string(), 0),
+        ];
+        counters.extend(
+            FILTER_LAYERS
+                .iter()
+                .map(|l| (format!("filter_hits_{}", l), 0)),
+        );
+        counters.extend(
+            SESSION_PROTOCOLS
+                .iter()
+                .map(|p| (format!("sessions_{}", p), 0)),
+        );
+        counters.extend(STAGES.iter().map(|s| (format!("cycles_{}", s), 0)));
+        for core in cores.iter().filter_map(|id| self.core(id)) {
+            let values = [
+                core.rx_pkts.load(Ordering::Relaxed),
+                core.rx_bytes.load(Ordering::Relaxed),
+                core.dropped_pkts.load(Ordering::Relaxed),
+                core.conn_table_size.load(Ordering::Relaxed),
+                core.shed_conns.load(Ordering::Relaxed),
+                core.ooo_overflows.load(Ordering::Relaxed),
+                core.ooo_bytes.load(Ordering::Relaxed),
+                core.evicted_conns.load(Ordering::Relaxed),
+                core.unsampled_pkts.load(Ordering::Relaxed),
+                core.unsampled_bytes.load(Ordering::Relaxed),
+                core.bypassed_pkts.load(Ordering::Relaxed),
+                core.untracked_pkts.load(Ordering::Relaxed),
+            ]
+            .into_iter()
+            .chain(core.filter_hits())
+            .chain(core.sessions.iter().map(|c| c.load(Ordering::Relaxed)))
+            .chain(core.stage_cycles());
+            for ((_, total), value) in counters.iter_mut().zip(values) {
+                *total += value;
+            }
+        }
+        counters
+    }
+
+    /// Renders the metrics of `cores` in the Prometheus text exposition format.
+    pub(crate) fn render(&self, cores: &[CoreId]) -> String {
+        let mut out = String::new();
+        let core_metrics = cores
+            .iter()
+            .filter_map(|id| self.core(id).map(|c| (id, c)))
+            .collect::<Vec<_>>();
+
+        let per_core: [(&str, &str, &str, fn(&CoreMetrics) -> u64); 12] = [
+            (
+                "retina_rx_packets_total",
+                "counter",
+                "Packets received by each core.",
+                |c| c.rx_pkts.load(Ordering::Relaxed),
+            ),
+            (
+                "retina_rx_bytes_total",
+                "counter",
+                "Bytes received by each core.",
+                |c| c.rx_bytes.load(Ordering::Relaxed),
+            ),
+            (
+                "retina_dropped_packets_total",
+                "counter",
+                "Packets dropped by the software packet filter on each core.",
+                |c| c.dropped_pkts.load(Ordering::Relaxed),
+            ),
+            (
+                "retina_conn_table_size",
+                "gauge",
+                "Connections in the connection table of each core.",
+                |c| c.conn_table_size.load(Ordering::Relaxed),
+            ),
+            (
+                "retina_shed_connections_total",
+                "counter",
+                "Connections not tracked or degraded by load shedding on each core.",
+                |c| c.shed_conns.load(Ordering::Relaxed),
+            ),
+            (
+                "retina_ooo_overflows_total",
+                "counter",
+                "TCP out-of-order buffer overflows on each core.",
+                |c| c.ooo_overflows.load(Ordering::Relaxed),
+            ),
+            (
+                "retina_ooo_bytes",
+                "gauge",
+                "Payload bytes held in TCP out-of-order buffers on each core.",
+                |c| c.ooo_bytes.load(Ordering::Relaxed),
+            ),
+            (
+                "retina_evicted_connections_total",
+                "counter",
+                "Connections evicted from the full connection table of each core.",
+                |c| c.evicted_conns.load(Ordering::Relaxed),
+            ),
+            (
+                "retina_unsampled_packets_total",
+                "counter",
+                "Packets of connections not sampled on each core.",
+                |c| c.unsampled_pkts.load(Ordering::Relaxed),
+            ),
+            (
+                "retina_unsampled_bytes_total",
+                "counter",
+                "Bytes of connections not sampled on each core.",
+                |c| c.unsampled_bytes.load(Ordering::Relaxed),
+            ),
+            (
+                "retina_bypassed_packets_total",
+                "counter",
+                "Packets of connections no longer needed by any subscription on each core.",
+                |c| c.bypassed_pkts.load(Ordering::Relaxed),
+            ),
+            (
+                "retina_untracked_packets_total",
+                "counter",
+                "Tracked packets dropped on each core because the tracked packet mempool was exhausted.",
+                |c| c.untracked_pkts.load(Ordering::Relaxed),
+            ),
+        ];
+        for (name, ty, help, get) in per_core {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, ty);
+            for (id, core) in core_metrics.iter() {
+                let _ = writeln!(out, "{}{{core=\"{}\"}} {}", name, id, get(core));
+            }
+        }
+
+        let name = "retina_sessions_parsed_total";
+        let _ = writeln!(
+            out,
+            "# HELP {} Application-layer sessions parsed by each core.\n# TYPE {} counter",
+            name, name
+        );
+        for (id, core) in core_metrics.iter() {
+            for (proto, count) in SESSION_PROTOCOLS.iter().zip(core.sessions.iter()) {
+                let _ = writeln!(
+                    out,
+                    "{}{{core=\"{}\",protocol=\"{}\"}} {}",
+                    name,
+                    id,
+                    proto,
+                    count.load(Ordering::Relaxed)
+                );
+            }
+        }
+
+        let name = "retina_filter_hits_total";
+        let _ = writeln!(
+            out,
+            "# HELP {} Matches of each filter layer on each core.\n# TYPE {} counter",
+            name, name
+        );
+        for (id, core) in core_metrics.iter() {
+            for (layer, hits) in FILTER_LAYERS.iter().zip(core.filter_hits()) {
+                let _ = writeln!(
+                    out,
+                    "{}{{core=\"{}\",layer=\"{}\"}} {}",
+                    name, id, layer, hits
+                );
+            }
+        }
+
+        let name = "retina_stage_cycles_total";
+        let _ = writeln!(
+            out,
+            "# HELP {} TSC cycles spent in each pipeline stage on each core, if profiling is enabled.\n# TYPE {} counter",
+            name, name
+        );
+        for (id, core) in core_metrics.iter() {
+            for (stage, cycles) in STAGES.iter().zip(core.stage_cycles()) {
+                let _ = writeln!(
+                    out,
+                    "{}{{core=\"{}\",stage=\"{}\"}} {}",
+                    name, id, stage, cycles
+                );
+            }
+        }
+
+        let name = "retina_deliveries_total";
+        let _ = writeln!(
+            out,
+            "# HELP {} Invocations of each subscription callback.\n# TYPE {} counter",
+            name, name
+        );
+        for (callback, total) in self.deliveries() {
+            let _ = writeln!(out, "{}{{callback=\"{}\"}} {}", name, callback, total);
+        }
+        out
+    }
+}
+
+/// Aggregate runtime statistics, passed to the callback registered with
+/// [Runtime::on_stats](crate::Runtime::on_stats).
+#[derive(Debug, Clone)]
+pub struct RuntimeStats {
+    /// Time since the runtime started processing packets.
+    pub elapsed: Duration,
+    /// Statistics of each packet processing core.
+    pub cores: Vec<CoreStats>,
+    /// Packets dropped by the NIC (e.g., due to full receive queues) across all ports, if known.
+    /// Always `None` in offline mode.
+    pub hw_dropped_pkts: Option<u64>,
+    /// Frequency of the TSC that stage cycles are counted in, in Hz.
+    pub tsc_hz: u64,
+}
+
+impl RuntimeStats {
+    /// Returns the number of packets received across all cores.
+    pub fn rx_pkts(&self) -> u64 {
+        self.cores.iter().map(|c| c.rx_pkts).sum()
+    }
+
+    /// Returns the number of connections currently tracked across all cores.
+    pub fn conn_table_size(&self) -> u64 {
+        self.cores.iter().map(|c| c.conn_table_size).sum()
+    }
+}
+
+/// Statistics of a single packet processing core.
+#[derive(Debug, Clone)]
+pub struct CoreStats {
+    /// Core identifier.
+    pub core_id: CoreId,
+    /// Packets received since the start of the run.
+    pub rx_pkts: u64,
+    /// Bytes received since the start of the run.
+    pub rx_bytes: u64,
+    /// Packets dropped by the software packet filter since the start of the run.
+    pub dropped_pkts: u64,
+    /// Number of connections currently in the connection table.
+    pub conn_table_size: u64,
+    /// Connections not tracked or degraded by load shedding since the start of the run.
+    pub shed_conns: u64,
+    /// TCP out-of-order buffer overflows since the start of the run.
+    pub ooo_overflows: u64,
+    /// Payload bytes currently held in TCP out-of-order buffers.
+    pub ooo_bytes: u64,
+    /// Connections evicted from the full connection table since the start of the run.
+    pub evicted_conns: u64,
+    /// Packets of connections not sampled since the start of the run.
+    pub unsampled_pkts: u64,
+    /// Bytes of connections not sampled since the start of the run.
+    pub unsampled_bytes: u64,
+    /// Packets of connections no longer needed by any subscription since the start of the run.
+    pub bypassed_pkts: u64,
+    /// Tracked packets dropped because the tracked packet mempool was exhausted, since the start
+    /// of the run.
+    pub untracked_pkts: u64,
+    /// Receive rate over the last interval, in packets per second.
+    pub rx_pps: f64,
+    /// Receive rate over the last interval, in bits per second.
+    pub rx_bps: f64,
+    /// Cycles spent in each stage of the pipeline since the start of the run. Always zero unless
+    /// profiling is enabled.
+    pub stage_cycles: StageCycles,
+}
+
+/// TSC cycles a core spent in each stage of the pipeline. Cycles of nested stages are only counted
+/// for the innermost one (e.g., parsing is not counted as reassembly).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StageCycles {
+    /// Polling packets from receive queues or dispatch rings.
+    pub rx: u64,
+    /// The software packet filter.
+    pub packet_filter: u64,
+    /// Connection tracking, and the filters applied by the connection tracker.
+    pub conntrack: u64,
+    /// TCP reassembly.
+    pub reassembly: u64,
+    /// Application-layer protocol probing and parsing.
+    pub parsing: u64,
+    /// Subscription callbacks, including the session filter that invokes session callbacks.
+    pub delivery: u64,
+}
+
+impl StageCycles {
+    /// Returns the cycles spent across all stages.
+    pub fn total(&self) -> u64 {
+        self.rx
+            + self.packet_filter
+            + self.conntrack
+            + self.reassembly
+            + self.parsing
+            + self.delivery
+    }
+}
+
+impl From<[u64; STAGES.len()]> for StageCycles {
+    fn from(cycles: [u64; STAGES.len()]) -> Self {
+        let [rx, packet_filter, conntrack, reassembly, parsing, delivery] = cycles;
+        StageCycles {
+            rx,
+            packet_filter,
+            conntrack,
+            reassembly,
+            parsing,
+            delivery,
+        }
+    }
+}
+
+/// Invokes a user callback with a snapshot of the core metrics every `interval`.
+pub(crate) struct StatsReporter {
+    interval: Duration,
+    callback: Box<dyn FnMut(&RuntimeStats) + Send>,
+    cores: Vec<CoreId>,
+    start: Instant,
+    last: Instant,
+    /// Packets and bytes received by each core at the last report.
+    prev: Vec<(u64, u64)>,
+}
+
+impl StatsReporter {
+    pub(crate) fn new(
+        interval: Duration,
+        callback: Box<dyn FnMut(&RuntimeStats) + Send>,
+        cores: Vec<CoreId>,
+    ) -> Self {
+        let now = Instant::now();
+        StatsReporter {
+            interval,
+            callback,
+            prev: vec![(0, 0); cores.len()],
+            cores,
+            start: now,
+            last: now,
+        }
+    }
+
+    /// Resets the reporting interval at the start of the run.
+    pub(crate) fn start(&mut self) {
+        self.start = Instant::now();
+        self.last = self.start;
+    }
+
+    /// Invokes the callback if `interval` has elapsed since the last report. `hw_dropped` is only
+    /// evaluated when a report is due.
+    pub(crate) fn poll(&mut self, hw_dropped: impl FnOnce() -> Option<u64>) {
+        let now = Instant::now();
+        let delta = now - self.last;
+        if delta < self.interval {
+            return;
+        }
+        self.last = now;
+        let secs = delta.as_secs_f64();
+        let mut cores = vec![];
+        for (core_id, prev) in self.cores.iter().zip(self.prev.iter_mut()) {
+            let core = match METRICS.core(core_id) {
+                Some(core) => core,
+                None => continue,
+            };
+            let rx_pkts = core.rx_pkts.load(Ordering::Relaxed);
+            let rx_bytes = core.rx_bytes.load(Ordering::Relaxed);
+            cores.push(CoreStats {
+                core_id: *core_id,
+                rx_pkts,
+                rx_bytes,
+                dropped_pkts: core.dropped_pkts.load(Ordering::Relaxed),
+                conn_table_size: core.conn_table_size.load(Ordering::Relaxed),
+                shed_conns: core.shed_conns.load(Ordering::Relaxed),
+                ooo_overflows: core.ooo_overflows.load(Ordering::Relaxed),
+                ooo_bytes: core.ooo_bytes.load(Ordering::Relaxed),
+                evicted_conns: core.evicted_conns.load(Ordering::Relaxed),
+                unsampled_pkts: core.unsampled_pkts.load(Ordering::Relaxed),
+                unsampled_bytes: core.unsampled_bytes.load(Ordering::Relaxed),
+                bypassed_pkts: core.bypassed_pkts.load(Ordering::Relaxed),
+                untracked_pkts: core.untracked_pkts.load(Ordering::Relaxed),
+                rx_pps: rx_pkts.saturating_sub(prev.0) as f64 / secs,
+                rx_bps: 8.0 * rx_bytes.saturating_sub(prev.1) as f64 / secs,
+                stage_cycles: StageCycles::from(core.stage_cycles()),
+            });
+            *prev = (rx_pkts, rx_bytes);
+        }
+        let stats = RuntimeStats {
+            elapsed: now - self.start,
+            cores,
+            hw_dropped_pkts: hw_dropped(),
+            tsc_hz: unsafe { dpdk::rte_get_tsc_hz() },
+        };
+        (self.callback)(&stats);
+    }
+}
+
+/// Counts deliveries to a subscription callback.
+///
+/// A `Counter` is declared as a static at each delivery site in the generated filter code, and
+/// registers itself with the global metrics registry on first use. Counters with the same callback
+/// name are summed when exported.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Counter {
+    name: &'static str,
+    counts: [PaddedCount; MAX_CORES],
+    registered: Once,
+}
+
+/// A per-core count, on its own cache line so that cores do not contend for it.
+#[derive(Debug)]
+#[repr(align(64))]
+struct PaddedCount(AtomicU64);
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO_PADDED: PaddedCount = PaddedCount(ZERO);
+
+impl Counter {
+    pub const fn new(name: &'static str) -> Self {
+        Counter {
+            name,
+            counts: [ZERO_PADDED; MAX_CORES],
+            registered: Once::new(),
+        }
+    }
+
+    #[inline]
+    pub fn inc(&'static self) {
+        self.registered
+            .call_once(|| METRICS.deliveries.lock().unwrap().push(self));
+        let core_id = unsafe { dpdk::rte_lcore_id() } as usize;
+        if let Some(count) = self.counts.get(core_id) {
+            count.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.counts
+            .iter()
+            .map(|c| c.0.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_metrics_render() {
+        let metrics = Metrics::new();
+        let core_id = CoreId(3);
+        metrics
+            .core(&core_id)
+            .unwrap()
+            .rx_pkts
+            .store(42, Ordering::Relaxed);
+        metrics.record_session(&core_id, &Session::default());
+        metrics.record_filter(&core_id, FilterLayer::Session, true);
+        metrics.core(&core_id).unwrap().stage_cycles[4].store(1000, Ordering::Relaxed);
+        let out = metrics.render(&[core_id]);
+        assert!(out.contains("retina_rx_packets_total{core=\"3\"} 42"));
+        assert!(out.contains("retina_sessions_parsed_total{core=\"3\",protocol=\"other\"} 1"));
+        assert!(out.contains("retina_filter_hits_total{core=\"3\",layer=\"session\"} 1"));
+        assert!(out.contains("retina_stage_cycles_total{core=\"3\",stage=\"parsing\"} 1000"));
+        assert!(metrics.core(&CoreId(MAX_CORES as u32)).is_none());
+    }
+}
@@ -0,0 +1,92 @@
+//! Port mirroring.
+//!
+//! Subscriptions with the `mirror` action (e.g., `#[filter("tls.sni ~ 'example'", action =
+//! "mirror")]`) copy the packets they match to the mirror port of the
+//! [OnlineConfig](crate::config::OnlineConfig), toward external tools such as an IDS. Copies are
+//! tagged with a VLAN ID per direction of the connection (see
+//! [MirrorConfig](crate::config::MirrorConfig)). Mirroring does not change the verdict for the
+//! packet in inline mode.
+//!
+//! Like verdicts, mirroring is recorded while a packet is processed, and applied by the core once
+//! processing is done.
+
+use super::transmit::TxPort;
+use crate::config::MirrorConfig;
+use crate::memory::mbuf::Mbuf;
+
+use std::cell::Cell;
+
+/// Direction of a mirrored packet in its connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent by the originator of the connection, or mirrored by a packet-level subscription.
+    Originator,
+    /// Sent by the responder of the connection.
+    Responder,
+}
+
+thread_local! {
+    /// Direction of the packet being processed on the current core, if it is mirrored.
+    static MIRROR: Cell<Option<Direction>> = const { Cell::new(None) };
+}
+
+/// Records that the packet being processed is mirrored.
+#[doc(hidden)]
+#[inline]
+pub fn record(dir: Direction) {
+    MIRROR.with(|m| m.set(Some(dir)));
+}
+
+/// Returns whether the packet that was just processed is mirrored, and resets it for the next
+/// packet.
+#[inline]
+pub(crate) fn take() -> Option<Direction> {
+    MIRROR.with(|m| m.take())
+}
+
+/// Transmit queue of a core on the mirror port.
+#[derive(Debug, Clone)]
+pub(crate) struct Mirror {
+    port: TxPort,
+    originator_vlan: Option<u16>,
+    responder_vlan: Option<u16>,
+}
+
+impl Mirror {
+    pub(crate) fn new(port: TxPort, config: &MirrorConfig) -> Self {
+        Mirror {
+            port,
+            originator_vlan: config.originator_vlan,
+            responder_vlan: config.responder_vlan,
+        }
+    }
+
+    /// Returns a tagged copy of `mbuf` to transmit, or `None` if no packet buffer could be
+    /// allocated.
+    pub(crate) fn copy(&self, mbuf: &Mbuf, dir: Direction) -> Option<Mbuf> {
+        let vlan = match dir {
+            Direction::Originator => self.originator_vlan,
+            Direction::Responder => self.responder_vlan,
+        };
+        mbuf.copy_tagged(vlan, self.port.mempool).ok()
+    }
+
+    /// Transmits `mbufs` on the mirror port. Returns the number of packets dropped because the
+    /// transmit queue is full.
+    pub(crate) fn tx_burst(&self, mbufs: Vec<Mbuf>) -> usize {
+        self.port.txqueue.tx_burst(mbufs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_mirror_take_resets() {
+        assert_eq!(take(), None);
+        record(Direction::Responder);
+        assert_eq!(take(), Some(Direction::Responder));
+        assert_eq!(take(), None);
+    }
+}
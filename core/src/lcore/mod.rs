@@ -1,8 +1,17 @@
 //! Utilities for managing and monitoring Retina cores.
 
+pub(crate) mod dispatch;
+pub(crate) mod idle;
+pub mod metrics;
+pub mod mirror;
 pub(crate) mod monitor;
-// pub(crate) mod ring;
+pub(crate) mod overload;
+pub(crate) mod profile;
+pub(crate) mod ring;
 pub(crate) mod rx_core;
+pub(crate) mod telemetry;
+pub mod transmit;
+pub mod verdict;
 
 use crate::dpdk;
 
@@ -10,6 +19,9 @@ use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
+/// Maximum number of cores that per-core state (e.g., metrics) is kept for.
+pub(crate) const MAX_CORES: usize = 128;
+
 #[derive(Debug, Copy, Clone, Hash, Ord, Eq, PartialEq, PartialOrd)]
 pub(crate) struct SocketId(pub(crate) u32);
 
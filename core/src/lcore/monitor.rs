@@ -1,15 +1,20 @@
+use super::metrics::{StatsReporter, METRICS};
+use super::CoreId;
 use crate::config::RuntimeConfig;
 use crate::dpdk;
+use crate::port::events::{EventCallback, PortEvents};
 use crate::port::{statistics::PortStats, Port, PortId, RxQueue, RxQueueType};
 
 use std::collections::{BTreeMap, HashMap};
 use std::ffi::CString;
 use std::fmt;
+use std::fmt::Write as _;
 use std::fs;
-use std::io::Write;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use anyhow::{bail, Result};
@@ -31,7 +36,12 @@ pub(crate) struct Monitor {
     duration: Option<Duration>,
     display: Option<Display>,
     logger: Option<Logger>,
+    prometheus: Option<Prometheus>,
     ports: BTreeMap<PortId, Vec<RxQueue>>,
+    /// Name of the mempool of each port
+    mempools: BTreeMap<PortId, String>,
+    /// Link-state and hotplug events of the ports.
+    events: PortEvents,
     is_running: Arc<AtomicBool>,
 }
 
@@ -40,7 +50,7 @@ impl Monitor {
         config: &RuntimeConfig,
         ports: &BTreeMap<PortId, Port>,
         is_running: Arc<AtomicBool>,
-    ) -> Self {
+    ) -> Result<Self> {
         let date = Local::now();
         let online_cfg = config
             .online
@@ -92,21 +102,66 @@ impl Monitor {
             None
         })();
 
+        let prometheus = (|| -> Result<Option<Prometheus>> {
+            if let Some(monitor_cfg) = &online_cfg.monitor {
+                if let Some(prometheus_cfg) = &monitor_cfg.prometheus {
+                    let listener = match TcpListener::bind(&prometheus_cfg.address) {
+                        Ok(listener) => listener,
+                        Err(error) => bail!(
+                            "Failed to bind Prometheus endpoint {}: {}",
+                            prometheus_cfg.address,
+                            error
+                        ),
+                    };
+                    log::info!("Serving Prometheus metrics on {}", prometheus_cfg.address);
+                    let body = Arc::new(Mutex::new(String::new()));
+                    let server_body = Arc::clone(&body);
+                    std::thread::spawn(move || Prometheus::serve(listener, server_body));
+                    return Ok(Some(Prometheus {
+                        ticker: tick(Duration::from_millis(prometheus_cfg.interval)),
+                        cores: config.get_all_rx_core_ids(),
+                        keywords: prometheus_cfg.port_stats.clone(),
+                        body,
+                    }));
+                }
+            }
+            Ok(None)
+        })()?;
+
         let mut monitor_ports: BTreeMap<PortId, Vec<RxQueue>> = BTreeMap::new();
+        let mut mempools: BTreeMap<PortId, String> = BTreeMap::new();
         for (port_id, port) in ports.iter() {
             monitor_ports.insert(*port_id, port.queue_map.keys().cloned().collect());
+            mempools.insert(*port_id, format!("mempool_{}", port.socket_id));
         }
-
-        Monitor {
+        let devices = ports
+            .iter()
+            .map(|(port_id, port)| (*port_id, port.device.clone()))
+            .collect();
+        let polled = ports
+            .iter()
+            .filter(|(_, port)| !port.lsc_interrupts())
+            .map(|(port_id, _)| *port_id)
+            .collect();
+        let events = PortEvents::register(devices, polled);
+
+        Ok(Monitor {
             duration,
             display,
             logger,
+            prometheus,
             ports: monitor_ports,
+            mempools,
+            events,
             is_running,
-        }
+        })
     }
 
-    pub(crate) fn run(&mut self) {
+    pub(crate) fn run(
+        &mut self,
+        mut stats: Option<&mut StatsReporter>,
+        mut on_event: Option<&mut EventCallback>,
+    ) {
         if let Some(logger) = &mut self.logger {
             logger.init_port_wtrs().expect("port logger init");
         }
@@ -122,6 +177,9 @@ impl Monitor {
         let mut init = true;
         // Add a small delay to allow workers to start polling for packets
         std::thread::sleep(Duration::from_millis(1000));
+        if let Some(stats) = stats.as_mut() {
+            stats.start();
+        }
         while self.is_running.load(Ordering::Relaxed) {
             if let Some(duration) = self.duration {
                 if start_ts.elapsed() >= duration {
@@ -144,7 +202,7 @@ impl Monitor {
                             if display.throughput {
                                 println!("----------------------------------------------");
                                 println!("Current time: {}s", (curr_ts - start_ts).as_secs());
-                                display.mempool_usage(&self.ports);
+                                display.mempool_usage(&self.mempools);
                                 AggRxStats::display_rates(curr_rx, prev_rx, nms);
                                 AggRxStats::display_dropped(curr_rx, init_rx);
                             }
@@ -160,12 +218,28 @@ impl Monitor {
 
             if let Some(logger) = &mut self.logger {
                 if logger.ticker.try_recv().is_ok() {
-                    match logger.log_stats(init_ts.elapsed()) {
+                    match logger.log_stats(init_ts.elapsed(), &self.mempools) {
                         Ok(_) => (),
                         Err(error) => log::error!("Monitor log error: {}", error),
                     }
                 }
             }
+
+            if let Some(prometheus) = &self.prometheus {
+                if prometheus.ticker.try_recv().is_ok() {
+                    prometheus.refresh(&self.ports);
+                }
+            }
+
+            self.events.poll(|event| {
+                if let Some(on_event) = on_event.as_mut() {
+                    on_event(event);
+                }
+            });
+
+            if let Some(stats) = stats.as_mut() {
+                stats.poll(|| self.hw_dropped_pkts());
+            }
         }
 
         std::thread::sleep(Duration::from_millis(100));
@@ -180,6 +254,19 @@ impl Monitor {
     }
 }
 
+impl Monitor {
+    /// Returns the number of packets dropped by the NIC across all ports, or `None` if a port
+    /// does not report it.
+    fn hw_dropped_pkts(&self) -> Option<u64> {
+        let mut dropped = 0;
+        for port_id in self.ports.keys() {
+            let port_stats = PortStats::collect(*port_id).ok()?;
+            dropped += port_stats.stats.get("rx_missed_errors")?;
+        }
+        Some(dropped)
+    }
+}
+
 #[derive(Debug)]
 struct Display {
     ticker: Receiver<Instant>,
@@ -189,8 +276,8 @@ struct Display {
 
 impl Display {
     /// Display mempool usage
-    fn mempool_usage(&self, ports: &BTreeMap<PortId, Vec<RxQueue>>) {
-        for name in ports.keys().map(|id| format!("mempool_{}", id.socket_id())) {
+    fn mempool_usage(&self, mempools: &BTreeMap<PortId, String>) {
+        for name in mempools.values() {
             let cname = CString::new(name.clone()).expect("Invalid CString conversion");
             let mempool_raw = unsafe { dpdk::rte_mempool_lookup(cname.as_ptr()) };
             let avail_cnt = unsafe { dpdk::rte_mempool_avail_count(mempool_raw) };
@@ -235,7 +322,7 @@ impl Logger {
     }
 
     /// Logs per-port statistics and mempool statistics (per-socket statistics).
-    fn log_stats(&mut self, elapsed: Duration) -> Result<()> {
+    fn log_stats(&mut self, elapsed: Duration, mempools: &BTreeMap<PortId, String>) -> Result<()> {
         for (port_id, wtr) in self.port_wtrs.iter_mut() {
             let port_stats = PortStats::collect(*port_id);
             match port_stats {
@@ -253,8 +340,8 @@ impl Logger {
                 }
                 Err(error) => log::error!("{}", error),
             }
-            let name = format!("mempool_{}", port_id.socket_id());
-            let cname = CString::new(name.clone()).expect("Invalid CString conversion");
+            let name = &mempools[port_id];
+            let cname = CString::new(name.as_str()).expect("Invalid CString conversion");
             let mempool_raw = unsafe { dpdk::rte_mempool_lookup(cname.as_ptr()) };
             let avail_cnt = unsafe { dpdk::rte_mempool_avail_count(mempool_raw) };
             let inuse_cnt = unsafe { dpdk::rte_mempool_in_use_count(mempool_raw) };
@@ -269,6 +356,75 @@ impl Logger {
     }
 }
 
+#[derive(Debug)]
+struct Prometheus {
+    ticker: Receiver<Instant>,
+    cores: Vec<CoreId>,
+    keywords: Vec<String>,
+    /// Most recently rendered metrics, served to scrapers.
+    body: Arc<Mutex<String>>,
+}
+
+impl Prometheus {
+    /// Re-renders the core metrics and port statistics. Port statistics are collected here rather
+    /// than in the server thread, as the server thread is not a DPDK core.
+    fn refresh(&self, ports: &BTreeMap<PortId, Vec<RxQueue>>) {
+        let mut body = METRICS.render(&self.cores);
+        let name = "retina_port_stat";
+        let _ = writeln!(
+            body,
+            "# HELP {} Device statistics of each port.\n# TYPE {} gauge",
+            name, name
+        );
+        for port_id in ports.keys() {
+            match PortStats::collect(*port_id) {
+                Ok(port_stats) => {
+                    for (label, value) in port_stats.stats.iter() {
+                        if self.keywords.iter().any(|k| label.contains(k)) {
+                            let _ = writeln!(
+                                body,
+                                "{}{{port=\"{}\",stat=\"{}\"}} {}",
+                                name, port_id, label, value
+                            );
+                        }
+                    }
+                }
+                Err(error) => log::error!("Prometheus port stats error: {}", error),
+            }
+        }
+        *self.body.lock().unwrap() = body;
+    }
+
+    /// Serves the most recently rendered metrics to each incoming HTTP request.
+    fn serve(listener: TcpListener, body: Arc<Mutex<String>>) {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(error) = Prometheus::respond(stream, &body) {
+                        log::warn!("Prometheus scrape error: {}", error);
+                    }
+                }
+                Err(error) => log::warn!("Prometheus connection error: {}", error),
+            }
+        }
+    }
+
+    fn respond(mut stream: TcpStream, body: &Mutex<String>) -> Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+        // The request itself is ignored: every path serves the metrics.
+        let mut buf = [0; 1024];
+        let _ = stream.read(&mut buf)?;
+        let body = body.lock().unwrap().clone();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        Ok(())
+    }
+}
+
 /// Aggregate RX port statistics at time of collection
 #[derive(Debug, Default, Clone, Copy)]
 struct AggRxStats {
@@ -0,0 +1,139 @@
+//! Overload detection.
+//!
+//! A packet processing core that cannot keep up with its receive queues eventually has packets
+//! dropped by the NIC, indiscriminately. With an [OverloadConfig](crate::config::OverloadConfig),
+//! each core instead watches the fill levels of its queues and of its socket's memory pool, and
+//! sheds load in a controlled way (see [Shedding](crate::conntrack::Shedding)) while they are high.
+
+use super::dispatch::DispatchRole;
+use super::ring::Ring;
+use super::CoreId;
+use crate::config::OverloadConfig;
+use crate::dpdk;
+use crate::port::{events, PortId, RxQueue};
+
+use std::ffi::CString;
+use std::sync::Arc;
+
+/// Number of polling iterations between checks of the fill levels.
+const CHECK_INTERVAL: u32 = 64;
+
+/// Fraction of the thresholds that all fill levels must fall below for the core to recover.
+const RECOVERY_FACTOR: f64 = 0.8;
+
+/// Tracks whether a core is overloaded.
+pub(crate) struct Overload {
+    config: OverloadConfig,
+    core_id: CoreId,
+    /// Port, queue, and number of descriptors of each polled receive queue.
+    rxqueues: Vec<(u16, u16, u32)>,
+    /// Worker ring, with software dispatch.
+    ring: Option<Arc<Ring>>,
+    /// Memory pool that the core's packets are allocated from.
+    mempool: *mut dpdk::rte_mempool,
+    polls: u32,
+    overloaded: bool,
+}
+
+impl Overload {
+    pub(crate) fn new(
+        config: &OverloadConfig,
+        core_id: CoreId,
+        rxqueues: &[RxQueue],
+        dispatch: &DispatchRole,
+    ) -> Self {
+        let rxqueues = rxqueues
+            .iter()
+            .map(|rxqueue| {
+                let (pid, qid) = (rxqueue.pid.raw(), rxqueue.qid.raw());
+                let mut info: dpdk::rte_eth_rxq_info = unsafe { std::mem::zeroed() };
+                let ret = unsafe { dpdk::rte_eth_rx_queue_info_get(pid, qid, &mut info) };
+                let nb_desc = if ret == 0 { info.nb_desc as u32 } else { 0 };
+                (pid, qid, nb_desc)
+            })
+            .collect();
+        let ring = match dispatch {
+            DispatchRole::Worker(ring) => Some(Arc::clone(ring)),
+            _ => None,
+        };
+        let name = format!("mempool_{}", core_id.socket_id());
+        let cname = CString::new(name).expect("Invalid CString conversion");
+        let mempool = unsafe { dpdk::rte_mempool_lookup(cname.as_ptr()) };
+        Overload {
+            config: config.clone(),
+            core_id,
+            rxqueues,
+            ring,
+            mempool,
+            polls: 0,
+            overloaded: false,
+        }
+    }
+
+    /// Called once per polling iteration. Returns the new state if the core became overloaded
+    /// (`true`) or recovered (`false`).
+    pub(crate) fn poll(&mut self) -> Option<bool> {
+        self.polls += 1;
+        if self.polls < CHECK_INTERVAL {
+            return None;
+        }
+        self.polls = 0;
+
+        let (queue_fill, mempool_fill) = (self.queue_fill(), self.mempool_fill());
+        let overloaded = if self.overloaded {
+            queue_fill >= self.config.queue_threshold * RECOVERY_FACTOR
+                || mempool_fill >= self.config.mempool_threshold * RECOVERY_FACTOR
+        } else {
+            queue_fill >= self.config.queue_threshold
+                || mempool_fill >= self.config.mempool_threshold
+        };
+        if overloaded == self.overloaded {
+            return None;
+        }
+        self.overloaded = overloaded;
+        if overloaded {
+            log::warn!(
+                "Core {} overloaded (queue {:.0}% full, mempool {:.0}% in use), shedding load: {:?}",
+                self.core_id,
+                100.0 * queue_fill,
+                100.0 * mempool_fill,
+                self.config.policy
+            );
+        } else {
+            log::info!("Core {} recovered from overload", self.core_id);
+        }
+        Some(overloaded)
+    }
+
+    /// Returns the highest fill level of the polled receive queues or worker ring.
+    fn queue_fill(&self) -> f64 {
+        let mut fill: f64 = 0.0;
+        for (pid, qid, nb_desc) in self.rxqueues.iter() {
+            if *nb_desc == 0 || !events::is_active(PortId(*pid)) {
+                continue;
+            }
+            // Negative if the PMD does not support counting used descriptors
+            let used = unsafe { dpdk::rte_eth_rx_queue_count(*pid, *qid) };
+            if used > 0 {
+                fill = fill.max(used as f64 / *nb_desc as f64);
+            }
+        }
+        if let Some(ring) = &self.ring {
+            fill = fill.max(ring.count() as f64 / ring.capacity().max(1) as f64);
+        }
+        fill
+    }
+
+    /// Returns the fraction of mbufs in use in the memory pool.
+    fn mempool_fill(&self) -> f64 {
+        if self.mempool.is_null() {
+            return 0.0;
+        }
+        let size = unsafe { (*self.mempool).size };
+        if size == 0 {
+            return 0.0;
+        }
+        let in_use = unsafe { dpdk::rte_mempool_in_use_count(self.mempool) };
+        in_use as f64 / size as f64
+    }
+}
@@ -1,5 +1,6 @@
 use crate::dpdk;
 use crate::lcore::SocketId;
+use crate::memory::mbuf::Mbuf;
 
 use anyhow::{bail, Result};
 use std::ffi::{CStr, CString};
@@ -15,14 +16,15 @@ pub(crate) struct Ring {
 unsafe impl Send for Ring {}
 unsafe impl Sync for Ring {}
 
+#[allow(dead_code)]
 impl Ring {
-    pub(crate) fn new(size: u32, socket_id: SocketId, flags: u32) -> Result<Self> {
-        if size == 0 || ((size & size - 1) != 0) {
+    /// Creates a ring named `name` with `size` slots on `socket_id`. Ring names must be unique.
+    pub(crate) fn new(name: &str, size: u32, socket_id: SocketId, flags: u32) -> Result<Self> {
+        if size == 0 || ((size & (size - 1)) != 0) {
             bail!("Ring size must be a power of 2");
         }
 
-        let name = format!("event_ring_{}", socket_id);
-        let cname = CString::new(name.clone()).unwrap();
+        let cname = CString::new(name).unwrap();
         log::debug!("Ring size: {}", size);
         let ring = unsafe {
             dpdk::rte_ring_create(
@@ -139,6 +141,51 @@ impl Ring {
     }
 }
 
+impl Ring {
+    /// Enqueues as many of `mbufs` as fit on the ring (multi-producers safe), and returns the
+    /// number of mbufs that were dropped because the ring was full.
+    pub(crate) fn enqueue_mbufs(&self, mbufs: Vec<Mbuf>) -> usize {
+        let nb_mbufs = mbufs.len();
+        if nb_mbufs == 0 {
+            return 0;
+        }
+        let ptrs: Vec<*mut c_void> = mbufs
+            .into_iter()
+            .map(|mbuf| mbuf.into_raw() as *mut c_void)
+            .collect();
+        let nb_enqueued = unsafe {
+            dpdk::rte_ring_mp_enqueue_burst(
+                self.raw.as_ptr(),
+                ptrs.as_ptr(),
+                nb_mbufs as c_uint,
+                std::ptr::null_mut(),
+            )
+        } as usize;
+        // Free the mbufs that were not enqueued
+        for ptr in ptrs[nb_enqueued..].iter() {
+            drop(Mbuf::new_unchecked(*ptr as *mut dpdk::rte_mbuf));
+        }
+        nb_mbufs - nb_enqueued
+    }
+
+    /// Dequeues up to `burst_size` mbufs from the ring (NOT multi-consumers safe).
+    pub(crate) fn dequeue_mbufs(&self, burst_size: usize) -> Vec<Mbuf> {
+        let mut ptrs: Vec<*mut c_void> = Vec::with_capacity(burst_size);
+        unsafe {
+            let nb_dequeued = dpdk::rte_ring_sc_dequeue_burst(
+                self.raw.as_ptr(),
+                ptrs.as_mut_ptr(),
+                burst_size as c_uint,
+                std::ptr::null_mut(),
+            );
+            ptrs.set_len(nb_dequeued as usize);
+        }
+        ptrs.into_iter()
+            .map(|ptr| Mbuf::new_unchecked(ptr as *mut dpdk::rte_mbuf))
+            .collect()
+    }
+}
+
 impl Drop for Ring {
     fn drop(&mut self) {
         log::info!("Dropping {}.", self.name());
@@ -1,11 +1,28 @@
+use super::dispatch::DispatchRole;
+use super::idle::Idle;
+use super::metrics::METRICS;
+use super::mirror::{self, Mirror};
+use super::overload::Overload;
+use super::profile::{self, Stage};
+use super::transmit::{self, TxPort};
+use super::verdict::{self, Verdict};
 use super::CoreId;
-use crate::config::ConnTrackConfig;
-use crate::conntrack::{ConnTracker, TrackerConfig};
+use crate::config::{IdleConfig, OverloadConfig};
+use crate::conntrack::defrag::Defrag;
+use crate::conntrack::fastpath;
+use crate::conntrack::tunnel::Decap;
+use crate::conntrack::{ConnTracker, Shedding, TrackerConfig};
 use crate::dpdk;
 use crate::memory::mbuf::Mbuf;
-use crate::port::{RxQueue, RxQueueType};
+use crate::memory::pool;
+use crate::port::events;
+use crate::port::{PortId, RxQueue, RxQueueType, TxQueue};
+use crate::runtime::{LiveConfig, RunLimits};
 use crate::subscription::*;
+use crate::utils::pcap::PcapWriter;
 
+use std::collections::BTreeMap;
+use std::ffi::CString;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
@@ -13,34 +30,71 @@ use itertools::Itertools;
 
 /// A RxCore polls from `rxqueues` and reduces the stream of packets into
 /// a stream of higher-level network events to be processed by the user.
+///
+/// With software dispatch, a RxCore either forwards the packets it polls to worker cores, or is a
+/// worker that processes the packets forwarded to its ring (see `DispatchRole`).
+///
+/// In inline mode, packets polled from a bridged port are transmitted on the core's queue of the
+/// other port after they are processed, unless they were dropped or diverted (see `Verdict`).
 pub(crate) struct RxCore<S>
 where
     S: Subscribable,
 {
     pub(crate) id: CoreId,
     pub(crate) rxqueues: Vec<RxQueue>,
-    pub(crate) conntrack: ConnTrackConfig,
+    pub(crate) live: Arc<LiveConfig>,
     pub(crate) subscription: Arc<Subscription<S>>,
     pub(crate) is_running: Arc<AtomicBool>,
+    pub(crate) limits: Arc<RunLimits>,
+    pub(crate) dispatch: DispatchRole,
+    /// Transmit queue for the packets received on each bridged port.
+    pub(crate) bridge: BTreeMap<PortId, TxQueue>,
+    /// Transmit queue on the divert port.
+    pub(crate) divert: Option<TxQueue>,
+    /// Transmit queues for packets sent by callbacks.
+    pub(crate) transmit: Vec<TxPort>,
+    /// Load shedding options, if enabled.
+    pub(crate) overload: Option<OverloadConfig>,
+    /// Transmit queue on the mirror port.
+    pub(crate) mirror: Option<Mirror>,
+    /// Idle back-off options, if enabled.
+    pub(crate) idle: Option<IdleConfig>,
 }
 
 impl<S> RxCore<S>
 where
     S: Subscribable,
 {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         core_id: CoreId,
         rxqueues: Vec<RxQueue>,
-        conntrack: ConnTrackConfig,
+        live: Arc<LiveConfig>,
         subscription: Arc<Subscription<S>>,
         is_running: Arc<AtomicBool>,
+        limits: Arc<RunLimits>,
+        dispatch: DispatchRole,
+        bridge: BTreeMap<PortId, TxQueue>,
+        divert: Option<TxQueue>,
+        transmit: Vec<TxPort>,
+        overload: Option<OverloadConfig>,
+        mirror: Option<Mirror>,
+        idle: Option<IdleConfig>,
     ) -> Self {
         RxCore {
             id: core_id,
             rxqueues,
-            conntrack,
+            live,
             subscription,
             is_running,
+            limits,
+            dispatch,
+            bridge,
+            divert,
+            transmit,
+            overload,
+            mirror,
+            idle,
         }
     }
 
@@ -63,33 +117,90 @@ where
     }
 
     pub(crate) fn rx_loop(&self) {
-        // TODO: need check to enforce that each core only has same queue types
-        if self.rxqueues[0].ty == RxQueueType::Receive {
-            self.rx_process();
-        } else {
-            self.rx_sink();
+        match &self.dispatch {
+            DispatchRole::Worker(_) => self.rx_process(),
+            DispatchRole::Dispatcher(_) => self.rx_dispatch(),
+            // Cores only poll queues of the same type (see `OnlineConfig::check_ports`)
+            DispatchRole::None if self.rxqueues[0].ty == RxQueueType::Receive => self.rx_process(),
+            DispatchRole::None => self.rx_sink(),
         }
     }
 
     fn rx_process(&self) {
-        log::info!(
-            "Launched RX on core {}, polling {}",
-            self.id,
-            self.rxqueues.iter().format(", "),
-        );
+        if let DispatchRole::Worker(ring) = &self.dispatch {
+            log::info!("Launched RX on core {}, polling {}", self.id, ring.name());
+        } else {
+            log::info!(
+                "Launched RX on core {}, polling {}",
+                self.id,
+                self.rxqueues.iter().format(", "),
+            );
+        }
 
         let mut nb_pkts = 0;
         let mut nb_bytes = 0;
+        let mut nb_dropped = 0;
+        let mut nb_conns = 0;
+        let mut nb_blocked = 0;
+        let mut nb_diverted = 0;
+        let mut nb_mirrored = 0;
+        let mut nb_tx_dropped = 0;
+        let metrics = METRICS.core(&self.id);
 
-        let config = TrackerConfig::from(&self.conntrack);
+        let mut generation = self.live.generation();
+        let config = TrackerConfig::from(&self.live.conntrack());
         let registry = S::Tracked::parsers();
         log::debug!("{:#?}", registry);
-        let mut conn_table = ConnTracker::<S::Tracked>::new(config, registry, self.id);
+        let pcap_writer = PcapWriter::new(&self.live.pcap_writer(), self.id);
+        let mut conn_table = ConnTracker::<S::Tracked>::new(config, registry, self.id, pcap_writer);
+        // Packet-level subscriptions with packet-layer filters never look up connections
+        let conntrack = !self.subscription.bypass_conntrack;
+        transmit::init(self.transmit.clone());
+        let jumbo_mempool = self.jumbo_mempool();
+        pool::set_tracked_mempool(self.tracked_mempool());
+        profile::start(&self.id);
+        let mut overload = self
+            .overload
+            .as_ref()
+            .map(|config| Overload::new(config, self.id, &self.rxqueues, &self.dispatch));
+        let shedding = self.overload.as_ref().map(Shedding::from);
+        let mut idle = self
+            .idle
+            .as_ref()
+            .map(|config| Idle::new(config, self.id, &self.rxqueues));
+        // Packets dispatched to workers were reassembled by the dispatcher
+        let mut defrag = match self.dispatch {
+            DispatchRole::Worker(_) => None,
+            _ => Defrag::new(&self.live.conntrack().defrag),
+        };
+        // Packets are dispatched to workers before decapsulation, to keep their tunnel metadata
+        let mut decap = match self.dispatch {
+            DispatchRole::Dispatcher(_) => None,
+            _ => Decap::new(&self.live.conntrack()),
+        };
 
         while self.is_running.load(Ordering::Relaxed) {
-            for rxqueue in self.rxqueues.iter() {
-                let mbufs: Vec<Mbuf> = self.rx_burst(rxqueue, 32);
-                for mbuf in mbufs.into_iter() {
+            let mut process = |mut mbufs: Vec<Mbuf>, txqueue: Option<&TxQueue>| {
+                let mut tx_mbufs = vec![];
+                let mut divert_mbufs = vec![];
+                let mut mirror_mbufs = vec![];
+                // Copies are taken once the packet is processed, as the mirror action can apply
+                // from that packet on
+                let mut mirror_copy = |mbuf: &Mbuf| {
+                    if let (Some(mirror), Some(dir)) = (&self.mirror, mirror::take()) {
+                        mirror_mbufs.extend(mirror.copy(mbuf, dir));
+                    }
+                };
+                mbufs.truncate(self.limits.admit_packets(mbufs.len()));
+                fastpath::prefetch_burst(&mbufs);
+                let mut burst = mbufs.into_iter();
+                while let Some(mbuf) = burst.next() {
+                    // Load the headers of later packets, and the connection of the next one,
+                    // while this packet is processed
+                    fastpath::prefetch_ahead(burst.as_slice());
+                    if let Some(next) = burst.as_slice().first().filter(|_| conntrack) {
+                        conn_table.prefetch(next);
+                    }
                     // log::debug!("{:#?}", mbuf);
                     // log::debug!("Mark: {}", mbuf.mark());
                     // log::debug!("RSS Hash: 0x{:x}", mbuf.rss_hash());
@@ -100,28 +211,287 @@ where
                     //     self.id,
                     // );
                     nb_pkts += 1;
-                    nb_bytes += mbuf.data_len() as u64;
+                    nb_bytes += mbuf.pkt_len() as u64;
 
-                    let actions = self.subscription.continue_packet(&mbuf, &self.id);
-                    if !actions.drop() {
-                        self.subscription
-                            .process_packet(mbuf, &mut conn_table, actions);
+                    // Fragments on bridged ports are forwarded as they are
+                    let released = match defrag.as_mut().filter(|_| txqueue.is_none()) {
+                        Some(defrag) => defrag.push(mbuf),
+                        None => Defrag::pass(mbuf),
+                    };
+                    for mbuf in released {
+                        // Tunneled packets on bridged ports are forwarded as they are
+                        let mbuf = match decap.as_mut().filter(|_| txqueue.is_none()) {
+                            Some(decap) => decap.push(mbuf),
+                            None => mbuf,
+                        };
+                        let actions = self.subscription.continue_packet(&mbuf, &self.id);
+                        if actions.drop() {
+                            nb_dropped += 1;
+                            // Unmatched packets are forwarded without further processing
+                            if txqueue.is_some() {
+                                tx_mbufs.push(mbuf);
+                            }
+                        } else if txqueue.is_some() {
+                            let mbuf = self.linearize(mbuf, jumbo_mempool);
+                            let shared = mbuf.share();
+                            verdict::take();
+                            self.subscription
+                                .process_packet(mbuf, &mut conn_table, actions);
+                            mirror_copy(&shared);
+                            match verdict::take() {
+                                Verdict::Forward | Verdict::Stop | Verdict::Escalate(_) => {
+                                    tx_mbufs.push(shared)
+                                }
+                                Verdict::Divert if self.divert.is_some() => {
+                                    divert_mbufs.push(shared)
+                                }
+                                Verdict::Divert | Verdict::Drop => nb_blocked += 1,
+                            }
+                        } else if self.mirror.is_some() {
+                            let mbuf = self.linearize(mbuf, jumbo_mempool);
+                            let shared = mbuf.share();
+                            self.subscription
+                                .process_packet(mbuf, &mut conn_table, actions);
+                            mirror_copy(&shared);
+                        } else {
+                            let mbuf = self.linearize(mbuf, jumbo_mempool);
+                            self.subscription
+                                .process_packet(mbuf, &mut conn_table, actions);
+                        }
+                    }
+                }
+                if let Some(txqueue) = txqueue {
+                    nb_tx_dropped += txqueue.tx_burst(tx_mbufs);
+                }
+                if let Some(divert) = &self.divert {
+                    nb_diverted += divert_mbufs.len();
+                    nb_tx_dropped += divert.tx_burst(divert_mbufs);
+                }
+                if let Some(mirror) = &self.mirror {
+                    nb_mirrored += mirror_mbufs.len();
+                    nb_tx_dropped += mirror.tx_burst(mirror_mbufs);
+                }
+            };
+            let mut nb_polled = 0;
+            if let DispatchRole::Worker(ring) = &self.dispatch {
+                let mbufs = {
+                    let _rx = profile::enter(Stage::Rx);
+                    ring.dequeue_mbufs(32)
+                };
+                nb_polled += mbufs.len();
+                process(mbufs, None);
+            } else {
+                for rxqueue in self.rxqueues.iter() {
+                    if !events::is_active(rxqueue.pid) {
+                        continue;
                     }
+                    let mbufs = {
+                        let _rx = profile::enter(Stage::Rx);
+                        self.rx_burst(rxqueue, 32)
+                    };
+                    nb_polled += mbufs.len();
+                    process(mbufs, self.bridge.get(&rxqueue.pid));
                 }
             }
-            conn_table.check_inactive(&self.subscription);
+            if let Some(idle) = idle.as_mut() {
+                idle.poll(nb_polled);
+            }
+            if conntrack {
+                conn_table.check_inactive(&self.subscription);
+            }
+            if let Some(overloaded) = overload.as_mut().and_then(|overload| overload.poll()) {
+                conn_table.set_shedding(shedding.filter(|_| overloaded));
+            }
+            if self.live.generation() != generation {
+                generation = self.live.generation();
+                conn_table.reconfigure(
+                    TrackerConfig::from(&self.live.conntrack()),
+                    &self.live.pcap_writer(),
+                );
+            }
+            if let Some(metrics) = metrics {
+                metrics.rx_pkts.store(nb_pkts, Ordering::Relaxed);
+                metrics.rx_bytes.store(nb_bytes, Ordering::Relaxed);
+                metrics.dropped_pkts.store(nb_dropped, Ordering::Relaxed);
+                metrics
+                    .conn_table_size
+                    .store(conn_table.size() as u64, Ordering::Relaxed);
+                metrics
+                    .shed_conns
+                    .store(conn_table.nb_shed(), Ordering::Relaxed);
+                metrics
+                    .ooo_overflows
+                    .store(conn_table.nb_ooo_overflows(), Ordering::Relaxed);
+                metrics
+                    .ooo_bytes
+                    .store(conn_table.ooo_bytes() as u64, Ordering::Relaxed);
+                metrics
+                    .evicted_conns
+                    .store(conn_table.nb_evicted(), Ordering::Relaxed);
+                metrics
+                    .unsampled_pkts
+                    .store(conn_table.nb_unsampled_pkts(), Ordering::Relaxed);
+                metrics
+                    .unsampled_bytes
+                    .store(conn_table.unsampled_bytes(), Ordering::Relaxed);
+                metrics
+                    .bypassed_pkts
+                    .store(conn_table.nb_bypassed_pkts(), Ordering::Relaxed);
+                metrics
+                    .untracked_pkts
+                    .store(pool::nb_untracked_pkts(), Ordering::Relaxed);
+            }
+            self.limits
+                .add_connections(conn_table.nb_created() - nb_conns);
+            nb_conns = conn_table.nb_created();
+            self.limits.check_duration();
         }
 
         // // Deliver remaining data in table from unfinished connections
         conn_table.drain(&self.subscription);
 
         log::info!(
-            "Core {} total recv from {}: {} pkts, {} bytes",
+            "Core {} total recv: {} pkts, {} bytes",
             self.id,
-            self.rxqueues.iter().format(", "),
             nb_pkts,
             nb_bytes
         );
+        if let Some(defrag) = defrag
+            .as_ref()
+            .filter(|d| d.nb_reassembled() + d.nb_dropped() + d.nb_passed() > 0)
+        {
+            log::info!(
+                "Core {} reassembled {} IP datagrams, dropped {} incomplete datagrams, passed {} \
+                 datagrams too large to reassemble as fragments",
+                self.id,
+                defrag.nb_reassembled(),
+                defrag.nb_dropped(),
+                defrag.nb_passed()
+            );
+        }
+        if let Some(decap) = decap.as_ref().filter(|d| d.nb_decapsulated() > 0) {
+            log::info!(
+                "Core {} decapsulated {} tunneled packets",
+                self.id,
+                decap.nb_decapsulated()
+            );
+        }
+        if conn_table.nb_shed() > 0 {
+            log::warn!(
+                "Core {} shed {} connections under overload",
+                self.id,
+                conn_table.nb_shed()
+            );
+        }
+        if conn_table.nb_evicted() > 0 {
+            log::warn!(
+                "Core {} evicted {} connections from its full connection table",
+                self.id,
+                conn_table.nb_evicted()
+            );
+        }
+        if conn_table.nb_unsampled_pkts() > 0 {
+            log::info!(
+                "Core {} did not sample {} pkts, {} bytes",
+                self.id,
+                conn_table.nb_unsampled_pkts(),
+                conn_table.unsampled_bytes()
+            );
+        }
+        if conn_table.nb_ooo_overflows() > 0 {
+            log::warn!(
+                "Core {} overflowed {} TCP out-of-order buffers",
+                self.id,
+                conn_table.nb_ooo_overflows()
+            );
+        }
+        if self.mirror.is_some() {
+            log::info!("Core {} mirrored {} pkts", self.id, nb_mirrored);
+        }
+        if !self.bridge.is_empty() {
+            log::info!(
+                "Core {} inline: {} pkts dropped by subscriptions, {} pkts diverted, {} pkts dropped at full TX queues",
+                self.id,
+                nb_blocked,
+                nb_diverted,
+                nb_tx_dropped
+            );
+        }
+    }
+
+    /// Returns the pool of full-size mbufs on the core's socket, or null if frames are not received
+    /// in multiple segments.
+    fn jumbo_mempool(&self) -> *mut dpdk::rte_mempool {
+        let name = format!("jumbo_mempool_{}", self.id.socket_id());
+        let cname = CString::new(name).expect("Invalid CString conversion");
+        unsafe { dpdk::rte_mempool_lookup(cname.as_ptr()) }
+    }
+
+    /// Returns the pool that tracked packets are copied into on the core's socket, or null if
+    /// tracked packets are kept in their receive mbufs.
+    fn tracked_mempool(&self) -> *mut dpdk::rte_mempool {
+        let name = format!("tracked_mempool_{}", self.id.socket_id());
+        let cname = CString::new(name).expect("Invalid CString conversion");
+        unsafe { dpdk::rte_mempool_lookup(cname.as_ptr()) }
+    }
+
+    /// Copies a frame received in multiple segments into a single mbuf, so that it can be parsed
+    /// and reassembled. Only the first segment is processed if no full-size mbuf is available.
+    fn linearize(&self, mbuf: Mbuf, jumbo_mempool: *mut dpdk::rte_mempool) -> Mbuf {
+        if mbuf.nb_segs() == 1 || jumbo_mempool.is_null() {
+            return mbuf;
+        }
+        match mbuf.linearize(jumbo_mempool) {
+            Ok(linear) => linear,
+            Err(error) => {
+                log::debug!(
+                    "Failed to linearize {}-byte frame: {}",
+                    mbuf.pkt_len(),
+                    error
+                );
+                mbuf
+            }
+        }
+    }
+
+    fn rx_dispatch(&self) {
+        log::info!(
+            "Launched DISPATCH on core {}, polling {}",
+            self.id,
+            self.rxqueues.iter().format(", "),
+        );
+        let dispatcher = match &self.dispatch {
+            DispatchRole::Dispatcher(dispatcher) => dispatcher,
+            _ => return,
+        };
+
+        let mut nb_pkts = 0;
+        let mut nb_dropped = 0;
+        // Fragments are hashed to workers once reassembled
+        let mut defrag = Defrag::new(&self.live.conntrack().defrag);
+        // Tunneled packets are hashed on their inner 5-tuple, and decapsulated by the workers
+        let decap = Decap::new(&self.live.conntrack());
+
+        while self.is_running.load(Ordering::Relaxed) {
+            for rxqueue in self.rxqueues.iter() {
+                if !events::is_active(rxqueue.pid) {
+                    continue;
+                }
+                let mut mbufs: Vec<Mbuf> = self.rx_burst(rxqueue, 32);
+                nb_pkts += mbufs.len();
+                if let Some(defrag) = defrag.as_mut() {
+                    mbufs = mbufs.into_iter().flat_map(|m| defrag.push(m)).collect();
+                }
+                nb_dropped += dispatcher.dispatch(mbufs, decap.as_ref());
+            }
+        }
+        log::info!(
+            "Dispatch Core {} total recv from {}: {} pkts, {} dropped at full worker rings",
+            self.id,
+            self.rxqueues.iter().format(", "),
+            nb_pkts,
+            nb_dropped
+        );
     }
 
     fn rx_sink(&self) {
@@ -136,6 +506,9 @@ where
 
         while self.is_running.load(Ordering::Relaxed) {
             for rxqueue in self.rxqueues.iter() {
+                if !events::is_active(rxqueue.pid) {
+                    continue;
+                }
                 let mbufs: Vec<Mbuf> = self.rx_burst(rxqueue, 32);
                 for mbuf in mbufs.into_iter() {
                     log::debug!("RSS Hash: 0x{:x}", mbuf.rss_hash());
@@ -146,7 +519,7 @@ where
                         self.id,
                     );
                     nb_pkts += 1;
-                    nb_bytes += mbuf.data_len() as u64;
+                    nb_bytes += mbuf.pkt_len() as u64;
                 }
             }
         }
@@ -0,0 +1,147 @@
+//! DPDK telemetry integration.
+//!
+//! Registers Retina's runtime metrics as commands of the DPDK telemetry socket, so that a running
+//! instance can be inspected with the standard `dpdk-telemetry.py` client:
+//!
+//! ```text
+//! --> /retina/stats
+//! {"/retina/stats": {"rx_pkts": 1043, "rx_bytes": 741209, "dropped_pkts": 12, ...}}
+//! --> /retina/core,3
+//! {"/retina/core": {"rx_pkts": 517, ...}}
+//! --> /retina/deliveries
+//! {"/retina/deliveries": {"tls_cb": 35}}
+//! ```
+//!
+//! Counters are read from the global metrics registry (see [metrics](super::metrics)), and have the
+//! same values as the Prometheus export.
+
+use super::metrics::METRICS;
+use super::CoreId;
+use crate::dpdk;
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::sync::OnceLock;
+
+/// Error returned for invalid command parameters.
+const EINVAL: c_int = 22;
+
+/// Packet processing cores of the runtime.
+static CORES: OnceLock<Vec<CoreId>> = OnceLock::new();
+
+type Handler = unsafe extern "C" fn(*const c_char, *const c_char, *mut dpdk::rte_tel_data) -> c_int;
+
+/// Telemetry commands, with their handler and help text.
+const COMMANDS: [(&[u8], Handler, &[u8]); 4] = [
+    (
+        b"/retina/cores\0",
+        cores,
+        b"Returns the packet processing cores. Takes no parameters.\0",
+    ),
+    (
+        b"/retina/stats\0",
+        stats,
+        b"Returns the counters summed across all cores: packets, connection table size, filter hits per layer, and parsed sessions. Takes no parameters.\0",
+    ),
+    (
+        b"/retina/core\0",
+        core,
+        b"Returns the counters of one core. Parameters: int core_id\0",
+    ),
+    (
+        b"/retina/deliveries\0",
+        deliveries,
+        b"Returns the number of deliveries to each callback. Takes no parameters.\0",
+    ),
+];
+
+/// Registers the telemetry commands. Called once the EAL is initialized.
+pub(crate) fn register(cores: Vec<CoreId>) {
+    if CORES.set(cores).is_err() {
+        return;
+    }
+    for (cmd, handler, help) in COMMANDS {
+        let ret = unsafe {
+            dpdk::rte_telemetry_register_cmd(
+                cmd.as_ptr() as *const c_char,
+                Some(handler),
+                help.as_ptr() as *const c_char,
+            )
+        };
+        if ret != 0 {
+            log::warn!(
+                "Failed to register telemetry command {}: {}",
+                String::from_utf8_lossy(&cmd[..cmd.len() - 1]),
+                ret
+            );
+        }
+    }
+    log::info!("Registered /retina telemetry commands");
+}
+
+unsafe extern "C" fn cores(
+    _cmd: *const c_char,
+    _params: *const c_char,
+    data: *mut dpdk::rte_tel_data,
+) -> c_int {
+    dpdk::rte_tel_data_start_array(data, dpdk::rte_tel_value_type_RTE_TEL_INT_VAL);
+    for core_id in CORES.get().into_iter().flatten() {
+        dpdk::rte_tel_data_add_array_int(data, core_id.raw() as c_int);
+    }
+    0
+}
+
+unsafe extern "C" fn stats(
+    _cmd: *const c_char,
+    _params: *const c_char,
+    data: *mut dpdk::rte_tel_data,
+) -> c_int {
+    let cores = CORES.get().map(Vec::as_slice).unwrap_or_default();
+    add_dict(data, METRICS.counters(cores));
+    0
+}
+
+unsafe extern "C" fn core(
+    _cmd: *const c_char,
+    params: *const c_char,
+    data: *mut dpdk::rte_tel_data,
+) -> c_int {
+    if params.is_null() {
+        return -EINVAL;
+    }
+    let core_id = match CStr::from_ptr(params)
+        .to_str()
+        .map(|p| p.trim().parse::<u32>())
+    {
+        Ok(Ok(core_id)) => CoreId(core_id),
+        _ => return -EINVAL,
+    };
+    if !CORES.get().is_some_and(|cores| cores.contains(&core_id)) {
+        return -EINVAL;
+    }
+    add_dict(data, METRICS.counters(&[core_id]));
+    0
+}
+
+unsafe extern "C" fn deliveries(
+    _cmd: *const c_char,
+    _params: *const c_char,
+    data: *mut dpdk::rte_tel_data,
+) -> c_int {
+    let deliveries = METRICS
+        .deliveries()
+        .into_iter()
+        .map(|(callback, total)| (callback.to_string(), total))
+        .collect();
+    add_dict(data, deliveries);
+    0
+}
+
+unsafe fn add_dict(data: *mut dpdk::rte_tel_data, values: Vec<(String, u64)>) {
+    dpdk::rte_tel_data_start_dict(data);
+    for (name, value) in values {
+        if let Ok(name) = CString::new(name) {
+            dpdk::rte_tel_data_add_dict_u64(data, name.as_ptr(), value);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Packet transmission.
+//!
+//! Packets can be transmitted on the ports listed in the `transmit` option of the
+//! [OnlineConfig](crate::config::OnlineConfig), either from callbacks on the core that invokes
+//! them, or from the application with a [Transmitter](Transmitter) (see
+//! [Runtime::transmitter](crate::Runtime::transmitter)). Each core, and each `Transmitter`, has its
+//! own transmit queue on each port.
+//!
+//! ## Example
+//! Resetting TLS connections to a blocked server:
+//! ```rust,ignore
+//! use retina_core::lcore::transmit;
+//!
+//! #[filter("tls.sni ~ 'example'")]
+//! fn tls_cb(tls: &TlsHandshake, five_tuple: &FiveTuple) {
+//!     let rst = build_rst(five_tuple);
+//!     if let Err(error) = transmit::transmit_bytes(&rst, "0000:3b:00.1") {
+//!         log::warn!("Failed to send reset: {}", error);
+//!     }
+//! }
+//! ```
+//!
+//! Replaying a pcap at 10,000 packets per second while the runtime is running:
+//! ```rust,ignore
+//! let transmitter = runtime.transmitter("0000:3b:00.1")?;
+//! std::thread::spawn(move || transmitter.replay("./traces/test.pcap", Some(10_000.0)));
+//! runtime.run();
+//! ```
+
+use crate::dpdk;
+use crate::memory::mbuf::Mbuf;
+use crate::port::TxQueue;
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use pcap::Capture;
+use thiserror::Error;
+
+/// A transmit queue on a port, with the memory pool that packets sent on it are allocated from.
+#[derive(Debug, Clone)]
+pub(crate) struct TxPort {
+    pub(crate) device: String,
+    pub(crate) txqueue: TxQueue,
+    pub(crate) mempool: *mut dpdk::rte_mempool,
+    /// Whether the ports have been started (and not yet stopped).
+    pub(crate) started: Arc<AtomicBool>,
+}
+
+impl TxPort {
+    fn transmit(&self, mbuf: Mbuf) -> Result<()> {
+        if !self.started.load(Ordering::Relaxed) {
+            bail!(TransmitError::NotStarted(self.device.clone()));
+        }
+        if self.txqueue.tx_burst(vec![mbuf]) > 0 {
+            bail!(TransmitError::QueueFull(self.device.clone()));
+        }
+        Ok(())
+    }
+
+    fn transmit_bytes(&self, data: &[u8]) -> Result<()> {
+        self.transmit(Mbuf::from_bytes(data, self.mempool)?)
+    }
+}
+
+thread_local! {
+    /// Transmit queues of the current core.
+    static TX_PORTS: RefCell<Vec<TxPort>> = const { RefCell::new(vec![]) };
+}
+
+/// Sets the transmit queues of the current core.
+pub(crate) fn init(tx_ports: Vec<TxPort>) {
+    TX_PORTS.with(|ports| *ports.borrow_mut() = tx_ports);
+}
+
+fn with_port<F>(device: &str, f: F) -> Result<()>
+where
+    F: FnOnce(&TxPort) -> Result<()>,
+{
+    TX_PORTS.with(
+        |ports| match ports.borrow().iter().find(|p| p.device == device) {
+            Some(port) => f(port),
+            None => bail!(TransmitError::NoQueue(device.to_string())),
+        },
+    )
+}
+
+/// Transmits `mbuf` on `device` from the current core. The packet is not modified, and remains
+/// valid for the caller.
+///
+/// Returns an error if the core cannot transmit on `device`, or its transmit queue is full.
+pub fn transmit(mbuf: &Mbuf, device: &str) -> Result<()> {
+    with_port(device, |port| port.transmit(mbuf.share()))
+}
+
+/// Transmits an Ethernet frame with contents `data` on `device` from the current core.
+///
+/// Returns an error if the core cannot transmit on `device`, its transmit queue is full, or no
+/// packet buffer could be allocated.
+pub fn transmit_bytes(data: &[u8], device: &str) -> Result<()> {
+    with_port(device, |port| port.transmit_bytes(data))
+}
+
+/// Transmits packets on a port from an application thread.
+///
+/// A `Transmitter` owns a transmit queue of the port, so there is at most one per port. It can be
+/// moved to another thread, and must not be used after the runtime is dropped. Packets can only be
+/// transmitted while the runtime is running.
+#[derive(Debug)]
+pub struct Transmitter {
+    port: TxPort,
+    is_running: Arc<AtomicBool>,
+}
+
+// Safety: the transmit queue and memory pool are only used by the thread that owns the
+// Transmitter, and DPDK memory pools are thread-safe.
+unsafe impl Send for Transmitter {}
+
+impl Transmitter {
+    pub(crate) fn new(port: TxPort, is_running: Arc<AtomicBool>) -> Self {
+        Transmitter { port, is_running }
+    }
+
+    /// Returns the device that packets are transmitted on.
+    pub fn device(&self) -> &str {
+        &self.port.device
+    }
+
+    /// Transmits `mbuf`. Returns an error if the runtime is not running or the transmit queue is
+    /// full.
+    pub fn transmit(&self, mbuf: &Mbuf) -> Result<()> {
+        self.port.transmit(mbuf.share())
+    }
+
+    /// Transmits an Ethernet frame with contents `data`. Returns an error if the runtime is not
+    /// running, the transmit queue is full, or no packet buffer could be allocated.
+    pub fn transmit_bytes(&self, data: &[u8]) -> Result<()> {
+        self.port.transmit_bytes(data)
+    }
+
+    /// Transmits all packets in the pcap file at `path`, at most `pps` packets per second (or as
+    /// fast as the port allows if `None`), and returns the number of packets transmitted.
+    ///
+    /// Waits for the runtime to start, and stops early if it is shut down. Packets are retried
+    /// while the transmit queue is full.
+    pub fn replay<P: AsRef<Path>>(&self, path: P, pps: Option<f64>) -> Result<u64> {
+        let mut cap = Capture::from_file(path.as_ref())?;
+        while !self.port.started.load(Ordering::Relaxed) {
+            if !self.is_running.load(Ordering::Relaxed) {
+                return Ok(0);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let interval = pps.filter(|pps| *pps > 0.0).map(|pps| 1.0 / pps);
+        let start = Instant::now();
+        let mut nb_pkts = 0;
+        while let Ok(frame) = cap.next() {
+            if let Some(interval) = interval {
+                let next = start + Duration::from_secs_f64(nb_pkts as f64 * interval);
+                if let Some(delay) = next.checked_duration_since(Instant::now()) {
+                    std::thread::sleep(delay);
+                }
+            }
+            loop {
+                if !self.is_running.load(Ordering::Relaxed) {
+                    log::info!("Stopping replay early.");
+                    return Ok(nb_pkts);
+                }
+                match self.port.transmit_bytes(frame.data) {
+                    Ok(()) => break,
+                    Err(error) => match error.downcast_ref::<TransmitError>() {
+                        Some(TransmitError::QueueFull(_)) => std::hint::spin_loop(),
+                        _ => return Err(error),
+                    },
+                }
+            }
+            nb_pkts += 1;
+        }
+        log::info!(
+            "Replayed {} pkts from {} on {}",
+            nb_pkts,
+            path.as_ref().display(),
+            self.port.device
+        );
+        Ok(nb_pkts)
+    }
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum TransmitError {
+    #[error("No transmit queue on port {0}, add it to the transmit ports")]
+    NoQueue(String),
+
+    #[error("Port {0} is not running")]
+    NotStarted(String),
+
+    #[error("Transmit queue on port {0} is full")]
+    QueueFull(String),
+}
@@ -0,0 +1,194 @@
+//! Per-packet verdicts in inline mode.
+//!
+//! When ports are bridged (see [OnlineConfig](crate::config::OnlineConfig)), each packet received
+//! on one port is transmitted on the other once it has been processed. Callbacks can return a
+//! [Verdict](Verdict) to drop the packet that is being processed when they are invoked instead;
+//! callbacks that return `()` forward it. For example, a packet-level callback drops the packet it
+//! is delivered, and a session-level callback drops the packet that completed the session.
+//! Packets that do not match any subscription are forwarded without further processing.
+//!
+//! ## Example
+//! ```rust,ignore
+//! use retina_core::Verdict;
+//!
+//! #[filter("tls.sni ~ 'example'")]
+//! fn tls_cb(_tls: &TlsHandshake) -> Verdict {
+//!     Verdict::Drop
+//! }
+//! ```
+//!
+//! Subscriptions can also drop or divert the packets they match without a callback round-trip, by
+//! specifying an action (e.g., `#[filter("tls.sni ~ 'example'", action = "drop")]`). Diverted
+//! packets are transmitted on the `divert` port of the [OnlineConfig](crate::config::OnlineConfig).
+//!
+//! Verdicts are ignored outside of inline mode.
+//!
+//! ## Stopping early
+//! In all modes, a callback can return [Verdict::Stop](Verdict::Stop) (or
+//! `ControlFlow::Break(())`) once it has what it needs from a connection. The framework then clears
+//! the remaining actions of the connection: its packets are no longer tracked, parsed, or buffered,
+//! and nothing more is delivered for it, including at termination. As actions are shared by all
+//! subscriptions, this stops the connection for every subscription it matched, so it is best
+//! suited to applications whose subscriptions are on disjoint traffic. Stop is only honored for
+//! callbacks invoked while processing a packet of the connection on the packet processing core
+//! (i.e., not for async or pooled callbacks, nor at connection termination).
+//!
+//! ```rust,ignore
+//! #[filter("tls")]
+//! fn tls_cb(tls: &TlsHandshake) -> Verdict {
+//!     println!("{}", tls.sni());
+//!     Verdict::Stop
+//! }
+//! ```
+//!
+//! ## Escalating
+//! Conversely, a callback can request additional processing for the rest of the connection by
+//! returning [Verdict::Escalate](Verdict::Escalate) with the [actions](crate::filter::ActionData)
+//! to add (or an [Actions](crate::filter::Actions) set, which converts into it). The actions are
+//! merged into the live actions of the connection as if a filter had matched on the packet being
+//! processed, and take effect through the existing datatypes and delivery filters: e.g.,
+//! `UpdatePDU` invokes `update` on the tracked datatypes for every following packet,
+//! `PacketDeliver` delivers the following packets to the matching packet-level subscriptions, and
+//! `ConnDeliver` delivers the connection at termination to the matching connection-level
+//! subscriptions. Only actions on packets and connection delivery can be escalated (see
+//! [Actions::escalate](crate::filter::Actions::escalate)); the same restrictions as for `Stop`
+//! apply, and `Stop` takes precedence.
+//!
+//! ```rust,ignore
+//! #[filter("http.user_agent ~ 'curl'")]
+//! fn http_cb(http: &HttpTransaction) -> Verdict {
+//!     Verdict::Escalate(ActionData::UpdatePDU | ActionData::PacketWrite)
+//! }
+//! ```
+
+use crate::filter::{ActionData, Actions};
+
+use std::cell::Cell;
+use std::ops::ControlFlow;
+
+/// Whether to forward or drop a packet in inline mode.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Transmit the packet on the bridged port.
+    #[default]
+    Forward,
+    /// Transmit the packet on the divert port.
+    Divert,
+    /// Drop the packet.
+    Drop,
+    /// Forward the packet, and stop tracking its connection.
+    Stop,
+    /// Forward the packet, and apply additional actions to the rest of its connection.
+    Escalate(ActionData),
+}
+
+impl From<()> for Verdict {
+    fn from(_: ()) -> Self {
+        Verdict::Forward
+    }
+}
+
+impl From<Actions> for Verdict {
+    fn from(actions: Actions) -> Self {
+        Verdict::Escalate(actions.data | actions.terminal_actions)
+    }
+}
+
+impl From<ControlFlow<()>> for Verdict {
+    fn from(flow: ControlFlow<()>) -> Self {
+        match flow {
+            ControlFlow::Continue(()) => Verdict::Forward,
+            ControlFlow::Break(()) => Verdict::Stop,
+        }
+    }
+}
+
+thread_local! {
+    /// Verdict for the packet being processed on the current core.
+    static VERDICT: Cell<Verdict> = const { Cell::new(Verdict::Forward) };
+    /// Whether a callback stopped the connection of the packet being processed.
+    static STOP: Cell<bool> = const { Cell::new(false) };
+    /// Actions requested by callbacks for the connection of the packet being processed.
+    static ESCALATED: Cell<ActionData> = Cell::new(ActionData::none());
+}
+
+/// Records the verdict returned by a callback. If several verdicts are recorded for the same
+/// packet, `Drop` takes precedence over `Divert`, which takes precedence over `Forward`. `Stop`
+/// and `Escalate` are recorded separately, and forward the packet.
+#[doc(hidden)]
+#[inline]
+pub fn record(verdict: Verdict) {
+    if verdict == Verdict::Stop {
+        STOP.with(|stop| stop.set(true));
+    } else if let Verdict::Escalate(actions) = verdict {
+        ESCALATED.with(|escalated| escalated.set(escalated.get() | actions));
+    } else if verdict != Verdict::Forward {
+        VERDICT.with(|v| {
+            if v.get() != Verdict::Drop {
+                v.set(verdict)
+            }
+        });
+    }
+}
+
+/// Returns the verdict for the packet that was just processed, and resets it for the next packet.
+#[inline]
+pub(crate) fn take() -> Verdict {
+    VERDICT.with(|v| v.replace(Verdict::Forward))
+}
+
+/// Returns `true` if a callback returned `Stop` since the last call, and resets it.
+#[inline]
+pub(crate) fn take_stop() -> bool {
+    STOP.with(|stop| stop.replace(false))
+}
+
+/// Returns the actions requested by callbacks since the last call, and resets them.
+#[inline]
+pub(crate) fn take_escalated() -> ActionData {
+    ESCALATED.with(|escalated| escalated.replace(ActionData::none()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_verdict_drop_wins() {
+        record(Verdict::Forward);
+        assert_eq!(take(), Verdict::Forward);
+        record(Verdict::Drop);
+        record(Verdict::Divert);
+        record(().into());
+        assert_eq!(take(), Verdict::Drop);
+        record(Verdict::Divert);
+        assert_eq!(take(), Verdict::Divert);
+        assert_eq!(take(), Verdict::Forward);
+    }
+
+    #[test]
+    fn core_verdict_stop() {
+        take_stop();
+        record(Verdict::Drop);
+        record(ControlFlow::Break(()).into());
+        assert_eq!(take(), Verdict::Drop);
+        assert!(take_stop());
+        assert!(!take_stop());
+        record(ControlFlow::Continue(()).into());
+        assert_eq!(take(), Verdict::Forward);
+        assert!(!take_stop());
+    }
+
+    #[test]
+    fn core_verdict_escalate() {
+        take_escalated();
+        record(Verdict::Escalate(ActionData::UpdatePDU));
+        record(Verdict::Escalate(ActionData::PacketTrack));
+        assert_eq!(take(), Verdict::Forward);
+        assert_eq!(
+            take_escalated(),
+            ActionData::UpdatePDU | ActionData::PacketTrack
+        );
+        assert!(take_escalated().is_none());
+    }
+}
@@ -91,9 +91,12 @@ pub mod utils;
 
 pub use self::conntrack::conn_id::{ConnId, FiveTuple};
 pub use self::conntrack::pdu::L4Pdu;
+pub use self::lcore::transmit::Transmitter;
+pub use self::lcore::verdict::Verdict;
 pub use self::lcore::CoreId;
 pub use self::memory::mbuf::Mbuf;
-pub use self::runtime::Runtime;
+pub use self::port::events::RuntimeEvent;
+pub use self::runtime::{Runtime, ShutdownHandle};
 
 pub use dpdk::rte_lcore_id;
 pub use dpdk::rte_rdtsc;
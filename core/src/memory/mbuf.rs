@@ -1,21 +1,25 @@
 //! Packet buffer manipulation.
 //!
 //! ## Remarks
-//! Retina does not support multi-segment Mbufs, but does support setting the maximum Mbuf size in
-//! the runtime configuration (see [configuration parameters](crate::config)). However, all Mbufs
-//! will be allocated with the specified size, so allowing jumbo frames will limit the maximum
-//! number of Mbufs available in the memory pool.
+//! By default, all Mbufs are allocated with the maximum frame size of the configured MTU, so
+//! allowing jumbo frames will limit the maximum number of Mbufs available in the memory pool. If
+//! the mempool `segment_size` is set (see [configuration parameters](crate::config)), larger frames
+//! are received in multiple segments, which are copied into a single Mbuf before the packet is
+//! processed. Accessors that return contiguous data only cover the first segment.
 //!
 //! This module is adapted from
 //! [capsule::Mbuf](https://docs.rs/capsule/0.1.5/capsule/struct.Mbuf.html).
 
 use crate::dpdk;
 use crate::memory::mempool::MempoolError;
+use crate::port::clock;
 use crate::protocols::packet::{Packet, PacketHeader, PacketParseError};
 
+use std::cmp;
 use std::fmt;
 use std::ptr::NonNull;
 use std::slice;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Result};
 use thiserror::Error;
@@ -48,7 +52,7 @@ impl Mbuf {
     /// Creates a new Mbuf from a byte slice.
     pub(crate) fn from_bytes(data: &[u8], mp: *mut dpdk::rte_mempool) -> Result<Mbuf> {
         let mut mbuf = unsafe { Mbuf::new(dpdk::rte_pktmbuf_alloc(mp))? };
-        if data.len() <= mbuf.raw().buf_len.into() {
+        if data.len() <= (mbuf.raw().buf_len - mbuf.raw().data_off) as usize {
             mbuf.raw_mut().data_len += data.len() as u16;
             mbuf.raw_mut().pkt_len += data.len() as u32;
             unsafe {
@@ -62,28 +66,209 @@ impl Mbuf {
         Ok(mbuf)
     }
 
+    /// Creates a new Mbuf with contents `data`, allocated from the memory pool of `self`. The
+    /// receive timestamp of `self` is kept.
+    pub(crate) fn with_data(&self, data: &[u8]) -> Result<Mbuf> {
+        let mut mbuf = Mbuf::from_bytes(data, self.raw().pool)?;
+        clock::copy_rx_timestamp(self.raw(), mbuf.raw_mut());
+        Ok(mbuf)
+    }
+
+    /// Removes the first `len` bytes of the packet (e.g., the outer headers of a tunneled frame).
+    /// The removed bytes are left in the headroom of the buffer.
+    pub(crate) fn strip(&mut self, len: usize) -> Result<()> {
+        if len >= self.data_len() {
+            bail!(MbufError::BadOffset);
+        }
+        let data = unsafe { dpdk::rte_pktmbuf_adj(self.raw.as_ptr(), len as u16) };
+        if data.is_null() {
+            bail!(MbufError::BadOffset);
+        }
+        Ok(())
+    }
+
+    /// Overwrites the packet data at `offset` with `data` (first segment only). The data must not
+    /// be shared.
+    pub(crate) fn write_data(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+        if offset + data.len() > self.data_len() {
+            bail!(MbufError::WritePastBuffer);
+        }
+        unsafe {
+            let dst = self.get_data_address(offset) as *mut u8;
+            std::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
+        }
+        Ok(())
+    }
+
     /// Returns a reference to the inner rte_mbuf for use with DPDK functions.
     pub(crate) fn raw(&self) -> &dpdk::rte_mbuf {
         unsafe { self.raw.as_ref() }
     }
 
+    /// Consumes the Mbuf and returns the raw rte_mbuf pointer without freeing it. The caller is
+    /// responsible for freeing the rte_mbuf (e.g., by converting it back with `new_unchecked`).
+    pub(crate) fn into_raw(self) -> *mut dpdk::rte_mbuf {
+        let raw = self.raw.as_ptr();
+        std::mem::forget(self);
+        raw
+    }
+
+    /// Returns another handle to the same rte_mbuf, incrementing its reference count. The rte_mbuf
+    /// is only freed once all handles are dropped (or transmitted). The data must not be modified
+    /// while it is shared.
+    pub(crate) fn share(&self) -> Mbuf {
+        unsafe { dpdk::rte_mbuf_refcnt_update(self.raw.as_ptr(), 1) };
+        Mbuf { raw: self.raw }
+    }
+
     /// Returns a mutable reference to the inner rte_mbuf.
     fn raw_mut(&mut self) -> &mut dpdk::rte_mbuf {
         unsafe { self.raw.as_mut() }
     }
 
-    /// Returns the UNIX timestamp of the packet.
-    #[allow(dead_code)]
-    pub(crate) fn timestamp(&self) -> usize {
-        unimplemented!();
+    /// Returns the time the NIC received the packet, if hardware timestamps are enabled (see
+    /// [OnlineConfig](crate::config::OnlineConfig)) and supported by the port. In offline mode,
+    /// returns the capture timestamp of the packet, mapped to host time.
+    pub fn timestamp(&self) -> Option<Instant> {
+        clock::rx_timestamp(self.raw())
+    }
+
+    /// Stamps the packet, read from a capture file, with its capture timestamp `ts` (since the Unix
+    /// epoch).
+    pub(crate) fn set_capture_timestamp(&mut self, ts: Duration) {
+        clock::set_capture_timestamp(self.raw_mut(), ts);
+    }
+
+    /// Returns the time the packet was received: the NIC (or capture) timestamp if available, or
+    /// the current time otherwise.
+    pub fn rx_time(&self) -> Instant {
+        self.timestamp().unwrap_or_else(Instant::now)
     }
 
-    /// Returns the length of the data in the Mbuf.
+    /// Returns the length of the data in the Mbuf (first segment only).
     pub fn data_len(&self) -> usize {
         self.raw().data_len as usize
     }
 
-    /// Returns the contents of the Mbuf as a byte slice.
+    /// Returns the length of the packet across all segments.
+    pub fn pkt_len(&self) -> usize {
+        self.raw().pkt_len as usize
+    }
+
+    /// Returns the number of segments the packet is stored in.
+    pub fn nb_segs(&self) -> usize {
+        self.raw().nb_segs as usize
+    }
+
+    /// Returns the contents of each segment of the packet.
+    pub fn segments(&self) -> impl Iterator<Item = &[u8]> {
+        let mut seg: *const dpdk::rte_mbuf = self.raw.as_ptr();
+        std::iter::from_fn(move || {
+            if seg.is_null() {
+                return None;
+            }
+            unsafe {
+                let raw = &*seg;
+                let ptr = (raw.buf_addr as *const u8).offset(raw.data_off as isize);
+                seg = raw.next;
+                Some(slice::from_raw_parts(ptr, raw.data_len as usize))
+            }
+        })
+    }
+
+    /// Copies a multi-segment packet into a single Mbuf allocated from `mp`, which must hold the
+    /// full packet.
+    pub(crate) fn linearize(&self, mp: *mut dpdk::rte_mempool) -> Result<Mbuf> {
+        let mut mbuf = unsafe { Mbuf::new(dpdk::rte_pktmbuf_alloc(mp))? };
+        let pkt_len = self.pkt_len();
+        if pkt_len > (mbuf.raw().buf_len - mbuf.raw().data_off) as usize {
+            bail!(MbufError::WritePastBuffer);
+        }
+        let mut dst = mbuf.get_data_address(0) as *mut u8;
+        for segment in self.segments() {
+            unsafe {
+                std::ptr::copy_nonoverlapping(segment.as_ptr(), dst, segment.len());
+                dst = dst.add(segment.len());
+            }
+        }
+        mbuf.raw_mut().data_len = pkt_len as u16;
+        mbuf.raw_mut().pkt_len = pkt_len as u32;
+        // keep the metadata set by the NIC
+        mbuf.raw_mut().__bindgen_anon_2 = self.raw().__bindgen_anon_2;
+        clock::copy_rx_timestamp(self.raw(), mbuf.raw_mut());
+        Ok(mbuf)
+    }
+
+    /// Copies the packet into a new Mbuf allocated from `mp`, truncated to the data room of the
+    /// new Mbuf. The packet length of the copy is that of the original packet, so that its wire
+    /// length is kept (e.g., in pcap records); only the first `data_len()` bytes are stored.
+    pub(crate) fn copy_truncated(&self, mp: *mut dpdk::rte_mempool) -> Result<Mbuf> {
+        let mut mbuf = unsafe { Mbuf::new(dpdk::rte_pktmbuf_alloc(mp))? };
+        let room = (mbuf.raw().buf_len - mbuf.raw().data_off) as usize;
+        let len = cmp::min(self.pkt_len(), room);
+        let mut dst = mbuf.get_data_address(0) as *mut u8;
+        let mut remaining = len;
+        for segment in self.segments() {
+            let count = cmp::min(segment.len(), remaining);
+            unsafe {
+                std::ptr::copy_nonoverlapping(segment.as_ptr(), dst, count);
+                dst = dst.add(count);
+            }
+            remaining -= count;
+            if remaining == 0 {
+                break;
+            }
+        }
+        mbuf.raw_mut().data_len = len as u16;
+        mbuf.raw_mut().pkt_len = self.raw().pkt_len;
+        // keep the metadata set by the NIC
+        mbuf.raw_mut().__bindgen_anon_2 = self.raw().__bindgen_anon_2;
+        clock::copy_rx_timestamp(self.raw(), mbuf.raw_mut());
+        Ok(mbuf)
+    }
+
+    /// Copies the packet (first segment only) into a new Mbuf allocated from `mp`, inserting an
+    /// 802.1Q tag with VLAN ID `vlan` after the Ethernet addresses if it is set.
+    pub(crate) fn copy_tagged(
+        &self,
+        vlan: Option<u16>,
+        mp: *mut dpdk::rte_mempool,
+    ) -> Result<Mbuf> {
+        let data = self.data();
+        let tag = match vlan {
+            Some(vlan) if data.len() >= 12 => {
+                let [tpid_hi, tpid_lo] = 0x8100u16.to_be_bytes();
+                let [tci_hi, tci_lo] = (vlan & 0x0fff).to_be_bytes();
+                Some([tpid_hi, tpid_lo, tci_hi, tci_lo])
+            }
+            _ => None,
+        };
+        let len = data.len() + tag.map_or(0, |tag| tag.len());
+        let mut mbuf = unsafe { Mbuf::new(dpdk::rte_pktmbuf_alloc(mp))? };
+        if len > (mbuf.raw().buf_len - mbuf.raw().data_off) as usize {
+            bail!(MbufError::WritePastBuffer);
+        }
+        let dst = mbuf.get_data_address(0) as *mut u8;
+        unsafe {
+            match tag {
+                Some(tag) => {
+                    std::ptr::copy_nonoverlapping(data.as_ptr(), dst, 12);
+                    std::ptr::copy_nonoverlapping(tag.as_ptr(), dst.add(12), tag.len());
+                    std::ptr::copy_nonoverlapping(
+                        data[12..].as_ptr(),
+                        dst.add(12 + tag.len()),
+                        data.len() - 12,
+                    );
+                }
+                None => std::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len()),
+            }
+        }
+        mbuf.raw_mut().data_len = len as u16;
+        mbuf.raw_mut().pkt_len = len as u32;
+        Ok(mbuf)
+    }
+
+    /// Returns the contents of the Mbuf as a byte slice (first segment only).
     pub fn data(&self) -> &[u8] {
         let ptr = self.get_data_address(0);
         unsafe { slice::from_raw_parts(ptr, self.data_len()) as &[u8] }
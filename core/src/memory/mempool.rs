@@ -1,12 +1,23 @@
 //! Memory pools to allocate DPDK message buffers.
+//!
+//! Unless its capacity is configured, each pool is sized from the mbufs that may be held at the
+//! same time by the queues, cores, and connections that allocate from it (see
+//! [MbufDemand](MbufDemand)). Pools are checked against the free hugepage memory of their socket,
+//! and its configured memory limit, before they are created, so that a misconfiguration fails at
+//! startup with the amount of memory missing. Once all pools are created, the memory used by each
+//! one is logged (see [log_budget](log_budget)).
 
-use crate::config::MempoolConfig;
+use crate::config::{MempoolConfig, TrackedPacketsConfig};
 use crate::dpdk;
 use crate::lcore::SocketId;
 use std::cmp;
+use std::collections::BTreeMap;
 use std::ffi::{CStr, CString};
 use std::fmt;
+use std::fs;
+use std::mem;
 use std::os::raw::{c_int, c_uint};
+use std::path::Path;
 use std::ptr::NonNull;
 
 use anyhow::Result;
@@ -14,27 +25,157 @@ use thiserror::Error;
 
 const RX_BUF_ALIGN: u32 = 1024;
 
+/// Number of mbufs received or dequeued at a time by each core.
+const BURST_SIZE: usize = 32;
+/// Average number of mbufs buffered by a connection that tracks packets.
+const TRACKED_PKTS_PER_CONN: usize = 16;
+/// Average number of out-of-order mbufs buffered by a connection being reassembled.
+const OOO_PKTS_PER_CONN: usize = 2;
+/// Per-object overhead of a mempool (object header and alignment), in bytes.
+const OBJ_OVERHEAD: usize = 64;
+
+/// The mbufs that may be held at the same time by the users of a mempool, from which its capacity
+/// is computed if it is not configured.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct MbufDemand {
+    /// RX and TX descriptors of the queues that allocate from the pool.
+    pub(crate) descriptors: usize,
+    /// Cores that allocate from the pool, or process packets allocated from it.
+    pub(crate) cores: usize,
+    /// Slots of the software dispatch rings.
+    pub(crate) ring_slots: usize,
+    /// Whether connections buffer packets for the subscription.
+    pub(crate) track_packets: bool,
+}
+
+impl MbufDemand {
+    /// Returns the number of mbufs to allocate: the configured capacity if set, or enough mbufs
+    /// to fill every queue and ring, the cores' caches and bursts, and the packets buffered by
+    /// `config.buffered_connections` connections per core. Rounded up to a power of two minus
+    /// one, the optimal size for a DPDK mempool.
+    pub(crate) fn capacity(&self, config: &MempoolConfig) -> usize {
+        if let Some(capacity) = config.capacity {
+            return capacity;
+        }
+        let per_conn = if self.track_packets {
+            TRACKED_PKTS_PER_CONN
+        } else {
+            OOO_PKTS_PER_CONN
+        };
+        let per_core =
+            config.cache_size * 3 / 2 + 2 * BURST_SIZE + config.buffered_connections * per_conn;
+        let capacity = self.descriptors + self.ring_slots + self.cores.max(1) * per_core;
+        (capacity + 1).next_power_of_two() - 1
+    }
+}
+
 /// A wrapper around a DPDK `rte_mempool` for packet mbufs.
 /// It is recommended to allocate one Mempool per NUMA node.
 pub(crate) struct Mempool {
     raw: NonNull<dpdk::rte_mempool>,
+    data_room: usize,
 }
 
 impl Mempool {
-    /// Creates a new mbuf pool on socket_id
-    pub(crate) fn new(config: &MempoolConfig, socket_id: SocketId, mtu: usize) -> Result<Self> {
+    /// Creates a new mbuf pool on socket_id, sized for `demand` unless the capacity is configured.
+    /// Mbufs hold a full frame of size `mtu`, or at most `segment_size` bytes if it is configured.
+    pub(crate) fn new(
+        config: &MempoolConfig,
+        socket_id: SocketId,
+        mtu: usize,
+        demand: MbufDemand,
+    ) -> Result<Self> {
+        let mut data_room = crate::port::mtu_to_max_frame_len(mtu as u32);
+        if let Some(segment_size) = config.segment_size {
+            data_room = cmp::min(data_room, segment_size as u32);
+        }
+        let capacity = demand.capacity(config);
+        if config.capacity.is_none() {
+            log::info!(
+                "Sizing mempool_{} for {} mbufs ({:?})",
+                socket_id,
+                capacity,
+                demand
+            );
+        }
+        Mempool::create(
+            format!("mempool_{}", socket_id),
+            capacity,
+            config,
+            rx_mbuf_size(data_room),
+            socket_id,
+        )
+    }
+
+    /// Creates a pool of full-size mbufs on socket_id that segmented frames are copied into.
+    pub(crate) fn new_jumbo(
+        config: &MempoolConfig,
+        socket_id: SocketId,
+        mtu: usize,
+    ) -> Result<Self> {
         let data_room = crate::port::mtu_to_max_frame_len(mtu as u32);
-        let data_room_aligned = round_up(data_room, RX_BUF_ALIGN);
-        let mbuf_size = data_room_aligned + dpdk::RTE_PKTMBUF_HEADROOM;
-        let mbuf_size = cmp::max(mbuf_size, dpdk::RTE_MBUF_DEFAULT_BUF_SIZE);
+        Mempool::create(
+            format!("jumbo_mempool_{}", socket_id),
+            config.jumbo_capacity,
+            config,
+            rx_mbuf_size(data_room),
+            socket_id,
+        )
+    }
+
+    /// Creates a pool on socket_id that tracked packets are copied into. Mbufs hold at most
+    /// `tracked.snaplen` bytes, or a full frame of size `mtu`. They are never used by the NIC, so
+    /// they are not aligned to the receive buffer size.
+    pub(crate) fn new_tracked(
+        config: &MempoolConfig,
+        tracked: &TrackedPacketsConfig,
+        socket_id: SocketId,
+        mtu: usize,
+    ) -> Result<Self> {
+        let mut data_room = crate::port::mtu_to_max_frame_len(mtu as u32);
+        if let Some(snaplen) = tracked.snaplen {
+            data_room = cmp::min(data_room, snaplen as u32);
+        }
+        Mempool::create(
+            format!("tracked_mempool_{}", socket_id),
+            tracked.capacity,
+            config,
+            data_room + dpdk::RTE_PKTMBUF_HEADROOM,
+            socket_id,
+        )
+    }
+
+    fn create(
+        name: String,
+        capacity: usize,
+        config: &MempoolConfig,
+        mbuf_size: u32,
+        socket_id: SocketId,
+    ) -> Result<Self> {
+        let cache_size = config.cache_size;
+        // Fail early, with the amount of memory missing, rather than with an opaque DPDK error
+        let required = capacity as u64
+            * (mbuf_size as u64 + mem::size_of::<dpdk::rte_mbuf>() as u64 + OBJ_OVERHEAD as u64);
+        let limit = config.socket_limit(socket_id).map(|mb| (mb as u64) << 20);
+        if let Some(available) = available_memory(socket_id, limit) {
+            if required > available {
+                return Err(MempoolError::InsufficientMemory {
+                    name,
+                    capacity,
+                    socket_id,
+                    required_mb: required >> 20,
+                    available_mb: available >> 20,
+                }
+                .into());
+            }
+        }
 
-        let name = format!("mempool_{}", socket_id);
         let cname = CString::new(name.clone()).expect("Invalid CString conversion");
         let mempool = unsafe {
             dpdk::rte_pktmbuf_pool_create(
                 cname.as_ptr(),
-                config.capacity as c_uint,
-                config.cache_size as c_uint,
+                capacity as c_uint,
+                cache_size as c_uint,
                 0,
                 mbuf_size as u16,
                 socket_id.raw() as c_int,
@@ -42,6 +183,7 @@ impl Mempool {
         };
         Ok(Mempool {
             raw: NonNull::new(mempool).ok_or(MempoolError::Create(name))?,
+            data_room: (mbuf_size - dpdk::RTE_PKTMBUF_HEADROOM) as usize,
         })
     }
 
@@ -61,6 +203,23 @@ impl Mempool {
         cstr.to_str().unwrap()
     }
 
+    /// Returns the hugepage memory used by the mbufs of the pool, in bytes.
+    pub(crate) fn footprint(&self) -> u64 {
+        let raw = self.raw();
+        raw.size as u64 * (raw.header_size + raw.elt_size + raw.trailer_size) as u64
+    }
+
+    /// Returns the size of each mbuf in the pool (including its headers), in bytes.
+    pub(crate) fn obj_size(&self) -> usize {
+        let raw = self.raw();
+        (raw.header_size + raw.elt_size + raw.trailer_size) as usize
+    }
+
+    /// Number of bytes of packet data that each mbuf holds.
+    pub(crate) fn data_room(&self) -> usize {
+        self.data_room
+    }
+
     /// Default mbuf size in bytes.
     pub(crate) fn default_mtu() -> usize {
         1500
@@ -85,6 +244,90 @@ impl fmt::Debug for Mempool {
     }
 }
 
+/// Logs the memory used by `mempools` on each socket, and the memory limit of the socket.
+pub(crate) fn log_budget<'a>(mempools: impl Iterator<Item = &'a Mempool>, config: &MempoolConfig) {
+    let mut sockets: BTreeMap<SocketId, Vec<&Mempool>> = BTreeMap::new();
+    for mempool in mempools {
+        sockets
+            .entry(SocketId(mempool.raw().socket_id as u32))
+            .or_default()
+            .push(mempool);
+    }
+    log::info!("Memory budget:");
+    for (socket_id, mempools) in sockets.iter() {
+        let total: u64 = mempools.iter().map(|mempool| mempool.footprint()).sum();
+        match config.socket_limit(*socket_id) {
+            Some(limit) => log::info!(
+                "  socket {}: {} MB of {} MB limit",
+                socket_id,
+                total >> 20,
+                limit
+            ),
+            None => log::info!("  socket {}: {} MB", socket_id, total >> 20),
+        }
+        for mempool in mempools.iter() {
+            log::info!(
+                "    {}: {} mbufs x {} B = {} MB (cache {} per core)",
+                mempool.name(),
+                mempool.raw().size,
+                mempool.obj_size(),
+                mempool.footprint() >> 20,
+                mempool.raw().cache_size
+            );
+        }
+    }
+}
+
+/// Returns the hugepage memory available to DPDK on `socket_id`, in bytes: the free space of the
+/// DPDK heap and the hugepages not yet reserved, capped by the memory left under `limit` (in
+/// bytes), if set. Returns `None` if it cannot be determined.
+fn available_memory(socket_id: SocketId, limit: Option<u64>) -> Option<u64> {
+    let mut stats: dpdk::rte_malloc_socket_stats = unsafe { mem::zeroed() };
+    let (heap_free, heap_alloc) =
+        match unsafe { dpdk::rte_malloc_get_socket_stats(socket_id.raw() as c_int, &mut stats) } {
+            0 => (
+                stats.heap_freesz_bytes as u64,
+                stats.heap_allocsz_bytes as u64,
+            ),
+            _ => (0, 0),
+        };
+    let remaining = limit.map(|limit| limit.saturating_sub(heap_alloc));
+    let available = free_hugepages(socket_id).map(|free_pages| heap_free + free_pages);
+    match (available, remaining) {
+        (Some(available), Some(remaining)) => Some(cmp::min(available, remaining)),
+        (available, remaining) => available.or(remaining),
+    }
+}
+
+/// Returns the size of the hugepages not yet reserved on `socket_id`, in bytes.
+fn free_hugepages(socket_id: SocketId) -> Option<u64> {
+    let node = format!("/sys/devices/system/node/node{}/hugepages", socket_id.raw());
+    let dir = if Path::new(&node).is_dir() {
+        node
+    } else {
+        "/sys/kernel/mm/hugepages".to_string()
+    };
+    let mut free_pages = 0;
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        // e.g., hugepages-2048kB
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let page_kb = name
+            .strip_prefix("hugepages-")
+            .and_then(|size| size.strip_suffix("kB"))
+            .and_then(|size| size.parse::<u64>().ok())?;
+        let free = fs::read_to_string(entry.path().join("free_hugepages")).ok()?;
+        free_pages += free.trim().parse::<u64>().ok()? * page_kb * 1024;
+    }
+    Some(free_pages)
+}
+
+/// Returns the size of mbufs that the NIC receives frames of `data_room` bytes into.
+fn rx_mbuf_size(data_room: u32) -> u32 {
+    let data_room_aligned = round_up(data_room, RX_BUF_ALIGN);
+    let mbuf_size = data_room_aligned + dpdk::RTE_PKTMBUF_HEADROOM;
+    cmp::max(mbuf_size, dpdk::RTE_MBUF_DEFAULT_BUF_SIZE)
+}
+
 /// Rounds `n` up to the nearest multiple of `s`
 fn round_up(n: u32, s: u32) -> u32 {
     ((n + s - 1) / s) * s
@@ -95,6 +338,40 @@ pub(crate) enum MempoolError {
     #[error("Mempool {0} creation failed")]
     Create(String),
 
+    #[error("Mempool {name} needs {required_mb} MB of hugepage memory for {capacity} mbufs, but only {available_mb} MB are available on socket {socket_id}. Allocate more hugepages on the socket (e.g., with dpdk-hugepages.py) or raise mempool.socket_limit, or reduce mempool.capacity, mempool.buffered_connections, or the number of queue descriptors.")]
+    InsufficientMemory {
+        name: String,
+        capacity: usize,
+        socket_id: SocketId,
+        required_mb: u64,
+        available_mb: u64,
+    },
+
     #[error("Mbuf allocation failed: mempool exhausted.")]
     Exhausted,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_mempool_capacity() {
+        let mut config = crate::config::default_config().mempool;
+        let demand = MbufDemand {
+            descriptors: 4 * 4096,
+            cores: 4,
+            ..Default::default()
+        };
+        assert_eq!(demand.capacity(&config), 8192);
+        config.capacity = None;
+        let capacity = demand.capacity(&config);
+        assert!((capacity + 1).is_power_of_two());
+        assert!(capacity >= 4 * 4096 + 4 * config.buffered_connections * OOO_PKTS_PER_CONN);
+        let tracked = MbufDemand {
+            track_packets: true,
+            ..demand
+        };
+        assert!(tracked.capacity(&config) > capacity);
+    }
+}
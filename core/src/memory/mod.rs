@@ -2,3 +2,4 @@
 
 pub mod mbuf;
 pub(crate) mod mempool;
+pub mod pool;
@@ -0,0 +1,144 @@
+//! Per-core pools of tracked-data buffers.
+//!
+//! Connection entries are not allocated individually: each core's connection table stores them,
+//! with their parser and tracked data inline, in an array that grows with the number of tracked
+//! connections up to `max_connections`, and is reused as connections come and go (see
+//! [ConnTable](crate::conntrack::table::ConnTable)).
+//! The pools of this module cover the buffers that grow with the data of a connection.
+//!
+//! Connections buffer tracked packets and out-of-order TCP segments in vectors that grow as data
+//! arrives, and are freed when the connection stops buffering or is removed. During connection
+//! storms, this churn contends on the allocator and fragments the heap. Instead, buffers are taken
+//! from per-core pools when a connection first buffers data, and returned to them emptied. Each
+//! pool keeps up to `MAX_POOLED` buffers, of at most `MAX_CAPACITY` elements (larger buffers are
+//! freed, so that a few long connections do not hold on to memory).
+//!
+//! Parsed sessions (which are boxed to be delivered to callbacks), the state of custom parsers,
+//! and the defragmentation buffers of parsers are still allocated on the heap.
+//!
+//! If the mempool `tracked` option is set, the tracked packets themselves are copied into mbufs
+//! from a separate pool (see [TrackedPacketsConfig](crate::config::TrackedPacketsConfig)), so
+//! that buffering connections do not hold on to the mbufs the NIC receives into. If that mempool
+//! is exhausted, packets are not tracked, and are counted in the `untracked_pkts` metric.
+
+use crate::conntrack::pdu::L4Pdu;
+use crate::dpdk;
+use crate::memory::mbuf::Mbuf;
+
+use std::cell::{Cell, RefCell};
+use std::ptr;
+
+/// Maximum number of buffers kept by each pool.
+const MAX_POOLED: usize = 4096;
+/// Maximum capacity of the buffers kept by the pools.
+const MAX_CAPACITY: usize = 256;
+
+/// A pool of emptied vectors.
+struct VecPool<T> {
+    free: RefCell<Vec<Vec<T>>>,
+}
+
+impl<T> VecPool<T> {
+    const fn new() -> Self {
+        VecPool {
+            free: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn take(&self) -> Vec<T> {
+        self.free.borrow_mut().pop().unwrap_or_default()
+    }
+
+    fn recycle(&self, mut vec: Vec<T>) {
+        if vec.capacity() == 0 || vec.capacity() > MAX_CAPACITY {
+            return;
+        }
+        vec.clear();
+        let mut free = self.free.borrow_mut();
+        if free.len() < MAX_POOLED {
+            free.push(vec);
+        }
+    }
+}
+
+thread_local! {
+    static PACKETS: VecPool<Mbuf> = const { VecPool::new() };
+    static SEGMENTS: VecPool<L4Pdu> = const { VecPool::new() };
+    static TRACKED_MEMPOOL: Cell<*mut dpdk::rte_mempool> = const { Cell::new(ptr::null_mut()) };
+    static UNTRACKED: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Sets the mempool that the packets tracked on this core are copied into, or null to track them
+/// in their receive mbufs.
+pub(crate) fn set_tracked_mempool(mempool: *mut dpdk::rte_mempool) {
+    TRACKED_MEMPOOL.with(|tracked| tracked.set(mempool));
+}
+
+/// Returns the packet to store for a connection that tracks `mbuf`: a (possibly truncated) copy
+/// from the tracked packet mempool of this core, if any, or `mbuf` itself. Returns `None` if the
+/// tracked packet mempool is exhausted, in which case `mbuf` is dropped rather than held on to.
+#[inline]
+pub fn track(mbuf: Mbuf) -> Option<Mbuf> {
+    let mempool = TRACKED_MEMPOOL.with(|tracked| tracked.get());
+    if mempool.is_null() {
+        return Some(mbuf);
+    }
+    match mbuf.copy_truncated(mempool) {
+        Ok(copy) => Some(copy),
+        Err(error) => {
+            log::debug!("Failed to copy tracked packet: {}", error);
+            UNTRACKED.with(|untracked| untracked.set(untracked.get() + 1));
+            None
+        }
+    }
+}
+
+/// Returns the number of packets that were not tracked on this core because the tracked packet
+/// mempool was exhausted.
+pub(crate) fn nb_untracked_pkts() -> u64 {
+    UNTRACKED.with(|untracked| untracked.get())
+}
+
+/// Returns an empty buffer for the tracked packets of a connection, from the pool of this core.
+#[inline]
+pub fn take_packets() -> Vec<Mbuf> {
+    PACKETS.with(|pool| pool.take())
+}
+
+/// Drops the tracked packets of a connection, and returns their buffer to the pool of this core.
+#[inline]
+pub fn recycle_packets(packets: Vec<Mbuf>) {
+    let _ = PACKETS.try_with(|pool| pool.recycle(packets));
+}
+
+/// Returns an empty buffer for out-of-order segments, from the pool of this core.
+#[inline]
+pub(crate) fn take_segments() -> Vec<L4Pdu> {
+    SEGMENTS.with(|pool| pool.take())
+}
+
+/// Drops out-of-order segments, and returns their buffer to the pool of this core.
+#[inline]
+pub(crate) fn recycle_segments(segments: Vec<L4Pdu>) {
+    let _ = SEGMENTS.try_with(|pool| pool.recycle(segments));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_pool_recycle() {
+        let pool = VecPool::<u32>::new();
+        assert_eq!(pool.take().capacity(), 0);
+        pool.recycle(Vec::with_capacity(8));
+        let mut vec = pool.take();
+        assert!(vec.is_empty() && vec.capacity() >= 8);
+        vec.push(1);
+        pool.recycle(vec);
+        assert!(pool.take().is_empty());
+        // Large buffers are freed
+        pool.recycle(Vec::with_capacity(MAX_CAPACITY + 1));
+        assert_eq!(pool.take().capacity(), 0);
+    }
+}
@@ -0,0 +1,155 @@
+//! NIC hardware RX timestamps.
+//!
+//! PMDs that support `DEV_RX_OFFLOAD_TIMESTAMP` store the time each packet was received in a
+//! dynamic mbuf field, in ticks of the device clock. Each port's clock is calibrated against the
+//! host monotonic clock once the port is started, so that timestamps can be converted to
+//! [Instant](std::time::Instant)s and compared with software time.
+//!
+//! In offline mode, packets read from capture files are stamped with their capture timestamps
+//! in the same field, so that time is measured on the capture rather than on processing.
+
+use super::PortId;
+use crate::dpdk;
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Duration over which the device clock is sampled to estimate its frequency.
+const CALIBRATION_PERIOD: Duration = Duration::from_millis(100);
+
+/// Offset of the timestamp field in the mbuf, or -1 if it is not registered.
+static TIMESTAMP_OFFSET: AtomicI32 = AtomicI32::new(-1);
+/// `ol_flags` bit set on mbufs that carry a timestamp.
+static TIMESTAMP_FLAG: AtomicU64 = AtomicU64::new(0);
+/// Calibrated device clock of each port.
+static CLOCKS: OnceLock<BTreeMap<u16, NicClock>> = OnceLock::new();
+/// Port of the mbufs read from capture files (`RTE_MBUF_PORT_INVALID`).
+const CAPTURE_PORT: u16 = u16::MAX;
+
+/// Mapping from device clock ticks to host time.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct NicClock {
+    base_ticks: u64,
+    base: Instant,
+    ns_per_tick: f64,
+}
+
+impl NicClock {
+    /// Estimates the frequency of the device clock of `port_id`. Returns `None` if the device
+    /// clock cannot be read.
+    pub(crate) fn calibrate(port_id: PortId) -> Option<NicClock> {
+        let (ticks0, start) = read_clock(port_id)?;
+        std::thread::sleep(CALIBRATION_PERIOD);
+        let (ticks1, end) = read_clock(port_id)?;
+        if ticks1 <= ticks0 {
+            return None;
+        }
+        Some(NicClock {
+            base_ticks: ticks1,
+            base: end,
+            ns_per_tick: (end - start).as_nanos() as f64 / (ticks1 - ticks0) as f64,
+        })
+    }
+
+    /// Converts a device timestamp to host time.
+    fn to_instant(self, ticks: u64) -> Instant {
+        let ns = (ticks as i64).wrapping_sub(self.base_ticks as i64) as f64 * self.ns_per_tick;
+        if ns >= 0.0 {
+            self.base + Duration::from_nanos(ns as u64)
+        } else {
+            self.base
+                .checked_sub(Duration::from_nanos(-ns as u64))
+                .unwrap_or(self.base)
+        }
+    }
+}
+
+fn read_clock(port_id: PortId) -> Option<(u64, Instant)> {
+    let mut ticks = 0;
+    let ret = unsafe { dpdk::rte_eth_read_clock(port_id.raw(), &mut ticks) };
+    if ret != 0 {
+        return None;
+    }
+    Some((ticks, Instant::now()))
+}
+
+/// Looks up the timestamp field registered by the PMDs, and records the calibrated clock of each
+/// port. Must be called once all ports with timestamping enabled have been started.
+pub(crate) fn init(clocks: BTreeMap<PortId, NicClock>) -> bool {
+    if !register() {
+        return false;
+    }
+    let clocks = clocks.into_iter().map(|(id, c)| (id.raw(), c)).collect();
+    CLOCKS.set(clocks).is_ok()
+}
+
+/// Looks up the timestamp field for packets read from capture files, whose timestamps (since the
+/// Unix epoch) are mapped to host time at `speed` times the pace of the capture, from `start`,
+/// the host time of the capture timestamp `first_ts`.
+pub(crate) fn init_capture(start: Instant, first_ts: Duration, speed: f64) -> bool {
+    if !register() {
+        return false;
+    }
+    let clock = NicClock {
+        base_ticks: first_ts.as_nanos() as u64,
+        base: start,
+        ns_per_tick: 1.0 / speed,
+    };
+    CLOCKS.set(BTreeMap::from([(CAPTURE_PORT, clock)])).is_ok()
+}
+
+/// Registers the timestamp field, if it is not already.
+fn register() -> bool {
+    let mut offset = -1;
+    let mut flag = 0;
+    let ret = unsafe { dpdk::rte_mbuf_dyn_rx_timestamp_register(&mut offset, &mut flag) };
+    if ret != 0 || offset < 0 {
+        return false;
+    }
+    TIMESTAMP_OFFSET.store(offset, Ordering::Relaxed);
+    TIMESTAMP_FLAG.store(flag, Ordering::Relaxed);
+    true
+}
+
+/// Returns the device timestamp of `mbuf`, if it has one.
+fn raw_timestamp(mbuf: &dpdk::rte_mbuf) -> Option<u64> {
+    let offset = TIMESTAMP_OFFSET.load(Ordering::Relaxed);
+    if offset < 0 || mbuf.ol_flags & TIMESTAMP_FLAG.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    let field = unsafe { (mbuf as *const _ as *const u8).offset(offset as isize) as *const u64 };
+    Some(unsafe { field.read_unaligned() })
+}
+
+/// Returns the time the NIC received `mbuf`, if it was timestamped.
+pub(crate) fn rx_timestamp(mbuf: &dpdk::rte_mbuf) -> Option<Instant> {
+    let ticks = raw_timestamp(mbuf)?;
+    let clock = CLOCKS.get()?.get(&mbuf.port)?;
+    Some(clock.to_instant(ticks))
+}
+
+/// Copies the timestamp of `src` (if any) to `dst`.
+pub(crate) fn copy_rx_timestamp(src: &dpdk::rte_mbuf, dst: &mut dpdk::rte_mbuf) {
+    if let Some(ticks) = raw_timestamp(src) {
+        let offset = TIMESTAMP_OFFSET.load(Ordering::Relaxed);
+        let field = unsafe { (dst as *mut _ as *mut u8).offset(offset as isize) as *mut u64 };
+        unsafe { field.write_unaligned(ticks) };
+        dst.ol_flags |= TIMESTAMP_FLAG.load(Ordering::Relaxed);
+        dst.port = src.port;
+    }
+}
+
+/// Stamps `mbuf`, read from a capture file, with its capture timestamp `ts` (since the Unix epoch),
+/// once the capture clock is initialized.
+pub(crate) fn set_capture_timestamp(mbuf: &mut dpdk::rte_mbuf, ts: Duration) {
+    let offset = TIMESTAMP_OFFSET.load(Ordering::Relaxed);
+    if offset < 0 {
+        return;
+    }
+    let field = unsafe { (mbuf as *mut _ as *mut u8).offset(offset as isize) as *mut u64 };
+    unsafe { field.write_unaligned(ts.as_nanos() as u64) };
+    mbuf.ol_flags |= TIMESTAMP_FLAG.load(Ordering::Relaxed);
+    mbuf.port = CAPTURE_PORT;
+}
@@ -0,0 +1,251 @@
+//! Link-state and hotplug events.
+//!
+//! Ports can lose their link (e.g., when a tap aggregator flaps), or be removed from the system
+//! altogether. DPDK reports these events through callbacks that run on its interrupt thread. The
+//! callbacks record the state of each port, which the RX cores check before polling its queues, and
+//! forward the events to the main core. There, they are logged and delivered to the callback
+//! registered with [on_event](crate::Runtime::on_event).
+//!
+//! Polling of a port is paused while its link is down and resumes once it is back up. A removed
+//! port is never polled again: a device that is attached again is reported, but is not
+//! reconfigured until the runtime is restarted. Ports whose driver does not report link-state
+//! changes through interrupts are checked periodically instead.
+
+use super::PortId;
+use crate::dpdk;
+
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Receiver, Sender};
+
+/// Interval between checks of the link state of ports without link-state interrupts.
+const LINK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const LINK_UP: u8 = 0;
+const LINK_DOWN: u8 = 1;
+const REMOVED: u8 = 2;
+
+/// State of each port, indexed by port ID. Ports are considered up until an event says otherwise.
+#[allow(clippy::declare_interior_mutable_const)]
+const UP: AtomicU8 = AtomicU8::new(LINK_UP);
+static STATES: [AtomicU8; dpdk::RTE_MAX_ETHPORTS as usize] = [UP; dpdk::RTE_MAX_ETHPORTS as usize];
+
+/// Events raised on the interrupt thread, to be handled on the main core.
+static EVENTS: OnceLock<Sender<(u16, dpdk::rte_eth_event_type)>> = OnceLock::new();
+
+/// Callback registered with [on_event](crate::Runtime::on_event).
+pub(crate) type EventCallback = Box<dyn FnMut(&RuntimeEvent) + Send>;
+
+/// A change in the state of a port, delivered to the callback registered with
+/// [on_event](crate::Runtime::on_event).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeEvent {
+    /// The link of a port went down. Packets are not polled from the port until the link is up
+    /// again.
+    LinkDown {
+        /// PCI address (or name) of the port.
+        device: String,
+    },
+    /// The link of a port came (back) up. Polling of the port resumes.
+    LinkUp {
+        /// PCI address (or name) of the port.
+        device: String,
+        /// Link speed, in Mbps.
+        speed: u32,
+    },
+    /// A port was removed from the system. Packets are not polled from the port for the rest of
+    /// the run.
+    DeviceRemoved {
+        /// PCI address (or name) of the port.
+        device: String,
+    },
+    /// A device was attached while running. Packets are only polled from it after a restart.
+    DeviceAdded {
+        /// PCI address (or name) of the device.
+        device: String,
+    },
+}
+
+/// Returns `true` if the queues of `port_id` can be polled.
+#[inline]
+pub(crate) fn is_active(port_id: PortId) -> bool {
+    STATES[port_id.raw() as usize].load(Ordering::Relaxed) == LINK_UP
+}
+
+/// Receives the events of the configured ports on the main core.
+pub(crate) struct PortEvents {
+    events: Receiver<(u16, dpdk::rte_eth_event_type)>,
+    /// Name of each configured port.
+    devices: BTreeMap<PortId, String>,
+    /// Configured ports whose driver does not raise link-state interrupts.
+    polled: Vec<PortId>,
+    last_poll: Instant,
+}
+
+impl std::fmt::Debug for PortEvents {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("PortEvents")
+            .field("devices", &self.devices)
+            .field("polled", &self.polled)
+            .finish()
+    }
+}
+
+impl PortEvents {
+    /// Registers the event callbacks. `devices` are the configured ports, and `polled` those whose
+    /// link state must be checked periodically. Must be called before the ports are started.
+    pub(crate) fn register(devices: BTreeMap<PortId, String>, polled: Vec<PortId>) -> Self {
+        let (tx, events) = crossbeam_channel::unbounded();
+        if EVENTS.set(tx).is_err() {
+            log::warn!("Port event callbacks already registered.");
+        }
+        let port_events = [
+            dpdk::rte_eth_event_type_RTE_ETH_EVENT_INTR_LSC,
+            dpdk::rte_eth_event_type_RTE_ETH_EVENT_INTR_RMV,
+        ];
+        for port_id in devices.keys() {
+            for event in port_events {
+                register_callback(port_id.raw(), event);
+            }
+        }
+        // Newly attached devices do not have a port ID yet
+        register_callback(
+            dpdk::RTE_MAX_ETHPORTS as u16,
+            dpdk::rte_eth_event_type_RTE_ETH_EVENT_NEW,
+        );
+        PortEvents {
+            events,
+            devices,
+            polled,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Handles pending events, passing each to `callback`. Called by the main core.
+    pub(crate) fn poll<F: FnMut(&RuntimeEvent)>(&mut self, mut callback: F) {
+        if !self.polled.is_empty() && self.last_poll.elapsed() >= LINK_POLL_INTERVAL {
+            self.last_poll = Instant::now();
+            for port_id in self.polled.iter() {
+                if let Some(event) = self.update_link(*port_id) {
+                    callback(&event);
+                }
+            }
+        }
+        while let Ok((port_id, event)) = self.events.try_recv() {
+            let event = match event {
+                dpdk::rte_eth_event_type_RTE_ETH_EVENT_INTR_LSC => {
+                    self.update_link(PortId(port_id))
+                }
+                dpdk::rte_eth_event_type_RTE_ETH_EVENT_INTR_RMV => {
+                    let device = self.device(port_id);
+                    log::error!("Port {} ({}) removed, no longer polling.", port_id, device);
+                    Some(RuntimeEvent::DeviceRemoved { device })
+                }
+                dpdk::rte_eth_event_type_RTE_ETH_EVENT_NEW => {
+                    let device = self.device(port_id);
+                    log::warn!(
+                        "Device {} attached, restart the runtime to capture from it.",
+                        device
+                    );
+                    Some(RuntimeEvent::DeviceAdded { device })
+                }
+                _ => None,
+            };
+            if let Some(event) = event {
+                callback(&event);
+            }
+        }
+    }
+
+    /// Reads the link state of `port_id`, and returns an event if it changed.
+    fn update_link(&self, port_id: PortId) -> Option<RuntimeEvent> {
+        let mut link: dpdk::rte_eth_link = unsafe { std::mem::zeroed() };
+        if unsafe { dpdk::rte_eth_link_get_nowait(port_id.raw(), &mut link) } != 0 {
+            return None;
+        }
+        let up = link.link_status() != 0;
+        let state = if up { LINK_UP } else { LINK_DOWN };
+        // A removed port stays removed
+        let prev = STATES[port_id.raw() as usize]
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |prev| {
+                (prev != REMOVED).then_some(state)
+            })
+            .ok()?;
+        let device = self.device(port_id.raw());
+        match (prev, up) {
+            (LINK_DOWN, true) => {
+                log::info!(
+                    "Port {} ({}) link up ({} Mbps), resuming polling.",
+                    port_id,
+                    device,
+                    link.link_speed
+                );
+                Some(RuntimeEvent::LinkUp {
+                    device,
+                    speed: link.link_speed,
+                })
+            }
+            (LINK_UP, false) => {
+                log::warn!("Port {} ({}) link down, pausing polling.", port_id, device);
+                Some(RuntimeEvent::LinkDown { device })
+            }
+            _ => None,
+        }
+    }
+
+    fn device(&self, port_id: u16) -> String {
+        match self.devices.get(&PortId(port_id)) {
+            Some(device) => device.clone(),
+            None => port_name(port_id).unwrap_or_else(|| format!("port {}", port_id)),
+        }
+    }
+}
+
+fn register_callback(port_id: u16, event: dpdk::rte_eth_event_type) {
+    let ret = unsafe {
+        dpdk::rte_eth_dev_callback_register(port_id, event, Some(on_event), ptr::null_mut())
+    };
+    if ret != 0 {
+        log::debug!(
+            "Failed to register callback for event {} on port {}: {}",
+            event,
+            port_id,
+            ret
+        );
+    }
+}
+
+/// Runs on the DPDK interrupt thread.
+unsafe extern "C" fn on_event(
+    port_id: u16,
+    event: dpdk::rte_eth_event_type,
+    _cb_arg: *mut c_void,
+    _ret_param: *mut c_void,
+) -> c_int {
+    // Stop polling a removed port immediately, accessing its queues may crash
+    if event == dpdk::rte_eth_event_type_RTE_ETH_EVENT_INTR_RMV {
+        if let Some(state) = STATES.get(port_id as usize) {
+            state.store(REMOVED, Ordering::Relaxed);
+        }
+    }
+    if let Some(events) = EVENTS.get() {
+        let _ = events.send((port_id, event));
+    }
+    0
+}
+
+fn port_name(port_id: u16) -> Option<String> {
+    let mut name = [0 as c_char; dpdk::RTE_ETH_NAME_MAX_LEN as usize];
+    let ret = unsafe { dpdk::rte_eth_dev_get_name_by_port(port_id, name.as_mut_ptr()) };
+    if ret != 0 {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
@@ -1,10 +1,13 @@
+pub(crate) mod clock;
+pub(crate) mod events;
 #[allow(dead_code)]
 mod info;
 pub(crate) mod statistics;
 
-use crate::config::PortMap;
+use crate::config::{NumaPolicy, PortMap, RssConfig};
 use crate::dpdk;
 use crate::lcore::{CoreId, SocketId};
+use crate::memory::mbuf::Mbuf;
 use crate::memory::mempool::Mempool;
 
 use self::info::PortInfo;
@@ -19,12 +22,6 @@ use std::ptr;
 
 use anyhow::{bail, Result};
 
-const RSS_KEY_LEN: usize = 40;
-pub(crate) const SYMMETRIC_RSS_KEY: [u8; RSS_KEY_LEN] = [
-    0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A,
-    0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A,
-    0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A, 0x6D, 0x5A,
-];
 const RSS_RETA_SIZE: usize = 512;
 
 #[derive(Debug, Copy, Clone, Hash, Ord, Eq, PartialEq, PartialOrd)]
@@ -48,8 +45,14 @@ impl PortId {
         PortId(port_id)
     }
 
-    pub(crate) fn socket_id(&self) -> SocketId {
-        unsafe { SocketId(dpdk::rte_eth_dev_socket_id(self.raw()) as u32) }
+    /// Returns the NUMA socket of the device, or `None` if it cannot be determined.
+    pub(crate) fn socket_id(&self) -> Option<SocketId> {
+        let socket_id = unsafe { dpdk::rte_eth_dev_socket_id(self.raw()) };
+        if socket_id < 0 {
+            None
+        } else {
+            Some(SocketId(socket_id as u32))
+        }
     }
 
     /// For DPDK functions
@@ -74,15 +77,32 @@ pub(crate) struct Port {
     /// Device PCI ID
     pub(crate) device: String,
 
+    /// Socket of the port's memory pool and receive queues
+    pub(crate) socket_id: SocketId,
+
     /// Mapping of receive queues to cores
     pub(crate) queue_map: BTreeMap<RxQueue, CoreId>,
 
+    /// Transmit queue of each core that forwards or transmits packets on the port
+    pub(crate) tx_queues: BTreeMap<CoreId, TxQueue>,
+
+    /// Transmit queue of the application, if packets can be transmitted on the port
+    pub(crate) runtime_tx_queue: Option<TxQueue>,
+
     /// Redirection table mapping RSS bucket IDs to RxQueueIds
     pub(crate) reta: [RxQueueId; RSS_RETA_SIZE],
+
+    /// RSS hash key and functions
+    pub(crate) rss: RssConfig,
 }
 
 impl Port {
-    pub(crate) fn new(port_map: &PortMap) -> Port {
+    pub(crate) fn new(
+        port_map: &PortMap,
+        numa: NumaPolicy,
+        tx_cores: &[u32],
+        transmit: bool,
+    ) -> Port {
         let port_id = PortId::new_from_device(port_map.device.clone());
 
         let mut queue_map: BTreeMap<RxQueue, CoreId> = BTreeMap::new();
@@ -90,8 +110,7 @@ impl Port {
         rx_core_ids.sort_unstable();
         rx_core_ids.dedup();
 
-        // TODO: display warning if cores do not match port socket
-        // TODO: display warning and handle duplicate cores per port and across ports
+        // Cores listed for multiple ports poll one queue on each port
         let mut q: u16 = 0;
         let nb_buckets = if let Some(sink) = &port_map.sink {
             queue_map.insert(
@@ -112,6 +131,35 @@ impl Port {
             q += 1;
         }
 
+        let socket_id = match port_map.socket {
+            Some(socket) => SocketId(socket),
+            None => port_id
+                .socket_id()
+                .unwrap_or_else(|| CoreId(rx_core_ids[0]).socket_id()),
+        };
+        let remote_cores: Vec<CoreId> = queue_map
+            .values()
+            .filter(|core_id| core_id.socket_id() != socket_id)
+            .copied()
+            .collect();
+        if !remote_cores.is_empty() {
+            let msg = format!(
+                "Cores {:?} polling Port {} ({}) are not on the port's socket {}.",
+                remote_cores.iter().map(|c| c.raw()).collect::<Vec<_>>(),
+                port_id,
+                port_map.device,
+                socket_id
+            );
+            match numa {
+                NumaPolicy::Allow => log::debug!("{}", msg),
+                NumaPolicy::Warn => log::warn!("{} May result in reduced performance.", msg),
+                NumaPolicy::Deny => {
+                    log::error!("{} Set `numa = \"allow\"` to override.", msg);
+                    panic!();
+                }
+            }
+        }
+
         if nb_buckets < rx_core_ids.len() {
             log::error!("Requested number of RX redirection table buckets ({}) less than number of RX queues ({}).", nb_buckets, rx_core_ids.len());
             panic!();
@@ -126,38 +174,87 @@ impl Port {
         }
 
         // Set RSS redirection table
-        let mut reta = [RxQueueId(0); RSS_RETA_SIZE];
         let rx_queues: Vec<RxQueueId> = queue_map
             .keys()
             .filter(|rxq| rxq.ty == RxQueueType::Receive)
             .map(|rxq| rxq.qid)
             .collect();
-        for i in 0..nb_buckets {
-            reta[i] = rx_queues[i % rx_queues.len()];
-        }
+        let reta = build_reta(&rx_queues, nb_buckets);
 
         log::debug!("{:?}", reta);
 
+        // Each core that transmits on the port has its own queue, followed by the application's
+        let tx_queues: BTreeMap<CoreId, TxQueue> = tx_cores
+            .iter()
+            .enumerate()
+            .map(|(q, core_id)| (CoreId(*core_id), TxQueue::new(port_id, TxQueueId(q as u16))))
+            .collect();
+
+        let runtime_tx_queue = if transmit {
+            Some(TxQueue::new(port_id, TxQueueId(tx_queues.len() as u16)))
+        } else {
+            None
+        };
+
         Port {
             id: port_id,
             device: port_map.device.clone(),
+            socket_id,
             queue_map,
+            tx_queues,
+            runtime_tx_queue,
             reta,
+            rss: port_map.rss.clone(),
+        }
+    }
+
+    /// Returns the RSS hash functions supported by the device.
+    pub(crate) fn rss_offloads(&self) -> u64 {
+        let mut dev_info: dpdk::rte_eth_dev_info = unsafe { std::mem::zeroed() };
+        // Safety: foreign function.
+        unsafe { dpdk::rte_eth_dev_info_get(self.id.raw(), &mut dev_info) };
+        dev_info.flow_type_rss_offloads
+    }
+
+    /// Returns `true` if the driver reports link-state changes through interrupts.
+    pub(crate) fn lsc_interrupts(&self) -> bool {
+        self.dev_flags() & dpdk::RTE_ETH_DEV_INTR_LSC != 0
+    }
+
+    /// Returns the `RTE_ETH_DEV_*` flags of the device.
+    fn dev_flags(&self) -> u32 {
+        let mut dev_info: dpdk::rte_eth_dev_info = unsafe { mem::zeroed() };
+        let ret = unsafe { dpdk::rte_eth_dev_info_get(self.id.raw(), &mut dev_info) };
+        if ret != 0 || dev_info.dev_flags.is_null() {
+            return 0;
         }
+        unsafe { *dev_info.dev_flags }
     }
 
-    /// Configure port and setup RX queues.
+    /// Configure port and setup RX (and TX) queues. RSS hashes on the configured functions that are also in
+    /// `rss_offloads`, which must be the same for ports that capture the same link.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn init(
         &self,
         mempools: &mut BTreeMap<SocketId, Mempool>,
         nb_rxd: usize,
+        nb_txd: usize,
         mtu: usize,
         promiscuous: bool,
+        rss_offloads: u64,
+        timestamps: bool,
+        rx_interrupts: bool,
     ) -> Result<()> {
-        self.configure(promiscuous, mtu)?;
-
-        let mempool = mempools.get_mut(&self.id.socket_id()).unwrap();
-        self.setup_queues(mempool, nb_rxd)?;
+        let mempool = mempools.get_mut(&self.socket_id).unwrap();
+        self.configure(
+            promiscuous,
+            mtu,
+            rss_offloads,
+            mempool.data_room(),
+            timestamps,
+            rx_interrupts,
+        )?;
+        self.setup_queues(mempool, nb_rxd, nb_txd)?;
         self.display_info();
         Ok(())
     }
@@ -245,32 +342,7 @@ impl Port {
     /// Sets RSS redirection table to full RSS_RETA_SIZE entries
     fn configure_rss_reta(&self) {
         log::info!("Configuring RSS redirection table...");
-        const GROUP_SIZE: usize = dpdk::RTE_RETA_GROUP_SIZE as usize;
-        let capacity = RSS_RETA_SIZE / GROUP_SIZE;
-        let mut reta_conf: Vec<dpdk::rte_eth_rss_reta_entry64> = Vec::with_capacity(capacity);
-
-        for i in 0..capacity {
-            let mut reta_entry64: dpdk::rte_eth_rss_reta_entry64 = unsafe { mem::zeroed() };
-            reta_entry64.mask = u64::MAX;
-            let start = i * GROUP_SIZE;
-            let end = (i + 1) * GROUP_SIZE;
-            let entry64 = self.reta[start..end]
-                .iter()
-                .map(|q| q.raw())
-                .collect::<Vec<_>>();
-
-            //reta_slice.copy_from_slice(&self.reta[start..end]);
-            reta_entry64.reta = entry64.try_into().unwrap();
-            reta_conf.push(reta_entry64);
-        }
-
-        let ret = unsafe {
-            dpdk::rte_eth_dev_rss_reta_update(
-                self.id.raw(),
-                reta_conf.as_mut_ptr(),
-                RSS_RETA_SIZE as u16,
-            )
-        };
+        let ret = update_reta(self.id, &self.reta);
         if ret != 0 {
             if ret == -95 {
                 log::warn!("Setting RSS redirection table is not supported for Port {}. Without a symmetrical key and more than one core, you will experience problems matching connections.", self.id);
@@ -282,7 +354,15 @@ impl Port {
         }
     }
 
-    fn configure(&self, promiscuous: bool, mtu: usize) -> Result<()> {
+    fn configure(
+        &self,
+        promiscuous: bool,
+        mtu: usize,
+        rss_offloads: u64,
+        data_room: usize,
+        timestamps: bool,
+        rx_interrupts: bool,
+    ) -> Result<()> {
         let mut port_conf: dpdk::rte_eth_conf = unsafe { mem::zeroed() };
 
         let mut dev_info: dpdk::rte_eth_dev_info = unsafe { std::mem::zeroed() };
@@ -290,17 +370,106 @@ impl Port {
         unsafe { dpdk::rte_eth_dev_info_get(self.id.raw(), &mut dev_info) };
 
         // turn on RSS
-        if dev_info.flow_type_rss_offloads != 0 {
+        let rss_offloads = rss_offloads & dev_info.flow_type_rss_offloads;
+        let mut rss_hf = 0;
+        for function in self.rss.functions.iter() {
+            let hf = match function.as_str() {
+                "ip" => dpdk::ETH_RSS_IP,
+                "tcp" => dpdk::ETH_RSS_TCP,
+                "udp" => dpdk::ETH_RSS_UDP,
+                "sctp" => dpdk::ETH_RSS_SCTP,
+                _ => bail!("Unknown RSS hash function {}", function),
+            } as u64;
+            if hf & rss_offloads == 0 {
+                log::warn!(
+                    "Port {} does not support RSS hash function {}.",
+                    self.id,
+                    function
+                );
+            }
+            rss_hf |= hf & rss_offloads;
+        }
+        if rss_hf != 0 {
+            if dev_info.hash_key_size != 0 && dev_info.hash_key_size as usize != self.rss.key.len()
+            {
+                bail!(
+                    "Port {} requires a {}-byte RSS key, got {} bytes",
+                    self.id,
+                    dev_info.hash_key_size,
+                    self.rss.key.len()
+                );
+            }
+            if !self.rss.is_symmetric() && self.queue_map.len() > 1 {
+                log::warn!("RSS key of Port {} is not symmetric, the two directions of a connection may be processed on different cores unless software dispatch is enabled.", self.id);
+            }
             port_conf.rxmode.mq_mode = dpdk::rte_eth_rx_mq_mode_ETH_MQ_RX_RSS;
-            port_conf.rx_adv_conf.rss_conf.rss_key = SYMMETRIC_RSS_KEY.as_ptr() as *mut u8;
-            port_conf.rx_adv_conf.rss_conf.rss_key_len = RSS_KEY_LEN as u8;
-            port_conf.rx_adv_conf.rss_conf.rss_hf =
-                (dpdk::ETH_RSS_IP | dpdk::ETH_RSS_TCP | dpdk::ETH_RSS_UDP) as u64
-                    & dev_info.flow_type_rss_offloads;
+            port_conf.rx_adv_conf.rss_conf.rss_key = self.rss.key.as_ptr() as *mut u8;
+            port_conf.rx_adv_conf.rss_conf.rss_key_len = self.rss.key.len() as u8;
+            port_conf.rx_adv_conf.rss_conf.rss_hf = rss_hf;
+        } else if self.queue_map.len() > 1 {
+            log::warn!(
+                "Port {} does not support RSS, all packets will be received on one queue. Consider enabling software dispatch.",
+                self.id
+            );
         }
 
         let max_rx_pkt_len = mtu_to_max_frame_len(mtu as u32);
         port_conf.rxmode.max_rx_pkt_len = cmp::max(dpdk::RTE_ETHER_MAX_LEN, max_rx_pkt_len);
+        if max_rx_pkt_len > dpdk::RTE_ETHER_MAX_LEN {
+            if dev_info.rx_offload_capa & dpdk::DEV_RX_OFFLOAD_JUMBO_FRAME as u64 != 0 {
+                port_conf.rxmode.offloads |= dpdk::DEV_RX_OFFLOAD_JUMBO_FRAME as u64;
+            } else {
+                log::warn!("Port {} does not support jumbo frames.", self.id);
+            }
+        }
+
+        // Link-state and removal events are reported through interrupts if the driver supports it
+        // (see `events`)
+        let dev_flags = self.dev_flags();
+        if dev_flags & dpdk::RTE_ETH_DEV_INTR_LSC != 0 {
+            port_conf.intr_conf.set_lsc(1);
+        }
+        if dev_flags & dpdk::RTE_ETH_DEV_INTR_RMV != 0 {
+            port_conf.intr_conf.set_rmv(1);
+        }
+        // Idle cores wait for receive interrupts instead of polling (see `idle`)
+        if rx_interrupts {
+            port_conf.intr_conf.set_rxq(1);
+        }
+
+        if timestamps {
+            if dev_info.rx_offload_capa & dpdk::DEV_RX_OFFLOAD_TIMESTAMP as u64 != 0 {
+                port_conf.rxmode.offloads |= dpdk::DEV_RX_OFFLOAD_TIMESTAMP as u64;
+            } else {
+                log::warn!(
+                    "Port {} does not support hardware timestamps, using software time.",
+                    self.id
+                );
+            }
+        }
+
+        // frames that do not fit in one mbuf are received (and transmitted) in multiple segments
+        if max_rx_pkt_len as usize > data_room {
+            if dev_info.rx_offload_capa & dpdk::DEV_RX_OFFLOAD_SCATTER as u64 == 0 {
+                bail!(
+                    "Port {} does not support receiving {}-byte frames in {}-byte segments, increase the mempool segment size",
+                    self.id,
+                    max_rx_pkt_len,
+                    data_room
+                );
+            }
+            port_conf.rxmode.offloads |= dpdk::DEV_RX_OFFLOAD_SCATTER as u64;
+            if self.tx_queues().next().is_some() {
+                if dev_info.tx_offload_capa & dpdk::DEV_TX_OFFLOAD_MULTI_SEGS as u64 != 0 {
+                    port_conf.txmode.offloads |= dpdk::DEV_TX_OFFLOAD_MULTI_SEGS as u64;
+                } else {
+                    log::warn!(
+                        "Port {} does not support transmitting segmented frames.",
+                        self.id
+                    );
+                }
+            }
+        }
 
         // turns on VLAN stripping if supported
         if dev_info.rx_offload_capa & dpdk::DEV_RX_OFFLOAD_VLAN_STRIP as u64 != 0 {
@@ -308,9 +477,15 @@ impl Port {
         }
 
         {
-            let nb_queues = self.queue_map.len() as u16;
+            let nb_rx_queues = self.queue_map.len() as u16;
+            let nb_tx_queues = self.tx_queues().count() as u16;
             let ret = unsafe {
-                dpdk::rte_eth_dev_configure(self.id.raw(), nb_queues, 0, &port_conf as *const _)
+                dpdk::rte_eth_dev_configure(
+                    self.id.raw(),
+                    nb_rx_queues,
+                    nb_tx_queues,
+                    &port_conf as *const _,
+                )
             };
             if ret < 0 {
                 bail!("Failed to configure Port {}", self.id);
@@ -359,14 +534,14 @@ impl Port {
         Ok(())
     }
 
-    fn setup_queues(&self, mempool: &mut Mempool, nb_rxd: usize) -> Result<()> {
+    fn setup_queues(&self, mempool: &mut Mempool, nb_rxd: usize, nb_txd: usize) -> Result<()> {
         for rxqueue in self.queue_map.keys() {
             let ret = unsafe {
                 dpdk::rte_eth_rx_queue_setup(
                     self.id.raw(),
                     rxqueue.qid.raw(),
                     nb_rxd as u16,
-                    self.id.socket_id().raw(),
+                    self.socket_id.raw(),
                     ptr::null(),
                     mempool.raw_mut(),
                 )
@@ -375,8 +550,36 @@ impl Port {
                 bail!("Failed to setup up RX queue {}", rxqueue);
             }
         }
+        for txqueue in self.tx_queues() {
+            let ret = unsafe {
+                dpdk::rte_eth_tx_queue_setup(
+                    self.id.raw(),
+                    txqueue.qid.raw(),
+                    nb_txd as u16,
+                    self.socket_id.raw(),
+                    ptr::null(),
+                )
+            };
+            if ret < 0 {
+                bail!("Failed to setup up TX queue {}", txqueue);
+            }
+        }
         Ok(())
     }
+
+    /// Returns the receive queues of the port that are polled for processing.
+    pub(crate) fn rx_queue_ids(&self) -> Vec<RxQueueId> {
+        self.queue_map
+            .keys()
+            .filter(|rxq| rxq.ty == RxQueueType::Receive)
+            .map(|rxq| rxq.qid)
+            .collect()
+    }
+
+    /// Returns all transmit queues of the port.
+    fn tx_queues(&self) -> impl Iterator<Item = &TxQueue> {
+        self.tx_queues.values().chain(self.runtime_tx_queue.iter())
+    }
 }
 
 impl Drop for Port {
@@ -386,6 +589,67 @@ impl Drop for Port {
     }
 }
 
+/// Returns a redirection table that spreads the first `nb_buckets` RSS buckets over `rx_queues`.
+/// The remaining buckets are steered to queue 0 (the sink queue, if any).
+fn build_reta(rx_queues: &[RxQueueId], nb_buckets: usize) -> [RxQueueId; RSS_RETA_SIZE] {
+    let mut reta = [RxQueueId(0); RSS_RETA_SIZE];
+    for i in 0..nb_buckets {
+        reta[i] = rx_queues[i % rx_queues.len()];
+    }
+    reta
+}
+
+/// Programs the redirection table of `port_id`, and returns the DPDK return code.
+fn update_reta(port_id: PortId, reta: &[RxQueueId; RSS_RETA_SIZE]) -> i32 {
+    const GROUP_SIZE: usize = dpdk::RTE_RETA_GROUP_SIZE as usize;
+    let capacity = RSS_RETA_SIZE / GROUP_SIZE;
+    let mut reta_conf: Vec<dpdk::rte_eth_rss_reta_entry64> = Vec::with_capacity(capacity);
+
+    for i in 0..capacity {
+        let mut reta_entry64: dpdk::rte_eth_rss_reta_entry64 = unsafe { mem::zeroed() };
+        reta_entry64.mask = u64::MAX;
+        let start = i * GROUP_SIZE;
+        let end = (i + 1) * GROUP_SIZE;
+        let entry64 = reta[start..end].iter().map(|q| q.raw()).collect::<Vec<_>>();
+
+        reta_entry64.reta = entry64.try_into().unwrap();
+        reta_conf.push(reta_entry64);
+    }
+
+    unsafe {
+        dpdk::rte_eth_dev_rss_reta_update(
+            port_id.raw(),
+            reta_conf.as_mut_ptr(),
+            RSS_RETA_SIZE as u16,
+        )
+    }
+}
+
+/// Changes the number of RSS buckets of a port with a sink core that are steered to `rx_queues`
+/// (i.e., the connection sampling rate) while the port is running.
+pub(crate) fn set_nb_buckets(
+    port_id: PortId,
+    rx_queues: &[RxQueueId],
+    nb_buckets: usize,
+) -> Result<()> {
+    if nb_buckets < rx_queues.len() || nb_buckets > RSS_RETA_SIZE {
+        bail!(
+            "number of RX redirection table buckets must be between {} and {}",
+            rx_queues.len(),
+            RSS_RETA_SIZE
+        );
+    }
+    let ret = update_reta(port_id, &build_reta(rx_queues, nb_buckets));
+    if ret != 0 {
+        bail!(
+            "Failed to set RSS redirection table for Port {}: Error {}",
+            port_id,
+            ret
+        );
+    }
+    Ok(())
+}
+
 fn mtu_to_frame_len(mtu: u32) -> u32 {
     mtu + dpdk::RTE_ETHER_HDR_LEN + dpdk::RTE_ETHER_CRC_LEN
 }
@@ -450,3 +714,65 @@ impl fmt::Display for RxQueue {
         write!(f, "p{}q{}{}", self.pid, self.qid, self.ty)
     }
 }
+
+/* --------------------------------------------------------------------------------- */
+
+#[derive(Debug, Copy, Clone, Hash, Ord, Eq, PartialEq, PartialOrd)]
+pub(crate) struct TxQueueId(pub(crate) u16);
+
+impl TxQueueId {
+    /// For DPDK functions
+    pub(crate) fn raw(&self) -> u16 {
+        self.0
+    }
+}
+
+impl fmt::Display for TxQueueId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Hash, Ord, Eq, PartialEq, PartialOrd)]
+pub(crate) struct TxQueue {
+    pub(crate) pid: PortId,
+    pub(crate) qid: TxQueueId,
+}
+
+impl TxQueue {
+    pub(crate) fn new(pid: PortId, qid: TxQueueId) -> Self {
+        TxQueue { pid, qid }
+    }
+
+    /// Transmits `mbufs`, and returns the number of packets dropped because the queue was full
+    /// (or the port is down). Each queue must only be used by one thread at a time.
+    pub(crate) fn tx_burst(&self, mbufs: Vec<Mbuf>) -> usize {
+        let nb_mbufs = mbufs.len();
+        if nb_mbufs == 0 {
+            return 0;
+        }
+        if !events::is_active(self.pid) {
+            return nb_mbufs;
+        }
+        let mut ptrs: Vec<*mut dpdk::rte_mbuf> = mbufs.into_iter().map(Mbuf::into_raw).collect();
+        let nb_tx = unsafe {
+            dpdk::rte_eth_tx_burst(
+                self.pid.raw(),
+                self.qid.raw(),
+                ptrs.as_mut_ptr(),
+                nb_mbufs as u16,
+            )
+        } as usize;
+        // Free the mbufs that were not transmitted
+        for ptr in ptrs[nb_tx..].iter() {
+            drop(Mbuf::new_unchecked(*ptr));
+        }
+        nb_mbufs - nb_tx
+    }
+}
+
+impl fmt::Display for TxQueue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "p{}q{}t", self.pid, self.qid)
+    }
+}
@@ -0,0 +1,136 @@
+//! ERSPAN packet.
+
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::{Packet, PacketHeader, PacketParseError};
+use crate::utils::types::*;
+
+use anyhow::{bail, Result};
+
+/// GRE protocol type of ERSPAN Type II.
+pub const ERSPAN_II_PROTOCOL: usize = 0x88be;
+/// GRE protocol type of ERSPAN Type III.
+pub const ERSPAN_III_PROTOCOL: usize = 0x22eb;
+
+/// Version field of ERSPAN Type II.
+pub const ERSPAN_II: u8 = 1;
+/// Version field of ERSPAN Type III.
+pub const ERSPAN_III: u8 = 2;
+
+const ERSPAN_II_HEADER_LEN: usize = 8;
+const ERSPAN_III_HEADER_LEN: usize = 12;
+const ERSPAN_III_SUBHEADER_LEN: usize = 8;
+/// "O" flag of ERSPAN Type III, set when the platform-specific subheader is present.
+const ERSPAN_III_OPTIONAL: u8 = 0x01;
+
+/// An ERSPAN Type II or Type III packet, carried over GRE.
+///
+/// The payload is the mirrored Ethernet frame, which can be parsed with
+/// `erspan.parse_to::<Ethernet>()`.
+#[derive(Debug)]
+pub struct Erspan<'a> {
+    /// Fixed header.
+    header: ErspanHeader,
+    /// Offset to `header` from the start of `mbuf`.
+    offset: usize,
+    /// Packet buffer.
+    mbuf: &'a Mbuf,
+}
+
+impl<'a> Erspan<'a> {
+    /// Returns the ERSPAN version (`1` for Type II, `2` for Type III).
+    #[inline]
+    pub fn version(&self) -> u8 {
+        (u16::from(self.header.version_vlan) >> 12) as u8
+    }
+
+    /// Returns the VLAN of the mirrored frame.
+    #[inline]
+    pub fn vlan(&self) -> u16 {
+        u16::from(self.header.version_vlan) & 0x0fff
+    }
+
+    /// Returns the class of service of the mirrored frame.
+    #[inline]
+    pub fn cos(&self) -> u8 {
+        (u16::from(self.header.cos_session) >> 13) as u8
+    }
+
+    /// Returns the identifier of the mirror session.
+    #[inline]
+    pub fn session_id(&self) -> u16 {
+        u16::from(self.header.cos_session) & 0x03ff
+    }
+
+    /// Returns the port index (Type II, lower 20 bits) or the timestamp (Type III) field.
+    #[inline]
+    pub fn index_timestamp(&self) -> u32 {
+        self.header.index_timestamp.into()
+    }
+}
+
+impl<'a> Packet<'a> for Erspan<'a> {
+    fn mbuf(&self) -> &Mbuf {
+        self.mbuf
+    }
+
+    fn header_len(&self) -> usize {
+        if self.version() == ERSPAN_II {
+            return ERSPAN_II_HEADER_LEN;
+        }
+        // The last byte of the Type III header holds the "O" flag
+        match self
+            .mbuf
+            .get_data_slice(self.offset + ERSPAN_III_HEADER_LEN - 1, 1)
+        {
+            Ok(flags) if flags[0] & ERSPAN_III_OPTIONAL != 0 => {
+                ERSPAN_III_HEADER_LEN + ERSPAN_III_SUBHEADER_LEN
+            }
+            _ => ERSPAN_III_HEADER_LEN,
+        }
+    }
+
+    fn next_header_offset(&self) -> usize {
+        self.offset + self.header_len()
+    }
+
+    fn next_header(&self) -> Option<usize> {
+        None
+    }
+
+    fn parse_from(outer: &'a impl Packet<'a>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let offset = outer.next_header_offset();
+        if let Ok(header) = outer.mbuf().get_data::<ErspanHeader>(offset) {
+            let erspan = Erspan {
+                header: unsafe { *header },
+                offset,
+                mbuf: outer.mbuf(),
+            };
+            match (outer.next_header(), erspan.version()) {
+                (Some(ERSPAN_II_PROTOCOL), ERSPAN_II) => Ok(erspan),
+                (Some(ERSPAN_III_PROTOCOL), ERSPAN_III) => Ok(erspan),
+                _ => bail!(PacketParseError::InvalidProtocol),
+            }
+        } else {
+            bail!(PacketParseError::InvalidRead)
+        }
+    }
+}
+
+/// Fixed portion of an ERSPAN header, common to Type II and Type III.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct ErspanHeader {
+    version_vlan: u16be,
+    cos_session: u16be,
+    /// Port index (Type II) or timestamp (Type III).
+    index_timestamp: u32be,
+}
+
+impl PacketHeader for ErspanHeader {
+    fn length(&self) -> usize {
+        ERSPAN_II_HEADER_LEN
+    }
+}
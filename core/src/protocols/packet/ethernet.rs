@@ -9,20 +9,25 @@ use pnet::datalink::MacAddr;
 
 const VLAN_802_1Q: u16 = 0x8100;
 const VLAN_802_1AD: u16 = 0x88a8;
+/// Pre-standard TPID of the outer tag of double-tagged frames.
+const VLAN_QINQ_LEGACY: u16 = 0x9100;
+/// Maximum number of VLAN tags parsed.
+const MAX_TAGS: usize = 2;
 
 const TAG_SIZE: usize = 4;
 const HDR_SIZE: usize = 14;
-const HDR_SIZE_802_1Q: usize = HDR_SIZE + TAG_SIZE;
-const HDR_SIZE_802_1AD: usize = HDR_SIZE_802_1Q + TAG_SIZE;
 
 /// An Ethernet frame.
 ///
-/// On networks that support virtual LANs, the frame may include a VLAN tag after the source MAC
-/// address. Double-tagged frames (QinQ) are not yet supported.
+/// On networks that support virtual LANs, the frame may include a VLAN tag (802.1Q) after the
+/// source MAC address, or two stacked tags (802.1ad, QinQ): a service tag followed by a customer
+/// tag. Frames with more than two tags have no next header.
 #[derive(Debug)]
 pub struct Ethernet<'a> {
     /// Fixed header.
     header: EthernetHeader,
+    /// VLAN tags, from the outer to the inner tag.
+    tags: [Option<Dot1q>; MAX_TAGS],
     /// Offset to `header` from the start of `mbuf`.
     offset: usize,
     /// Packet buffer.
@@ -42,28 +47,39 @@ impl<'a> Ethernet<'a> {
         self.header.src
     }
 
-    /// Returns the encapsulated protocol identifier for untagged and single-tagged frames, and `0`
-    /// for incorrectly fornatted and (not yet supported) double-tagged frames,.
+    /// Returns the encapsulated protocol identifier for untagged, single-tagged, and double-tagged
+    /// frames, and `0` for incorrectly formatted frames and frames with more than two tags.
     #[inline]
     pub fn ether_type(&self) -> u16 {
         self.next_header().unwrap_or(0) as u16
     }
 
-    /// Returns the Tag Control Information field from a 802.1Q (single-tagged)
-    /// frame, if available.
+    /// Returns the Tag Control Information field of the outer (or only) VLAN tag, if available.
     pub fn tci(&self) -> Option<u16> {
-        let ether_type: u16 = u16::from(self.header.ether_type);
-        match ether_type {
-            VLAN_802_1Q => {
-                if let Ok(dot1q) = self.mbuf.get_data(HDR_SIZE) {
-                    let dot1q: Dot1q = unsafe { *dot1q };
-                    Some(dot1q.tci.into())
-                } else {
-                    None
-                }
-            }
-            _ => None,
-        }
+        self.tags[0].map(|tag| tag.tci.into())
+    }
+
+    /// Returns the Tag Control Information field of the inner VLAN tag of a double-tagged
+    /// frame, if available.
+    pub fn inner_tci(&self) -> Option<u16> {
+        self.tags[1].map(|tag| tag.tci.into())
+    }
+
+    /// Returns the VLAN ID of the outer (or only) VLAN tag, if available.
+    #[inline]
+    pub fn vlan_id(&self) -> Option<u16> {
+        self.tci().map(|tci| tci & 0x0fff)
+    }
+
+    /// Returns the VLAN ID of the inner VLAN tag of a double-tagged frame, if available.
+    #[inline]
+    pub fn inner_vlan_id(&self) -> Option<u16> {
+        self.inner_tci().map(|tci| tci & 0x0fff)
+    }
+
+    /// Returns the number of VLAN tags parsed.
+    fn nb_tags(&self) -> usize {
+        self.tags.iter().flatten().count()
     }
 }
 
@@ -73,7 +89,7 @@ impl<'a> Packet<'a> for Ethernet<'a> {
     }
 
     fn header_len(&self) -> usize {
-        self.header.length()
+        self.header.length() + self.nb_tags() * TAG_SIZE
     }
 
     fn next_header_offset(&self) -> usize {
@@ -81,21 +97,14 @@ impl<'a> Packet<'a> for Ethernet<'a> {
     }
 
     fn next_header(&self) -> Option<usize> {
-        let ether_type: u16 = u16::from(self.header.ether_type);
-        match ether_type {
-            VLAN_802_1Q => {
-                if let Ok(dot1q) = self.mbuf.get_data(HDR_SIZE) {
-                    let dot1q: Dot1q = unsafe { *dot1q };
-                    Some(u16::from(dot1q.ether_type).into())
-                } else {
-                    None
-                }
-            }
-            VLAN_802_1AD => {
-                // Unimplemented. TODO: support QinQ
-                None
-            }
-            _ => Some(ether_type.into()),
+        let ether_type: u16 = match self.tags.iter().flatten().next_back() {
+            Some(tag) => tag.ether_type.into(),
+            None => self.header.ether_type.into(),
+        };
+        match is_vlan(ether_type) {
+            // Unimplemented: more than two tags
+            true => None,
+            false => Some(ether_type.into()),
         }
     }
 
@@ -103,18 +112,39 @@ impl<'a> Packet<'a> for Ethernet<'a> {
     where
         Self: Sized,
     {
-        if let Ok(header) = outer.mbuf().get_data(0) {
-            Ok(Ethernet {
-                header: unsafe { *header },
-                offset: 0,
-                mbuf: outer.mbuf(),
-            })
-        } else {
-            bail!(PacketParseError::InvalidRead)
+        let offset = outer.next_header_offset();
+        let mbuf = outer.mbuf();
+        let header: EthernetHeader = match mbuf.get_data(offset) {
+            Ok(header) => unsafe { *header },
+            Err(_) => bail!(PacketParseError::InvalidRead),
+        };
+        let mut tags = [None; MAX_TAGS];
+        let mut ether_type: u16 = header.ether_type.into();
+        for (i, tag) in tags.iter_mut().enumerate() {
+            if !is_vlan(ether_type) {
+                break;
+            }
+            let dot1q: Dot1q = match mbuf.get_data(offset + HDR_SIZE + i * TAG_SIZE) {
+                Ok(dot1q) => unsafe { *dot1q },
+                Err(_) => bail!(PacketParseError::InvalidRead),
+            };
+            ether_type = dot1q.ether_type.into();
+            *tag = Some(dot1q);
         }
+        Ok(Ethernet {
+            header,
+            tags,
+            offset,
+            mbuf,
+        })
     }
 }
 
+/// Returns `true` if `ether_type` is the Tag Protocol Identifier of a VLAN tag.
+fn is_vlan(ether_type: u16) -> bool {
+    matches!(ether_type, VLAN_802_1Q | VLAN_802_1AD | VLAN_QINQ_LEGACY)
+}
+
 /// Fixed portion of an Ethernet header.
 #[derive(Debug, Clone, Copy)]
 #[repr(C, packed)]
@@ -125,12 +155,9 @@ struct EthernetHeader {
 }
 
 impl PacketHeader for EthernetHeader {
+    /// Length of the header without VLAN tags.
     fn length(&self) -> usize {
-        match self.ether_type.into() {
-            VLAN_802_1Q => HDR_SIZE_802_1Q,
-            VLAN_802_1AD => HDR_SIZE_802_1AD,
-            _ => HDR_SIZE,
-        }
+        HDR_SIZE
     }
 }
 
@@ -153,5 +180,3 @@ impl PacketHeader for Dot1q {
         TAG_SIZE
     }
 }
-
-// TODO: Implement QinQ.
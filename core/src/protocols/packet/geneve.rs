@@ -0,0 +1,211 @@
+//! GENEVE packet.
+
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::{Packet, PacketHeader, PacketParseError};
+use crate::utils::types::*;
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+/// IANA-assigned GENEVE UDP port.
+pub const GENEVE_PORT: u16 = 6081;
+const GENEVE_HEADER_LEN: usize = 8;
+const GENEVE_OPTION_HEADER_LEN: usize = 4;
+/// Unit of the option length fields, in bytes.
+const GENEVE_WORD_LEN: usize = 4;
+
+const GENEVE_OAM: u8 = 0x80;
+const GENEVE_CRITICAL: u8 = 0x40;
+
+/// A GENEVE packet (RFC 8926).
+///
+/// Only version 0 is supported. The payload is identified by its EtherType (see
+/// [protocol_type](Geneve::protocol_type)). GENEVE is identified by its UDP destination port,
+/// which is left to the caller to check.
+#[derive(Debug)]
+pub struct Geneve<'a> {
+    /// Fixed header.
+    header: GeneveHeader,
+    /// Offset to `header` from the start of `mbuf`.
+    offset: usize,
+    /// Packet buffer.
+    mbuf: &'a Mbuf,
+}
+
+impl<'a> Geneve<'a> {
+    /// Returns the GENEVE version.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.header.version_opt_len >> 6
+    }
+
+    /// Returns the length of the options, in bytes.
+    #[inline]
+    pub fn options_len(&self) -> usize {
+        (self.header.version_opt_len & 0x3f) as usize * GENEVE_WORD_LEN
+    }
+
+    /// Returns `true` if the packet is an OAM (control) message.
+    #[inline]
+    pub fn oam(&self) -> bool {
+        (self.header.flags & GENEVE_OAM) != 0
+    }
+
+    /// Returns `true` if the packet has critical options.
+    #[inline]
+    pub fn critical(&self) -> bool {
+        (self.header.flags & GENEVE_CRITICAL) != 0
+    }
+
+    /// Returns the EtherType of the payload.
+    #[inline]
+    pub fn protocol_type(&self) -> u16 {
+        self.header.protocol_type.into()
+    }
+
+    /// Returns the 24-bit Virtual Network Identifier.
+    #[inline]
+    pub fn vni(&self) -> u32 {
+        u32::from(self.header.vni) >> 8
+    }
+
+    /// Returns an iterator over the option TLVs.
+    pub fn options(&self) -> GeneveOptions<'a> {
+        let data = self
+            .mbuf
+            .get_data_slice(self.offset + GENEVE_HEADER_LEN, self.options_len())
+            .unwrap_or_default();
+        GeneveOptions { data }
+    }
+}
+
+impl<'a> Packet<'a> for Geneve<'a> {
+    fn mbuf(&self) -> &Mbuf {
+        self.mbuf
+    }
+
+    fn header_len(&self) -> usize {
+        self.header.length()
+    }
+
+    fn next_header_offset(&self) -> usize {
+        self.offset + self.header_len()
+    }
+
+    fn next_header(&self) -> Option<usize> {
+        Some(self.protocol_type().into())
+    }
+
+    fn parse_from(outer: &'a impl Packet<'a>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let offset = outer.next_header_offset();
+        if let Ok(header) = outer.mbuf().get_data::<GeneveHeader>(offset) {
+            let header = unsafe { *header };
+            if header.version_opt_len >> 6 != 0 {
+                bail!(PacketParseError::InvalidProtocol);
+            }
+            Ok(Geneve {
+                header,
+                offset,
+                mbuf: outer.mbuf(),
+            })
+        } else {
+            bail!(PacketParseError::InvalidRead)
+        }
+    }
+}
+
+/// Fixed portion of a GENEVE header.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct GeneveHeader {
+    version_opt_len: u8,
+    flags: u8,
+    protocol_type: u16be,
+    /// VNI in the upper 24 bits, followed by a reserved byte.
+    vni: u32be,
+}
+
+impl PacketHeader for GeneveHeader {
+    /// Header length measured in bytes, including the options. Equivalent to the payload offset.
+    fn length(&self) -> usize {
+        GENEVE_HEADER_LEN + (self.version_opt_len & 0x3f) as usize * GENEVE_WORD_LEN
+    }
+}
+
+/// A GENEVE option TLV.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct GeneveOption<'a> {
+    /// Namespace of the option type.
+    pub class: u16,
+    /// Option type, whose high bit marks critical options.
+    pub option_type: u8,
+    /// Option data.
+    pub data: &'a [u8],
+}
+
+impl<'a> GeneveOption<'a> {
+    /// Returns the length of the option TLV in bytes, including its header.
+    #[inline]
+    pub fn len(&self) -> usize {
+        GENEVE_OPTION_HEADER_LEN + self.data.len()
+    }
+
+    /// Returns `true` if the option has no data.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// Iterator over the option TLVs of a GENEVE header. Stops at the first truncated option.
+#[derive(Debug, Clone)]
+pub struct GeneveOptions<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> GeneveOptions<'a> {
+    /// Returns an iterator over the option TLVs encoded in `data`.
+    pub fn new(data: &'a [u8]) -> Self {
+        GeneveOptions { data }
+    }
+}
+
+impl<'a> Iterator for GeneveOptions<'a> {
+    type Item = GeneveOption<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = self.data.get(..GENEVE_OPTION_HEADER_LEN)?;
+        let len = GENEVE_OPTION_HEADER_LEN + (header[3] & 0x1f) as usize * GENEVE_WORD_LEN;
+        let option = self.data.get(..len)?;
+        self.data = &self.data[len..];
+        Some(GeneveOption {
+            class: u16::from_be_bytes([option[0], option[1]]),
+            option_type: option[2],
+            data: &option[GENEVE_OPTION_HEADER_LEN..],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_geneve_options() {
+        let data = [
+            0x01, 0x03, 0x80, 0x01, 0xde, 0xad, 0xbe, 0xef, // class 0x0103, one word
+            0xff, 0xff, 0x01, 0x00, // class 0xffff, no data
+            0x00, 0x01, 0x02, 0x02, 0x00, 0x00, // truncated
+        ];
+        let options: Vec<_> = GeneveOptions::new(&data).collect();
+        assert_eq!(options.len(), 2);
+        assert_eq!(options[0].class, 0x0103);
+        assert_eq!(options[0].option_type, 0x80);
+        assert_eq!(options[0].data, &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(options[1].class, 0xffff);
+        assert!(options[1].is_empty());
+    }
+}
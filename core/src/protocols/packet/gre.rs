@@ -0,0 +1,138 @@
+//! GRE packet.
+
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::{Packet, PacketHeader, PacketParseError};
+use crate::utils::types::*;
+
+use anyhow::{bail, Result};
+
+/// GRE assigned protocol number.
+pub const GRE_PROTOCOL: usize = 47;
+/// Protocol type of Ethernet frames carried over GRE (Transparent Ethernet Bridging).
+pub const GRE_TEB: u16 = 0x6558;
+const GRE_HEADER_LEN: usize = 4;
+const GRE_FIELD_LEN: usize = 4;
+
+const GRE_CHECKSUM: u16 = 0x8000;
+const GRE_ROUTING: u16 = 0x4000;
+const GRE_KEY: u16 = 0x2000;
+const GRE_SEQUENCE: u16 = 0x1000;
+const GRE_VERSION: u16 = 0x0007;
+
+/// A GRE packet (RFC 2784, RFC 2890).
+///
+/// Only version 0 is supported. Packets with the (deprecated) Routing Present bit set fail to
+/// parse. The payload is identified by its EtherType (see [protocol_type](Gre::protocol_type)),
+/// and can be parsed as an IP packet (e.g., `gre.parse_to::<Ipv4>()`).
+#[derive(Debug)]
+pub struct Gre<'a> {
+    /// Fixed header.
+    header: GreHeader,
+    /// Offset to `header` from the start of `mbuf`.
+    offset: usize,
+    /// Packet buffer.
+    mbuf: &'a Mbuf,
+}
+
+impl<'a> Gre<'a> {
+    /// Returns the flags and version field.
+    #[inline]
+    pub fn flags_version(&self) -> u16 {
+        self.header.flags_version.into()
+    }
+
+    /// Returns `true` if the Checksum Present bit is set.
+    #[inline]
+    pub fn checksum_present(&self) -> bool {
+        (self.flags_version() & GRE_CHECKSUM) != 0
+    }
+
+    /// Returns the EtherType of the payload.
+    #[inline]
+    pub fn protocol_type(&self) -> u16 {
+        self.header.protocol_type.into()
+    }
+
+    /// Returns the key, if the Key Present bit is set.
+    pub fn key(&self) -> Option<u32> {
+        if (self.flags_version() & GRE_KEY) == 0 {
+            return None;
+        }
+        let offset =
+            self.offset + GRE_HEADER_LEN + self.checksum_present() as usize * GRE_FIELD_LEN;
+        self.read_field(offset)
+    }
+
+    /// Returns the sequence number, if the Sequence Number Present bit is set.
+    pub fn sequence(&self) -> Option<u32> {
+        if (self.flags_version() & GRE_SEQUENCE) == 0 {
+            return None;
+        }
+        self.read_field(self.next_header_offset() - GRE_FIELD_LEN)
+    }
+
+    fn read_field(&self, offset: usize) -> Option<u32> {
+        let field = self.mbuf.get_data_slice(offset, GRE_FIELD_LEN).ok()?;
+        Some(u32::from_be_bytes(field.try_into().ok()?))
+    }
+}
+
+impl<'a> Packet<'a> for Gre<'a> {
+    fn mbuf(&self) -> &Mbuf {
+        self.mbuf
+    }
+
+    fn header_len(&self) -> usize {
+        self.header.length()
+    }
+
+    fn next_header_offset(&self) -> usize {
+        self.offset + self.header_len()
+    }
+
+    fn next_header(&self) -> Option<usize> {
+        Some(self.protocol_type().into())
+    }
+
+    fn parse_from(outer: &'a impl Packet<'a>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let offset = outer.next_header_offset();
+        if let Ok(header) = outer.mbuf().get_data::<GreHeader>(offset) {
+            let header = unsafe { *header };
+            let flags_version: u16 = header.flags_version.into();
+            match outer.next_header() {
+                Some(GRE_PROTOCOL) if flags_version & (GRE_ROUTING | GRE_VERSION) == 0 => Ok(Gre {
+                    header,
+                    offset,
+                    mbuf: outer.mbuf(),
+                }),
+                _ => bail!(PacketParseError::InvalidProtocol),
+            }
+        } else {
+            bail!(PacketParseError::InvalidRead)
+        }
+    }
+}
+
+/// Fixed portion of a GRE header.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct GreHeader {
+    flags_version: u16be,
+    protocol_type: u16be,
+}
+
+impl PacketHeader for GreHeader {
+    /// Header length measured in bytes, including the optional checksum, key, and sequence number
+    /// fields. Equivalent to the payload offset.
+    fn length(&self) -> usize {
+        let flags: u16 = self.flags_version.into();
+        let nb_fields = [GRE_CHECKSUM, GRE_KEY, GRE_SEQUENCE]
+            .iter()
+            .filter(|&&flag| flags & flag != 0)
+            .count();
+        GRE_HEADER_LEN + nb_fields * GRE_FIELD_LEN
+    }
+}
@@ -0,0 +1,164 @@
+//! GTP-U packet.
+
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::ipv4::IPV4_PROTOCOL;
+use crate::protocols::packet::ipv6::IPV6_PROTOCOL;
+use crate::protocols::packet::{Packet, PacketHeader, PacketParseError};
+use crate::utils::types::*;
+
+use anyhow::{bail, Result};
+
+/// IANA-assigned GTP-U UDP port.
+pub const GTPU_PORT: u16 = 2152;
+/// Message type of G-PDUs, which carry user traffic.
+pub const GTPU_GPDU: u8 = 0xff;
+const GTPU_HEADER_LEN: usize = 8;
+/// Length of the sequence number, N-PDU number, and next extension header type fields.
+const GTPU_OPTIONAL_LEN: usize = 4;
+/// Unit of the extension header length field, in bytes.
+const GTPU_WORD_LEN: usize = 4;
+
+const GTPU_VERSION: u8 = 1;
+const GTPU_PT: u8 = 0x10;
+const GTPU_E: u8 = 0x04;
+const GTPU_S: u8 = 0x02;
+const GTPU_PN: u8 = 0x01;
+
+/// A GTP-U (GTPv1 user plane) packet (3GPP TS 29.281).
+///
+/// The payload of a G-PDU is the IP packet of the subscriber, which can be parsed with
+/// `gtpu.parse_to::<Ipv4>()` or `gtpu.parse_to::<Ipv6>()`. GTP-U is identified by its UDP
+/// destination port, which is left to the caller to check.
+#[derive(Debug)]
+pub struct Gtpu<'a> {
+    /// Fixed header.
+    header: GtpuHeader,
+    /// Length of the header, including the optional fields and extension headers.
+    header_len: usize,
+    /// Offset to `header` from the start of `mbuf`.
+    offset: usize,
+    /// Packet buffer.
+    mbuf: &'a Mbuf,
+}
+
+impl<'a> Gtpu<'a> {
+    /// Returns the flags (version, protocol type, and presence of optional fields).
+    #[inline]
+    pub fn flags(&self) -> u8 {
+        self.header.flags
+    }
+
+    /// Returns the message type.
+    #[inline]
+    pub fn message_type(&self) -> u8 {
+        self.header.message_type
+    }
+
+    /// Returns the length of the payload, including the optional fields, in bytes.
+    #[inline]
+    pub fn length(&self) -> u16 {
+        self.header.length.into()
+    }
+
+    /// Returns the Tunnel Endpoint Identifier.
+    #[inline]
+    pub fn teid(&self) -> u32 {
+        self.header.teid.into()
+    }
+
+    /// Returns the sequence number, if present.
+    pub fn sequence(&self) -> Option<u16> {
+        if self.header.flags & GTPU_S == 0 {
+            return None;
+        }
+        let field = self
+            .mbuf
+            .get_data_slice(self.offset + GTPU_HEADER_LEN, 2)
+            .ok()?;
+        Some(u16::from_be_bytes([field[0], field[1]]))
+    }
+}
+
+impl<'a> Packet<'a> for Gtpu<'a> {
+    fn mbuf(&self) -> &Mbuf {
+        self.mbuf
+    }
+
+    fn header_len(&self) -> usize {
+        self.header_len
+    }
+
+    fn next_header_offset(&self) -> usize {
+        self.offset + self.header_len()
+    }
+
+    fn next_header(&self) -> Option<usize> {
+        if self.message_type() != GTPU_GPDU {
+            return None;
+        }
+        let version = self
+            .mbuf
+            .get_data_slice(self.next_header_offset(), 1)
+            .ok()?[0]
+            >> 4;
+        match version {
+            4 => Some(IPV4_PROTOCOL),
+            6 => Some(IPV6_PROTOCOL),
+            _ => None,
+        }
+    }
+
+    fn parse_from(outer: &'a impl Packet<'a>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let offset = outer.next_header_offset();
+        let header = match outer.mbuf().get_data::<GtpuHeader>(offset) {
+            Ok(header) => unsafe { *header },
+            Err(_) => bail!(PacketParseError::InvalidRead),
+        };
+        if header.flags >> 5 != GTPU_VERSION || header.flags & GTPU_PT == 0 {
+            bail!(PacketParseError::InvalidProtocol);
+        }
+        let mut header_len = header.length();
+        if header.flags & (GTPU_E | GTPU_S | GTPU_PN) != 0 {
+            header_len += GTPU_OPTIONAL_LEN;
+            // Extension headers are chained by the type in their last byte
+            let mut next_type = header.flags & GTPU_E;
+            while next_type != 0 {
+                let ext = outer.mbuf().get_data_slice(offset + header_len - 1, 2)?;
+                next_type = ext[0];
+                if next_type == 0 {
+                    break;
+                }
+                let ext_len = ext[1] as usize * GTPU_WORD_LEN;
+                if ext_len == 0 {
+                    bail!(PacketParseError::InvalidProtocol);
+                }
+                header_len += ext_len;
+            }
+        }
+        Ok(Gtpu {
+            header,
+            header_len,
+            offset,
+            mbuf: outer.mbuf(),
+        })
+    }
+}
+
+/// Fixed portion of a GTP-U header.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct GtpuHeader {
+    flags: u8,
+    message_type: u8,
+    length: u16be,
+    teid: u32be,
+}
+
+impl PacketHeader for GtpuHeader {
+    fn length(&self) -> usize {
+        GTPU_HEADER_LEN
+    }
+}
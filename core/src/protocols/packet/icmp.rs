@@ -0,0 +1,148 @@
+//! ICMP packet.
+
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::ipv4::IPV4_PROTOCOL;
+use crate::protocols::packet::{Packet, PacketHeader, PacketParseError};
+use crate::utils::types::*;
+
+use anyhow::{bail, Result};
+
+/// ICMP assigned protocol number.
+pub const ICMP_PROTOCOL: usize = 1;
+/// ICMP Echo Request message type.
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+/// ICMP Echo Reply message type.
+pub const ICMP_ECHO_REPLY: u8 = 0;
+/// ICMP Destination Unreachable message type.
+pub const ICMP_DEST_UNREACHABLE: u8 = 3;
+/// ICMP Time Exceeded message type.
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+/// ICMP Destination Unreachable code of packets too big to be forwarded without fragmentation.
+pub const ICMP_FRAG_NEEDED: u8 = 4;
+const ICMP_HEADER_LEN: usize = 8;
+
+/// An ICMP packet.
+#[derive(Debug)]
+pub struct Icmp<'a> {
+    /// Fixed header.
+    header: IcmpHeader,
+    /// Offset to `header` from the start of `mbuf`.
+    offset: usize,
+    /// Packet buffer.
+    mbuf: &'a Mbuf,
+}
+
+impl<'a> Icmp<'a> {
+    /// Returns the message type.
+    #[inline]
+    pub fn r#type(&self) -> u8 {
+        self.header.r#type
+    }
+
+    /// Returns the message code.
+    #[inline]
+    pub fn code(&self) -> u8 {
+        self.header.code
+    }
+
+    /// Returns the ICMP checksum.
+    #[inline]
+    pub fn checksum(&self) -> u16 {
+        self.header.checksum.into()
+    }
+
+    /// Returns the 32-bit message-specific field following the checksum.
+    #[inline]
+    pub fn rest_of_header(&self) -> u32 {
+        self.header.rest_of_header.into()
+    }
+
+    /// Returns `1` if the message is an Echo Request or Echo Reply.
+    #[inline]
+    pub fn echo(&self) -> u8 {
+        matches!(self.r#type(), ICMP_ECHO_REQUEST | ICMP_ECHO_REPLY) as u8
+    }
+
+    /// Returns `1` if the message is an error message quoting the packet that caused it.
+    #[inline]
+    pub fn error(&self) -> u8 {
+        matches!(self.r#type(), ICMP_DEST_UNREACHABLE | ICMP_TIME_EXCEEDED) as u8
+    }
+
+    /// Returns the Echo identifier, or `0` if the message is not an Echo Request or Reply.
+    #[inline]
+    pub fn identifier(&self) -> u16 {
+        if self.echo() == 0 {
+            return 0;
+        }
+        (self.rest_of_header() >> 16) as u16
+    }
+
+    /// Returns the Echo sequence number, or `0` if the message is not an Echo Request or Reply.
+    #[inline]
+    pub fn sequence(&self) -> u16 {
+        if self.echo() == 0 {
+            return 0;
+        }
+        self.rest_of_header() as u16
+    }
+}
+
+impl<'a> Packet<'a> for Icmp<'a> {
+    fn mbuf(&self) -> &Mbuf {
+        self.mbuf
+    }
+
+    fn header_len(&self) -> usize {
+        self.header.length()
+    }
+
+    fn next_header_offset(&self) -> usize {
+        self.offset + self.header_len()
+    }
+
+    /// Returns the EtherType of IPv4 for error messages, whose payload is the start of the
+    /// offending packet. It can be parsed with `icmp.parse_to::<Ipv4>()`.
+    fn next_header(&self) -> Option<usize> {
+        match self.error() {
+            0 => None,
+            _ => Some(IPV4_PROTOCOL),
+        }
+    }
+
+    fn parse_from(outer: &'a impl Packet<'a>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let offset = outer.next_header_offset();
+        if let Ok(header) = outer.mbuf().get_data(offset) {
+            match outer.next_header() {
+                Some(ICMP_PROTOCOL) => Ok(Icmp {
+                    header: unsafe { *header },
+                    offset,
+                    mbuf: outer.mbuf(),
+                }),
+                _ => bail!(PacketParseError::InvalidProtocol),
+            }
+        } else {
+            bail!(PacketParseError::InvalidRead)
+        }
+    }
+}
+
+/// ICMP header.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct IcmpHeader {
+    r#type: u8,
+    code: u8,
+    checksum: u16be,
+    rest_of_header: u32be,
+}
+
+impl PacketHeader for IcmpHeader {
+    /// Header length measured in bytes. Equivalent to the payload offset.
+    fn length(&self) -> usize {
+        ICMP_HEADER_LEN
+    }
+}
@@ -0,0 +1,151 @@
+//! ICMPv6 packet.
+
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::ipv6::IPV6_PROTOCOL;
+use crate::protocols::packet::{Packet, PacketHeader, PacketParseError};
+use crate::utils::types::*;
+
+use anyhow::{bail, Result};
+
+/// ICMPv6 assigned protocol number.
+pub const ICMPV6_PROTOCOL: usize = 58;
+/// ICMPv6 Echo Request message type.
+pub const ICMPV6_ECHO_REQUEST: u8 = 128;
+/// ICMPv6 Echo Reply message type.
+pub const ICMPV6_ECHO_REPLY: u8 = 129;
+/// ICMPv6 Destination Unreachable message type.
+pub const ICMPV6_DEST_UNREACHABLE: u8 = 1;
+/// ICMPv6 Packet Too Big message type.
+pub const ICMPV6_PACKET_TOO_BIG: u8 = 2;
+/// ICMPv6 Time Exceeded message type.
+pub const ICMPV6_TIME_EXCEEDED: u8 = 3;
+const ICMPV6_HEADER_LEN: usize = 8;
+
+/// An ICMPv6 packet.
+#[derive(Debug)]
+pub struct Icmpv6<'a> {
+    /// Fixed header.
+    header: Icmpv6Header,
+    /// Offset to `header` from the start of `mbuf`.
+    offset: usize,
+    /// Packet buffer.
+    mbuf: &'a Mbuf,
+}
+
+impl<'a> Icmpv6<'a> {
+    /// Returns the message type.
+    #[inline]
+    pub fn r#type(&self) -> u8 {
+        self.header.r#type
+    }
+
+    /// Returns the message code.
+    #[inline]
+    pub fn code(&self) -> u8 {
+        self.header.code
+    }
+
+    /// Returns the ICMPv6 checksum.
+    #[inline]
+    pub fn checksum(&self) -> u16 {
+        self.header.checksum.into()
+    }
+
+    /// Returns the 32-bit message-specific field following the checksum.
+    #[inline]
+    pub fn rest_of_header(&self) -> u32 {
+        self.header.rest_of_header.into()
+    }
+
+    /// Returns `1` if the message is an Echo Request or Echo Reply.
+    #[inline]
+    pub fn echo(&self) -> u8 {
+        matches!(self.r#type(), ICMPV6_ECHO_REQUEST | ICMPV6_ECHO_REPLY) as u8
+    }
+
+    /// Returns `1` if the message is an error message quoting the packet that caused it.
+    #[inline]
+    pub fn error(&self) -> u8 {
+        matches!(
+            self.r#type(),
+            ICMPV6_DEST_UNREACHABLE | ICMPV6_PACKET_TOO_BIG | ICMPV6_TIME_EXCEEDED
+        ) as u8
+    }
+
+    /// Returns the Echo identifier, or `0` if the message is not an Echo Request or Reply.
+    #[inline]
+    pub fn identifier(&self) -> u16 {
+        if self.echo() == 0 {
+            return 0;
+        }
+        (self.rest_of_header() >> 16) as u16
+    }
+
+    /// Returns the Echo sequence number, or `0` if the message is not an Echo Request or Reply.
+    #[inline]
+    pub fn sequence(&self) -> u16 {
+        if self.echo() == 0 {
+            return 0;
+        }
+        self.rest_of_header() as u16
+    }
+}
+
+impl<'a> Packet<'a> for Icmpv6<'a> {
+    fn mbuf(&self) -> &Mbuf {
+        self.mbuf
+    }
+
+    fn header_len(&self) -> usize {
+        self.header.length()
+    }
+
+    fn next_header_offset(&self) -> usize {
+        self.offset + self.header_len()
+    }
+
+    /// Returns the EtherType of IPv6 for error messages, whose payload is the start of the
+    /// offending packet. It can be parsed with `icmp.parse_to::<Ipv6>()`.
+    fn next_header(&self) -> Option<usize> {
+        match self.error() {
+            0 => None,
+            _ => Some(IPV6_PROTOCOL),
+        }
+    }
+
+    fn parse_from(outer: &'a impl Packet<'a>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let offset = outer.next_header_offset();
+        if let Ok(header) = outer.mbuf().get_data(offset) {
+            match outer.next_header() {
+                Some(ICMPV6_PROTOCOL) => Ok(Icmpv6 {
+                    header: unsafe { *header },
+                    offset,
+                    mbuf: outer.mbuf(),
+                }),
+                _ => bail!(PacketParseError::InvalidProtocol),
+            }
+        } else {
+            bail!(PacketParseError::InvalidRead)
+        }
+    }
+}
+
+/// ICMPv6 header.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct Icmpv6Header {
+    r#type: u8,
+    code: u8,
+    checksum: u16be,
+    rest_of_header: u32be,
+}
+
+impl PacketHeader for Icmpv6Header {
+    /// Header length measured in bytes. Equivalent to the payload offset.
+    fn length(&self) -> usize {
+        ICMPV6_HEADER_LEN
+    }
+}
@@ -9,7 +9,7 @@ use std::net::Ipv4Addr;
 use anyhow::{bail, Result};
 
 /// IPv4 EtherType
-const IPV4_PROTOCOL: usize = 0x0800;
+pub(crate) const IPV4_PROTOCOL: usize = 0x0800;
 /// Flag: "Reserved bit"
 const IPV4_RF: u16 = 0x8000;
 /// Flag: "Don't fragment"
@@ -129,6 +129,12 @@ impl<'a> Ipv4<'a> {
         self.header.time_to_live
     }
 
+    /// Returns the time to live (TTL) of the packet. Alias for `time_to_live`.
+    #[inline]
+    pub fn ttl(&self) -> u8 {
+        self.time_to_live()
+    }
+
     /// Returns the encapsulated protocol identifier.
     #[inline]
     pub fn protocol(&self) -> u8 {
@@ -8,16 +8,47 @@ use std::net::Ipv6Addr;
 
 use anyhow::{bail, Result};
 
-const IPV6_PROTOCOL: usize = 0x86DD;
+pub(crate) const IPV6_PROTOCOL: usize = 0x86DD;
 const IPV6_HEADER_LEN: usize = 40;
 
+// Extension header protocol numbers
+pub(crate) const IPV6_EXT_HOP_BY_HOP: u8 = 0;
+pub(crate) const IPV6_EXT_ROUTING: u8 = 43;
+pub(crate) const IPV6_EXT_FRAGMENT: u8 = 44;
+pub(crate) const IPV6_EXT_AUTH: u8 = 51;
+pub(crate) const IPV6_EXT_DST_OPTS: u8 = 60;
+
+// Bits in the extension header bitmap
+const EXT_HOP_BY_HOP: u8 = 0x01;
+const EXT_ROUTING: u8 = 0x02;
+const EXT_FRAGMENT: u8 = 0x04;
+const EXT_AUTH: u8 = 0x08;
+const EXT_DST_OPTS: u8 = 0x10;
+
+/// Maximum number of extension headers walked before giving up on the packet.
+const IPV6_MAX_EXT_HEADERS: usize = 8;
+
+const IPV6_FRAG_OFFSET: u16 = 0xfff8;
+const IPV6_FRAG_MF: u16 = 0x0001;
+
 /// An IPv6 packet.
 ///
-/// Optional IPv6 extension headers are not parsed by default.
+/// The chain of extension headers (Hop-by-Hop Options, Routing, Fragment, Destination Options,
+/// and Authentication) is walked on parse, so that `next_header_offset` points to the upper-layer
+/// header. Packets with malformed, truncated, or excessively long extension header chains are
+/// rejected.
 #[derive(Debug)]
 pub struct Ipv6<'a> {
     /// Fixed header.
     header: Ipv6Header,
+    /// Total length of the extension headers in bytes.
+    ext_len: usize,
+    /// Bitmap of extension headers present in the packet.
+    exts: u8,
+    /// Protocol identifier following the last extension header.
+    upper_protocol: u8,
+    /// Fragment extension header, if present.
+    fragment: Option<Ipv6FragmentHeader>,
     /// Offset to `header` from the start of `mbuf`.
     offset: usize,
     /// Packet buffer.
@@ -77,6 +108,86 @@ impl<'a> Ipv6<'a> {
         self.header.next_header
     }
 
+    /// Returns the upper-layer protocol identifier, following any extension headers.
+    #[inline]
+    pub fn upper_protocol(&self) -> u8 {
+        self.upper_protocol
+    }
+
+    /// Returns the total length of the extension headers in bytes.
+    #[inline]
+    pub fn ext_length(&self) -> usize {
+        self.ext_len
+    }
+
+    /// Returns `1` if the packet contains a Hop-by-Hop Options header.
+    #[inline]
+    pub fn has_ext_hop_by_hop(&self) -> u8 {
+        (self.exts & EXT_HOP_BY_HOP != 0) as u8
+    }
+
+    /// Returns `1` if the packet contains a Routing header.
+    #[inline]
+    pub fn has_ext_routing(&self) -> u8 {
+        (self.exts & EXT_ROUTING != 0) as u8
+    }
+
+    /// Returns `1` if the packet contains a Fragment header.
+    #[inline]
+    pub fn has_ext_fragment(&self) -> u8 {
+        (self.exts & EXT_FRAGMENT != 0) as u8
+    }
+
+    /// Returns `1` if the packet contains an Authentication header.
+    #[inline]
+    pub fn has_ext_auth(&self) -> u8 {
+        (self.exts & EXT_AUTH != 0) as u8
+    }
+
+    /// Returns `1` if the packet contains a Destination Options header.
+    #[inline]
+    pub fn has_ext_dst_opts(&self) -> u8 {
+        (self.exts & EXT_DST_OPTS != 0) as u8
+    }
+
+    /// Returns `1` if the packet contains the extension header `name` (one of `hop_by_hop`,
+    /// `routing`, `fragment`, `auth`, or `dst_opts`, case-insensitive).
+    pub fn has_ext(&self, name: &str) -> u8 {
+        match name.to_lowercase().replace('-', "_").as_str() {
+            "hop_by_hop" => self.has_ext_hop_by_hop(),
+            "routing" => self.has_ext_routing(),
+            "fragment" => self.has_ext_fragment(),
+            "auth" => self.has_ext_auth(),
+            "dst_opts" => self.has_ext_dst_opts(),
+            _ => 0,
+        }
+    }
+
+    /// Returns `1` if the packet is a fragment. Alias for `has_ext_fragment`.
+    #[inline]
+    pub fn frag(&self) -> u8 {
+        self.has_ext_fragment()
+    }
+
+    /// Returns the fragment offset in units of 8 bytes, or `0` if the packet is not a fragment.
+    #[inline]
+    pub fn fragment_offset(&self) -> u16 {
+        self.fragment.map_or(0, |f| u16::from(f.offset_flags) >> 3)
+    }
+
+    /// Returns `true` if the More Fragments flag is set.
+    #[inline]
+    pub fn mf(&self) -> bool {
+        self.fragment
+            .map_or(false, |f| u16::from(f.offset_flags) & IPV6_FRAG_MF != 0)
+    }
+
+    /// Returns the fragment identification, or `0` if the packet is not a fragment.
+    #[inline]
+    pub fn fragment_id(&self) -> u32 {
+        self.fragment.map_or(0, |f| f.identification.into())
+    }
+
     /// Returns hop limit/time to live of the packet.
     #[inline]
     pub fn hop_limit(&self) -> u8 {
@@ -96,13 +207,74 @@ impl<'a> Ipv6<'a> {
     }
 }
 
+impl<'a> Ipv6<'a> {
+    /// Walks the extension header chain, recording the headers present and the upper-layer
+    /// protocol.
+    fn parse_ext_headers(&mut self) -> Result<()> {
+        let payload_len = usize::from(self.payload_length());
+        let mut next_header = self.header.next_header;
+        let mut ext_offset = self.offset + IPV6_HEADER_LEN;
+        for _ in 0..IPV6_MAX_EXT_HEADERS {
+            let bit = match next_header {
+                IPV6_EXT_HOP_BY_HOP => EXT_HOP_BY_HOP,
+                IPV6_EXT_ROUTING => EXT_ROUTING,
+                IPV6_EXT_FRAGMENT => EXT_FRAGMENT,
+                IPV6_EXT_AUTH => EXT_AUTH,
+                IPV6_EXT_DST_OPTS => EXT_DST_OPTS,
+                _ => {
+                    self.upper_protocol = next_header;
+                    return Ok(());
+                }
+            };
+            // Hop-by-Hop Options must immediately follow the fixed header (RFC 8200)
+            if bit == EXT_HOP_BY_HOP && self.exts != 0 {
+                bail!(PacketParseError::InvalidProtocol);
+            }
+            let ext: Ipv6ExtHeader = match self.mbuf.get_data(ext_offset) {
+                Ok(ext) => unsafe { *ext },
+                Err(_) => bail!(PacketParseError::InvalidRead),
+            };
+            let len = match next_header {
+                IPV6_EXT_FRAGMENT => {
+                    let fragment: Ipv6FragmentHeader = match self.mbuf.get_data(ext_offset) {
+                        Ok(fragment) => unsafe { *fragment },
+                        Err(_) => bail!(PacketParseError::InvalidRead),
+                    };
+                    self.fragment = Some(fragment);
+                    // Headers following the Fragment header are only in the first fragment
+                    if u16::from(fragment.offset_flags) & IPV6_FRAG_OFFSET != 0 {
+                        self.ext_len += Ipv6FragmentHeader::size_of();
+                        if self.ext_len > payload_len {
+                            bail!(PacketParseError::InvalidRead);
+                        }
+                        self.exts |= bit;
+                        self.upper_protocol = fragment.next_header;
+                        return Ok(());
+                    }
+                    Ipv6FragmentHeader::size_of()
+                }
+                IPV6_EXT_AUTH => (usize::from(ext.length) + 2) * 4,
+                _ => (usize::from(ext.length) + 1) * 8,
+            };
+            self.ext_len += len;
+            if self.ext_len > payload_len {
+                bail!(PacketParseError::InvalidRead);
+            }
+            self.exts |= bit;
+            ext_offset += len;
+            next_header = ext.next_header;
+        }
+        bail!(PacketParseError::InvalidProtocol)
+    }
+}
+
 impl<'a> Packet<'a> for Ipv6<'a> {
     fn mbuf(&self) -> &Mbuf {
         self.mbuf
     }
 
     fn header_len(&self) -> usize {
-        self.header.length()
+        self.header.length() + self.ext_len
     }
 
     fn next_header_offset(&self) -> usize {
@@ -110,7 +282,13 @@ impl<'a> Packet<'a> for Ipv6<'a> {
     }
 
     fn next_header(&self) -> Option<usize> {
-        Some(self.next_header().into())
+        // Non-initial fragments do not carry the upper-layer header
+        if let Some(fragment) = self.fragment {
+            if u16::from(fragment.offset_flags) & IPV6_FRAG_OFFSET != 0 {
+                return Some(IPV6_EXT_FRAGMENT.into());
+            }
+        }
+        Some(self.upper_protocol.into())
     }
 
     fn parse_from(outer: &'a impl Packet<'a>) -> Result<Self>
@@ -120,11 +298,20 @@ impl<'a> Packet<'a> for Ipv6<'a> {
         let offset = outer.next_header_offset();
         if let Ok(header) = outer.mbuf().get_data(offset) {
             match outer.next_header() {
-                Some(IPV6_PROTOCOL) => Ok(Ipv6 {
-                    header: unsafe { *header },
-                    offset,
-                    mbuf: outer.mbuf(),
-                }),
+                Some(IPV6_PROTOCOL) => {
+                    let header: Ipv6Header = unsafe { *header };
+                    let mut ipv6 = Ipv6 {
+                        header,
+                        ext_len: 0,
+                        exts: 0,
+                        upper_protocol: header.next_header,
+                        fragment: None,
+                        offset,
+                        mbuf: outer.mbuf(),
+                    };
+                    ipv6.parse_ext_headers()?;
+                    Ok(ipv6)
+                }
                 _ => bail!(PacketParseError::InvalidProtocol),
             }
         } else {
@@ -133,7 +320,7 @@ impl<'a> Packet<'a> for Ipv6<'a> {
     }
 }
 
-// Fixed portion of Ipv6 header TODO: handle extension headers
+// Fixed portion of Ipv6 header
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 struct Ipv6Header {
@@ -151,3 +338,33 @@ impl PacketHeader for Ipv6Header {
         IPV6_HEADER_LEN
     }
 }
+
+// Common prefix of the extension headers
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct Ipv6ExtHeader {
+    next_header: u8,
+    length: u8,
+}
+
+impl PacketHeader for Ipv6ExtHeader {
+    fn length(&self) -> usize {
+        (usize::from(self.length) + 1) * 8
+    }
+}
+
+// Fragment extension header
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct Ipv6FragmentHeader {
+    next_header: u8,
+    reserved: u8,
+    offset_flags: u16be,
+    identification: u32be,
+}
+
+impl PacketHeader for Ipv6FragmentHeader {
+    fn length(&self) -> usize {
+        8
+    }
+}
@@ -5,11 +5,22 @@
 //! [pnet::packet](https://docs.rs/pnet/latest/pnet/packet/index.html). Every packet type represents
 //! a single frame on the wire.
 
+pub mod erspan;
 pub mod ethernet;
+pub mod geneve;
+pub mod gre;
+pub mod gtpu;
+pub mod icmp;
+pub mod icmpv6;
 pub mod ipv4;
 pub mod ipv6;
+pub mod mpls;
+pub mod pppoe;
+pub mod sctp;
 pub mod tcp;
+pub mod teredo;
 pub mod udp;
+pub mod vxlan;
 use crate::memory::mbuf::Mbuf;
 
 use anyhow::Result;
@@ -0,0 +1,208 @@
+//! MPLS packet.
+
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::gre::GRE_TEB;
+use crate::protocols::packet::ipv4::IPV4_PROTOCOL;
+use crate::protocols::packet::ipv6::IPV6_PROTOCOL;
+use crate::protocols::packet::{Packet, PacketParseError};
+
+use anyhow::{bail, Result};
+
+/// EtherType of MPLS unicast packets.
+pub const MPLS_UNICAST: u16 = 0x8847;
+/// EtherType of MPLS multicast packets.
+pub const MPLS_MULTICAST: u16 = 0x8848;
+const MPLS_LABEL_LEN: usize = 4;
+const MPLS_CONTROL_WORD_LEN: usize = 4;
+
+/// Reserved label of IPv4 payloads (IPv4 Explicit NULL).
+const MPLS_IPV4_NULL: u32 = 0;
+/// Reserved label of IPv6 payloads (IPv6 Explicit NULL).
+const MPLS_IPV6_NULL: u32 = 2;
+
+/// An MPLS label stack (RFC 3032).
+///
+/// MPLS does not identify its payload. It is inferred from the bottom label if it is an Explicit
+/// NULL label, and otherwise from the first nibble of the payload: `4` for IPv4, `6` for IPv6, and
+/// `0` for a pseudowire control word (RFC 4385) followed by an Ethernet frame (RFC 4448). Ethernet
+/// pseudowires without a control word are not recognized. The payload can then be parsed with
+/// `mpls.parse_to::<Ipv4>()`, `mpls.parse_to::<Ipv6>()`, or `mpls.parse_to::<Ethernet>()` (see
+/// [next_header](Mpls::next_header)).
+#[derive(Debug)]
+pub struct Mpls<'a> {
+    /// Offset to the top of the label stack from the start of `mbuf`.
+    offset: usize,
+    /// Number of labels in the stack.
+    depth: usize,
+    /// Whether the label stack is followed by a pseudowire control word.
+    control_word: bool,
+    /// Packet buffer.
+    mbuf: &'a Mbuf,
+}
+
+impl<'a> Mpls<'a> {
+    /// Returns the number of labels in the stack.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the label stack entries, from the top to the bottom of the stack.
+    pub fn labels(&self) -> LabelStack<'a> {
+        let data = self
+            .mbuf
+            .get_data_slice(self.offset, self.depth * MPLS_LABEL_LEN)
+            .unwrap_or_default();
+        LabelStack { data }
+    }
+
+    /// Returns the top label, which is the one used for forwarding.
+    pub fn label(&self) -> u32 {
+        self.labels().next().map_or(0, |entry| entry.label)
+    }
+
+    /// Returns the bottom label, which usually identifies the service (e.g., the VPN).
+    pub fn bottom_label(&self) -> u32 {
+        self.labels().last().map_or(0, |entry| entry.label)
+    }
+
+    /// Returns the Time To Live of the top label stack entry.
+    pub fn ttl(&self) -> u8 {
+        self.labels().next().map_or(0, |entry| entry.ttl)
+    }
+
+    /// Returns `true` if the label stack is followed by a pseudowire control word.
+    #[inline]
+    pub fn control_word(&self) -> bool {
+        self.control_word
+    }
+}
+
+impl<'a> Packet<'a> for Mpls<'a> {
+    fn mbuf(&self) -> &Mbuf {
+        self.mbuf
+    }
+
+    fn header_len(&self) -> usize {
+        self.depth * MPLS_LABEL_LEN + self.control_word as usize * MPLS_CONTROL_WORD_LEN
+    }
+
+    fn next_header_offset(&self) -> usize {
+        self.offset + self.header_len()
+    }
+
+    /// Returns the EtherType of the payload, inferred as described in [Mpls](Mpls). Ethernet
+    /// frames are identified as Transparent Ethernet Bridging (`0x6558`).
+    fn next_header(&self) -> Option<usize> {
+        if self.control_word {
+            return Some(GRE_TEB.into());
+        }
+        match self.bottom_label() {
+            MPLS_IPV4_NULL => return Some(IPV4_PROTOCOL),
+            MPLS_IPV6_NULL => return Some(IPV6_PROTOCOL),
+            _ => (),
+        }
+        let version = self
+            .mbuf
+            .get_data_slice(self.next_header_offset(), 1)
+            .ok()?[0]
+            >> 4;
+        match version {
+            4 => Some(IPV4_PROTOCOL),
+            6 => Some(IPV6_PROTOCOL),
+            _ => None,
+        }
+    }
+
+    fn parse_from(outer: &'a impl Packet<'a>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        match outer.next_header().map(|ether_type| ether_type as u16) {
+            Some(MPLS_UNICAST) | Some(MPLS_MULTICAST) => (),
+            _ => bail!(PacketParseError::InvalidProtocol),
+        }
+        let offset = outer.next_header_offset();
+        let mbuf = outer.mbuf();
+        // The stack ends with the Bottom of Stack bit, at any depth
+        let mut depth = 0;
+        loop {
+            let entry = mbuf.get_data_slice(offset + depth * MPLS_LABEL_LEN, MPLS_LABEL_LEN)?;
+            depth += 1;
+            if entry[2] & 0x01 != 0 {
+                break;
+            }
+        }
+        let payload = mbuf.get_data_slice(offset + depth * MPLS_LABEL_LEN, 1)?;
+        Ok(Mpls {
+            offset,
+            depth,
+            control_word: payload[0] >> 4 == 0,
+            mbuf,
+        })
+    }
+}
+
+/// An MPLS label stack entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LabelEntry {
+    /// 20-bit label value.
+    pub label: u32,
+    /// Traffic Class.
+    pub tc: u8,
+    /// Whether the entry is at the bottom of the stack.
+    pub bottom: bool,
+    /// Time To Live.
+    pub ttl: u8,
+}
+
+/// Iterator over the entries of an MPLS label stack, from the top to the bottom of the stack.
+#[derive(Debug, Clone)]
+pub struct LabelStack<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> LabelStack<'a> {
+    /// Returns an iterator over the label stack entries encoded in `data`.
+    pub fn new(data: &'a [u8]) -> Self {
+        LabelStack { data }
+    }
+}
+
+impl<'a> Iterator for LabelStack<'a> {
+    type Item = LabelEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.data.get(..MPLS_LABEL_LEN)?;
+        let entry = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+        self.data = &self.data[MPLS_LABEL_LEN..];
+        Some(LabelEntry {
+            label: entry >> 12,
+            tc: ((entry >> 9) & 0x07) as u8,
+            bottom: entry & 0x100 != 0,
+            ttl: entry as u8,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_mpls_labels() {
+        let data = [
+            0x00, 0x06, 0x40, 0x3f, // label 100, TC 0, TTL 63
+            0x03, 0xe8, 0x0b, 0x40, // label 16000, TC 5, bottom, TTL 64
+        ];
+        let entries: Vec<_> = LabelStack::new(&data).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].label, 100);
+        assert_eq!(entries[0].ttl, 63);
+        assert!(!entries[0].bottom);
+        assert_eq!(entries[1].label, 16000);
+        assert_eq!(entries[1].tc, 5);
+        assert!(entries[1].bottom);
+        assert_eq!(entries[1].ttl, 64);
+    }
+}
@@ -0,0 +1,119 @@
+//! PPPoE packet.
+
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::ipv4::IPV4_PROTOCOL;
+use crate::protocols::packet::ipv6::IPV6_PROTOCOL;
+use crate::protocols::packet::{Packet, PacketHeader, PacketParseError};
+use crate::utils::types::*;
+
+use anyhow::{bail, Result};
+
+/// EtherType of PPPoE session packets.
+pub const PPPOE_SESSION: u16 = 0x8864;
+/// PPP protocol number of IPv4.
+pub const PPP_IPV4: u16 = 0x0021;
+/// PPP protocol number of IPv6.
+pub const PPP_IPV6: u16 = 0x0057;
+/// Length of the PPPoE header and the PPP protocol field.
+const PPPOE_HEADER_LEN: usize = 8;
+
+const PPPOE_VERSION_TYPE: u8 = 0x11;
+const PPPOE_CODE_SESSION: u8 = 0x00;
+
+/// A PPPoE session packet (RFC 2516), with the protocol field of the PPP frame it carries.
+///
+/// Only session packets are parsed, discovery packets (EtherType `0x8863`) are not. The payload of
+/// IPv4 and IPv6 PPP frames can be parsed with `pppoe.parse_to::<Ipv4>()` or
+/// `pppoe.parse_to::<Ipv6>()`.
+#[derive(Debug)]
+pub struct Pppoe<'a> {
+    /// Fixed header.
+    header: PppoeHeader,
+    /// Offset to `header` from the start of `mbuf`.
+    offset: usize,
+    /// Packet buffer.
+    mbuf: &'a Mbuf,
+}
+
+impl<'a> Pppoe<'a> {
+    /// Returns the PPPoE session ID.
+    #[inline]
+    pub fn session_id(&self) -> u16 {
+        self.header.session_id.into()
+    }
+
+    /// Returns the length of the PPP frame, including its protocol field.
+    #[inline]
+    pub fn length(&self) -> u16 {
+        self.header.length.into()
+    }
+
+    /// Returns the PPP protocol number of the payload.
+    #[inline]
+    pub fn protocol(&self) -> u16 {
+        self.header.protocol.into()
+    }
+}
+
+impl<'a> Packet<'a> for Pppoe<'a> {
+    fn mbuf(&self) -> &Mbuf {
+        self.mbuf
+    }
+
+    fn header_len(&self) -> usize {
+        self.header.length()
+    }
+
+    fn next_header_offset(&self) -> usize {
+        self.offset + self.header_len()
+    }
+
+    /// Returns the EtherType of the payload, or `None` if it is neither IPv4 nor IPv6 (e.g., PPP
+    /// control protocols).
+    fn next_header(&self) -> Option<usize> {
+        match self.protocol() {
+            PPP_IPV4 => Some(IPV4_PROTOCOL),
+            PPP_IPV6 => Some(IPV6_PROTOCOL),
+            _ => None,
+        }
+    }
+
+    fn parse_from(outer: &'a impl Packet<'a>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        if outer.next_header() != Some(PPPOE_SESSION.into()) {
+            bail!(PacketParseError::InvalidProtocol);
+        }
+        let offset = outer.next_header_offset();
+        let header = match outer.mbuf().get_data::<PppoeHeader>(offset) {
+            Ok(header) => unsafe { *header },
+            Err(_) => bail!(PacketParseError::InvalidRead),
+        };
+        if header.version_type != PPPOE_VERSION_TYPE || header.code != PPPOE_CODE_SESSION {
+            bail!(PacketParseError::InvalidProtocol);
+        }
+        Ok(Pppoe {
+            header,
+            offset,
+            mbuf: outer.mbuf(),
+        })
+    }
+}
+
+/// PPPoE header, followed by the protocol field of the PPP frame.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct PppoeHeader {
+    version_type: u8,
+    code: u8,
+    session_id: u16be,
+    length: u16be,
+    protocol: u16be,
+}
+
+impl PacketHeader for PppoeHeader {
+    fn length(&self) -> usize {
+        PPPOE_HEADER_LEN
+    }
+}
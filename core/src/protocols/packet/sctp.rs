@@ -0,0 +1,312 @@
+//! SCTP packet.
+
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::{Packet, PacketHeader, PacketParseError};
+use crate::utils::types::*;
+
+use anyhow::{bail, Result};
+
+/// SCTP assigned protocol number.
+pub const SCTP_PROTOCOL: usize = 132;
+const SCTP_HEADER_LEN: usize = 12;
+const SCTP_CHUNK_HEADER_LEN: usize = 4;
+
+// SCTP chunk types.
+pub const SCTP_DATA: u8 = 0;
+pub const SCTP_INIT: u8 = 1;
+pub const SCTP_INIT_ACK: u8 = 2;
+pub const SCTP_SACK: u8 = 3;
+pub const SCTP_HEARTBEAT: u8 = 4;
+pub const SCTP_HEARTBEAT_ACK: u8 = 5;
+pub const SCTP_ABORT: u8 = 6;
+pub const SCTP_SHUTDOWN: u8 = 7;
+pub const SCTP_SHUTDOWN_ACK: u8 = 8;
+pub const SCTP_ERROR: u8 = 9;
+pub const SCTP_COOKIE_ECHO: u8 = 10;
+pub const SCTP_COOKIE_ACK: u8 = 11;
+pub const SCTP_SHUTDOWN_COMPLETE: u8 = 14;
+
+/// An SCTP packet.
+///
+/// Chunks are not parsed by default, but can be read on demand (see [chunks](Sctp::chunks)).
+#[derive(Debug)]
+pub struct Sctp<'a> {
+    /// Fixed header.
+    header: SctpHeader,
+    /// Offset to `header` from the start of `mbuf`.
+    offset: usize,
+    /// Packet buffer.
+    mbuf: &'a Mbuf,
+}
+
+impl<'a> Sctp<'a> {
+    /// Returns the sending port.
+    #[inline]
+    pub fn src_port(&self) -> u16 {
+        self.header.src_port.into()
+    }
+
+    /// Returns the receiving port.
+    #[inline]
+    pub fn dst_port(&self) -> u16 {
+        self.header.dst_port.into()
+    }
+
+    /// Returns the verification tag of the association.
+    #[inline]
+    pub fn verification_tag(&self) -> u32 {
+        self.header.verification_tag.into()
+    }
+
+    /// Returns the CRC32c checksum.
+    #[inline]
+    pub fn checksum(&self) -> u32 {
+        self.header.checksum.into()
+    }
+
+    /// Returns an iterator over the chunks of the packet. Iteration stops at the end of the
+    /// buffer, or at the first malformed chunk.
+    pub fn chunks(&self) -> SctpChunks<'a> {
+        let start = self.next_header_offset();
+        let data = self
+            .mbuf
+            .get_data_slice(start, self.mbuf.data_len().saturating_sub(start))
+            .unwrap_or_default();
+        SctpChunks { data }
+    }
+
+    /// Returns the number of chunks in the packet.
+    pub fn nb_chunks(&self) -> usize {
+        self.chunks().count()
+    }
+
+    /// Returns `1` if the packet has a chunk of type `chunk_type`.
+    #[inline]
+    pub fn has_chunk(&self, chunk_type: u8) -> u8 {
+        self.chunks().any(|c| c.chunk_type() == chunk_type) as u8
+    }
+
+    /// Returns `1` if the packet has a DATA chunk.
+    #[inline]
+    pub fn data(&self) -> u8 {
+        self.has_chunk(SCTP_DATA)
+    }
+
+    /// Returns `1` if the packet has an INIT chunk (association setup).
+    #[inline]
+    pub fn init(&self) -> u8 {
+        self.has_chunk(SCTP_INIT)
+    }
+
+    /// Returns `1` if the packet has an INIT ACK chunk.
+    #[inline]
+    pub fn init_ack(&self) -> u8 {
+        self.has_chunk(SCTP_INIT_ACK)
+    }
+
+    /// Returns `1` if the packet has an ABORT chunk (association reset).
+    #[inline]
+    pub fn abort(&self) -> u8 {
+        self.has_chunk(SCTP_ABORT)
+    }
+
+    /// Returns `1` if the packet has a SHUTDOWN chunk (start of the association teardown).
+    #[inline]
+    pub fn shutdown(&self) -> u8 {
+        self.has_chunk(SCTP_SHUTDOWN)
+    }
+
+    /// Returns `1` if the packet has a SHUTDOWN COMPLETE chunk (end of the association
+    /// teardown).
+    #[inline]
+    pub fn shutdown_complete(&self) -> u8 {
+        self.has_chunk(SCTP_SHUTDOWN_COMPLETE)
+    }
+
+    /// Returns the Payload Protocol Identifier of the first DATA chunk (e.g., `46` for Diameter),
+    /// or `0` if the packet has none.
+    #[inline]
+    pub fn ppid(&self) -> u32 {
+        self.chunks().find_map(|c| c.ppid()).unwrap_or(0)
+    }
+
+    /// Returns the stream identifier of the first DATA chunk, or `0` if the packet has none.
+    #[inline]
+    pub fn stream_id(&self) -> u16 {
+        self.chunks().find_map(|c| c.stream_id()).unwrap_or(0)
+    }
+}
+
+impl<'a> Packet<'a> for Sctp<'a> {
+    fn mbuf(&self) -> &Mbuf {
+        self.mbuf
+    }
+
+    fn header_len(&self) -> usize {
+        self.header.length()
+    }
+
+    fn next_header_offset(&self) -> usize {
+        self.offset + self.header_len()
+    }
+
+    fn next_header(&self) -> Option<usize> {
+        None
+    }
+
+    fn parse_from(outer: &'a impl Packet<'a>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let offset = outer.next_header_offset();
+        if let Ok(header) = outer.mbuf().get_data(offset) {
+            match outer.next_header() {
+                Some(SCTP_PROTOCOL) => Ok(Sctp {
+                    header: unsafe { *header },
+                    offset,
+                    mbuf: outer.mbuf(),
+                }),
+                _ => bail!(PacketParseError::InvalidProtocol),
+            }
+        } else {
+            bail!(PacketParseError::InvalidRead)
+        }
+    }
+}
+
+/// SCTP common header.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct SctpHeader {
+    src_port: u16be,
+    dst_port: u16be,
+    verification_tag: u32be,
+    checksum: u32be,
+}
+
+impl PacketHeader for SctpHeader {
+    /// Header length measured in bytes. Equivalent to the offset of the first chunk.
+    fn length(&self) -> usize {
+        SCTP_HEADER_LEN
+    }
+}
+
+/// An SCTP chunk.
+#[derive(Debug, Clone, Copy)]
+pub struct SctpChunk<'a> {
+    chunk_type: u8,
+    flags: u8,
+    /// Chunk value, without padding.
+    value: &'a [u8],
+}
+
+impl<'a> SctpChunk<'a> {
+    /// Returns the chunk type.
+    #[inline]
+    pub fn chunk_type(&self) -> u8 {
+        self.chunk_type
+    }
+
+    /// Returns the chunk flags.
+    #[inline]
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Returns the chunk value (following the chunk header).
+    #[inline]
+    pub fn value(&self) -> &'a [u8] {
+        self.value
+    }
+
+    /// Returns the Transmission Sequence Number of a DATA chunk.
+    pub fn tsn(&self) -> Option<u32> {
+        self.data_field(0..4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+    }
+
+    /// Returns the stream identifier of a DATA chunk.
+    pub fn stream_id(&self) -> Option<u16> {
+        self.data_field(4..6)
+            .map(|b| u16::from_be_bytes(b.try_into().unwrap()))
+    }
+
+    /// Returns the stream sequence number of a DATA chunk.
+    pub fn stream_seq(&self) -> Option<u16> {
+        self.data_field(6..8)
+            .map(|b| u16::from_be_bytes(b.try_into().unwrap()))
+    }
+
+    /// Returns the Payload Protocol Identifier of a DATA chunk.
+    pub fn ppid(&self) -> Option<u32> {
+        self.data_field(8..12)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+    }
+
+    /// Returns the user data of a DATA chunk.
+    pub fn user_data(&self) -> Option<&'a [u8]> {
+        self.data_field(12..self.value.len().max(12))
+    }
+
+    fn data_field(&self, range: std::ops::Range<usize>) -> Option<&'a [u8]> {
+        if self.chunk_type != SCTP_DATA {
+            return None;
+        }
+        self.value.get(range)
+    }
+}
+
+/// Iterator over the chunks of an SCTP packet.
+#[derive(Debug)]
+pub struct SctpChunks<'a> {
+    /// Remaining chunk bytes.
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for SctpChunks<'a> {
+    type Item = SctpChunk<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = self.data.get(..SCTP_CHUNK_HEADER_LEN)?;
+        let length = u16::from_be_bytes([header[2], header[3]]) as usize;
+        if length < SCTP_CHUNK_HEADER_LEN || length > self.data.len() {
+            self.data = &[];
+            return None;
+        }
+        let chunk = SctpChunk {
+            chunk_type: header[0],
+            flags: header[1],
+            value: &self.data[SCTP_CHUNK_HEADER_LEN..length],
+        };
+        // Chunks are padded to a multiple of 4 bytes
+        let padded = (length + 3) & !3;
+        self.data = self.data.get(padded..).unwrap_or_default();
+        Some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_sctp_chunks() {
+        #[rustfmt::skip]
+        let data = [
+            // DATA: TSN 1, stream 2, stream sequence 3, PPID 46, 3 bytes of user data and padding
+            SCTP_DATA, 0x03, 0, 19,
+            0, 0, 0, 1, 0, 2, 0, 3, 0, 0, 0, 46, b'a', b'b', b'c', 0,
+            // SHUTDOWN COMPLETE
+            SCTP_SHUTDOWN_COMPLETE, 0, 0, 4,
+        ];
+        let chunks: Vec<_> = SctpChunks { data: &data }.collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].tsn(), Some(1));
+        assert_eq!(chunks[0].stream_id(), Some(2));
+        assert_eq!(chunks[0].stream_seq(), Some(3));
+        assert_eq!(chunks[0].ppid(), Some(46));
+        assert_eq!(chunks[0].user_data(), Some(&b"abc"[..]));
+        assert_eq!(chunks[1].chunk_type(), SCTP_SHUTDOWN_COMPLETE);
+        assert_eq!(chunks[1].ppid(), None);
+    }
+}
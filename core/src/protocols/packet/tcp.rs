@@ -19,9 +19,20 @@ pub const RST: u8 = 0b0000_0100;
 pub const SYN: u8 = 0b0000_0010;
 pub const FIN: u8 = 0b0000_0001;
 
+// TCP option kinds.
+pub const OPT_END: u8 = 0;
+pub const OPT_NOP: u8 = 1;
+pub const OPT_SACK_PERMITTED: u8 = 4;
+pub const OPT_SACK: u8 = 5;
+pub const OPT_MPTCP: u8 = 30;
+
+/// Length of the fixed portion of the TCP header.
+const FIXED_HEADER_LEN: usize = 20;
+
 /// A TCP packet.
 ///
-/// TCP options are not parsed by default.
+/// TCP options are not parsed by default, but can be read on demand (see
+/// [options](Tcp::options)).
 #[derive(Debug)]
 pub struct Tcp<'a> {
     /// Fixed header.
@@ -160,6 +171,73 @@ impl<'a> Tcp<'a> {
     pub fn synack(&self) -> u8 {
         ((self.flags() & (ACK | SYN)) != 0) as u8
     }
+
+    // ------------------------------------------------
+
+    /// Returns an iterator over the TCP options, as `(kind, data)` pairs. Iteration stops at the
+    /// end of option list, or at the first malformed option.
+    pub fn options(&self) -> TcpOptions<'a> {
+        let len = self.header_len().saturating_sub(FIXED_HEADER_LEN);
+        let data = self
+            .mbuf
+            .get_data_slice(self.offset + FIXED_HEADER_LEN, len)
+            .unwrap_or_default();
+        TcpOptions { data }
+    }
+
+    /// Returns `true` if the segment carries the SACK-permitted option.
+    pub fn sack_permitted(&self) -> bool {
+        self.options().any(|(kind, _)| kind == OPT_SACK_PERMITTED)
+    }
+
+    /// Returns the blocks of the SACK option, as `(left edge, right edge)` sequence numbers, or an
+    /// empty vector if the segment does not carry one.
+    pub fn sack_blocks(&self) -> Vec<(u32, u32)> {
+        match self.options().find(|(kind, _)| *kind == OPT_SACK) {
+            Some((_, data)) => data
+                .chunks_exact(8)
+                .map(|block| {
+                    let left = u32::from_be_bytes([block[0], block[1], block[2], block[3]]);
+                    let right = u32::from_be_bytes([block[4], block[5], block[6], block[7]]);
+                    (left, right)
+                })
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+/// Iterator over the options of a TCP header.
+#[derive(Debug)]
+pub struct TcpOptions<'a> {
+    /// Remaining option bytes.
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for TcpOptions<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match *self.data.first()? {
+                OPT_END => {
+                    self.data = &[];
+                    return None;
+                }
+                OPT_NOP => self.data = &self.data[1..],
+                kind => {
+                    let len = *self.data.get(1)? as usize;
+                    if len < 2 || len > self.data.len() {
+                        self.data = &[];
+                        return None;
+                    }
+                    let option = &self.data[2..len];
+                    self.data = &self.data[len..];
+                    return Some((kind, option));
+                }
+            }
+        }
+    }
 }
 
 impl<'a> Packet<'a> for Tcp<'a> {
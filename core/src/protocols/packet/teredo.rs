@@ -0,0 +1,114 @@
+//! Teredo packet.
+
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::ipv6::IPV6_PROTOCOL;
+use crate::protocols::packet::{Packet, PacketParseError};
+
+use anyhow::{bail, Result};
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+/// IANA-assigned Teredo UDP port.
+pub const TEREDO_PORT: u16 = 3544;
+const TEREDO_AUTH: u16 = 0x0001;
+const TEREDO_ORIGIN: u16 = 0x0000;
+/// Length of the authentication indicator, without the client identifier and authentication
+/// value: indicator type, lengths, nonce, and confirmation byte.
+const TEREDO_AUTH_LEN: usize = 13;
+const TEREDO_ORIGIN_LEN: usize = 8;
+const IPV6_HEADER_LEN: usize = 40;
+
+/// A Teredo packet (RFC 4380): an IPv6 packet carried over UDP, optionally preceded by an
+/// authentication indicator and an origin indication.
+///
+/// The payload can be parsed with `teredo.parse_to::<Ipv6>()`, and only parses if it looks like an
+/// IPv6 header. Teredo is identified by its UDP ports, which are left to the caller to check.
+#[derive(Debug)]
+pub struct Teredo<'a> {
+    /// Offset of the Teredo indicators from the start of `mbuf`.
+    offset: usize,
+    /// Length of the indicators.
+    header_len: usize,
+    /// Whether an authentication indicator is present.
+    auth: bool,
+    /// Offset of the origin indication, if present.
+    origin: Option<usize>,
+    /// Packet buffer.
+    mbuf: &'a Mbuf,
+}
+
+impl<'a> Teredo<'a> {
+    /// Returns `true` if the packet has an authentication indicator.
+    pub fn has_auth(&self) -> bool {
+        self.auth
+    }
+
+    /// Returns the mapped address and port of the Teredo client from the origin indication, if
+    /// present.
+    pub fn origin(&self) -> Option<SocketAddrV4> {
+        let origin = self
+            .mbuf
+            .get_data_slice(self.origin?, TEREDO_ORIGIN_LEN)
+            .ok()?;
+        // Both are obfuscated by inverting all their bits
+        let port = !u16::from_be_bytes([origin[2], origin[3]]);
+        let addr = !u32::from_be_bytes([origin[4], origin[5], origin[6], origin[7]]);
+        Some(SocketAddrV4::new(Ipv4Addr::from(addr), port))
+    }
+}
+
+impl<'a> Packet<'a> for Teredo<'a> {
+    fn mbuf(&self) -> &Mbuf {
+        self.mbuf
+    }
+
+    fn header_len(&self) -> usize {
+        self.header_len
+    }
+
+    fn next_header_offset(&self) -> usize {
+        self.offset + self.header_len()
+    }
+
+    fn next_header(&self) -> Option<usize> {
+        let offset = self.next_header_offset();
+        let header = self.mbuf.get_data_slice(offset, IPV6_HEADER_LEN).ok()?;
+        let payload_len = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if header[0] >> 4 != 6 || offset + IPV6_HEADER_LEN + payload_len > self.mbuf.data_len() {
+            return None;
+        }
+        Some(IPV6_PROTOCOL)
+    }
+
+    fn parse_from(outer: &'a impl Packet<'a>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let offset = outer.next_header_offset();
+        let mbuf = outer.mbuf();
+        let indicator = |at: usize| -> Result<u16> {
+            let data = mbuf.get_data_slice(at, 2)?;
+            Ok(u16::from_be_bytes([data[0], data[1]]))
+        };
+        let mut header_len = 0;
+        let auth = indicator(offset)? == TEREDO_AUTH;
+        if auth {
+            let lengths = mbuf.get_data_slice(offset + 2, 2)?;
+            header_len += TEREDO_AUTH_LEN + lengths[0] as usize + lengths[1] as usize;
+        }
+        let mut origin = None;
+        if indicator(offset + header_len)? == TEREDO_ORIGIN {
+            origin = Some(offset + header_len);
+            header_len += TEREDO_ORIGIN_LEN;
+        }
+        if header_len > 0 && mbuf.data_len() <= offset + header_len {
+            bail!(PacketParseError::InvalidRead);
+        }
+        Ok(Teredo {
+            offset,
+            header_len,
+            auth,
+            origin,
+            mbuf,
+        })
+    }
+}
@@ -0,0 +1,98 @@
+//! VXLAN packet.
+
+use crate::memory::mbuf::Mbuf;
+use crate::protocols::packet::{Packet, PacketHeader, PacketParseError};
+use crate::utils::types::*;
+
+use anyhow::{bail, Result};
+
+/// IANA-assigned VXLAN UDP port.
+pub const VXLAN_PORT: u16 = 4789;
+/// UDP port used by the Linux kernel VXLAN driver by default.
+pub const VXLAN_LINUX_PORT: u16 = 8472;
+const VXLAN_HEADER_LEN: usize = 8;
+/// "I" flag, set when the VNI is valid.
+const VXLAN_FLAG_VNI: u8 = 0x08;
+
+/// A VXLAN packet (RFC 7348).
+///
+/// The payload is an Ethernet frame, which can be parsed with
+/// `vxlan.parse_to::<Ethernet>()`. VXLAN is identified by its UDP destination port, which is left
+/// to the caller to check: any UDP payload whose "I" flag is set parses as a VXLAN header.
+#[derive(Debug)]
+pub struct Vxlan<'a> {
+    /// Fixed header.
+    header: VxlanHeader,
+    /// Offset to `header` from the start of `mbuf`.
+    offset: usize,
+    /// Packet buffer.
+    mbuf: &'a Mbuf,
+}
+
+impl<'a> Vxlan<'a> {
+    /// Returns the VXLAN flags.
+    #[inline]
+    pub fn flags(&self) -> u8 {
+        self.header.flags
+    }
+
+    /// Returns the 24-bit VXLAN Network Identifier.
+    #[inline]
+    pub fn vni(&self) -> u32 {
+        u32::from(self.header.vni) >> 8
+    }
+}
+
+impl<'a> Packet<'a> for Vxlan<'a> {
+    fn mbuf(&self) -> &Mbuf {
+        self.mbuf
+    }
+
+    fn header_len(&self) -> usize {
+        self.header.length()
+    }
+
+    fn next_header_offset(&self) -> usize {
+        self.offset + self.header_len()
+    }
+
+    fn next_header(&self) -> Option<usize> {
+        None
+    }
+
+    fn parse_from(outer: &'a impl Packet<'a>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let offset = outer.next_header_offset();
+        if let Ok(header) = outer.mbuf().get_data::<VxlanHeader>(offset) {
+            let header = unsafe { *header };
+            if header.flags & VXLAN_FLAG_VNI == 0 {
+                bail!(PacketParseError::InvalidProtocol);
+            }
+            Ok(Vxlan {
+                header,
+                offset,
+                mbuf: outer.mbuf(),
+            })
+        } else {
+            bail!(PacketParseError::InvalidRead)
+        }
+    }
+}
+
+/// VXLAN header.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct VxlanHeader {
+    flags: u8,
+    reserved: [u8; 3],
+    /// VNI in the upper 24 bits, followed by a reserved byte.
+    vni: u32be,
+}
+
+impl PacketHeader for VxlanHeader {
+    fn length(&self) -> usize {
+        VXLAN_HEADER_LEN
+    }
+}
@@ -1,4 +1,7 @@
 use super::{ConnDataError, ConnField};
+use crate::protocols::packet::icmp::ICMP_PROTOCOL;
+use crate::protocols::packet::icmpv6::ICMPV6_PROTOCOL;
+use crate::protocols::packet::sctp::SCTP_PROTOCOL;
 use crate::protocols::packet::tcp::TCP_PROTOCOL;
 use crate::protocols::packet::udp::UDP_PROTOCOL;
 use crate::protocols::stream::ConnData;
@@ -97,3 +100,124 @@ impl ConnField for UdpCData {
         bail!(ConnDataError::InvalidProtocol)
     }
 }
+
+/// ICMP Echo Connection Metadata, parsed from ConnData.
+///
+/// Each ICMP Echo Request/Reply exchange is tracked as a connection keyed by the Echo identifier
+/// and sequence number.
+#[derive(Debug)]
+pub struct IcmpCData {
+    identifier: u16,
+    sequence: u16,
+}
+
+impl IcmpCData {
+    /// Returns the Echo identifier.
+    #[inline]
+    pub fn identifier(&self) -> u16 {
+        self.identifier
+    }
+
+    /// Returns the Echo sequence number.
+    #[inline]
+    pub fn sequence(&self) -> u16 {
+        self.sequence
+    }
+}
+
+impl ConnField for IcmpCData {
+    fn supported_fields() -> Vec<&'static str> {
+        vec!["identifier", "sequence"]
+    }
+
+    fn parse_from(conn_data: &ConnData) -> Result<Self> {
+        if let (ICMP_PROTOCOL, Some((identifier, sequence))) =
+            (conn_data.five_tuple.proto, conn_data.echo)
+        {
+            return Ok(Self {
+                identifier,
+                sequence,
+            });
+        }
+        bail!(ConnDataError::InvalidProtocol)
+    }
+}
+
+/// ICMPv6 Echo Connection Metadata, parsed from ConnData.
+///
+/// Each ICMPv6 Echo Request/Reply exchange is tracked as a connection keyed by the Echo identifier
+/// and sequence number.
+#[derive(Debug)]
+pub struct Icmpv6CData {
+    identifier: u16,
+    sequence: u16,
+}
+
+impl Icmpv6CData {
+    /// Returns the Echo identifier.
+    #[inline]
+    pub fn identifier(&self) -> u16 {
+        self.identifier
+    }
+
+    /// Returns the Echo sequence number.
+    #[inline]
+    pub fn sequence(&self) -> u16 {
+        self.sequence
+    }
+}
+
+impl ConnField for Icmpv6CData {
+    fn supported_fields() -> Vec<&'static str> {
+        vec!["identifier", "sequence"]
+    }
+
+    fn parse_from(conn_data: &ConnData) -> Result<Self> {
+        if let (ICMPV6_PROTOCOL, Some((identifier, sequence))) =
+            (conn_data.five_tuple.proto, conn_data.echo)
+        {
+            return Ok(Self {
+                identifier,
+                sequence,
+            });
+        }
+        bail!(ConnDataError::InvalidProtocol)
+    }
+}
+
+/// SCTP Association Metadata, parsed from ConnData.
+#[derive(Debug)]
+pub struct SctpCData {
+    src_port: u16,
+    dst_port: u16,
+}
+
+impl SctpCData {
+    /// Returns the sending port.
+    #[inline]
+    pub fn src_port(&self) -> u16 {
+        self.src_port
+    }
+
+    /// Returns the receiving port.
+    #[inline]
+    pub fn dst_port(&self) -> u16 {
+        self.dst_port
+    }
+}
+
+impl ConnField for SctpCData {
+    fn supported_fields() -> Vec<&'static str> {
+        vec!["src_port", "dst_port"]
+    }
+
+    fn parse_from(conn_data: &ConnData) -> Result<Self> {
+        if matches!(conn_data.five_tuple.proto, SCTP_PROTOCOL) {
+            return Ok(Self {
+                src_port: conn_data.five_tuple.orig.port(),
+                dst_port: conn_data.five_tuple.resp.port(),
+            });
+        }
+        bail!(ConnDataError::InvalidProtocol)
+    }
+}
@@ -3,9 +3,15 @@
 /// packet is not available, but connection data is.
 pub mod layer3;
 pub mod layer4;
+pub mod mpls;
+pub mod vlan;
+pub mod volume;
 
 pub use layer3::{Ipv4CData, Ipv6CData};
-pub use layer4::{TcpCData, UdpCData};
+pub use layer4::{IcmpCData, Icmpv6CData, SctpCData, TcpCData, UdpCData};
+pub use mpls::MplsCData;
+pub use vlan::VlanCData;
+pub use volume::ConnCData;
 
 use crate::protocols::stream::ConnData;
 use anyhow::Result;
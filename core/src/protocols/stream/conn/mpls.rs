@@ -0,0 +1,49 @@
+use crate::protocols::stream::ConnData;
+
+use super::{ConnDataError, ConnField};
+use anyhow::{bail, Result};
+
+/// MPLS label stack metadata, parsed from ConnData.
+#[derive(Debug)]
+pub struct MplsCData {
+    label: u32,
+    bottom_label: u32,
+    depth: usize,
+}
+
+impl MplsCData {
+    /// Returns the top label of the first packet of the connection.
+    #[inline]
+    pub fn label(&self) -> u32 {
+        self.label
+    }
+
+    /// Returns the bottom label of the first packet of the connection.
+    #[inline]
+    pub fn bottom_label(&self) -> u32 {
+        self.bottom_label
+    }
+
+    /// Returns the number of labels in the stack.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+impl ConnField for MplsCData {
+    fn supported_fields() -> Vec<&'static str> {
+        vec!["label", "bottom_label", "depth"]
+    }
+
+    fn parse_from(conn_data: &ConnData) -> Result<Self> {
+        if let Some(labels) = conn_data.tunnels.iter().find_map(|t| t.mpls_labels()) {
+            return Ok(Self {
+                label: labels.top(),
+                bottom_label: labels.bottom(),
+                depth: labels.depth(),
+            });
+        }
+        bail!(ConnDataError::InvalidProtocol)
+    }
+}
@@ -0,0 +1,51 @@
+use crate::protocols::stream::ConnData;
+
+use super::{ConnDataError, ConnField};
+use anyhow::{bail, Result};
+
+/// VLAN metadata, parsed from ConnData.
+#[derive(Debug)]
+pub struct VlanCData {
+    id: u16,
+    inner_id: u16,
+    qinq: bool,
+}
+
+impl VlanCData {
+    /// Returns the VLAN ID of the outer (or only) tag of the first packet of the connection.
+    #[inline]
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Returns the VLAN ID of the inner tag of the first packet of the connection, or `0` if it is
+    /// not double-tagged.
+    #[inline]
+    pub fn inner_id(&self) -> u16 {
+        self.inner_id
+    }
+
+    /// Returns `1` if the first packet of the connection is double-tagged (QinQ), and `0`
+    /// otherwise.
+    #[inline]
+    pub fn qinq(&self) -> u8 {
+        self.qinq as u8
+    }
+}
+
+impl ConnField for VlanCData {
+    fn supported_fields() -> Vec<&'static str> {
+        vec!["id", "inner_id", "qinq"]
+    }
+
+    fn parse_from(conn_data: &ConnData) -> Result<Self> {
+        if let [Some(id), inner_id] = conn_data.vlan_ids {
+            return Ok(Self {
+                id,
+                inner_id: inner_id.unwrap_or(0),
+                qinq: inner_id.is_some(),
+            });
+        }
+        bail!(ConnDataError::InvalidProtocol)
+    }
+}
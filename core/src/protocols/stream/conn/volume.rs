@@ -0,0 +1,38 @@
+use crate::protocols::stream::ConnData;
+
+use super::ConnField;
+use anyhow::Result;
+
+/// Connection volume metadata, parsed from ConnData.
+#[derive(Debug)]
+pub struct ConnCData {
+    bytes: u64,
+    packets: u64,
+}
+
+impl ConnCData {
+    /// Returns the number of bytes observed in the connection, in both directions.
+    #[inline]
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the number of packets observed in the connection, in both directions.
+    #[inline]
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+}
+
+impl ConnField for ConnCData {
+    fn supported_fields() -> Vec<&'static str> {
+        vec!["bytes", "packets"]
+    }
+
+    fn parse_from(conn_data: &ConnData) -> Result<Self> {
+        Ok(Self {
+            bytes: conn_data.num_bytes,
+            packets: conn_data.num_pkts,
+        })
+    }
+}
@@ -0,0 +1,173 @@
+//! Application-defined stream protocols.
+//!
+//! Applications can supply parsers for protocols that Retina does not implement (e.g., proprietary
+//! protocols). A custom parser implements [ConnParsable](super::ConnParsable), and returns sessions
+//! with [SessionData::Custom](super::SessionData::Custom) data implementing
+//! [CustomSession](CustomSession). Once its protocol is registered with
+//! [register_protocol](register_protocol), the protocol can be used in filters like a built-in one:
+//! the parser participates in probing, `myproto` matches connections identified as the protocol,
+//! and `myproto.field` predicates are evaluated on the fields exposed by
+//! [CustomSession::field](CustomSession::field).
+//!
+//! Custom parsers are usually declared with the `parser` attribute of the
+//! [retina_filtergen](fixlink) crate, which registers the protocol and adds the parser to the
+//! generated [ParserRegistry](super::ParserRegistry).
+
+use super::{ConnParsable, ParseResult, ParsingState, ProbeResult, Session, IMPLEMENTED_PROTOCOLS};
+use crate::conntrack::pdu::L4Pdu;
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Mutex;
+
+use anyhow::{bail, Result};
+
+/// Transport protocols that custom protocols can run over.
+const TRANSPORTS: [&str; 2] = ["tcp", "udp"];
+
+/// Registered custom protocols, with the transport protocols they run over.
+static PROTOCOLS: Mutex<Vec<(&'static str, Vec<&'static str>)>> = Mutex::new(Vec::new());
+
+/// Registers custom protocol `name`, carried over `transports` (`tcp` and/or `udp`).
+///
+/// Protocols must be registered before any filter is parsed.
+pub fn register_protocol(name: &'static str, transports: &[&'static str]) -> Result<()> {
+    if IMPLEMENTED_PROTOCOLS.contains(&name) {
+        bail!("Protocol {} is built-in", name);
+    }
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        bail!("Invalid protocol name: {}", name);
+    }
+    if transports.is_empty() {
+        bail!("No transport protocol for {}", name);
+    }
+    if let Some(transport) = transports.iter().find(|t| !TRANSPORTS.contains(t)) {
+        bail!("Invalid transport protocol for {}: {}", name, transport);
+    }
+    let mut protocols = PROTOCOLS.lock().unwrap();
+    protocols.retain(|(registered, _)| *registered != name);
+    protocols.push((name, transports.to_vec()));
+    Ok(())
+}
+
+/// Returns the registered custom protocols, with the transport protocols they run over.
+pub fn custom_protocols() -> Vec<(&'static str, Vec<&'static str>)> {
+    PROTOCOLS.lock().unwrap().clone()
+}
+
+/// Returns `true` if `name` is a registered custom protocol.
+pub fn is_custom_protocol(name: &str) -> bool {
+    PROTOCOLS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|(registered, _)| *registered == name)
+}
+
+/// The value of a field of a custom session, compared against filter predicates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldValue {
+    Int(u64),
+    Text(String),
+}
+
+impl FieldValue {
+    /// Returns the value if it is an integer.
+    pub fn as_int(&self) -> Option<u64> {
+        match self {
+            FieldValue::Int(val) => Some(*val),
+            FieldValue::Text(_) => None,
+        }
+    }
+
+    /// Returns the value if it is text.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            FieldValue::Int(_) => None,
+            FieldValue::Text(val) => Some(val),
+        }
+    }
+}
+
+/// Session data of a custom protocol.
+pub trait CustomSession: fmt::Debug + Send + Any {
+    /// The name of the protocol, as registered with [register_protocol](register_protocol).
+    fn protocol(&self) -> &'static str;
+
+    /// Returns the value of field `name`, used by `<protocol>.<name>` filter predicates. Predicates
+    /// on missing fields do not match.
+    fn field(&self, name: &str) -> Option<FieldValue>;
+
+    /// Returns `self`, for downcasting to the concrete session type.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// A custom protocol parser, with the constructor used to reset its state for new connections.
+#[doc(hidden)]
+pub struct CustomParser {
+    name: &'static str,
+    new: fn() -> Box<dyn ConnParsable>,
+    parser: Box<dyn ConnParsable>,
+}
+
+impl CustomParser {
+    pub(crate) fn new(name: &'static str, new: fn() -> Box<dyn ConnParsable>) -> Self {
+        CustomParser {
+            name,
+            new,
+            parser: new(),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub(crate) fn reset_new(&self) -> Self {
+        CustomParser::new(self.name, self.new)
+    }
+
+    pub(crate) fn parse(&mut self, pdu: &L4Pdu) -> ParseResult {
+        self.parser.parse(pdu)
+    }
+
+    pub(crate) fn probe(&self, pdu: &L4Pdu) -> ProbeResult {
+        self.parser.probe(pdu)
+    }
+
+    pub(crate) fn remove_session(&mut self, session_id: usize) -> Option<Session> {
+        self.parser.remove_session(session_id)
+    }
+
+    pub(crate) fn drain_sessions(&mut self) -> Vec<Session> {
+        self.parser.drain_sessions()
+    }
+
+    pub(crate) fn session_parsed_state(&self) -> ParsingState {
+        self.parser.session_parsed_state()
+    }
+}
+
+impl fmt::Debug for CustomParser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomParser")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_custom_register() {
+        assert!(register_protocol("tls", &["tcp"]).is_err());
+        assert!(register_protocol("my-proto", &["tcp"]).is_err());
+        assert!(register_protocol("myproto", &[]).is_err());
+        assert!(register_protocol("myproto", &["sctp"]).is_err());
+        assert!(!is_custom_protocol("myproto"));
+        assert!(register_protocol("myproto", &["tcp", "udp"]).is_ok());
+        assert!(is_custom_protocol("myproto"));
+    }
+}
@@ -5,12 +5,16 @@ mod transaction;
 
 pub use self::transaction::*;
 
+use dns_parser::ResponseCode;
+#[cfg(feature = "serde")]
+use serde::Deserialize;
 use serde::Serialize;
 
 /// Parsed DNS transaction contents.
 ///
 /// A DNS transaction consists of a query and a response.
 #[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct Dns {
     /// DNS transaction ID.
     pub transaction_id: u16,
@@ -31,6 +35,30 @@ impl Dns {
         ""
     }
 
+    /// Returns the query type (QTYPE) of the first question, or `0` if no query was observed in the
+    /// transaction.
+    pub fn qtype(&self) -> u16 {
+        if let Some(query) = &self.query {
+            if let Some(qtype) = query.query_types.first() {
+                return *qtype;
+            }
+        }
+        0
+    }
+
+    /// Returns the response code (RCODE), or `0` if no response was observed in the transaction.
+    pub fn rcode(&self) -> u8 {
+        match self.response.as_ref().map(|resp| resp.response_code) {
+            Some(ResponseCode::NoError) | None => 0,
+            Some(ResponseCode::FormatError) => 1,
+            Some(ResponseCode::ServerFailure) => 2,
+            Some(ResponseCode::NameError) => 3,
+            Some(ResponseCode::NotImplemented) => 4,
+            Some(ResponseCode::Refused) => 5,
+            Some(ResponseCode::Reserved(code)) => code,
+        }
+    }
+
     /// Returns a string representation of the answers
     pub fn answers(&self) -> String {
         if let Some(resp) = &self.response {
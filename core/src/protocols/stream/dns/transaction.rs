@@ -3,34 +3,46 @@
 use dns_parser::rdata::{Aaaa, RData, A};
 use dns_parser::{Packet, ResponseCode};
 
+#[cfg(feature = "serde")]
+use serde::Deserialize;
 use serde::Serialize;
 
 /// A DNS Query.
 #[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct DnsQuery {
     pub num_questions: u16,
     pub recursion_desired: bool, // appears in query & answer
     pub queries: Vec<String>,    // typically only one question per query, could have multiple
+    pub query_types: Vec<u16>,   // QTYPE of each question, in the same order as `queries`
 }
 
 impl DnsQuery {
     pub(super) fn parse_query(pkt: &Packet) -> Self {
         let mut queries = Vec::new();
+        let mut query_types = Vec::new();
         for q in &pkt.questions {
             log::debug!("  query: {}/{:?}", q.qname, q.qtype);
             queries.push(q.qname.to_string());
+            query_types.push(q.qtype as u16);
         }
         DnsQuery {
             num_questions: pkt.header.questions,
             recursion_desired: pkt.header.recursion_desired,
             queries,
+            query_types,
         }
     }
 }
 
 /// A DNS Response.
 #[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct DnsResponse {
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "remote::ResponseCodeDef::deserialize")
+    )]
     pub response_code: ResponseCode,
     pub authoritative: bool, // if the DNS server is authoritative for the queried hostname, appear in answer
     pub recursion_available: bool, // appear in answer
@@ -88,6 +100,7 @@ impl DnsResponse {
 
 /// A DNS Record.
 #[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct DnsRecord {
     pub name: String,
     pub data: Data,
@@ -96,9 +109,22 @@ pub struct DnsRecord {
 
 /// RData types.
 #[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub enum Data {
-    A(A),
-    Aaaa(Aaaa),
+    A(
+        #[cfg_attr(
+            feature = "serde",
+            serde(deserialize_with = "remote::ADef::deserialize")
+        )]
+        A,
+    ),
+    Aaaa(
+        #[cfg_attr(
+            feature = "serde",
+            serde(deserialize_with = "remote::AaaaDef::deserialize")
+        )]
+        Aaaa,
+    ),
     Cname(String),
     Mx(Mx),
     Ns(String),
@@ -144,6 +170,7 @@ impl Data {
 
 /// A DNS mail exchange (MX) record.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct Mx {
     pub preference: u16,
     pub exchange: String,
@@ -151,6 +178,7 @@ pub struct Mx {
 
 /// A DNS start of authority (SOA) record.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct Soa {
     pub primary_ns: String,
     pub mailbox: String,
@@ -163,9 +191,40 @@ pub struct Soa {
 
 /// A DNS service (SRV) record.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct Srv {
     pub priority: u16,
     pub weight: u16,
     pub port: u16,
     pub target: String,
 }
+
+/// Deserializes the [dns-parser](https://docs.rs/dns-parser/latest/dns_parser/) types of DNS
+/// responses, as they are serialized.
+#[cfg(feature = "serde")]
+mod remote {
+    use dns_parser::rdata::{Aaaa, A};
+    use dns_parser::ResponseCode;
+    use serde::Deserialize;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[derive(Deserialize)]
+    #[serde(remote = "ResponseCode")]
+    pub(super) enum ResponseCodeDef {
+        NoError,
+        FormatError,
+        ServerFailure,
+        NameError,
+        NotImplemented,
+        Refused,
+        Reserved(u8),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(remote = "A")]
+    pub(super) struct ADef(pub Ipv4Addr);
+
+    #[derive(Deserialize)]
+    #[serde(remote = "Aaaa")]
+    pub(super) struct AaaaDef(pub Ipv6Addr);
+}
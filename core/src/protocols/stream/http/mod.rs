@@ -22,10 +22,13 @@ mod transaction;
 
 pub use self::transaction::{HttpRequest, HttpResponse};
 
+#[cfg(feature = "serde")]
+use serde::Deserialize;
 use serde::Serialize;
 
 /// Parsed HTTP transaction contents.
 #[derive(Debug, Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct Http {
     /// HTTP Request.
     pub request: HttpRequest,
@@ -113,5 +116,25 @@ impl Http {
         self.response.transfer_encoding.as_deref().unwrap_or("")
     }
 
+    /// Returns the value of the header `name` (case-insensitive), or `""` if it does not exist.
+    /// Request headers take precedence over response headers.
+    pub fn header(&self, name: &str) -> &str {
+        self.request
+            .headers
+            .iter()
+            .chain(self.response.headers.iter())
+            .find(|(hdr, _)| hdr.eq_ignore_ascii_case(name))
+            .map_or("", |(_, value)| value.as_str())
+    }
+
+    /// Returns `1` if the request or response contains the header `name` (case-insensitive).
+    pub fn has_header(&self, name: &str) -> u8 {
+        self.request
+            .headers
+            .iter()
+            .chain(self.response.headers.iter())
+            .any(|(hdr, _)| hdr.eq_ignore_ascii_case(name)) as u8
+    }
+
     // TODO: more methods...
 }
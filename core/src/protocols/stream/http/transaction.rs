@@ -6,10 +6,13 @@
 
 use anyhow::{bail, Result};
 use httparse::{Request, Response, EMPTY_HEADER};
+#[cfg(feature = "serde")]
+use serde::Deserialize;
 use serde::Serialize;
 
 /// An HTTP Request
 #[derive(Debug, Default, Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct HttpRequest {
     pub method: Option<String>,
     pub uri: Option<String>,
@@ -20,6 +23,9 @@ pub struct HttpRequest {
     pub content_length: Option<usize>,
     pub content_type: Option<String>,
     pub transfer_encoding: Option<String>,
+    /// All request headers as (lowercase name, value) pairs, retained for filter evaluation.
+    #[serde(skip)]
+    pub headers: Vec<(String, String)>,
     // /// `false` if request body needs continuation pub is_complete: bool, /// Actual length in
     // bytes of body data transferred from the client. pub body_len: usize,
 }
@@ -46,6 +52,9 @@ impl HttpRequest {
             request.version = Some(format!("HTTP/1.{}", version));
         }
         for hdr in &headers {
+            if hdr.name.is_empty() {
+                continue;
+            }
             let name = hdr.name.to_lowercase();
             match name.as_ref() {
                 "user-agent" => {
@@ -83,6 +92,8 @@ impl HttpRequest {
                 }
                 _ => (),
             }
+            let value = String::from_utf8_lossy(hdr.value).into_owned();
+            request.headers.push((name, value));
         }
         Ok(request)
     }
@@ -90,6 +101,7 @@ impl HttpRequest {
 
 /// An HTTP Response
 #[derive(Debug, Default, Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct HttpResponse {
     pub version: Option<String>,
     pub status_code: Option<u16>,
@@ -97,6 +109,9 @@ pub struct HttpResponse {
     pub content_length: Option<usize>,
     pub content_type: Option<String>,
     pub transfer_encoding: Option<String>,
+    /// All response headers as (lowercase name, value) pairs, retained for filter evaluation.
+    #[serde(skip)]
+    pub headers: Vec<(String, String)>,
     // /// `false` if response body needs continuation pub is_complete: bool, /// Actual length in
     // bytes of body data transferred from the server. pub body_len: usize, pub chunk_length:
     // Option<usize>, pub in_next_frame: bool,
@@ -125,6 +140,9 @@ impl HttpResponse {
         }
 
         for hdr in &headers {
+            if hdr.name.is_empty() {
+                continue;
+            }
             let name = hdr.name.to_lowercase();
             match name.as_ref() {
                 "content-length" => {
@@ -156,6 +174,8 @@ impl HttpResponse {
                 }
                 _ => (),
             }
+            let value = String::from_utf8_lossy(hdr.value).into_owned();
+            response.headers.push((name, value));
         }
         Ok(response)
     }
@@ -6,19 +6,27 @@
 
 #[doc(hidden)]
 pub mod conn;
+pub mod custom;
 pub mod dns;
 pub mod http;
 pub mod quic;
 pub mod tls;
 
 use self::conn::ConnField;
-use self::conn::{Ipv4CData, Ipv6CData, TcpCData, UdpCData};
+use self::conn::{
+    ConnCData, IcmpCData, Icmpv6CData, Ipv4CData, Ipv6CData, MplsCData, SctpCData, TcpCData,
+    UdpCData, VlanCData,
+};
+use self::custom::{CustomParser, CustomSession};
 use self::dns::{parser::DnsParser, Dns};
 use self::http::{parser::HttpParser, Http};
 use self::quic::parser::QuicParser;
 use self::tls::{parser::TlsParser, Tls};
 use crate::conntrack::conn_id::FiveTuple;
 use crate::conntrack::pdu::L4Pdu;
+use crate::conntrack::tunnel::Tunnels;
+use crate::protocols::packet::icmp::ICMP_PROTOCOL;
+use crate::protocols::packet::icmpv6::ICMPV6_PROTOCOL;
 
 use std::collections::HashSet;
 use std::str::FromStr;
@@ -31,7 +39,7 @@ pub(crate) const IMPLEMENTED_PROTOCOLS: [&str; 4] = ["tls", "dns", "http", "quic
 
 /// Represents the result of parsing one packet as a protocol message.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum ParseResult {
+pub enum ParseResult {
     /// Session parsing done, check session filter. Returns the most-recently-updated session ID.
     Done(usize),
     /// Successfully extracted data, continue processing more packets. Returns most recently updated
@@ -45,7 +53,7 @@ pub(crate) enum ParseResult {
 
 /// Represents the result of a probing one packet as a protocol message type.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum ProbeResult {
+pub enum ProbeResult {
     /// Segment matches the parser with great probability.
     Certain,
     /// Unsure if the segment matches the parser.
@@ -85,9 +93,32 @@ impl ParserRegistry {
         ParserRegistry(parsers)
     }
 
+    /// Adds the parser of custom protocol `name`, built by `new`.
+    pub fn with_custom(mut self, name: &'static str, new: fn() -> Box<dyn ConnParsable>) -> Self {
+        self.0
+            .push(ConnParser::Custom(CustomParser::new(name, new)));
+        self
+    }
+
+    /// Restricts the parser of `protocol` to the session `fields`, when only filters need its
+    /// sessions. The parser may then stop as soon as these fields are parsed (currently, TLS
+    /// parsing stops after the ClientHello if only client fields are needed). Parsers of other
+    /// protocols parse whole sessions.
+    pub fn with_fields(mut self, protocol: &str, fields: &[&str]) -> Self {
+        for parser in self.0.iter_mut() {
+            if let ConnParser::Tls(tls) = parser {
+                if protocol == "tls" {
+                    *tls = TlsParser::with_fields(fields);
+                }
+            }
+        }
+        self
+    }
+
     /// Probe the packet `pdu` with all registered protocol parsers.
     pub(crate) fn probe_all(&self, pdu: &L4Pdu) -> ProbeRegistryResult {
-        if self.0.is_empty() {
+        // ICMP Echo payloads do not carry application-layer protocols
+        if self.0.is_empty() || matches!(pdu.ctxt.proto, ICMP_PROTOCOL | ICMPV6_PROTOCOL) {
             return ProbeRegistryResult::None;
         }
         if pdu.length() == 0 {
@@ -115,7 +146,7 @@ impl ParserRegistry {
 }
 
 /// A trait all application-layer protocol parsers must implement.
-pub(crate) trait ConnParsable {
+pub trait ConnParsable {
     /// Parse the L4 protocol data unit as the parser's protocol.
     fn parse(&mut self, pdu: &L4Pdu) -> ParseResult;
 
@@ -146,6 +177,17 @@ pub struct ConnData {
     pub five_tuple: FiveTuple,
     /// The protocol parser associated with the connection.
     pub conn_parser: ConnParser,
+    /// Number of packets observed in the connection.
+    pub num_pkts: u64,
+    /// Number of bytes observed in the connection (wire length of its packets).
+    pub num_bytes: u64,
+    /// The tunnels the first packet of the connection was decapsulated from, if any.
+    pub tunnels: Tunnels,
+    /// VLAN IDs of the first packet of the connection, from the outer to the inner tag.
+    pub vlan_ids: [Option<u16>; 2],
+    /// Identifier and sequence number of an ICMP or ICMPv6 Echo exchange. The ports of the
+    /// 5-tuple hold them in the order of the first packet, which may be the request or the reply.
+    pub(crate) echo: Option<(u16, u16)>,
 }
 
 impl ConnData {
@@ -155,24 +197,49 @@ impl ConnData {
             .chain(UdpCData::supported_fields())
             .chain(Ipv4CData::supported_fields())
             .chain(Ipv6CData::supported_fields())
+            .chain(IcmpCData::supported_fields())
+            .chain(Icmpv6CData::supported_fields())
+            .chain(SctpCData::supported_fields())
+            .chain(ConnCData::supported_fields())
+            .chain(MplsCData::supported_fields())
+            .chain(VlanCData::supported_fields())
             .collect();
         v.dedup();
         v
     }
 
     pub(crate) fn supported_protocols() -> Vec<&'static str> {
-        vec!["ipv4", "ipv6", "tcp", "udp"]
+        vec![
+            "ipv4", "ipv6", "tcp", "udp", "icmp", "icmpv6", "sctp", "conn", "mpls", "vlan",
+        ]
     }
 
     /// Create a new `ConnData` from the connection `five_tuple` and the ID of the last matched node
     /// in the filter predicate trie.
-    pub(crate) fn new(five_tuple: FiveTuple) -> Self {
+    pub(crate) fn new(
+        five_tuple: FiveTuple,
+        tunnels: Tunnels,
+        vlan_ids: [Option<u16>; 2],
+        echo: Option<(u16, u16)>,
+    ) -> Self {
         ConnData {
             five_tuple,
             conn_parser: ConnParser::Unknown,
+            num_pkts: 0,
+            num_bytes: 0,
+            tunnels,
+            vlan_ids,
+            echo,
         }
     }
 
+    /// Updates the connection volume counters with a packet of `length` bytes.
+    #[inline]
+    pub(crate) fn update_volume(&mut self, length: usize) {
+        self.num_pkts += 1;
+        self.num_bytes += length as u64;
+    }
+
     pub(crate) fn clear(&mut self) {
         self.conn_parser = ConnParser::Unknown;
     }
@@ -207,6 +274,25 @@ pub enum SessionData {
     Http(Box<Http>),
     Quic(Box<QuicConn>),
     Null,
+    Custom(Box<dyn CustomSession>),
+}
+
+impl SessionData {
+    /// Returns the session data if it is of custom protocol `name`.
+    pub fn custom(&self, name: &str) -> Option<&dyn CustomSession> {
+        match self {
+            SessionData::Custom(session) if session.protocol() == name => Some(session.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// Returns the session data if it is a custom session of type `T`.
+    pub fn as_custom<T: CustomSession>(&self) -> Option<&T> {
+        match self {
+            SessionData::Custom(session) => session.as_any().downcast_ref::<T>(),
+            _ => None,
+        }
+    }
 }
 
 /// An application-layer protocol session.
@@ -251,17 +337,20 @@ pub enum ConnParser {
     Http(HttpParser),
     Quic(QuicParser),
     Unknown,
+    #[strum(disabled)]
+    Custom(CustomParser),
 }
 
 impl ConnParser {
     /// Returns a new connection protocol parser of the same type, but with state reset.
     pub(crate) fn reset_new(&self) -> ConnParser {
         match self {
-            ConnParser::Tls(_) => ConnParser::Tls(TlsParser::default()),
+            ConnParser::Tls(parser) => ConnParser::Tls(parser.reset_new()),
             ConnParser::Dns(_) => ConnParser::Dns(DnsParser::default()),
             ConnParser::Http(_) => ConnParser::Http(HttpParser::default()),
             ConnParser::Quic(_) => ConnParser::Quic(QuicParser::default()),
             ConnParser::Unknown => ConnParser::Unknown,
+            ConnParser::Custom(parser) => ConnParser::Custom(parser.reset_new()),
         }
     }
 
@@ -273,6 +362,7 @@ impl ConnParser {
             ConnParser::Http(parser) => parser.parse(pdu),
             ConnParser::Quic(parser) => parser.parse(pdu),
             ConnParser::Unknown => ParseResult::None,
+            ConnParser::Custom(parser) => parser.parse(pdu),
         }
     }
 
@@ -284,6 +374,7 @@ impl ConnParser {
             ConnParser::Http(parser) => parser.probe(pdu),
             ConnParser::Quic(parser) => parser.probe(pdu),
             ConnParser::Unknown => ProbeResult::Error,
+            ConnParser::Custom(parser) => parser.probe(pdu),
         }
     }
 
@@ -296,6 +387,7 @@ impl ConnParser {
             ConnParser::Http(parser) => parser.remove_session(session_id),
             ConnParser::Quic(parser) => parser.remove_session(session_id),
             ConnParser::Unknown => None,
+            ConnParser::Custom(parser) => parser.remove_session(session_id),
         }
     }
 
@@ -307,6 +399,7 @@ impl ConnParser {
             ConnParser::Http(parser) => parser.drain_sessions(),
             ConnParser::Quic(parser) => parser.drain_sessions(),
             ConnParser::Unknown => vec![],
+            ConnParser::Custom(parser) => parser.drain_sessions(),
         }
     }
 
@@ -317,6 +410,7 @@ impl ConnParser {
             ConnParser::Http(parser) => parser.session_parsed_state(),
             ConnParser::Quic(parser) => parser.session_parsed_state(),
             ConnParser::Unknown => ParsingState::Stop,
+            ConnParser::Custom(parser) => parser.session_parsed_state(),
         }
     }
 
@@ -329,6 +423,7 @@ impl ConnParser {
             ConnParser::Http(_parser) => Some("http".into()),
             ConnParser::Quic(_parser) => Some("quic".into()),
             ConnParser::Unknown => None,
+            ConnParser::Custom(parser) => Some(parser.name().into()),
         }
     }
 
@@ -340,6 +435,11 @@ impl ConnParser {
                 out.insert(s);
             }
         }
+        for (s, _) in custom::custom_protocols() {
+            if filter_str.contains(s) {
+                out.insert(s);
+            }
+        }
         out
     }
 }
@@ -1,6 +1,8 @@
 // QUIC Frame types and parsing
 // Implemented per RFC 9000: https://datatracker.ietf.org/doc/html/rfc9000#name-frame-types-and-formats
 
+#[cfg(feature = "serde")]
+use serde::Deserialize;
 use serde::Serialize;
 use std::collections::BTreeMap;
 
@@ -10,6 +12,7 @@ use crate::protocols::stream::quic::QuicPacket;
 // Types of supported QUIC frames
 // Currently only includes those seen in the Init and Handshake packets
 #[derive(Debug, Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub enum QuicFrame {
     Padding {
         length: usize,
@@ -30,6 +33,7 @@ pub enum QuicFrame {
 // ACK Range field, part of ACK frame
 // https://datatracker.ietf.org/doc/html/rfc9000#ack-range-format
 #[derive(Debug, Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct AckRange {
     gap: u64,
     ack_range_len: u64,
@@ -38,6 +42,7 @@ pub struct AckRange {
 // ECN Counts field, part of some ACK frames
 // https://datatracker.ietf.org/doc/html/rfc9000#ecn-count-format
 #[derive(Debug, Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct EcnCounts {
     ect0_count: u64,
     ect1_count: u64,
@@ -1,11 +1,14 @@
 //! Quic header types
 
+#[cfg(feature = "serde")]
+use serde::Deserialize;
 use serde::Serialize;
 
 use crate::protocols::stream::quic::QuicError;
 
 /// Quic Long Header
 #[derive(Debug, Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct QuicLongHeader {
     pub packet_type: LongHeaderPacketType,
     pub type_specific: u8,
@@ -21,12 +24,14 @@ pub struct QuicLongHeader {
 
 /// Quic Short Header
 #[derive(Debug, Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct QuicShortHeader {
     pub dcid: Option<String>, // optional. If not pre-existing cid then none.
 }
 
 // Long Header Packet Types from RFC 9000 Table 5
 #[derive(Debug, Clone, Serialize, Copy)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub enum LongHeaderPacketType {
     Initial,
     ZeroRTT,
@@ -28,6 +28,8 @@ pub use self::header::{QuicLongHeader, QuicShortHeader};
 use crypto::Open;
 use frame::QuicFrame;
 use header::LongHeaderPacketType;
+#[cfg(feature = "serde")]
+use serde::Deserialize;
 use serde::Serialize;
 
 use super::tls::Tls;
@@ -55,6 +57,7 @@ pub enum QuicError {
 
 /// Parsed Quic connections
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct QuicConn {
     // All packets associated with the connection
     pub packets: Vec<QuicPacket>,
@@ -66,22 +69,25 @@ pub struct QuicConn {
     pub tls: Tls,
 
     // Crypto needed to decrypt initial packets sent by client
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     pub client_opener: Option<Open>,
 
     // Crypto needed to decrypt initial packets sent by server
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     pub server_opener: Option<Open>,
 
     // Client buffer for multi-packet TLS messages
-    #[serde(skip_serializing)]
+    #[serde(skip)]
     pub client_buffer: Vec<u8>,
 
     // Server buffer for multi-packet TLS messages
-    #[serde(skip_serializing)]
+    #[serde(skip)]
     pub server_buffer: Vec<u8>,
 }
 
 /// Parsed Quic Packet contents
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct QuicPacket {
     /// Quic Short header
     pub short_header: Option<QuicShortHeader>,
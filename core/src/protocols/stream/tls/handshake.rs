@@ -4,6 +4,8 @@
 
 use crate::utils::base64;
 
+#[cfg(feature = "serde")]
+use serde::Deserialize;
 use serde::Serialize;
 use tls_parser::{
     NamedGroup, SignatureScheme, TlsCipherSuiteID, TlsCompressionID, TlsExtensionType, TlsVersion,
@@ -11,43 +13,58 @@ use tls_parser::{
 
 /// A parsed TLS ClientHello message.
 #[derive(Clone, Debug, Default, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct ClientHello {
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::version"))]
     pub version: TlsVersion,
     #[serde(with = "base64")]
     pub random: Vec<u8>,
     #[serde(with = "base64")]
     pub session_id: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::cipher_suites"))]
     pub cipher_suites: Vec<TlsCipherSuiteID>,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::compression_algs"))]
     pub compression_algs: Vec<TlsCompressionID>,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::extensions"))]
     pub extension_list: Vec<TlsExtensionType>,
     pub server_name: Option<String>,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::groups"))]
     pub supported_groups: Vec<NamedGroup>,
     pub ec_point_formats: Vec<u8>,
     pub alpn_protocols: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::signature_schemes"))]
     pub signature_algs: Vec<SignatureScheme>,
     pub key_shares: Vec<KeyShareEntry>,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::versions"))]
     pub supported_versions: Vec<TlsVersion>,
 }
 
 /// A parsed TLS ServerHello message.
 #[derive(Clone, Debug, Default, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct ServerHello {
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::version"))]
     pub version: TlsVersion,
     #[serde(with = "base64")]
     pub random: Vec<u8>,
     #[serde(with = "base64")]
     pub session_id: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::cipher_suite"))]
     pub cipher_suite: TlsCipherSuiteID,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::compression_alg"))]
     pub compression_alg: TlsCompressionID,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::extensions"))]
     pub extension_list: Vec<TlsExtensionType>,
     pub ec_point_formats: Vec<u8>,
     pub alpn_protocol: Option<String>,
     pub key_share: Option<KeyShareEntry>,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::opt_version"))]
     pub selected_version: Option<TlsVersion>,
 }
 
 /// A raw X509 certificate.
 #[derive(Clone, Debug, Default, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct Certificate {
     #[serde(with = "base64")]
     pub raw: Vec<u8>,
@@ -56,6 +73,7 @@ pub struct Certificate {
 
 /// Key data sent by the server in a ServerKeyExchange message.
 #[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 #[serde(rename_all = "snake_case")]
 pub enum ServerKeyExchange {
     Ecdh(ServerECDHParams),
@@ -74,6 +92,7 @@ impl Default for ServerKeyExchange {
 
 /// Key data sent by the client in a ClientKeyExchange message.
 #[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 #[serde(rename_all = "snake_case")]
 pub enum ClientKeyExchange {
     Ecdh(ClientECDHParams),
@@ -91,6 +110,7 @@ impl Default for ClientKeyExchange {
 
 /// RSA parameters sent by the server in a ServerKeyExchange message. (RSA_EXPORT cipher suites).
 #[derive(Clone, Debug, Default, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct ServerRSAParams {
     #[serde(with = "base64")]
     pub modulus: Vec<u8>,
@@ -101,6 +121,7 @@ pub struct ServerRSAParams {
 /// Stores the encrypted premaster secret sent by the client in a ClientKeyExchange message in an
 /// RSA handshake.
 #[derive(Clone, Debug, Default, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct ClientRSAParams {
     #[serde(with = "base64")]
     pub encrypted_pms: Vec<u8>,
@@ -108,6 +129,7 @@ pub struct ClientRSAParams {
 
 /// Finite-field Diffie-Hellman parameters sent by the server in a ServerKeyExchange message.
 #[derive(Clone, Debug, Default, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct ServerDHParams {
     #[serde(with = "base64")]
     pub prime: Vec<u8>,
@@ -119,6 +141,7 @@ pub struct ServerDHParams {
 
 /// Finite-field Diffie-Hellman parameters sent by the client in a ClientKeyExchange message.
 #[derive(Clone, Debug, Default, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct ClientDHParams {
     #[serde(with = "base64")]
     pub kx_data: Vec<u8>,
@@ -126,7 +149,9 @@ pub struct ClientDHParams {
 
 /// Elliptic-curve Diffie-Hellman parameters sent by the server in a ServerKeyExchange message.
 #[derive(Clone, Debug, Default, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct ServerECDHParams {
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::group"))]
     pub curve: NamedGroup,
     #[serde(with = "base64")]
     pub kx_data: Vec<u8>,
@@ -134,6 +159,7 @@ pub struct ServerECDHParams {
 
 /// Elliptic-curve Diffie-Hellman parameters sent by the client in a ClientKeyExchange message.
 #[derive(Clone, Debug, Default, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct ClientECDHParams {
     #[serde(with = "base64")]
     pub kx_data: Vec<u8>,
@@ -147,8 +173,52 @@ pub struct ClientECDHParams {
 /// point prepended with the value 0x4. See [Key
 /// Share](https://datatracker.ietf.org/doc/html/rfc8446#section-4.2.8) for details.
 #[derive(Clone, Debug, Default, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct KeyShareEntry {
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "ids::group"))]
     pub group: NamedGroup,
     #[serde(with = "base64")]
     pub kx_data: Vec<u8>,
 }
+
+/// Deserializes the identifiers of [tls-parser](https://docs.rs/tls-parser/latest/tls_parser/),
+/// serialized as their numeric values.
+#[cfg(feature = "serde")]
+mod ids {
+    use serde::{Deserialize, Deserializer};
+    use tls_parser::{
+        NamedGroup, SignatureScheme, TlsCipherSuiteID, TlsCompressionID, TlsExtensionType,
+        TlsVersion,
+    };
+
+    macro_rules! ids {
+        ($($id:ident, $list:ident: $ty:ident($raw:ty);)*) => {
+            $(
+                #[allow(dead_code)]
+                pub(super) fn $id<'de, D: Deserializer<'de>>(d: D) -> Result<$ty, D::Error> {
+                    <$raw>::deserialize(d).map($ty)
+                }
+
+                #[allow(dead_code)]
+                pub(super) fn $list<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<$ty>, D::Error> {
+                    Vec::<$raw>::deserialize(d).map(|ids| ids.into_iter().map($ty).collect())
+                }
+            )*
+        };
+    }
+
+    ids! {
+        version, versions: TlsVersion(u16);
+        cipher_suite, cipher_suites: TlsCipherSuiteID(u16);
+        compression_alg, compression_algs: TlsCompressionID(u8);
+        extension, extensions: TlsExtensionType(u16);
+        group, groups: NamedGroup(u16);
+        signature_scheme, signature_schemes: SignatureScheme(u16);
+    }
+
+    pub(super) fn opt_version<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<Option<TlsVersion>, D::Error> {
+        Option::<u16>::deserialize(d).map(|version| version.map(TlsVersion))
+    }
+}
@@ -6,6 +6,8 @@ pub mod parser;
 pub use self::handshake::*;
 
 use itertools::Itertools;
+#[cfg(feature = "serde")]
+use serde::Deserialize;
 use serde::Serialize;
 use tls_parser::{TlsCipherSuite, TlsState};
 
@@ -17,6 +19,7 @@ const GREASE_TABLE: &[u16] = &[
 
 /// Parsed TLS handshake contents.
 #[derive(Debug, Default, Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct Tls {
     /// ClientHello message.
     pub client_hello: Option<ClientHello>,
@@ -180,6 +183,15 @@ impl Tls {
         }
     }
 
+    /// Returns the IANA identifier of the cipher suite chosen by the server, or `0` if no
+    /// ServerHello was observed in the handshake.
+    pub fn cipher_id(&self) -> u16 {
+        match &self.server_hello {
+            Some(server_hello) => server_hello.cipher_suite.0,
+            None => 0,
+        }
+    }
+
     /// Returns the cipher suite chosen by the server, or `None` if no ServerHello was observed in
     /// the handshake.
     pub fn cipher_suite(&self) -> Option<&'static TlsCipherSuite> {
@@ -21,18 +21,52 @@ use crate::protocols::stream::{
 
 use tls_parser::*;
 
+/// Session fields available once the ClientHello is parsed.
+const CLIENT_HELLO_FIELDS: [&str; 10] = [
+    "client_version",
+    "client_random",
+    "client_ciphers",
+    "client_compression_algs",
+    "client_alpn_protocols",
+    "client_signature_algs",
+    "client_extensions",
+    "sni",
+    "ja3_str",
+    "ja3_hash",
+];
+
 /// Parses a single TLS handshake per connection.
 #[derive(Debug)]
 pub struct TlsParser {
     sessions: Vec<Tls>,
+    /// Whether the handshake is done once the ClientHello is parsed.
+    client_hello_only: bool,
 }
 
-impl TlsParser {}
+impl TlsParser {
+    /// Returns a parser that only parses the handshake up to the session `fields`. If these are
+    /// all in the ClientHello (e.g., the SNI), the rest of the handshake is not parsed.
+    pub(crate) fn with_fields(fields: &[&str]) -> Self {
+        TlsParser {
+            client_hello_only: fields.iter().all(|f| CLIENT_HELLO_FIELDS.contains(f)),
+            ..TlsParser::default()
+        }
+    }
+
+    /// Returns a new parser that parses the same fields.
+    pub(crate) fn reset_new(&self) -> Self {
+        TlsParser {
+            client_hello_only: self.client_hello_only,
+            ..TlsParser::default()
+        }
+    }
+}
 
 impl Default for TlsParser {
     fn default() -> Self {
         TlsParser {
             sessions: vec![Tls::new()],
+            client_hello_only: false,
         }
     }
 }
@@ -47,7 +81,14 @@ impl ConnParsable for TlsParser {
         }
 
         if let Ok(data) = (pdu.mbuf_ref()).get_data_slice(offset, length) {
-            self.sessions[0].parse_tcp_level(data, pdu.dir)
+            let result = self.sessions[0].parse_tcp_level(data, pdu.dir);
+            if self.client_hello_only
+                && self.sessions[0].client_hello.is_some()
+                && matches!(result, ParseResult::Continue(_))
+            {
+                return ParseResult::Done(0);
+            }
+            result
         } else {
             log::warn!("Malformed packet");
             ParseResult::Skipped
@@ -0,0 +1,190 @@
+//! gRPC control plane.
+//!
+//! With a [ControlConfig](crate::config::ControlConfig), the runtime serves the
+//! `retina.control.v1.Control` service (see `proto/control.proto`) from a dedicated thread, so that
+//! an orchestrator can manage a fleet of instances: read the runtime status and counters, toggle
+//! [subscription groups](crate::subscription::group), and update the members of [filter
+//! sets](crate::filter::sets). Requests only read shared counters or flip shared flags, and never
+//! block the packet processing cores.
+
+use crate::config::ControlConfig;
+use crate::filter::sets;
+use crate::lcore::metrics::METRICS;
+use crate::lcore::CoreId;
+use crate::subscription::group;
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::Result;
+use tonic::transport::Server;
+use tonic::{Request, Response};
+
+mod proto {
+    tonic::include_proto!("retina.control.v1");
+}
+
+use proto::control_server::{Control, ControlServer};
+use proto::update_set_request::Operation;
+
+/// State of the runtime exposed by the control plane.
+pub(crate) struct ControlService {
+    pub(crate) is_running: Arc<AtomicBool>,
+    pub(crate) mode: &'static str,
+    pub(crate) filter: String,
+    pub(crate) cores: Vec<CoreId>,
+    pub(crate) allow_shutdown: bool,
+    pub(crate) start: Instant,
+}
+
+/// Starts serving the control plane on `config.address`.
+pub(crate) fn start(config: &ControlConfig, service: ControlService) -> Result<()> {
+    let address: SocketAddr = config.address.parse()?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    std::thread::Builder::new()
+        .name("retina-control".to_string())
+        .spawn(move || {
+            let server = Server::builder()
+                .add_service(ControlServer::new(service))
+                .serve(address);
+            if let Err(error) = runtime.block_on(server) {
+                log::error!("Control plane stopped: {}", error);
+            }
+        })?;
+    log::info!("Serving control plane on {}", address);
+    Ok(())
+}
+
+fn filter_set(name: String, members: Vec<String>) -> proto::FilterSet {
+    proto::FilterSet {
+        name,
+        len: members.len() as u64,
+        members,
+    }
+}
+
+#[tonic::async_trait]
+impl Control for ControlService {
+    async fn get_status(
+        &self,
+        _: Request<proto::GetStatusRequest>,
+    ) -> Result<Response<proto::Status>, tonic::Status> {
+        Ok(Response::new(proto::Status {
+            running: self.is_running.load(Ordering::Relaxed),
+            mode: self.mode.to_string(),
+            filter: self.filter.clone(),
+            cores: self.cores.iter().map(CoreId::raw).collect(),
+            uptime: self.start.elapsed().as_secs_f64(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }))
+    }
+
+    async fn get_stats(
+        &self,
+        _: Request<proto::GetStatsRequest>,
+    ) -> Result<Response<proto::Stats>, tonic::Status> {
+        let cores = self
+            .cores
+            .iter()
+            .map(|core_id| proto::CoreCounters {
+                core_id: core_id.raw(),
+                counters: METRICS.counters(&[*core_id]).into_iter().collect(),
+            })
+            .collect();
+        Ok(Response::new(proto::Stats {
+            counters: METRICS.counters(&self.cores).into_iter().collect(),
+            cores,
+            deliveries: METRICS
+                .deliveries()
+                .into_iter()
+                .map(|(callback, count)| (callback.to_string(), count))
+                .collect(),
+        }))
+    }
+
+    async fn list_groups(
+        &self,
+        _: Request<proto::ListGroupsRequest>,
+    ) -> Result<Response<proto::ListGroupsResponse>, tonic::Status> {
+        let groups = group::groups()
+            .into_iter()
+            .map(|(name, enabled)| proto::Group { name, enabled })
+            .collect();
+        Ok(Response::new(proto::ListGroupsResponse { groups }))
+    }
+
+    async fn set_group_enabled(
+        &self,
+        request: Request<proto::SetGroupEnabledRequest>,
+    ) -> Result<Response<proto::Group>, tonic::Status> {
+        let request = request.into_inner();
+        group::set_enabled(&request.name, request.enabled)
+            .map_err(|error| tonic::Status::not_found(error.to_string()))?;
+        Ok(Response::new(proto::Group {
+            name: request.name,
+            enabled: request.enabled,
+        }))
+    }
+
+    async fn list_sets(
+        &self,
+        _: Request<proto::ListSetsRequest>,
+    ) -> Result<Response<proto::ListSetsResponse>, tonic::Status> {
+        let sets = sets::sets()
+            .into_iter()
+            .map(|(name, len)| proto::FilterSet {
+                name,
+                len: len as u64,
+                members: vec![],
+            })
+            .collect();
+        Ok(Response::new(proto::ListSetsResponse { sets }))
+    }
+
+    async fn get_set(
+        &self,
+        request: Request<proto::GetSetRequest>,
+    ) -> Result<Response<proto::FilterSet>, tonic::Status> {
+        let name = request.into_inner().name;
+        match sets::members(&name) {
+            Some(members) => Ok(Response::new(filter_set(name, members))),
+            None => Err(tonic::Status::not_found(format!(
+                "Unknown filter set: {}",
+                name
+            ))),
+        }
+    }
+
+    async fn update_set(
+        &self,
+        request: Request<proto::UpdateSetRequest>,
+    ) -> Result<Response<proto::FilterSet>, tonic::Status> {
+        let request = request.into_inner();
+        let updated = match request.operation() {
+            Operation::Insert => sets::insert(&request.name, &request.members[..]),
+            Operation::Remove => sets::remove(&request.name, &request.members[..]),
+            Operation::Replace => sets::replace(&request.name, &request.members[..]),
+        };
+        updated.map_err(|error| tonic::Status::not_found(error.to_string()))?;
+        let members = sets::members(&request.name).unwrap_or_default();
+        Ok(Response::new(filter_set(request.name, members)))
+    }
+
+    async fn shutdown(
+        &self,
+        _: Request<proto::ShutdownRequest>,
+    ) -> Result<Response<proto::ShutdownResponse>, tonic::Status> {
+        if !self.allow_shutdown {
+            return Err(tonic::Status::permission_denied(
+                "Shutdown is not allowed by the control configuration",
+            ));
+        }
+        log::info!("Shutdown requested by the control plane");
+        self.is_running.store(false, Ordering::Relaxed);
+        Ok(Response::new(proto::ShutdownResponse {}))
+    }
+}
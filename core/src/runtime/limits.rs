@@ -0,0 +1,80 @@
+use crate::config::RunConfig;
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Run limits shared by all packet processing cores.
+///
+/// Stops the runtime by clearing `is_running` once the duration, packet, or connection limit is
+/// reached.
+#[derive(Debug)]
+pub(crate) struct RunLimits {
+    is_running: Arc<AtomicBool>,
+    start: Instant,
+    duration: Option<Duration>,
+    max_packets: Option<u64>,
+    max_connections: Option<u64>,
+    nb_pkts: AtomicU64,
+    nb_conns: AtomicU64,
+}
+
+impl RunLimits {
+    pub(crate) fn new(config: Option<&RunConfig>, is_running: Arc<AtomicBool>) -> Self {
+        RunLimits {
+            is_running,
+            start: Instant::now(),
+            duration: config.and_then(|c| c.duration).map(Duration::from_secs),
+            max_packets: config.and_then(|c| c.max_packets),
+            max_connections: config.and_then(|c| c.max_connections),
+            nb_pkts: AtomicU64::new(0),
+            nb_conns: AtomicU64::new(0),
+        }
+    }
+
+    /// Records `nb` received packets, and returns how many of them are within the packet limit.
+    #[inline]
+    pub(crate) fn admit_packets(&self, nb: usize) -> usize {
+        let max = match self.max_packets {
+            Some(max) => max,
+            None => return nb,
+        };
+        let prev = self.nb_pkts.fetch_add(nb as u64, Ordering::Relaxed);
+        if prev + nb as u64 >= max {
+            self.stop("packet");
+        }
+        max.saturating_sub(prev).min(nb as u64) as usize
+    }
+
+    /// Records `nb` newly tracked connections.
+    #[inline]
+    pub(crate) fn add_connections(&self, nb: u64) {
+        let max = match self.max_connections {
+            Some(max) => max,
+            None => return,
+        };
+        if nb == 0 {
+            return;
+        }
+        let prev = self.nb_conns.fetch_add(nb, Ordering::Relaxed);
+        if prev + nb >= max {
+            self.stop("connection");
+        }
+    }
+
+    /// Stops the runtime if the run duration has elapsed.
+    #[inline]
+    pub(crate) fn check_duration(&self) {
+        if let Some(duration) = self.duration {
+            if self.start.elapsed() >= duration {
+                self.stop("duration");
+            }
+        }
+    }
+
+    fn stop(&self, limit: &str) {
+        if self.is_running.swap(false, Ordering::Relaxed) {
+            log::info!("Reached {} limit, stopping...", limit);
+        }
+    }
+}
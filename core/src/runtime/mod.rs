@@ -3,23 +3,39 @@
 //! The runtime initializes the DPDK environment abstraction layer, creates memory pools, launches
 //! the packet processing cores, and manages logging and display output.
 
+#[cfg(feature = "grpc")]
+mod control;
+mod limits;
 mod offline;
 mod online;
+mod reload;
+pub(crate) use self::limits::RunLimits;
 use self::offline::*;
 use self::online::*;
+pub(crate) use self::reload::LiveConfig;
+use self::reload::Reloader;
 
 use crate::config::*;
 use crate::dpdk;
 use crate::filter::FilterFactory;
-use crate::lcore::SocketId;
-use crate::memory::mempool::Mempool;
+use crate::lcore::metrics::{RuntimeStats, StatsReporter};
+use crate::lcore::transmit::Transmitter;
+use crate::lcore::{CoreId, SocketId};
+use crate::memory::mempool::{self, MbufDemand, Mempool};
+use crate::port::events::{EventCallback, RuntimeEvent};
 use crate::subscription::*;
+use crate::utils::sink::Sink;
 
 use std::collections::BTreeMap;
 use std::ffi::CString;
-use std::sync::Arc;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::{bail, Result};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
 
 /// The Retina runtime.
 ///
@@ -33,6 +49,12 @@ where
     mempools: BTreeMap<SocketId, Mempool>,
     online: Option<OnlineRuntime<S>>,
     offline: Option<OfflineRuntime<S>>,
+    is_running: Arc<AtomicBool>,
+    rx_cores: Vec<CoreId>,
+    stats: Option<StatsReporter>,
+    on_event: Option<EventCallback>,
+    sinks: Vec<&'static Sink>,
+    reloader: Arc<Mutex<Reloader>>,
     #[cfg(feature = "timing")]
     subscription: Arc<Subscription<S>>,
 }
@@ -52,12 +74,54 @@ where
     /// # Example
     ///
     /// let mut runtime = Runtime::new(config, filter, callback)?;
-    pub fn new(config: RuntimeConfig, factory: fn() -> FilterFactory<S::Tracked>) -> Result<Self> {
-        let factory = factory();
+    pub fn new(
+        mut config: RuntimeConfig,
+        factory: fn() -> FilterFactory<S::Tracked>,
+    ) -> Result<Self> {
+        // Configurations that were not read from a file have not been checked yet
+        if let Err(error) = config.assign_cores() {
+            bail!("Invalid core assignment: {}", error);
+        }
+        let mut factory = factory();
+        // Sampled-out connections are only discarded by the connection table
+        if factory.bypass_conntrack && config.conntrack.sampling.is_some() {
+            log::warn!("Flow sampling is configured, not bypassing connection tracking.");
+            factory.bypass_conntrack = false;
+        }
         let filter_str = factory.filter_str.clone();
+        crate::subscription::group::register(&factory.groups);
+        crate::filter::sets::register(&factory.sets);
+        for (name, path) in config.filter_sets.iter() {
+            crate::filter::sets::load(name, path)?;
+        }
         let subscription = Arc::new(Subscription::new(factory));
 
         println!("Initializing Retina runtime...");
+        // Set up signal handler: SIGHUP reloads the configuration, other signals stop the runtime
+        let is_running = Arc::new(AtomicBool::new(true));
+        let live = Arc::new(LiveConfig::new(&config));
+        let reloader = Arc::new(Mutex::new(Reloader::new(&config, Arc::clone(&live))));
+        let mut signals =
+            Signals::new([SIGINT, SIGTERM, SIGHUP]).expect("Error setting signal handler");
+        let r = Arc::clone(&is_running);
+        let signal_reloader = Arc::clone(&reloader);
+        std::thread::spawn(move || {
+            for signal in signals.forever() {
+                if signal == SIGHUP {
+                    signal_reloader.lock().unwrap().reload();
+                } else {
+                    r.store(false, Ordering::Relaxed);
+                }
+            }
+        });
+        #[cfg(feature = "async")]
+        crate::subscription::async_bridge::init(&config.async_callbacks)?;
+        crate::subscription::worker_pool::init(&config.callbacks)?;
+        let limits = Arc::new(RunLimits::new(
+            config.runtime.as_ref(),
+            Arc::clone(&is_running),
+        ));
+
         log::info!("Initializing EAL...");
         dpdk::load_drivers();
         {
@@ -78,27 +142,65 @@ where
             }
         }
 
+        // The EAL may not run all cores, e.g., with a coremask or supplementary arguments
+        let placement = config.core_placement();
+        if let Some(core_id) = placement
+            .keys()
+            .find(|c| unsafe { dpdk::rte_lcore_is_enabled(c.raw()) } == 0)
+        {
+            bail!(
+                "Core {} is assigned a role, but is not enabled by the EAL",
+                core_id
+            );
+        }
+        log::info!("Core placement:");
+        for (core_id, role) in placement.iter() {
+            log::info!(
+                "  core {} (socket {}): {}",
+                core_id,
+                core_id.socket_id(),
+                role
+            );
+        }
+
+        if config.telemetry {
+            crate::lcore::telemetry::register(config.get_all_rx_core_ids());
+        }
+        if config.profile {
+            crate::lcore::profile::enable();
+        }
+
+        if subscription.bypass_conntrack {
+            log::info!("Packet-level subscriptions only, bypassing connection tracking.");
+        }
+
         log::info!("Initializing Mempools...");
         let mut mempools = BTreeMap::new();
-        let socket_ids = config.get_all_socket_ids();
-        let mtu = if let Some(online) = &config.online {
-            online.mtu
-        } else if let Some(offline) = &config.offline {
-            offline.mtu
-        } else {
-            Mempool::default_mtu()
-        };
-        for socket_id in socket_ids {
-            log::debug!("Socket ID: {}", socket_id);
-            let mempool = Mempool::new(&config.mempool, socket_id, mtu)?;
-            mempools.insert(socket_id, mempool);
+        // Online mode allocates one mempool on the socket of each port instead (see
+        // `OnlineRuntime::new`)
+        if config.online.is_none() {
+            let mtu = if let Some(offline) = &config.offline {
+                offline.mtu
+            } else {
+                Mempool::default_mtu()
+            };
+            for socket_id in config.get_all_socket_ids() {
+                log::debug!("Socket ID: {}", socket_id);
+                let demand = MbufDemand {
+                    cores: 1,
+                    track_packets: subscription.track_packets,
+                    ..Default::default()
+                };
+                let mempool = Mempool::new(&config.mempool, socket_id, mtu, demand)?;
+                mempools.insert(socket_id, mempool);
+            }
         }
 
         let online = config.online.as_ref().map(|cfg| {
             log::info!("Initializing Online Runtime...");
             let online_opts = OnlineOptions {
                 online: cfg.clone(),
-                conntrack: config.conntrack.clone(),
+                live: Arc::clone(&live),
             };
             OnlineRuntime::new(
                 &config,
@@ -106,46 +208,274 @@ where
                 &mut mempools,
                 filter_str.clone(),
                 Arc::clone(&subscription),
+                Arc::clone(&is_running),
+                Arc::clone(&limits),
             )
         });
+        let online = online.transpose()?;
 
         let offline = config.offline.as_ref().map(|cfg| {
             log::info!("Initializing Offline Analysis...");
             let offline_opts = OfflineOptions {
                 offline: cfg.clone(),
-                conntrack: config.conntrack.clone(),
+                live: Arc::clone(&live),
             };
-            OfflineRuntime::new(offline_opts, &mempools, Arc::clone(&subscription))
+            OfflineRuntime::new(
+                offline_opts,
+                &mempools,
+                Arc::clone(&subscription),
+                Arc::clone(&is_running),
+                Arc::clone(&limits),
+            )
         });
 
+        mempool::log_budget(
+            mempools
+                .values()
+                .chain(online.iter().flat_map(|online| online.extra_mempools())),
+            &config.mempool,
+        );
+
+        if let Some(online) = &online {
+            reloader
+                .lock()
+                .unwrap()
+                .set_sampled_ports(online.sampled_ports());
+        }
+
+        if let Some(control) = &config.control {
+            #[cfg(feature = "grpc")]
+            control::start(
+                control,
+                control::ControlService {
+                    is_running: Arc::clone(&is_running),
+                    mode: if online.is_some() {
+                        "online"
+                    } else {
+                        "offline"
+                    },
+                    filter: filter_str.clone(),
+                    cores: config.get_all_rx_core_ids(),
+                    allow_shutdown: control.allow_shutdown,
+                    start: std::time::Instant::now(),
+                },
+            )?;
+            #[cfg(not(feature = "grpc"))]
+            log::warn!(
+                "Control plane on {} requires the `grpc` feature, not serving it.",
+                control.address
+            );
+        }
+
         log::info!("Runtime ready.");
         Ok(Runtime {
             mempools,
             online,
             offline,
+            is_running,
+            rx_cores: config.get_all_rx_core_ids(),
+            stats: None,
+            on_event: None,
+            sinks: vec![],
+            reloader,
             #[cfg(feature = "timing")]
             subscription,
         })
     }
 
+    /// Returns a handle that can be used to stop the runtime from another thread.
+    ///
+    /// # Example
+    ///
+    /// let handle = runtime.shutdown_handle();
+    /// std::thread::spawn(move || {
+    ///     std::thread::sleep(std::time::Duration::from_secs(60));
+    ///     handle.shutdown();
+    /// });
+    /// runtime.run();
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            is_running: Arc::clone(&self.is_running),
+        }
+    }
+
+    /// Requests that the runtime stop processing packets. Equivalent to `ctrl-c`.
+    ///
+    /// Connections that are still being tracked are drained before `run` returns, invoking
+    /// connection-level callbacks for all live connections.
+    pub fn shutdown(&self) {
+        self.is_running.store(false, Ordering::Relaxed);
+    }
+
+    /// Registers `callback` to be invoked every `interval` with aggregate runtime statistics
+    /// (per-core throughput, drops, and connection table size). The callback runs on the main
+    /// core, so it should return quickly. Replaces any previously registered callback.
+    ///
+    /// # Example
+    ///
+    /// runtime.on_stats(Duration::from_secs(10), |stats| {
+    ///     log::info!("{} pkts, {} conns", stats.rx_pkts(), stats.conn_table_size());
+    /// });
+    /// runtime.run();
+    pub fn on_stats<F>(&mut self, interval: Duration, callback: F)
+    where
+        F: FnMut(&RuntimeStats) + Send + 'static,
+    {
+        self.stats = Some(StatsReporter::new(
+            interval,
+            Box::new(callback),
+            self.rx_cores.clone(),
+        ));
+    }
+
+    /// Registers `callback` to be invoked when the link of a port goes down or comes back up, or
+    /// when a device is removed or attached while running (see [RuntimeEvent](crate::RuntimeEvent)).
+    /// Polling of a port is paused while its link is down and resumes once it is up again, so the
+    /// runtime keeps running on flapping links. The callback runs on the main core, so it should
+    /// return quickly. Events are only raised in online mode. Replaces any previously registered
+    /// callback.
+    ///
+    /// # Example
+    ///
+    /// runtime.on_event(|event| match event {
+    ///     RuntimeEvent::LinkDown { device } => log::warn!("Lost link on {}", device),
+    ///     _ => (),
+    /// });
+    /// runtime.run();
+    pub fn on_event<F>(&mut self, callback: F)
+    where
+        F: FnMut(&RuntimeEvent) + Send + 'static,
+    {
+        self.on_event = Some(Box::new(callback));
+    }
+
+    /// Registers `sink` to be merged into its output file after all cores have stopped.
+    ///
+    /// # Example
+    ///
+    /// lazy_static! {
+    ///     static ref SINK: Sink = Sink::new("out.jsonl");
+    /// }
+    /// runtime.add_sink(&SINK);
+    /// runtime.run();
+    pub fn add_sink(&mut self, sink: &'static Sink) {
+        self.sinks.push(sink);
+        self.reloader.lock().unwrap().add_sink(sink);
+    }
+
+    /// Reloads the configuration from `path` (typically the file the configuration was loaded
+    /// from) when the process receives SIGHUP. Connection tracking timeouts and limits, packet
+    /// capture output, the output files of sinks, the files of filter sets (which are read again
+    /// even if the configuration is unchanged), and the sampling rate of ports with a sink core are
+    /// applied to the running runtime; changes to other settings are logged and ignored until
+    /// restart. Without a configuration path, SIGHUP is ignored.
+    ///
+    /// # Example
+    ///
+    /// let config = load_config(&args.config);
+    /// let mut runtime = Runtime::new(config, filter)?;
+    /// runtime.reload_on_sighup(&args.config);
+    /// runtime.run();
+    pub fn reload_on_sighup<P: AsRef<Path>>(&mut self, path: P) {
+        self.reloader
+            .lock()
+            .unwrap()
+            .set_path(path.as_ref().to_path_buf());
+    }
+
+    /// Enables or disables the subscriptions tagged with group `name`, e.g., to toggle expensive
+    /// analyses without restarting. While a group is disabled, new connections are not tracked
+    /// for its subscriptions and nothing is delivered to them. Groups are enabled at startup.
+    /// Returns an error if no subscription is in group `name`.
+    ///
+    /// Groups can also be toggled while the runtime is running, from another thread or a callback,
+    /// with [set_enabled](crate::subscription::group::set_enabled).
+    ///
+    /// # Example
+    ///
+    /// runtime.set_group_enabled("tls-deep-inspect", false)?;
+    /// std::thread::spawn(|| {
+    ///     std::thread::sleep(std::time::Duration::from_secs(600));
+    ///     retina_core::subscription::group::set_enabled("tls-deep-inspect", true).unwrap();
+    /// });
+    /// runtime.run();
+    pub fn set_group_enabled(&self, name: &str, enabled: bool) -> Result<()> {
+        crate::subscription::group::set_enabled(name, enabled)
+    }
+
+    /// Returns a [Transmitter](crate::Transmitter) for sending packets on `device` from the
+    /// application, e.g., to replay test traffic through the same binary. `device` must be listed
+    /// in the `transmit` ports of the online configuration, and there is at most one transmitter
+    /// per port.
+    ///
+    /// # Example
+    ///
+    /// let transmitter = runtime.transmitter("0000:3b:00.1")?;
+    /// std::thread::spawn(move || transmitter.replay("./traces/test.pcap", Some(10_000.0)));
+    /// runtime.run();
+    pub fn transmitter(&mut self, device: &str) -> Result<Transmitter> {
+        let online = match &mut self.online {
+            Some(online) => online,
+            None => bail!("Packets can only be transmitted in online mode"),
+        };
+        match online.transmitters.remove(device) {
+            Some(transmitter) => Ok(transmitter),
+            None => bail!(
+                "No transmitter available for port {} (not a transmit port, or already taken)",
+                device
+            ),
+        }
+    }
+
     /// Run Retina for the duration specified in the configuration or until `ctrl-c` to terminate.
     ///
+    /// On termination, each core stops polling, delivers the data of all connections it is still
+    /// tracking, and exits. `run` returns once all cores have been joined.
+    ///
     /// # Example
     ///
     /// runtime.run();
     pub fn run(&mut self) {
         if let Some(online) = &mut self.online {
-            online.run();
+            online.run(self.stats.as_mut(), self.on_event.as_mut());
         } else if let Some(offline) = &self.offline {
-            offline.run();
+            offline.run(self.stats.as_mut());
         } else {
             log::error!("No runtime");
         }
+        crate::subscription::worker_pool::drain();
+        #[cfg(feature = "async")]
+        crate::subscription::async_bridge::drain();
         #[cfg(feature = "timing")]
         {
             self.subscription.timers.display_stats();
             self.subscription.timers.dump_stats();
         }
+        for sink in self.sinks.iter() {
+            let destination = sink.destination();
+            log::info!("Merging results into {}...", destination.display());
+            if let Err(error) = sink.merge() {
+                log::error!("Failed to merge {}: {}", destination.display(), error);
+            }
+        }
         log::info!("Done.");
     }
 }
+
+/// A handle to stop a running [Runtime](crate::Runtime) gracefully.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    is_running: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    /// Requests that the runtime stop processing packets. Equivalent to `ctrl-c`.
+    pub fn shutdown(&self) {
+        self.is_running.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if shutdown has been requested or the runtime has finished.
+    pub fn is_shutdown(&self) -> bool {
+        !self.is_running.load(Ordering::Relaxed)
+    }
+}
@@ -1,16 +1,27 @@
-use crate::config::{ConnTrackConfig, OfflineConfig};
+use super::limits::RunLimits;
+use super::reload::LiveConfig;
+use crate::config::OfflineConfig;
+use crate::conntrack::defrag::Defrag;
+use crate::conntrack::tunnel::Decap;
 use crate::conntrack::{ConnTracker, TrackerConfig};
 use crate::dpdk;
+use crate::lcore::metrics::{StatsReporter, METRICS};
+use crate::lcore::profile;
 use crate::lcore::{CoreId, SocketId};
 use crate::memory::mbuf::Mbuf;
 use crate::memory::mempool::Mempool;
+use crate::port::clock;
 use crate::subscription::*;
+use crate::utils::pcap::PcapWriter;
 
 use std::collections::BTreeMap;
 use std::ffi::CString;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use cpu_time::ProcessTime;
+use itertools::Itertools;
 use pcap::Capture;
 
 pub(crate) struct OfflineRuntime<S>
@@ -21,6 +32,8 @@ where
     pub(crate) subscription: Arc<Subscription<S>>,
     pub(crate) options: OfflineOptions,
     id: CoreId,
+    is_running: Arc<AtomicBool>,
+    limits: Arc<RunLimits>,
 }
 
 impl<S> OfflineRuntime<S>
@@ -31,6 +44,8 @@ where
         options: OfflineOptions,
         mempools: &BTreeMap<SocketId, Mempool>,
         subscription: Arc<Subscription<S>>,
+        is_running: Arc<AtomicBool>,
+        limits: Arc<RunLimits>,
     ) -> Self {
         let core_id = CoreId(unsafe { dpdk::rte_lcore_id() } as u32);
         let mempool_name = mempools
@@ -43,41 +58,138 @@ where
             subscription,
             options,
             id: core_id,
+            is_running,
+            limits,
         }
     }
 
-    pub(crate) fn run(&self) {
+    pub(crate) fn run(&self, mut stats: Option<&mut StatsReporter>) {
+        let pcaps = self
+            .options
+            .offline
+            .pcap_files()
+            .expect("Invalid offline configuration");
         log::info!(
             "Launched offline analysis. Processing pcap: {}",
-            self.options.offline.pcap,
+            pcaps.iter().map(|pcap| pcap.display()).format(", "),
         );
 
         let mut nb_pkts = 0;
         let mut nb_bytes = 0;
+        let mut nb_dropped = 0;
+        let mut nb_conns = 0;
+        let metrics = METRICS.core(&self.id);
 
-        let config = TrackerConfig::from(&self.options.conntrack);
+        let mut generation = self.options.live.generation();
+        let config = TrackerConfig::from(&self.options.live.conntrack());
         let registry = S::Tracked::parsers();
         log::debug!("{:#?}", registry);
-        let mut stream_table = ConnTracker::<S::Tracked>::new(config, registry, self.id);
+        let pcap_writer = PcapWriter::new(&self.options.live.pcap_writer(), self.id);
+        let mut stream_table =
+            ConnTracker::<S::Tracked>::new(config, registry, self.id, pcap_writer);
+        let mut defrag = Defrag::new(&self.options.live.conntrack().defrag);
+        let mut decap = Decap::new(&self.options.live.conntrack());
 
         let mempool_raw = self.get_mempool_raw();
-        let pcap = self.options.offline.pcap.as_str();
-        let mut cap = Capture::from_file(pcap).expect("Error opening pcap. Aborting.");
+        let mut pacer = Pacer::new(self.options.offline.replay_speed);
+        profile::start(&self.id);
         let start = ProcessTime::try_now().expect("Getting process time failed");
-        while let Ok(frame) = cap.next() {
-            if frame.header.len as usize > self.options.offline.mtu {
-                continue;
+        if let Some(stats) = stats.as_mut() {
+            stats.start();
+        }
+        // Connections are tracked across files, as if the files were a single capture
+        'pcaps: for pcap in pcaps.iter() {
+            if pcaps.len() > 1 {
+                log::info!("Processing pcap: {}", pcap.display());
             }
-            let mbuf = Mbuf::from_bytes(frame.data, mempool_raw)
-                .expect("Unable to allocate mbuf. Try increasing mempool size.");
-            nb_pkts += 1;
-            nb_bytes += mbuf.data_len() as u64;
-
-            /* Apply the packet filter to get actions */
-            let actions = self.subscription.continue_packet(&mbuf, &self.id);
-            if !actions.drop() {
-                self.subscription
-                    .process_packet(mbuf, &mut stream_table, actions);
+            let mut cap = Capture::from_file(pcap).expect("Error opening pcap. Aborting.");
+            while let Ok(frame) = cap.next() {
+                if !self.is_running.load(Ordering::Relaxed) || self.limits.admit_packets(1) == 0 {
+                    log::info!("Stopping offline analysis early.");
+                    break 'pcaps;
+                }
+                if frame.header.len as usize > self.options.offline.mtu {
+                    continue;
+                }
+                let ts = Duration::new(
+                    frame.header.ts.tv_sec as u64,
+                    frame.header.ts.tv_usec as u32 * 1000,
+                );
+                let deadline = pacer.deadline(ts);
+                if pacer.is_paced() {
+                    while let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                        if !self.is_running.load(Ordering::Relaxed) {
+                            break 'pcaps;
+                        }
+                        std::thread::sleep(wait.min(PACING_SLEEP));
+                        stream_table.check_inactive(&self.subscription);
+                    }
+                    stream_table.check_inactive(&self.subscription);
+                }
+                let mut mbuf = Mbuf::from_bytes(frame.data, mempool_raw)
+                    .expect("Unable to allocate mbuf. Try increasing mempool size.");
+                mbuf.set_capture_timestamp(ts);
+                nb_pkts += 1;
+                nb_bytes += mbuf.data_len() as u64;
+                let released = match defrag.as_mut() {
+                    Some(defrag) => defrag.push(mbuf),
+                    None => Defrag::pass(mbuf),
+                };
+                for mbuf in released {
+                    let mbuf = match decap.as_mut() {
+                        Some(decap) => decap.push(mbuf),
+                        None => mbuf,
+                    };
+
+                    /* Apply the packet filter to get actions */
+                    let actions = self.subscription.continue_packet(&mbuf, &self.id);
+                    if !actions.drop() {
+                        self.subscription
+                            .process_packet(mbuf, &mut stream_table, actions);
+                    } else {
+                        nb_dropped += 1;
+                    }
+                }
+                if self.options.live.generation() != generation {
+                    generation = self.options.live.generation();
+                    stream_table.reconfigure(
+                        TrackerConfig::from(&self.options.live.conntrack()),
+                        &self.options.live.pcap_writer(),
+                    );
+                }
+                if let Some(metrics) = metrics {
+                    metrics.rx_pkts.store(nb_pkts, Ordering::Relaxed);
+                    metrics.rx_bytes.store(nb_bytes, Ordering::Relaxed);
+                    metrics.dropped_pkts.store(nb_dropped, Ordering::Relaxed);
+                    metrics
+                        .conn_table_size
+                        .store(stream_table.size() as u64, Ordering::Relaxed);
+                    metrics
+                        .ooo_overflows
+                        .store(stream_table.nb_ooo_overflows(), Ordering::Relaxed);
+                    metrics
+                        .ooo_bytes
+                        .store(stream_table.ooo_bytes() as u64, Ordering::Relaxed);
+                    metrics
+                        .evicted_conns
+                        .store(stream_table.nb_evicted(), Ordering::Relaxed);
+                    metrics
+                        .unsampled_pkts
+                        .store(stream_table.nb_unsampled_pkts(), Ordering::Relaxed);
+                    metrics
+                        .unsampled_bytes
+                        .store(stream_table.unsampled_bytes(), Ordering::Relaxed);
+                    metrics
+                        .bypassed_pkts
+                        .store(stream_table.nb_bypassed_pkts(), Ordering::Relaxed);
+                }
+                if let Some(stats) = stats.as_mut() {
+                    stats.poll(|| None);
+                }
+                self.limits
+                    .add_connections(stream_table.nb_created() - nb_conns);
+                nb_conns = stream_table.nb_created();
+                self.limits.check_duration();
             }
         }
 
@@ -94,9 +206,54 @@ where
     }
 }
 
+/// Maximum time to sleep between checks for inactive connections, while waiting to process the
+/// next packet.
+const PACING_SLEEP: Duration = Duration::from_millis(10);
+
+/// Maps capture timestamps to host time, and paces packet processing accordingly if a replay
+/// speed is set.
+struct Pacer {
+    /// Replay speed, if packets are paced.
+    speed: Option<f64>,
+    /// Time the first packet was processed, and its capture timestamp.
+    origin: Option<(Instant, Duration)>,
+}
+
+impl Pacer {
+    fn new(speed: Option<f64>) -> Self {
+        Pacer {
+            speed,
+            origin: None,
+        }
+    }
+
+    /// Returns whether packets are processed at the pace of their capture timestamps.
+    fn is_paced(&self) -> bool {
+        self.speed.is_some()
+    }
+
+    /// Returns the time at which to process a packet captured at `ts`, if paced. Packets with
+    /// timestamps earlier than the first packet are processed immediately.
+    ///
+    /// The mbuf timestamps of the packets map their capture timestamps to host time the same way
+    /// (at the original pace if packets are not paced), so that paced packets are timestamped
+    /// with the time they are processed at.
+    fn deadline(&mut self, ts: Duration) -> Instant {
+        let speed = self.speed.unwrap_or(1.0);
+        let (start, first_ts) = *self.origin.get_or_insert_with(|| {
+            let start = Instant::now();
+            if !clock::init_capture(start, ts, speed) {
+                log::warn!("Unable to timestamp packets: time is measured on processing");
+            }
+            (start, ts)
+        });
+        start + ts.saturating_sub(first_ts).div_f64(speed)
+    }
+}
+
 /// Read-only runtime options for the offline core
 #[derive(Debug)]
 pub(crate) struct OfflineOptions {
     pub(crate) offline: OfflineConfig,
-    pub(crate) conntrack: ConnTrackConfig,
+    pub(crate) live: Arc<LiveConfig>,
 }
@@ -1,10 +1,18 @@
-use crate::config::{ConnTrackConfig, OnlineConfig, RuntimeConfig};
+use super::limits::RunLimits;
+use super::reload::{LiveConfig, SampledPort};
+use crate::config::{OnlineConfig, RuntimeConfig};
 use crate::dpdk;
 use crate::filter::Filter;
+use crate::lcore::dispatch::{DispatchRole, Dispatcher};
+use crate::lcore::metrics::StatsReporter;
+use crate::lcore::mirror::Mirror;
 use crate::lcore::monitor::Monitor;
+use crate::lcore::ring::Ring;
 use crate::lcore::rx_core::RxCore;
+use crate::lcore::transmit::{Transmitter, TxPort};
 use crate::lcore::{CoreId, SocketId};
-use crate::memory::mempool::Mempool;
+use crate::memory::mempool::{MbufDemand, Mempool};
+use crate::port::events::EventCallback;
 use crate::port::*;
 use crate::subscription::*;
 
@@ -14,6 +22,8 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
 
+use anyhow::Result;
+
 pub(crate) struct OnlineRuntime<S>
 where
     S: Subscribable,
@@ -23,6 +33,13 @@ where
     monitor: Monitor,
     filter: Filter,
     options: OnlineOptions,
+    /// Set while the ports are started, which packets can only be transmitted in.
+    started: Arc<AtomicBool>,
+    pub(crate) transmitters: BTreeMap<String, Transmitter>,
+    /// Full-size mbufs that frames received in multiple segments are copied into, per socket.
+    jumbo_mempools: BTreeMap<SocketId, Mempool>,
+    /// Mbufs that tracked packets are copied into, per socket.
+    tracked_mempools: BTreeMap<SocketId, Mempool>,
 }
 
 impl<S> OnlineRuntime<S>
@@ -35,42 +52,113 @@ where
         mempools: &mut BTreeMap<SocketId, Mempool>,
         filter_str: String,
         subscription: Arc<Subscription<S>>,
-    ) -> Self {
+        is_running: Arc<AtomicBool>,
+        limits: Arc<RunLimits>,
+    ) -> Result<Self> {
         let hw_filter = Filter::new(&filter_str).expect("Failed to parse collapsed filter");
-        // Set up signal handler
-        let is_running = Arc::new(AtomicBool::new(true));
-        let r = Arc::clone(&is_running);
-        ctrlc::set_handler(move || {
-            r.store(false, Ordering::Relaxed);
-        })
-        .expect("Error setting Ctrl-C handler");
 
         log::info!("Initializing Ports...");
         let mut ports: BTreeMap<PortId, Port> = BTreeMap::new();
-        for port_map in options.online.ports.iter() {
-            let port = Port::new(port_map);
-            let socket_id = port.id.socket_id();
+        let new_ports: Vec<Port> = options
+            .online
+            .ports
+            .iter()
+            .map(|port_map| {
+                let tx_cores = options.online.tx_cores(&port_map.device);
+                let transmit = options.online.transmit.contains(&port_map.device);
+                Port::new(port_map, options.online.numa, &tx_cores, transmit)
+            })
+            .collect();
+        let rss_offloads: Vec<u64> = new_ports
+            .iter()
+            .map(|port| {
+                // Linked ports must hash the same fields so that both directions of a connection
+                // are steered to the same core
+                let mut rss_offloads = port.rss_offloads();
+                if let Some(linked) = options.online.linked_device(&port.device) {
+                    log::info!("Port {} linked with {}", port.device, linked);
+                    if let Some(linked_port) = new_ports.iter().find(|p| p.device == linked) {
+                        rss_offloads &= linked_port.rss_offloads();
+                    }
+                }
+                rss_offloads
+            })
+            .collect();
+        // Tracked packets are only held in receive mbufs if they are not copied to their own pool
+        let track_copies = subscription.track_packets && config.mempool.tracked.is_some();
+        let demands = Self::mbuf_demands(
+            &new_ports,
+            &options.online,
+            subscription.track_packets && !track_copies,
+        );
+        for (port, rss_offloads) in new_ports.into_iter().zip(rss_offloads) {
+            // Allocate packet buffers on the port's socket
+            let socket_id = port.socket_id;
             mempools.entry(socket_id).or_insert_with(|| {
-                // Create a local mempool if user is not polling the port
-                // from the same socket.
+                log::debug!("Socket ID: {}", socket_id);
                 let mtu = if let Some(online) = &config.online {
                     online.mtu
                 } else {
                     Mempool::default_mtu()
                 };
-                Mempool::new(&config.mempool, socket_id, mtu)
-                    .expect("Unable to initialize local mempool")
+                Mempool::new(&config.mempool, socket_id, mtu, demands[&socket_id])
+                    .unwrap_or_else(|error| panic!("Unable to initialize local mempool: {}", error))
             });
             port.init(
                 mempools,
                 options.online.nb_rxd,
+                options.online.nb_txd,
                 options.online.mtu,
                 options.online.promiscuous,
+                rss_offloads,
+                options.online.hardware_timestamps,
+                options.online.rx_interrupts(),
             )
             .expect("Failed to initialize port.");
             ports.insert(port.id, port);
         }
 
+        // Frames larger than an mbuf are copied into a full-size mbuf on the processing core's socket
+        let mut jumbo_mempools = BTreeMap::new();
+        let segmented = mtu_to_max_frame_len(options.online.mtu as u32) as usize
+            > mempools
+                .values()
+                .map(|mempool| mempool.data_room())
+                .min()
+                .unwrap_or(usize::MAX);
+        let mut core_ids: Vec<u32> = options
+            .online
+            .ports
+            .iter()
+            .flat_map(|port| port.cores.iter().copied())
+            .collect();
+        if let Some(dispatch) = &options.online.dispatch {
+            core_ids.extend(dispatch.cores.iter());
+        }
+        let core_sockets: Vec<SocketId> = core_ids
+            .into_iter()
+            .map(|c| CoreId(c).socket_id())
+            .collect();
+        if segmented {
+            for socket_id in core_sockets.iter() {
+                jumbo_mempools.entry(*socket_id).or_insert_with(|| {
+                    Mempool::new_jumbo(&config.mempool, *socket_id, options.online.mtu)
+                        .expect("Unable to initialize jumbo mempool")
+                });
+            }
+        }
+
+        // Tracked packets are copied on the processing core's socket
+        let mut tracked_mempools = BTreeMap::new();
+        if let Some(tracked) = config.mempool.tracked.as_ref().filter(|_| track_copies) {
+            for socket_id in core_sockets.iter() {
+                tracked_mempools.entry(*socket_id).or_insert_with(|| {
+                    Mempool::new_tracked(&config.mempool, tracked, *socket_id, options.online.mtu)
+                        .expect("Unable to initialize tracked packet mempool")
+                });
+            }
+        }
+
         log::info!("Initializing RX Cores...");
         let mut rx_cores: BTreeMap<CoreId, RxCore<S>> = BTreeMap::new();
         let mut core_map: BTreeMap<CoreId, Vec<RxQueue>> = BTreeMap::new();
@@ -79,29 +167,145 @@ where
                 core_map.entry(*core_id).or_default().push(*rxqueue);
             }
         }
+        // Packets received on a bridged port are transmitted on the other port of the bridge
+        let mut bridge_map: BTreeMap<CoreId, BTreeMap<PortId, TxQueue>> = BTreeMap::new();
+        let divert_port = options
+            .online
+            .divert
+            .as_ref()
+            .and_then(|device| ports.values().find(|p| &p.device == device));
+        for port in ports.values() {
+            let peer = options
+                .online
+                .bridged_device(&port.device)
+                .and_then(|device| ports.values().find(|p| p.device == device));
+            if let Some(peer) = peer {
+                log::info!("Port {} bridged with {}", port.device, peer.device);
+                for (core_id, txqueue) in peer.tx_queues.iter() {
+                    bridge_map
+                        .entry(*core_id)
+                        .or_default()
+                        .insert(port.id, *txqueue);
+                }
+            }
+        }
+        // Callbacks and the application transmit on the `transmit` ports with their own queues
+        let started = Arc::new(AtomicBool::new(false));
+        let tx_port = |port: &Port, txqueue: TxQueue| TxPort {
+            device: port.device.clone(),
+            txqueue,
+            mempool: mempools.get(&port.socket_id).unwrap().raw() as *const _ as *mut _,
+            started: Arc::clone(&started),
+        };
+        let mut transmit_map: BTreeMap<CoreId, Vec<TxPort>> = BTreeMap::new();
+        let mut transmitters = BTreeMap::new();
+        for port in ports.values() {
+            if !options.online.transmit.contains(&port.device) {
+                continue;
+            }
+            for (core_id, txqueue) in port.tx_queues.iter() {
+                transmit_map
+                    .entry(*core_id)
+                    .or_default()
+                    .push(tx_port(port, *txqueue));
+            }
+            if let Some(txqueue) = port.runtime_tx_queue {
+                let transmitter = Transmitter::new(tx_port(port, txqueue), Arc::clone(&is_running));
+                transmitters.insert(port.device.clone(), transmitter);
+            }
+        }
+        // Matched packets are copied to the mirror port with each core's own queue
+        let mirror_port = options.online.mirror.as_ref().and_then(|config| {
+            ports
+                .values()
+                .find(|p| p.device == config.device)
+                .map(|port| (port, config))
+        });
+        let mirror = |core_id: &CoreId| {
+            mirror_port.and_then(|(port, config)| {
+                port.tx_queues
+                    .get(core_id)
+                    .map(|txqueue| Mirror::new(tx_port(port, *txqueue), config))
+            })
+        };
+        let mut dispatch = DispatchRole::None;
+        if let Some(dispatch_config) = &options.online.dispatch {
+            log::info!("Initializing software dispatch...");
+            let mut rings = vec![];
+            for core_id in dispatch_config.cores.iter().map(|c| CoreId(*c)) {
+                let ring = Ring::new(
+                    &format!("dispatch_ring_{}", core_id),
+                    dispatch_config.ring_size,
+                    core_id.socket_id(),
+                    0,
+                )
+                .expect("Failed to create dispatch ring.");
+                let ring = Arc::new(ring);
+                let rx_core = RxCore::new(
+                    core_id,
+                    vec![],
+                    Arc::clone(&options.live),
+                    Arc::clone(&subscription),
+                    Arc::clone(&is_running),
+                    Arc::clone(&limits),
+                    DispatchRole::Worker(Arc::clone(&ring)),
+                    BTreeMap::new(),
+                    None,
+                    transmit_map.remove(&core_id).unwrap_or_default(),
+                    options.online.overload.clone(),
+                    mirror(&core_id),
+                    options.online.idle.clone(),
+                );
+                rx_cores.insert(core_id, rx_core);
+                rings.push(ring);
+            }
+            dispatch = DispatchRole::Dispatcher(Arc::new(Dispatcher::new(rings)));
+        }
         for (core_id, rxqueues) in core_map.into_iter() {
+            // Sink cores only drop traffic
+            let role = if rxqueues[0].ty == RxQueueType::Receive {
+                dispatch.clone()
+            } else {
+                DispatchRole::None
+            };
             let rx_core = RxCore::new(
                 core_id,
                 rxqueues,
-                options.conntrack.clone(),
+                Arc::clone(&options.live),
                 Arc::clone(&subscription),
                 Arc::clone(&is_running),
+                Arc::clone(&limits),
+                role,
+                bridge_map.remove(&core_id).unwrap_or_default(),
+                divert_port.and_then(|port| port.tx_queues.get(&core_id).copied()),
+                transmit_map.remove(&core_id).unwrap_or_default(),
+                options.online.overload.clone(),
+                mirror(&core_id),
+                options.online.idle.clone(),
             );
             rx_cores.insert(core_id, rx_core);
         }
 
-        let monitor = Monitor::new(config, &ports, Arc::clone(&is_running));
+        let monitor = Monitor::new(config, &ports, Arc::clone(&is_running))?;
 
-        OnlineRuntime {
+        Ok(OnlineRuntime {
             ports,
             rx_cores,
             monitor,
             filter: hw_filter,
             options,
-        }
+            started,
+            transmitters,
+            jumbo_mempools,
+            tracked_mempools,
+        })
     }
 
-    pub(crate) fn run(&mut self) {
+    pub(crate) fn run(
+        &mut self,
+        stats: Option<&mut StatsReporter>,
+        on_event: Option<&mut EventCallback>,
+    ) {
         self.start_ports();
 
         log::info!("Launching RX cores...");
@@ -123,18 +327,87 @@ where
         }
 
         // run main thread
-        self.run_main();
+        self.run_main(stats, on_event);
         unsafe { dpdk::rte_eal_mp_wait_lcore() };
 
         log::info!("Exiting loop...");
         self.stop_ports();
     }
 
-    fn run_main(&mut self) {
+    /// Returns the mempools created by the online runtime, in addition to the mempools of the
+    /// ports.
+    pub(crate) fn extra_mempools(&self) -> impl Iterator<Item = &Mempool> {
+        self.jumbo_mempools
+            .values()
+            .chain(self.tracked_mempools.values())
+    }
+
+    /// Returns the mbufs that may be held at the same time from the mempool of each socket.
+    fn mbuf_demands(
+        ports: &[Port],
+        online: &OnlineConfig,
+        track_packets: bool,
+    ) -> BTreeMap<SocketId, MbufDemand> {
+        let mut demands: BTreeMap<SocketId, MbufDemand> = BTreeMap::new();
+        let mut cores: BTreeMap<SocketId, Vec<CoreId>> = BTreeMap::new();
+        for port in ports.iter() {
+            let demand = demands.entry(port.socket_id).or_default();
+            demand.track_packets = track_packets;
+            demand.descriptors += online.nb_rxd * port.queue_map.len()
+                + online.nb_txd * (port.tx_queues.len() + port.runtime_tx_queue.iter().count());
+            cores
+                .entry(port.socket_id)
+                .or_default()
+                .extend(port.queue_map.values());
+        }
+        // With software dispatch, packets from any socket are processed by the workers
+        let workers = online.dispatch.as_ref().map(|dispatch| {
+            (
+                dispatch.cores.len(),
+                dispatch.cores.len() * dispatch.ring_size,
+            )
+        });
+        for (socket_id, demand) in demands.iter_mut() {
+            let socket_cores = cores.get_mut(socket_id).unwrap();
+            socket_cores.sort();
+            socket_cores.dedup();
+            demand.cores = socket_cores.len();
+            if let Some((nb_workers, ring_slots)) = workers {
+                demand.cores += nb_workers;
+                demand.ring_slots = ring_slots;
+            }
+        }
+        demands
+    }
+
+    /// Returns the ports with a sink core, whose sampling rate can be changed while running.
+    pub(crate) fn sampled_ports(&self) -> BTreeMap<String, SampledPort> {
+        self.ports
+            .values()
+            .filter(|port| {
+                port.queue_map
+                    .keys()
+                    .any(|rxqueue| rxqueue.ty == RxQueueType::Sink)
+            })
+            .map(|port| {
+                let sampled = SampledPort {
+                    id: port.id,
+                    rx_queues: port.rx_queue_ids(),
+                };
+                (port.device.clone(), sampled)
+            })
+            .collect()
+    }
+
+    fn run_main(
+        &mut self,
+        stats: Option<&mut StatsReporter>,
+        on_event: Option<&mut EventCallback>,
+    ) {
         let id = unsafe { dpdk::rte_lcore_id() };
         log::info!("Running main on Core {}", id);
         let start = Instant::now();
-        self.monitor.run();
+        self.monitor.run(stats, on_event);
         println!("Main done. Ran for {:?}", start.elapsed());
     }
 
@@ -143,7 +416,12 @@ where
         for port in self.ports.values() {
             port.start();
 
-            if self.options.online.hardware_assist {
+            if self.options.online.bridged_device(&port.device).is_some() {
+                log::info!(
+                    "Port {} is bridged, passing all traffic through device.",
+                    port.id
+                );
+            } else if self.options.online.hardware_assist {
                 log::info!("Applying hardware filters...");
                 let res = self.filter.set_hardware_filter(port);
                 match res {
@@ -156,10 +434,37 @@ where
                 log::info!("No hardware assist configured for port {}, passing all traffic through device.", port.id);
             }
         }
+        if self.options.online.hardware_timestamps {
+            self.init_clocks();
+        }
+        self.started.store(true, Ordering::Relaxed);
+    }
+
+    /// Calibrates the device clock of each port, so that hardware timestamps can be converted to
+    /// host time.
+    fn init_clocks(&self) {
+        log::info!("Calibrating device clocks...");
+        let mut clocks = BTreeMap::new();
+        for port in self.ports.values() {
+            match clock::NicClock::calibrate(port.id) {
+                Some(nic_clock) => {
+                    log::debug!("Port {} clock: {:?}", port.id, nic_clock);
+                    clocks.insert(port.id, nic_clock);
+                }
+                None => log::warn!(
+                    "Failed to read the device clock of Port {}, using software time.",
+                    port.id
+                ),
+            }
+        }
+        if !clock::init(clocks) {
+            log::warn!("Hardware timestamps are not available, using software time.");
+        }
     }
 
     fn stop_ports(&self) {
         log::info!("Stopping ports...");
+        self.started.store(false, Ordering::Relaxed);
         for port in self.ports.values() {
             port.stop();
         }
@@ -170,7 +475,7 @@ where
 #[derive(Debug)]
 pub(crate) struct OnlineOptions {
     pub(crate) online: OnlineConfig,
-    pub(crate) conntrack: ConnTrackConfig,
+    pub(crate) live: Arc<LiveConfig>,
 }
 
 extern "C" fn launch_rx<S>(arg: *mut c_void) -> i32
@@ -0,0 +1,354 @@
+//! Configuration reload on SIGHUP.
+//!
+//! Some settings can be changed while the runtime is running: connection tracking timeouts and
+//! limits, packet capture output, the output files of sinks, the files of filter sets, and the
+//! connection sampling rate of ports with a sink core. On SIGHUP, the configuration file is read
+//! again and changes to these settings are applied, and the files of filter sets are read again
+//! even if the configuration is unchanged. Changes to all other settings are reported and ignored
+//! until the application is restarted.
+
+use crate::config::{read_config, ConnTrackConfig, PcapWriterConfig, RuntimeConfig};
+use crate::filter::sets;
+use crate::port::{self, PortId, RxQueueId};
+use crate::utils::sink::Sink;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Settings that can be changed while the runtime is running, read by the packet processing cores
+/// when their generation changes.
+#[derive(Debug)]
+pub(crate) struct LiveConfig {
+    generation: AtomicU64,
+    conntrack: Mutex<ConnTrackConfig>,
+    pcap_writer: Mutex<PcapWriterConfig>,
+}
+
+impl LiveConfig {
+    pub(crate) fn new(config: &RuntimeConfig) -> Self {
+        LiveConfig {
+            generation: AtomicU64::new(0),
+            conntrack: Mutex::new(config.conntrack.clone()),
+            pcap_writer: Mutex::new(config.pcap_writer.clone()),
+        }
+    }
+
+    /// Returns the number of times the settings have been changed.
+    #[inline]
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn conntrack(&self) -> ConnTrackConfig {
+        self.conntrack.lock().unwrap().clone()
+    }
+
+    pub(crate) fn pcap_writer(&self) -> PcapWriterConfig {
+        self.pcap_writer.lock().unwrap().clone()
+    }
+
+    fn update(&self, config: &RuntimeConfig) {
+        *self.conntrack.lock().unwrap() = config.conntrack.clone();
+        *self.pcap_writer.lock().unwrap() = config.pcap_writer.clone();
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// Receive queues of a port with a sink core, whose redirection table sets the sampling rate.
+#[derive(Debug)]
+pub(crate) struct SampledPort {
+    pub(crate) id: PortId,
+    pub(crate) rx_queues: Vec<RxQueueId>,
+}
+
+/// Reloads the configuration file and applies the settings that can be changed live.
+#[derive(Debug)]
+pub(crate) struct Reloader {
+    path: Option<PathBuf>,
+    /// Configuration currently in effect.
+    current: RuntimeConfig,
+    live: Arc<LiveConfig>,
+    /// Ports with a sink core, by device.
+    sampled_ports: BTreeMap<String, SampledPort>,
+    /// Sinks registered with the runtime.
+    sinks: Vec<&'static Sink>,
+}
+
+impl Reloader {
+    pub(crate) fn new(config: &RuntimeConfig, live: Arc<LiveConfig>) -> Self {
+        Reloader {
+            path: None,
+            current: config.clone(),
+            live,
+            sampled_ports: BTreeMap::new(),
+            sinks: vec![],
+        }
+    }
+
+    pub(crate) fn set_path(&mut self, path: PathBuf) {
+        self.path = Some(path);
+    }
+
+    pub(crate) fn set_sampled_ports(&mut self, sampled_ports: BTreeMap<String, SampledPort>) {
+        self.sampled_ports = sampled_ports;
+    }
+
+    /// Registers `sink`, and sets its output file if the configuration overrides it.
+    pub(crate) fn add_sink(&mut self, sink: &'static Sink) {
+        let name = sink.path().to_string_lossy();
+        if let Err(error) = sink.redirect(self.current.sinks.get(name.as_ref()).map(PathBuf::from))
+        {
+            log::error!("Failed to set the output file of sink {}: {}", name, error);
+        }
+        self.sinks.push(sink);
+    }
+
+    /// Reads the configuration file, applies changes to live settings, and logs which changes were
+    /// applied and which require a restart.
+    pub(crate) fn reload(&mut self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => {
+                log::warn!("Received SIGHUP, but no configuration file to reload. Ignoring.");
+                return;
+            }
+        };
+        log::info!("Received SIGHUP, reloading {}...", path.display());
+        let new = match read_config(path) {
+            Ok(config) => config,
+            Err(error) => {
+                log::error!(
+                    "Failed to reload configuration, keeping current settings: {}",
+                    error
+                );
+                return;
+            }
+        };
+
+        let mut next = self.current.clone();
+        let mut applied = vec![];
+        let mut ignored = vec![];
+        for key in changed_keys(&self.current, &new) {
+            match self.apply(&key, &new, &mut next) {
+                Ok(true) => applied.push(key),
+                Ok(false) => ignored.push(key),
+                Err(error) => log::error!("Failed to apply {}: {}", key, error),
+            }
+        }
+        // The files may have changed even if the configuration did not
+        let mut reloaded = vec![];
+        for (name, path) in next.filter_sets.iter() {
+            match sets::load(name, path) {
+                Ok(()) => reloaded.push(name.as_str()),
+                Err(error) => log::error!("{}", error),
+            }
+        }
+        if !reloaded.is_empty() {
+            log::info!("Reloaded filter sets: {}", reloaded.join(", "));
+        }
+        if applied.is_empty() && ignored.is_empty() {
+            log::info!("Configuration unchanged.");
+            return;
+        }
+        if !applied.is_empty() {
+            self.live.update(&next);
+            log::info!("Applied changes to: {}", applied.join(", "));
+        }
+        if !ignored.is_empty() {
+            log::warn!(
+                "The following settings cannot be changed without restarting and were ignored: {}",
+                ignored.join(", ")
+            );
+        }
+        self.current = next;
+    }
+
+    /// Copies the setting `key` from `new` to `next` if it can be changed live. Returns whether it
+    /// was applied.
+    fn apply(
+        &self,
+        key: &str,
+        new: &RuntimeConfig,
+        next: &mut RuntimeConfig,
+    ) -> Result<bool, String> {
+        let path: Vec<&str> = key.split('.').collect();
+        match path.as_slice() {
+            ["conntrack", "max_connections"] => {
+                next.conntrack.max_connections = new.conntrack.max_connections
+            }
+            ["conntrack", "max_out_of_order"] => {
+                next.conntrack.max_out_of_order = new.conntrack.max_out_of_order
+            }
+            ["conntrack", "max_out_of_order_bytes"] => {
+                next.conntrack.max_out_of_order_bytes = new.conntrack.max_out_of_order_bytes
+            }
+            ["conntrack", "max_out_of_order_total_bytes"] => {
+                next.conntrack.max_out_of_order_total_bytes =
+                    new.conntrack.max_out_of_order_total_bytes
+            }
+            ["conntrack", "out_of_order_overflow"] => {
+                next.conntrack.out_of_order_overflow = new.conntrack.out_of_order_overflow
+            }
+            ["conntrack", "midstream"] => next.conntrack.midstream = new.conntrack.midstream,
+            ["conntrack", "eviction"] => next.conntrack.eviction = new.conntrack.eviction,
+            ["conntrack", "port_timeouts", ..] => {
+                next.conntrack.port_timeouts = new.conntrack.port_timeouts.clone()
+            }
+            ["conntrack", "tcp_overlap_policy"] => {
+                next.conntrack.tcp_overlap_policy = new.conntrack.tcp_overlap_policy
+            }
+            ["conntrack", "udp_inactivity_timeout"] => {
+                next.conntrack.udp_inactivity_timeout = new.conntrack.udp_inactivity_timeout
+            }
+            ["conntrack", "tcp_inactivity_timeout"] => {
+                next.conntrack.tcp_inactivity_timeout = new.conntrack.tcp_inactivity_timeout
+            }
+            ["conntrack", "tcp_establish_timeout"] => {
+                next.conntrack.tcp_establish_timeout = new.conntrack.tcp_establish_timeout
+            }
+            ["conntrack", "tcp_rst_timeout"] => {
+                next.conntrack.tcp_rst_timeout = new.conntrack.tcp_rst_timeout
+            }
+            ["conntrack", "tcp_half_closed_timeout"] => {
+                next.conntrack.tcp_half_closed_timeout = new.conntrack.tcp_half_closed_timeout
+            }
+            ["conntrack", "tcp_time_wait"] => {
+                next.conntrack.tcp_time_wait = new.conntrack.tcp_time_wait
+            }
+            ["conntrack", "skip_acked_gaps"] => {
+                next.conntrack.skip_acked_gaps = new.conntrack.skip_acked_gaps
+            }
+            ["conntrack", "sctp_inactivity_timeout"] => {
+                next.conntrack.sctp_inactivity_timeout = new.conntrack.sctp_inactivity_timeout
+            }
+            ["pcap_writer", ..] => next.pcap_writer = new.pcap_writer.clone(),
+            // Set names may contain dots
+            ["filter_sets", _, ..] => {
+                let name = &key["filter_sets.".len()..];
+                match new.filter_sets.get(name) {
+                    Some(path) => next.filter_sets.insert(name.to_string(), path.clone()),
+                    None => next.filter_sets.remove(name),
+                };
+            }
+            // Sink names are paths, which may contain dots
+            ["sinks", _, ..] => {
+                let name = &key["sinks.".len()..];
+                let destination = new.sinks.get(name);
+                for sink in self
+                    .sinks
+                    .iter()
+                    .filter(|sink| sink.path().to_string_lossy() == name)
+                {
+                    sink.redirect(destination.map(PathBuf::from))
+                        .map_err(|error| error.to_string())?;
+                }
+                match destination {
+                    Some(path) => next.sinks.insert(name.to_string(), path.clone()),
+                    None => next.sinks.remove(name),
+                };
+            }
+            ["online", "ports", idx, "sink", "nb_buckets"] => {
+                let idx: usize = idx.parse().map_err(|_| "invalid port index".to_string())?;
+                let (current, new) = match (next.online.as_mut(), new.online.as_ref()) {
+                    (Some(current), Some(new)) => (current, new),
+                    _ => return Ok(false),
+                };
+                let (port_map, new_port) = match (current.ports.get_mut(idx), new.ports.get(idx)) {
+                    (Some(port_map), Some(new_port)) if port_map.device == new_port.device => {
+                        (port_map, new_port)
+                    }
+                    _ => return Ok(false),
+                };
+                let (sink, nb_buckets) = match (&mut port_map.sink, &new_port.sink) {
+                    (Some(sink), Some(new_sink)) => (sink, new_sink.nb_buckets),
+                    _ => return Ok(false),
+                };
+                let sampled = match self.sampled_ports.get(&port_map.device) {
+                    Some(sampled) => sampled,
+                    None => return Ok(false),
+                };
+                port::set_nb_buckets(sampled.id, &sampled.rx_queues, nb_buckets)
+                    .map_err(|error| error.to_string())?;
+                sink.nb_buckets = nb_buckets;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// Returns the dotted paths (e.g., `conntrack.max_connections`, `online.ports.0.cores`) of the
+/// settings that differ between `old` and `new`.
+fn changed_keys(old: &RuntimeConfig, new: &RuntimeConfig) -> Vec<String> {
+    let mut old_values = BTreeMap::new();
+    let mut new_values = BTreeMap::new();
+    if let Ok(value) = toml::Value::try_from(old) {
+        flatten(String::new(), &value, &mut old_values);
+    }
+    if let Ok(value) = toml::Value::try_from(new) {
+        flatten(String::new(), &value, &mut new_values);
+    }
+    let mut keys: Vec<String> = old_values
+        .keys()
+        .chain(new_values.keys())
+        .filter(|key| old_values.get(*key) != new_values.get(*key))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+fn flatten(prefix: String, value: &toml::Value, out: &mut BTreeMap<String, toml::Value>) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        }
+    };
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter() {
+                flatten(join(key), value, out);
+            }
+        }
+        // Arrays of tables (e.g., ports) are compared entry by entry
+        toml::Value::Array(array) if array.iter().all(|v| v.is_table()) && !array.is_empty() => {
+            for (idx, value) in array.iter().enumerate() {
+                flatten(join(&idx.to_string()), value, out);
+            }
+        }
+        _ => {
+            out.insert(prefix, value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_reload_changed_keys() {
+        let old = RuntimeConfig::default();
+        let mut new = old.clone();
+        new.conntrack.tcp_inactivity_timeout = 1000;
+        new.pcap_writer.directory = "./out".to_string();
+        new.main_core = 1;
+        new.sinks
+            .insert("tls.jsonl".to_string(), "/data/tls.jsonl".to_string());
+        assert_eq!(
+            changed_keys(&old, &new),
+            vec![
+                "conntrack.tcp_inactivity_timeout",
+                "main_core",
+                "pcap_writer.directory",
+                "sinks.tls.jsonl"
+            ]
+        );
+        assert!(changed_keys(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+//! Delivery to async callbacks.
+//!
+//! Callbacks declared as `async fn` are not awaited on the packet processing cores. The generated
+//! delivery code clones the subscribed data, creates the callback's future, and hands it to the
+//! subscription's [AsyncCallback](AsyncCallback), which queues it on a bounded channel without
+//! blocking. A tokio runtime owned by the framework drains each channel, running at most
+//! `max_in_flight` callbacks of a subscription at a time (see
+//! [AsyncConfig](crate::config::AsyncConfig)). If the callbacks of a subscription fall behind, its
+//! queue fills up and further deliveries are dropped, so slow I/O never stalls the datapath.
+//!
+//! Requires the `async` feature.
+//!
+//! ## Example
+//! ```rust,ignore
+//! #[filter("http")]
+//! async fn post_http(http: HttpTransaction, five_tuple: FiveTuple) {
+//!     let client = reqwest::Client::new();
+//!     let _ = client
+//!         .post("http://collector:8080/http")
+//!         .body(format!("{} {}", five_tuple, http.uri()))
+//!         .send()
+//!         .await;
+//! }
+//! ```
+//!
+//! Async callbacks take their datatypes by value, except [FilterStr](../../retina_datatypes),
+//! which is passed as `FilterStr<'static>`. Packet datatypes cannot be subscribed to by async
+//! callbacks, as packet buffers cannot leave the core that received them. Async callbacks return
+//! `()`: the packets they are delivered have already been forwarded, so they cannot return a
+//! verdict.
+
+use crate::config::AsyncConfig;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tokio::sync::{mpsc, Semaphore};
+
+/// A boxed future returned by an async callback.
+pub type CallbackFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// The tokio runtime that runs async callbacks.
+struct Bridge {
+    runtime: tokio::runtime::Runtime,
+    config: AsyncConfig,
+    /// Number of deliveries queued or running, across all subscriptions.
+    pending: Arc<AtomicUsize>,
+    /// Subscriptions that have delivered at least once.
+    callbacks: Mutex<Vec<&'static AsyncCallback>>,
+}
+
+static BRIDGE: OnceLock<Bridge> = OnceLock::new();
+
+/// Starts the tokio runtime for async callbacks. Called once when the [Runtime](crate::Runtime)
+/// is created.
+pub(crate) fn init(config: &AsyncConfig) -> Result<()> {
+    if BRIDGE.get().is_some() {
+        return Ok(());
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(config.worker_threads.max(1))
+        .thread_name("retina-async")
+        .enable_all()
+        .build()?;
+    let _ = BRIDGE.set(Bridge {
+        runtime,
+        config: config.clone(),
+        pending: Arc::new(AtomicUsize::new(0)),
+        callbacks: Mutex::new(vec![]),
+    });
+    Ok(())
+}
+
+/// Waits up to the configured drain timeout for queued and running callbacks to complete, and
+/// logs the number of deliveries dropped by each subscription. Called once all cores have stopped.
+pub(crate) fn drain() {
+    let bridge = match BRIDGE.get() {
+        Some(bridge) => bridge,
+        None => return,
+    };
+    let deadline = Instant::now() + Duration::from_millis(bridge.config.drain_timeout);
+    while bridge.pending.load(Ordering::Acquire) > 0 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let pending = bridge.pending.load(Ordering::Acquire);
+    if pending > 0 {
+        log::warn!(
+            "{} async callback(s) did not complete before shutdown",
+            pending
+        );
+    }
+    for callback in bridge.callbacks.lock().unwrap().iter() {
+        let dropped = callback.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            log::warn!(
+                "Async callback {} dropped {} deliveries (queue full)",
+                callback.name,
+                dropped
+            );
+        }
+    }
+}
+
+/// Decrements the pending count when a queued callback completes, or is dropped (e.g., panics).
+struct PendingGuard(Arc<AtomicUsize>);
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// The delivery queue of one async subscription. Declared as a `static` by the generated code.
+#[derive(Debug)]
+pub struct AsyncCallback {
+    name: &'static str,
+    queue: OnceLock<Option<mpsc::Sender<CallbackFuture>>>,
+    dropped: AtomicU64,
+}
+
+impl AsyncCallback {
+    pub const fn new(name: &'static str) -> Self {
+        AsyncCallback {
+            name,
+            queue: OnceLock::new(),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Queues `future` to be run on the async runtime. Never blocks: if the queue is full, the
+    /// delivery is dropped and counted.
+    pub fn deliver(&'static self, future: CallbackFuture) {
+        let queue = match self.queue.get_or_init(|| self.start()) {
+            Some(queue) => queue,
+            None => return,
+        };
+        let bridge = BRIDGE.get().unwrap();
+        bridge.pending.fetch_add(1, Ordering::AcqRel);
+        if queue.try_send(future).is_err() {
+            bridge.pending.fetch_sub(1, Ordering::AcqRel);
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the number of deliveries dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Creates the queue and spawns the task that drains it.
+    fn start(&'static self) -> Option<mpsc::Sender<CallbackFuture>> {
+        let bridge = match BRIDGE.get() {
+            Some(bridge) => bridge,
+            None => {
+                log::error!(
+                    "Async callback {} invoked without an async runtime, deliveries are dropped",
+                    self.name
+                );
+                return None;
+            }
+        };
+        bridge.callbacks.lock().unwrap().push(self);
+        let (tx, mut rx) = mpsc::channel::<CallbackFuture>(bridge.config.queue_depth.max(1));
+        let permits = Arc::new(Semaphore::new(bridge.config.max_in_flight.max(1)));
+        let pending = Arc::clone(&bridge.pending);
+        bridge.runtime.spawn(async move {
+            while let Some(future) = rx.recv().await {
+                let guard = PendingGuard(Arc::clone(&pending));
+                // Stop reading from the queue while `max_in_flight` callbacks are running
+                let permit = match Arc::clone(&permits).acquire_owned().await {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                };
+                tokio::spawn(async move {
+                    let _guard = guard;
+                    let _permit = permit;
+                    future.await;
+                });
+            }
+        });
+        Some(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_async_bridge_deliver() {
+        static DONE: AtomicUsize = AtomicUsize::new(0);
+        static CALLBACK: AsyncCallback = AsyncCallback::new("test_cb");
+        init(&AsyncConfig::default()).unwrap();
+        for _ in 0..10 {
+            CALLBACK.deliver(Box::pin(async {
+                DONE.fetch_add(1, Ordering::Relaxed);
+            }));
+        }
+        drain();
+        assert_eq!(DONE.load(Ordering::Relaxed), 10);
+        assert_eq!(CALLBACK.dropped(), 0);
+    }
+}
@@ -0,0 +1,97 @@
+//! Subscription match budgets.
+//!
+//! A subscription can be capped to a number of matches (the `max_matches` argument of the `filter`
+//! attribute, or the `max_matches` key of subscription files), e.g., for data-collection studies
+//! that need a fixed number of samples. The callback is invoked at most `max_matches` times, across
+//! all cores. Once the budget is exhausted, the filter of the subscription is no longer applied,
+//! so new connections are not tracked for it; connections that already matched finish without
+//! delivery.
+//!
+//! Each generated check holds a [MatchBudget](MatchBudget), which looks up the counter of its
+//! subscription once, so checking the budget on the packet processing path is a single atomic
+//! load.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Number of matches of each budgeted subscription, by name.
+static MATCHES: Mutex<BTreeMap<String, Arc<AtomicU64>>> = Mutex::new(BTreeMap::new());
+
+/// The match budget of a subscription. Declared as a `static` by the generated code.
+#[derive(Debug)]
+pub struct MatchBudget {
+    name: &'static str,
+    max_matches: u64,
+    matches: OnceLock<Arc<AtomicU64>>,
+}
+
+impl MatchBudget {
+    pub const fn new(name: &'static str, max_matches: u64) -> Self {
+        MatchBudget {
+            name,
+            max_matches,
+            matches: OnceLock::new(),
+        }
+    }
+
+    fn matches(&self) -> &AtomicU64 {
+        self.matches.get_or_init(|| counter(self.name))
+    }
+
+    /// Returns `true` if the subscription has used up its budget.
+    #[inline]
+    pub fn exhausted(&self) -> bool {
+        self.matches().load(Ordering::Relaxed) >= self.max_matches
+    }
+
+    /// Counts a match of the subscription. Returns `true` if the match is within the budget, and
+    /// should be delivered.
+    #[inline]
+    pub fn take(&self) -> bool {
+        let matches = self.matches().fetch_add(1, Ordering::Relaxed) + 1;
+        if matches == self.max_matches {
+            log::info!(
+                "Subscription {} reached its budget of {} matches",
+                self.name,
+                self.max_matches
+            );
+        }
+        matches <= self.max_matches
+    }
+}
+
+fn counter(name: &str) -> Arc<AtomicU64> {
+    let mut budgets = MATCHES.lock().unwrap();
+    let counter = budgets
+        .entry(name.to_string())
+        .or_insert_with(|| Arc::new(AtomicU64::new(0)));
+    Arc::clone(counter)
+}
+
+/// Returns the number of matches of budgeted subscription `name` (as reported in the logs),
+/// including those over budget, or `None` if its budget has not been checked yet.
+pub fn matches(name: &str) -> Option<u64> {
+    MATCHES
+        .lock()
+        .unwrap()
+        .get(name)
+        .map(|counter| counter.load(Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_budget_take() {
+        static FIRST: MatchBudget = MatchBudget::new("budget_cb", 2);
+        static SECOND: MatchBudget = MatchBudget::new("budget_cb", 2);
+        assert!(!FIRST.exhausted());
+        assert!(FIRST.take());
+        assert!(SECOND.take());
+        assert!(FIRST.exhausted());
+        assert!(!SECOND.take());
+        assert_eq!(matches("budget_cb"), Some(3));
+    }
+}
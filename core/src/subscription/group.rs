@@ -0,0 +1,95 @@
+//! Subscription groups.
+//!
+//! Subscriptions can be tagged into named groups (the `group` argument of the `filter` attribute,
+//! or the `group` key of subscription files). The generated filters only apply the subscriptions of
+//! a group while the group is enabled, so expensive analyses can be toggled without restarting:
+//! while a group is disabled, new connections are not tracked for its subscriptions, and nothing is
+//! delivered to them. Groups are enabled when the runtime starts.
+//!
+//! Each generated check holds a [SubscriptionGroup](SubscriptionGroup), which looks up the flag of
+//! its group once, so checking a group on the packet processing path is a single atomic load.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use anyhow::{bail, Result};
+
+/// Flag of each group, by name.
+static GROUPS: Mutex<BTreeMap<String, Arc<AtomicBool>>> = Mutex::new(BTreeMap::new());
+
+/// A group of subscriptions. Declared as a `static` by the generated code.
+#[derive(Debug)]
+pub struct SubscriptionGroup {
+    name: &'static str,
+    enabled: OnceLock<Arc<AtomicBool>>,
+}
+
+impl SubscriptionGroup {
+    pub const fn new(name: &'static str) -> Self {
+        SubscriptionGroup {
+            name,
+            enabled: OnceLock::new(),
+        }
+    }
+
+    /// Returns `true` if the subscriptions of the group are applied.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.enabled
+            .get_or_init(|| flag(self.name))
+            .load(Ordering::Relaxed)
+    }
+}
+
+fn flag(name: &str) -> Arc<AtomicBool> {
+    let mut groups = GROUPS.lock().unwrap();
+    let flag = groups
+        .entry(name.to_string())
+        .or_insert_with(|| Arc::new(AtomicBool::new(true)));
+    Arc::clone(flag)
+}
+
+/// Registers the groups of the subscriptions. Called once when the [Runtime](crate::Runtime) is
+/// created.
+pub(crate) fn register(names: &[&str]) {
+    for name in names {
+        flag(name);
+    }
+}
+
+/// Enables or disables the subscriptions of group `name`. Can be called from any thread, including
+/// from callbacks.
+pub fn set_enabled(name: &str, enabled: bool) -> Result<()> {
+    match GROUPS.lock().unwrap().get(name) {
+        Some(flag) => {
+            flag.store(enabled, Ordering::Relaxed);
+            log::info!(
+                "{} subscription group {}",
+                if enabled { "Enabled" } else { "Disabled" },
+                name
+            );
+            Ok(())
+        }
+        None => bail!("Unknown subscription group: {}", name),
+    }
+}
+
+/// Returns the name of each group, and whether it is enabled.
+pub fn groups() -> Vec<(String, bool)> {
+    GROUPS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, flag)| (name.clone(), flag.load(Ordering::Relaxed)))
+        .collect()
+}
+
+/// Returns whether group `name` is enabled, or `None` if no subscription is in group `name`.
+pub fn is_enabled(name: &str) -> Option<bool> {
+    GROUPS
+        .lock()
+        .unwrap()
+        .get(name)
+        .map(|flag| flag.load(Ordering::Relaxed))
+}
@@ -1,6 +1,16 @@
-use crate::conntrack::pdu::{L4Context, L4Pdu};
+#[cfg(feature = "async")]
+pub mod async_bridge;
+pub mod budget;
+pub mod group;
+pub mod worker_pool;
+
+use crate::conntrack::conn::TerminationReason;
+use crate::conntrack::pdu::{IcmpError, L4Context, L4Pdu};
+use crate::conntrack::tunnel;
 use crate::conntrack::ConnTracker;
 use crate::filter::*;
+use crate::lcore::metrics::{FilterLayer, METRICS};
+use crate::lcore::profile::{self, Stage};
 use crate::lcore::CoreId;
 use crate::memory::mbuf::Mbuf;
 use crate::protocols::stream::{ConnData, ParserRegistry, Session};
@@ -46,6 +56,13 @@ pub trait Trackable {
 
     /// Clear all internal data
     fn clear(&mut self);
+
+    /// The connection terminated for `reason`. Invoked before the connection is delivered.
+    fn terminate(&mut self, reason: TerminationReason);
+
+    /// An ICMP error message was received about a packet of the connection, sent by the
+    /// originator if `dir` is `true`.
+    fn icmp_error(&mut self, error: &IcmpError, dir: bool);
 }
 
 pub struct Subscription<S>
@@ -58,6 +75,12 @@ where
     session_filter: SessionFilterFn<S::Tracked>,
     packet_deliver: PacketDeliverFn<S::Tracked>,
     conn_deliver: ConnDeliverFn<S::Tracked>,
+    /// Whether connections buffer packets for the subscriptions.
+    pub(crate) track_packets: bool,
+    /// Whether packets are never forwarded to the connection tracker.
+    pub(crate) bypass_conntrack: bool,
+    /// Largest connection volume that the subscriptions can match.
+    pub(crate) volume_limits: VolumeLimits,
     #[cfg(feature = "timing")]
     pub(crate) timers: Timers,
 }
@@ -74,6 +97,9 @@ where
             session_filter: factory.session_filter,
             packet_deliver: factory.packet_deliver,
             conn_deliver: factory.conn_deliver,
+            track_packets: factory.track_packets,
+            bypass_conntrack: factory.bypass_conntrack,
+            volume_limits: factory.volume_limits,
             #[cfg(feature = "timing")]
             timers: Timers::new(),
         }
@@ -85,8 +111,10 @@ where
         conn_tracker: &mut ConnTracker<S::Tracked>,
         actions: Actions,
     ) {
-        if actions.data.intersects(ActionData::PacketContinue) {
-            if let Ok(ctxt) = L4Context::new(&mbuf) {
+        if !self.bypass_conntrack && actions.data.intersects(ActionData::PacketContinue) {
+            let _conntrack = profile::enter(Stage::Conntrack);
+            if let Ok(mut ctxt) = L4Context::new(&mbuf) {
+                ctxt.tunnels = tunnel::take();
                 conn_tracker.process(mbuf, ctxt, self);
             }
         }
@@ -100,19 +128,25 @@ where
     /// Used for each packet to determine
     /// forwarding to conn. tracker.
     pub fn continue_packet(&self, mbuf: &Mbuf, core_id: &CoreId) -> Actions {
+        let _packet_filter = profile::enter(Stage::PacketFilter);
+        rate::next_packet();
         (self.packet_continue)(mbuf, core_id)
     }
 
     /// Invokes the five-tuple filter.
     /// Applied to the first packet in the connection.
     pub fn filter_packet(&self, mbuf: &Mbuf, tracked: &S::Tracked) -> Actions {
-        (self.packet_filter)(mbuf, tracked)
+        let actions = (self.packet_filter)(mbuf, tracked);
+        METRICS.record_filter(tracked.core_id(), FilterLayer::Connection, !actions.drop());
+        actions
     }
 
     /// Invokes the end-to-end protocol filter.
     /// Applied once a parser identifies the application-layer protocol.
     pub fn filter_protocol(&self, conn: &ConnData, tracked: &S::Tracked) -> Actions {
-        (self.proto_filter)(conn, tracked)
+        let actions = (self.proto_filter)(conn, tracked);
+        METRICS.record_filter(tracked.core_id(), FilterLayer::Protocol, !actions.drop());
+        actions
     }
 
     /// Invokes the application-layer session filter.
@@ -123,16 +157,21 @@ where
         conn: &ConnData,
         tracked: &S::Tracked,
     ) -> Actions {
-        (self.session_filter)(session, conn, tracked)
+        let _delivery = profile::enter(Stage::Delivery);
+        let actions = (self.session_filter)(session, conn, tracked);
+        METRICS.record_filter(tracked.core_id(), FilterLayer::Session, !actions.drop());
+        actions
     }
 
     /// Delivery functions, including delivery to the correct callback
 
     pub fn deliver_packet(&self, mbuf: &Mbuf, conn_data: &ConnData, tracked: &S::Tracked) {
+        let _delivery = profile::enter(Stage::Delivery);
         (self.packet_deliver)(mbuf, conn_data, tracked)
     }
 
     pub fn deliver_conn(&self, conn_data: &ConnData, tracked: &S::Tracked) {
+        let _delivery = profile::enter(Stage::Delivery);
         (self.conn_deliver)(conn_data, tracked)
     }
 }
@@ -0,0 +1,213 @@
+//! Callback worker pool.
+//!
+//! When the [CallbackConfig](crate::config::CallbackConfig) sets `workers > 0`, the generated
+//! delivery code does not call the callback on the packet processing core. It clones the
+//! subscribed data into a closure and hands it to the subscription's
+//! [PooledCallback](PooledCallback), which queues it on a bounded queue. Worker threads take
+//! closures from all queues in the order they were queued and run them.
+//!
+//! Each successful enqueue also sends a token naming the subscription to a queue shared by all
+//! workers. A worker that receives a token runs the next closure of that subscription, if any (with
+//! `drop_oldest`, an evicted closure leaves a spare token behind).
+
+use crate::config::{CallbackConfig, DropPolicy};
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::Result;
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+
+/// A callback invocation with owned data.
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+struct Pool {
+    config: CallbackConfig,
+    ready: Sender<&'static PooledCallback>,
+    /// Number of closures queued or running, across all subscriptions.
+    pending: AtomicUsize,
+    /// Subscriptions that have delivered at least once.
+    callbacks: Mutex<Vec<&'static PooledCallback>>,
+}
+
+static POOL: OnceLock<Pool> = OnceLock::new();
+
+/// Starts the worker threads, if enabled. Called once when the [Runtime](crate::Runtime) is
+/// created.
+pub(crate) fn init(config: &CallbackConfig) -> Result<()> {
+    if config.workers == 0 || POOL.get().is_some() {
+        return Ok(());
+    }
+    let (ready, tokens) = crossbeam_channel::unbounded();
+    let _ = POOL.set(Pool {
+        config: config.clone(),
+        ready,
+        pending: AtomicUsize::new(0),
+        callbacks: Mutex::new(vec![]),
+    });
+    for idx in 0..config.workers {
+        let tokens = tokens.clone();
+        std::thread::Builder::new()
+            .name(format!("retina-cb-{}", idx))
+            .spawn(move || worker(tokens))?;
+    }
+    log::info!("Running callbacks on {} worker thread(s)", config.workers);
+    Ok(())
+}
+
+fn worker(tokens: Receiver<&'static PooledCallback>) {
+    while let Ok(callback) = tokens.recv() {
+        let queue = match callback.queue.get() {
+            Some(Some(queue)) => queue,
+            _ => continue,
+        };
+        if let Ok(job) = queue.rx.try_recv() {
+            let pending = &POOL.get().unwrap().pending;
+            if std::panic::catch_unwind(std::panic::AssertUnwindSafe(job)).is_err() {
+                log::error!("Callback {} panicked on a worker thread", callback.name);
+            }
+            pending.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+/// Waits for all queued callbacks to complete, and logs the number of deliveries dropped by each
+/// subscription. Called once all cores have stopped.
+pub(crate) fn drain() {
+    let pool = match POOL.get() {
+        Some(pool) => pool,
+        None => return,
+    };
+    while pool.pending.load(Ordering::Acquire) > 0 {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    for callback in pool.callbacks.lock().unwrap().iter() {
+        let dropped = callback.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            log::warn!(
+                "Callback {} dropped {} deliveries (queue full)",
+                callback.name,
+                dropped
+            );
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Queue {
+    tx: Sender<Job>,
+    /// Used by the workers, and by the sender to evict with `drop_oldest`.
+    rx: Receiver<Job>,
+    policy: DropPolicy,
+}
+
+/// The delivery queue of one subscription. Declared as a `static` by the generated code.
+#[derive(Debug)]
+pub struct PooledCallback {
+    name: &'static str,
+    queue: OnceLock<Option<Queue>>,
+    dropped: AtomicU64,
+}
+
+impl PooledCallback {
+    pub const fn new(name: &'static str) -> Self {
+        PooledCallback {
+            name,
+            queue: OnceLock::new(),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns `true` if the callback runs on the worker pool, `false` if it runs inline.
+    #[inline]
+    pub fn enabled(&'static self) -> bool {
+        self.queue.get_or_init(|| self.start()).is_some()
+    }
+
+    /// Queues `job` for the worker pool, applying the subscription's drop policy if its queue is
+    /// full. Must only be called if [enabled](PooledCallback::enabled) returned `true`.
+    pub fn deliver(&'static self, job: Job) {
+        let (queue, pool) = match (self.queue.get(), POOL.get()) {
+            (Some(Some(queue)), Some(pool)) => (queue, pool),
+            _ => return,
+        };
+        pool.pending.fetch_add(1, Ordering::AcqRel);
+        let mut job = job;
+        loop {
+            match queue.tx.try_send(job) {
+                Ok(()) => break,
+                Err(TrySendError::Full(rejected)) => match queue.policy {
+                    DropPolicy::DropNewest => {
+                        self.drop_one(pool);
+                        return;
+                    }
+                    DropPolicy::DropOldest => {
+                        // Workers may empty the queue concurrently, in which case nothing is
+                        // evicted and the send is retried
+                        if queue.rx.try_recv().is_ok() {
+                            self.drop_one(pool);
+                        }
+                        job = rejected;
+                    }
+                    DropPolicy::Block => {
+                        if queue.tx.send(rejected).is_err() {
+                            self.drop_one(pool);
+                            return;
+                        }
+                        break;
+                    }
+                },
+                Err(TrySendError::Disconnected(_)) => {
+                    self.drop_one(pool);
+                    return;
+                }
+            }
+        }
+        let _ = pool.ready.send(self);
+    }
+
+    /// Returns the number of deliveries dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn drop_one(&self, pool: &Pool) {
+        pool.pending.fetch_sub(1, Ordering::AcqRel);
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Creates the queue if the worker pool is enabled.
+    fn start(&'static self) -> Option<Queue> {
+        let pool = POOL.get()?;
+        pool.callbacks.lock().unwrap().push(self);
+        let (depth, policy) = pool.config.queue(self.name);
+        let (tx, rx) = crossbeam_channel::bounded(depth.max(1));
+        Some(Queue { tx, rx, policy })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_worker_pool_deliver() {
+        static DONE: AtomicUsize = AtomicUsize::new(0);
+        static CALLBACK: PooledCallback = PooledCallback::new("test_cb");
+        let config = CallbackConfig {
+            workers: 2,
+            ..Default::default()
+        };
+        init(&config).unwrap();
+        assert!(CALLBACK.enabled());
+        for _ in 0..10 {
+            CALLBACK.deliver(Box::new(|| {
+                DONE.fetch_add(1, Ordering::Relaxed);
+            }));
+        }
+        drain();
+        assert_eq!(DONE.load(Ordering::Relaxed), 10);
+        assert_eq!(CALLBACK.dropped(), 0);
+    }
+}
@@ -0,0 +1,307 @@
+//! Kafka producer sink.
+//!
+//! A [KafkaSink](KafkaSink) serializes records of a datatype that implements `Serialize` as JSON
+//! and publishes them to Kafka topics. Serialization happens on the calling core, which then
+//! queues the record on a bounded queue and returns. Dedicated sink threads take records from the
+//! queue in batches and hand them to a librdkafka producer, which batches them further into
+//! message sets. See [KafkaConfig](crate::config::KafkaConfig).
+//!
+//! Backpressure is handled in two places. If the producer's queue is full (e.g., the brokers are
+//! slow or unreachable), a sink thread waits for outstanding deliveries before producing more. If
+//! the sink's own queue then fills up, the configured [DropPolicy](crate::config::DropPolicy)
+//! applies on the calling core.
+//!
+//! The sink must be closed after the runtime stops, to publish queued records.
+//!
+//! ## Example
+//! ```rust,ignore
+//! use retina_core::config::KafkaConfig;
+//! use retina_core::utils::kafka::KafkaSink;
+//! use retina_datatypes::TlsHandshake;
+//! use lazy_static::lazy_static;
+//!
+//! lazy_static! {
+//!     static ref TLS: KafkaSink<TlsHandshake> = KafkaSink::new(KafkaConfig {
+//!         topic: "retina.tls".to_string(),
+//!         ..Default::default()
+//!     })
+//!     .expect("Kafka producer");
+//! }
+//!
+//! #[filter("tls")]
+//! fn tls_cb(tls: &TlsHandshake) {
+//!     TLS.send(tls);
+//! }
+//!
+//! #[retina_main(1)]
+//! fn main() {
+//!     let config = default_config();
+//!     let mut runtime: Runtime<SubscribedWrapper> = Runtime::new(config, filter).unwrap();
+//!     runtime.run();
+//!     TLS.close();
+//! }
+//! ```
+
+use super::queue::SinkQueue;
+use crate::config::KafkaConfig;
+
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::Result;
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::{BaseProducer, BaseRecord, DeliveryResult, Producer, ProducerContext};
+use rdkafka::ClientContext;
+use serde::Serialize;
+
+/// Time a sink thread waits for records before serving delivery reports.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Time the producers have to deliver outstanding records when the sink is closed.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A serialized record.
+struct Message {
+    /// Topic, if not the configured one.
+    topic: Option<String>,
+    key: Option<Vec<u8>>,
+    payload: Vec<u8>,
+}
+
+/// Counters shared by the sink and its threads.
+#[derive(Default)]
+struct Stats {
+    /// Records acknowledged by the brokers.
+    delivered: AtomicU64,
+    /// Records that failed to serialize, could not be produced, or were not delivered.
+    failed: AtomicU64,
+    /// Set once an error was logged, to avoid flooding the log.
+    logged: AtomicBool,
+}
+
+impl Stats {
+    fn fail(&self, error: impl std::fmt::Display) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        if !self.logged.swap(true, Ordering::Relaxed) {
+            log::error!("Kafka sink: {}", error);
+        }
+    }
+}
+
+/// Counts delivery reports.
+struct Context {
+    stats: Arc<Stats>,
+}
+
+impl ClientContext for Context {}
+
+impl ProducerContext for Context {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        match result {
+            Ok(_) => {
+                self.stats.delivered.fetch_add(1, Ordering::Relaxed);
+            }
+            Err((error, _)) => self.stats.fail(error),
+        }
+    }
+}
+
+/// Publishes records of type `T` to Kafka.
+pub struct KafkaSink<T> {
+    config: KafkaConfig,
+    queue: SinkQueue<Message>,
+    stats: Arc<Stats>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
+    _record: PhantomData<fn(&T)>,
+}
+
+impl<T: Serialize> KafkaSink<T> {
+    /// Creates the producers and starts the sink threads. Fails if the producer configuration is
+    /// invalid. Brokers are only contacted once records are published.
+    pub fn new(config: KafkaConfig) -> Result<Self> {
+        let queue = SinkQueue::new(config.queue_depth, config.drop_policy);
+        let stats = Arc::new(Stats::default());
+        let client_config = client_config(&config);
+        let mut threads = vec![];
+        for idx in 0..config.sink_threads.max(1) {
+            let producer: BaseProducer<Context> = client_config.create_with_context(Context {
+                stats: Arc::clone(&stats),
+            })?;
+            let worker = SinkThread {
+                producer,
+                topic: config.topic.clone(),
+                batch_size: config.batch_size.max(1),
+                rx: queue.receiver(),
+                stats: Arc::clone(&stats),
+            };
+            threads.push(
+                std::thread::Builder::new()
+                    .name(format!("retina-kafka-{}", idx))
+                    .spawn(move || worker.run())?,
+            );
+        }
+        log::info!(
+            "Publishing to Kafka topic {} from {} sink thread(s)",
+            config.topic,
+            threads.len()
+        );
+        Ok(KafkaSink {
+            config,
+            queue,
+            stats,
+            threads: Mutex::new(threads),
+            _record: PhantomData,
+        })
+    }
+
+    /// Publishes `record` to the configured topic.
+    pub fn send(&self, record: &T) {
+        self.queue(None, None, record);
+    }
+
+    /// Publishes `record` to the configured topic with a message key, e.g., to partition
+    /// records by connection.
+    pub fn send_keyed(&self, key: &[u8], record: &T) {
+        self.queue(None, Some(key), record);
+    }
+
+    /// Publishes `record` to `topic` instead of the configured topic.
+    pub fn send_to(&self, topic: &str, key: Option<&[u8]>, record: &T) {
+        self.queue(Some(topic), key, record);
+    }
+
+    /// Publishes the queued records, waits for their delivery, and stops the sink threads.
+    /// Records sent afterwards are dropped.
+    pub fn close(&self) {
+        self.queue.close();
+        for thread in self.threads.lock().unwrap().drain(..) {
+            let _ = thread.join();
+        }
+        log::info!(
+            "Kafka topic {}: {} records delivered, {} dropped (queue full), {} failed",
+            self.config.topic,
+            self.stats.delivered.load(Ordering::Relaxed),
+            self.queue.dropped(),
+            self.stats.failed.load(Ordering::Relaxed),
+        );
+    }
+
+    fn queue(&self, topic: Option<&str>, key: Option<&[u8]>, record: &T) {
+        let payload = match serde_json::to_vec(record) {
+            Ok(payload) => payload,
+            Err(error) => {
+                self.stats.fail(error);
+                return;
+            }
+        };
+        self.queue.push(Message {
+            topic: topic.map(str::to_string),
+            key: key.map(<[u8]>::to_vec),
+            payload,
+        });
+    }
+}
+
+/// Returns the producer configuration: the configured brokers, batching, and additional
+/// properties, which take precedence.
+fn client_config(config: &KafkaConfig) -> ClientConfig {
+    let mut client_config = ClientConfig::new();
+    client_config
+        .set("bootstrap.servers", &config.brokers)
+        .set("linger.ms", config.linger_ms.to_string())
+        .set("batch.num.messages", config.batch_size.max(1).to_string());
+    for (key, value) in config.properties.iter() {
+        client_config.set(key, value);
+    }
+    client_config
+}
+
+/// State of one sink thread.
+struct SinkThread {
+    producer: BaseProducer<Context>,
+    topic: String,
+    batch_size: usize,
+    rx: Receiver<Message>,
+    stats: Arc<Stats>,
+}
+
+impl SinkThread {
+    fn run(self) {
+        let mut batch = Vec::with_capacity(self.batch_size);
+        loop {
+            match self.rx.recv_timeout(POLL_INTERVAL) {
+                Ok(msg) => {
+                    batch.push(msg);
+                    batch.extend(self.rx.try_iter().take(self.batch_size - 1));
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                // The sink was closed and the queue is empty
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            for msg in batch.drain(..) {
+                self.produce(msg);
+            }
+            // Serves delivery reports
+            self.producer.poll(Duration::ZERO);
+        }
+        if let Err(error) = self.producer.flush(FLUSH_TIMEOUT) {
+            self.stats.fail(error);
+        }
+    }
+
+    fn produce(&self, msg: Message) {
+        let topic = msg.topic.as_deref().unwrap_or(&self.topic);
+        let mut record = BaseRecord::to(topic).payload(&msg.payload);
+        if let Some(key) = msg.key.as_ref() {
+            record = record.key(key);
+        }
+        loop {
+            match self.producer.send(record) {
+                Ok(()) => return,
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), rejected)) => {
+                    // Waits for outstanding deliveries to make room
+                    self.producer.poll(POLL_INTERVAL);
+                    record = rejected;
+                }
+                Err((error, _)) => {
+                    self.stats.fail(error);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_kafka_client_config() {
+        let mut config = KafkaConfig {
+            brokers: "kafka1:9092,kafka2:9092".to_string(),
+            linger_ms: 20,
+            ..Default::default()
+        };
+        config
+            .properties
+            .insert("linger.ms".to_string(), "50".to_string());
+        config
+            .properties
+            .insert("compression.type".to_string(), "lz4".to_string());
+        let client_config = client_config(&config);
+        assert_eq!(
+            client_config.get("bootstrap.servers"),
+            Some("kafka1:9092,kafka2:9092")
+        );
+        assert_eq!(client_config.get("batch.num.messages"), Some("1000"));
+        assert_eq!(client_config.get("linger.ms"), Some("50"));
+        assert_eq!(client_config.get("compression.type"), Some("lz4"));
+    }
+}
@@ -1,4 +1,15 @@
 //! Utility modules.
 
 pub mod base64;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod pcap;
+#[cfg(any(feature = "kafka", feature = "zmq"))]
+mod queue;
+pub mod shm_ring;
+pub mod sink;
 pub mod types;
+#[cfg(feature = "zmq")]
+pub mod zmq;
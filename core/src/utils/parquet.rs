@@ -0,0 +1,495 @@
+//! Per-core Parquet file writers.
+//!
+//! A [ParquetSink](ParquetSink) writes records of a datatype that implements
+//! [ToArrow](ToArrow) as the rows of Parquet files, for analysis with pandas, Spark, DuckDB, etc.
+//! Like [Sink](crate::utils::sink::Sink), each core buffers its rows into Arrow record batches and
+//! writes them to its own sequence of files, named `core<id>_<n>.parquet` in the configured
+//! directory, and starts a new file when the current one exceeds the configured size or age. See
+//! [ParquetConfig](crate::config::ParquetConfig).
+//!
+//! Files are only readable once closed: the sink must be closed after the runtime stops.
+//!
+//! ## Example
+//! ```rust,ignore
+//! use retina_core::config::ParquetConfig;
+//! use retina_core::protocols::stream::tls::Tls;
+//! use retina_core::utils::parquet::ParquetSink;
+//! use lazy_static::lazy_static;
+//!
+//! lazy_static! {
+//!     static ref TLS: ParquetSink<Tls> = ParquetSink::new(ParquetConfig {
+//!         directory: "./parquet/tls".to_string(),
+//!         ..Default::default()
+//!     });
+//! }
+//!
+//! #[filter("tls")]
+//! fn tls_cb(tls: &TlsHandshake) {
+//!     TLS.write(tls);
+//! }
+//!
+//! #[retina_main(1)]
+//! fn main() {
+//!     let config = default_config();
+//!     let mut runtime: Runtime<SubscribedWrapper> = Runtime::new(config, filter).unwrap();
+//!     runtime.run();
+//!     TLS.close();
+//! }
+//! ```
+//!
+//! Datatypes defined outside of retina-core implement [ToArrow](ToArrow) with the re-exported
+//! [arrow](arrow) crate.
+
+pub use arrow;
+
+use crate::config::{ParquetCompression, ParquetConfig};
+use crate::conntrack::conn_id::FiveTuple;
+use crate::dpdk;
+use crate::lcore::{CoreId, MAX_CORES};
+use crate::protocols::stream::dns::Dns;
+use crate::protocols::stream::http::Http;
+use crate::protocols::stream::quic::QuicConn;
+use crate::protocols::stream::tls::Tls;
+
+use arrow::array::{
+    ArrayBuilder, BooleanBuilder, Float64Builder, ListBuilder, StringBuilder, StructBuilder,
+    UInt16Builder, UInt32Builder, UInt64Builder, UInt8Builder,
+};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+
+use std::fs::{self, File};
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A datatype that is written as rows of Parquet files.
+pub trait ToArrow {
+    /// Returns the columns of the rows.
+    fn fields() -> Vec<Field>;
+
+    /// Appends a value to each column of `row`, in the order of [fields](ToArrow::fields).
+    fn append(&self, row: &mut Row);
+}
+
+impl<T: ToArrow> ToArrow for Box<T> {
+    fn fields() -> Vec<Field> {
+        T::fields()
+    }
+
+    fn append(&self, row: &mut Row) {
+        self.as_ref().append(row)
+    }
+}
+
+/// The columns of a row being appended, filled in order.
+///
+/// Each method appends a value to the next column, which must have the matching type: `Utf8`,
+/// `UInt8` to `UInt64`, `Float64`, `Boolean`, or a `List` of `Utf8`. `None` appends a null.
+pub struct Row<'a> {
+    builder: &'a mut StructBuilder,
+    column: usize,
+}
+
+impl Row<'_> {
+    pub fn append_str(&mut self, value: Option<&str>) {
+        self.next::<StringBuilder>().append_option(value);
+    }
+
+    pub fn append_u8(&mut self, value: Option<u8>) {
+        self.next::<UInt8Builder>().append_option(value);
+    }
+
+    pub fn append_u16(&mut self, value: Option<u16>) {
+        self.next::<UInt16Builder>().append_option(value);
+    }
+
+    pub fn append_u32(&mut self, value: Option<u32>) {
+        self.next::<UInt32Builder>().append_option(value);
+    }
+
+    pub fn append_u64(&mut self, value: Option<u64>) {
+        self.next::<UInt64Builder>().append_option(value);
+    }
+
+    pub fn append_f64(&mut self, value: Option<f64>) {
+        self.next::<Float64Builder>().append_option(value);
+    }
+
+    pub fn append_bool(&mut self, value: Option<bool>) {
+        self.next::<BooleanBuilder>().append_option(value);
+    }
+
+    pub fn append_strs<'s>(&mut self, values: impl IntoIterator<Item = &'s str>) {
+        let list = self.next::<ListBuilder<Box<dyn ArrayBuilder>>>();
+        let items = list
+            .values()
+            .as_any_mut()
+            .downcast_mut::<StringBuilder>()
+            .expect("list column is not a list of strings");
+        for value in values {
+            items.append_value(value);
+        }
+        list.append(true);
+    }
+
+    /// Returns the builder of the next column.
+    fn next<B: ArrayBuilder>(&mut self) -> &mut B {
+        let column = self.column;
+        self.column += 1;
+        self.builder
+            .field_builder::<B>(column)
+            .unwrap_or_else(|| panic!("column {} does not match the schema", column))
+    }
+}
+
+/// Returns a nullable column of strings.
+pub fn str_list_field(name: &str) -> Field {
+    Field::new_list(name, Field::new("item", DataType::Utf8, true), true)
+}
+
+/// Writes the records of a core to a rotating sequence of Parquet files.
+struct ParquetWriter<T> {
+    directory: PathBuf,
+    batch_size: usize,
+    rotate_size: Option<u64>,
+    rotate_interval: Option<Duration>,
+    props: WriterProperties,
+    core_id: CoreId,
+    schema: SchemaRef,
+    /// Rows not yet written.
+    rows: StructBuilder,
+    /// Current file, opened on the first batch.
+    wtr: Option<ArrowWriter<File>>,
+    /// Index of the current file.
+    file_idx: usize,
+    /// Time the current file was opened.
+    file_start: Instant,
+    /// Set after a write error, to stop writing and avoid logging the error for each record.
+    failed: bool,
+    _record: PhantomData<fn(&T)>,
+}
+
+impl<T: ToArrow> ParquetWriter<T> {
+    fn new(config: &ParquetConfig, core_id: CoreId) -> Self {
+        let compression = match config.compression {
+            ParquetCompression::None => Compression::UNCOMPRESSED,
+            ParquetCompression::Snappy => Compression::SNAPPY,
+            ParquetCompression::Zstd => Compression::ZSTD(ZstdLevel::default()),
+        };
+        let batch_size = config.batch_size.max(1);
+        ParquetWriter {
+            directory: PathBuf::from(&config.directory),
+            batch_size,
+            rotate_size: config.rotate_size,
+            rotate_interval: config.rotate_interval.map(Duration::from_secs),
+            props: WriterProperties::builder()
+                .set_compression(compression)
+                .build(),
+            core_id,
+            schema: Arc::new(Schema::new(T::fields())),
+            rows: StructBuilder::from_fields(T::fields(), batch_size),
+            wtr: None,
+            file_idx: 0,
+            file_start: Instant::now(),
+            failed: false,
+            _record: PhantomData,
+        }
+    }
+
+    fn write(&mut self, record: &T) {
+        if self.failed {
+            return;
+        }
+        let mut row = Row {
+            builder: &mut self.rows,
+            column: 0,
+        };
+        record.append(&mut row);
+        debug_assert_eq!(row.column, self.schema.fields().len());
+        self.rows.append(true);
+        if self.rows.len() >= self.batch_size {
+            self.write_batch();
+        }
+    }
+
+    /// Writes the buffered rows as a record batch, and starts a new file if the current one is
+    /// too large or too old.
+    fn write_batch(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let batch = RecordBatch::from(self.rows.finish());
+        if let Err(error) = self.try_write_batch(&batch) {
+            log::error!(
+                "Core {}: failed to write Parquet file: {}",
+                self.core_id,
+                error
+            );
+            self.failed = true;
+            self.wtr = None;
+            return;
+        }
+        let expired = self
+            .rotate_interval
+            .is_some_and(|interval| self.file_start.elapsed() >= interval);
+        let full = match (&self.wtr, self.rotate_size) {
+            (Some(wtr), Some(size)) => wtr.bytes_written() as u64 >= size,
+            _ => false,
+        };
+        if expired || full {
+            self.close_file();
+        }
+    }
+
+    fn try_write_batch(&mut self, batch: &RecordBatch) -> anyhow::Result<()> {
+        if self.wtr.is_none() {
+            fs::create_dir_all(&self.directory)?;
+            let path = self
+                .directory
+                .join(format!("core{}_{}.parquet", self.core_id, self.file_idx));
+            log::info!("Writing records to {:?}", path);
+            let file = File::create(path)?;
+            let wtr = ArrowWriter::try_new(file, self.schema.clone(), Some(self.props.clone()))?;
+            self.wtr = Some(wtr);
+            self.file_start = Instant::now();
+        }
+        self.wtr.as_mut().unwrap().write(batch)?;
+        Ok(())
+    }
+
+    /// Writes the footer of the current file. The next batch starts a new file.
+    fn close_file(&mut self) {
+        if let Some(wtr) = self.wtr.take() {
+            if let Err(error) = wtr.close() {
+                log::error!(
+                    "Core {}: failed to close Parquet file: {}",
+                    self.core_id,
+                    error
+                );
+            }
+            self.file_idx += 1;
+        }
+    }
+
+    fn close(&mut self) {
+        self.write_batch();
+        self.close_file();
+    }
+}
+
+/// A set of rotating Parquet files that callbacks on all cores write records of `T` to without
+/// contention.
+///
+/// Like [Sink](crate::utils::sink::Sink), each core writes to its own files. Writes from threads
+/// that are not DPDK cores share one additional set of files.
+pub struct ParquetSink<T: ToArrow> {
+    config: ParquetConfig,
+    writers: Vec<Mutex<Option<ParquetWriter<T>>>>,
+}
+
+impl<T: ToArrow> ParquetSink<T> {
+    /// Creates a sink that writes to `config.directory`. Files are created on the first record
+    /// batch.
+    pub fn new(config: ParquetConfig) -> Self {
+        ParquetSink {
+            config,
+            writers: (0..=MAX_CORES).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    /// Appends `record` to the rows of the current core.
+    pub fn write(&self, record: &T) {
+        let core_id = unsafe { dpdk::rte_lcore_id() } as usize;
+        self.write_core(core_id, record);
+    }
+
+    /// Writes the buffered rows of all cores and closes their current files. Records written
+    /// afterwards start new files.
+    pub fn close(&self) {
+        for slot in self.writers.iter() {
+            if let Some(wtr) = slot.lock().unwrap().as_mut() {
+                wtr.close();
+            }
+        }
+    }
+
+    fn write_core(&self, core_id: usize, record: &T) {
+        let idx = core_id.min(MAX_CORES);
+        let mut slot = self.writers[idx].lock().unwrap();
+        slot.get_or_insert_with(|| ParquetWriter::new(&self.config, CoreId(idx as u32)))
+            .write(record);
+    }
+}
+
+/* --------------------------------------------------------------------------------- */
+
+impl ToArrow for FiveTuple {
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("orig_addr", DataType::Utf8, false),
+            Field::new("orig_port", DataType::UInt16, false),
+            Field::new("resp_addr", DataType::Utf8, false),
+            Field::new("resp_port", DataType::UInt16, false),
+            Field::new("proto", DataType::UInt8, false),
+        ]
+    }
+
+    fn append(&self, row: &mut Row) {
+        row.append_str(Some(&self.orig.ip().to_string()));
+        row.append_u16(Some(self.orig.port()));
+        row.append_str(Some(&self.resp.ip().to_string()));
+        row.append_u16(Some(self.resp.port()));
+        row.append_u8(Some(self.proto as u8));
+    }
+}
+
+/// Returns `None` for empty strings, written as nulls.
+fn non_empty(value: &str) -> Option<&str> {
+    Some(value).filter(|value| !value.is_empty())
+}
+
+impl ToArrow for Tls {
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("sni", DataType::Utf8, true),
+            Field::new("version", DataType::UInt16, false),
+            Field::new("client_version", DataType::UInt16, false),
+            Field::new("cipher", DataType::Utf8, true),
+            str_list_field("client_ciphers"),
+            str_list_field("client_extensions"),
+            str_list_field("alpn"),
+            Field::new("server_certificates", DataType::UInt32, false),
+        ]
+    }
+
+    fn append(&self, row: &mut Row) {
+        row.append_str(non_empty(self.sni()));
+        row.append_u16(Some(self.version()));
+        row.append_u16(Some(self.client_version()));
+        row.append_str(non_empty(&self.cipher()));
+        row.append_strs(self.client_ciphers().iter().map(String::as_str));
+        row.append_strs(self.client_extensions().iter().map(String::as_str));
+        row.append_strs(self.client_alpn_protocols().iter().map(String::as_str));
+        row.append_u32(Some(self.server_certificates.len() as u32));
+    }
+}
+
+impl ToArrow for Http {
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("method", DataType::Utf8, true),
+            Field::new("uri", DataType::Utf8, true),
+            Field::new("host", DataType::Utf8, true),
+            Field::new("user_agent", DataType::Utf8, true),
+            Field::new("request_version", DataType::Utf8, true),
+            Field::new("request_content_length", DataType::UInt64, true),
+            Field::new("request_content_type", DataType::Utf8, true),
+            Field::new("status_code", DataType::UInt16, true),
+            Field::new("status_msg", DataType::Utf8, true),
+            Field::new("response_content_length", DataType::UInt64, true),
+            Field::new("response_content_type", DataType::Utf8, true),
+            Field::new("trans_depth", DataType::UInt64, false),
+        ]
+    }
+
+    fn append(&self, row: &mut Row) {
+        let (request, response) = (&self.request, &self.response);
+        row.append_str(request.method.as_deref());
+        row.append_str(request.uri.as_deref());
+        row.append_str(request.host.as_deref());
+        row.append_str(request.user_agent.as_deref());
+        row.append_str(request.version.as_deref());
+        row.append_u64(request.content_length.map(|len| len as u64));
+        row.append_str(request.content_type.as_deref());
+        row.append_u16(response.status_code);
+        row.append_str(response.status_msg.as_deref());
+        row.append_u64(response.content_length.map(|len| len as u64));
+        row.append_str(response.content_type.as_deref());
+        row.append_u64(Some(self.trans_depth as u64));
+    }
+}
+
+impl ToArrow for Dns {
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("transaction_id", DataType::UInt16, false),
+            Field::new("query", DataType::Utf8, true),
+            Field::new("qtype", DataType::UInt16, true),
+            Field::new("rcode", DataType::UInt8, true),
+            Field::new("answers", DataType::Utf8, true),
+        ]
+    }
+
+    fn append(&self, row: &mut Row) {
+        row.append_u16(Some(self.transaction_id));
+        row.append_str(self.query.as_ref().map(|_| self.query_domain()));
+        row.append_u16(self.query.as_ref().map(|_| self.qtype()));
+        row.append_u8(self.response.as_ref().map(|_| self.rcode()));
+        // Answer records, as JSON
+        row.append_str(non_empty(&self.answers()));
+    }
+}
+
+impl ToArrow for QuicConn {
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("version", DataType::UInt32, true),
+            Field::new("sni", DataType::Utf8, true),
+            Field::new("packets", DataType::UInt64, false),
+            str_list_field("cids"),
+        ]
+    }
+
+    fn append(&self, row: &mut Row) {
+        let version = self
+            .packets
+            .iter()
+            .find_map(|packet| packet.long_header.as_ref())
+            .map(|header| header.version);
+        row.append_u32(version);
+        row.append_str(non_empty(self.tls.sni()));
+        row.append_u64(Some(self.packets.len() as u64));
+        row.append_strs(self.cids.iter().map(String::as_str));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use std::net::SocketAddr;
+
+    #[test]
+    fn core_parquet_rotate() {
+        let directory = std::env::temp_dir().join(format!("retina_parquet_{}", std::process::id()));
+        let config = ParquetConfig {
+            directory: directory.to_str().unwrap().to_string(),
+            batch_size: 2,
+            rotate_size: Some(1),
+            ..Default::default()
+        };
+        let sink = ParquetSink::<FiveTuple>::new(config);
+        let five_tuple = FiveTuple {
+            orig: "10.0.0.1:1234".parse::<SocketAddr>().unwrap(),
+            resp: "10.0.0.2:443".parse::<SocketAddr>().unwrap(),
+            proto: 6,
+        };
+        for _ in 0..3 {
+            sink.write_core(1, &five_tuple);
+        }
+        sink.close();
+        let rows = |idx: usize| {
+            let file = File::open(directory.join(format!("core1_{}.parquet", idx))).unwrap();
+            let reader = SerializedFileReader::new(file).unwrap();
+            reader.metadata().file_metadata().num_rows()
+        };
+        assert_eq!(rows(0), 2);
+        assert_eq!(rows(1), 1);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,321 @@
+//! Per-core pcap file writers.
+//!
+//! Subscriptions that request the `PcapCapture` datatype have all packets of their matched
+//! connections written to pcap files by the framework (see the `PacketWrite` action). Each core
+//! writes its own sequence of files, named `core<id>_<n>.pcap` in the configured directory, and
+//! starts a new file when the current one exceeds the configured size or age. See
+//! [PcapWriterConfig](crate::config::PcapWriterConfig).
+//!
+//! To write the packets of each subscription to a separate set of files, callbacks can instead
+//! write the packets they receive (e.g., a `PacketList`) to their own [PcapSink](PcapSink). A
+//! `PcapSink` can also write each connection to its own file.
+//!
+//! ## Example
+//! ```rust,ignore
+//! use retina_core::config::PcapWriterConfig;
+//! use retina_core::utils::pcap::PcapSink;
+//! use lazy_static::lazy_static;
+//!
+//! lazy_static! {
+//!     static ref SSH: PcapSink = PcapSink::new(PcapWriterConfig {
+//!         directory: "./pcap/ssh".to_string(),
+//!         rotate_size: Some(1 << 30),
+//!         ..Default::default()
+//!     });
+//! }
+//!
+//! #[filter("tcp.port = 22")]
+//! fn ssh_cb(five_tuple: &FiveTuple, packets: &PacketList) {
+//!     SSH.write_connection(five_tuple, packets);
+//! }
+//! ```
+
+use crate::config::PcapWriterConfig;
+use crate::conntrack::conn_id::FiveTuple;
+use crate::dpdk;
+use crate::lcore::{CoreId, MAX_CORES};
+use crate::memory::mbuf::Mbuf;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Size of the pcap global header.
+const FILE_HEADER_LEN: u64 = 24;
+/// Size of the pcap per-packet record header.
+const RECORD_HEADER_LEN: u64 = 16;
+/// Ethernet link type.
+const LINKTYPE_ETHERNET: u32 = 1;
+
+/// A packet that is held until its connection matches a subscription.
+#[derive(Debug)]
+pub(crate) struct PendingPacket {
+    ts: SystemTime,
+    orig_len: u32,
+    data: Vec<u8>,
+}
+
+/// Writes packets to a rotating sequence of pcap files.
+#[derive(Debug)]
+pub struct PcapWriter {
+    directory: PathBuf,
+    snaplen: u32,
+    rotate_size: Option<u64>,
+    rotate_interval: Option<Duration>,
+    core_id: CoreId,
+    /// Current file, opened on first write.
+    wtr: Option<BufWriter<File>>,
+    /// Index of the current file.
+    file_idx: usize,
+    /// Bytes written to the current file.
+    file_len: u64,
+    /// Time the current file was opened.
+    file_start: Instant,
+    /// Set after a write error, to stop writing and avoid logging the error for each packet.
+    failed: bool,
+}
+
+impl PcapWriter {
+    /// Creates a writer for `core_id`. Files are only created once packets are written.
+    pub fn new(config: &PcapWriterConfig, core_id: CoreId) -> Self {
+        PcapWriter {
+            directory: PathBuf::from(&config.directory),
+            snaplen: config.snaplen,
+            rotate_size: config.rotate_size,
+            rotate_interval: config.rotate_interval.map(Duration::from_secs),
+            core_id,
+            wtr: None,
+            file_idx: 0,
+            file_len: 0,
+            file_start: Instant::now(),
+            failed: false,
+        }
+    }
+
+    /// Applies a new configuration. If the directory or snapshot length changed, the current file
+    /// is closed and packets are written to a new file.
+    pub(crate) fn reconfigure(&mut self, config: &PcapWriterConfig) {
+        let directory = PathBuf::from(&config.directory);
+        if directory != self.directory || config.snaplen != self.snaplen {
+            self.flush();
+            if self.wtr.take().is_some() {
+                self.file_idx += 1;
+            }
+            self.directory = directory;
+            self.snaplen = config.snaplen;
+            self.failed = false;
+        }
+        self.rotate_size = config.rotate_size;
+        self.rotate_interval = config.rotate_interval.map(Duration::from_secs);
+    }
+
+    /// Writes the frame in `mbuf`, truncated to the snapshot length.
+    pub fn write_mbuf(&mut self, mbuf: &Mbuf) {
+        self.write(SystemTime::now(), mbuf.pkt_len() as u32, mbuf.data());
+    }
+
+    /// Flushes the current file.
+    pub fn flush(&mut self) {
+        if let Some(wtr) = &mut self.wtr {
+            if let Err(error) = wtr.flush() {
+                log::error!("Failed to flush pcap on core {}: {}", self.core_id, error);
+            }
+        }
+    }
+
+    /// Copies the frame in `mbuf`, truncated to the snapshot length, to be written later.
+    pub(crate) fn pending(&self, mbuf: &Mbuf) -> PendingPacket {
+        let data = mbuf.data();
+        PendingPacket {
+            ts: SystemTime::now(),
+            orig_len: data.len() as u32,
+            data: data[..data.len().min(self.snaplen as usize)].to_vec(),
+        }
+    }
+
+    /// Writes a packet copied with `pending`.
+    pub(crate) fn write_pending(&mut self, pkt: &PendingPacket) {
+        self.write(pkt.ts, pkt.orig_len, &pkt.data);
+    }
+
+    fn write(&mut self, ts: SystemTime, orig_len: u32, data: &[u8]) {
+        if self.failed {
+            return;
+        }
+        if let Err(error) = self.try_write(ts, orig_len, data) {
+            log::error!(
+                "Failed to write pcap on core {}, disabling packet capture: {}",
+                self.core_id,
+                error
+            );
+            self.failed = true;
+        }
+    }
+
+    fn try_write(&mut self, ts: SystemTime, orig_len: u32, data: &[u8]) -> io::Result<()> {
+        let caplen = data.len().min(self.snaplen as usize);
+        let rotate = self.wtr.is_some()
+            && (self.rotate_size.is_some_and(|size| self.file_len >= size)
+                || self
+                    .rotate_interval
+                    .is_some_and(|interval| self.file_start.elapsed() >= interval));
+        if rotate {
+            self.file_idx += 1;
+            if let Some(mut wtr) = self.wtr.take() {
+                wtr.flush()?;
+            }
+        }
+        if self.wtr.is_none() {
+            self.open()?;
+        }
+        write_record(self.wtr.as_mut().unwrap(), ts, orig_len, &data[..caplen])?;
+        self.file_len += RECORD_HEADER_LEN + caplen as u64;
+        Ok(())
+    }
+
+    /// Opens the next file and writes the pcap global header.
+    fn open(&mut self) -> io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        let path = self
+            .directory
+            .join(format!("core{}_{}.pcap", self.core_id, self.file_idx));
+        log::info!("Writing packets to {:?}", path);
+        let mut wtr = BufWriter::new(File::create(path)?);
+        write_header(&mut wtr, self.snaplen)?;
+        self.file_len = FILE_HEADER_LEN;
+        self.file_start = Instant::now();
+        self.wtr = Some(wtr);
+        Ok(())
+    }
+}
+
+/// A set of rotating pcap files that callbacks on all cores write to without contention.
+///
+/// Like [Sink](crate::utils::sink::Sink), each core writes to its own files. Writes from threads
+/// that are not DPDK cores share one additional set of files. Packets are timestamped when they
+/// are written.
+#[derive(Debug)]
+pub struct PcapSink {
+    config: PcapWriterConfig,
+    writers: Vec<Mutex<Option<PcapWriter>>>,
+}
+
+impl PcapSink {
+    /// Creates a sink that writes to `config.directory`. Files are created on first write.
+    pub fn new(config: PcapWriterConfig) -> Self {
+        PcapSink {
+            config,
+            writers: (0..=MAX_CORES).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    /// Writes `mbuf` to the files of the current core.
+    pub fn write(&self, mbuf: &Mbuf) {
+        self.write_all(std::iter::once(mbuf));
+    }
+
+    /// Writes `mbufs` to the files of the current core. The packets are not interleaved with
+    /// packets written by other callbacks on the same core.
+    pub fn write_all<'a>(&self, mbufs: impl IntoIterator<Item = &'a Mbuf>) {
+        let core_id = unsafe { dpdk::rte_lcore_id() } as usize;
+        let idx = core_id.min(MAX_CORES);
+        let mut slot = self.writers[idx].lock().unwrap();
+        let wtr = slot.get_or_insert_with(|| PcapWriter::new(&self.config, CoreId(idx as u32)));
+        for mbuf in mbufs {
+            wtr.write_mbuf(mbuf);
+        }
+    }
+
+    /// Writes the packets of a connection to a new file of its own, named after `five_tuple`.
+    /// Size and time limits do not apply to per-connection files.
+    pub fn write_connection(&self, five_tuple: &FiveTuple, mbufs: &[Mbuf]) {
+        let name = format!(
+            "{}_{}_{}.pcap",
+            five_tuple.orig, five_tuple.resp, five_tuple.proto
+        );
+        let path = PathBuf::from(&self.config.directory).join(name);
+        let result = fs::create_dir_all(&self.config.directory)
+            .and_then(|_| File::create(&path))
+            .and_then(|file| {
+                let mut wtr = BufWriter::new(file);
+                write_header(&mut wtr, self.config.snaplen)?;
+                for mbuf in mbufs {
+                    let data = mbuf.data();
+                    write_record(
+                        &mut wtr,
+                        SystemTime::now(),
+                        data.len() as u32,
+                        &data[..data.len().min(self.config.snaplen as usize)],
+                    )?;
+                }
+                wtr.flush()
+            });
+        if let Err(error) = result {
+            log::error!("Failed to write pcap {:?}: {}", path, error);
+        }
+    }
+
+    /// Flushes the current files of all cores.
+    pub fn flush(&self) {
+        for slot in self.writers.iter() {
+            if let Some(wtr) = slot.lock().unwrap().as_mut() {
+                wtr.flush();
+            }
+        }
+    }
+}
+
+/// Writes the pcap global header.
+fn write_header<W: Write>(wtr: &mut W, snaplen: u32) -> io::Result<()> {
+    wtr.write_all(&0xa1b2c3d4_u32.to_le_bytes())?;
+    wtr.write_all(&2_u16.to_le_bytes())?;
+    wtr.write_all(&4_u16.to_le_bytes())?;
+    wtr.write_all(&0_i32.to_le_bytes())?;
+    wtr.write_all(&0_u32.to_le_bytes())?;
+    wtr.write_all(&snaplen.to_le_bytes())?;
+    wtr.write_all(&LINKTYPE_ETHERNET.to_le_bytes())
+}
+
+/// Writes a packet record. `data` must already be truncated to the snapshot length.
+fn write_record<W: Write>(
+    wtr: &mut W,
+    ts: SystemTime,
+    orig_len: u32,
+    data: &[u8],
+) -> io::Result<()> {
+    let ts = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+    wtr.write_all(&(ts.as_secs() as u32).to_le_bytes())?;
+    wtr.write_all(&ts.subsec_micros().to_le_bytes())?;
+    wtr.write_all(&(data.len() as u32).to_le_bytes())?;
+    wtr.write_all(&orig_len.to_le_bytes())?;
+    wtr.write_all(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_pcap_rotate() {
+        let directory = std::env::temp_dir().join(format!("retina_pcap_{}", std::process::id()));
+        let config = PcapWriterConfig {
+            directory: directory.to_str().unwrap().to_string(),
+            snaplen: 4,
+            rotate_size: Some(FILE_HEADER_LEN + 1),
+            rotate_interval: None,
+        };
+        let mut writer = PcapWriter::new(&config, CoreId(1));
+        writer.write(UNIX_EPOCH, 6, &[0, 1, 2, 3, 4, 5]);
+        writer.write(UNIX_EPOCH, 2, &[0, 1]);
+        writer.flush();
+        drop(writer);
+        let first = fs::read(directory.join("core1_0.pcap")).unwrap();
+        assert_eq!(first.len() as u64, FILE_HEADER_LEN + RECORD_HEADER_LEN + 4);
+        assert_eq!(&first[32..36], &6_u32.to_le_bytes());
+        let second = fs::read(directory.join("core1_1.pcap")).unwrap();
+        assert_eq!(second.len() as u64, FILE_HEADER_LEN + RECORD_HEADER_LEN + 2);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
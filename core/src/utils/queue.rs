@@ -0,0 +1,118 @@
+//! Bounded queue of the output sinks.
+//!
+//! Callbacks queue serialized records on the packet processing cores, and dedicated sink threads
+//! take them from the queue. If the queue is full, the configured
+//! [DropPolicy](crate::config::DropPolicy) applies on the calling core. Closing the queue lets
+//! the sink threads take the remaining records, after which they receive `Disconnected`; records
+//! queued afterwards are dropped.
+
+use crate::config::DropPolicy;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+
+pub(crate) struct SinkQueue<M> {
+    drop_policy: DropPolicy,
+    /// Dropped when the queue is closed. Senders hold the read lock while queueing, so no record
+    /// is queued after the sink threads have stopped.
+    tx: RwLock<Option<Sender<M>>>,
+    /// Used by the sink threads, and by the sender to evict with `drop_oldest`.
+    rx: Receiver<M>,
+    /// Records dropped because the queue was full, or queued after it was closed.
+    dropped: AtomicU64,
+}
+
+impl<M> SinkQueue<M> {
+    pub(crate) fn new(depth: usize, drop_policy: DropPolicy) -> Self {
+        let (tx, rx) = crossbeam_channel::bounded(depth.max(1));
+        SinkQueue {
+            drop_policy,
+            tx: RwLock::new(Some(tx)),
+            rx,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns a receiver for a sink thread.
+    pub(crate) fn receiver(&self) -> Receiver<M> {
+        self.rx.clone()
+    }
+
+    /// Queues `msg`, applying the drop policy if the queue is full.
+    pub(crate) fn push(&self, mut msg: M) {
+        let tx = self.tx.read().unwrap();
+        let tx = match tx.as_ref() {
+            Some(tx) => tx,
+            None => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        loop {
+            match tx.try_send(msg) {
+                Ok(()) => return,
+                Err(TrySendError::Full(rejected)) => match self.drop_policy {
+                    DropPolicy::DropNewest => {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        return;
+                    }
+                    DropPolicy::DropOldest => {
+                        // Sink threads may empty the queue concurrently, in which case nothing is
+                        // evicted and the send is retried
+                        if self.rx.try_recv().is_ok() {
+                            self.dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        msg = rejected;
+                    }
+                    DropPolicy::Block => {
+                        let _ = tx.send(rejected);
+                        return;
+                    }
+                },
+                Err(TrySendError::Disconnected(_)) => return,
+            }
+        }
+    }
+
+    /// Closes the queue. Sink threads receive `Disconnected` once it is empty.
+    pub(crate) fn close(&self) {
+        self.tx.write().unwrap().take();
+    }
+
+    /// Returns the number of records dropped.
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::TryRecvError;
+
+    #[test]
+    fn core_sink_queue() {
+        let queue = SinkQueue::new(1, DropPolicy::DropNewest);
+        let rx = queue.receiver();
+        queue.push(1);
+        queue.push(2);
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(rx.try_recv(), Ok(1));
+
+        let queue = SinkQueue::new(1, DropPolicy::DropOldest);
+        let rx = queue.receiver();
+        queue.push(1);
+        queue.push(2);
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(rx.try_recv(), Ok(2));
+
+        queue.push(3);
+        queue.close();
+        queue.push(4);
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+}
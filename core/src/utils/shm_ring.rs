@@ -0,0 +1,246 @@
+//! Shared-memory ring output.
+//!
+//! A [ShmRing](ShmRing) hands records (e.g., subscribed data serialized as JSON) to a separate
+//! process through a ring buffer in a memory-mapped file, typically under `/dev/shm`. The consumer
+//! can be written in any language, and runs heavyweight analysis without slowing down the packet
+//! processing cores. When the consumer falls behind and the ring is full, records are dropped and
+//! counted rather than blocking the cores.
+//!
+//! ## Example
+//! ```rust,ignore
+//! use retina_core::utils::shm_ring::ShmRing;
+//! use lazy_static::lazy_static;
+//!
+//! lazy_static! {
+//!     static ref RING: ShmRing = ShmRing::create("/dev/shm/retina_tls", 1 << 26).unwrap();
+//! }
+//!
+//! #[filter("tls")]
+//! fn tls_cb(tls: &TlsHandshake) {
+//!     RING.write_json(tls).unwrap();
+//! }
+//! ```
+//!
+//! `examples/shm_ring_reader.py` is a reference consumer.
+//!
+//! ## Layout
+//! All integers are little-endian. The file starts with a 192-byte header:
+//!
+//! | Offset | Type      | Field                                                            |
+//! |--------|-----------|------------------------------------------------------------------|
+//! | 0      | `[u8; 8]` | Magic, `RETINARB`                                                |
+//! | 8      | `u32`     | Version, `1`                                                     |
+//! | 16     | `u64`     | Capacity of the data area in bytes (a power of 2)                |
+//! | 24     | `u64`     | Number of records dropped because the ring was full              |
+//! | 64     | `u64`     | Head: position up to which space is reserved by writers          |
+//! | 128    | `u64`     | Tail: position up to which the consumer has read                 |
+//!
+//! The data area follows the header. Positions increase monotonically, and map to offset
+//! `position % capacity` in the data area. Each record starts at an 8-byte aligned position with a
+//! `u32` length and a `u32` flags word, followed by `length` bytes of payload and padding to the
+//! next multiple of 8. Flags are `0` while the record is being written, `1` once it is complete,
+//! and `2` for filler that skips to the end of the data area (records never wrap around).
+//!
+//! The consumer reads the record at the tail once its flags word is non-zero (with acquire
+//! semantics), zeroes the whole record (header included), then advances the tail past it (with
+//! release semantics). Records are consumed in the order their space was reserved; a record that
+//! is still being written blocks those after it.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use memmap2::MmapMut;
+use serde::Serialize;
+
+const MAGIC: [u8; 8] = *b"RETINARB";
+const VERSION: u32 = 1;
+
+const HEADER_SIZE: usize = 192;
+const VERSION_OFFSET: usize = 8;
+const CAPACITY_OFFSET: usize = 16;
+const DROPPED_OFFSET: usize = 24;
+const HEAD_OFFSET: usize = 64;
+const TAIL_OFFSET: usize = 128;
+
+const RECORD_HEADER_SIZE: usize = 8;
+const RECORD: u32 = 1;
+const FILLER: u32 = 2;
+
+/// A ring buffer in shared memory, written to by all cores and read by another process.
+#[derive(Debug)]
+pub struct ShmRing {
+    path: PathBuf,
+    capacity: usize,
+    base: *mut u8,
+    _map: MmapMut,
+}
+
+// Safety: the mapping lives as long as the ShmRing, and concurrent writers only access the
+// header through atomics, and the data area through disjoint reservations.
+unsafe impl Send for ShmRing {}
+unsafe impl Sync for ShmRing {}
+
+impl ShmRing {
+    /// Creates the file at `path` (replacing any existing file) and maps a ring with `capacity`
+    /// bytes of data. `capacity` must be a power of 2, of at least 64 bytes.
+    pub fn create<P: AsRef<Path>>(path: P, capacity: usize) -> io::Result<Self> {
+        if !capacity.is_power_of_two() || capacity < 64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("ring capacity {} is not a power of 2 >= 64", capacity),
+            ));
+        }
+        let file: File = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path.as_ref())?;
+        file.set_len((HEADER_SIZE + capacity) as u64)?;
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        map[..MAGIC.len()].copy_from_slice(&MAGIC);
+        map[VERSION_OFFSET..VERSION_OFFSET + 4].copy_from_slice(&VERSION.to_le_bytes());
+        map[CAPACITY_OFFSET..CAPACITY_OFFSET + 8].copy_from_slice(&(capacity as u64).to_le_bytes());
+        let base = map.as_mut_ptr();
+        Ok(ShmRing {
+            path: path.as_ref().to_path_buf(),
+            capacity,
+            base,
+            _map: map,
+        })
+    }
+
+    /// Returns the path of the shared memory file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the number of records dropped because the ring was full or they were too large.
+    pub fn dropped(&self) -> u64 {
+        self.atomic_u64(DROPPED_OFFSET).load(Ordering::Relaxed)
+    }
+
+    /// Writes `record` to the ring. Returns `false` if it was dropped, because the ring is full or
+    /// the record is larger than half of its capacity.
+    pub fn write(&self, record: &[u8]) -> bool {
+        let stride = align(RECORD_HEADER_SIZE + record.len());
+        if stride > self.capacity / 2 {
+            self.drop_record();
+            return false;
+        }
+        let capacity = self.capacity as u64;
+        let head = self.atomic_u64(HEAD_OFFSET);
+        let tail = self.atomic_u64(TAIL_OFFSET);
+        let mut pos = head.load(Ordering::Relaxed);
+        let start = loop {
+            // Records do not wrap around, skip to the start of the data area instead
+            let contiguous = capacity - (pos & (capacity - 1));
+            let start = if stride as u64 > contiguous {
+                pos + contiguous
+            } else {
+                pos
+            };
+            let end = start + stride as u64;
+            // Acquire: the consumer zeroed the space it released
+            if end - tail.load(Ordering::Acquire) > capacity {
+                self.drop_record();
+                return false;
+            }
+            match head.compare_exchange_weak(pos, end, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => break start,
+                Err(current) => pos = current,
+            }
+        };
+        if start != pos {
+            self.commit(pos, (start - pos) as usize - RECORD_HEADER_SIZE, FILLER);
+        }
+        unsafe {
+            let dst = self.data(start).add(RECORD_HEADER_SIZE);
+            ptr::copy_nonoverlapping(record.as_ptr(), dst, record.len());
+        }
+        self.commit(start, record.len(), RECORD);
+        true
+    }
+
+    /// Serializes `value` as JSON and writes it to the ring. Returns `Ok(false)` if the record was
+    /// dropped (see [write](ShmRing::write)).
+    pub fn write_json<T: Serialize + ?Sized>(&self, value: &T) -> serde_json::Result<bool> {
+        let record = serde_json::to_vec(value)?;
+        Ok(self.write(&record))
+    }
+
+    /// Publishes the record at `pos`, once its payload is written.
+    fn commit(&self, pos: u64, len: usize, flags: u32) {
+        unsafe {
+            let header = self.data(pos);
+            ptr::write(header as *mut [u8; 4], (len as u32).to_le_bytes());
+            let flags_word = &*(header.add(4) as *const AtomicU32);
+            flags_word.store(flags.to_le(), Ordering::Release);
+        }
+    }
+
+    fn drop_record(&self) {
+        self.atomic_u64(DROPPED_OFFSET)
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn data(&self, pos: u64) -> *mut u8 {
+        let offset = pos as usize & (self.capacity - 1);
+        unsafe { self.base.add(HEADER_SIZE + offset) }
+    }
+
+    fn atomic_u64(&self, offset: usize) -> &AtomicU64 {
+        unsafe { &*(self.base.add(offset) as *const AtomicU64) }
+    }
+}
+
+fn align(len: usize) -> usize {
+    (len + 7) & !7
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads the next complete record, as an external consumer would.
+    fn read(ring: &ShmRing) -> Option<Vec<u8>> {
+        loop {
+            let tail = ring.atomic_u64(TAIL_OFFSET).load(Ordering::Relaxed);
+            let header = ring.data(tail);
+            let flags = unsafe { &*(header.add(4) as *const AtomicU32) }.load(Ordering::Acquire);
+            if flags == 0 {
+                return None;
+            }
+            let len = u32::from_le_bytes(unsafe { ptr::read(header as *const [u8; 4]) }) as usize;
+            let stride = align(RECORD_HEADER_SIZE + len);
+            let record = unsafe { std::slice::from_raw_parts(header.add(8), len) }.to_vec();
+            unsafe { ptr::write_bytes(header, 0, stride) };
+            ring.atomic_u64(TAIL_OFFSET)
+                .store(tail + stride as u64, Ordering::Release);
+            if flags == RECORD {
+                return Some(record);
+            }
+        }
+    }
+
+    #[test]
+    fn core_shm_ring_wrap() {
+        let path = std::env::temp_dir().join(format!("retina_ring_{}", std::process::id()));
+        let ring = ShmRing::create(&path, 64).unwrap();
+        assert!(ring.write(b"first record"));
+        assert!(ring.write(b"second record"));
+        // 16 bytes left before the end, the record and the filler do not fit
+        assert!(!ring.write(b"third record, too long"));
+        assert_eq!(ring.dropped(), 1);
+        assert_eq!(read(&ring).unwrap(), b"first record");
+        assert_eq!(read(&ring).unwrap(), b"second record");
+        assert_eq!(read(&ring), None);
+        assert!(ring.write(b"third record, too long"));
+        assert_eq!(read(&ring).unwrap(), b"third record, too long");
+        assert!(!ring.write(&[0; 32]));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+//! Per-core output sinks.
+//!
+//! Callbacks run concurrently on all packet processing cores, so writing results to a single file
+//! requires synchronization on every write. A [Sink](Sink) instead gives each core its own
+//! buffered file, and concatenates the per-core files into the output file once the runtime has
+//! stopped. The output file can be changed in the runtime configuration (see
+//! [RuntimeConfig](crate::config::RuntimeConfig)'s `sinks`), including on SIGHUP.
+//!
+//! ## Example
+//! ```rust,ignore
+//! use retina_core::utils::sink::Sink;
+//! use lazy_static::lazy_static;
+//!
+//! lazy_static! {
+//!     static ref SINK: Sink = Sink::new("tls.jsonl");
+//! }
+//!
+//! #[filter("tls")]
+//! fn tls_cb(tls: &TlsHandshake) {
+//!     SINK.write_line(tls.sni()).unwrap();
+//! }
+//!
+//! #[retina_main(1)]
+//! fn main() {
+//!     let config = default_config();
+//!     let mut runtime: Runtime<SubscribedWrapper> = Runtime::new(config, filter).unwrap();
+//!     // Per-core files are merged into `tls.jsonl` when `run` returns
+//!     runtime.add_sink(&SINK);
+//!     runtime.run();
+//! }
+//! ```
+
+use crate::dpdk;
+use crate::lcore::MAX_CORES;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// An output file written to by all cores without contention.
+///
+/// Each core writes to its own file (`<path>.core<id>`). Writes from threads that are not DPDK
+/// cores share one additional file. Each file is behind its own lock, which is only contended if
+/// non-DPDK threads write to the sink concurrently.
+#[derive(Debug)]
+pub struct Sink {
+    path: PathBuf,
+    /// Output file set in the runtime configuration, if any.
+    destination: Mutex<Option<PathBuf>>,
+    writers: Vec<Mutex<Option<BufWriter<File>>>>,
+}
+
+impl Sink {
+    /// Creates a sink whose per-core files are merged into `path`. Files are created on first
+    /// write.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Sink {
+            path: path.as_ref().to_path_buf(),
+            destination: Mutex::new(None),
+            writers: (0..=MAX_CORES).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    /// Returns the path the sink was created with, which names its per-core files.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of the merged output file: the one set in the runtime configuration, or
+    /// the path the sink was created with.
+    pub fn destination(&self) -> PathBuf {
+        let destination = self.destination.lock().unwrap();
+        destination.clone().unwrap_or_else(|| self.path.clone())
+    }
+
+    /// Sets the output file, or restores the path the sink was created with if `None`. Records
+    /// written so far are first merged into the previous output file.
+    pub(crate) fn redirect(&self, destination: Option<PathBuf>) -> io::Result<()> {
+        if *self.destination.lock().unwrap() == destination {
+            return Ok(());
+        }
+        if self
+            .writers
+            .iter()
+            .any(|slot| slot.lock().unwrap().is_some())
+        {
+            self.merge()?;
+        }
+        *self.destination.lock().unwrap() = destination;
+        Ok(())
+    }
+
+    /// Writes `buf` to the file of the current core.
+    pub fn write(&self, buf: &[u8]) -> io::Result<()> {
+        let core_id = unsafe { dpdk::rte_lcore_id() } as usize;
+        self.write_core(core_id, buf)
+    }
+
+    /// Writes `line` followed by a newline to the file of the current core.
+    pub fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(line.len() + 1);
+        buf.extend_from_slice(line.as_bytes());
+        buf.push(b'\n');
+        self.write(&buf)
+    }
+
+    /// Flushes all per-core files and concatenates them, in core order, into the output file.
+    /// The per-core files are removed.
+    ///
+    /// This is called by the runtime for sinks registered with
+    /// [Runtime::add_sink](crate::Runtime::add_sink) after all cores have stopped. Writes after
+    /// merging start new per-core files.
+    pub fn merge(&self) -> io::Result<()> {
+        let mut output = BufWriter::new(File::create(self.destination())?);
+        for (idx, slot) in self.writers.iter().enumerate() {
+            // Writes of the core wait until its file is merged, rather than recreate it
+            let mut slot = slot.lock().unwrap();
+            if let Some(mut wtr) = slot.take() {
+                wtr.flush()?;
+                drop(wtr);
+                let core_path = self.core_path(idx);
+                io::copy(&mut File::open(&core_path)?, &mut output)?;
+                fs::remove_file(core_path)?;
+            }
+        }
+        output.flush()
+    }
+
+    fn write_core(&self, core_id: usize, buf: &[u8]) -> io::Result<()> {
+        // Non-DPDK threads (e.g., `LCORE_ID_ANY`) share the last file
+        let idx = core_id.min(MAX_CORES);
+        let mut slot = self.writers[idx].lock().unwrap();
+        if slot.is_none() {
+            *slot = Some(BufWriter::new(File::create(self.core_path(idx))?));
+        }
+        slot.as_mut().unwrap().write_all(buf)
+    }
+
+    fn core_path(&self, idx: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".core{}", idx));
+        PathBuf::from(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_sink_merge() {
+        let path = std::env::temp_dir().join(format!("retina_sink_{}.txt", std::process::id()));
+        let sink = Sink::new(&path);
+        sink.write_core(2, b"c\n").unwrap();
+        sink.write_core(1, b"a\n").unwrap();
+        sink.write_core(1, b"b\n").unwrap();
+        sink.write_core(u16::MAX as usize, b"d\n").unwrap();
+        sink.merge().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\nc\nd\n");
+        assert!(!sink.core_path(1).exists());
+
+        // Records written before a redirection are merged into the previous file
+        let redirected = path.with_extension("redirected");
+        sink.write_core(1, b"e\n").unwrap();
+        sink.redirect(Some(redirected.clone())).unwrap();
+        sink.write_core(1, b"f\n").unwrap();
+        sink.merge().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "e\n");
+        assert_eq!(fs::read_to_string(&redirected).unwrap(), "f\n");
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&redirected).unwrap();
+    }
+}
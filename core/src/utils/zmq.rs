@@ -0,0 +1,158 @@
+//! ZeroMQ publish sink.
+//!
+//! A [ZmqSink](ZmqSink) publishes records of any datatype that implements `Serialize` on a
+//! ZeroMQ PUB socket, as a lighter-weight alternative to [KafkaSink](crate::utils::kafka) for
+//! streaming records to external subscribers: no broker, no persistence, and slow subscribers lose
+//! messages instead of slowing down Retina. Each message has two frames, the topic and the record
+//! serialized as JSON. Subscribers select records by topic prefix (e.g.,
+//! `zmq_setsockopt(ZMQ_SUBSCRIBE, "tls")`). See [ZmqConfig](crate::config::ZmqConfig).
+//!
+//! The topic is not derived from the subscription that matched: each call to
+//! [send](ZmqSink::send) passes a key chosen by the application, which is mapped to a topic by
+//! [ZmqConfig::topics](crate::config::ZmqConfig::topics), or used as the topic if it is not
+//! mapped. Using the name of the callback as the key gives each subscription its own topic, as in
+//! the example below.
+//!
+//! Records are serialized on the calling core and queued for a dedicated thread that owns the
+//! socket. The sink must be closed after the runtime stops, to publish queued records.
+//!
+//! ## Example
+//! ```rust,ignore
+//! use retina_core::config::ZmqConfig;
+//! use retina_core::utils::zmq::ZmqSink;
+//! use lazy_static::lazy_static;
+//!
+//! lazy_static! {
+//!     static ref ZMQ: ZmqSink = ZmqSink::new(ZmqConfig::default()).expect("ZeroMQ socket");
+//! }
+//!
+//! #[filter("tls")]
+//! fn tls_cb(tls: &TlsHandshake) {
+//!     ZMQ.send("tls_cb", tls);
+//! }
+//!
+//! #[filter("dns")]
+//! fn dns_cb(dns: &DnsTransaction) {
+//!     ZMQ.send("dns_cb", dns);
+//! }
+//!
+//! #[retina_main(2)]
+//! fn main() {
+//!     let config = default_config();
+//!     let mut runtime: Runtime<SubscribedWrapper> = Runtime::new(config, filter).unwrap();
+//!     runtime.run();
+//!     ZMQ.close();
+//! }
+//! ```
+
+use super::queue::SinkQueue;
+use crate::config::ZmqConfig;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+
+use anyhow::Result;
+use crossbeam_channel::Receiver;
+use serde::Serialize;
+
+/// A serialized record and its topic.
+type Message = (Vec<u8>, Vec<u8>);
+
+/// Publishes records on a ZeroMQ PUB socket.
+pub struct ZmqSink {
+    config: ZmqConfig,
+    queue: SinkQueue<Message>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    /// Records that failed to serialize.
+    failed: AtomicU64,
+}
+
+impl ZmqSink {
+    /// Binds the PUB socket and starts the sink thread.
+    pub fn new(config: ZmqConfig) -> Result<Self> {
+        let context = zmq::Context::new();
+        let socket = context.socket(zmq::PUB)?;
+        socket.set_sndhwm(config.high_water_mark)?;
+        socket.bind(&config.endpoint)?;
+        let queue = SinkQueue::new(config.queue_depth, config.drop_policy);
+        let rx = queue.receiver();
+        let thread = std::thread::Builder::new()
+            .name("retina-zmq".to_string())
+            .spawn(move || publish(socket, rx))?;
+        log::info!("Publishing records on {}", config.endpoint);
+        Ok(ZmqSink {
+            config,
+            queue,
+            thread: Mutex::new(Some(thread)),
+            failed: AtomicU64::new(0),
+        })
+    }
+
+    /// Publishes `record` under the topic mapped to `key` (see
+    /// [ZmqConfig::topics](crate::config::ZmqConfig::topics)), or under `key` if it is not mapped.
+    pub fn send<T: Serialize + ?Sized>(&self, key: &str, record: &T) {
+        let payload = match serde_json::to_vec(record) {
+            Ok(payload) => payload,
+            Err(error) => {
+                if self.failed.fetch_add(1, Ordering::Relaxed) == 0 {
+                    log::error!("ZeroMQ sink: {}", error);
+                }
+                return;
+            }
+        };
+        let topic = topic(&self.config, key).as_bytes().to_vec();
+        self.queue.push((topic, payload));
+    }
+
+    /// Publishes the queued records and stops the sink thread. Records sent afterwards are
+    /// dropped.
+    pub fn close(&self) {
+        self.queue.close();
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+        let (dropped, failed) = (self.queue.dropped(), self.failed.load(Ordering::Relaxed));
+        if dropped > 0 || failed > 0 {
+            log::warn!(
+                "ZeroMQ sink {}: {} records dropped (queue full), {} failed",
+                self.config.endpoint,
+                dropped,
+                failed
+            );
+        }
+    }
+}
+
+/// Returns the topic of the records sent with `key`.
+fn topic<'a>(config: &'a ZmqConfig, key: &'a str) -> &'a str {
+    config.topics.get(key).map(String::as_str).unwrap_or(key)
+}
+
+/// Publishes queued records until the sink is closed and the queue is empty.
+fn publish(socket: zmq::Socket, queue: Receiver<Message>) {
+    let mut logged = false;
+    for (topic, payload) in queue.iter() {
+        if let Err(error) = socket.send_multipart([topic, payload], 0) {
+            if !logged {
+                log::error!("ZeroMQ sink: {}", error);
+                logged = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_zmq_topic() {
+        let mut config = ZmqConfig::default();
+        config
+            .topics
+            .insert("tls_cb".to_string(), "tls".to_string());
+        assert_eq!(topic(&config, "tls_cb"), "tls");
+        assert_eq!(topic(&config, "dns_cb"), "dns_cb");
+    }
+}
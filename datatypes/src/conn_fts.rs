@@ -2,6 +2,7 @@
 //! connection information, statistics, and state history.
 
 use crate::Tracked;
+use retina_core::conntrack::pdu::IcmpError;
 use retina_core::L4Pdu;
 use serde::ser::{Serialize, SerializeSeq, SerializeStruct, Serializer};
 use std::time::{Duration, Instant};
@@ -27,6 +28,27 @@ impl Serialize for ConnDuration {
     }
 }
 
+/// Deserializes the duration, from a start time at the time of deserialization.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ConnDuration {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            duration: u64,
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        let start_ts = Instant::now();
+        Ok(ConnDuration {
+            start_ts,
+            last_ts: start_ts + Duration::from_millis(fields.duration),
+        })
+    }
+}
+
 impl ConnDuration {
     /// The duration of the connection in milliseconds
     pub fn duration_ms(&self) -> u128 {
@@ -40,8 +62,8 @@ impl ConnDuration {
 }
 
 impl Tracked for ConnDuration {
-    fn new(_first_pkt: &L4Pdu) -> Self {
-        let now = Instant::now();
+    fn new(first_pkt: &L4Pdu) -> Self {
+        let now = first_pkt.mbuf_ref().rx_time();
         Self {
             start_ts: now,
             last_ts: now,
@@ -52,9 +74,9 @@ impl Tracked for ConnDuration {
     fn clear(&mut self) {}
 
     #[inline]
-    fn update(&mut self, _pdu: &L4Pdu, reassembled: bool) {
+    fn update(&mut self, pdu: &L4Pdu, reassembled: bool) {
         if !reassembled {
-            self.last_ts = Instant::now();
+            self.last_ts = pdu.mbuf_ref().rx_time();
         }
     }
 
@@ -65,6 +87,7 @@ impl Tracked for ConnDuration {
 
 /// The number of packets observed in a connection
 #[derive(Debug, serde::Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
 pub struct PktCount {
     pub pkt_count: usize,
 }
@@ -97,6 +120,7 @@ impl Tracked for PktCount {
 
 /// The number of bytes, including headers, observed in a connection
 #[derive(Debug, serde::Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
 pub struct ByteCount {
     pub byte_count: usize,
 }
@@ -118,7 +142,7 @@ impl Tracked for ByteCount {
     #[inline]
     fn update(&mut self, pdu: &L4Pdu, reassembled: bool) {
         if !reassembled {
-            self.byte_count += pdu.mbuf_ref().data_len();
+            self.byte_count += pdu.mbuf_ref().pkt_len();
         }
     }
 
@@ -164,7 +188,7 @@ impl Tracked for InterArrivals {
     #[inline]
     fn update(&mut self, pdu: &L4Pdu, reassembled: bool) {
         if !reassembled {
-            let now = Instant::now();
+            let now = pdu.mbuf_ref().rx_time();
             if pdu.dir {
                 self.pkt_count_ctos += 1;
                 if self.pkt_count_ctos > 1 {
@@ -212,6 +236,30 @@ impl Serialize for InterArrivals {
     }
 }
 
+/// Deserializes the inter-arrival times. Packet counts and arrival times are not serialized, and
+/// are reset as of the time of deserialization.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for InterArrivals {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            interarrivals_ctos: Vec<u64>,
+            interarrivals_stoc: Vec<u64>,
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        let durations = |nanos: Vec<u64>| nanos.into_iter().map(Duration::from_nanos).collect();
+        Ok(InterArrivals {
+            interarrivals_ctos: durations(fields.interarrivals_ctos),
+            interarrivals_stoc: durations(fields.interarrivals_stoc),
+            ..InterArrivals::new_empty()
+        })
+    }
+}
+
 use crate::connection::update_history;
 
 /// Connection history.
@@ -230,6 +278,7 @@ use crate::connection::update_history;
 ///
 /// Each letter is recorded a maximum of once in either direction.
 #[derive(Default, Debug, serde::Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
 pub struct ConnHistory {
     pub history: Vec<u8>,
 }
@@ -259,3 +308,85 @@ impl Tracked for ConnHistory {
         vec![]
     }
 }
+
+/// Requests that all packets in matched connections be written to pcap files by the framework.
+///
+/// Packets are written pre-reassembly, in the order received, including those received before the
+/// connection matched the filter. Output files and rotation are set in the
+/// [PcapWriterConfig](retina_core::config::PcapWriterConfig). The delivered value holds the number
+/// of packets observed in the connection.
+#[derive(Debug, serde::Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub struct PcapCapture {
+    pub pkt_count: usize,
+}
+
+impl Tracked for PcapCapture {
+    fn new(_first_pkt: &L4Pdu) -> Self {
+        Self { pkt_count: 0 }
+    }
+
+    #[inline]
+    fn clear(&mut self) {}
+
+    #[inline]
+    fn update(&mut self, _pdu: &L4Pdu, reassembled: bool) {
+        if !reassembled {
+            self.pkt_count += 1;
+        }
+    }
+
+    fn stream_protocols() -> Vec<&'static str> {
+        vec![]
+    }
+}
+
+/// ICMP and ICMPv6 error messages (e.g., Destination Unreachable, Time Exceeded) received about
+/// packets of a connection, in the order received.
+///
+/// ICMP error messages are only attributed to connections if they pass the packet filter (e.g.,
+/// `tcp or icmp` rather than `tcp`).
+#[derive(Default, Debug, serde::Serialize, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub struct IcmpErrors {
+    /// Errors about packets sent by the originator.
+    pub orig: Vec<IcmpError>,
+    /// Errors about packets sent by the responder.
+    pub resp: Vec<IcmpError>,
+}
+
+impl IcmpErrors {
+    /// The total number of errors
+    pub fn len(&self) -> usize {
+        self.orig.len() + self.resp.len()
+    }
+
+    /// Returns `true` if no error was received
+    pub fn is_empty(&self) -> bool {
+        self.orig.is_empty() && self.resp.is_empty()
+    }
+}
+
+impl Tracked for IcmpErrors {
+    fn new(_first_pkt: &L4Pdu) -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    fn clear(&mut self) {}
+
+    #[inline]
+    fn update(&mut self, _pdu: &L4Pdu, _reassembled: bool) {}
+
+    fn icmp_error(&mut self, error: &IcmpError, dir: bool) {
+        if dir {
+            self.orig.push(*error);
+        } else {
+            self.resp.push(*error);
+        }
+    }
+
+    fn stream_protocols() -> Vec<&'static str> {
+        vec![]
+    }
+}
@@ -2,17 +2,23 @@
 //! information, statistics, and state history. It does not deliver payload data.
 
 use retina_core::conntrack::conn::tcp_conn::reassembly::wrapping_lt;
+use retina_core::conntrack::conn::TerminationReason;
 use retina_core::conntrack::conn_id::FiveTuple;
+use retina_core::conntrack::mptcp::MptcpSubflow;
 use retina_core::conntrack::pdu::L4Pdu;
+use retina_core::conntrack::tunnel::Tunnels;
 use retina_core::protocols::packet::tcp::{ACK, FIN, RST, SYN};
 
 use super::Tracked;
 
+#[cfg(feature = "parquet")]
+use retina_core::utils::parquet::{arrow, Row, ToArrow};
+
 use serde::ser::{SerializeStruct, Serializer};
 use serde::Serialize;
 use std::time::{Duration, Instant};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::net::SocketAddr;
 
@@ -74,6 +80,47 @@ impl ConnRecord {
         self.last_seen_ts - self.first_seen_ts
     }
 
+    /// Returns why the connection was delivered (e.g., FIN, RST, or inactivity timeout), or `None`
+    /// if it has not terminated yet.
+    #[inline]
+    pub fn termination(&self) -> Option<TerminationReason> {
+        self.termination
+    }
+
+    /// Returns the token identifying the MPTCP connection the connection is a subflow of, or
+    /// `None` if it is a plain TCP (or UDP) connection. All subflows of an MPTCP connection share
+    /// the token.
+    #[inline]
+    pub fn mptcp_token(&self) -> Option<u32> {
+        self.mptcp.map(|subflow| subflow.token)
+    }
+
+    /// Aggregates `subflow`, another subflow of the same MPTCP connection, into the record:
+    /// timestamps span both subflows, and packet and byte counts of each direction are summed.
+    /// The 5-tuple and history remain those of the record, and sequence-space data (content gaps,
+    /// acknowledgments) is not merged.
+    ///
+    /// Records that are not subflows of the same MPTCP connection are left unchanged. Returns
+    /// whether `subflow` was aggregated.
+    pub fn aggregate_subflow(&mut self, subflow: &ConnRecord) -> bool {
+        if self.mptcp_token().is_none() || self.mptcp_token() != subflow.mptcp_token() {
+            return false;
+        }
+        self.first_seen_ts = std::cmp::min(self.first_seen_ts, subflow.first_seen_ts);
+        self.last_seen_ts = std::cmp::max(self.last_seen_ts, subflow.last_seen_ts);
+        self.max_inactivity = std::cmp::max(self.max_inactivity, subflow.max_inactivity);
+        for (flow, other) in [
+            (&mut self.orig, &subflow.orig),
+            (&mut self.resp, &subflow.resp),
+        ] {
+            flow.nb_pkts += other.nb_pkts;
+            flow.nb_malformed_pkts += other.nb_malformed_pkts;
+            flow.nb_late_start_pkts += other.nb_late_start_pkts;
+            flow.nb_bytes += other.nb_bytes;
+        }
+        true
+    }
+
     /// The duration (approximate) between the first and second packets.
     #[inline]
     pub fn time_to_second_packet(&self) -> Duration {
@@ -89,7 +136,7 @@ impl Serialize for ConnRecord {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("ConnRecord", 6)?;
+        let mut state = serializer.serialize_struct("ConnRecord", 10)?;
         state.serialize_field("five_tuple", &self.five_tuple)?;
         state.serialize_field("duration", &self.duration())?;
         state.serialize_field("time_to_second_pkt", &self.time_to_second_packet())?;
@@ -97,10 +144,88 @@ impl Serialize for ConnRecord {
         state.serialize_field("history", &self.history())?;
         state.serialize_field("orig", &self.orig)?;
         state.serialize_field("resp", &self.resp)?;
+        state.serialize_field("termination", &self.termination)?;
+        state.serialize_field("mptcp", &self.mptcp)?;
+        state.serialize_field("tunnels", &self.tunnels)?;
         state.end()
     }
 }
 
+/// Deserializes a record as it is serialized. Timestamps are not serialized: the first packet is
+/// set to the time of deserialization, and later packets to preserve the duration of the
+/// connection and the time to the second packet.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ConnRecord {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            five_tuple: FiveTuple,
+            duration: Duration,
+            time_to_second_pkt: Duration,
+            max_inactivity: Duration,
+            history: String,
+            orig: Flow,
+            resp: Flow,
+            termination: Option<TerminationReason>,
+            mptcp: Option<MptcpSubflow>,
+            tunnels: Tunnels,
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        let first_seen_ts = Instant::now();
+        Ok(ConnRecord {
+            five_tuple: fields.five_tuple,
+            first_seen_ts,
+            second_seen_ts: first_seen_ts + fields.time_to_second_pkt,
+            last_seen_ts: first_seen_ts + fields.duration,
+            max_inactivity: fields.max_inactivity,
+            history: fields.history.into_bytes(),
+            orig: fields.orig,
+            resp: fields.resp,
+            termination: fields.termination,
+            mptcp: fields.mptcp,
+            tunnels: fields.tunnels,
+        })
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl ToArrow for ConnRecord {
+    fn fields() -> Vec<arrow::datatypes::Field> {
+        use arrow::datatypes::{DataType, Field};
+        let mut fields = FiveTuple::fields();
+        fields.extend([
+            Field::new("duration_us", DataType::UInt64, false),
+            Field::new("history", DataType::Utf8, false),
+            Field::new("orig_pkts", DataType::UInt64, false),
+            Field::new("orig_bytes", DataType::UInt64, false),
+            Field::new("resp_pkts", DataType::UInt64, false),
+            Field::new("resp_bytes", DataType::UInt64, false),
+            Field::new("termination", DataType::Utf8, true),
+            Field::new("mptcp_token", DataType::UInt32, true),
+        ]);
+        fields
+    }
+
+    fn append(&self, row: &mut Row) {
+        self.five_tuple.append(row);
+        row.append_u64(Some(self.duration().as_micros() as u64));
+        row.append_str(Some(&self.history()));
+        row.append_u64(Some(self.orig.nb_pkts));
+        row.append_u64(Some(self.orig.nb_bytes));
+        row.append_u64(Some(self.resp.nb_pkts));
+        row.append_u64(Some(self.resp.nb_bytes));
+        let termination = self
+            .termination
+            .map(|reason| format!("{:?}", reason).to_lowercase());
+        row.append_str(termination.as_deref());
+        row.append_u32(self.mptcp_token());
+    }
+}
+
 impl fmt::Display for ConnRecord {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}: {}", self.five_tuple, self.history())?;
@@ -113,15 +238,16 @@ impl fmt::Display for ConnRecord {
 /// ## Note
 /// Internal connection state is an associated type of a `pub` trait, and therefore must also be
 /// public. Documentation is hidden by default to avoid confusing users.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConnRecord {
     /// The connection 5-tuple.
     pub five_tuple: FiveTuple,
     /// Timestamp of the first packet.
     ///
     /// ## Remarks
-    /// This represents the time Retina observed the first packet in the connection, and does not
-    /// reflect timestamps read from a packet capture in offline analysis.
+    /// This represents the time Retina observed the first packet in the connection (or the NIC
+    /// received it, if hardware timestamps are enabled), and does not reflect timestamps read from
+    /// a packet capture in offline analysis.
     pub first_seen_ts: Instant,
     /// Timestamp of the second packet (approximate).
     pub second_seen_ts: Instant,
@@ -149,6 +275,13 @@ pub struct ConnRecord {
     pub orig: Flow,
     /// Responder flow.
     pub resp: Flow,
+    /// Why the connection was delivered, set when it terminates.
+    pub termination: Option<TerminationReason>,
+    /// The MPTCP connection the connection is a subflow of, if its handshake negotiated MPTCP.
+    pub mptcp: Option<MptcpSubflow>,
+    /// The tunnels the connection was decapsulated from, from the outermost to the innermost (see
+    /// [tunnel](retina_core::conntrack::tunnel)).
+    pub tunnels: Tunnels,
 }
 
 #[inline]
@@ -180,21 +313,28 @@ pub(crate) fn update_history(history: &mut Vec<u8>, segment: &L4Pdu, mask: u8) {
 impl ConnRecord {
     #[inline]
     fn update_data(&mut self, segment: &L4Pdu) {
-        let now = Instant::now();
+        let now = segment.mbuf_ref().rx_time();
         let inactivity = now - self.last_seen_ts;
         if inactivity > self.max_inactivity {
             self.max_inactivity = inactivity;
         }
         self.last_seen_ts = now;
 
+        // MPTCP options of the handshake
+        if self.mptcp.is_none() && self.orig.nb_pkts + self.resp.nb_pkts < 3 {
+            self.mptcp = MptcpSubflow::from_pdu(segment);
+        }
+
         if segment.dir {
             update_history(&mut self.history, segment, 0x0);
             // TODO need a separate `update` for `update_owned`
             // Cloning segment is a non-starter.
             self.orig.insert_segment(segment);
+            self.resp.insert_ack(segment);
         } else {
             update_history(&mut self.history, segment, 0x20);
             self.resp.insert_segment(segment);
+            self.orig.insert_ack(segment);
         }
 
         if self.orig.nb_pkts + self.resp.nb_pkts == 2 {
@@ -206,7 +346,7 @@ impl ConnRecord {
 impl Tracked for ConnRecord {
     fn new(first_pkt: &L4Pdu) -> Self {
         let five_tuple = FiveTuple::from_ctxt(first_pkt.ctxt);
-        let now = Instant::now();
+        let now = first_pkt.mbuf_ref().rx_time();
         Self {
             five_tuple,
             first_seen_ts: now,
@@ -216,6 +356,9 @@ impl Tracked for ConnRecord {
             history: Vec::with_capacity(16),
             orig: Flow::new(),
             resp: Flow::new(),
+            termination: None,
+            mptcp: None,
+            tunnels: first_pkt.ctxt.tunnels,
         }
     }
 
@@ -223,8 +366,10 @@ impl Tracked for ConnRecord {
     fn clear(&mut self) {
         self.orig.chunks = Vec::with_capacity(0);
         self.orig.gaps = HashMap::with_capacity(0);
+        self.orig.sack_holes = HashSet::with_capacity(0);
         self.resp.chunks = Vec::with_capacity(0);
         self.resp.gaps = HashMap::with_capacity(0);
+        self.resp.sack_holes = HashSet::with_capacity(0);
         self.history = Vec::with_capacity(0);
     }
 
@@ -237,6 +382,10 @@ impl Tracked for ConnRecord {
     fn stream_protocols() -> Vec<&'static str> {
         vec![]
     }
+
+    fn terminate(&mut self, reason: TerminationReason) {
+        self.termination = Some(reason);
+    }
 }
 
 /// Default value for maximum chunk capacity.
@@ -244,6 +393,7 @@ const DEFAULT_CHUNK_CAPACITY: usize = 100;
 
 /// A uni-directional flow.
 #[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
 pub struct Flow {
     /// Number of packets seen for this flow, including malformed and late start segments.
     ///
@@ -276,6 +426,16 @@ pub struct Flow {
     /// Maps relative sequence number of a content gap to the number of packets observed before it
     /// is filled. Only applies to TCP flows.
     pub gaps: HashMap<u32, u64>,
+    /// Relative sequence number of the highest byte acknowledged by the receiver (exclusive).
+    /// Only applies to TCP flows.
+    pub max_acked: u32,
+    /// Number of SACK blocks sent by the receiver. Only applies to TCP flows that negotiated
+    /// SACK.
+    pub nb_sack_blocks: u64,
+    /// Relative sequence numbers of the holes reported by the receiver, i.e., of the cumulative
+    /// acknowledgments sent with SACK blocks beyond them. Only applies to TCP flows that
+    /// negotiated SACK.
+    pub sack_holes: HashSet<u32>,
 }
 
 impl Flow {
@@ -290,6 +450,9 @@ impl Flow {
             capacity: DEFAULT_CHUNK_CAPACITY,
             chunks: Vec::with_capacity(DEFAULT_CHUNK_CAPACITY),
             gaps: HashMap::new(),
+            max_acked: 0,
+            nb_sack_blocks: 0,
+            sack_holes: HashSet::new(),
         }
     }
 
@@ -328,6 +491,29 @@ impl Flow {
         }
     }
 
+    /// Updates the data acknowledged by the receiver with `segment`, sent in the opposite
+    /// direction.
+    #[inline]
+    fn insert_ack(&mut self, segment: &L4Pdu) {
+        if segment.flags() & ACK == 0 || self.chunks.is_empty() {
+            return;
+        }
+        let ack = segment.ack_no();
+        if wrapping_lt(ack, self.data_start) {
+            return;
+        }
+        let acked = ack.wrapping_sub(self.data_start);
+        if acked > self.max_acked {
+            self.max_acked = acked;
+        }
+        let blocks = segment.sack_blocks();
+        self.nb_sack_blocks += blocks.len() as u64;
+        // Blocks below the cumulative acknowledgment report duplicates (D-SACK), not holes
+        if blocks.iter().any(|(left, _)| wrapping_lt(ack, *left)) {
+            self.sack_holes.insert(acked);
+        }
+    }
+
     /// Insert `chunk` into flow, merging intervals as necessary. Flow `chunks` are a sorted set of
     /// non-overlapping intervals.
     #[inline]
@@ -381,6 +567,45 @@ impl Flow {
         self.chunks.windows(2).map(|w| w[1].0 - w[0].1).sum::<u32>() as u64
     }
 
+    /// Returns the number of loss events reported by the receiver with SACK.
+    ///
+    /// Each hole in the data received by the receiver is counted once, however many SACK blocks
+    /// report it. Holes are caused by packets lost (or heavily reordered) between the sender and
+    /// the receiver. Always `0` if the connection did not negotiate SACK.
+    #[inline]
+    pub fn loss_events(&self) -> u64 {
+        self.sack_holes.len() as u64
+    }
+
+    /// Returns the number of content gaps that were filled without the receiver reporting them
+    /// with SACK, i.e., that were caused by reordering rather than loss.
+    ///
+    /// Only meaningful if the connection negotiated SACK: otherwise, every filled gap is counted.
+    #[inline]
+    pub fn reordered_gaps(&self) -> u64 {
+        let open = &self.chunks[..self.chunks.len().saturating_sub(1)];
+        self.gaps
+            .keys()
+            .filter(|start| !self.sack_holes.contains(start))
+            .filter(|start| open.iter().all(|chunk| chunk.1 != **start))
+            .count() as u64
+    }
+
+    /// Returns the number of payload bytes acknowledged by the receiver but missed in content
+    /// gaps at connection end.
+    ///
+    /// These bytes reached the receiver but were not observed, e.g., because they were dropped by
+    /// the NIC or by the capture, rather than lost in the network. Missing data after the last
+    /// observed segment is not counted.
+    #[inline]
+    pub fn capture_missed_bytes(&self) -> u64 {
+        self.chunks
+            .windows(2)
+            .filter(|w| w[1].0 <= self.max_acked)
+            .map(|w| (w[1].0 - w[0].1) as u64)
+            .sum()
+    }
+
     /// Returns the mean number of packet arrivals before a content gap is filled, or `0` if there
     /// were no gaps.
     #[inline]
@@ -411,6 +636,7 @@ impl Flow {
 
 /// Start (inclusive) and end (exclusive) interval of contiguous TCP payload bytes.
 #[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
 pub struct Chunk(u32, u32);
 
 #[cfg(test)]
@@ -464,4 +690,21 @@ mod tests {
         flow.merge_chunk(Chunk(11, 15));
         assert_eq!(flow.chunks, vec![Chunk(4, 6), Chunk(8, 10), Chunk(11, 15)]);
     }
+
+    #[test]
+    fn core_gap_accounting() {
+        let mut flow = Flow::new();
+        flow.merge_chunk(Chunk(0, 3));
+        flow.merge_chunk(Chunk(4, 5));
+        flow.merge_chunk(Chunk(8, 10));
+        flow.merge_chunk(Chunk(3, 4));
+        flow.sack_holes.insert(3);
+        flow.max_acked = 10;
+        assert_eq!(flow.loss_events(), 1);
+        assert_eq!(flow.reordered_gaps(), 0);
+        assert_eq!(flow.capture_missed_bytes(), 3);
+        flow.merge_chunk(Chunk(5, 8));
+        assert_eq!(flow.reordered_gaps(), 1);
+        assert_eq!(flow.capture_missed_bytes(), 0);
+    }
 }
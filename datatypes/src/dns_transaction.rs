@@ -1,13 +1,93 @@
 //! A DNS transaction.
 //! Subscribable alias for [`retina_core::protocols::stream::dns::Dns`]
 
-use retina_core::protocols::stream::dns::Dns;
+use retina_core::protocols::stream::dns::{Dns, DnsRecord};
 use retina_core::protocols::stream::{Session, SessionData};
 
 use super::{FromSession, SessionList};
 
 pub type DnsTransaction = Box<Dns>;
 
+/// Borrowed variant of [DnsTransaction]: a view of the parsed session, delivered by reference
+/// and never copied. Callbacks taking it are always invoked on the packet processing core, and
+/// its accessors borrow the query and response for the duration of the callback.
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct DnsTransactionRef(Dns);
+
+impl DnsTransactionRef {
+    fn view(dns: &Dns) -> &Self {
+        // Safety: `DnsTransactionRef` is a transparent wrapper of `Dns`.
+        unsafe { &*(dns as *const Dns as *const DnsTransactionRef) }
+    }
+
+    /// Returns the DNS transaction ID.
+    pub fn transaction_id(&self) -> u16 {
+        self.0.transaction_id
+    }
+
+    /// Returns the domain name of the first question, or `""` if no query was observed.
+    pub fn query_domain(&self) -> &str {
+        self.0.query_domain()
+    }
+
+    /// Returns the domain names of the questions, or an empty slice if no query was observed.
+    pub fn queries(&self) -> &[String] {
+        self.0
+            .query
+            .as_ref()
+            .map_or(&[], |query| query.queries.as_slice())
+    }
+
+    /// Returns the query type (QTYPE) of the first question, or `0` if no query was observed.
+    pub fn qtype(&self) -> u16 {
+        self.0.qtype()
+    }
+
+    /// Returns the response code (RCODE), or `0` if no response was observed.
+    pub fn rcode(&self) -> u8 {
+        self.0.rcode()
+    }
+
+    /// Returns the answer records, or an empty slice if no response was observed.
+    pub fn answers(&self) -> &[DnsRecord] {
+        self.0
+            .response
+            .as_ref()
+            .map_or(&[], |resp| resp.answers.as_slice())
+    }
+
+    /// Returns the authority records, or an empty slice if no response was observed.
+    pub fn nameservers(&self) -> &[DnsRecord] {
+        self.0
+            .response
+            .as_ref()
+            .map_or(&[], |resp| resp.nameservers.as_slice())
+    }
+
+    /// Returns the additional records, or an empty slice if no response was observed.
+    pub fn additionals(&self) -> &[DnsRecord] {
+        self.0
+            .response
+            .as_ref()
+            .map_or(&[], |resp| resp.additionals.as_slice())
+    }
+}
+
+impl FromSession for DnsTransactionRef {
+    fn stream_protocols() -> Vec<&'static str> {
+        DnsTransaction::stream_protocols()
+    }
+
+    fn from_session(session: &Session) -> Option<&Self> {
+        DnsTransaction::from_session(session).map(|dns| Self::view(dns))
+    }
+
+    fn from_sessionlist(session_list: &SessionList) -> Option<&Self> {
+        DnsTransaction::from_sessionlist(session_list).map(|dns| Self::view(dns))
+    }
+}
+
 impl FromSession for DnsTransaction {
     fn stream_protocols() -> Vec<&'static str> {
         vec!["dns"]
@@ -0,0 +1,471 @@
+//! Suricata EVE JSON events.
+//!
+//! Formats datatypes as [EVE](https://docs.suricata.io/en/latest/output/eve/eve-json-format.html)
+//! events of type `flow`, `tls`, `http`, and `dns`, so that Retina output can be ingested by
+//! pipelines built for Suricata (e.g., the Suricata integrations of a SIEM). Each event is one
+//! JSON object per line, with Suricata's common fields (`timestamp`, `flow_id`, `event_type`,
+//! `src_ip`, `src_port`, `dest_ip`, `dest_port`, `proto`) followed by the event type object.
+//!
+//! An [EveLog](EveLog) writes events of all types to a per-core [Sink](Sink), like Suricata's
+//! `eve.json`. Register its sink with the runtime to merge the per-core files once it stops.
+//! Events can also be built with the `*_event` functions, e.g., to publish them to Kafka.
+//!
+//! As in Suricata, the `flow_id` of the events of a connection is the same; it is derived from the
+//! 5-tuple. Byte counts are payload bytes, not the size of the packets on the wire. Timestamps are
+//! wall-clock times of the start of the connection, derived from the time elapsed since its first
+//! packet was received (see [ConnStart](crate::ConnStart)).
+//!
+//! ## Example
+//! ```rust,ignore
+//! use retina_datatypes::eve::EveLog;
+//! use lazy_static::lazy_static;
+//!
+//! lazy_static! {
+//!     static ref EVE: EveLog = EveLog::new("eve.json");
+//! }
+//!
+//! #[filter("tls")]
+//! fn flow_cb(conn: &ConnRecord) {
+//!     EVE.write_flow(conn).unwrap();
+//! }
+//!
+//! #[filter("tls")]
+//! fn tls_cb(tls: &TlsHandshake, five_tuple: &FiveTuple, start: &ConnStart) {
+//!     EVE.write_tls(five_tuple, start, tls).unwrap();
+//! }
+//!
+//! #[retina_main(2)]
+//! fn main() {
+//!     let config = default_config();
+//!     let mut runtime: Runtime<SubscribedWrapper> = Runtime::new(config, filter).unwrap();
+//!     runtime.add_sink(EVE.sink());
+//!     runtime.run();
+//! }
+//! ```
+
+use crate::output::{conn_hash, qtype_name, rcode_name, wallclock};
+use crate::{ConnRecord, ConnStart};
+
+use retina_core::conntrack::conn::TerminationReason;
+use retina_core::conntrack::conn_id::FiveTuple;
+use retina_core::protocols::packet::icmp::ICMP_PROTOCOL;
+use retina_core::protocols::packet::icmpv6::ICMPV6_PROTOCOL;
+use retina_core::protocols::packet::sctp::SCTP_PROTOCOL;
+use retina_core::protocols::packet::tcp::{ACK, FIN, RST, SYN, TCP_PROTOCOL};
+use retina_core::protocols::packet::udp::UDP_PROTOCOL;
+use retina_core::protocols::stream::dns::{Data, Dns, DnsRecord};
+use retina_core::protocols::stream::http::Http;
+use retina_core::protocols::stream::tls::Tls;
+use retina_core::utils::sink::Sink;
+
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde_json::{json, Map, Value};
+
+/// Writes EVE events to a file.
+#[derive(Debug)]
+pub struct EveLog {
+    sink: Sink,
+}
+
+impl EveLog {
+    /// Creates a log whose per-core files are merged into `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        EveLog {
+            sink: Sink::new(path),
+        }
+    }
+
+    /// Returns the sink of the log, to register with
+    /// [Runtime::add_sink](retina_core::Runtime::add_sink).
+    pub fn sink(&self) -> &Sink {
+        &self.sink
+    }
+
+    /// Writes the `flow` event of `conn`.
+    pub fn write_flow(&self, conn: &ConnRecord) -> io::Result<()> {
+        self.write(&flow_event(conn))
+    }
+
+    /// Writes the `tls` event of `tls`, in the connection that started at `start`.
+    pub fn write_tls(
+        &self,
+        five_tuple: &FiveTuple,
+        start: &ConnStart,
+        tls: &Tls,
+    ) -> io::Result<()> {
+        self.write(&tls_event(five_tuple, start, tls))
+    }
+
+    /// Writes the `http` event of `http`, in the connection that started at `start`.
+    pub fn write_http(
+        &self,
+        five_tuple: &FiveTuple,
+        start: &ConnStart,
+        http: &Http,
+    ) -> io::Result<()> {
+        self.write(&http_event(five_tuple, start, http))
+    }
+
+    /// Writes the `dns` events of `dns`, in the connection that started at `start`: one for the
+    /// query, and one for the response.
+    pub fn write_dns(
+        &self,
+        five_tuple: &FiveTuple,
+        start: &ConnStart,
+        dns: &Dns,
+    ) -> io::Result<()> {
+        for event in dns_events(five_tuple, start, dns) {
+            self.write(&event)?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, event: &Value) -> io::Result<()> {
+        self.sink.write_line(&event.to_string())
+    }
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Returns the `flow` event of `conn`.
+pub fn flow_event(conn: &ConnRecord) -> Value {
+    let start = wallclock(conn.first_seen_ts);
+    let end = wallclock(conn.last_seen_ts);
+    let mut event = header("flow", start, &conn.five_tuple);
+    let reason = match conn.termination {
+        Some(TerminationReason::Drain) => "shutdown",
+        Some(TerminationReason::Eviction) => "forced",
+        _ => "timeout",
+    };
+    event.insert(
+        "flow".to_string(),
+        json!({
+            "pkts_toserver": conn.orig.nb_pkts,
+            "pkts_toclient": conn.resp.nb_pkts,
+            "bytes_toserver": conn.orig.nb_bytes,
+            "bytes_toclient": conn.resp.nb_bytes,
+            "start": timestamp(start),
+            "end": timestamp(end),
+            "age": conn.duration().as_secs(),
+            "state": flow_state(conn),
+            "reason": reason,
+            "alerted": false,
+        }),
+    );
+    if conn.five_tuple.proto == TCP_PROTOCOL {
+        event.insert("tcp".to_string(), tcp_flags(&conn.history));
+    }
+    Value::Object(event)
+}
+
+/// Returns the Suricata flow state summarized by the history of `conn`.
+fn flow_state(conn: &ConnRecord) -> &'static str {
+    let has = |events: &[u8]| events.iter().any(|event| conn.history.contains(event));
+    if has(b"FfRr") {
+        return "closed";
+    }
+    let established = match conn.five_tuple.proto {
+        TCP_PROTOCOL => has(b"S") && has(b"h") && has(b"AD"),
+        _ => conn.orig.nb_pkts > 0 && conn.resp.nb_pkts > 0,
+    };
+    match established {
+        true => "established",
+        false => "new",
+    }
+}
+
+/// Returns the `tcp` object: the TCP flags seen in each direction, reconstructed from the history.
+fn tcp_flags(history: &[u8]) -> Value {
+    let flags = |upper: bool| {
+        history
+            .iter()
+            .filter(|event| event.is_ascii_uppercase() == upper)
+            .fold(0, |flags, event| {
+                flags
+                    | match event.to_ascii_uppercase() {
+                        b'S' => SYN,
+                        b'H' => SYN | ACK,
+                        b'A' => ACK,
+                        b'F' => FIN,
+                        b'R' => RST,
+                        _ => 0,
+                    }
+            })
+    };
+    let (ts, tc) = (flags(true), flags(false));
+    let mut tcp = Map::new();
+    tcp.insert("tcp_flags".to_string(), json!(format!("{:02x}", ts | tc)));
+    tcp.insert("tcp_flags_ts".to_string(), json!(format!("{:02x}", ts)));
+    tcp.insert("tcp_flags_tc".to_string(), json!(format!("{:02x}", tc)));
+    for (name, flag) in [("syn", SYN), ("fin", FIN), ("rst", RST), ("ack", ACK)] {
+        if (ts | tc) & flag != 0 {
+            tcp.insert(name.to_string(), json!(true));
+        }
+    }
+    Value::Object(tcp)
+}
+
+/// Returns the `tls` event of `tls`, in the connection that started at `start`.
+pub fn tls_event(five_tuple: &FiveTuple, start: &ConnStart, tls: &Tls) -> Value {
+    let mut event = header("tls", wallclock(start.0), five_tuple);
+    let mut object = Map::new();
+    if !tls.sni().is_empty() {
+        object.insert("sni".to_string(), json!(tls.sni()));
+    }
+    object.insert("version".to_string(), json!(tls_version(tls.version())));
+    if let Some(cert) = tls.server_certificates.first() {
+        object.insert("fingerprint".to_string(), json!(sha1(&cert.raw)));
+    }
+    if tls.client_hello.is_some() {
+        object.insert(
+            "ja3".to_string(),
+            json!({ "hash": tls.ja3_hash(), "string": tls.ja3_str() }),
+        );
+    }
+    if tls.server_hello.is_some() {
+        object.insert(
+            "ja3s".to_string(),
+            json!({ "hash": tls.ja3s_hash(), "string": tls.ja3s_str() }),
+        );
+    }
+    if !tls.client_alpn_protocols().is_empty() {
+        object.insert(
+            "client_alpns".to_string(),
+            json!(tls.client_alpn_protocols()),
+        );
+    }
+    if let Some(alpn) = tls
+        .server_hello
+        .as_ref()
+        .and_then(|server_hello| server_hello.alpn_protocol.as_ref())
+    {
+        object.insert("server_alpns".to_string(), json!([alpn]));
+    }
+    event.insert("app_proto".to_string(), json!("tls"));
+    event.insert("tls".to_string(), Value::Object(object));
+    Value::Object(event)
+}
+
+fn tls_version(version: u16) -> &'static str {
+    match version {
+        0x0002 => "SSLv2",
+        0x0300 => "SSLv3",
+        0x0301 => "TLS 1.0",
+        0x0302 => "TLS 1.1",
+        0x0303 => "TLS 1.2",
+        0x0304 => "TLS 1.3",
+        _ => "UNDETERMINED",
+    }
+}
+
+/// Returns the colon-separated SHA-1 fingerprint of a certificate.
+fn sha1(data: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, data);
+    digest
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Returns the `http` event of `http`, in the connection that started at `start`.
+pub fn http_event(five_tuple: &FiveTuple, start: &ConnStart, http: &Http) -> Value {
+    let mut event = header("http", wallclock(start.0), five_tuple);
+    let mut object = Map::new();
+    let fields = [
+        ("hostname", http.host()),
+        ("url", http.uri()),
+        ("http_user_agent", http.user_agent()),
+        ("http_content_type", http.response_content_type()),
+        ("http_refer", http.header("referer")),
+        ("http_method", http.method()),
+        ("protocol", http.request_version()),
+    ];
+    for (name, value) in fields {
+        if !value.is_empty() {
+            object.insert(name.to_string(), json!(value));
+        }
+    }
+    if let Some(status) = http.response.status_code {
+        object.insert("status".to_string(), json!(status));
+    }
+    object.insert("length".to_string(), json!(http.response_content_length()));
+    event.insert("tx_id".to_string(), json!(http.trans_depth));
+    event.insert("app_proto".to_string(), json!("http"));
+    event.insert("http".to_string(), Value::Object(object));
+    Value::Object(event)
+}
+
+/// Returns the `dns` events of `dns`, in the connection that started at `start`, in the EVE
+/// version 2 format: a `query` event if the query was observed, and an `answer` event if the
+/// response was observed.
+pub fn dns_events(five_tuple: &FiveTuple, start: &ConnStart, dns: &Dns) -> Vec<Value> {
+    let ts = wallclock(start.0);
+    let mut events = vec![];
+    if let Some(query) = dns.query.as_ref() {
+        for (idx, name) in query.queries.iter().enumerate() {
+            let qtype = query.query_types.get(idx).copied().unwrap_or_default();
+            let mut event = header("dns", ts, five_tuple);
+            event.insert(
+                "dns".to_string(),
+                json!({
+                    "type": "query",
+                    "id": dns.transaction_id,
+                    "rrname": name,
+                    "rrtype": rrtype(qtype),
+                    "tx_id": 0,
+                    "opcode": 0,
+                }),
+            );
+            events.push(Value::Object(event));
+        }
+    }
+    if let Some(response) = dns.response.as_ref() {
+        let rd = dns
+            .query
+            .as_ref()
+            .is_some_and(|query| query.recursion_desired);
+        let mut object = json!({
+            "version": 2,
+            "type": "answer",
+            "id": dns.transaction_id,
+            "qr": true,
+            "rd": rd,
+            "ra": response.recursion_available,
+            "aa": response.authoritative,
+            "opcode": 0,
+            "rcode": rcode_name(dns.rcode())
+                .map_or_else(|| dns.rcode().to_string(), str::to_string),
+        });
+        if !dns.query_domain().is_empty() {
+            object["rrname"] = json!(dns.query_domain());
+            object["rrtype"] = json!(rrtype(dns.qtype()));
+        }
+        if !response.answers.is_empty() {
+            object["answers"] = response.answers.iter().map(answer).collect();
+            let mut grouped = Map::new();
+            for record in response.answers.iter() {
+                if let Value::Array(rdata) = grouped
+                    .entry(record_type(&record.data))
+                    .or_insert_with(|| json!([]))
+                {
+                    rdata.push(json!(rdata_str(&record.data)));
+                }
+            }
+            object["grouped"] = Value::Object(grouped);
+        }
+        let mut event = header("dns", ts, five_tuple);
+        event.insert("dns".to_string(), object);
+        events.push(Value::Object(event));
+    }
+    events
+}
+
+/// Returns the `rrtype` of `qtype`: its mnemonic, or its number if it is not a common one.
+fn rrtype(qtype: u16) -> String {
+    qtype_name(qtype).map_or_else(|| qtype.to_string(), str::to_string)
+}
+
+fn answer(record: &DnsRecord) -> Value {
+    json!({
+        "rrname": record.name,
+        "rrtype": record_type(&record.data),
+        "ttl": record.ttl,
+        "rdata": rdata_str(&record.data),
+    })
+}
+
+fn record_type(data: &Data) -> &'static str {
+    match data {
+        Data::A(_) => "A",
+        Data::Aaaa(_) => "AAAA",
+        Data::Cname(_) => "CNAME",
+        Data::Mx(_) => "MX",
+        Data::Ns(_) => "NS",
+        Data::Ptr(_) => "PTR",
+        Data::Soa(_) => "SOA",
+        Data::Srv(_) => "SRV",
+        Data::Txt(_) => "TXT",
+        Data::Unknown => "UNKNOWN",
+    }
+}
+
+fn rdata_str(data: &Data) -> String {
+    match data {
+        Data::A(addr) => addr.to_string(),
+        Data::Aaaa(addr) => addr.to_string(),
+        Data::Cname(name) | Data::Ns(name) | Data::Ptr(name) | Data::Txt(name) => name.clone(),
+        Data::Mx(mx) => mx.exchange.clone(),
+        Data::Soa(soa) => soa.primary_ns.clone(),
+        Data::Srv(srv) => srv.target.clone(),
+        Data::Unknown => String::new(),
+    }
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Returns the common fields of an event.
+fn header(event_type: &str, ts: SystemTime, five_tuple: &FiveTuple) -> Map<String, Value> {
+    let mut event = Map::new();
+    event.insert("timestamp".to_string(), json!(timestamp(ts)));
+    event.insert("flow_id".to_string(), json!(flow_id(five_tuple)));
+    event.insert("event_type".to_string(), json!(event_type));
+    event.insert("src_ip".to_string(), json!(five_tuple.orig.ip()));
+    event.insert("src_port".to_string(), json!(five_tuple.orig.port()));
+    event.insert("dest_ip".to_string(), json!(five_tuple.resp.ip()));
+    event.insert("dest_port".to_string(), json!(five_tuple.resp.port()));
+    event.insert("proto".to_string(), json!(proto(five_tuple.proto)));
+    event
+}
+
+/// Returns the flow identifier of `five_tuple`, kept below 2^51 as in Suricata so that it is
+/// exactly representable as a JSON number.
+fn flow_id(five_tuple: &FiveTuple) -> u64 {
+    conn_hash(five_tuple) & ((1 << 51) - 1)
+}
+
+fn proto(proto: usize) -> String {
+    let name = match proto {
+        TCP_PROTOCOL => "TCP",
+        UDP_PROTOCOL => "UDP",
+        ICMP_PROTOCOL => "ICMP",
+        ICMPV6_PROTOCOL => "IPv6-ICMP",
+        SCTP_PROTOCOL => "SCTP",
+        _ => return proto.to_string(),
+    };
+    name.to_string()
+}
+
+/// Formats `time` as an EVE timestamp, e.g., `2024-01-31T12:00:00.123456+0000`.
+fn timestamp(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Local>::from(time)
+        .format("%Y-%m-%dT%H:%M:%S%.6f%z")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_eve_tcp_flags() {
+        let tcp = tcp_flags(b"ShADadFf");
+        assert_eq!(tcp["tcp_flags"], "13");
+        assert_eq!(tcp["tcp_flags_ts"], "13");
+        assert_eq!(tcp["tcp_flags_tc"], "13");
+        assert_eq!(tcp["syn"], true);
+        assert!(tcp.get("rst").is_none());
+
+        let tcp = tcp_flags(b"Sr");
+        assert_eq!(tcp["tcp_flags_ts"], "02");
+        assert_eq!(tcp["tcp_flags_tc"], "04");
+        assert_eq!(proto(58), "IPv6-ICMP");
+        assert_eq!(tls_version(0x0304), "TLS 1.3");
+        assert_eq!(rrtype(65), "HTTPS");
+        assert_eq!(rrtype(999), "999");
+    }
+}
@@ -8,6 +8,93 @@ use super::{FromSession, SessionList};
 
 pub type HttpTransaction = Box<Http>;
 
+/// Borrowed variant of [HttpTransaction]: a view of the parsed session, delivered by reference
+/// and never copied. Callbacks taking it are always invoked on the packet processing core, and
+/// its accessors borrow the request and response for the duration of the callback.
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct HttpTransactionRef(Http);
+
+impl HttpTransactionRef {
+    fn view(http: &Http) -> &Self {
+        // Safety: `HttpTransactionRef` is a transparent wrapper of `Http`.
+        unsafe { &*(http as *const Http as *const HttpTransactionRef) }
+    }
+
+    /// Returns the request method, or `""` if it does not exist.
+    pub fn method(&self) -> &str {
+        self.0.method()
+    }
+
+    /// Returns the request URI, or `""` if it does not exist.
+    pub fn uri(&self) -> &str {
+        self.0.uri()
+    }
+
+    /// Returns the domain name of the server specified by the client, or `""` if it does not exist.
+    pub fn host(&self) -> &str {
+        self.0.host()
+    }
+
+    /// Returns the user agent string, or `""` if it does not exist.
+    pub fn user_agent(&self) -> &str {
+        self.0.user_agent()
+    }
+
+    /// Returns the HTTP status code, or `0` if no response was observed.
+    pub fn status_code(&self) -> u16 {
+        self.0.status_code()
+    }
+
+    /// Returns the status text, or `""` if it does not exist.
+    pub fn status_msg(&self) -> &str {
+        self.0.status_msg()
+    }
+
+    /// Returns the value of the header `name` (case-insensitive), or `""` if it does not exist.
+    /// Request headers take precedence over response headers.
+    pub fn header(&self, name: &str) -> &str {
+        self.0.header(name)
+    }
+
+    /// Returns the request headers, in order.
+    pub fn request_headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .request
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Returns the response headers, in order.
+    pub fn response_headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .response
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Returns the pipelined depth into the connection of this transaction.
+    pub fn trans_depth(&self) -> usize {
+        self.0.trans_depth
+    }
+}
+
+impl FromSession for HttpTransactionRef {
+    fn stream_protocols() -> Vec<&'static str> {
+        HttpTransaction::stream_protocols()
+    }
+
+    fn from_session(session: &Session) -> Option<&Self> {
+        HttpTransaction::from_session(session).map(|http| Self::view(http))
+    }
+
+    fn from_sessionlist(session_list: &SessionList) -> Option<&Self> {
+        HttpTransaction::from_sessionlist(session_list).map(|http| Self::view(http))
+    }
+}
+
 impl FromSession for HttpTransaction {
     fn stream_protocols() -> Vec<&'static str> {
         vec!["http"]
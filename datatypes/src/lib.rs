@@ -10,6 +10,16 @@
 //! - Be defined as a [retina_core::filter::DataType], with appropriate parameters and [retina_core::filter::Level].
 //! - Implement one of the traits defined in this module (Tracked, FromSession, etc.)
 //! - Be added to the [crate::typedefs::DATATYPES] map
+//! - Implement `Clone` and `Send`, so that it can be delivered to callbacks running off the packet
+//!   processing cores, or be added to [crate::typedefs::NOT_CLONEABLE]
+//!
+//! Applications can also define datatypes in their own crate, with the `datatype` attribute of
+//! retina-filtergen.
+//!
+//! Built-in datatypes implement `serde::Serialize`. With the `serde` feature, they also implement
+//! `serde::Deserialize`, so that serialized records (e.g., JSON or bincode) can be read back.
+//! Values that are not serialized, such as timestamps or decryption state, are reset: timestamps
+//! are taken relative to the time of deserialization, preserving serialized durations.
 //!
 //!
 
@@ -19,20 +29,24 @@ pub use conn_fts::*;
 pub mod connection;
 pub use connection::ConnRecord;
 pub mod http_transaction;
-pub use http_transaction::HttpTransaction;
+pub use http_transaction::{HttpTransaction, HttpTransactionRef};
 pub mod dns_transaction;
-pub use dns_transaction::DnsTransaction;
+pub use dns_transaction::{DnsTransaction, DnsTransactionRef};
 pub mod tls_handshake;
-pub use tls_handshake::TlsHandshake;
+pub use tls_handshake::{TlsHandshake, TlsHandshakeRef};
 pub mod quic_stream;
 pub use quic_stream::QuicStream;
 pub mod packet;
 pub use packet::{Payload, ZcFrame};
 pub mod static_type;
 pub use static_type::*;
+pub mod eve;
+mod output;
+pub mod zeek;
 pub use typedefs::*;
 
-use retina_core::conntrack::pdu::L4Pdu;
+use retina_core::conntrack::conn::TerminationReason;
+use retina_core::conntrack::pdu::{IcmpError, L4Pdu};
 use retina_core::filter::SubscriptionSpec;
 use retina_core::protocols::stream::Session;
 use retina_core::Mbuf;
@@ -54,6 +68,12 @@ pub trait Tracked {
     /// Clear internal data; called if connection no longer matches filter
     /// that requires the Tracked type.
     fn clear(&mut self);
+    /// The connection terminated for `reason`; called before the connection
+    /// is delivered.
+    fn terminate(&mut self, _reason: TerminationReason) {}
+    /// An ICMP error message was received about a packet of the connection,
+    /// sent by the originator if `dir` is true.
+    fn icmp_error(&mut self, _error: &IcmpError, _dir: bool) {}
 }
 
 /// Trait implemented by datatypes that are built from session data.
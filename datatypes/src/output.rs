@@ -0,0 +1,59 @@
+//! Helpers shared by the [Zeek](crate::zeek) and [EVE](crate::eve) formatters.
+
+use retina_core::conntrack::conn_id::FiveTuple;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::{Instant, SystemTime};
+
+/// Returns the wall-clock time of `instant`.
+pub(crate) fn wallclock(instant: Instant) -> SystemTime {
+    let now = SystemTime::now();
+    now.checked_sub(instant.elapsed()).unwrap_or(now)
+}
+
+/// Returns a hash of `five_tuple`, from which connection identifiers are derived.
+pub(crate) fn conn_hash(five_tuple: &FiveTuple) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    five_tuple.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns the mnemonic of a DNS query type, if it is a common one.
+pub(crate) fn qtype_name(qtype: u16) -> Option<&'static str> {
+    let name = match qtype {
+        1 => "A",
+        2 => "NS",
+        5 => "CNAME",
+        6 => "SOA",
+        12 => "PTR",
+        15 => "MX",
+        16 => "TXT",
+        28 => "AAAA",
+        33 => "SRV",
+        35 => "NAPTR",
+        43 => "DS",
+        46 => "RRSIG",
+        47 => "NSEC",
+        48 => "DNSKEY",
+        64 => "SVCB",
+        65 => "HTTPS",
+        255 => "ANY",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Returns the mnemonic of a DNS response code, if it is a common one.
+pub(crate) fn rcode_name(rcode: u8) -> Option<&'static str> {
+    let name = match rcode {
+        0 => "NOERROR",
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        _ => return None,
+    };
+    Some(name)
+}
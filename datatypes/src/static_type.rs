@@ -6,6 +6,7 @@ use super::StaticData;
 use pnet::datalink::MacAddr;
 use retina_core::conntrack::conn_id::FiveTuple;
 use retina_core::conntrack::pdu::L4Pdu;
+use std::time::Instant;
 
 /// Subscribable alias for [`retina_core::FiveTuple`]
 impl StaticData for FiveTuple {
@@ -14,27 +15,64 @@ impl StaticData for FiveTuple {
     }
 }
 
+/// The time the first packet of a connection was received (see
+/// [Mbuf::rx_time](retina_core::Mbuf::rx_time)), e.g., to timestamp records of sessions by the
+/// start of their connection rather than by their delivery.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnStart(pub Instant);
+
+impl StaticData for ConnStart {
+    fn new(first_pkt: &L4Pdu) -> Self {
+        ConnStart(first_pkt.mbuf_ref().rx_time())
+    }
+}
+
 use retina_core::protocols::packet::{ethernet::Ethernet, Packet};
 
-/// Tag Control Information field on the first packet, or none
+/// Tag Control Information fields of the VLAN tags on the first packet, or none
 #[derive(Clone, Copy, Debug, PartialEq)]
-pub struct EtherTCI(Option<u16>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EtherTCI {
+    /// The outer (or only) tag.
+    pub outer: Option<u16>,
+    /// The inner tag of double-tagged (QinQ) frames.
+    pub inner: Option<u16>,
+}
+
+impl EtherTCI {
+    /// Returns the VLAN ID of the outer (or only) tag.
+    pub fn vlan_id(&self) -> Option<u16> {
+        self.outer.map(|tci| tci & 0x0fff)
+    }
+
+    /// Returns the VLAN ID of the inner tag of double-tagged frames.
+    pub fn inner_vlan_id(&self) -> Option<u16> {
+        self.inner.map(|tci| tci & 0x0fff)
+    }
+}
 
 impl StaticData for EtherTCI {
     fn new(first_pkt: &L4Pdu) -> Self {
-        if let Ok(ethernet) = &Packet::parse_to::<Ethernet>(first_pkt.mbuf_ref()) {
-            if let Some(tci) = ethernet.tci() {
-                return EtherTCI(Some(tci));
-            }
+        match &Packet::parse_to::<Ethernet>(first_pkt.mbuf_ref()) {
+            Ok(ethernet) => EtherTCI {
+                outer: ethernet.tci(),
+                inner: ethernet.inner_tci(),
+            },
+            Err(_) => EtherTCI {
+                outer: None,
+                inner: None,
+            },
         }
-        EtherTCI(None)
     }
 }
 
 /// The src/dst MAC of a connection
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EthAddr {
+    #[cfg_attr(feature = "serde", serde(with = "mac_addr"))]
     pub src: MacAddr,
+    #[cfg_attr(feature = "serde", serde(with = "mac_addr"))]
     pub dst: MacAddr,
 }
 
@@ -50,3 +88,20 @@ impl StaticData for EthAddr {
         }
     }
 }
+
+/// (De)serializes MAC addresses as strings (e.g., `"00:11:22:33:44:55"`).
+#[cfg(feature = "serde")]
+mod mac_addr {
+    use pnet::datalink::MacAddr;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(addr: &MacAddr, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(addr)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<MacAddr, D::Error> {
+        String::deserialize(d)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
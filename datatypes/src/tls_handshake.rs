@@ -8,6 +8,97 @@ use super::{FromSession, SessionList};
 
 pub type TlsHandshake = Box<Tls>;
 
+/// Borrowed variant of [TlsHandshake]: a view of the parsed session, delivered by reference and
+/// never copied. Callbacks taking it are always invoked on the packet processing core, and its
+/// accessors borrow the handshake messages for the duration of the callback.
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct TlsHandshakeRef(Tls);
+
+impl TlsHandshakeRef {
+    fn view(tls: &Tls) -> &Self {
+        // Safety: `TlsHandshakeRef` is a transparent wrapper of `Tls`.
+        unsafe { &*(tls as *const Tls as *const TlsHandshakeRef) }
+    }
+
+    /// Returns the name of the server the client is trying to connect to, or `""` if it was not
+    /// sent.
+    pub fn sni(&self) -> &str {
+        self.0.sni()
+    }
+
+    /// Returns the negotiated handshake version, or `0` if no ServerHello was observed.
+    pub fn version(&self) -> u16 {
+        self.0.version()
+    }
+
+    /// Returns the client random, or an empty slice if no ClientHello was observed.
+    pub fn client_random(&self) -> &[u8] {
+        self.0
+            .client_hello
+            .as_ref()
+            .map_or(&[], |client_hello| client_hello.random.as_slice())
+    }
+
+    /// Returns the server random, or an empty slice if no ServerHello was observed.
+    pub fn server_random(&self) -> &[u8] {
+        self.0
+            .server_hello
+            .as_ref()
+            .map_or(&[], |server_hello| server_hello.random.as_slice())
+    }
+
+    /// Returns the ALPN protocol names offered by the client.
+    pub fn client_alpn_protocols(&self) -> &[String] {
+        self.0.client_alpn_protocols()
+    }
+
+    /// Returns the ALPN protocol name chosen by the server, or `""` if there is none.
+    pub fn server_alpn_protocol(&self) -> &str {
+        self.0
+            .server_hello
+            .as_ref()
+            .and_then(|server_hello| server_hello.alpn_protocol.as_deref())
+            .unwrap_or("")
+    }
+
+    /// Returns the identifier of the cipher suite chosen by the server, or `0` if no ServerHello
+    /// was observed.
+    pub fn cipher_id(&self) -> u16 {
+        self.0.cipher_id()
+    }
+
+    /// Returns the DER-encoded server certificates, leaf first.
+    pub fn server_certificates(&self) -> impl Iterator<Item = &[u8]> {
+        self.0
+            .server_certificates
+            .iter()
+            .map(|cert| cert.raw.as_slice())
+    }
+
+    /// Returns the DER-encoded client certificates, leaf first.
+    pub fn client_certificates(&self) -> impl Iterator<Item = &[u8]> {
+        self.0
+            .client_certificates
+            .iter()
+            .map(|cert| cert.raw.as_slice())
+    }
+}
+
+impl FromSession for TlsHandshakeRef {
+    fn stream_protocols() -> Vec<&'static str> {
+        TlsHandshake::stream_protocols()
+    }
+
+    fn from_session(session: &Session) -> Option<&Self> {
+        TlsHandshake::from_session(session).map(|tls| Self::view(tls))
+    }
+
+    fn from_sessionlist(session_list: &SessionList) -> Option<&Self> {
+        TlsHandshake::from_sessionlist(session_list).map(|tls| Self::view(tls))
+    }
+}
+
 impl FromSession for TlsHandshake {
     fn stream_protocols() -> Vec<&'static str> {
         vec!["tls"]
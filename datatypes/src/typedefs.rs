@@ -5,7 +5,7 @@ use lazy_static::lazy_static;
 use proc_macro2::Span;
 use quote::quote;
 use retina_core::filter::{DataType, Level, SubscriptionSpec};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::*;
 
@@ -29,6 +29,7 @@ lazy_static! {
                 "ConnHistory",
                 DataType::new_default_connection("ConnHistory"),
             ),
+            ("IcmpErrors", DataType::new_default_connection("IcmpErrors")),
             (
                 "HttpTransaction",
                 DataType::new_default_session(
@@ -44,6 +45,28 @@ lazy_static! {
                 "TlsHandshake",
                 DataType::new_default_session("TlsHandshake", TlsHandshake::stream_protocols()),
             ),
+            // Borrowed views of the parsed sessions, delivered without copies
+            (
+                "HttpTransactionRef",
+                DataType::new_default_session(
+                    "HttpTransactionRef",
+                    HttpTransactionRef::stream_protocols(),
+                ),
+            ),
+            (
+                "DnsTransactionRef",
+                DataType::new_default_session(
+                    "DnsTransactionRef",
+                    DnsTransactionRef::stream_protocols(),
+                ),
+            ),
+            (
+                "TlsHandshakeRef",
+                DataType::new_default_session(
+                    "TlsHandshakeRef",
+                    TlsHandshakeRef::stream_protocols(),
+                ),
+            ),
             (
                 "QuicStream",
                 DataType::new_default_session("QuicStream", QuicStream::stream_protocols()),
@@ -58,8 +81,10 @@ lazy_static! {
                     needs_update: false,
                     needs_update_reassembled: false,
                     track_packets: true,
+                    write_packets: false,
                     stream_protos: vec![],
                     as_str: "PacketList",
+                    stream_sessions: false,
                 }
             }),
             ("SessionList", {
@@ -70,14 +95,31 @@ lazy_static! {
                     needs_update: false,
                     needs_update_reassembled: false,
                     track_packets: false,
+                    write_packets: false,
                     stream_protos: vec!["tls", "dns", "http", "quic"],
                     as_str: "SessionList",
+                    stream_sessions: false,
+                }
+            }),
+            ("PcapCapture", {
+                DataType {
+                    level: Level::Connection,
+                    needs_parse: false,
+                    track_sessions: false,
+                    needs_update: true,
+                    needs_update_reassembled: false,
+                    track_packets: false,
+                    write_packets: true,
+                    stream_protos: vec![],
+                    as_str: "PcapCapture",
+                    stream_sessions: false,
                 }
             }),
             ("CoreId", { DataType::new_default_static("CoreId") }),
             ("FiveTuple", { DataType::new_default_static("FiveTuple") }),
             ("EtherTCI", { DataType::new_default_static("EtherTCI") }),
             ("EthAddr", { DataType::new_default_static("EthAddr") }),
+            ("ConnStart", { DataType::new_default_static("ConnStart") }),
             ("FilterStr", { DataType::new_default_static("FilterStr") }),
         ])
     };
@@ -106,6 +148,22 @@ lazy_static! {
         ("CoreId", "core_id")
     ]);
 
+    /// Datatypes that cannot be cloned, and so cannot be delivered off the packet processing
+    /// cores (to async callbacks or the callback worker pool). Packets must be released on the
+    /// core that received them, QUIC connections hold packet protection keys, and borrowed
+    /// variants of session datatypes are never copied.
+    #[doc(hidden)]
+    pub static ref NOT_CLONEABLE: HashSet<&'static str> = HashSet::from([
+        "ZcFrame",
+        "Payload",
+        "PacketList",
+        "QuicStream",
+        "SessionList",
+        "HttpTransactionRef",
+        "DnsTransactionRef",
+        "TlsHandshakeRef",
+    ]);
+
     /// See `FilterStr`
     #[doc(hidden)]
     pub static ref FILTER_STR: &'static str = "FilterStr";
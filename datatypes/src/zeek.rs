@@ -0,0 +1,859 @@
+//! Zeek log formatters.
+//!
+//! Formats datatypes as records of Zeek's
+//! [conn.log](https://docs.zeek.org/en/master/scripts/base/protocols/conn/main.zeek.html),
+//! [dns.log](https://docs.zeek.org/en/master/scripts/base/protocols/dns/main.zeek.html),
+//! [http.log](https://docs.zeek.org/en/master/scripts/base/protocols/http/main.zeek.html), and
+//! [ssl.log](https://docs.zeek.org/en/master/scripts/base/protocols/ssl/main.zeek.html), with
+//! Zeek's default columns, in either the tab-separated (TSV) or the JSON format of Zeek's ASCII
+//! writer, so that Retina output can be read by tools built for Zeek logs (e.g., `zeek-cut`, or the
+//! Zeek parsers of a SIEM).
+//!
+//! A [ZeekLog](ZeekLog) writes one log through a per-core [Sink](retina_core::utils::sink::Sink).
+//! It must be closed after the runtime stops, which writes the log file with its TSV header.
+//!
+//! Fields that Retina does not track (e.g., `local_orig` in conn.log, or file identifiers in
+//! http.log) are unset. Connection identifiers (`uid`) are derived from the 5-tuple, so that the
+//! records of a connection share a `uid` across logs. Timestamps are wall-clock times, derived from
+//! the time elapsed since the connection was first seen for conn.log, and the time the record is
+//! created for the other logs.
+//!
+//! ## Example
+//! ```rust,ignore
+//! use retina_datatypes::zeek::{ZeekConn, ZeekFormat, ZeekLog, ZeekSsl};
+//! use lazy_static::lazy_static;
+//!
+//! lazy_static! {
+//!     static ref CONN: ZeekLog = ZeekLog::new::<ZeekConn>("logs", ZeekFormat::Tsv);
+//!     static ref SSL: ZeekLog = ZeekLog::new::<ZeekSsl>("logs", ZeekFormat::Tsv);
+//! }
+//!
+//! #[filter("tls")]
+//! fn conn_cb(conn: &ConnRecord) {
+//!     CONN.write(&ZeekConn::new(conn).with_service("ssl")).unwrap();
+//! }
+//!
+//! #[filter("tls")]
+//! fn ssl_cb(tls: &TlsHandshake, five_tuple: &FiveTuple) {
+//!     SSL.write(&ZeekSsl::new(five_tuple, tls)).unwrap();
+//! }
+//!
+//! #[retina_main(2)]
+//! fn main() {
+//!     let config = default_config();
+//!     let mut runtime: Runtime<SubscribedWrapper> = Runtime::new(config, filter).unwrap();
+//!     runtime.run();
+//!     // Writes logs/conn.log and logs/ssl.log
+//!     CONN.close().unwrap();
+//!     SSL.close().unwrap();
+//! }
+//! ```
+
+use crate::output::{conn_hash, qtype_name, rcode_name, wallclock};
+use crate::ConnRecord;
+
+use retina_core::conntrack::conn_id::FiveTuple;
+use retina_core::protocols::packet::icmp::ICMP_PROTOCOL;
+use retina_core::protocols::packet::icmpv6::ICMPV6_PROTOCOL;
+use retina_core::protocols::packet::tcp::TCP_PROTOCOL;
+use retina_core::protocols::packet::udp::UDP_PROTOCOL;
+use retina_core::protocols::stream::dns::{Data, Dns};
+use retina_core::protocols::stream::http::Http;
+use retina_core::protocols::stream::tls::{Certificate, ServerKeyExchange, Tls};
+use retina_core::utils::sink::Sink;
+
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Format of the written logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZeekFormat {
+    /// Tab-separated values, with Zeek's `#fields` and `#types` header.
+    Tsv,
+    /// One JSON object per line, as written with `LogAscii::use_json`. Unset fields are omitted.
+    Json,
+}
+
+/// A typed field value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZeekValue {
+    Time(SystemTime),
+    Interval(Duration),
+    Str(String),
+    Addr(IpAddr),
+    Port(u16),
+    Count(u64),
+    Bool(bool),
+    /// A `set` or `vector`.
+    Vector(Vec<ZeekValue>),
+    Unset,
+}
+
+/// A record of a Zeek log.
+pub trait ZeekRecord {
+    /// The name of the log (e.g., `conn`).
+    const PATH: &'static str;
+    /// The name and Zeek type of each column.
+    const FIELDS: &'static [(&'static str, &'static str)];
+
+    /// Returns the value of each column, in the order of [FIELDS](ZeekRecord::FIELDS).
+    fn values(&self) -> Vec<ZeekValue>;
+}
+
+/// Formats `record` as a line of a log, without the trailing newline.
+pub fn format<R: ZeekRecord>(record: &R, format: ZeekFormat) -> String {
+    let values = record.values();
+    debug_assert_eq!(values.len(), R::FIELDS.len());
+    let mut line = String::new();
+    match format {
+        ZeekFormat::Tsv => {
+            for (idx, value) in values.iter().enumerate() {
+                if idx > 0 {
+                    line.push('\t');
+                }
+                write_tsv(&mut line, value, false);
+            }
+        }
+        ZeekFormat::Json => {
+            line.push('{');
+            let set = R::FIELDS
+                .iter()
+                .zip(values.iter())
+                .filter(|(_, value)| **value != ZeekValue::Unset);
+            for (idx, ((name, _), value)) in set.enumerate() {
+                if idx > 0 {
+                    line.push(',');
+                }
+                write_json_str(&mut line, name);
+                line.push(':');
+                write_json(&mut line, value);
+            }
+            line.push('}');
+        }
+    }
+    line
+}
+
+/// Returns the Zeek connection identifier of `five_tuple`: `C` followed by a base62-encoded
+/// hash of the 5-tuple.
+///
+/// ## Remarks
+/// Unlike the random identifiers of Zeek, connections that reuse a 5-tuple share a `uid`.
+pub fn uid(five_tuple: &FiveTuple) -> String {
+    const ALPHABET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+    let mut hash = conn_hash(five_tuple);
+    let mut uid = String::from("C");
+    while hash > 0 {
+        uid.push(ALPHABET[(hash % 62) as usize] as char);
+        hash /= 62;
+    }
+    uid
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// Writes one Zeek log, e.g., `conn.log`.
+#[derive(Debug)]
+pub struct ZeekLog {
+    name: &'static str,
+    fields: &'static [(&'static str, &'static str)],
+    format: ZeekFormat,
+    path: PathBuf,
+    /// Records, merged into the log when it is closed.
+    sink: Sink,
+    open: String,
+}
+
+impl ZeekLog {
+    /// Creates the log of records `R`, written to `<name>.log` in `directory` when it is closed.
+    pub fn new<R: ZeekRecord>(directory: impl AsRef<Path>, format: ZeekFormat) -> Self {
+        let path = directory.as_ref().join(format!("{}.log", R::PATH));
+        let mut body = path.clone().into_os_string();
+        body.push(".records");
+        ZeekLog {
+            name: R::PATH,
+            fields: R::FIELDS,
+            format,
+            path,
+            sink: Sink::new(body),
+            open: timestamp(),
+        }
+    }
+
+    /// Returns the path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes `record` to the file of the current core.
+    pub fn write<R: ZeekRecord>(&self, record: &R) -> io::Result<()> {
+        debug_assert_eq!(R::PATH, self.name);
+        self.sink.write_line(&format(record, self.format))
+    }
+
+    /// Writes the log file: the TSV header, the records of all cores, and the TSV footer.
+    pub fn close(&self) -> io::Result<()> {
+        self.sink.merge()?;
+        let mut output = BufWriter::new(File::create(&self.path)?);
+        if self.format == ZeekFormat::Tsv {
+            output.write_all(self.header().as_bytes())?;
+        }
+        io::copy(&mut File::open(self.sink.path())?, &mut output)?;
+        fs::remove_file(self.sink.path())?;
+        if self.format == ZeekFormat::Tsv {
+            writeln!(output, "#close\t{}", timestamp())?;
+        }
+        output.flush()
+    }
+
+    fn header(&self) -> String {
+        let names: Vec<&str> = self.fields.iter().map(|(name, _)| *name).collect();
+        let types: Vec<&str> = self.fields.iter().map(|(_, ty)| *ty).collect();
+        format!(
+            "#separator \\x09\n\
+             #set_separator\t,\n\
+             #empty_field\t(empty)\n\
+             #unset_field\t-\n\
+             #path\t{}\n\
+             #open\t{}\n\
+             #fields\t{}\n\
+             #types\t{}\n",
+            self.name,
+            self.open,
+            names.join("\t"),
+            types.join("\t")
+        )
+    }
+}
+
+/// Returns the local time, formatted as in the `#open` and `#close` lines.
+fn timestamp() -> String {
+    chrono::Local::now().format("%Y-%m-%d-%H-%M-%S").to_string()
+}
+
+/* --------------------------------------------------------------------------------- */
+
+fn write_tsv(out: &mut String, value: &ZeekValue, in_set: bool) {
+    match value {
+        ZeekValue::Time(time) => {
+            let _ = write!(out, "{:.6}", epoch(*time));
+        }
+        ZeekValue::Interval(duration) => {
+            let _ = write!(out, "{:.6}", duration.as_secs_f64());
+        }
+        ZeekValue::Str(text) if text.is_empty() => out.push_str("(empty)"),
+        ZeekValue::Str(text) if text == "-" => out.push_str("\\x2d"),
+        ZeekValue::Str(text) => escape(out, text, in_set),
+        ZeekValue::Addr(addr) => {
+            let _ = write!(out, "{}", addr);
+        }
+        ZeekValue::Port(port) => {
+            let _ = write!(out, "{}", port);
+        }
+        ZeekValue::Count(count) => {
+            let _ = write!(out, "{}", count);
+        }
+        ZeekValue::Bool(flag) => out.push(if *flag { 'T' } else { 'F' }),
+        ZeekValue::Vector(values) if values.is_empty() => out.push_str("(empty)"),
+        ZeekValue::Vector(values) => {
+            for (idx, value) in values.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                write_tsv(out, value, true);
+            }
+        }
+        ZeekValue::Unset => out.push('-'),
+    }
+}
+
+/// Escapes the separators and non-printable bytes of `text` as `\xNN`, as Zeek does.
+fn escape(out: &mut String, text: &str, in_set: bool) {
+    for byte in text.bytes() {
+        let printable = byte.is_ascii_graphic() || byte == b' ';
+        if printable && byte != b'\\' && !(in_set && byte == b',') {
+            out.push(byte as char);
+        } else {
+            let _ = write!(out, "\\x{:02x}", byte);
+        }
+    }
+}
+
+fn write_json(out: &mut String, value: &ZeekValue) {
+    match value {
+        ZeekValue::Time(time) => {
+            let _ = write!(out, "{:.6}", epoch(*time));
+        }
+        ZeekValue::Interval(duration) => {
+            let _ = write!(out, "{:.6}", duration.as_secs_f64());
+        }
+        ZeekValue::Str(text) => write_json_str(out, text),
+        ZeekValue::Addr(addr) => write_json_str(out, &addr.to_string()),
+        ZeekValue::Port(port) => {
+            let _ = write!(out, "{}", port);
+        }
+        ZeekValue::Count(count) => {
+            let _ = write!(out, "{}", count);
+        }
+        ZeekValue::Bool(flag) => out.push_str(if *flag { "true" } else { "false" }),
+        ZeekValue::Vector(values) => {
+            out.push('[');
+            for (idx, value) in values.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                write_json(out, value);
+            }
+            out.push(']');
+        }
+        ZeekValue::Unset => out.push_str("null"),
+    }
+}
+
+fn write_json_str(out: &mut String, text: &str) {
+    out.push_str(&serde_json::to_string(text).unwrap_or_default());
+}
+
+fn epoch(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Returns `text`, or unset if it is empty.
+fn text(text: &str) -> ZeekValue {
+    match text.is_empty() {
+        true => ZeekValue::Unset,
+        false => ZeekValue::Str(text.to_string()),
+    }
+}
+
+fn proto(five_tuple: &FiveTuple) -> ZeekValue {
+    let proto = match five_tuple.proto {
+        TCP_PROTOCOL => "tcp",
+        UDP_PROTOCOL => "udp",
+        ICMP_PROTOCOL | ICMPV6_PROTOCOL => "icmp",
+        _ => "unknown_transport",
+    };
+    ZeekValue::Str(proto.to_string())
+}
+
+/// Returns the `ts`, `uid`, and `id` columns.
+fn conn_id(ts: SystemTime, five_tuple: &FiveTuple) -> Vec<ZeekValue> {
+    vec![
+        ZeekValue::Time(ts),
+        ZeekValue::Str(uid(five_tuple)),
+        ZeekValue::Addr(five_tuple.orig.ip()),
+        ZeekValue::Port(five_tuple.orig.port()),
+        ZeekValue::Addr(five_tuple.resp.ip()),
+        ZeekValue::Port(five_tuple.resp.port()),
+    ]
+}
+
+macro_rules! fields {
+    ($($name:literal: $ty:literal),* $(,)?) => {
+        &[
+            ("ts", "time"),
+            ("uid", "string"),
+            ("id.orig_h", "addr"),
+            ("id.orig_p", "port"),
+            ("id.resp_h", "addr"),
+            ("id.resp_p", "port"),
+            $(($name, $ty)),*
+        ]
+    };
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// A conn.log record.
+#[derive(Debug, Clone, Copy)]
+pub struct ZeekConn<'a> {
+    pub conn: &'a ConnRecord,
+    /// The application protocol, e.g., `ssl`, as named by Zeek.
+    pub service: Option<&'a str>,
+}
+
+impl<'a> ZeekConn<'a> {
+    pub fn new(conn: &'a ConnRecord) -> Self {
+        ZeekConn {
+            conn,
+            service: None,
+        }
+    }
+
+    /// Sets the `service` column.
+    pub fn with_service(mut self, service: &'a str) -> Self {
+        self.service = Some(service);
+        self
+    }
+}
+
+impl ZeekRecord for ZeekConn<'_> {
+    const PATH: &'static str = "conn";
+    const FIELDS: &'static [(&'static str, &'static str)] = fields![
+        "proto": "enum",
+        "service": "string",
+        "duration": "interval",
+        "orig_bytes": "count",
+        "resp_bytes": "count",
+        "conn_state": "string",
+        "local_orig": "bool",
+        "local_resp": "bool",
+        "missed_bytes": "count",
+        "history": "string",
+        "orig_pkts": "count",
+        "orig_ip_bytes": "count",
+        "resp_pkts": "count",
+        "resp_ip_bytes": "count",
+        "tunnel_parents": "set[string]",
+    ];
+
+    fn values(&self) -> Vec<ZeekValue> {
+        let conn = self.conn;
+        let mut values = conn_id(wallclock(conn.first_seen_ts), &conn.five_tuple);
+        let duration = match conn.total_pkts() > 1 {
+            true => ZeekValue::Interval(conn.duration()),
+            false => ZeekValue::Unset,
+        };
+        values.extend([
+            proto(&conn.five_tuple),
+            self.service.map_or(ZeekValue::Unset, text),
+            duration,
+            ZeekValue::Count(conn.orig.nb_bytes),
+            ZeekValue::Count(conn.resp.nb_bytes),
+            ZeekValue::Str(conn_state(conn).to_string()),
+            ZeekValue::Unset,
+            ZeekValue::Unset,
+            ZeekValue::Count(conn.orig.missed_bytes() + conn.resp.missed_bytes()),
+            text(&conn.history()),
+            ZeekValue::Count(conn.orig.nb_pkts),
+            ZeekValue::Unset,
+            ZeekValue::Count(conn.resp.nb_pkts),
+            ZeekValue::Unset,
+            ZeekValue::Unset,
+        ]);
+        values
+    }
+}
+
+/// Returns the Zeek connection state summarized by the history of `conn`.
+fn conn_state(conn: &ConnRecord) -> &'static str {
+    if conn.five_tuple.proto != TCP_PROTOCOL {
+        return match (conn.orig.nb_pkts > 0, conn.resp.nb_pkts > 0) {
+            (true, true) => "SF",
+            (true, false) => "S0",
+            _ => "OTH",
+        };
+    }
+    let has = |event: u8| conn.history.contains(&event);
+    let (syn, synack) = (has(b'S'), has(b'h'));
+    let (orig_fin, resp_fin) = (has(b'F'), has(b'f'));
+    let (orig_rst, resp_rst) = (has(b'R'), has(b'r'));
+    match (syn, synack) {
+        (true, false) if orig_rst => "RSTOS0",
+        (true, false) if resp_rst => "REJ",
+        (true, false) if orig_fin => "SH",
+        (true, false) => "S0",
+        (false, true) if resp_rst => "RSTRH",
+        (false, true) if resp_fin => "SHR",
+        (false, _) => "OTH",
+        (true, true) if orig_rst => "RSTO",
+        (true, true) if resp_rst => "RSTR",
+        (true, true) => match (orig_fin, resp_fin) {
+            (true, true) => "SF",
+            (true, false) => "S2",
+            (false, true) => "S3",
+            (false, false) => "S1",
+        },
+    }
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// A dns.log record.
+#[derive(Debug, Clone, Copy)]
+pub struct ZeekDns<'a> {
+    pub ts: SystemTime,
+    pub five_tuple: &'a FiveTuple,
+    pub dns: &'a Dns,
+}
+
+impl<'a> ZeekDns<'a> {
+    /// Creates a record of `dns` at the current time.
+    pub fn new(five_tuple: &'a FiveTuple, dns: &'a Dns) -> Self {
+        ZeekDns {
+            ts: SystemTime::now(),
+            five_tuple,
+            dns,
+        }
+    }
+}
+
+impl ZeekRecord for ZeekDns<'_> {
+    const PATH: &'static str = "dns";
+    const FIELDS: &'static [(&'static str, &'static str)] = fields![
+        "proto": "enum",
+        "trans_id": "count",
+        "rtt": "interval",
+        "query": "string",
+        "qclass": "count",
+        "qclass_name": "string",
+        "qtype": "count",
+        "qtype_name": "string",
+        "rcode": "count",
+        "rcode_name": "string",
+        "AA": "bool",
+        "TC": "bool",
+        "RD": "bool",
+        "RA": "bool",
+        "Z": "count",
+        "answers": "vector[string]",
+        "TTLs": "vector[interval]",
+        "rejected": "bool",
+    ];
+
+    fn values(&self) -> Vec<ZeekValue> {
+        let dns = self.dns;
+        let mut values = conn_id(self.ts, self.five_tuple);
+        values.extend([
+            proto(self.five_tuple),
+            ZeekValue::Count(dns.transaction_id as u64),
+            ZeekValue::Unset,
+        ]);
+        match dns.query.as_ref().filter(|query| !query.queries.is_empty()) {
+            Some(_) => values.extend([
+                text(dns.query_domain()),
+                ZeekValue::Count(1),
+                ZeekValue::Str("C_INTERNET".to_string()),
+                ZeekValue::Count(dns.qtype() as u64),
+                ZeekValue::Str(match dns.qtype() {
+                    255 => "*".to_string(),
+                    qtype => {
+                        qtype_name(qtype).map_or_else(|| format!("query-{}", qtype), str::to_string)
+                    }
+                }),
+            ]),
+            None => values.resize(values.len() + 5, ZeekValue::Unset),
+        }
+        match dns.response.as_ref() {
+            Some(response) => values.extend([
+                ZeekValue::Count(dns.rcode() as u64),
+                ZeekValue::Str(
+                    rcode_name(dns.rcode())
+                        .map_or_else(|| format!("unknown-{}", dns.rcode()), str::to_string),
+                ),
+                ZeekValue::Bool(response.authoritative),
+            ]),
+            None => values.extend([ZeekValue::Unset, ZeekValue::Unset, ZeekValue::Bool(false)]),
+        }
+        let recursion_desired = dns.query.as_ref().is_some_and(|q| q.recursion_desired);
+        let recursion_available = dns.response.as_ref().is_some_and(|r| r.recursion_available);
+        let answers = dns.response.as_ref().map(|r| &r.answers[..]).unwrap_or(&[]);
+        let (answers, ttls) = match answers.is_empty() {
+            true => (ZeekValue::Unset, ZeekValue::Unset),
+            false => (
+                ZeekValue::Vector(
+                    answers
+                        .iter()
+                        .map(|answer| ZeekValue::Str(rdata(&answer.data)))
+                        .collect(),
+                ),
+                ZeekValue::Vector(
+                    answers
+                        .iter()
+                        .map(|answer| ZeekValue::Interval(Duration::from_secs(answer.ttl as u64)))
+                        .collect(),
+                ),
+            ),
+        };
+        values.extend([
+            ZeekValue::Bool(false),
+            ZeekValue::Bool(recursion_desired),
+            ZeekValue::Bool(recursion_available),
+            ZeekValue::Count(0),
+            answers,
+            ttls,
+            ZeekValue::Bool(false),
+        ]);
+        values
+    }
+}
+
+/// Formats an answer as in the `answers` column.
+fn rdata(data: &Data) -> String {
+    match data {
+        Data::A(addr) => addr.to_string(),
+        Data::Aaaa(addr) => addr.to_string(),
+        Data::Cname(name) | Data::Ns(name) | Data::Ptr(name) => name.clone(),
+        Data::Mx(mx) => mx.exchange.clone(),
+        Data::Soa(soa) => soa.primary_ns.clone(),
+        Data::Srv(srv) => srv.target.clone(),
+        Data::Txt(txt) => format!("TXT {} {}", txt.len(), txt),
+        Data::Unknown => "<unknown type>".to_string(),
+    }
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// An http.log record.
+#[derive(Debug, Clone, Copy)]
+pub struct ZeekHttp<'a> {
+    pub ts: SystemTime,
+    pub five_tuple: &'a FiveTuple,
+    pub http: &'a Http,
+}
+
+impl<'a> ZeekHttp<'a> {
+    /// Creates a record of `http` at the current time.
+    pub fn new(five_tuple: &'a FiveTuple, http: &'a Http) -> Self {
+        ZeekHttp {
+            ts: SystemTime::now(),
+            five_tuple,
+            http,
+        }
+    }
+}
+
+impl ZeekRecord for ZeekHttp<'_> {
+    const PATH: &'static str = "http";
+    const FIELDS: &'static [(&'static str, &'static str)] = fields![
+        "trans_depth": "count",
+        "method": "string",
+        "host": "string",
+        "uri": "string",
+        "referrer": "string",
+        "version": "string",
+        "user_agent": "string",
+        "origin": "string",
+        "request_body_len": "count",
+        "response_body_len": "count",
+        "status_code": "count",
+        "status_msg": "string",
+        "info_code": "count",
+        "info_msg": "string",
+        "tags": "set[enum]",
+        "username": "string",
+        "password": "string",
+        "proxied": "set[string]",
+        "orig_fuids": "vector[string]",
+        "orig_filenames": "vector[string]",
+        "orig_mime_types": "vector[string]",
+        "resp_fuids": "vector[string]",
+        "resp_filenames": "vector[string]",
+        "resp_mime_types": "vector[string]",
+    ];
+
+    fn values(&self) -> Vec<ZeekValue> {
+        let http = self.http;
+        let version = match http.response_version() {
+            "" => http.request_version(),
+            version => version,
+        };
+        let status_code = match http.response.status_code {
+            Some(code) => ZeekValue::Count(code as u64),
+            None => ZeekValue::Unset,
+        };
+        let mime_types = |content_type: &str| match content_type.is_empty() {
+            true => ZeekValue::Unset,
+            false => ZeekValue::Vector(vec![ZeekValue::Str(content_type.to_string())]),
+        };
+        let mut values = conn_id(self.ts, self.five_tuple);
+        values.extend([
+            // Zeek counts transactions from 1
+            ZeekValue::Count(http.trans_depth as u64 + 1),
+            text(http.method()),
+            text(http.host()),
+            text(http.uri()),
+            text(http.header("referer")),
+            text(version.trim_start_matches("HTTP/")),
+            text(http.user_agent()),
+            text(http.header("origin")),
+            ZeekValue::Count(http.request_content_length() as u64),
+            ZeekValue::Count(http.response_content_length() as u64),
+            status_code,
+            text(http.status_msg()),
+            ZeekValue::Unset,
+            ZeekValue::Unset,
+            ZeekValue::Vector(vec![]),
+            ZeekValue::Unset,
+            ZeekValue::Unset,
+            ZeekValue::Unset,
+            ZeekValue::Unset,
+            ZeekValue::Unset,
+            mime_types(http.request_content_type()),
+            ZeekValue::Unset,
+            ZeekValue::Unset,
+            mime_types(http.response_content_type()),
+        ]);
+        values
+    }
+}
+
+/* --------------------------------------------------------------------------------- */
+
+/// An ssl.log record.
+#[derive(Debug, Clone, Copy)]
+pub struct ZeekSsl<'a> {
+    pub ts: SystemTime,
+    pub five_tuple: &'a FiveTuple,
+    pub tls: &'a Tls,
+}
+
+impl<'a> ZeekSsl<'a> {
+    /// Creates a record of `tls` at the current time.
+    pub fn new(five_tuple: &'a FiveTuple, tls: &'a Tls) -> Self {
+        ZeekSsl {
+            ts: SystemTime::now(),
+            five_tuple,
+            tls,
+        }
+    }
+}
+
+impl ZeekRecord for ZeekSsl<'_> {
+    const PATH: &'static str = "ssl";
+    const FIELDS: &'static [(&'static str, &'static str)] = fields![
+        "version": "string",
+        "cipher": "string",
+        "curve": "string",
+        "server_name": "string",
+        "resumed": "bool",
+        "last_alert": "string",
+        "next_protocol": "string",
+        "established": "bool",
+        "ssl_history": "string",
+        "cert_chain_fps": "vector[string]",
+        "client_cert_chain_fps": "vector[string]",
+        "sni_matches_cert": "bool",
+    ];
+
+    fn values(&self) -> Vec<ZeekValue> {
+        let tls = self.tls;
+        let server_hello = tls.server_hello.as_ref();
+        let curve = server_hello
+            .and_then(|server_hello| server_hello.key_share.as_ref())
+            .map(|key_share| key_share.group)
+            .or(match &tls.server_key_exchange {
+                Some(ServerKeyExchange::Ecdh(params)) => Some(params.curve),
+                _ => None,
+            })
+            .map_or(ZeekValue::Unset, |group| {
+                ZeekValue::Str(format!("{:?}", group).to_lowercase())
+            });
+        // The server echoes the session ID offered by the client to resume a session
+        let resumed = match (&tls.client_hello, server_hello) {
+            (Some(client_hello), Some(server_hello)) => {
+                !client_hello.session_id.is_empty()
+                    && client_hello.session_id == server_hello.session_id
+            }
+            _ => false,
+        };
+        let mut values = conn_id(self.ts, self.five_tuple);
+        values.extend([
+            version_name(tls.version()),
+            text(&tls.cipher()),
+            curve,
+            text(tls.sni()),
+            ZeekValue::Bool(resumed),
+            ZeekValue::Unset,
+            server_hello
+                .and_then(|server_hello| server_hello.alpn_protocol.as_deref())
+                .map_or(ZeekValue::Unset, text),
+            // Retina does not track the Finished messages: a handshake is considered established
+            // once the server has replied
+            ZeekValue::Bool(server_hello.is_some()),
+            ZeekValue::Unset,
+            fingerprints(&tls.server_certificates),
+            fingerprints(&tls.client_certificates),
+            ZeekValue::Unset,
+        ]);
+        values
+    }
+}
+
+fn version_name(version: u16) -> ZeekValue {
+    let name = match version {
+        0 => return ZeekValue::Unset,
+        0x0002 => "SSLv2",
+        0x0300 => "SSLv3",
+        0x0301 => "TLSv10",
+        0x0302 => "TLSv11",
+        0x0303 => "TLSv12",
+        0x0304 => "TLSv13",
+        _ => return ZeekValue::Str(format!("unknown-{}", version)),
+    };
+    ZeekValue::Str(name.to_string())
+}
+
+/// Returns the SHA-256 fingerprints of `certificates`, or unset if there are none.
+fn fingerprints(certificates: &[Certificate]) -> ZeekValue {
+    match certificates.is_empty() {
+        true => ZeekValue::Unset,
+        false => ZeekValue::Vector(
+            certificates
+                .iter()
+                .map(|cert| ZeekValue::Str(sha256(&cert.raw)))
+                .collect(),
+        ),
+    }
+}
+
+/// Returns the hex-encoded SHA-256 digest of `data`.
+fn sha256(data: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, data);
+    let mut hex = String::with_capacity(64);
+    for byte in digest.as_ref() {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Record(Vec<ZeekValue>);
+
+    impl ZeekRecord for Record {
+        const PATH: &'static str = "test";
+        const FIELDS: &'static [(&'static str, &'static str)] = &[
+            ("ts", "time"),
+            ("query", "string"),
+            ("answers", "vector[string]"),
+            ("AA", "bool"),
+            ("rtt", "interval"),
+        ];
+
+        fn values(&self) -> Vec<ZeekValue> {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn core_zeek_format() {
+        let record = Record(vec![
+            ZeekValue::Time(UNIX_EPOCH + Duration::from_micros(1_700_000_000_250_000)),
+            ZeekValue::Str("a\tb\\c d".to_string()),
+            ZeekValue::Vector(vec![
+                ZeekValue::Str("x,y".to_string()),
+                ZeekValue::Str(String::new()),
+            ]),
+            ZeekValue::Bool(true),
+            ZeekValue::Unset,
+        ]);
+        assert_eq!(
+            format(&record, ZeekFormat::Tsv),
+            "1700000000.250000\ta\\x09b\\x5cc d\tx\\x2cy,(empty)\tT\t-"
+        );
+        assert_eq!(
+            format(&record, ZeekFormat::Json),
+            r#"{"ts":1700000000.250000,"query":"a\tb\\c d","answers":["x,y",""],"AA":true}"#
+        );
+        assert_eq!(version_name(0x0303), ZeekValue::Str("TLSv12".to_string()));
+    }
+}
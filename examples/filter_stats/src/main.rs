@@ -1,41 +1,15 @@
-use array_init::array_init;
 use retina_core::config::load_config;
+use retina_core::utils::sink::Sink;
 use retina_core::{CoreId, Runtime};
 use retina_datatypes::*;
 use retina_filtergen::subscription;
-use std::sync::atomic::{AtomicPtr, Ordering};
 
 use clap::Parser;
-use lazy_static::lazy_static;
-use std::fs::File;
-use std::io::{BufWriter, Write};
 use std::path::PathBuf;
+use std::sync::OnceLock;
 
-// Number of cores being used by the runtime; should match config file
-// Should be defined at compile-time so that we can use a
-// statically-sized array for RESULTS
-const NUM_CORES: usize = 16;
-// Add 1 for ARR_LEN to avoid overflow; one core is used as main_core
-const ARR_LEN: usize = NUM_CORES + 1;
-// Temporary per-core files
-const OUTFILE_PREFIX: &str = "filter_stats_";
-
-lazy_static! {
-    static ref RESULTS: [AtomicPtr<BufWriter<File>>; ARR_LEN] = {
-        let mut results = vec![];
-        for core_id in 0..ARR_LEN {
-            let file_name = String::from(OUTFILE_PREFIX) + &format!("{}", core_id) + ".jsonl";
-            let core_wtr = BufWriter::new(File::create(&file_name).unwrap());
-            let core_wtr = Box::into_raw(Box::new(core_wtr));
-            results.push(core_wtr);
-        }
-        array_init(|i| AtomicPtr::new(results[i].clone()))
-    };
-}
-
-fn init() {
-    println!("Initializing {} results", RESULTS.len());
-}
+// Each core writes to its own file, which are merged into `outfile` after the run
+static RESULTS: OnceLock<Sink> = OnceLock::new();
 
 #[derive(Parser, Debug)]
 struct Args {
@@ -51,78 +25,49 @@ struct Args {
     outfile: PathBuf,
 }
 
-fn write_result(key: &str, value: String, core_id: &CoreId) {
+fn write_result(key: &str, value: String) {
     if value.is_empty() {
         return;
     } // Would it be helpful to count these?
-    let with_proto = format!("\n{}: {}", key, value);
-    let ptr = RESULTS[core_id.raw() as usize].load(Ordering::Relaxed);
-    let wtr = unsafe { &mut *ptr };
-    wtr.write_all(with_proto.as_bytes()).unwrap();
+    let with_proto = format!("{}: {}", key, value);
+    RESULTS.get().unwrap().write_line(&with_proto).unwrap();
 }
 
-fn dns_cb(dns: &DnsTransaction, core_id: &CoreId, filter_str: &FilterStr) {
+fn dns_cb(dns: &DnsTransaction, _core_id: &CoreId, filter_str: &FilterStr) {
     let query_domain = (*dns).query_domain().to_string();
-    write_result(*filter_str, query_domain, core_id);
+    write_result(*filter_str, query_domain);
 }
 
-fn http_cb(http: &HttpTransaction, core_id: &CoreId, filter_str: &FilterStr) {
+fn http_cb(http: &HttpTransaction, _core_id: &CoreId, filter_str: &FilterStr) {
     let uri = (*http).uri().to_string();
-    write_result(*filter_str, uri, core_id);
+    write_result(*filter_str, uri);
 }
 
-fn tls_cb(tls: &TlsHandshake, core_id: &CoreId, filter_str: &FilterStr) {
+fn tls_cb(tls: &TlsHandshake, _core_id: &CoreId, filter_str: &FilterStr) {
     let sni = (*tls).sni().to_string();
-    write_result(*filter_str, sni, core_id);
+    write_result(*filter_str, sni);
 }
 
 #[allow(dead_code)]
-fn quic_cb(quic: &QuicStream, core_id: &CoreId, filter_str: &FilterStr) {
+fn quic_cb(quic: &QuicStream, _core_id: &CoreId, filter_str: &FilterStr) {
     let sni = (*quic).tls.sni().to_string();
-    write_result(*filter_str, sni, core_id);
-}
-
-fn packet_cb(_frame: &ZcFrame, core_id: &CoreId, filter_str: &FilterStr) {
-    write_result(*filter_str, String::from(""), core_id);
+    write_result(*filter_str, sni);
 }
 
-fn conn_cb(core_id: &CoreId, filter_str: &FilterStr) {
-    write_result(*filter_str, String::from(""), core_id);
+fn packet_cb(_frame: &ZcFrame, _core_id: &CoreId, filter_str: &FilterStr) {
+    write_result(*filter_str, String::from(""));
 }
 
-fn combine_results(outfile: &PathBuf) {
-    println!("Combining results from {} cores...", NUM_CORES);
-    let mut results = Vec::new();
-    for core_id in 0..ARR_LEN {
-        let fp = String::from(OUTFILE_PREFIX) + &format!("{}", core_id) + ".jsonl";
-        let content = std::fs::read(fp.clone()).unwrap();
-        results.extend_from_slice(&content);
-        std::fs::remove_file(fp).unwrap();
-    }
-    let mut file = std::fs::File::create(outfile).unwrap();
-    file.write_all(&results).unwrap();
+fn conn_cb(_core_id: &CoreId, filter_str: &FilterStr) {
+    write_result(*filter_str, String::from(""));
 }
 
 #[subscription("./examples/filter_stats/spec.toml")]
 fn main() {
-    init();
     let args = Args::parse();
     let config = load_config(&args.config);
-    let cores = config.get_all_rx_core_ids();
-    let num_cores = cores.len();
-    if num_cores > NUM_CORES {
-        panic!(
-            "Compile-time NUM_CORES ({}) must be <= num cores ({}) in config file",
-            NUM_CORES, num_cores
-        );
-    }
-    if cores.len() > 1 && !cores.windows(2).all(|w| w[1].raw() - w[0].raw() == 1) {
-        panic!("Cores in config file should be consecutive for zero-lock indexing");
-    }
-    if cores[0].raw() > 1 {
-        panic!("RX core IDs should start at 0 or 1");
-    }
+    let results = RESULTS.get_or_init(|| Sink::new(&args.outfile));
     let mut runtime: Runtime<SubscribedWrapper> = Runtime::new(config, filter).unwrap();
+    runtime.add_sink(results);
     runtime.run();
-    combine_results(&args.outfile);
 }
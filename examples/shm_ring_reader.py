@@ -0,0 +1,49 @@
+"""Reference consumer of a Retina shared-memory ring (see retina_core::utils::shm_ring).
+
+Prints each record (e.g., a JSON document) on its own line:
+    python3 shm_ring_reader.py /dev/shm/retina_tls
+"""
+import argparse, mmap, struct, sys, time
+
+MAGIC = b"RETINARB"
+HEADER_SIZE = 192
+HEAD_OFFSET, TAIL_OFFSET, DROPPED_OFFSET = 64, 128, 24
+RECORD, FILLER = 1, 2
+
+def align(n):
+    return (n + 7) & ~7
+
+def main():
+    parser = argparse.ArgumentParser()
+    parser.add_argument("path", help="Path of the ring file")
+    parser.add_argument("--interval", type=float, default=0.01, help="Polling interval when empty (s)")
+    args = parser.parse_args()
+
+    with open(args.path, "r+b") as f:
+        ring = mmap.mmap(f.fileno(), 0)
+    if ring[:8] != MAGIC:
+        sys.exit(f"{args.path} is not a Retina ring")
+    version, = struct.unpack_from("<I", ring, 8)
+    if version != 1:
+        sys.exit(f"unsupported ring version {version}")
+    capacity, = struct.unpack_from("<Q", ring, 16)
+
+    try:
+        while True:
+            tail, = struct.unpack_from("<Q", ring, TAIL_OFFSET)
+            offset = HEADER_SIZE + tail % capacity
+            length, flags = struct.unpack_from("<II", ring, offset)
+            if flags == 0:
+                time.sleep(args.interval)
+                continue
+            stride = align(8 + length)
+            if flags == RECORD:
+                sys.stdout.buffer.write(ring[offset + 8:offset + 8 + length] + b"\n")
+            ring[offset:offset + stride] = bytes(stride)
+            struct.pack_into("<Q", ring, TAIL_OFFSET, tail + stride)
+    except KeyboardInterrupt:
+        dropped, = struct.unpack_from("<Q", ring, DROPPED_OFFSET)
+        print(f"{dropped} records dropped by the writer", file=sys.stderr)
+
+if __name__ == "__main__":
+    main()
@@ -1,43 +1,15 @@
-use array_init::array_init;
 use retina_core::config::load_config;
-use retina_core::{CoreId, Runtime};
+use retina_core::utils::sink::Sink;
+use retina_core::Runtime;
 use retina_datatypes::*;
 use retina_filtergen::{filter, retina_main};
-use std::sync::atomic::{AtomicPtr, Ordering};
 
 use clap::Parser;
-use std::fs::File;
-use std::io::{BufWriter, Write};
 use std::path::PathBuf;
 use std::sync::OnceLock;
 
-// Number of cores being used by the runtime; should match config file
-// Should be defined at compile-time so that we can use a
-// statically-sized array for results()
-const NUM_CORES: usize = 16;
-// Add 1 for ARR_LEN to avoid overflow; one core is used as main_core
-const ARR_LEN: usize = NUM_CORES + 1;
-// Temporary per-core files
-const OUTFILE_PREFIX: &str = "websites_";
-
-static RESULTS: OnceLock<[AtomicPtr<BufWriter<File>>; ARR_LEN]> = OnceLock::new();
-
-fn results() -> &'static [AtomicPtr<BufWriter<File>>; ARR_LEN] {
-    RESULTS.get_or_init(|| {
-        let mut outp = vec![];
-        for core_id in 0..ARR_LEN {
-            let file_name = String::from(OUTFILE_PREFIX) + &format!("{}", core_id) + ".jsonl";
-            let core_wtr = BufWriter::new(File::create(&file_name).unwrap());
-            let core_wtr = Box::into_raw(Box::new(core_wtr));
-            outp.push(core_wtr);
-        }
-        array_init(|i| AtomicPtr::new(outp[i]))
-    })
-}
-
-fn init() {
-    let _ = results();
-}
+// Each core writes to its own file, which are merged into `outfile` after the run
+static RESULTS: OnceLock<Sink> = OnceLock::new();
 
 #[derive(Parser, Debug)]
 struct Args {
@@ -53,76 +25,44 @@ struct Args {
     outfile: PathBuf,
 }
 
-fn write_result(key: &str, value: String, core_id: &CoreId) {
+fn write_result(key: &str, value: String) {
     if value.is_empty() {
         return;
     } // Would it be helpful to count these?
-    let with_proto = format!("\n{}: {}", key, value);
-    let ptr = results()[core_id.raw() as usize].load(Ordering::Relaxed);
-    let wtr = unsafe { &mut *ptr };
-    wtr.write_all(with_proto.as_bytes()).unwrap();
+    let with_proto = format!("{}: {}", key, value);
+    RESULTS.get().unwrap().write_line(&with_proto).unwrap();
 }
 
 #[filter("dns")]
-fn dns_cb(dns: &DnsTransaction, core_id: &CoreId) {
+fn dns_cb(dns: &DnsTransaction) {
     let query_domain = (*dns).query_domain().to_string();
-    write_result("dns", query_domain, core_id);
+    write_result("dns", query_domain);
 }
 
 #[filter("http")]
-fn http_cb(http: &HttpTransaction, core_id: &CoreId) {
+fn http_cb(http: &HttpTransaction) {
     let uri = (*http).uri().to_string();
-    write_result("http", uri, core_id);
+    write_result("http", uri);
 }
 
 #[filter("tls")]
-fn tls_cb(tls: &TlsHandshake, core_id: &CoreId) {
+fn tls_cb(tls: &TlsHandshake) {
     let sni = (*tls).sni().to_string();
-    write_result("tls", sni, core_id);
+    write_result("tls", sni);
 }
 
 #[filter("quic")]
-fn quic_cb(quic: &QuicStream, core_id: &CoreId) {
+fn quic_cb(quic: &QuicStream) {
     let sni = quic.tls.sni().to_string();
-    write_result("quic", sni, core_id);
-}
-
-fn combine_results(outfile: &PathBuf) {
-    println!("Combining results from {} cores...", NUM_CORES);
-    let mut output = Vec::new();
-    for core_id in 0..ARR_LEN {
-        let ptr = results()[core_id].load(Ordering::Relaxed);
-        let wtr = unsafe { &mut *ptr };
-        wtr.flush().unwrap();
-        let fp = String::from(OUTFILE_PREFIX) + &format!("{}", core_id) + ".jsonl";
-        let content = std::fs::read(fp.clone()).unwrap();
-        output.extend_from_slice(&content);
-        std::fs::remove_file(fp).unwrap();
-    }
-    let mut file = std::fs::File::create(outfile).unwrap();
-    file.write_all(&output).unwrap();
+    write_result("quic", sni);
 }
 
 #[retina_main(4)]
 fn main() {
-    init();
     let args = Args::parse();
     let config = load_config(&args.config);
-    let cores = config.get_all_rx_core_ids();
-    let num_cores = cores.len();
-    if num_cores > NUM_CORES {
-        panic!(
-            "Compile-time NUM_CORES ({}) must be <= num cores ({}) in config file",
-            NUM_CORES, num_cores
-        );
-    }
-    if cores.len() > 1 && !cores.windows(2).all(|w| w[1].raw() - w[0].raw() == 1) {
-        panic!("Cores in config file should be consecutive for zero-lock indexing");
-    }
-    if cores[0].raw() > 1 {
-        panic!("RX core IDs should start at 0 or 1");
-    }
+    let results = RESULTS.get_or_init(|| Sink::new(&args.outfile));
     let mut runtime: Runtime<SubscribedWrapper> = Runtime::new(config, filter).unwrap();
+    runtime.add_sink(results);
     runtime.run();
-    combine_results(&args.outfile);
 }
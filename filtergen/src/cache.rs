@@ -1,5 +1,9 @@
 use super::parse::{ConfigRaw, SubscriptionRaw};
 use quote::ToTokens;
+use retina_core::filter::DataType;
+use retina_core::protocols::stream::custom::register_protocol;
+use retina_datatypes::{DATATYPES, NOT_CLONEABLE};
+use std::collections::{HashMap, HashSet};
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Mutex,
@@ -10,9 +14,93 @@ lazy_static! {
     pub(crate) static ref CACHED_SUBSCRIPTIONS: Mutex<ConfigRaw> = Mutex::new(ConfigRaw {
         subscriptions: vec![]
     });
+    // Datatypes defined in the application with the `datatype` attribute
+    pub(crate) static ref CACHED_DATATYPES: Mutex<HashMap<&'static str, DataType>> =
+        Mutex::new(HashMap::new());
+    pub(crate) static ref CACHED_NOT_CLONEABLE: Mutex<HashSet<&'static str>> =
+        Mutex::new(HashSet::new());
+    // Datatypes defined with `state = true`, passed to callbacks by mutable reference
+    pub(crate) static ref CACHED_STATES: Mutex<HashSet<&'static str>> =
+        Mutex::new(HashSet::new());
+    // Protocol parsers defined in the application with the `parser` attribute: protocol name,
+    // transport protocols, and name of the parser type
+    pub(crate) static ref CACHED_PARSERS: Mutex<Vec<(&'static str, Vec<&'static str>, String)>> =
+        Mutex::new(vec![]);
 }
 
-pub(crate) fn parse_input(input: &syn::ItemFn) -> (Vec<String>, String) {
+pub(crate) fn add_parser(name: String, transports: Vec<String>, parser: String) {
+    let name: &'static str = Box::leak(name.into_boxed_str());
+    let transports: Vec<&'static str> = transports
+        .into_iter()
+        .map(|t| Box::leak(t.into_boxed_str()) as &'static str)
+        .collect();
+    // Filters on the protocol are parsed when subscriptions are added
+    if let Err(e) = register_protocol(name, &transports) {
+        panic!("{}", e);
+    }
+    CACHED_PARSERS
+        .lock()
+        .unwrap()
+        .push((name, transports, parser));
+}
+
+// Returns the name of the parser type of custom protocol `name`
+pub(crate) fn get_parser(name: &str) -> Option<String> {
+    CACHED_PARSERS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(proto, _, _)| *proto == name)
+        .map(|(_, _, parser)| parser.clone())
+}
+
+pub(crate) fn custom_parsers() -> Vec<(&'static str, Vec<&'static str>, String)> {
+    CACHED_PARSERS.lock().unwrap().clone()
+}
+
+pub(crate) fn add_datatype(datatype: DataType, cloneable: bool, state: bool) {
+    let name = datatype.as_str;
+    if DATATYPES.contains_key(name) {
+        panic!("Datatype {} is already defined by retina-datatypes", name);
+    }
+    // State is mutated by callbacks in place, so is never copied
+    if !cloneable || state {
+        CACHED_NOT_CLONEABLE.lock().unwrap().insert(name);
+    }
+    if state {
+        CACHED_STATES.lock().unwrap().insert(name);
+    }
+    CACHED_DATATYPES.lock().unwrap().insert(name, datatype);
+}
+
+// Returns the datatype named `name`, from retina-datatypes or defined in the application
+pub(crate) fn get_datatype(name: &str) -> Option<DataType> {
+    match DATATYPES.get(name) {
+        Some(datatype) => Some(datatype.clone()),
+        None => CACHED_DATATYPES.lock().unwrap().get(name).cloned(),
+    }
+}
+
+// Names of all datatypes, from retina-datatypes and defined in the application
+pub(crate) fn datatype_names() -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = DATATYPES.keys().copied().collect();
+    names.extend(CACHED_DATATYPES.lock().unwrap().keys());
+    names
+}
+
+pub(crate) fn is_cloneable(name: &str) -> bool {
+    !NOT_CLONEABLE.contains(name) && !CACHED_NOT_CLONEABLE.lock().unwrap().contains(name)
+}
+
+// Returns `true` if `name` is per-connection state defined in the application
+pub(crate) fn is_state(name: &str) -> bool {
+    CACHED_STATES.lock().unwrap().contains(name)
+}
+
+// Returns the datatypes and name of the callback, and whether it is an `async fn`. Async callbacks
+// take their datatypes by value.
+pub(crate) fn parse_input(input: &syn::ItemFn) -> (Vec<String>, String, bool) {
+    let is_async = input.sig.asyncness.is_some();
     let datatypes = input
         .sig
         .inputs
@@ -20,6 +108,19 @@ pub(crate) fn parse_input(input: &syn::ItemFn) -> (Vec<String>, String) {
         .filter_map(|arg| {
             if let syn::FnArg::Typed(syn::PatType { pat: _, ty, .. }) = arg {
                 let mut param_type: String = (*ty).to_token_stream().to_string();
+                if is_async {
+                    if param_type.contains("&") {
+                        panic!(
+                            "Parameters to async callbacks must be passed by value ({})",
+                            param_type
+                        );
+                    }
+                    // `FilterStr<'static>`
+                    if param_type.starts_with("FilterStr") {
+                        return Some("FilterStr".to_string());
+                    }
+                    return Some(param_type);
+                }
                 if !param_type.contains("&") {
                     panic!(
                         "Parameters to callbacks must be passed by reference ({})",
@@ -27,6 +128,18 @@ pub(crate) fn parse_input(input: &syn::ItemFn) -> (Vec<String>, String) {
                     );
                 }
                 param_type = param_type.replace("&", "").trim().to_string();
+                // Per-connection state is passed by mutable reference, other datatypes are not
+                let mutable = param_type.starts_with("mut ");
+                if mutable {
+                    param_type = param_type["mut ".len()..].trim().to_string();
+                }
+                if mutable != is_state(&param_type) {
+                    panic!(
+                        "Parameters to callbacks must be passed by mutable reference if and \
+                         only if they are per-connection state ({})",
+                        param_type
+                    );
+                }
                 return Some(param_type);
             }
             None
@@ -35,10 +148,23 @@ pub(crate) fn parse_input(input: &syn::ItemFn) -> (Vec<String>, String) {
 
     let callback = input.sig.ident.to_token_stream().to_string();
 
-    (datatypes, callback)
+    (datatypes, callback, is_async)
 }
 
-pub(crate) fn add_subscription(callback: String, datatypes: Vec<String>, filter: String) {
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn add_subscription(
+    callback: String,
+    datatypes: Vec<String>,
+    filter: String,
+    action: Option<String>,
+    is_async: bool,
+    sample_connections: Option<f64>,
+    group: Option<String>,
+    priority: i32,
+    with: Option<String>,
+    max_matches: Option<u64>,
+    stream_sessions: bool,
+) {
     CACHED_SUBSCRIPTIONS
         .lock()
         .unwrap()
@@ -47,6 +173,14 @@ pub(crate) fn add_subscription(callback: String, datatypes: Vec<String>, filter:
             filter,
             datatypes,
             callback,
+            action,
+            is_async,
+            sample_connections,
+            group,
+            priority,
+            with,
+            max_matches,
+            stream_sessions,
         });
 }
 
@@ -1,11 +1,15 @@
 use proc_macro2::{Ident, Span};
-use retina_core::filter::{ptree::FilterLayer, Level, SubscriptionSpec};
+use retina_core::filter::ast::Predicate;
+use retina_core::filter::{
+    ptree::FilterLayer, DataType, Filter, Level, PacketAction, SubscriptionSpec,
+};
 use retina_core::protocols::stream::ConnParser;
 use retina_datatypes::*;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use quote::quote;
 
+use crate::cache::{get_parser, is_cloneable, is_state};
 use crate::SubscriptionConfig;
 
 pub(crate) struct TrackedDataBuilder {
@@ -13,7 +17,11 @@ pub(crate) struct TrackedDataBuilder {
     struct_def: Vec<proc_macro2::TokenStream>,
     new: Vec<proc_macro2::TokenStream>,
     clear: Vec<proc_macro2::TokenStream>,
+    terminate: Vec<proc_macro2::TokenStream>,
+    icmp_error: Vec<proc_macro2::TokenStream>,
     stream_protocols: HashSet<&'static str>,
+    /// Session fields needed from each parsed protocol, or `None` if whole sessions are needed.
+    session_fields: HashMap<&'static str, Option<HashSet<String>>>,
     datatypes: HashSet<&'static str>,
 }
 
@@ -24,7 +32,10 @@ impl TrackedDataBuilder {
             struct_def: vec![],
             new: vec![],
             clear: vec![],
+            terminate: vec![],
+            icmp_error: vec![],
             stream_protocols: HashSet::new(),
+            session_fields: HashMap::new(),
             datatypes: HashSet::new(),
         };
         ret.build(subscribed_data);
@@ -33,8 +44,18 @@ impl TrackedDataBuilder {
 
     pub(crate) fn build(&mut self, subscribed_data: &SubscriptionConfig) {
         for spec in &subscribed_data.subscriptions {
-            self.stream_protocols
-                .extend(ConnParser::requires_parsing(&spec.filter));
+            let filter_protocols = ConnParser::requires_parsing(&spec.filter);
+            for protocol in &filter_protocols {
+                let fields = self
+                    .session_fields
+                    .entry(*protocol)
+                    .or_insert_with(|| Some(HashSet::new()));
+                match (fields.as_mut(), filter_fields(&spec.filter, protocol)) {
+                    (Some(fields), Some(filter_fields)) => fields.extend(filter_fields),
+                    _ => *fields = None,
+                }
+            }
+            self.stream_protocols.extend(filter_protocols);
             for datatype in &spec.datatypes {
                 let name = datatype.as_str;
                 if self.datatypes.contains(name) || name == *FILTER_STR {
@@ -42,6 +63,9 @@ impl TrackedDataBuilder {
                 }
                 self.datatypes.insert(name);
                 self.stream_protocols.extend(&datatype.stream_protos);
+                for protocol in &datatype.stream_protos {
+                    self.session_fields.insert(*protocol, None);
+                }
                 if matches!(datatype.level, Level::Session)
                     || matches!(datatype.level, Level::Packet)
                     || DIRECTLY_TRACKED.contains_key(name)
@@ -52,10 +76,39 @@ impl TrackedDataBuilder {
                 let type_name = Ident::new(name, Span::call_site());
                 let field_name = Ident::new(&name.to_lowercase(), Span::call_site());
 
+                if is_state(name) {
+                    // Borrowed mutably by callbacks, which only have a shared reference to the
+                    // tracked data
+                    self.struct_def.push(quote! {
+                        #field_name : ::std::cell::RefCell<#type_name>,
+                    });
+                    self.new.push(quote! {
+                        #field_name: ::std::cell::RefCell::new(#type_name::new(pdu)),
+                    });
+                    self.terminate
+                        .push(quote! { self.#field_name.get_mut().terminate(reason); });
+                    self.icmp_error
+                        .push(quote! { self.#field_name.get_mut().icmp_error(error, dir); });
+                    if datatype.needs_update {
+                        self.clear
+                            .push(quote! { self.#field_name.get_mut().clear(); });
+                        self.update
+                            .push(quote! { self.#field_name.get_mut().update(pdu, reassembled); });
+                    }
+                    continue;
+                }
+
                 self.struct_def.push(quote! {
                     #field_name : #type_name,
                 });
                 self.new.push(quote! { #field_name: #type_name::new(pdu), });
+                // Static data does not track connection events
+                if matches!(datatype.level, Level::Connection) {
+                    self.terminate
+                        .push(quote! { self.#field_name.terminate(reason); });
+                    self.icmp_error
+                        .push(quote! { self.#field_name.icmp_error(error, dir); });
+                }
 
                 if datatype.needs_update {
                     self.clear.push(quote! { self.#field_name.clear(); });
@@ -94,10 +147,35 @@ impl TrackedDataBuilder {
         let update = std::mem::take(&mut self.update);
         let new = std::mem::take(&mut self.new);
         let clear = std::mem::take(&mut self.clear);
+        let terminate = std::mem::take(&mut self.terminate);
+        let icmp_error = std::mem::take(&mut self.icmp_error);
 
         let mut conn_parsers: Vec<proc_macro2::TokenStream> = vec![];
+        let mut custom_parsers: Vec<proc_macro2::TokenStream> = vec![];
         for datatype in &self.stream_protocols {
-            conn_parsers.push(quote! { #datatype, });
+            match get_parser(datatype) {
+                Some(parser) => {
+                    let parser = Ident::new(&parser, Span::call_site());
+                    let parsable = quote! { retina_core::protocols::stream::ConnParsable };
+                    custom_parsers.push(quote! {
+                        .with_custom(#datatype, || -> Box<dyn #parsable> {
+                            Box::new(#parser::default())
+                        })
+                    });
+                }
+                None => conn_parsers.push(quote! { #datatype, }),
+            }
+        }
+
+        let mut restricted_parsers = vec![];
+        let mut session_fields: Vec<_> = self.session_fields.iter().collect();
+        session_fields.sort_by_key(|(protocol, _)| **protocol);
+        for (protocol, fields) in session_fields {
+            if let Some(fields) = fields {
+                let mut fields: Vec<_> = fields.iter().collect();
+                fields.sort();
+                restricted_parsers.push(quote! { .with_fields(#protocol, &[ #( #fields ),* ]) });
+            }
         }
 
         quote! {
@@ -133,7 +211,12 @@ impl TrackedDataBuilder {
                 }
 
                 fn track_packet(&mut self, mbuf: retina_core::Mbuf) {
-                    self.mbufs.push(mbuf);
+                    if self.mbufs.capacity() == 0 {
+                        self.mbufs = retina_core::memory::pool::take_packets();
+                    }
+                    if let Some(mbuf) = retina_core::memory::pool::track(mbuf) {
+                        self.mbufs.push(mbuf);
+                    }
                 }
 
                 fn packets(&self) -> &Vec<retina_core::Mbuf> {
@@ -141,7 +224,7 @@ impl TrackedDataBuilder {
                 }
 
                 fn drain_packets(&mut self) {
-                    self.mbufs = vec![];
+                    retina_core::memory::pool::recycle_packets(std::mem::take(&mut self.mbufs));
                 }
 
                 fn clear(&mut self) {
@@ -150,6 +233,19 @@ impl TrackedDataBuilder {
                     #( #clear )*
                 }
 
+                fn terminate(&mut self,
+                             reason: retina_core::conntrack::conn::TerminationReason)
+                {
+                    #( #terminate )*
+                }
+
+                fn icmp_error(&mut self,
+                              error: &retina_core::conntrack::pdu::IcmpError,
+                              dir: bool)
+                {
+                    #( #icmp_error )*
+                }
+
                 fn sessions(&self) -> &Vec<retina_core::protocols::Session> {
                     &self.sessions
                 }
@@ -160,10 +256,33 @@ impl TrackedDataBuilder {
 
                 fn parsers() -> retina_core::protocols::stream::ParserRegistry {
                     retina_core::protocols::stream::ParserRegistry::from_strings(vec![ #( #conn_parsers )* ])
+                        #( #custom_parsers )*
+                        #( #restricted_parsers )*
+                }
+            }
+        }
+    }
+}
+
+// Returns the fields of `protocol` that `filter` compares, or `None` if the filter is invalid
+fn filter_fields(filter: &str, protocol: &str) -> Option<HashSet<String>> {
+    let filter = Filter::new(filter).ok()?;
+    let mut fields = HashSet::new();
+    for pattern in filter.get_patterns_flat() {
+        for predicate in pattern.predicates {
+            if let Predicate::Binary {
+                protocol: name,
+                field,
+                ..
+            } = predicate
+            {
+                if name.name() == protocol {
+                    fields.insert(field.name().to_string());
                 }
             }
         }
     }
+    Some(fields)
 }
 
 // Build parameters for a packet-level subscription
@@ -206,12 +325,141 @@ pub(crate) fn build_packet_params(
     (params, type_ident)
 }
 
+// Counts invocations of the subscription callback for the runtime metrics.
+fn delivery_counter(spec: &SubscriptionSpec) -> proc_macro2::TokenStream {
+    let name = &spec.callback;
+    quote! {
+        {
+            static DELIVERED: retina_core::lcore::metrics::Counter =
+                retina_core::lcore::metrics::Counter::new(#name);
+            DELIVERED.inc();
+        }
+    }
+}
+
+// Declares the match budget of a subscription as `BUDGET`
+pub(crate) fn match_budget(spec: &SubscriptionSpec) -> proc_macro2::TokenStream {
+    let name = spec.as_str();
+    let max_matches = spec.max_matches.unwrap_or(u64::MAX);
+    quote! {
+        static BUDGET: retina_core::subscription::budget::MatchBudget =
+            retina_core::subscription::budget::MatchBudget::new(#name, #max_matches);
+    }
+}
+
+// Only runs `deliver` while the subscription has budget left, if budgeted
+fn budgeted(
+    spec: &SubscriptionSpec,
+    deliver: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    if spec.max_matches.is_none() {
+        return deliver;
+    }
+    let budget = match_budget(spec);
+    quote! {
+        {
+            #budget
+            if BUDGET.take() {
+                #deliver
+            }
+        }
+    }
+}
+
+// Invokes the callback: async callbacks are handed owned copies of the data (the filter string is
+// static) and queued on the async runtime. Other callbacks are queued on the callback worker pool
+// with owned copies of the data if it is enabled and the data can be cloned, and otherwise called
+// in place with their verdict recorded.
+fn invoke_callback(
+    spec: &SubscriptionSpec,
+    params: &[proc_macro2::TokenStream],
+) -> proc_macro2::TokenStream {
+    let callback = Ident::new(&spec.callback, Span::call_site());
+    let name = &spec.callback;
+    let owned = |param: &proc_macro2::TokenStream, datatype: &DataType| {
+        if datatype.as_str == *FILTER_STR {
+            quote! { #param }
+        } else {
+            quote! { ::std::clone::Clone::clone(#param) }
+        }
+    };
+    if !spec.is_async {
+        let inline = quote! {
+            retina_core::lcore::verdict::record(#callback(#( #params ),*).into());
+        };
+        if spec.datatypes.iter().any(|d| !is_cloneable(d.as_str)) {
+            return inline;
+        }
+        let vars: Vec<Ident> = (0..params.len())
+            .map(|idx| Ident::new(&format!("arg{}", idx), Span::call_site()))
+            .collect();
+        let args = params
+            .iter()
+            .zip(&spec.datatypes)
+            .map(|(param, datatype)| owned(param, datatype));
+        let refs = vars.iter().zip(&spec.datatypes).map(|(var, datatype)| {
+            if datatype.as_str == *FILTER_STR {
+                quote! { #var }
+            } else {
+                quote! { &#var }
+            }
+        });
+        return quote! {
+            {
+                static POOLED: retina_core::subscription::worker_pool::PooledCallback =
+                    retina_core::subscription::worker_pool::PooledCallback::new(#name);
+                if POOLED.enabled() {
+                    let ( #( #vars, )* ) = ( #( #args, )* );
+                    POOLED.deliver(Box::new(move || {
+                        let _ = #callback(#( #refs ),*);
+                    }));
+                } else {
+                    #inline
+                }
+            }
+        };
+    }
+    let args = params
+        .iter()
+        .zip(&spec.datatypes)
+        .map(|(param, datatype)| owned(param, datatype));
+    quote! {
+        {
+            static QUEUE: retina_core::subscription::async_bridge::AsyncCallback =
+                retina_core::subscription::async_bridge::AsyncCallback::new(#name);
+            QUEUE.deliver(Box::pin(#callback(#( #args ),*)));
+        }
+    }
+}
+
 pub(crate) fn build_packet_callback(
     spec: &SubscriptionSpec,
     filter_layer: FilterLayer,
 ) -> proc_macro2::TokenStream {
-    let callback = Ident::new(&spec.callback, Span::call_site());
     let (params, type_ident) = build_packet_params(spec, filter_layer);
+    let counter = delivery_counter(spec);
+    let invoke = invoke_callback(spec, &params);
+    // Packet-level subscriptions apply their action to each delivered packet
+    let action = match spec.action {
+        Some(PacketAction::Drop) => quote! {
+            retina_core::lcore::verdict::record(retina_core::Verdict::Drop);
+        },
+        Some(PacketAction::Divert) => quote! {
+            retina_core::lcore::verdict::record(retina_core::Verdict::Divert);
+        },
+        Some(PacketAction::Mirror) => quote! {
+            retina_core::lcore::mirror::record(retina_core::lcore::mirror::Direction::Originator);
+        },
+        None => quote! {},
+    };
+    let deliver = budgeted(
+        spec,
+        quote! {
+            #counter
+            #invoke
+            #action
+        },
+    );
 
     let condition = match type_ident {
         Some(type_ident) => quote! { let Some(p) = #type_ident::from_mbuf(mbuf) },
@@ -223,7 +471,7 @@ pub(crate) fn build_packet_callback(
         FilterLayer::PacketContinue | FilterLayer::PacketDeliver => {
             quote! {
                 if #condition {
-                    #callback(#( #params ),*);
+                    #deliver
                 }
             }
         }
@@ -232,7 +480,7 @@ pub(crate) fn build_packet_callback(
             quote! {
                 for mbuf in tracked.packets() {
                     if #condition {
-                        #callback(#( #params ),*);
+                        #deliver
                     }
                 }
             }
@@ -245,7 +493,6 @@ pub(crate) fn build_callback(
     filter_layer: FilterLayer,
     session_loop: bool,
 ) -> proc_macro2::TokenStream {
-    let callback = Ident::new(&spec.callback, Span::call_site());
     let mut params = vec![];
     let mut condition = quote! {};
 
@@ -270,7 +517,11 @@ pub(crate) fn build_callback(
         } else if matches!(datatype.level, Level::Static | Level::Connection) {
             let tracked_field: Ident =
                 Ident::new(&datatype.as_str.to_lowercase(), Span::call_site());
-            params.push(quote! { &tracked.#tracked_field });
+            if is_state(datatype.as_str) {
+                params.push(quote! { &mut tracked.#tracked_field.borrow_mut() });
+            } else {
+                params.push(quote! { &tracked.#tracked_field });
+            }
         } else if matches!(datatype.level, Level::Session)
             && matches!(filter_layer, FilterLayer::ConnectionDeliver)
         {
@@ -286,6 +537,15 @@ pub(crate) fn build_callback(
         }
     }
 
+    let counter = delivery_counter(spec);
+    let invoke = invoke_callback(spec, &params);
+    let deliver = budgeted(
+        spec,
+        quote! {
+            #counter
+            #invoke
+        },
+    );
     let break_early = match session_loop {
         true => quote! { break; },
         false => quote! {},
@@ -293,7 +553,7 @@ pub(crate) fn build_callback(
 
     quote! {
         #condition {
-            #callback(#( #params ),*);
+            #deliver
             #break_early
         }
     }
@@ -38,6 +38,82 @@
 //! }
 //! ```
 //!
+//! Callbacks return `()`, or a [`Verdict`](retina_core::Verdict) to drop packets when ports are
+//! bridged in inline mode. Simple policies can instead attach an action to the subscription, which
+//! the framework applies to matched packets without a verdict from the callback:
+//! `#[filter("tls.sni ~ 'example'", action = "drop")]`. Valid actions are `drop`, `divert`
+//! (transmit on the configured divert port) and `mirror` (transmit a copy on the configured mirror
+//! port, also outside of inline mode). In TOML files, subscriptions take an optional `action` key.
+//!
+//! A callback that has what it needs from a connection can return `Verdict::Stop` (or
+//! `std::ops::ControlFlow<()>`) to stop tracking, parsing, and delivering the connection, in all
+//! modes (see [verdict](retina_core::lcore::verdict)). Conversely, it can return
+//! `Verdict::Escalate` (or an `Actions` set) to request additional actions, such as tracking or
+//! writing packets, for the rest of the connection.
+//!
+//! Heavy-weight subscriptions can be run on a random sample of the connections matching their
+//! filter, alongside full-rate light ones: `#[filter("tls", sample_connections = 0.01)]` only
+//! tracks (and delivers) about 1% of TLS connections for this subscription. Connections are sampled
+//! by hash of their 5-tuple, so the decision is the same for all packets of a connection. This is
+//! equivalent to adding the `sample(0.01)` predicate to the filter, except that the
+//! [`FilterStr`](retina_datatypes::FilterStr) delivered to the callback is left unchanged. In TOML
+//! files, subscriptions take an optional `sample_connections` key.
+//!
+//! Large or frequently edited filters can be kept out of the Rust source:
+//! `#[filter(file = "filters/tls.toml")]` compiles the filter defined in the TOML file, either a
+//! single `filter = "..."`, or a list `filters = ["...", ...]` that matches if any of its filters
+//! matches. The other arguments (e.g., `action`) can follow the file. As for subscription files,
+//! the path is relative to the directory the application is built from. The file is tracked by
+//! the compiler, so editing it rebuilds the application.
+//!
+//! ```toml
+//! # filters/tls.toml
+//! filters = [
+//!     "tls.sni ~ 'netflix'",
+//!     "tls.sni ~ 'nflxvideo'",
+//! ]
+//! ```
+//!
+//! Subscriptions can be tagged into named groups, e.g.,
+//! `#[filter("tls", group = "tls-deep-inspect")]`, so that expensive analyses can be switched off
+//! and on without restarting, with `runtime.set_group_enabled("tls-deep-inspect", false)` (see
+//! [group](retina_core::subscription::group)). The filter of each group is generated separately and
+//! only applied while the group is enabled. In TOML files, subscriptions take an optional `group`
+//! key.
+//!
+//! When a packet, session, or connection matches several subscriptions, their callbacks are
+//! invoked in decreasing order of `priority` (default 0), e.g.,
+//! `#[filter("tls", priority = 10)]`. The filter of each priority level is generated separately,
+//! so that callbacks of a level are invoked after all callbacks of higher levels. Within a level,
+//! subscriptions delivered on the same filter conditions are invoked in the order they are
+//! defined; otherwise, the order follows the generated filter and should not be relied on.
+//! In TOML files, subscriptions take an optional `priority` key.
+//!
+//! Several callbacks can share the filter of a subscription, e.g., to receive different datatypes
+//! for the same traffic: `#[filter(with = "log_tls")]` attaches the callback to the filter (as well
+//! as the sampling, group, and priority) of the subscription of callback `log_tls`, which may be
+//! defined later. The filter is then evaluated once for both callbacks, which are invoked in turn
+//! on a match. Each callback can take datatypes of any level, and its own `action`. In TOML files,
+//! subscriptions take a `with` key instead of `filter`.
+//!
+//! Data collection can be capped with `max_matches`, e.g.,
+//! `#[filter("tls", max_matches = 10000)]` invokes the callback at most 10000 times. Once the
+//! budget is used up, the filter of the subscription is no longer applied (see
+//! [budget](retina_core::subscription::budget)). The filter of each budgeted subscription is
+//! generated separately. In TOML files, subscriptions take an optional `max_matches` key.
+//!
+//! Session-level callbacks are invoked for the first matching session of a connection. With
+//! `stream_sessions = true`, e.g., `#[filter("http", stream_sessions = true)]`, they are invoked
+//! for each matching session instead: each HTTP transaction of a keep-alive connection, or each
+//! DNS query over TCP. In TOML files, subscriptions take an optional `stream_sessions` key.
+//!
+//! Callbacks that do I/O per match (e.g., HTTP requests or database writes) can be declared as
+//! `async fn`, taking their datatypes by value (`FilterStr<'static>` for the filter string):
+//! `async fn post_tls(tls: TlsHandshake) { ... }`. The subscribed data is cloned and queued for a
+//! tokio runtime managed by the framework, so the packet processing cores never wait on the
+//! callback (see [async_bridge](retina_core::subscription::async_bridge)). This requires the
+//! `async` feature of `retina-core`. In TOML files, async subscriptions set `async = true`.
+//!
 //! # Specifying Subscriptions in TOML File
 //!
 //! [`subscription`](macro@self::subscription) is an attribute macro that allows users to specify
@@ -85,9 +161,159 @@
 //! }
 //! ```
 //!
+//! If every subscription is packet-level (e.g., `ZcFrame`) and its filter only has predicates on
+//! packet headers (e.g., `ipv4 and tcp.port = 80`, but not `tls`), packets are delivered as soon
+//! as the packet filter matches, and the generated program bypasses connection tracking entirely:
+//! cores never look up, create, or expire connections. Connections are still tracked if flow
+//! sampling (`conntrack.sampling`) is configured, as sampled-out connections are discarded by the
+//! connection table.
+//!
 //! # Datatype syntax
 //! All subscribed datatypes -- parameters to callbacks -- must be requested by reference.
-//! Supported datatypes are defined in the [retina_datatypes](../datatypes) crate.
+//! Supported datatypes are defined in the [retina_datatypes](../datatypes) crate. Session
+//! datatypes are copied when delivered off the packet processing cores; at high session rates,
+//! their borrowed variants (`HttpTransactionRef`, `DnsTransactionRef` and `TlsHandshakeRef`) are
+//! views of the parsed session instead, whose accessors return `&str` and `&[u8]`. They cannot be
+//! cloned, so callbacks taking them always run on the packet processing cores.
+//!
+//! Applications can define their own datatypes with the [`datatype`](macro@self::datatype)
+//! attribute, instead of adding them to the retina_datatypes crate. The attribute takes the
+//! `level` of the datatype (`"connection"`, `"session"`, `"packet"` or `"static"`), and the type
+//! must implement the corresponding trait of retina_datatypes (`Tracked`, `FromSession`,
+//! `FromMbuf` or `StaticData`). Optional arguments are:
+//! - `parsers = ["tls", ...]`: the application-layer parsers required by the datatype;
+//! - `update = false`: do not invoke `update` on each packet (connection-level datatypes invoke it
+//!   by default);
+//! - `update_reassembled = true`: invoke `update` on each packet after TCP reassembly;
+//! - `stream_sessions = true`: deliver a session-level datatype for each matching session;
+//! - `cloneable = false`: the datatype does not implement `Clone`, so cannot be delivered off the
+//!   packet processing cores;
+//! - `state = true`: the connection-level datatype is per-connection state of the application
+//!   (see below).
+//!
+//! Datatypes must be defined before the subscriptions that use them, and be in scope where the
+//! subscriptions are generated (the [`retina_main`](macro@self::retina_main) or
+//! [`subscription`](macro@self::subscription) function).
+//!
+//! ```rust,ignore
+//! #[datatype(level = "connection")]
+//! #[derive(Debug, Clone)]
+//! pub struct SynCount(usize);
+//!
+//! impl Tracked for SynCount {
+//!     fn new(_first_pkt: &L4Pdu) -> Self {
+//!         SynCount(0)
+//!     }
+//!     fn update(&mut self, pdu: &L4Pdu, reassembled: bool) {
+//!         if !reassembled && pdu.flags() & SYN != 0 {
+//!             self.0 += 1;
+//!         }
+//!     }
+//!     fn stream_protocols() -> Vec<&'static str> {
+//!         vec![]
+//!     }
+//!     fn clear(&mut self) {}
+//! }
+//!
+//! #[filter("tcp")]
+//! fn syn_cb(syns: &SynCount) { ... }
+//! ```
+//!
+//! Per-connection state of the application, rather than keeping it in a map indexed by five-tuple,
+//! is defined as a connection-level datatype with `state = true`. The framework creates the state
+//! with `new` when the connection is first tracked, invokes `update` on it like other
+//! connection-level datatypes, and drops it with the connection. Callbacks take the state by
+//! mutable reference, so that they can update it as sessions are delivered, and connection-level
+//! callbacks receive it when the connection terminates. Callbacks taking state are always invoked
+//! on the packet processing core of the connection (i.e., never pooled, and cannot be `async`).
+//!
+//! ```rust,ignore
+//! #[datatype(level = "connection", state = true)]
+//! #[derive(Debug, Default)]
+//! pub struct HostState {
+//!     requests: usize,
+//! }
+//!
+//! impl Tracked for HostState {
+//!     fn new(_first_pkt: &L4Pdu) -> Self {
+//!         HostState::default()
+//!     }
+//!     fn update(&mut self, _pdu: &L4Pdu, _reassembled: bool) {}
+//!     fn stream_protocols() -> Vec<&'static str> {
+//!         vec![]
+//!     }
+//!     fn clear(&mut self) {}
+//! }
+//!
+//! #[filter("http")]
+//! fn http_cb(http: &HttpTransaction, state: &mut HostState) {
+//!     state.requests += 1;
+//! }
+//!
+//! #[filter("http")]
+//! fn conn_cb(conn: &ConnRecord, state: &mut HostState) {
+//!     println!("{} requests on {:?}", state.requests, conn.five_tuple);
+//! }
+//! ```
+//!
+//! # Custom protocols
+//! Applications can parse their own application-layer protocols with the
+//! [`parser`](macro@self::parser) attribute. The parser type must implement `Default` and
+//! `ConnParsable`, and its sessions hold `SessionData::Custom` data implementing `CustomSession`
+//! (see [custom](../retina_core/protocols/stream/custom)). The attribute takes the `name` of the
+//! protocol in filters, and the `transports` it runs over (`"tcp"` and/or `"udp"`).
+//!
+//! The parser probes connections alongside the built-in parsers. Filters match connections of the
+//! protocol by name (`myproto`), and sessions on the fields returned by `CustomSession::field`
+//! (`myproto.user = 'admin'`, `myproto.version >= 2`); predicates on missing fields do not match.
+//! Session-level datatypes for the protocol are defined with the `datatype` attribute and
+//! `parsers = ["myproto"]`, and are typically the session type itself, retrieved with
+//! `session.data.as_custom()`.
+//!
+//! As with datatypes, parsers must be defined before the subscriptions that use them, and be in
+//! scope where the subscriptions are generated.
+//!
+//! ```rust,ignore
+//! #[parser(name = "myproto", transports = ["tcp"])]
+//! #[derive(Debug, Default)]
+//! pub struct MyProtoParser { ... }
+//!
+//! impl ConnParsable for MyProtoParser { ... }
+//!
+//! #[datatype(level = "session", parsers = ["myproto"], cloneable = false)]
+//! #[derive(Debug)]
+//! pub struct MyProtoSession { user: String }
+//!
+//! impl CustomSession for MyProtoSession {
+//!     fn protocol(&self) -> &'static str {
+//!         "myproto"
+//!     }
+//!     fn field(&self, name: &str) -> Option<FieldValue> {
+//!         match name {
+//!             "user" => Some(FieldValue::Text(self.user.clone())),
+//!             _ => None,
+//!         }
+//!     }
+//!     fn as_any(&self) -> &dyn Any {
+//!         self
+//!     }
+//! }
+//!
+//! impl FromSession for MyProtoSession {
+//!     fn stream_protocols() -> Vec<&'static str> {
+//!         vec!["myproto"]
+//!     }
+//!     fn from_session(session: &Session) -> Option<&Self> {
+//!         session.data.as_custom()
+//!     }
+//!     fn from_sessionlist(sessions: &SessionList) -> Option<&Self> {
+//!         sessions.iter().find_map(|session| session.data.as_custom())
+//!     }
+//! }
+//!
+//! #[filter("myproto.user ~ '^adm'")]
+//! fn myproto_cb(session: &MyProtoSession) { ... }
+//! ```
 //!
 //! # Filter syntax
 //! The Retina filter syntax is similar to that of [Wireshark display
@@ -112,6 +338,10 @@
 //! Retina will also automatically expand filter expressions to their fully-qualified form. For
 //! example, the filter `tcp` is equivalent to `(ipv4 and tcp) or (ipv6 and tcp)`.
 //!
+//! When a protocol is only parsed for filters (no subscribed datatype needs its sessions), its
+//! parser only extracts the fields that the filters compare. For example, with `tls.sni ~
+//! 'netflix'` and a `ConnRecord` callback, TLS parsing stops after the ClientHello.
+//!
 //! ## Fields
 //! All field identifiers are valid as long as Retina exposes a public accessor method for the
 //! corresponding protocol struct of the same name, and the method returns a supported RHS field
@@ -124,6 +354,59 @@
 //! `Ipv4Addr`, and `sni()` is a public method associated with the `Tls` struct that returns a
 //! `String`.
 //!
+//! Packet header fields such as `ipv4.dscp`, `ipv4.ecn`, `ipv4.ttl`, and `ipv6.hop_limit` are
+//! filterable in the same way. For example, `ipv4.dscp = 46` matches Expedited Forwarding traffic.
+//!
+//! ICMP and ICMPv6 messages are filterable by type and code, e.g., `icmp.type = 8` or
+//! `icmpv6.type = 135`. Each Echo Request/Reply exchange is additionally tracked as a connection
+//! keyed by the Echo identifier and sequence number (`icmp.identifier`, `icmp.sequence`), so
+//! connection- and packet-level subscriptions can target ping traffic directly. Other ICMP
+//! messages are only available to packet-level subscriptions.
+//!
+//! SCTP packets are filterable by port, e.g., `sctp.port = 3868` for Diameter, by chunk type with
+//! flags such as `sctp.init`, `sctp.abort`, or `sctp.data`, and by `sctp.ppid` (the Payload
+//! Protocol Identifier of the first DATA chunk). SCTP associations are tracked as connections,
+//! from their first packet to their ABORT or SHUTDOWN COMPLETE, without reassembly.
+//!
+//! Some integer fields also accept names: `tls.version = 'TLS1.0'` is equivalent to `tls.version =
+//! 769`, and `dns.qtype` and `dns.rcode` accept record types and response codes by their standard
+//! mnemonics (e.g., `dns.qtype = 'TXT'`, `dns.rcode = 'NXDOMAIN'`). Integer sets are only valid with `in`, and are equivalent to a disjunction of equality
+//! predicates (e.g., `tls.cipher in {0x002f, 0x0035}` matches either cipher suite). `tls.cipher`
+//! compares the cipher suite name when given a string, and the IANA identifier otherwise.
+//!
+//! Connection volume is filterable using the `conn` protocol: `conn.bytes > 10MB` or
+//! `conn.packets > 1000`. Byte counts accept the `KB`, `MB`, and `GB` (decimal) suffixes, and count
+//! the wire length of packets. Volume counters are updated on every packet of a tracked
+//! connection, and volume predicates are checked when the connection is delivered, so they are only
+//! supported in connection-level subscriptions. Connections that exceed the upper bounds of all
+//! filters (e.g., `conn.packets < 10`) can no longer match, and are dropped as soon as they do.
+//! They can be combined with transport-layer predicates (e.g., `tcp.port = 443 and conn.bytes >
+//! 10MB`), but not with application-layer protocols.
+//!
+//! When MPLS decapsulation is enabled (see `MplsConfig` in the runtime configuration), the label
+//! stack of a connection is filterable using the `mpls` protocol: `mpls.label` (the top label),
+//! `mpls.bottom_label`, and `mpls.depth`, e.g., `tcp.port = 179 and mpls.label = 16004`. The `mpls`
+//! predicate alone matches connections received over MPLS. Like volume, MPLS predicates are
+//! checked when the connection is delivered, and are only supported in connection-level
+//! subscriptions.
+//!
+//! VLAN tags of a connection are filterable using the `vlan` protocol: `vlan.id` is the VLAN ID of
+//! the outer (or only) tag, `vlan.inner_id` that of the inner tag of double-tagged (802.1ad, QinQ)
+//! frames, and `vlan.qinq` matches double-tagged connections (e.g., `vlan.id = 100 and
+//! vlan.inner_id = 2001`). The `vlan` predicate alone matches tagged connections. Like MPLS
+//! predicates, they are checked when the connection is delivered.
+//!
+//! Flag fields (accessors that return `1` when set and `0` otherwise) can be written without a
+//! comparison: `tcp.syn` is equivalent to `tcp.syn = 1`. Some fields take a string argument:
+//! `ipv6.has_ext('routing')` matches packets with an IPv6 Routing header (also `hop_by_hop`,
+//! `fragment`, `auth`, and `dst_opts`; other names are invalid), and `ipv6.frag` matches IPv6
+//! fragments. Other fields must be compared: `tcp.port` alone is an invalid filter.
+//!
+//! HTTP headers are filterable by name (case-insensitive) using `http.header('name')`, which
+//! compares like any other text field (e.g., `http.header('host') ~ 'internal'`). The `exists`
+//! operator checks for the presence of a header: `http.header('x-forwarded-for') exists` is
+//! equivalent to `http.has_header('x-forwarded-for') = 1`.
+//!
 //! Retina also supports two combined fields: `addr` and `port`. Logically, these are equivalent to
 //! `src_addr or dst_addr` and `src_port or dst_port`, respectively, except in predicates that use
 //! the `!=` comparison operator (details below).
@@ -135,9 +418,12 @@
 //! | IPv4 prefix   | `1.2.3.4/24`       |
 //! | IPv6 address  | `2001:db8::1`      |
 //! | IPv6 prefix   | `2001:db8::1/64`   |
-//! | Integer       | `443`              |
+//! | Integer       | `443`, `0x01bb`    |
+//! | Integer set   | `{0x002f, 0x0035}` |
 //! | String        | `'Safari'`         |
 //! | Integer range | `1024..5000`       |
+//! | Byte count    | `10MB`             |
+//! | Rate          | `100/60s`          |
 //!
 //! ## Binary comparison operators
 //! | Operator |   Alias   |         Description        | Example                         |
@@ -147,7 +433,7 @@
 //! | `>=`     | `ge`      | Greater than or equals     | `tcp.port >= 1024`              |
 //! | `<=`     | `le`      | Less than or equals        | `tls.version <= 771`            |
 //! | `>`      | `gt`      | Greater than               | `ipv4.time_to_live > 64`        |
-//! | `<`      | `lt`      | Less than                  | `ipv6.payload_length < 500`     |
+//! | `<`      | `lt`      | Less than                  | `ipv4.ttl < 10`                 |
 //! | `in`     |           | In a range, or in a subnet | `ipv4.src_addr in 1.2.3.4/16`   |
 //! | `~`      | `matches` | Regular expression match   | `tls.sni ~ 'netflix\\.com$'`    |
 //!
@@ -164,12 +450,44 @@
 //! [`regex`](https://crates.io/crates/regex) and
 //! [`lazy_static`](https://crates.io/crates/lazy_static) crates. As of this writing, `proc-macro`
 //! crates like this one cannot export any items other than procedural macros, thus requiring
-//! applications that wish to use Retina's regular expression filtering to specify `regex` and
-//! `lazy_static` as dependencies. Also note that regular expressions are written as normal strings
+//! applications that wish to use Retina's regular expression filtering to specify
+//! `lazy_static` as a dependency. Also note that regular expressions are written as normal strings
 //! in Rust, and not as [raw string
 //! literals](https://doc.rust-lang.org/stable/reference/tokens.html#raw-string-literals). They are
 //! allowed to match anywhere in the text, unless start (`^`) and end (`$`) anchors are used.
 //!
+//! Applications with many regular expression filters can enable the `hyperscan` feature of
+//! `retina-core`, which matches all of them in a single Hyperscan (or Vectorscan) scan of each
+//! field value (see [regexes](retina_core::filter::regexes)).
+//!
+//! **Rate predicates**
+//!
+//! `rate(<key>[, <event>]) <op> <count>/<window>` counts, per value of the `key` field, the number
+//! of packets for which the `event` field is non-zero over a sliding window. If `event` is omitted,
+//! every packet is counted. For example, `rate(ipv4.src_addr, tcp.syn) > 100/60s` matches packets
+//! from sources that have sent more than 100 SYNs in the last minute. Supported window units are
+//! `ms`, `s`, `m`, and `h`, and only the `>`, `>=`, `<`, and `<=` operators are allowed.
+//!
+//! Counts are approximate (a per-core count-min sketch), are kept separately on each core, and are
+//! only evaluated per-packet, so rate predicates are currently limited to packet-level
+//! subscriptions and are never offloaded to hardware. The same rate predicate in several
+//! subscriptions (with any threshold) shares its counts, and each packet is counted once.
+//!
+//! **Filter sets**
+//!
+//! `<field> in $<name>` tests a field against a named set whose members are updated while the
+//! runtime is running, e.g., `ipv4.addr in $blocklist` or `tls.sni in $watched_domains`, with
+//! [sets::insert](retina_core::filter::sets::insert) or over the control plane (see
+//! [sets](retina_core::filter::sets)). Sets are empty at startup, and are never offloaded to
+//! hardware.
+//!
+//! **Sampling predicates**
+//!
+//! `sample(<fraction>)` matches the given fraction (in `(0, 1]`) of connections, e.g.,
+//! `tls and sample(0.01)`. Connections are selected by a hash of their 5-tuple rather than at
+//! random, so the predicate holds for all packets of a connection in both directions, at every
+//! filter stage. It is usually set with the `sample_connections` subscription argument.
+//!
 //! ## Logical operators
 //! | Operator | Alias | Description | Example                                      |
 //! |----------|-------|-------------|----------------------------------------------|
@@ -181,6 +499,26 @@
 //! Retina does not yet support the logical `NOT` operator. For some expressions, it can be
 //! approximated using the `!=` binary comparison operator, taking the above mentioned pitfall into
 //! consideration.
+//!
+//! # Action report
+//! Some datatypes force expensive processing on every connection that matches their filter (e.g.,
+//! buffering all packets until delivery, or reassembling TCP streams). Set `RETINA_ACTION_REPORT`
+//! to a file when building the application to write a report of, for each subscription, the
+//! actions that its filter and datatypes induce, and which filter or datatypes cause the expensive
+//! ones (application-layer parsing, session and packet buffering, per-packet and reassembled
+//! updates). Set it to `-` to print the report in the build output instead.
+//!
+//! # Debugging filters
+//! Subscriptions are compiled into a predicate tree per filter layer (packet, protocol, session,
+//! and delivery). To see why a subscription does not fire, set `RETINA_FILTER_DUMP` to a directory
+//! when building the application (e.g., `RETINA_FILTER_DUMP=/tmp/filters cargo build`). For each
+//! tree, `<layer>_<subscriptions>.dot` is a Graphviz graph of its nodes, with the actions and
+//! deliveries of each node, and `<layer>_<subscriptions>.txt` explains which predicates are
+//! evaluated at the layer and what each match triggers. Subscriptions are numbered in the order
+//! they are defined, and are listed at the top of each explanation.
+//!
+//! Since the trees are built when the application is compiled, changing `RETINA_FILTER_DUMP` does
+//! not rebuild it; touch the file that defines the subscriptions to dump them again.
 
 use proc_macro::TokenStream;
 use quote::quote;
@@ -188,7 +526,7 @@ use retina_core::filter::ptree::*;
 use retina_core::filter::*;
 use std::str::FromStr;
 use syn::parse_macro_input;
-use utils::DELIVER;
+use utils::{rate_sketches, regex_database, DELIVER, SETS};
 
 #[macro_use]
 extern crate lazy_static;
@@ -199,6 +537,7 @@ mod deliver_filter;
 mod packet_filter;
 mod parse;
 mod proto_filter;
+mod report;
 mod session_filter;
 mod utils;
 
@@ -218,14 +557,15 @@ fn get_hw_filter(packet_continue: &PTree) -> String {
     ret
 }
 
-// Returns a PTree from the given config
-fn filter_subtree(input: &SubscriptionConfig, filter_layer: FilterLayer) -> PTree {
+// Returns a PTree from the given subscriptions (by index) of the config
+fn filter_subtree(input: &SubscriptionConfig, ids: &[usize], filter_layer: FilterLayer) -> PTree {
     let mut ptree = PTree::new_empty(filter_layer);
 
-    for i in 0..input.subscriptions.len() {
+    for &i in ids {
         let spec = &input.subscriptions[i];
-        let filter = Filter::new(&spec.filter)
-            .unwrap_or_else(|err| panic!("Failed to parse filter {}: {:?}", spec.filter, err));
+        let filter_str = spec.sampled_filter();
+        let filter = Filter::new(&filter_str)
+            .unwrap_or_else(|err| panic!("Failed to parse filter {}: {:?}", filter_str, err));
 
         let patterns = filter.get_patterns_flat();
         let deliver = Deliver {
@@ -239,49 +579,188 @@ fn filter_subtree(input: &SubscriptionConfig, filter_layer: FilterLayer) -> PTre
 
     ptree.collapse();
     println!("{}", ptree);
+    if let Ok(dir) = std::env::var("RETINA_FILTER_DUMP") {
+        dump_filter(&ptree, ids, &dir);
+    }
     ptree
 }
 
+// Writes the action report of the subscriptions to file `dest`, or to the build output if `dest`
+// is `-`.
+fn write_report(config: &SubscriptionConfig, dest: &str) {
+    let report = report::action_report(config);
+    if dest == "-" {
+        eprintln!("{}", report);
+    } else if let Err(err) = std::fs::write(dest, report) {
+        panic!("Failed to write action report to {}: {}", dest, err);
+    }
+}
+
+// Writes the DOT graph and explanation of the tree for subscriptions `ids` to directory `dir`, as
+// `<layer>_<ids>.dot` and `<layer>_<ids>.txt`.
+fn dump_filter(ptree: &PTree, ids: &[usize], dir: &str) {
+    let layer = match ptree.filter_layer {
+        FilterLayer::PacketContinue => "packet_continue",
+        FilterLayer::Packet => "packet",
+        FilterLayer::Protocol => "protocol",
+        FilterLayer::Session => "session",
+        FilterLayer::ConnectionDeliver => "connection_deliver",
+        FilterLayer::PacketDeliver => "packet_deliver",
+    };
+    let mut explain = String::new();
+    let specs = DELIVER.lock().unwrap();
+    for id in ids {
+        let spec = &specs[id];
+        explain.push_str(&format!("#{} {}: {}\n", id, spec.as_str(), spec.filter));
+    }
+    explain.push('\n');
+    explain.push_str(&ptree.explain());
+
+    let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+    let path = std::path::Path::new(dir).join(format!("{}_{}", layer, ids.join("_")));
+    let written = std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(path.with_extension("dot"), ptree.to_dot()))
+        .and_then(|_| std::fs::write(path.with_extension("txt"), explain));
+    if let Err(err) = written {
+        panic!("Failed to dump filter to {}: {}", dir, err);
+    }
+}
+
+// Generates the filter of each group of subscriptions with `gen_filter`, and applies the filter
+// of a group only while the group is enabled and its subscription has budget left, if budgeted.
+// `returns_actions` is true for filters that return the actions to apply, which are then combined
+// across groups.
+fn gen_grouped<F>(
+    config: &SubscriptionConfig,
+    groups: &[(Option<String>, Option<usize>, Vec<usize>)],
+    returns_actions: bool,
+    mut gen_filter: F,
+) -> proc_macro2::TokenStream
+where
+    F: FnMut(&[usize]) -> proc_macro2::TokenStream,
+{
+    if let [(None, None, ids)] = groups {
+        return gen_filter(ids);
+    }
+    let mut body: Vec<proc_macro2::TokenStream> = vec![];
+    for (group, budget, ids) in groups {
+        let filter = gen_filter(ids);
+        let filter = match returns_actions {
+            true => quote! { result.push(&{ #filter }); },
+            false => quote! { { #filter } },
+        };
+        let filter = match budget {
+            Some(idx) => {
+                let budget = match_budget(&config.subscriptions[*idx]);
+                quote! {
+                    {
+                        #budget
+                        if !BUDGET.exhausted() {
+                            #filter
+                        }
+                    }
+                }
+            }
+            None => filter,
+        };
+        body.push(match group {
+            Some(name) => quote! {
+                {
+                    static GROUP: retina_core::subscription::group::SubscriptionGroup =
+                        retina_core::subscription::group::SubscriptionGroup::new(#name);
+                    if GROUP.enabled() {
+                        #filter
+                    }
+                }
+            },
+            None => filter,
+        });
+    }
+    match returns_actions {
+        true => quote! {
+            let mut result = retina_core::filter::Actions::new();
+            #( #body )*
+            result
+        },
+        false => quote! { #( #body )* },
+    }
+}
+
 // Generate code from the given config (all subscriptions)
 // Also includes the original input (typically a callback or main function)
 fn generate(input: syn::ItemFn, config: SubscriptionConfig) -> TokenStream {
+    if let Ok(dest) = std::env::var("RETINA_ACTION_REPORT") {
+        write_report(&config, &dest);
+    }
     let mut statics: Vec<proc_macro2::TokenStream> = vec![];
+    let groups = config.groups();
 
-    let packet_cont_ptree = filter_subtree(&config, FilterLayer::PacketContinue);
-    let packet_continue = gen_packet_filter(
-        &packet_cont_ptree,
-        &mut statics,
-        FilterLayer::PacketContinue,
-    );
+    let packet_continue = gen_grouped(&config, &groups, true, |ids| {
+        let ptree = filter_subtree(&config, ids, FilterLayer::PacketContinue);
+        gen_packet_filter(&ptree, &mut statics, FilterLayer::PacketContinue)
+    });
 
-    let packet_ptree = filter_subtree(&config, FilterLayer::Packet);
-    let packet_filter = gen_packet_filter(&packet_ptree, &mut statics, FilterLayer::Packet);
+    let packet_filter = gen_grouped(&config, &groups, true, |ids| {
+        let ptree = filter_subtree(&config, ids, FilterLayer::Packet);
+        gen_packet_filter(&ptree, &mut statics, FilterLayer::Packet)
+    });
 
-    let conn_ptree = filter_subtree(&config, FilterLayer::Protocol);
-    let proto_filter = gen_proto_filter(&conn_ptree, &mut statics);
+    let proto_filter = gen_grouped(&config, &groups, true, |ids| {
+        let ptree = filter_subtree(&config, ids, FilterLayer::Protocol);
+        gen_proto_filter(&ptree, &mut statics)
+    });
 
-    let session_ptree = filter_subtree(&config, FilterLayer::Session);
-    let session_filter = gen_session_filter(&session_ptree, &mut statics);
+    let session_filter = gen_grouped(&config, &groups, true, |ids| {
+        let ptree = filter_subtree(&config, ids, FilterLayer::Session);
+        gen_session_filter(&ptree, &mut statics)
+    });
 
-    let conn_deliver_ptree = filter_subtree(&config, FilterLayer::ConnectionDeliver);
-    let conn_deliver_filter = gen_deliver_filter(
-        &conn_deliver_ptree,
-        &mut statics,
-        FilterLayer::ConnectionDeliver,
-    );
-    let packet_deliver_ptree = filter_subtree(&config, FilterLayer::PacketDeliver);
-    let packet_deliver_filter = gen_deliver_filter(
-        &packet_deliver_ptree,
-        &mut statics,
-        FilterLayer::PacketDeliver,
-    );
+    let conn_deliver_filter = gen_grouped(&config, &groups, false, |ids| {
+        let ptree = filter_subtree(&config, ids, FilterLayer::ConnectionDeliver);
+        gen_deliver_filter(&ptree, &mut statics, FilterLayer::ConnectionDeliver)
+    });
+    let packet_deliver_filter = gen_grouped(&config, &groups, false, |ids| {
+        let ptree = filter_subtree(&config, ids, FilterLayer::PacketDeliver);
+        gen_deliver_filter(&ptree, &mut statics, FilterLayer::PacketDeliver)
+    });
 
     let mut tracked_data = TrackedDataBuilder::new(&config);
     let subscribable = tracked_data.subscribable_wrapper();
     let tracked = tracked_data.tracked();
 
-    let filter_str = get_hw_filter(&packet_cont_ptree); // Packet-level keep/drop filter
+    // Packet-level keep/drop filter, for all groups
+    let all: Vec<usize> = (0..config.subscriptions.len()).collect();
+    let packet_cont_ptree = filter_subtree(&config, &all, FilterLayer::PacketContinue);
+    let filter_str = get_hw_filter(&packet_cont_ptree);
+    let track_packets = config.tracks_packets();
+    let bypass_conntrack = config.bypasses_conntrack();
+    let volume_limits = config.volume_limits();
+    let (max_bytes, max_packets) = (
+        volume_limits
+            .bytes
+            .map_or(quote! { None }, |max| quote! { Some(#max) }),
+        volume_limits
+            .packets
+            .map_or(quote! { None }, |max| quote! { Some(#max) }),
+    );
+    // A group is split across priorities
+    let group_names: std::collections::BTreeSet<&String> = groups
+        .iter()
+        .filter_map(|(group, _, _)| group.as_ref())
+        .collect();
+    let set_names: Vec<String> = SETS.lock().unwrap().iter().cloned().collect();
+    let custom_protocols = custom_parsers().into_iter().map(|(name, transports, _)| {
+        quote! {
+            retina_core::protocols::stream::custom::register_protocol(
+                #name,
+                &[ #( #transports ),* ],
+            )
+            .unwrap();
+        }
+    });
 
+    statics.extend(regex_database());
+    let rate_sketches = rate_sketches();
     let lazy_statics = if statics.is_empty() {
         quote! {}
     } else {
@@ -304,7 +783,11 @@ fn generate(input: syn::ItemFn, config: SubscriptionConfig) -> TokenStream {
 
         #lazy_statics
 
+        #rate_sketches
+
         pub fn filter() -> retina_core::filter::FilterFactory<TrackedWrapper> {
+            // Custom protocols must be known before filters are parsed
+            #( #custom_protocols )*
 
             fn packet_continue(mbuf: &retina_core::Mbuf,
                                core_id: &retina_core::CoreId) -> Actions {
@@ -349,6 +832,14 @@ fn generate(input: syn::ItemFn, config: SubscriptionConfig) -> TokenStream {
                 packet_deliver,
                 connection_deliver,
             )
+            .with_packet_tracking(#track_packets)
+            .with_conntrack_bypass(#bypass_conntrack)
+            .with_groups(&[ #( #group_names ),* ])
+            .with_sets(&[ #( #set_names ),* ])
+            .with_volume_limits(retina_core::filter::VolumeLimits {
+                bytes: #max_bytes,
+                packets: #max_packets,
+            })
         }
 
         #input
@@ -368,22 +859,61 @@ pub fn subscription(args: TokenStream, input: TokenStream) -> TokenStream {
 }
 
 /// Generate a Retina program without a specification file.
-/// This expects a #[filter("...")] macro followed by the expected callback.
+/// This expects a #[filter("...")] (or #[filter(file = "...")]) macro followed by the expected
+/// callback.
 /// It must be used with #[retina_main(X)], where X = number of subscriptions.
 #[proc_macro_attribute]
 pub fn filter(args: TokenStream, input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as syn::ItemFn);
-    let filter_str = parse_macro_input!(args as syn::LitStr).value();
-    let (datatypes, callback) = parse_input(&input);
+    let FilterArgs {
+        filter: filter_str,
+        file,
+        with,
+        action,
+        sample_connections,
+        group,
+        priority,
+        max_matches,
+        stream_sessions,
+    } = parse_macro_input!(args as FilterArgs);
+    // Filters from a file are compiled as if written inline, and the file is included so that
+    // editing it rebuilds the application
+    let (filter_str, tracked_file) = match file {
+        Some(path) => {
+            let filter_str = FilterFileRaw::read(&path);
+            let path = std::fs::canonicalize(&path)
+                .unwrap_or_else(|err| panic!("ERROR: File read failed {}: {:?}", path, err));
+            let path = syn::LitStr::new(&path.to_string_lossy(), proc_macro2::Span::call_site());
+            (
+                filter_str,
+                quote! { const _: &[u8] = include_bytes!(#path); },
+            )
+        }
+        None => (filter_str, quote! {}),
+    };
+    let (datatypes, callback, is_async) = parse_input(&input);
     println!(
         "Filter: {}, Datatypes: {:?}, Callback: {:?}",
         filter_str, datatypes, callback
     );
 
     // If more subscriptions to parse, just output the callback
-    add_subscription(callback, datatypes, filter_str);
+    add_subscription(
+        callback,
+        datatypes,
+        filter_str,
+        action,
+        is_async,
+        sample_connections,
+        group,
+        priority,
+        with,
+        max_matches,
+        stream_sessions,
+    );
     if !is_done() {
         return quote! {
+            #tracked_file
             #input
         }
         .into();
@@ -392,7 +922,82 @@ pub fn filter(args: TokenStream, input: TokenStream) -> TokenStream {
     // Otherwise, ready to assemble
     let config = SubscriptionConfig::from_raw(&CACHED_SUBSCRIPTIONS.lock().unwrap());
 
-    generate(input, config)
+    let generated = proc_macro2::TokenStream::from(generate(input, config));
+    quote! {
+        #tracked_file
+        #generated
+    }
+    .into()
+}
+
+/// Define a subscribable datatype in the application (see [Datatype syntax](self#datatype-syntax)).
+/// This expects the arguments of the datatype (e.g., `level = "connection"`) followed by the
+/// definition of the type.
+#[proc_macro_attribute]
+pub fn datatype(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as DatatypeArgs);
+    let item = parse_macro_input!(input as syn::DeriveInput);
+    if !item.generics.params.is_empty() {
+        return syn::Error::new_spanned(&item.generics, "Datatypes cannot be generic")
+            .to_compile_error()
+            .into();
+    }
+    let ident = &item.ident;
+    let datatype = args.to_datatype(ident.to_string());
+    add_datatype(datatype, args.cloneable, args.state);
+
+    let level_trait = match args.level {
+        Level::Connection => quote! { retina_datatypes::Tracked },
+        Level::Session => quote! { retina_datatypes::FromSession },
+        Level::Packet => quote! { retina_datatypes::FromMbuf },
+        Level::Static => quote! { retina_datatypes::StaticData },
+    };
+    let bounds = match args.cloneable && !args.state {
+        true => quote! { #level_trait + Clone + Send },
+        false => level_trait,
+    };
+    quote! {
+        #item
+
+        // Generated code requires the trait of the datatype's level
+        const _: fn() = || {
+            fn implements_datatype<T: #bounds>() {}
+            implements_datatype::<#ident>();
+        };
+    }
+    .into()
+}
+
+/// Define a parser for an application-defined protocol (see
+/// [Custom protocols](self#custom-protocols)).
+/// This expects the name of the protocol and its transport protocols (e.g., `name = "myproto",
+/// transports = ["tcp"]`) followed by the definition of the parser type.
+#[proc_macro_attribute]
+pub fn parser(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as ParserArgs);
+    let item = parse_macro_input!(input as syn::DeriveInput);
+    if !item.generics.params.is_empty() {
+        return syn::Error::new_spanned(&item.generics, "Parsers cannot be generic")
+            .to_compile_error()
+            .into();
+    }
+    let ident = &item.ident;
+    add_parser(args.name, args.transports, ident.to_string());
+
+    quote! {
+        #item
+
+        // Generated code builds a parser per connection
+        const _: fn() = || {
+            fn implements_parser<T>()
+            where
+                T: retina_core::protocols::stream::ConnParsable + Default + 'static,
+            {
+            }
+            implements_parser::<#ident>();
+        };
+    }
+    .into()
 }
 
 // For generating a Retina program without a specification file
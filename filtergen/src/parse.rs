@@ -1,5 +1,5 @@
-use retina_core::filter::SubscriptionSpec;
-use retina_datatypes::DATATYPES;
+use crate::cache::{datatype_names, get_datatype, is_cloneable};
+use retina_core::filter::{DataType, Filter, Level, SubscriptionSpec, VolumeLimits};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 
@@ -12,10 +12,301 @@ pub(crate) struct ConfigRaw {
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct SubscriptionRaw {
+    #[serde(default)]
     pub(crate) filter: String,
     #[serde_as(as = "serde_with::OneOrMany<_>")]
     pub(crate) datatypes: Vec<String>,
     pub(crate) callback: String,
+    #[serde(default)]
+    pub(crate) action: Option<String>,
+    #[serde(default, rename = "async")]
+    pub(crate) is_async: bool,
+    #[serde(default)]
+    pub(crate) sample_connections: Option<f64>,
+    #[serde(default)]
+    pub(crate) group: Option<String>,
+    #[serde(default)]
+    pub(crate) priority: i32,
+    #[serde(default)]
+    pub(crate) with: Option<String>,
+    #[serde(default)]
+    pub(crate) max_matches: Option<u64>,
+    #[serde(default)]
+    pub(crate) stream_sessions: bool,
+}
+
+// Filters loaded from a file with `#[filter(file = "...")]`. Filters are combined with `or`.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct FilterFileRaw {
+    #[serde(default)]
+    pub(crate) filter: Option<String>,
+    #[serde(default)]
+    pub(crate) filters: Vec<String>,
+}
+
+impl FilterFileRaw {
+    // Returns the filter defined in file `filepath_in`
+    pub(crate) fn read(filepath_in: &str) -> String {
+        let filter_str = std::fs::read_to_string(filepath_in)
+            .unwrap_or_else(|err| panic!("ERROR: File read failed {}: {:?}", filepath_in, err));
+        let raw: FilterFileRaw = toml::from_str(&filter_str)
+            .unwrap_or_else(|err| panic!("ERROR: Filter file invalid {}: {:?}", filepath_in, err));
+        let filters: Vec<String> = raw.filter.into_iter().chain(raw.filters).collect();
+        match filters.len() {
+            0 => panic!("ERROR: No filter in {}", filepath_in),
+            1 => filters[0].clone(),
+            _ => filters
+                .iter()
+                .map(|f| format!("({})", f))
+                .collect::<Vec<_>>()
+                .join(" or "),
+        }
+    }
+}
+
+// Arguments to the `filter` attribute: a filter, `file = "..."` or `with = "<callback>"`,
+// optionally followed by `action = "..."`, `sample_connections = <fraction>`, `group = "..."`,
+// `priority = <int>`, `max_matches = <int>` and/or `stream_sessions = <bool>`
+pub(crate) struct FilterArgs {
+    pub(crate) filter: String,
+    pub(crate) file: Option<String>,
+    pub(crate) with: Option<String>,
+    pub(crate) action: Option<String>,
+    pub(crate) sample_connections: Option<f64>,
+    pub(crate) group: Option<String>,
+    pub(crate) priority: i32,
+    pub(crate) max_matches: Option<u64>,
+    pub(crate) stream_sessions: bool,
+}
+
+impl syn::parse::Parse for FilterArgs {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut filter = String::new();
+        let mut file = None;
+        let mut with = None;
+        if input.peek(syn::LitStr) {
+            filter = input.parse::<syn::LitStr>()?.value();
+        } else {
+            let key = input.parse::<syn::Ident>()?;
+            if key != "file" && key != "with" {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "expected a filter, `file` or `with`",
+                ));
+            }
+            input.parse::<syn::Token![=]>()?;
+            let value = input.parse::<syn::LitStr>()?.value();
+            match key == "file" {
+                true => file = Some(value),
+                false => with = Some(value),
+            }
+        }
+        let mut action = None;
+        let mut sample_connections = None;
+        let mut group = None;
+        let mut priority = 0;
+        let mut max_matches = None;
+        let mut stream_sessions = false;
+        while input.parse::<Option<syn::Token![,]>>()?.is_some() && !input.is_empty() {
+            let key = input.parse::<syn::Ident>()?;
+            input.parse::<syn::Token![=]>()?;
+            if key == "action" {
+                action = Some(input.parse::<syn::LitStr>()?.value());
+            } else if key == "sample_connections" {
+                sample_connections = Some(input.parse::<syn::LitFloat>()?.base10_parse()?);
+            } else if key == "group" {
+                group = Some(input.parse::<syn::LitStr>()?.value());
+            } else if key == "priority" {
+                let negative = input.parse::<Option<syn::Token![-]>>()?.is_some();
+                priority = input.parse::<syn::LitInt>()?.base10_parse::<i32>()?;
+                if negative {
+                    priority = -priority;
+                }
+            } else if key == "max_matches" {
+                max_matches = Some(input.parse::<syn::LitInt>()?.base10_parse()?);
+            } else if key == "stream_sessions" {
+                stream_sessions = input.parse::<syn::LitBool>()?.value;
+            } else {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "expected `action`, `sample_connections`, `group`, `priority`, \
+                     `max_matches` or `stream_sessions`",
+                ));
+            }
+        }
+        if with.is_some() && (sample_connections.is_some() || group.is_some() || priority != 0) {
+            return Err(input.error(
+                "`sample_connections`, `group` and `priority` are shared with the `with` callback",
+            ));
+        }
+        Ok(FilterArgs {
+            filter,
+            file,
+            with,
+            action,
+            sample_connections,
+            group,
+            priority,
+            max_matches,
+            stream_sessions,
+        })
+    }
+}
+
+// Arguments to the `datatype` attribute, e.g.
+// `level = "connection", parsers = ["tls"], update_reassembled = true`
+pub(crate) struct DatatypeArgs {
+    pub(crate) level: Level,
+    pub(crate) parsers: Vec<String>,
+    pub(crate) update: Option<bool>,
+    pub(crate) update_reassembled: bool,
+    pub(crate) stream_sessions: bool,
+    pub(crate) cloneable: bool,
+    pub(crate) state: bool,
+}
+
+impl DatatypeArgs {
+    // The datatype named `name`; names and parsers live for the whole compilation
+    pub(crate) fn to_datatype(&self, name: String) -> DataType {
+        let as_str: &'static str = Box::leak(name.into_boxed_str());
+        let stream_protos: Vec<&'static str> = self
+            .parsers
+            .iter()
+            .map(|p| Box::leak(p.clone().into_boxed_str()) as &'static str)
+            .collect();
+        let mut datatype = match self.level {
+            Level::Connection => DataType::new_default_connection(as_str),
+            Level::Session => DataType::new_default_session(as_str, vec![]),
+            Level::Packet => DataType::new_default_packet(as_str),
+            Level::Static => DataType::new_default_static(as_str),
+        };
+        datatype.needs_parse = matches!(self.level, Level::Session) || !stream_protos.is_empty();
+        datatype.stream_protos = stream_protos;
+        if let Some(update) = self.update {
+            datatype.needs_update = update;
+        }
+        datatype.needs_update_reassembled = self.update_reassembled;
+        datatype.stream_sessions = self.stream_sessions;
+        datatype
+    }
+}
+
+impl syn::parse::Parse for DatatypeArgs {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut level = None;
+        let mut args = DatatypeArgs {
+            level: Level::Static,
+            parsers: vec![],
+            update: None,
+            update_reassembled: false,
+            stream_sessions: false,
+            cloneable: true,
+            state: false,
+        };
+        while !input.is_empty() {
+            let key = input.parse::<syn::Ident>()?;
+            input.parse::<syn::Token![=]>()?;
+            if key == "level" {
+                let value = input.parse::<syn::LitStr>()?;
+                level = Some(match value.value().as_str() {
+                    "connection" => Level::Connection,
+                    "session" => Level::Session,
+                    "packet" => Level::Packet,
+                    "static" => Level::Static,
+                    _ => {
+                        return Err(syn::Error::new(
+                            value.span(),
+                            "expected \"connection\", \"session\", \"packet\" or \"static\"",
+                        ))
+                    }
+                });
+            } else if key == "parsers" {
+                let content;
+                syn::bracketed!(content in input);
+                let parsers =
+                    syn::punctuated::Punctuated::<syn::LitStr, syn::Token![,]>::parse_terminated(
+                        &content,
+                    )?;
+                args.parsers = parsers.iter().map(|p| p.value()).collect();
+            } else if key == "update" {
+                args.update = Some(input.parse::<syn::LitBool>()?.value);
+            } else if key == "update_reassembled" {
+                args.update_reassembled = input.parse::<syn::LitBool>()?.value;
+            } else if key == "stream_sessions" {
+                args.stream_sessions = input.parse::<syn::LitBool>()?.value;
+            } else if key == "cloneable" {
+                args.cloneable = input.parse::<syn::LitBool>()?.value;
+            } else if key == "state" {
+                args.state = input.parse::<syn::LitBool>()?.value;
+            } else {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "expected `level`, `parsers`, `update`, `update_reassembled`, \
+                     `stream_sessions`, `cloneable` or `state`",
+                ));
+            }
+            if input.parse::<Option<syn::Token![,]>>()?.is_none() {
+                break;
+            }
+        }
+        if !input.is_empty() {
+            return Err(input.error("expected `,`"));
+        }
+        args.level = match level {
+            Some(level) => level,
+            None => return Err(input.error("missing `level`")),
+        };
+        if args.state && !matches!(args.level, Level::Connection) {
+            return Err(input.error("per-connection state must have level \"connection\""));
+        }
+        Ok(args)
+    }
+}
+
+pub(crate) struct ParserArgs {
+    pub(crate) name: String,
+    pub(crate) transports: Vec<String>,
+}
+
+impl syn::parse::Parse for ParserArgs {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut name = None;
+        let mut transports = vec![];
+        while !input.is_empty() {
+            let key = input.parse::<syn::Ident>()?;
+            input.parse::<syn::Token![=]>()?;
+            if key == "name" {
+                name = Some(input.parse::<syn::LitStr>()?.value());
+            } else if key == "transports" {
+                let content;
+                syn::bracketed!(content in input);
+                let values =
+                    syn::punctuated::Punctuated::<syn::LitStr, syn::Token![,]>::parse_terminated(
+                        &content,
+                    )?;
+                transports = values.iter().map(|t| t.value()).collect();
+            } else {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "expected `name` or `transports`",
+                ));
+            }
+            if input.parse::<Option<syn::Token![,]>>()?.is_none() {
+                break;
+            }
+        }
+        if !input.is_empty() {
+            return Err(input.error("expected `,`"));
+        }
+        let name = match name {
+            Some(name) => name,
+            None => return Err(input.error("missing `name`")),
+        };
+        if transports.is_empty() {
+            return Err(input.error("missing `transports`"));
+        }
+        Ok(ParserArgs { name, transports })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -24,14 +315,120 @@ pub(crate) struct SubscriptionConfig {
 }
 
 impl SubscriptionConfig {
+    /// Returns `true` if connections buffer packets for any subscription.
+    pub(crate) fn tracks_packets(&self) -> bool {
+        self.subscriptions.iter().any(|spec| {
+            matches!(spec.level, Level::Packet) || spec.datatypes.iter().any(|d| d.track_packets)
+        })
+    }
+
+    /// Returns the largest connection volume that any subscription can match, from the upper
+    /// bounds of their `conn` predicates.
+    pub(crate) fn volume_limits(&self) -> VolumeLimits {
+        self.subscriptions
+            .iter()
+            .map(|spec| {
+                Filter::new(&spec.filter).map_or(VolumeLimits::default(), |f| f.volume_limits())
+            })
+            .reduce(VolumeLimits::union)
+            .unwrap_or_default()
+    }
+
+    /// Returns `true` if every subscription is packet-level and its filter only has predicates
+    /// that can be applied to individual packets. Packets are then delivered by the packet
+    /// continue filter, and never need to be tracked in a connection table.
+    pub(crate) fn bypasses_conntrack(&self) -> bool {
+        !self.subscriptions.is_empty()
+            && self.subscriptions.iter().all(|spec| {
+                matches!(spec.level, Level::Packet)
+                    && Filter::new(&spec.filter).is_ok_and(|filter| {
+                        filter
+                            .get_patterns_flat()
+                            .iter()
+                            .all(|p| p.predicates.iter().all(|p| p.on_packet()))
+                    })
+            })
+    }
+
+    /// Returns the subscriptions (by index) of each group, starting with the subscriptions that
+    /// are not in any group, if any. Subscriptions of different priorities are split into
+    /// separate groups, in decreasing order of priority, so that their filters (and callbacks) are
+    /// applied in order. Each subscription with a match budget is in a separate group of its own,
+    /// with its index as second element, so that its filter is no longer applied once the budget
+    /// is exhausted.
+    pub(crate) fn groups(&self) -> Vec<(Option<String>, Option<usize>, Vec<usize>)> {
+        let mut priorities: Vec<i32> = self.subscriptions.iter().map(|s| s.priority).collect();
+        priorities.sort_unstable_by(|a, b| b.cmp(a));
+        priorities.dedup();
+
+        let mut groups: Vec<(Option<String>, Option<usize>, Vec<usize>)> = vec![];
+        for priority in priorities {
+            let start = groups.len();
+            let specs = self
+                .subscriptions
+                .iter()
+                .enumerate()
+                .filter(|(_, spec)| spec.priority == priority);
+            if specs
+                .clone()
+                .any(|(_, spec)| spec.group.is_none() && spec.max_matches.is_none())
+            {
+                groups.push((None, None, vec![]));
+            }
+            for (idx, spec) in specs {
+                if spec.max_matches.is_some() {
+                    groups.push((spec.group.clone(), Some(idx), vec![idx]));
+                    continue;
+                }
+                match groups[start..]
+                    .iter_mut()
+                    .find(|(group, budget, _)| *group == spec.group && budget.is_none())
+                {
+                    Some((_, _, ids)) => ids.push(idx),
+                    None => groups.push((spec.group.clone(), None, vec![idx])),
+                }
+            }
+        }
+        groups
+    }
+
     pub(crate) fn from_raw(config: &ConfigRaw) -> Self {
         let mut subscriptions = vec![];
         for s in &config.subscriptions {
             assert!(!s.datatypes.is_empty());
-            let mut spec = SubscriptionSpec::new(s.filter.clone(), s.callback.clone());
+            let shared = Self::shared_filter(config, s);
+            let mut spec = SubscriptionSpec::new(shared.filter.clone(), s.callback.clone());
+            if let Some(action) = &s.action {
+                spec.set_action(
+                    action.parse().unwrap_or_else(|err| {
+                        panic!("Invalid subscription {}: {}", s.callback, err)
+                    }),
+                );
+            }
+            if s.is_async {
+                spec.set_async();
+            }
+            if let Some(fraction) = shared.sample_connections {
+                spec.set_sample_connections(fraction);
+            }
+            if let Some(group) = &shared.group {
+                spec.set_group(group.clone());
+            }
+            spec.set_priority(shared.priority);
+            if let Some(max_matches) = s.max_matches {
+                spec.set_max_matches(max_matches);
+            }
             for datatype_str in &s.datatypes {
                 Self::validate_datatype(datatype_str.as_str());
-                let datatype = DATATYPES.get(datatype_str.as_str()).unwrap().clone();
+                if s.is_async && !is_cloneable(datatype_str.as_str()) {
+                    panic!(
+                        "Invalid subscription {}: {} cannot be delivered to an async callback",
+                        s.callback, datatype_str
+                    );
+                }
+                let mut datatype = get_datatype(datatype_str.as_str()).unwrap();
+                // Opt-in: deliver each matching session rather than only the first one
+                datatype.stream_sessions |= s.stream_sessions;
                 spec.add_datatype(datatype);
             }
             spec.validate_spec();
@@ -40,6 +437,50 @@ impl SubscriptionConfig {
         Self { subscriptions }
     }
 
+    // Returns the subscription whose filter (with its sampling, group, and priority) is shared by
+    // `s`: the subscription of the callback that `s` is attached to `with`, if any, or `s` itself.
+    // Identical filters in the same group and priority are evaluated once for all of their
+    // subscriptions.
+    fn shared_filter<'a>(config: &'a ConfigRaw, s: &'a SubscriptionRaw) -> &'a SubscriptionRaw {
+        let mut shared = s;
+        for _ in 0..config.subscriptions.len() {
+            let callback = match &shared.with {
+                Some(callback) => callback,
+                None => return shared,
+            };
+            if !shared.filter.is_empty()
+                || shared.sample_connections.is_some()
+                || shared.group.is_some()
+                || shared.priority != 0
+            {
+                panic!(
+                    "Invalid subscription {}: `filter`, `sample_connections`, `group` and \
+                     `priority` are shared with {}",
+                    shared.callback, callback
+                );
+            }
+            let mut candidates = config
+                .subscriptions
+                .iter()
+                .filter(|other| other.callback == *callback);
+            shared = match (candidates.next(), candidates.next()) {
+                (Some(other), None) => other,
+                (None, _) => panic!(
+                    "Invalid subscription {}: no subscription for callback {}",
+                    s.callback, callback
+                ),
+                (Some(_), Some(_)) => panic!(
+                    "Invalid subscription {}: several subscriptions for callback {}",
+                    s.callback, callback
+                ),
+            };
+        }
+        panic!(
+            "Invalid subscription {}: cycle of `with` callbacks",
+            s.callback
+        );
+    }
+
     pub(crate) fn from_file(filepath_in: &str) -> Self {
         let config_str = std::fs::read_to_string(filepath_in)
             .unwrap_or_else(|err| panic!("ERROR: File read failed {}: {:?}", filepath_in, err));
@@ -50,8 +491,8 @@ impl SubscriptionConfig {
     }
 
     fn validate_datatype(datatype: &str) {
-        if !DATATYPES.contains_key(datatype) {
-            let valid_types: Vec<&str> = DATATYPES.keys().copied().collect();
+        if get_datatype(datatype).is_none() {
+            let valid_types = datatype_names();
             panic!(
                 "Invalid datatype: {};\nDid you mean:\n {}",
                 datatype,
@@ -0,0 +1,128 @@
+use retina_core::filter::actions::{ActionData, Actions};
+use retina_core::filter::ptree::{Deliver, FilterLayer, PTree};
+use retina_core::filter::{Filter, Level, SubscriptionSpec};
+use retina_core::protocols::stream::ConnParser;
+
+use crate::parse::SubscriptionConfig;
+
+// Filter layers at which the framework decides what to do with a connection
+const ACTION_LAYERS: [FilterLayer; 4] = [
+    FilterLayer::PacketContinue,
+    FilterLayer::Packet,
+    FilterLayer::Protocol,
+    FilterLayer::Session,
+];
+
+// Summarizes, for each subscription, the actions that its filter and datatypes induce and which
+// of them are expensive.
+pub(crate) fn action_report(config: &SubscriptionConfig) -> String {
+    let mut report = String::from("Retina subscription action report\n");
+    for (id, spec) in config.subscriptions.iter().enumerate() {
+        report.push('\n');
+        report.push_str(&format!("#{} {}\n", id, spec.as_str()));
+        report.push_str(&format!("  filter: {}\n", spec.sampled_filter()));
+        report.push_str(&format!("  level: {:?}\n", spec.level));
+
+        let actions = subscription_actions(id, spec);
+        report.push_str(&format!(
+            "  actions: {:?}\n",
+            actions.data | actions.terminal_actions
+        ));
+        let costs = costs(spec, &actions);
+        if costs.is_empty() {
+            report.push_str("  costs: none\n");
+        } else {
+            report.push_str("  costs:\n");
+            for cost in costs {
+                report.push_str(&format!("    - {}\n", cost));
+            }
+        }
+    }
+    report
+}
+
+// Union of the actions returned by the filters of a subscription, as if it were the only one
+fn subscription_actions(id: usize, spec: &SubscriptionSpec) -> Actions {
+    let filter_str = spec.sampled_filter();
+    let filter = Filter::new(&filter_str)
+        .unwrap_or_else(|err| panic!("Failed to parse filter {}: {:?}", filter_str, err));
+    let patterns = filter.get_patterns_flat();
+    let deliver = Deliver {
+        id,
+        as_str: spec.as_str(),
+        must_deliver: false,
+    };
+    let mut actions = Actions::new();
+    for layer in ACTION_LAYERS {
+        let mut ptree = PTree::new_empty(layer);
+        ptree.add_filter(&patterns, spec, &deliver);
+        actions.push(&ptree.actions);
+    }
+    actions
+}
+
+// Expensive actions of a subscription, with the filter or datatypes that cause them
+fn costs(spec: &SubscriptionSpec, actions: &Actions) -> Vec<String> {
+    let all = actions.data | actions.terminal_actions;
+    let datatypes = |f: fn(&retina_core::filter::DataType) -> bool| -> String {
+        let names: Vec<&str> = spec
+            .datatypes
+            .iter()
+            .filter(|d| f(d))
+            .map(|d| d.as_str)
+            .collect();
+        names.join(", ")
+    };
+    let mut costs = vec![];
+
+    let mut parsers: Vec<&str> = ConnParser::requires_parsing(&spec.filter)
+        .into_iter()
+        .chain(spec.datatypes.iter().flat_map(|d| d.stream_protos.clone()))
+        .collect();
+    parsers.sort();
+    parsers.dedup();
+    if all.intersects(ActionData::ProtoProbe | ActionData::ProtoFilter) || !parsers.is_empty() {
+        costs.push(format!(
+            "probes and parses application-layer protocols ({})",
+            parsers.join(", ")
+        ));
+    }
+    if all.intersects(ActionData::SessionTrack) {
+        costs.push(format!(
+            "buffers parsed sessions until the connection is delivered ({})",
+            datatypes(|d| d.track_sessions || matches!(d.level, Level::Session))
+        ));
+    }
+    if all.intersects(ActionData::PacketTrack) {
+        let cause = match matches!(spec.level, Level::Packet) {
+            true => "packet-level subscription with a connection or session filter".to_string(),
+            false => datatypes(|d| d.track_packets),
+        };
+        costs.push(format!(
+            "buffers the packets of the connection until delivery ({})",
+            cause
+        ));
+    }
+    if all.intersects(ActionData::UpdatePDU) {
+        costs.push(format!(
+            "updates tracked data on every packet ({})",
+            datatypes(|d| d.needs_update)
+        ));
+    }
+    if all.intersects(ActionData::ReassembledUpdatePDU) {
+        costs.push(format!(
+            "reassembles TCP streams to update tracked data in order ({})",
+            datatypes(|d| d.needs_update_reassembled)
+        ));
+    }
+    if all.intersects(ActionData::PacketWrite) {
+        costs.push(format!(
+            "writes the packets of the connection to pcap files ({})",
+            datatypes(|d| d.write_packets)
+        ));
+    }
+    if spec.is_async {
+        costs.push("clones delivered data to run the callback off the packet cores".to_string());
+    }
+    costs
+}
@@ -1,18 +1,88 @@
 use retina_core::filter::ast::{BinOp, FieldName, ProtocolName, Value};
 use retina_core::filter::ptree::{FilterLayer, PNode};
 use retina_core::filter::{Level, SubscriptionSpec};
+use retina_core::protocols::stream::custom::is_custom_protocol;
 
 use crate::data::{build_callback, build_packet_callback};
 use heck::CamelCase;
 use proc_macro2::{Ident, Span};
 use quote::quote;
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::sync::Mutex;
 
 lazy_static! {
     pub(crate) static ref DELIVER: Mutex<HashMap<usize, SubscriptionSpec>> =
         Mutex::new(HashMap::new());
+    // Regular expressions of the filter predicates, matched by index in `REGEX_DATABASE`
+    pub(crate) static ref REGEXES: Mutex<Vec<String>> = Mutex::new(vec![]);
+    // Names of the filter sets referenced by the filter predicates
+    pub(crate) static ref SETS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+    // Rate predicates (without their threshold) and their windows, counted by index in
+    // `RATE_SKETCHES`
+    pub(crate) static ref RATES: Mutex<Vec<(String, u64)>> = Mutex::new(vec![]);
+}
+
+// Returns the index of `text` in `REGEX_DATABASE`
+fn regex_id(text: &str) -> usize {
+    if Regex::new(text).is_err() {
+        panic!("Invalid Regex string")
+    }
+    let mut regexes = REGEXES.lock().unwrap();
+    match regexes.iter().position(|re| re == text) {
+        Some(id) => id,
+        None => {
+            regexes.push(text.to_string());
+            regexes.len() - 1
+        }
+    }
+}
+
+// Declares `REGEX_DATABASE`, with the regular expressions of all filter predicates
+pub(crate) fn regex_database() -> Option<proc_macro2::TokenStream> {
+    let regexes = REGEXES.lock().unwrap();
+    if regexes.is_empty() {
+        return None;
+    }
+    let regexes = regexes.iter();
+    Some(quote! {
+        static ref REGEX_DATABASE: retina_core::filter::regexes::RegexDatabase =
+            retina_core::filter::regexes::RegexDatabase::new(&[ #( #regexes ),* ]);
+    })
+}
+
+// Returns the index of the rate predicate on `field` over `window` in `RATE_SKETCHES`
+fn rate_id(field: &FieldName, window: u64) -> usize {
+    let rate = (field.to_string(), window);
+    let mut rates = RATES.lock().unwrap();
+    match rates.iter().position(|r| *r == rate) {
+        Some(id) => id,
+        None => {
+            rates.push(rate);
+            rates.len() - 1
+        }
+    }
+}
+
+// Declares `RATE_SKETCHES`, with the per-core sketch of each rate predicate
+pub(crate) fn rate_sketches() -> Option<proc_macro2::TokenStream> {
+    let rates = RATES.lock().unwrap();
+    if rates.is_empty() {
+        return None;
+    }
+    let windows = rates.iter().map(|(_, window)| {
+        let window_lit = syn::LitInt::new(&window.to_string(), Span::call_site());
+        quote! {
+            std::cell::RefCell::new(retina_core::filter::rate::RateSketch::new(#window_lit))
+        }
+    });
+    Some(quote! {
+        thread_local! {
+            // One sketch per rate predicate and per core
+            static RATE_SKETCHES: Vec<std::cell::RefCell<retina_core::filter::rate::RateSketch>> =
+                vec![ #( #windows ),* ];
+        }
+    })
 }
 
 pub(crate) fn binary_to_tokens(
@@ -20,22 +90,31 @@ pub(crate) fn binary_to_tokens(
     field: &FieldName,
     op: &BinOp,
     value: &Value,
-    statics: &mut Vec<proc_macro2::TokenStream>,
 ) -> proc_macro2::TokenStream {
     assert!(!field.is_combined()); // should have been split when building tree
+    if let Value::Rate { count, window } = value {
+        return rate_to_tokens(field, op, *count, *window);
+    }
+    if is_custom_protocol(protocol.name()) {
+        return custom_to_tokens(protocol, field, op, value);
+    }
     let proto = Ident::new(protocol.name(), Span::call_site());
-    let field = Ident::new(field.name(), Span::call_site());
+    // string argument of the accessor, e.g., `http.header('host')`
+    let arg = field
+        .arg()
+        .map(|arg| syn::LitStr::new(arg, Span::call_site()));
+    let field = field_ident(field.method());
 
     match value {
         Value::Int(val) => {
             let val_lit = syn::LitInt::new(&val.to_string(), Span::call_site());
             match *op {
-                BinOp::Eq => quote! { #proto.#field() == #val_lit },
-                BinOp::Ne => quote! { #proto.#field() != #val_lit },
-                BinOp::Ge => quote! { #proto.#field() >= #val_lit },
-                BinOp::Le => quote! { #proto.#field() <= #val_lit },
-                BinOp::Gt => quote! { #proto.#field() > #val_lit },
-                BinOp::Lt => quote! { #proto.#field() < #val_lit },
+                BinOp::Eq => quote! { #proto.#field(#arg) == #val_lit },
+                BinOp::Ne => quote! { #proto.#field(#arg) != #val_lit },
+                BinOp::Ge => quote! { #proto.#field(#arg) >= #val_lit },
+                BinOp::Le => quote! { #proto.#field(#arg) <= #val_lit },
+                BinOp::Gt => quote! { #proto.#field(#arg) > #val_lit },
+                BinOp::Lt => quote! { #proto.#field(#arg) < #val_lit },
                 _ => panic!("Invalid binary operation `{}` for value: `{}`.", op, value),
             }
         }
@@ -44,7 +123,7 @@ pub(crate) fn binary_to_tokens(
             let to_lit = syn::LitInt::new(&to.to_string(), Span::call_site());
             match *op {
                 BinOp::In => quote! {
-                    #proto.#field() >= #from_lit && #proto.#field() <= #to_lit
+                    #proto.#field(#arg) >= #from_lit && #proto.#field(#arg) <= #to_lit
                 },
                 _ => panic!("Invalid binary operation `{}` for value: `{}`.", op, value),
             }
@@ -62,23 +141,23 @@ pub(crate) fn binary_to_tokens(
             match *op {
                 BinOp::Eq => {
                     if ipv4net.prefix_len() == 32 {
-                        quote! { u32::from(#proto.#field()) == #addr_lit }
+                        quote! { u32::from(#proto.#field(#arg)) == #addr_lit }
                     } else {
-                        quote! { u32::from(#proto.#field()) & #netmask_lit == #net_lit }
+                        quote! { u32::from(#proto.#field(#arg)) & #netmask_lit == #net_lit }
                     }
                 }
                 BinOp::Ne => {
                     if ipv4net.prefix_len() == 32 {
-                        quote! { u32::from(#proto.#field()) != #addr_lit }
+                        quote! { u32::from(#proto.#field(#arg)) != #addr_lit }
                     } else {
-                        quote! { u32::from(#proto.#field()) & #netmask_lit != #net_lit }
+                        quote! { u32::from(#proto.#field(#arg)) & #netmask_lit != #net_lit }
                     }
                 }
                 BinOp::In => {
                     if ipv4net.prefix_len() == 32 {
-                        quote! { u32::from(#proto.#field()) == #addr_lit }
+                        quote! { u32::from(#proto.#field(#arg)) == #addr_lit }
                     } else {
-                        quote! { u32::from(#proto.#field()) & #netmask_lit == #net_lit }
+                        quote! { u32::from(#proto.#field(#arg)) & #netmask_lit == #net_lit }
                     }
                 }
                 _ => panic!("Invalid binary operation `{}` for value: `{}`.", op, value),
@@ -97,23 +176,23 @@ pub(crate) fn binary_to_tokens(
             match *op {
                 BinOp::Eq => {
                     if ipv6net.prefix_len() == 128 {
-                        quote! { u128::from(#proto.#field()) == #addr_lit }
+                        quote! { u128::from(#proto.#field(#arg)) == #addr_lit }
                     } else {
-                        quote! { u128::from(#proto.#field()) & #netmask_lit == #net_lit }
+                        quote! { u128::from(#proto.#field(#arg)) & #netmask_lit == #net_lit }
                     }
                 }
                 BinOp::Ne => {
                     if ipv6net.prefix_len() == 128 {
-                        quote! { u128::from(#proto.#field()) != #addr_lit }
+                        quote! { u128::from(#proto.#field(#arg)) != #addr_lit }
                     } else {
-                        quote! { u128::from(#proto.#field()) & #netmask_lit != #net_lit }
+                        quote! { u128::from(#proto.#field(#arg)) & #netmask_lit != #net_lit }
                     }
                 }
                 BinOp::In => {
                     if ipv6net.prefix_len() == 128 {
-                        quote! { u128::from(#proto.#field()) == #addr_lit }
+                        quote! { u128::from(#proto.#field(#arg)) == #addr_lit }
                     } else {
-                        quote! { u128::from(#proto.#field()) & #netmask_lit == #net_lit }
+                        quote! { u128::from(#proto.#field(#arg)) & #netmask_lit == #net_lit }
                     }
                 }
                 _ => panic!("Invalid binary operation `{}` for value: `{}`.", op, value),
@@ -123,38 +202,151 @@ pub(crate) fn binary_to_tokens(
             match *op {
                 BinOp::Eq => {
                     let val_lit = syn::LitStr::new(text, Span::call_site());
-                    quote! { #proto.#field() == #val_lit }
+                    quote! { #proto.#field(#arg) == #val_lit }
                 }
                 BinOp::En => {
                     let field_ident =
                         Ident::new(&field.to_string().to_camel_case(), Span::call_site());
                     let variant_ident =
                         Ident::new(&text.as_str().to_camel_case(), Span::call_site());
-                    quote! { #proto.#field() == retina_core::protocols::stream::#proto::#field_ident::#variant_ident }
+                    quote! { #proto.#field(#arg) == retina_core::protocols::stream::#proto::#field_ident::#variant_ident }
                 }
                 BinOp::Re => {
-                    let val_lit = syn::LitStr::new(text, Span::call_site());
-                    if Regex::new(text).is_err() {
-                        panic!("Invalid Regex string")
-                    }
-
-                    let re_name = format!("RE{}", statics.len());
-                    let re_ident = Ident::new(&re_name, Span::call_site());
-                    let lazy_re = quote! {
-                        static ref #re_ident: regex::Regex = regex::Regex::new(#val_lit).unwrap();
-                    };
-                    // avoids compiling the Regex every time
-                    statics.push(lazy_re);
+                    // All regular expressions are compiled once, together
+                    let id = regex_id(text);
                     quote! {
-                        #re_ident.is_match(&#proto.#field()[..])
+                        REGEX_DATABASE.is_match(#id, &#proto.#field(#arg)[..])
                     }
-                    // quote! {
-                    //     Regex::new(#val_lit).unwrap().is_match(#proto.#field())
-                    // }
                 }
                 _ => panic!("Invalid binary operation `{}` for value: `{}`.", op, value),
             }
         }
+        Value::Set(name) => match *op {
+            BinOp::In => {
+                SETS.lock().unwrap().insert(name.clone());
+                quote! {
+                    {
+                        static SET: retina_core::filter::sets::FilterSet =
+                            retina_core::filter::sets::FilterSet::new(#name);
+                        SET.contains(&#proto.#field(#arg))
+                    }
+                }
+            }
+            _ => panic!("Invalid binary operation `{}` for value: `{}`.", op, value),
+        },
+        Value::Rate { .. } => unreachable!("rate predicates are handled separately"),
+    }
+}
+
+// Field accessors named after Rust keywords (e.g., `icmp.type`) are raw identifiers.
+fn field_ident(name: &str) -> Ident {
+    if syn::parse_str::<Ident>(name).is_ok() {
+        Ident::new(name, Span::call_site())
+    } else {
+        Ident::new_raw(name, Span::call_site())
+    }
+}
+
+// Stateful predicate on the number of events per key, e.g. `rate(ipv4.src_addr, tcp.syn) > 100/60s`.
+// Occurrences of the same rate predicate (with any threshold) in the generated filter share a
+// per-core sketch in `RATE_SKETCHES`, which counts each packet once.
+fn rate_to_tokens(
+    field: &FieldName,
+    op: &BinOp,
+    count: u64,
+    window: u64,
+) -> proc_macro2::TokenStream {
+    let ((key_proto, key_field), event) = field
+        .rate_args()
+        .unwrap_or_else(|| panic!("Invalid rate predicate `{}`.", field));
+    let key_proto = Ident::new(key_proto.name(), Span::call_site());
+    let key_field = field_ident(key_field.name());
+    let event = match event {
+        Some((event_proto, event_field)) => {
+            let event_proto = Ident::new(event_proto.name(), Span::call_site());
+            let event_field = field_ident(event_field.name());
+            quote! { #event_proto.#event_field() != 0 }
+        }
+        None => quote! { true },
+    };
+    let count_lit = syn::LitInt::new(&count.to_string(), Span::call_site());
+    let id = rate_id(field, window);
+    let rate = quote! {
+        RATE_SKETCHES.with(|sketches| {
+            sketches[#id]
+                .borrow_mut()
+                .observe_packet(&#key_proto.#key_field(), #event, mbuf.rx_time())
+        })
+    };
+    match *op {
+        BinOp::Ge => quote! { #rate >= #count_lit },
+        BinOp::Le => quote! { #rate <= #count_lit },
+        BinOp::Gt => quote! { #rate > #count_lit },
+        BinOp::Lt => quote! { #rate < #count_lit },
+        _ => panic!(
+            "Invalid binary operation `{}` for rate predicate: `{}`.",
+            op, field
+        ),
+    }
+}
+
+// Stateless sampling predicate, e.g. `sample(0.01)`. The decision is made on the 5-tuple of the
+// packet (`mbuf`) or connection (`conn`), so all filter stages agree on it.
+fn sample_to_tokens(field: &FieldName, on_packet: bool) -> proc_macro2::TokenStream {
+    let fraction = field
+        .sample_fraction()
+        .unwrap_or_else(|| panic!("Invalid sampling predicate `{}`.", field));
+    let fraction_lit = syn::LitFloat::new(&format!("{:?}", fraction), Span::call_site());
+    match on_packet {
+        true => quote! { retina_core::filter::sample::sample_packet(mbuf, #fraction_lit) },
+        false => quote! { retina_core::filter::sample::sample_conn(conn, #fraction_lit) },
+    }
+}
+
+// Predicate on a field of a custom protocol session (see
+// `retina_core::protocols::stream::custom`), e.g. `myproto.user = 'admin'`. Fields are looked up by
+// name, and predicates on missing fields or fields of another type do not match.
+fn custom_to_tokens(
+    protocol: &ProtocolName,
+    field: &FieldName,
+    op: &BinOp,
+    value: &Value,
+) -> proc_macro2::TokenStream {
+    let proto = Ident::new(protocol.name(), Span::call_site());
+    let name = syn::LitStr::new(field.name(), Span::call_site());
+    let field = quote! { #proto.field(#name) };
+    match value {
+        Value::Int(val) => {
+            let val_lit = syn::LitInt::new(&val.to_string(), Span::call_site());
+            let cmp = match *op {
+                BinOp::Eq => quote! { == },
+                BinOp::Ne => quote! { != },
+                BinOp::Ge => quote! { >= },
+                BinOp::Le => quote! { <= },
+                BinOp::Gt => quote! { > },
+                BinOp::Lt => quote! { < },
+                _ => panic!("Invalid binary operation `{}` for value: `{}`.", op, value),
+            };
+            quote! { #field.and_then(|v| v.as_int()).map_or(false, |v| v #cmp #val_lit) }
+        }
+        Value::IntRange { from, to } if *op == BinOp::In => {
+            let from_lit = syn::LitInt::new(&from.to_string(), Span::call_site());
+            let to_lit = syn::LitInt::new(&to.to_string(), Span::call_site());
+            quote! {
+                #field.and_then(|v| v.as_int()).map_or(false, |v| v >= #from_lit && v <= #to_lit)
+            }
+        }
+        Value::Text(text) if *op == BinOp::Eq => {
+            let val_lit = syn::LitStr::new(text, Span::call_site());
+            quote! { #field.map_or(false, |v| v.as_str() == Some(#val_lit)) }
+        }
+        Value::Text(text) if *op == BinOp::Re => {
+            let id = regex_id(text);
+            quote! {
+                #field.map_or(false, |v| v.as_str().map_or(false, |s| REGEX_DATABASE.is_match(#id, s)))
+            }
+        }
+        _ => panic!("Invalid binary operation `{}` for value: `{}`.", op, value),
     }
 }
 
@@ -169,7 +361,11 @@ pub(crate) fn update_body(
         body.push(quote! { result.push(&#actions); });
     }
     if !node.deliver.is_empty() {
-        for d in &node.deliver {
+        // Subscriptions delivered together have the same priority (see
+        // `SubscriptionConfig::groups`), and are invoked in the order they are defined
+        let mut deliver: Vec<_> = node.deliver.iter().collect();
+        deliver.sort_by_key(|d| d.id);
+        for d in deliver {
             let id = &d.id;
             {
                 let lock = DELIVER.lock().unwrap();
@@ -216,6 +412,15 @@ impl PacketDataFilter {
         (build_child_nodes)(&mut body, statics, node, filter_layer);
         update_body(&mut body, node, filter_layer, false);
 
+        if node.pred.on_conn() {
+            // Checked on connection data when the connection is delivered
+            code.push(match first_unary {
+                true => quote! { if true { #( #body )* } },
+                false => quote! { else if true { #( #body )* } },
+            });
+            return;
+        }
+
         if first_unary {
             code.push(quote! {
                 if let Ok(#ident) = &retina_core::protocols::packet::Packet::parse_to::<retina_core::protocols::packet::#ident::#ident_type>(#outer) {
@@ -247,7 +452,12 @@ impl PacketDataFilter {
         (build_child_nodes)(&mut body, statics, node, filter_layer);
         update_body(&mut body, node, filter_layer, false);
 
-        let pred_tokenstream = binary_to_tokens(protocol, field, op, value, statics);
+        let pred_tokenstream = match node.pred.on_conn() {
+            // Checked on connection data when the connection is delivered
+            true => quote! { true },
+            false if field.is_sample() => sample_to_tokens(field, true),
+            false => binary_to_tokens(protocol, field, op, value),
+        };
         if node.if_else {
             code.push(quote! {
                 else if #pred_tokenstream {
@@ -342,7 +552,10 @@ impl ConnDataFilter {
         (build_child_nodes)(&mut body, statics, node, filter_layer);
         update_body(&mut body, node, filter_layer, false);
 
-        let pred_tokenstream = binary_to_tokens(protocol, field, op, value, statics);
+        let pred_tokenstream = match field.is_sample() {
+            true => sample_to_tokens(field, false),
+            false => binary_to_tokens(protocol, field, op, value),
+        };
         if node.if_else {
             code.push(quote! {
                 else if #pred_tokenstream {
@@ -372,18 +585,27 @@ impl ConnDataFilter {
         (build_child_nodes)(&mut body, statics, node, filter_layer);
         update_body(&mut body, node, filter_layer, false);
 
+        let condition = match is_custom_protocol(protocol.name()) {
+            true => {
+                let service = protocol.name();
+                quote! { conn.service().protocol_name().as_deref() == Some(#service) }
+            }
+            false => quote! {
+                matches!(conn.service(), retina_core::protocols::stream::ConnParser::#service_ident { .. })
+            },
+        };
         if node.if_else {
-            code.push( quote! {
-                else if matches!(conn.service(), retina_core::protocols::stream::ConnParser::#service_ident { .. }) {
+            code.push(quote! {
+                else if #condition {
                     #( #body )*
                 }
-            } );
+            });
         } else {
-            code.push( quote! {
-                if matches!(conn.service(), retina_core::protocols::stream::ConnParser::#service_ident { .. }) {
+            code.push(quote! {
+                if #condition {
                     #( #body )*
                 }
-            } );
+            });
         }
     }
 }
@@ -409,7 +631,12 @@ impl SessionDataFilter {
         let proto_name = Ident::new(service, Span::call_site());
         let proto_variant = Ident::new(&service.to_camel_case(), Span::call_site());
 
-        let condition = quote! { let retina_core::protocols::stream::SessionData::#proto_variant(#proto_name) = &session.data };
+        let condition = match is_custom_protocol(service) {
+            true => quote! { let Some(#proto_name) = session.data.custom(#service) },
+            false => quote! {
+                let retina_core::protocols::stream::SessionData::#proto_variant(#proto_name) = &session.data
+            },
+        };
         if first_unary {
             code.push(quote! {
                 if #condition {